@@ -0,0 +1,61 @@
+//! The `auth` subcommand.
+
+use common_failures::Result;
+use dbcrossbarlib::{
+    auth::{gcloud_credentials, gcloud_login, gcloud_logout},
+    config::Configuration,
+    Context,
+};
+use std::path::PathBuf;
+use structopt::{self, StructOpt};
+
+/// Authentication arguments.
+#[derive(Debug, StructOpt)]
+pub(crate) struct Opt {
+    /// The authentication command to run.
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+/// An authentication command.
+#[derive(Debug, StructOpt)]
+pub(crate) enum Command {
+    /// Log in to Google Cloud using your browser, and cache the tokens for
+    /// BigQuery and Cloud Storage.
+    #[structopt(name = "login")]
+    Login {
+        /// A client secret JSON file to save and use. Defaults to
+        /// GCLOUD_CLIENT_SECRET or the saved client secret.
+        #[structopt(long = "client-secret", parse(from_os_str))]
+        client_secret: Option<PathBuf>,
+    },
+
+    /// Delete cached Google Cloud login tokens.
+    #[structopt(name = "logout")]
+    Logout,
+
+    /// Show which Google Cloud credentials will be used.
+    #[structopt(name = "status")]
+    Status,
+}
+
+/// Log in or out.
+pub(crate) async fn run(ctx: Context, _config: Configuration, opt: Opt) -> Result<()> {
+    match &opt.command {
+        Command::Login { client_secret } => {
+            gcloud_login(&ctx, client_secret.as_deref()).await?;
+            println!("Logged in to Google Cloud.");
+        }
+        Command::Logout => {
+            if gcloud_logout(&ctx).await? {
+                println!("Logged out of Google Cloud.");
+            } else {
+                println!("Not logged in to Google Cloud.");
+            }
+        }
+        Command::Status => {
+            println!("Google Cloud: {}", gcloud_credentials(&ctx).await?);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,359 @@
+//! The `bench` subcommand.
+
+use common_failures::Result;
+use dbcrossbarlib::{
+    config::Configuration,
+    schema::{Column, DataType, Table},
+    tokio_glue::BoxStream,
+    validate::{validate_csvs, ValidationMode},
+    BoxLocator, ColumnMatching, Context, CsvStream, DestinationArguments,
+    DriverArguments, IfExists, Locator, OutputCompression, SchemaEvolution,
+    SharedArguments, SourceArguments, TemporarySelection, TemporaryStorage,
+    UnparsedLocator, Unverified,
+};
+use failure::{format_err, ResultExt};
+use futures::TryStreamExt;
+use slog::{debug, o};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use structopt::{self, StructOpt};
+
+/// Benchmark arguments.
+#[derive(Debug, StructOpt)]
+pub(crate) struct Opt {
+    /// The schema to use (defaults to a built-in schema with a mix of common
+    /// column types).
+    #[structopt(long = "schema")]
+    schema: Option<UnparsedLocator>,
+
+    /// The number of rows of synthetic data to generate.
+    #[structopt(long = "rows", default_value = "100000")]
+    rows: u64,
+
+    /// Temporary directories, cloud storage buckets, datasets to use during
+    /// transfer (can be repeated).
+    #[structopt(long = "temporary")]
+    temporaries: Vec<String>,
+
+    /// How to choose between temporary locations with the same scheme:
+    /// `first` or `region:$REGION`.
+    #[structopt(long = "temporary-policy", default_value = "first")]
+    temporary_policy: TemporarySelection,
+
+    /// Pass an extra argument of the form `key=value` to the `synthetic:`
+    /// source driver.
+    #[structopt(long = "from-arg")]
+    from_args: Vec<String>,
+
+    /// Pass an extra argument of the form `key=value` to the destination
+    /// drivers.
+    #[structopt(long = "to-arg")]
+    to_args: Vec<String>,
+
+    /// How many data streams should we attempt to copy in parallel?
+    #[structopt(long = "max-streams", short = "J", default_value = "4")]
+    max_streams: usize,
+
+    /// Destination tables to copy our synthetic data to. These will be
+    /// overwritten.
+    destinations: Vec<UnparsedLocator>,
+}
+
+/// Run our benchmarks.
+pub(crate) async fn run(
+    ctx: Context,
+    config: Configuration,
+    enable_unstable: bool,
+    opt: Opt,
+) -> Result<()> {
+    // Figure out what table schema to use.
+    let schema = match &opt.schema {
+        Some(schema_locator) => {
+            let schema_locator = schema_locator.parse(enable_unstable)?;
+            schema_locator
+                .schema(ctx.clone())
+                .await
+                .with_context(|_| {
+                    format!("error reading schema from {}", schema_locator)
+                })?
+                .ok_or_else(|| {
+                    format_err!(
+                        "don't know how to read schema from {}",
+                        schema_locator
+                    )
+                })?
+        }
+        None => default_schema(),
+    };
+    let temporary_storage =
+        TemporaryStorage::with_config(opt.temporaries.clone(), &config)?
+            .with_policy(Arc::new(opt.temporary_policy.clone()));
+    let bench = Bench {
+        ctx,
+        schema,
+        temporary_storage,
+        enable_unstable,
+        opt,
+    };
+
+    // Generate our data once without doing anything else, so we know how
+    // fast our source is, and how many bytes we're copying.
+    let mut results = vec![];
+    let (extract, bytes) = bench.extract(false).await?;
+    results.push(extract);
+    let (transform, _) = bench.extract(true).await?;
+    results.push(transform);
+
+    // Copy our data to each destination.
+    for dest in &bench.opt.destinations {
+        results.extend(bench.copy_to(dest).await?);
+    }
+
+    println!(
+        "{:<10} {:<40} {:>10} {:>10} {:>12} {:>8}",
+        "STAGE", "LOCATOR", "ROWS", "SECONDS", "ROWS/S", "MB/S",
+    );
+    for result in &results {
+        result.print(bench.opt.rows, bytes);
+    }
+    Ok(())
+}
+
+/// Shared state for our benchmarks.
+struct Bench {
+    ctx: Context,
+    schema: Table,
+    temporary_storage: TemporaryStorage,
+    enable_unstable: bool,
+    opt: Opt,
+}
+
+impl Bench {
+    /// The `synthetic:` locator we copy data from.
+    fn source_locator(&self) -> Result<BoxLocator> {
+        let source: UnparsedLocator =
+            format!("synthetic:{}", self.opt.rows).parse()?;
+        source.parse(self.enable_unstable)
+    }
+
+    /// Shared arguments for each copy.
+    fn shared_args(&self) -> SharedArguments<Unverified> {
+        SharedArguments::new(
+            self.schema.clone(),
+            self.temporary_storage.clone(),
+            self.opt.max_streams,
+        )
+    }
+
+    /// Source arguments for the `synthetic:` driver. We always use a fixed
+    /// seed unless one was specified, so that runs are comparable.
+    fn source_args(&self) -> Result<SourceArguments<Unverified>> {
+        let mut from_args = self.opt.from_args.clone();
+        if !from_args.iter().any(|arg| arg.starts_with("seed=")) {
+            from_args.push("seed=0".to_owned());
+        }
+        let from_args = DriverArguments::from_cli_args(&from_args)?;
+        Ok(SourceArguments::new(from_args, None))
+    }
+
+    /// Destination arguments for each copy.
+    fn dest_args(&self) -> Result<DestinationArguments<Unverified>> {
+        let to_args = DriverArguments::from_cli_args(&self.opt.to_args)?;
+        Ok(DestinationArguments::new(
+            to_args,
+            IfExists::Overwrite,
+            SchemaEvolution::default(),
+            ColumnMatching::default(),
+            OutputCompression::default(),
+        ))
+    }
+
+    /// Get a stream of CSV data from our source.
+    async fn local_data(&self) -> Result<BoxStream<CsvStream>> {
+        let source = self.source_locator()?;
+        let data = source
+            .local_data(self.ctx.clone(), self.shared_args(), self.source_args()?)
+            .await?
+            .ok_or_else(|| format_err!("cannot read data from {}", source))?;
+        Ok(data)
+    }
+
+    /// Generate our synthetic data and throw it away, optionally running it
+    /// through `validate_csvs` first. Returns the number of bytes generated.
+    async fn extract(&self, transform: bool) -> Result<(StageResult, usize)> {
+        let ctx = self.ctx.child(o!("bench_stage" => "extract"));
+        debug!(ctx.log(), "generating {} rows", self.opt.rows);
+        let start = Instant::now();
+        let mut data = self.local_data().await?;
+        if transform {
+            data = validate_csvs(
+                ctx.clone(),
+                self.schema.clone(),
+                ValidationMode::Warn,
+                1,
+                data,
+            )?;
+        }
+        let mut bytes = 0;
+        while let Some(stream) = data.try_next().await? {
+            let mut stream_data = stream.data;
+            while let Some(chunk) = stream_data.try_next().await? {
+                bytes += chunk.len();
+            }
+        }
+        let result = StageResult {
+            stage: if transform { "transform" } else { "extract" },
+            locator: self.source_locator()?.to_string(),
+            elapsed: start.elapsed(),
+        };
+        Ok((result, bytes))
+    }
+
+    /// Copy our synthetic data to `dest`.
+    ///
+    /// If `dest` can load data from a `gs://` or `s3://` directory in our
+    /// temporary storage, we first upload our data there, and then load it
+    /// into `dest`, timing each stage separately. Otherwise, we copy our data
+    /// directly to `dest`, and record the entire copy as a `load`.
+    async fn copy_to(&self, dest: &UnparsedLocator) -> Result<Vec<StageResult>> {
+        let dest = dest.parse(self.enable_unstable)?;
+        let ctx = self.ctx.child(o!("to_locator" => dest.to_string()));
+        let mut results = vec![];
+
+        // Upload our data to a staging area, if we can.
+        let staging = self.staging_locator_for(dest.as_ref())?;
+        if let Some(staging) = &staging {
+            let start = Instant::now();
+            let staging = staging.parse(self.enable_unstable)?;
+            self.write_local_data(&ctx, staging.as_ref()).await?;
+            results.push(StageResult {
+                stage: "upload",
+                locator: staging.to_string(),
+                elapsed: start.elapsed(),
+            });
+        }
+
+        // Load our data into `dest`.
+        let start = Instant::now();
+        match &staging {
+            Some(staging) => {
+                let staging = staging.parse(self.enable_unstable)?;
+                dest.write_remote_data(
+                    ctx.clone(),
+                    staging,
+                    self.shared_args(),
+                    SourceArguments::for_temporary(),
+                    self.dest_args()?,
+                )
+                .await?;
+            }
+            None => {
+                self.write_local_data(&ctx, dest.as_ref()).await?;
+            }
+        }
+        results.push(StageResult {
+            stage: "load",
+            locator: dest.to_string(),
+            elapsed: start.elapsed(),
+        });
+        Ok(results)
+    }
+
+    /// Copy our synthetic data to `dest` via the local machine.
+    async fn write_local_data(&self, ctx: &Context, dest: &dyn Locator) -> Result<()> {
+        let data = self.local_data().await?;
+        let shared_args = self.shared_args();
+        dest.write_local_data(ctx.clone(), data, shared_args, self.dest_args()?)
+            .await?
+            .try_buffer_unordered(self.opt.max_streams)
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(())
+    }
+
+    /// Find a temporary `gs://` or `s3://` directory which `dest` can load
+    /// data from.
+    fn staging_locator_for(
+        &self,
+        dest: &dyn Locator,
+    ) -> Result<Option<UnparsedLocator>> {
+        for scheme in &["gs:", "s3:"] {
+            if let Some(temp) = self.temporary_storage.find_scheme(scheme) {
+                let mut temp = temp.to_owned();
+                if !temp.ends_with('/') {
+                    temp.push('/');
+                }
+                temp.push_str(&format!(
+                    "dbcrossbar-bench-{}/",
+                    TemporaryStorage::random_tag()
+                ));
+                let staging: UnparsedLocator = temp.parse()?;
+                let parsed = staging.parse(self.enable_unstable)?;
+                if dest.supports_write_remote_data(parsed.as_ref()) {
+                    return Ok(Some(staging));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// How long a single stage of a benchmark took.
+struct StageResult {
+    /// The name of this stage.
+    stage: &'static str,
+    /// The locator we were reading or writing.
+    locator: String,
+    /// How long this stage took.
+    elapsed: Duration,
+}
+
+impl StageResult {
+    /// Print this result, given the number of `rows` and `bytes` we copied.
+    fn print(&self, rows: u64, bytes: usize) {
+        // Avoid floating point, so that we don't need to worry about
+        // precision loss when converting large integers.
+        let micros = self.elapsed.as_micros().max(1);
+        let rows_per_sec = u128::from(rows) * 1_000_000 / micros;
+        // Bytes per microsecond is the same as megabytes per second, and we
+        // want one decimal place.
+        let tenths_mb_per_sec = bytes as u128 * 10 / micros;
+        println!(
+            "{:<10} {:<40} {:>10} {:>6}.{:03} {:>12} {:>6}.{}",
+            self.stage,
+            self.locator,
+            rows,
+            self.elapsed.as_secs(),
+            self.elapsed.subsec_millis(),
+            rows_per_sec,
+            tenths_mb_per_sec / 10,
+            tenths_mb_per_sec % 10,
+        );
+    }
+}
+
+/// A default schema containing a mix of common column types.
+fn default_schema() -> Table {
+    let column = |name: &str, is_nullable: bool, data_type: DataType| Column {
+        name: name.to_owned(),
+        is_nullable,
+        data_type,
+        comment: None,
+    };
+    Table {
+        name: "bench".to_owned(),
+        columns: vec![
+            column("id", false, DataType::Int64),
+            column("uuid", false, DataType::Uuid),
+            column("name", true, DataType::Text),
+            column("active", true, DataType::Bool),
+            column("score", true, DataType::Float64),
+            column("born", true, DataType::Date),
+            column("created_at", true, DataType::TimestampWithTimeZone),
+            column("tags", true, DataType::Array(Box::new(DataType::Text))),
+            column("payload", true, DataType::Json),
+        ],
+    }
+}
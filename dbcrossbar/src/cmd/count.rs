@@ -3,9 +3,10 @@
 use common_failures::Result;
 use dbcrossbarlib::{
     config::Configuration, Context, DriverArguments, SharedArguments, SourceArguments,
-    TemporaryStorage, UnparsedLocator,
+    TemporarySelection, TemporaryStorage, UnparsedLocator,
 };
 use failure::{format_err, ResultExt};
+use std::sync::Arc;
 use structopt::{self, StructOpt};
 
 /// Count arguments.
@@ -20,6 +21,11 @@ pub(crate) struct Opt {
     #[structopt(long = "temporary")]
     temporaries: Vec<String>,
 
+    /// How to choose between temporary locations with the same scheme:
+    /// `first` or `region:$REGION`.
+    #[structopt(long = "temporary-policy", default_value = "first")]
+    temporary_policy: TemporarySelection,
+
     /// Pass an extra argument of the form `key=value` to the source driver.
     #[structopt(long = "from-arg")]
     from_args: Vec<String>,
@@ -42,11 +48,19 @@ pub(crate) async fn run(
     let schema_opt = opt.schema.map(|s| s.parse(enable_unstable)).transpose()?;
     let locator = opt.locator.parse(enable_unstable)?;
 
+    // Parse our source arguments early, because they may specify API
+    // endpoints that we need to read the schema.
+    let from_args = DriverArguments::from_cli_args(&opt.from_args)?;
+
     // Figure out what table schema to use.
     let schema = {
         let schema_locator = schema_opt.as_ref().unwrap_or(&locator);
+        let mut schema_ctx = ctx.with_endpoints_from_args(&from_args)?;
+        if schema_opt.is_none() {
+            schema_ctx = schema_ctx.with_schema_source_args(from_args.clone());
+        }
         schema_locator
-            .schema(ctx.clone())
+            .schema(schema_ctx)
             .await
             .with_context(|_| format!("error reading schema from {}", schema_locator))?
             .ok_or_else(|| {
@@ -57,11 +71,11 @@ pub(crate) async fn run(
     // Build our shared arguments. Specify 1 for `max_streams` until we actually
     // implement local counting.
     let temporaries = opt.temporaries.clone();
-    let temporary_storage = TemporaryStorage::with_config(temporaries, &config)?;
+    let temporary_storage = TemporaryStorage::with_config(temporaries, &config)?
+        .with_policy(Arc::new(opt.temporary_policy.clone()));
     let shared_args = SharedArguments::new(schema, temporary_storage, 1);
 
     // Build our source arguments.
-    let from_args = DriverArguments::from_cli_args(&opt.from_args)?;
     let source_args = SourceArguments::new(from_args, opt.where_clause.clone());
 
     let count = locator.count(ctx.clone(), shared_args, source_args).await?;
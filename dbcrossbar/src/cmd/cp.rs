@@ -2,14 +2,44 @@
 
 use common_failures::Result;
 use dbcrossbarlib::{
-    config::Configuration, rechunk::rechunk_csvs, tokio_glue::try_forward, Context,
-    DestinationArguments, DisplayOutputLocators, DriverArguments, IfExists,
-    SharedArguments, SourceArguments, TemporaryStorage, UnparsedLocator,
+    checkpoint::Checkpoint,
+    column_stats::ColumnStatsCollector,
+    config::Configuration,
+    copy_path::check_copy_path,
+    cost::estimate_copy_cost,
+    events::Event,
+    expectations::{check_expectations, Expectations},
+    limits::check_limits,
+    lossy_types::check_lossy_columns,
+    names::{rename_csv_headers, report_names, NameRules},
+    normalize::{
+        normalize_csvs, BoolRule, Cleanups, ColumnRule, DateFormat, NormalizeOptions,
+        NumberFormat,
+    },
+    progress::report_stream_progress,
+    provenance::Provenance,
+    rechunk::rechunk_csvs,
+    select::ColumnSelection,
+    shard::Shard,
+    tokio_glue::{try_forward, BoxStream},
+    validate::{validate_csvs, ValidationMode},
+    verify::{tally_rows, RowCounter, RowTally, VerifyMode},
+    BoxLocator, ColumnMatching, ConsumeWithParallelism, Context, CsvStream,
+    DestinationArguments, DisplayOutputLocators, DriverArguments, Error, IfExists,
+    Locator, OutputCompression, SchemaEvolution, SharedArguments, SourceArguments,
+    TemporarySelection, TemporaryStorage, UnparsedLocator, Unverified,
 };
 use failure::{format_err, ResultExt};
 use futures::{pin_mut, stream, FutureExt, StreamExt, TryStreamExt};
 use humanize_rs::bytes::Bytes as HumanizedBytes;
-use slog::{debug, o};
+use slog::{debug, error, o};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 use structopt::{self, StructOpt};
 use tokio::io;
 use tokio_util::codec::{FramedWrite, LinesCodec};
@@ -21,15 +51,51 @@ pub(crate) struct Opt {
     #[structopt(long = "if-exists", default_value = "error")]
     if_exists: IfExists,
 
+    /// When appending or upserting into a table which is missing some of our
+    /// columns, one of `fail`, `add-missing-columns` or `ignore-extra`.
+    #[structopt(long = "schema-evolution", default_value = "fail")]
+    schema_evolution: SchemaEvolution,
+
+    /// When appending or upserting into an existing table, how to match our
+    /// columns to its columns: one of `exact`, `case-insensitive` or
+    /// `snake-case` (which also matches `camelCase` to `snake_case`).
+    #[structopt(long = "match-columns", default_value = "exact")]
+    match_columns: ColumnMatching,
+
+    /// Compress the files we write: `none` or `gzip`.
+    #[structopt(long = "compression", default_value = "none")]
+    compression: OutputCompression,
+
     /// The schema to use (defaults to input table schema).
     #[structopt(long = "schema")]
     schema: Option<UnparsedLocator>,
 
+    /// Use the columns returned by this SQL query as the schema. The query is
+    /// described by the `--schema` database (or the input database) without
+    /// actually being run.
+    #[structopt(long = "schema-query")]
+    schema_query: Option<String>,
+
+    /// Infer the types of CSV columns from up to this many rows of data,
+    /// instead of treating every column as text.
+    #[structopt(long = "infer-schema-rows")]
+    infer_schema_rows: Option<usize>,
+
     /// Temporary directories, cloud storage buckets, datasets to use during
     /// transfer (can be repeated).
     #[structopt(long = "temporary")]
     temporaries: Vec<String>,
 
+    /// Fail instead of staging data in temporary storage, including any
+    /// temporaries in the configuration file.
+    #[structopt(long = "no-temp", conflicts_with = "temporaries")]
+    no_temp: bool,
+
+    /// How to choose between temporary locations with the same scheme:
+    /// `first` or `region:$REGION`.
+    #[structopt(long = "temporary-policy", default_value = "first")]
+    temporary_policy: TemporarySelection,
+
     /// Specify the approximate size of the CSV streams manipulated by
     /// `dbcrossbar`. This can be used to split a large input into multiple
     /// smaller outputs. Actual data streams may be bigger or smaller depending
@@ -50,14 +116,110 @@ pub(crate) struct Opt {
     #[structopt(long = "where")]
     where_clause: Option<String>,
 
+    /// Only copy these columns, renaming any written as `COL AS NEW_NAME`.
+    /// Example: "id, name AS customer_name".
+    #[structopt(long = "select")]
+    select: Option<ColumnSelection>,
+
+    /// Clean up text values, using a list of `trim`, `empty-as-null` and
+    /// `collapse-newlines`, optionally prefixed by `COL=` (can be repeated).
+    #[structopt(long = "cleanup")]
+    cleanups: Vec<ColumnRule<Cleanups>>,
+
+    /// Accept an extra pair of boolean values, of the form `[COL=]TRUE/FALSE`
+    /// (can be repeated).
+    #[structopt(long = "parse-bool")]
+    parse_bools: Vec<ColumnRule<BoolRule>>,
+
+    /// Accept an extra date format, of the form `[COL=]FORMAT`, where FORMAT
+    /// is something like `%d/%m/%Y` (can be repeated).
+    #[structopt(long = "parse-date")]
+    parse_dates: Vec<ColumnRule<DateFormat>>,
+
+    /// Accept an extra number format, of the form `[COL=]EXAMPLE`, where
+    /// EXAMPLE is something like `1.234,56` or `$1,234.56` (can be repeated).
+    #[structopt(long = "parse-number")]
+    parse_numbers: Vec<ColumnRule<NumberFormat>>,
+
+    /// Check each value against the declared column type. One of `warn` (log
+    /// a summary of columns with bad data) or `error` (fail on the first bad
+    /// value).
+    #[structopt(long = "validate")]
+    validate: Option<ValidationMode>,
+
+    /// When using `--validate`, only check one out of every N rows.
+    #[structopt(long = "validate-sample", default_value = "1")]
+    validate_sample: usize,
+
+    /// After copying, check that the destination has the same rows as the
+    /// source: `count` (compare the number of rows) or `checksum` (also
+    /// compare a checksum of their values).
+    #[structopt(long = "verify")]
+    verify: Option<VerifyMode>,
+
+    /// Check each row against the expectations in this JSON file, such as
+    /// `not_null`, `in_set`, `matches` and `between`.
+    #[structopt(long = "expectations")]
+    expectations: Option<PathBuf>,
+
+    /// Write per-column statistics (NULL count, min/max and approximate
+    /// distinct values) to this JSON file.
+    #[structopt(long = "column-stats")]
+    column_stats: Option<PathBuf>,
+
+    /// Add `_dbcrossbar_loaded_at`, `_dbcrossbar_job_id` and `_source_file`
+    /// columns to the destination.
+    #[structopt(long = "add-provenance-columns")]
+    add_provenance_columns: bool,
+
+    /// The job ID to use for `--add-provenance-columns` (defaults to a random
+    /// UUID).
+    #[structopt(long = "job-id", requires = "add-provenance-columns")]
+    job_id: Option<String>,
+
+    /// Normalize table and column names for the destination, using a list of
+    /// `lowercase`, `replace-illegal`, `max-length=N` and
+    /// `reserved-suffix=SUFFIX`.
+    #[structopt(long = "normalize-names")]
+    normalize_names: Option<NameRules>,
+
     /// How many data streams should we attempt to copy in parallel?
     #[structopt(long = "max-streams", short = "J", default_value = "4")]
     max_streams: usize,
 
+    /// Only copy the source streams belonging to this shard, of the form
+    /// `INDEX/COUNT`, where INDEX starts at 0. Used to split a large copy
+    /// between several processes. `{shard}` in the output table is replaced
+    /// by INDEX.
+    #[structopt(long = "shard")]
+    shard: Option<Shard>,
+
+    /// Record which streams have been written in this JSON file. If the copy
+    /// fails, running it again with the same file skips those streams. The
+    /// file is deleted once the copy succeeds.
+    #[structopt(long = "checkpoint")]
+    checkpoint: Option<PathBuf>,
+
     /// Display where we wrote our output data.
     #[structopt(long = "display-output-locators")]
     display_output_locators: bool,
 
+    /// Describe how we would copy the data, print the SQL we would run and
+    /// estimate the cloud costs, without copying anything.
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+
+    /// Hold a lock with this name while copying, using the destination (a
+    /// PostgreSQL advisory lock or a Cloud Storage lock object). If another
+    /// process holds the same lock, fail without copying anything.
+    #[structopt(long = "lock")]
+    lock: Option<String>,
+
+    /// Use the arguments from this profile in the configuration file. Flags
+    /// passed on the command line override the same flags in the profile.
+    #[structopt(long = "profile")]
+    profile: Option<String>,
+
     /// The input table.
     from_locator: UnparsedLocator,
 
@@ -67,6 +229,57 @@ pub(crate) struct Opt {
 
 /// Perform our schema conversion.
 pub(crate) async fn run(
+    ctx: Context,
+    config: Configuration,
+    enable_unstable: bool,
+    mut opt: Opt,
+) -> Result<()> {
+    // Fill in `{shard}` in our destination, and make sure shards won't
+    // overwrite each other.
+    if let Some(shard) = opt.shard {
+        opt.to_locator = shard.expand_destination(&opt.to_locator, &opt.if_exists)?;
+    }
+
+    // Our profile's arguments were already added by `args_with_profile`.
+    if let Some(profile) = &opt.profile {
+        debug!(ctx.log(), "using arguments from profile {:?}", profile);
+    }
+
+    // Honor --lock if passed.
+    let lock = match (&opt.lock, opt.dry_run) {
+        (Some(name), false) => {
+            let to_locator = opt.to_locator.parse(enable_unstable)?;
+            let to_args = DriverArguments::from_cli_args(&opt.to_args)?;
+            let lock_ctx = ctx.with_endpoints_from_args(&to_args)?;
+            let lock = to_locator
+                .lock(lock_ctx.clone(), name.to_owned())
+                .await?
+                .ok_or_else(|| {
+                    format_err!("don't know how to hold --lock using {}", to_locator)
+                })?;
+            debug!(ctx.log(), "acquired lock {:?}", name);
+            Some((lock_ctx, lock))
+        }
+        _ => None,
+    };
+
+    let result = copy(ctx.clone(), config, enable_unstable, opt).await;
+
+    // Release our lock, even if the copy failed.
+    if let Some((lock_ctx, lock)) = lock {
+        match (lock.release(lock_ctx).await, &result) {
+            (Err(err), Ok(())) => return Err(err),
+            (Err(err), Err(_)) => {
+                error!(ctx.log(), "could not release lock: {}", err);
+            }
+            (Ok(()), _) => {}
+        }
+    }
+    result
+}
+
+/// Copy our data, once we've acquired any lock.
+async fn copy(
     ctx: Context,
     config: Configuration,
     enable_unstable: bool,
@@ -76,36 +289,283 @@ pub(crate) async fn run(
     let from_locator = opt.from_locator.parse(enable_unstable)?;
     let to_locator = opt.to_locator.parse(enable_unstable)?;
 
+    // Parse our source arguments early, because they may specify API
+    // endpoints that we need to read the schema.
+    let from_args = DriverArguments::from_cli_args(&opt.from_args)?;
+
     // Figure out what table schema to use.
     let schema = {
         let schema_locator = schema_opt.as_ref().unwrap_or(&from_locator);
-        schema_locator
-            .schema(ctx.clone())
-            .await
-            .with_context(|_| format!("error reading schema from {}", schema_locator))?
-            .ok_or_else(|| {
-                format_err!("don't know how to read schema from {}", schema_locator)
-            })
+        let mut schema_ctx = ctx
+            .with_endpoints_from_args(&from_args)?
+            .with_infer_schema_rows(opt.infer_schema_rows);
+        if schema_opt.is_none() {
+            schema_ctx = schema_ctx.with_schema_source_args(from_args.clone());
+        }
+        if let Some(sql) = &opt.schema_query {
+            schema_locator
+                .query_schema(
+                    schema_ctx,
+                    sql.to_owned(),
+                    SourceArguments::new(from_args.clone(), None),
+                )
+                .await
+                .with_context(|_| {
+                    format!("error reading query schema from {}", schema_locator)
+                })?
+                .ok_or_else(|| {
+                    format_err!(
+                        "don't know how to read query schema from {}",
+                        schema_locator,
+                    )
+                })
+        } else {
+            schema_locator
+                .schema(schema_ctx)
+                .await
+                .with_context(|_| {
+                    format!("error reading schema from {}", schema_locator)
+                })?
+                .ok_or_else(|| {
+                    format_err!(
+                        "don't know how to read schema from {}",
+                        schema_locator
+                    )
+                })
+        }
     }?;
 
+    // Honor --select by only reading the selected columns, and then renaming
+    // them. From here on, `schema` describes the selected columns.
+    let (source_schema, schema) = match &opt.select {
+        Some(selection) => (
+            selection.source_schema(&schema)?,
+            selection.select_schema(&schema)?,
+        ),
+        None => (schema.clone(), schema),
+    };
+
     // Build our shared arguments.
-    let temporaries = opt.temporaries.clone();
-    let temporary_storage = TemporaryStorage::with_config(temporaries, &config)?;
-    let shared_args = SharedArguments::new(schema, temporary_storage, opt.max_streams);
+    let temporary_storage = if opt.no_temp {
+        TemporaryStorage::forbidden()
+    } else {
+        let temporaries = opt.temporaries.clone();
+        TemporaryStorage::with_config(temporaries, &config)?
+            .with_policy(Arc::new(opt.temporary_policy.clone()))
+    };
+    let shared_args = SharedArguments::new(
+        source_schema,
+        temporary_storage.clone(),
+        opt.max_streams,
+    );
+
+    // If we're normalizing names or adding provenance columns, our
+    // destination needs a different schema.
+    let to_driver = to_locator.driver()?;
+    let renamed_schema = opt
+        .normalize_names
+        .as_ref()
+        .map(|rules| rules.normalize_schema(to_driver, &schema))
+        .transpose()?;
+    report_names(
+        &ctx,
+        to_locator.as_ref(),
+        &schema,
+        renamed_schema.as_ref().unwrap_or(&schema),
+    )?;
+    let provenance = if opt.add_provenance_columns {
+        Some(Provenance::new(opt.job_id.clone()))
+    } else {
+        None
+    };
+    let dest_schema = renamed_schema.as_ref().unwrap_or(&schema);
+    let dest_schema = match &provenance {
+        Some(provenance) => provenance.add_columns_to_schema(dest_schema)?,
+        None => dest_schema.to_owned(),
+    };
+    let dest_shared_args =
+        SharedArguments::new(dest_schema.clone(), temporary_storage, opt.max_streams)
+            .with_stream_size(opt.stream_size.map(|size| size.size()));
 
     // Build our source arguments.
-    let from_args = DriverArguments::from_cli_args(&opt.from_args)?;
     let source_args = SourceArguments::new(from_args, opt.where_clause.clone());
 
     // Build our destination arguments.
     let to_args = DriverArguments::from_cli_args(&opt.to_args)?;
-    let dest_args = DestinationArguments::new(to_args, opt.if_exists);
+    let dest_args = DestinationArguments::new(
+        to_args,
+        opt.if_exists.clone(),
+        opt.schema_evolution,
+        opt.match_columns,
+        opt.compression,
+    );
 
     // Can we short-circuit this particular copy using special features of the
     // the source and destination, or do we need to pull the data down to the
     // local machine?
+    let normalize_options = NormalizeOptions {
+        cleanups: opt.cleanups,
+        bools: opt.parse_bools,
+        dates: opt.parse_dates,
+        numbers: opt.parse_numbers,
+    };
     let should_use_remote = opt.stream_size.is_none()
+        && normalize_options.is_empty()
+        && opt.validate.is_none()
+        && opt.expectations.is_none()
+        && opt.column_stats.is_none()
+        && opt.select.is_none()
+        && opt.shard.is_none()
+        && opt.checkpoint.is_none()
+        && opt.compression == OutputCompression::None
+        && renamed_schema.is_none()
+        && provenance.is_none()
         && to_locator.supports_write_remote_data(from_locator.as_ref());
+    check_copy_path(
+        from_locator.as_ref(),
+        to_locator.as_ref(),
+        should_use_remote,
+    )?;
+    check_limits(to_locator.as_ref(), &dest_schema)?;
+    check_lossy_columns(&ctx, to_locator.as_ref(), &dest_schema)?;
+    if opt.dry_run {
+        let cost = estimate_copy_cost(
+            &ctx,
+            from_locator.as_ref(),
+            to_locator.as_ref(),
+            shared_args.clone(),
+            source_args.clone(),
+            dest_args.clone(),
+            should_use_remote,
+        )
+        .await?;
+        println!(
+            "would copy {} to {} {}",
+            from_locator,
+            to_locator,
+            if should_use_remote {
+                "directly"
+            } else {
+                "through this machine"
+            },
+        );
+
+        // Describe the SQL and staging steps each driver would run.
+        let from_desc = from_locator.to_string();
+        let mut steps = vec![];
+        if should_use_remote {
+            let write_sql = to_locator
+                .dry_run_write(
+                    ctx.clone(),
+                    Some(from_locator),
+                    dest_shared_args,
+                    source_args,
+                    dest_args,
+                )
+                .await?;
+            steps.push((format!("copy from {}", from_desc), write_sql));
+        } else {
+            let read_sql = from_locator
+                .dry_run_read(ctx.clone(), shared_args.clone(), source_args.clone())
+                .await?;
+            steps.push((format!("read from {}", from_desc), read_sql));
+            let write_sql = to_locator
+                .dry_run_write(
+                    ctx.clone(),
+                    None,
+                    dest_shared_args,
+                    source_args,
+                    dest_args,
+                )
+                .await?;
+            steps.push((format!("write to {}", to_locator), write_sql));
+        }
+        if steps.iter().any(|(_, sql)| !sql.is_empty()) {
+            println!("statements:");
+            for (step, sql) in steps.into_iter().filter(|(_, sql)| !sql.is_empty()) {
+                println!("-- {}", step);
+                for statement in sql {
+                    // Staging steps are just comments, and don't need a `;`.
+                    let statement = statement.trim();
+                    let is_comment = statement
+                        .lines()
+                        .last()
+                        .is_none_or(|line| line.trim_start().starts_with("--"));
+                    if is_comment || statement.ends_with(';') {
+                        println!("{}", statement);
+                    } else {
+                        println!("{};", statement);
+                    }
+                }
+            }
+        }
+
+        println!("estimated costs:");
+        print!("{}", cost);
+        return Ok(());
+    }
+    let expectations = opt
+        .expectations
+        .as_deref()
+        .map(Expectations::from_json_file)
+        .transpose()?;
+    let column_stats = opt
+        .column_stats
+        .as_ref()
+        .map(|_| ColumnStatsCollector::new(&schema));
+    let checkpoint = opt
+        .checkpoint
+        .as_deref()
+        .map(|path| {
+            Checkpoint::load_or_new(
+                path,
+                &from_locator.to_string(),
+                &to_locator.to_string(),
+            )
+        })
+        .transpose()?
+        .map(Arc::new);
+    let checkpoint_file_dir = match &checkpoint {
+        Some(_) => checkpoint_file_dir(to_locator.as_ref(), &opt.if_exists)?,
+        None => None,
+    };
+
+    // Honor --verify by making sure we can tally our destination, and by
+    // tallying any rows that we're about to append to.
+    let from_desc = from_locator.to_string();
+    let dest_tally_before = match opt.verify {
+        Some(mode) => {
+            check_can_verify(
+                mode,
+                to_locator.as_ref(),
+                &opt.if_exists,
+                checkpoint.as_deref(),
+            )?;
+            if let IfExists::Append = opt.if_exists {
+                match tally_dest(
+                    &ctx,
+                    mode,
+                    to_locator.as_ref(),
+                    &dest_shared_args,
+                    &opt.to_args,
+                )
+                .await
+                {
+                    Ok(tally) => Some(tally),
+                    Err(err) => {
+                        debug!(ctx.log(), "assuming {} is empty: {}", to_locator, err);
+                        Some(RowTally::empty(mode))
+                    }
+                }
+            } else {
+                Some(RowTally::empty(mode))
+            }
+        }
+        None => None,
+    };
+    let mut source_tally = None;
+    let row_counter = opt.verify.map(|mode| RowCounter::new(mode, &dest_schema));
+
     let dests = if should_use_remote {
         // Build a logging context.
         let ctx = ctx.child(o!(
@@ -113,6 +573,21 @@ pub(crate) async fn run(
             "to_locator" => to_locator.to_string(),
         ));
 
+        // We can't see the rows in a remote transfer, so ask our source for
+        // them before we start.
+        if let Some(mode) = opt.verify {
+            source_tally = Some(
+                tally_rows(
+                    &ctx,
+                    mode,
+                    from_locator.as_ref(),
+                    shared_args.clone(),
+                    source_args.clone(),
+                )
+                .await?,
+            );
+        }
+
         // Perform a remote transfer.
         debug!(ctx.log(), "performing remote data transfer");
         let dests = to_locator
@@ -134,28 +609,152 @@ pub(crate) async fn run(
                 format_err!("don't know how to read data from {}", from_locator)
             })?;
 
+        // Honor --shard if passed.
+        if let Some(shard) = opt.shard {
+            data = shard.filter_streams(ctx.clone(), data);
+        }
+
+        // Honor --select if passed.
+        if let Some(selection) = &opt.select {
+            data = selection.select_csv_columns(ctx.clone(), data);
+        }
+
+        // Normalize our data using --cleanup and --parse-* options.
+        if !normalize_options.is_empty() {
+            data =
+                normalize_csvs(ctx.clone(), schema.clone(), normalize_options, data)?;
+        }
+
+        // Honor --validate if passed.
+        if let Some(mode) = opt.validate {
+            data = validate_csvs(
+                ctx.clone(),
+                schema.clone(),
+                mode,
+                opt.validate_sample,
+                data,
+            )?;
+        }
+
+        // Honor --expectations if passed.
+        if let Some(expectations) = expectations {
+            data =
+                check_expectations(ctx.clone(), schema.clone(), expectations, data)?;
+        }
+
+        // Honor --column-stats if passed.
+        if let Some(column_stats) = &column_stats {
+            data = column_stats.collect_from(ctx.clone(), data);
+        }
+
+        // Honor --normalize-names if passed.
+        if let Some(renamed_schema) = &renamed_schema {
+            data = rename_csv_headers(ctx.clone(), renamed_schema, data);
+        }
+
+        // Honor --add-provenance-columns if passed.
+        if let Some(provenance) = &provenance {
+            debug!(
+                ctx.log(),
+                "adding provenance for job {}",
+                provenance.job_id()
+            );
+            data = provenance.add_columns(ctx.clone(), data);
+        }
+
         // Honor --stream-size if passed.
         if let Some(stream_size) = opt.stream_size {
             let stream_size = stream_size.size();
             data = rechunk_csvs(ctx.clone(), stream_size, data)?;
         }
 
+        // Honor --verify if passed. We count streams skipped by --checkpoint,
+        // because they're already in the destination.
+        if let Some(row_counter) = &row_counter {
+            data = row_counter.count_rows(ctx.clone(), data);
+        }
+
+        // Honor --checkpoint if passed, by skipping any streams written by an
+        // earlier attempt. We do this last, so that we see the same stream
+        // names as the destination.
+        if let Some(checkpoint) = &checkpoint {
+            data = checkpoint.clone().filter_streams(ctx.clone(), data);
+        }
+
+        // Report how much data we've sent, for `--tui`, `--progress` and
+        // `--event-log`.
+        data = report_stream_progress(ctx.clone(), data);
+
         // Write data to output.
         let output_ctx = ctx.child(o!("to_locator" => to_locator.to_string()));
-        let result_stream = to_locator
-            .write_local_data(output_ctx, data, shared_args.clone(), dest_args)
+        if let Some(checkpoint) = &checkpoint {
+            let to_args = &opt.to_args;
+            let schema_evolution = opt.schema_evolution;
+            let match_columns = opt.match_columns;
+            let compression = opt.compression;
+            let dest_args_for = |if_exists: IfExists| -> Result<_> {
+                Ok(DestinationArguments::new(
+                    DriverArguments::from_cli_args(to_args)?,
+                    if_exists,
+                    schema_evolution,
+                    match_columns,
+                    compression,
+                ))
+            };
+            let stream_dest_for = checkpoint_file_dir.as_ref().map(|dir| {
+                move |name: &str| -> Result<BoxLocator> {
+                    format!("{}{}.csv{}", dir, name, compression.extension())
+                        .parse::<UnparsedLocator>()?
+                        .parse(enable_unstable)
+                }
+            });
+            let dests = write_with_checkpoint(
+                output_ctx,
+                to_locator.as_ref(),
+                data,
+                dest_shared_args.clone(),
+                dest_args_for,
+                stream_dest_for,
+                &opt.if_exists,
+                checkpoint,
+            )
             .await?;
+            stream::iter(dests).map(Ok).boxed()
+        } else {
+            let result_stream = to_locator
+                .write_local_data(
+                    output_ctx,
+                    data,
+                    dest_shared_args.clone(),
+                    dest_args,
+                )
+                .await?;
 
-        // Consume the stream of futures produced by `write_local_data`, allowing a
-        // certain degree of parallelism. This is where all the actual work happens,
-        // and this what controls how many "input driver" -> "output driver"
-        // connections are running at any given time.
-        result_stream
-            // Run up to `parallelism` futures in parallel.
-            .try_buffer_unordered(shared_args.max_streams())
-            .boxed()
+            // Consume the stream of futures produced by `write_local_data`,
+            // allowing a certain degree of parallelism. This is where all the
+            // actual work happens, and this what controls how many "input
+            // driver" -> "output driver" connections are running at any given
+            // time.
+            result_stream
+                // Run up to `parallelism` futures in parallel.
+                .try_buffer_unordered(shared_args.max_streams())
+                .boxed()
+        }
     };
 
+    // Report our progress as each destination is written.
+    let streams_written = Arc::new(AtomicU64::new(0));
+    let progress_ctx = ctx.clone();
+    let progress_count = streams_written.clone();
+    let dests = dests.inspect_ok(move |dest| {
+        let completed = progress_count.fetch_add(1, Ordering::SeqCst) + 1;
+        progress_ctx.emit(Event::Progress {
+            message: format!("wrote {}", dest),
+            completed,
+            total: None,
+        });
+    });
+
     // Optionally display `dests`, depending on a combination of
     // `--display-output-locators` and the defaults for `to_locator`.
     let display_output_locators = match (
@@ -205,5 +804,184 @@ pub(crate) async fn run(
         let dests = dests.try_collect::<Vec<_>>().boxed().await?;
         debug!(ctx.log(), "destination locators: {:?}", dests);
     }
+
+    ctx.emit(Event::Metric {
+        name: "streams_written".to_owned(),
+        value: streams_written.load(Ordering::SeqCst),
+    });
+
+    // Write our column statistics, now that we've seen all the data.
+    if let (Some(column_stats), Some(path)) = (&column_stats, &opt.column_stats) {
+        column_stats.write_json(path)?;
+    }
+
+    // We've finished, so we won't need to resume.
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.remove()?;
+    }
+
+    // Honor --verify, now that everything has been written.
+    if let (Some(mode), Some(before)) = (opt.verify, dest_tally_before) {
+        let expected = source_tally
+            .or_else(|| row_counter.as_ref().map(|counter| counter.tally()))
+            .expect("should have tallied source rows");
+        let after = tally_dest(
+            &ctx,
+            mode,
+            to_locator.as_ref(),
+            &dest_shared_args,
+            &opt.to_args,
+        )
+        .await?;
+        expected.check(&from_desc, after.appended_since(before), &to_locator)?;
+        debug!(
+            ctx.log(),
+            "verified {} rows using --verify={}", expected.rows, mode
+        );
+    }
     Ok(())
 }
+
+/// Make sure that we can honor `--verify=mode` for this copy.
+fn check_can_verify(
+    mode: VerifyMode,
+    to_locator: &dyn Locator,
+    if_exists: &IfExists,
+    checkpoint: Option<&Checkpoint>,
+) -> Result<()> {
+    if let IfExists::Upsert(_) = if_exists {
+        // Upserts may replace rows instead of adding them.
+        return Err(format_err!(
+            "cannot use --verify with --if-exists={}",
+            if_exists,
+        ));
+    }
+    if let (IfExists::Append, Some(_)) = (if_exists, checkpoint) {
+        // When resuming, we don't know how many rows were there originally.
+        return Err(format_err!(
+            "cannot use --verify with --checkpoint and --if-exists=append"
+        ));
+    }
+    let caps = to_locator.capabilities()?;
+    let can_count = mode == VerifyMode::Count && caps.count;
+    let writes_stdout = matches!(
+        to_locator.display_output_locators(),
+        DisplayOutputLocators::Never
+    );
+    if writes_stdout || !(caps.local_data || can_count) {
+        return Err(format_err!(
+            "cannot use --verify={} with {}, because we can't read it back",
+            mode,
+            to_locator,
+        ));
+    }
+    Ok(())
+}
+
+/// Tally the rows in our destination, for `--verify`.
+async fn tally_dest(
+    ctx: &Context,
+    mode: VerifyMode,
+    to_locator: &dyn Locator,
+    shared_args: &SharedArguments<Unverified>,
+    to_args: &[String],
+) -> Result<RowTally> {
+    // Drivers parse source and destination arguments differently, so we only
+    // pass along any API endpoints.
+    let to_args = DriverArguments::from_cli_args(to_args)?;
+    let ctx = ctx.with_endpoints_from_args(&to_args)?;
+    let source_args = SourceArguments::new(DriverArguments::default(), None);
+    let tally = tally_rows(&ctx, mode, to_locator, shared_args.clone(), source_args)
+        .await
+        .with_context(|_| format!("error verifying {}", to_locator))?;
+    Ok(tally)
+}
+
+/// With `--checkpoint`, we write each stream separately. If `to_locator` can
+/// append (or upsert) each stream, return `None`. Otherwise, if `to_locator` is
+/// a directory, return it, and we'll write each stream to its own file there.
+fn checkpoint_file_dir(
+    to_locator: &dyn Locator,
+    if_exists: &IfExists,
+) -> Result<Option<String>> {
+    let can_append = to_locator
+        .capabilities()?
+        .if_exists
+        .iter()
+        .any(|name| name == "append");
+    let dest = to_locator.to_string();
+    if can_append || matches!(if_exists, IfExists::Upsert(_)) {
+        Ok(None)
+    } else if dest.ends_with('/') {
+        Ok(Some(dest))
+    } else {
+        Err(format_err!(
+            "cannot use --checkpoint with {}, because it can't append streams (try writing to a directory)",
+            to_locator,
+        ))
+    }
+}
+
+/// Write each stream in `data` to `to_locator` using a separate call to
+/// `write_local_data`, and record it in `checkpoint` once it has been written.
+///
+/// If we have a `stream_dest_for` function, we use it to find a separate
+/// destination for each stream, and we write each stream there using
+/// `if_exists`. Otherwise, the first stream is written on its own using
+/// `if_exists`, so that it can prepare the destination, and the rest are
+/// appended (or upserted) in parallel. When resuming, the destination has
+/// already been prepared, so we append every stream.
+#[allow(clippy::too_many_arguments)]
+async fn write_with_checkpoint(
+    ctx: Context,
+    to_locator: &dyn Locator,
+    mut data: BoxStream<CsvStream>,
+    shared_args: SharedArguments<Unverified>,
+    dest_args_for: impl Fn(IfExists) -> Result<DestinationArguments<Unverified>>,
+    stream_dest_for: Option<impl Fn(&str) -> Result<BoxLocator>>,
+    if_exists: &IfExists,
+    checkpoint: &Checkpoint,
+) -> Result<Vec<BoxLocator>> {
+    let append = match (&stream_dest_for, if_exists) {
+        (Some(_), _) => if_exists.clone(),
+        (None, IfExists::Upsert(keys)) => IfExists::Upsert(keys.clone()),
+        (None, _) => IfExists::Append,
+    };
+    let write_stream = |stream: CsvStream, if_exists: IfExists| {
+        let ctx = ctx.child(o!("stream" => stream.name.clone()));
+        let shared_args = shared_args.clone();
+        let dest_args = dest_args_for(if_exists);
+        let stream_dest = stream_dest_for
+            .as_ref()
+            .map(|stream_dest_for| stream_dest_for(&stream.name))
+            .transpose();
+        async move {
+            let stream_dest = stream_dest?;
+            let to_locator = stream_dest.as_deref().unwrap_or(to_locator);
+            let name = stream.name.clone();
+            let data = stream::once(async { Ok(stream) }).boxed();
+            let dests = to_locator
+                .write_local_data(ctx, data, shared_args, dest_args?)
+                .await?
+                .consume_with_parallelism(1)
+                .await?;
+            checkpoint.mark_written(&name)?;
+            Ok::<_, Error>(dests)
+        }
+    };
+
+    let mut dests = vec![];
+    if !checkpoint.is_resuming() {
+        if let Some(stream) = data.try_next().await? {
+            dests.extend(write_stream(stream, if_exists.clone()).await?);
+        }
+    }
+    let max_streams = shared_args.max_streams();
+    let rest = data
+        .map_ok(|stream| write_stream(stream, append.clone()))
+        .try_buffer_unordered(max_streams)
+        .try_collect::<Vec<_>>()
+        .await?;
+    dests.extend(rest.into_iter().flatten());
+    Ok(dests)
+}
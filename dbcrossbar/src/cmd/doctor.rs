@@ -0,0 +1,56 @@
+//! The `doctor` subcommand.
+
+use common_failures::Result;
+use dbcrossbarlib::{
+    config::Configuration,
+    doctor::{run_checks, CheckStatus},
+    Context,
+};
+use failure::format_err;
+use structopt::{self, StructOpt};
+
+/// Doctor arguments.
+#[derive(Debug, StructOpt)]
+pub(crate) struct Opt {
+    /// Temporary directories, cloud storage buckets, datasets to check (can be
+    /// repeated). Temporaries from `dbcrossbar config` are always checked.
+    #[structopt(long = "temporary")]
+    temporaries: Vec<String>,
+
+    /// Locators to check.
+    locators: Vec<String>,
+}
+
+/// Check our environment and print a report.
+pub(crate) async fn run(
+    ctx: Context,
+    config: Configuration,
+    enable_unstable: bool,
+    opt: Opt,
+) -> Result<()> {
+    let checks = run_checks(
+        &ctx,
+        &config,
+        &opt.temporaries,
+        &opt.locators,
+        enable_unstable,
+    )
+    .await;
+
+    let mut failures = 0;
+    for check in &checks {
+        println!("[{:<4}] {}: {}", check.status, check.name, check.message);
+        if let Some(fix) = &check.fix {
+            println!("       fix: {}", fix);
+        }
+        if check.status == CheckStatus::Failed {
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        Err(format_err!("{} check(s) failed", failures))
+    } else {
+        Ok(())
+    }
+}
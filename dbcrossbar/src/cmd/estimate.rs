@@ -0,0 +1,56 @@
+//! The `estimate` subcommand.
+
+use common_failures::Result;
+use dbcrossbarlib::{
+    config::Configuration, Context, DriverArguments, SourceArguments, UnparsedLocator,
+};
+use failure::format_err;
+use structopt::{self, StructOpt};
+
+/// Estimate arguments.
+#[derive(Debug, StructOpt)]
+pub(crate) struct Opt {
+    /// Pass an extra argument of the form `key=value` to the source driver.
+    #[structopt(long = "from-arg")]
+    from_args: Vec<String>,
+
+    /// Print the estimate as JSON.
+    #[structopt(long = "json")]
+    json: bool,
+
+    /// The locator specifying the data to estimate.
+    locator: UnparsedLocator,
+}
+
+/// Estimate the size of the data at a locator.
+pub(crate) async fn run(
+    ctx: Context,
+    _config: Configuration,
+    enable_unstable: bool,
+    opt: Opt,
+) -> Result<()> {
+    let locator = opt.locator.parse(enable_unstable)?;
+    let from_args = DriverArguments::from_cli_args(&opt.from_args)?;
+    let source_args = SourceArguments::new(from_args, None);
+
+    let estimate = locator
+        .estimate(ctx.clone(), source_args)
+        .await?
+        .ok_or_else(|| {
+            format_err!("don't know how to estimate size of {}", locator)
+        })?;
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&estimate)?);
+    } else {
+        let display = |value: Option<u64>| {
+            value.map_or_else(|| "unknown".to_owned(), |v| v.to_string())
+        };
+        println!("rows: {}", display(estimate.rows));
+        println!("bytes: {}", display(estimate.bytes));
+        if let Some(files) = estimate.files {
+            println!("files: {}", files);
+        }
+        println!("method: {}", estimate.method);
+    }
+    Ok(())
+}
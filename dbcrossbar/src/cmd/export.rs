@@ -0,0 +1,261 @@
+//! The `export` subcommand.
+
+use common_failures::{display::DisplayCausesAndBacktraceExt, Result};
+use dbcrossbarlib::{
+    config::Configuration, copy_path::check_copy_path, drivers::bigquery::list_tables,
+    events::Event, limits::check_limits, lossy_types::check_lossy_columns,
+    shard::Shard, BoxLocator, ColumnMatching, Context, DestinationArguments,
+    DriverArguments, IfExists, OutputCompression, SchemaEvolution, SharedArguments,
+    SourceArguments, TemporarySelection, TemporaryStorage, UnparsedLocator,
+};
+use failure::{format_err, ResultExt};
+use futures::{stream, StreamExt, TryStreamExt};
+use slog::{debug, error, info, o};
+use std::{
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use structopt::{self, StructOpt};
+
+/// The placeholder in `to_template` which is replaced by each table name.
+const TABLE_PLACEHOLDER: &str = "{table}";
+
+/// Export arguments.
+#[derive(Debug, StructOpt)]
+pub(crate) struct Opt {
+    /// One of `error`, `overwrite`, `append` or `upsert-on:COL`.
+    #[structopt(long = "if-exists", default_value = "error")]
+    if_exists: IfExists,
+
+    /// Only export tables whose names match this pattern, which may contain
+    /// `*` and `?`.
+    #[structopt(long = "tables")]
+    tables: Option<String>,
+
+    /// Only export the tables belonging to this shard, of the form
+    /// `INDEX/COUNT`, where INDEX starts at 0. Used to split an export between
+    /// several processes.
+    #[structopt(long = "shard")]
+    shard: Option<Shard>,
+
+    /// Temporary directories, cloud storage buckets, datasets to use during
+    /// transfer (can be repeated).
+    #[structopt(long = "temporary")]
+    temporaries: Vec<String>,
+
+    /// How to choose between temporary locations with the same scheme:
+    /// `first` or `region:$REGION`.
+    #[structopt(long = "temporary-policy", default_value = "first")]
+    temporary_policy: TemporarySelection,
+
+    /// Pass an extra argument of the form `key=value` to the source driver.
+    #[structopt(long = "from-arg")]
+    from_args: Vec<String>,
+
+    /// Pass an extra argument of the form `key=value` to the destination
+    /// driver.
+    #[structopt(long = "to-arg")]
+    to_args: Vec<String>,
+
+    /// How many tables should we export in parallel?
+    #[structopt(long = "max-tables", short = "P", default_value = "2")]
+    max_tables: usize,
+
+    /// How many data streams should we attempt to copy in parallel for each
+    /// table?
+    #[structopt(long = "max-streams", short = "J", default_value = "4")]
+    max_streams: usize,
+
+    /// The dataset to export, of the form `bigquery:$PROJECT:$DATASET`.
+    from_dataset: String,
+
+    /// Where to write each table. `{table}` will be replaced by the name of
+    /// the table.
+    to_template: String,
+}
+
+/// Export all the tables in a dataset.
+pub(crate) async fn run(
+    ctx: Context,
+    config: Configuration,
+    enable_unstable: bool,
+    opt: Opt,
+) -> Result<()> {
+    if !opt.to_template.contains(TABLE_PLACEHOLDER) {
+        return Err(format_err!(
+            "destination {:?} must contain {}, or every table would be written to the same place",
+            opt.to_template,
+            TABLE_PLACEHOLDER,
+        ));
+    }
+    if opt.max_tables == 0 {
+        return Err(format_err!("--max-tables must be at least 1"));
+    }
+
+    // List the tables we want to export.
+    let from_args = DriverArguments::from_cli_args(&opt.from_args)?;
+    let tables = list_tables(
+        ctx.clone(),
+        &opt.from_dataset,
+        opt.tables.as_deref(),
+        &from_args,
+    )
+    .await
+    .with_context(|_| format!("could not list tables in {}", opt.from_dataset))?;
+    let tables = match opt.shard {
+        Some(shard) => tables
+            .into_iter()
+            .filter(|table| shard.includes(&table.to_string()))
+            .collect(),
+        None => tables,
+    };
+    info!(
+        ctx.log(),
+        "exporting {} tables from {}",
+        tables.len(),
+        opt.from_dataset,
+    );
+
+    // Export each table, keeping going if any individual table fails.
+    let temporary_storage =
+        TemporaryStorage::with_config(opt.temporaries.clone(), &config)?
+            .with_policy(Arc::new(opt.temporary_policy.clone()));
+    let total = u64::try_from(tables.len()).unwrap_or(u64::MAX);
+    let exported = Arc::new(AtomicU64::new(0));
+    let opt = &opt;
+    let temporary_storage = &temporary_storage;
+    let results = stream::iter(tables)
+        .map(|table| {
+            let ctx = ctx.clone();
+            let exported = exported.clone();
+            async move {
+                let table_name = table.to_string();
+                let bare_name = table_name
+                    .rsplit('.')
+                    .next()
+                    .expect("always have at least one component")
+                    .to_owned();
+                let to_locator: UnparsedLocator = opt
+                    .to_template
+                    .replace(TABLE_PLACEHOLDER, &bare_name)
+                    .parse()?;
+                let to_locator = to_locator.parse(enable_unstable)?;
+                let ctx = ctx.child(o!(
+                    "from_locator" => table_name.clone(),
+                    "to_locator" => to_locator.to_string(),
+                ));
+                let result = export_table(
+                    &ctx,
+                    opt,
+                    temporary_storage,
+                    Box::new(table),
+                    to_locator,
+                )
+                .await;
+                match &result {
+                    Ok(()) => {
+                        let completed = exported.fetch_add(1, Ordering::SeqCst) + 1;
+                        ctx.emit(Event::Progress {
+                            message: format!("exported {}", table_name),
+                            completed,
+                            total: Some(total),
+                        });
+                    }
+                    Err(err) => error!(
+                        ctx.log(),
+                        "could not export {}: {}",
+                        table_name,
+                        err.display_causes_without_backtrace(),
+                    ),
+                }
+                Ok::<_, failure::Error>(result.is_ok())
+            }
+        })
+        .buffer_unordered(opt.max_tables)
+        .try_collect::<Vec<bool>>()
+        .await?;
+
+    let failed = results.iter().filter(|&&ok| !ok).count();
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "could not export {} of {} tables",
+            failed,
+            results.len(),
+        ))
+    }
+}
+
+/// Copy `from_locator` to `to_locator`.
+async fn export_table(
+    ctx: &Context,
+    opt: &Opt,
+    temporary_storage: &TemporaryStorage,
+    from_locator: BoxLocator,
+    to_locator: BoxLocator,
+) -> Result<()> {
+    let from_args = DriverArguments::from_cli_args(&opt.from_args)?;
+    let schema_ctx = ctx
+        .with_endpoints_from_args(&from_args)?
+        .with_schema_source_args(from_args.clone());
+    let schema = from_locator
+        .schema(schema_ctx)
+        .await
+        .with_context(|_| format!("error reading schema from {}", from_locator))?
+        .ok_or_else(|| {
+            format_err!("don't know how to read schema from {}", from_locator)
+        })?;
+    check_limits(to_locator.as_ref(), &schema)?;
+    check_lossy_columns(ctx, to_locator.as_ref(), &schema)?;
+    let shared_args =
+        SharedArguments::new(schema, temporary_storage.clone(), opt.max_streams);
+    let source_args = SourceArguments::new(from_args, None);
+    let to_args = DriverArguments::from_cli_args(&opt.to_args)?;
+    let dest_args = DestinationArguments::new(
+        to_args,
+        opt.if_exists.clone(),
+        SchemaEvolution::default(),
+        ColumnMatching::default(),
+        OutputCompression::default(),
+    );
+
+    let should_use_remote =
+        to_locator.supports_write_remote_data(from_locator.as_ref());
+    check_copy_path(
+        from_locator.as_ref(),
+        to_locator.as_ref(),
+        should_use_remote,
+    )?;
+    if should_use_remote {
+        debug!(ctx.log(), "performing remote data transfer");
+        to_locator
+            .write_remote_data(
+                ctx.clone(),
+                from_locator,
+                shared_args,
+                source_args,
+                dest_args,
+            )
+            .await?;
+    } else {
+        debug!(ctx.log(), "performing local data transfer");
+        let data = from_locator
+            .local_data(ctx.clone(), shared_args.clone(), source_args)
+            .await?
+            .ok_or_else(|| {
+                format_err!("don't know how to read data from {}", from_locator)
+            })?;
+        let max_streams = shared_args.max_streams();
+        to_locator
+            .write_local_data(ctx.clone(), data, shared_args, dest_args)
+            .await?
+            .try_buffer_unordered(max_streams)
+            .try_collect::<Vec<_>>()
+            .await?;
+    }
+    Ok(())
+}
@@ -2,6 +2,7 @@
 
 use common_failures::Result;
 use dbcrossbarlib::{
+    capabilities::Capabilities,
     config::Configuration,
     drivers::{all_drivers, find_driver},
     Context,
@@ -11,6 +12,10 @@ use structopt::{self, StructOpt};
 /// Schema conversion arguments.
 #[derive(Debug, StructOpt)]
 pub(crate) struct Opt {
+    /// Print the capabilities of each driver as JSON, for use by other tools.
+    #[structopt(long = "json")]
+    json: bool,
+
     /// Print help about a specific driver name.
     driver: Option<String>,
 }
@@ -25,11 +30,31 @@ pub(crate) async fn run(
     if let Some(name) = &opt.driver {
         let scheme = format!("{}:", name);
         let driver = find_driver(&scheme, enable_unstable)?;
+        if opt.json {
+            println!("{}", serde_json::to_string_pretty(&driver.capabilities())?);
+            return Ok(());
+        }
         println!("{} features:", name);
         print!("{}", driver.features());
+        let capabilities = driver.capabilities();
+        if !capabilities.remote_sources.is_empty() {
+            println!("- cp TO directly FROM:");
+            println!("  {}", capabilities.remote_sources.join(" "));
+        }
+        if capabilities.write_local_data {
+            println!("- cp TO supports types:");
+            println!("  {}", capabilities.data_types.join(" "));
+        }
         if driver.is_unstable() {
             println!("\nThis driver is UNSTABLE and may change without warning.");
         }
+    } else if opt.json {
+        let capabilities = all_drivers()
+            .iter()
+            .filter(|driver| !driver.is_unstable() || enable_unstable)
+            .map(|driver| driver.capabilities())
+            .collect::<Vec<Capabilities>>();
+        println!("{}", serde_json::to_string_pretty(&capabilities)?);
     } else {
         println!("Supported drivers:");
         for driver in all_drivers() {
@@ -1,18 +1,32 @@
 //! Command parsing.
 
-use dbcrossbarlib::{config::Configuration, tokio_glue::BoxFuture, Context};
+use common_failures::Result;
+use dbcrossbarlib::{
+    config::{flag_name, Configuration},
+    tokio_glue::BoxFuture,
+    Context, FileIo,
+};
 use futures::FutureExt;
+use std::{collections::HashSet, ffi::OsString, path::PathBuf};
+use url::Url;
 //use structopt::StructOpt;
 use structopt_derive::StructOpt;
 
 use crate::logging::LogFormat;
 
+pub(crate) mod auth;
+pub(crate) mod bench;
 pub(crate) mod config;
 pub(crate) mod count;
 pub(crate) mod cp;
+pub(crate) mod doctor;
+pub(crate) mod estimate;
+pub(crate) mod export;
 pub(crate) mod features;
 pub(crate) mod license;
+pub(crate) mod pipeline;
 pub(crate) mod schema;
+pub(crate) mod serve;
 
 /// Command-line options, parsed using `structopt`.
 #[derive(Debug, StructOpt)]
@@ -29,6 +43,43 @@ pub(crate) struct Opt {
     #[structopt(long = "log-extra")]
     pub(crate) log_extra: Vec<String>,
 
+    /// Write structured events (logs, warnings, progress and metrics) to this
+    /// file, one JSON object per line.
+    #[structopt(long = "event-log", parse(from_os_str))]
+    pub(crate) event_log: Option<PathBuf>,
+
+    /// Append every SQL statement we run against a destination to this file,
+    /// one JSON object per line, with timings and any errors.
+    #[structopt(long = "sql-audit-log", parse(from_os_str))]
+    pub(crate) sql_audit_log: Option<PathBuf>,
+
+    /// Fail if any warnings are reported, including columns which the
+    /// destination can't store without losing information.
+    #[structopt(long = "deny-warnings")]
+    pub(crate) deny_warnings: bool,
+
+    /// How to read and write local files: `tokio`, or `threads` to give each
+    /// file its own thread with larger buffers.
+    #[structopt(long = "file-io", default_value = "tokio")]
+    pub(crate) file_io: FileIo,
+
+    /// When the command finishes, `POST` a report to this webhook URL. Slack
+    /// webhooks receive a short message, and other URLs receive a JSON report.
+    /// May be passed multiple times.
+    #[structopt(long = "notify")]
+    pub(crate) notify: Vec<Url>,
+
+    /// Show a live display of per-stream progress, the current stage,
+    /// throughput, retries and recent errors, instead of logging to standard
+    /// error.
+    #[structopt(long = "tui")]
+    pub(crate) tui: bool,
+
+    /// Print the total bytes and rows sent and our throughput to standard
+    /// error every few seconds, in addition to our usual logs.
+    #[structopt(long = "progress")]
+    pub(crate) progress: bool,
+
     /// Enable unstable, experimental features.
     #[structopt(long = "enable-unstable")]
     pub(crate) enable_unstable: bool,
@@ -42,6 +93,25 @@ pub(crate) struct Opt {
 #[derive(Debug, StructOpt)]
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum Command {
+    /// Log in to cloud services interactively.
+    #[structopt(name = "auth")]
+    Auth {
+        #[structopt(flatten)]
+        command: auth::Opt,
+    },
+
+    /// Measure how fast we can copy synthetic data to various destinations.
+    #[structopt(name = "bench")]
+    #[structopt(after_help = r#"EXAMPLE LOCATORS:
+    csv:/tmp/bench/
+    postgres://localhost:5432/db#bench
+    bigquery:project:dataset.bench
+"#)]
+    Bench {
+        #[structopt(flatten)]
+        command: bench::Opt,
+    },
+
     /// Update configuration.
     #[structopt(name = "config")]
     Config {
@@ -71,6 +141,43 @@ pub(crate) enum Command {
         command: cp::Opt,
     },
 
+    /// Check credentials, tools and connectivity, and suggest fixes.
+    #[structopt(name = "doctor")]
+    #[structopt(after_help = r#"EXAMPLE LOCATORS:
+    postgres://localhost:5432/db#table
+    bigquery:project:dataset.table
+    gs://bucket/dir/
+"#)]
+    Doctor {
+        #[structopt(flatten)]
+        command: doctor::Opt,
+    },
+
+    /// Estimate how many rows and bytes a locator contains, without copying it.
+    #[structopt(name = "estimate")]
+    #[structopt(after_help = r#"EXAMPLE LOCATORS:
+    postgres://localhost:5432/db#table
+    bigquery:project:dataset.table
+    gs://bucket/dir/
+    csv:file.csv
+"#)]
+    Estimate {
+        #[structopt(flatten)]
+        command: estimate::Opt,
+    },
+
+    /// Export every table in a BigQuery dataset.
+    #[structopt(name = "export")]
+    #[structopt(after_help = r#"EXAMPLES:
+    dbcrossbar export bigquery:project:dataset 'gs://bucket/export/{table}/'
+    dbcrossbar export --tables='events_*' bigquery:project:dataset \
+        'postgres://localhost:5432/db#{table}'
+"#)]
+    Export {
+        #[structopt(flatten)]
+        command: export::Opt,
+    },
+
     /// List available drivers and supported features.
     #[structopt(name = "features")]
     Features {
@@ -85,15 +192,44 @@ pub(crate) enum Command {
         command: license::Opt,
     },
 
+    /// Run a multi-step pipeline described in a JSON file.
+    #[structopt(name = "pipeline")]
+    #[structopt(after_help = r#"EXAMPLE PIPELINE:
+    {
+      "steps": [
+        { "name": "extract", "args": ["cp", "postgres:...", "csv:/tmp/t/"] },
+        { "name": "load", "depends_on": ["extract"],
+          "args": ["cp", "csv:/tmp/t/", "bigquery:..."] },
+        { "name": "analyze", "depends_on": ["load"],
+          "exec": ["bq", "query", "..."] }
+      ]
+    }
+"#)]
+    Pipeline {
+        #[structopt(flatten)]
+        command: pipeline::Opt,
+    },
+
     /// Schema-related commands.
     Schema {
         #[structopt(flatten)]
         command: schema::Opt,
     },
+
+    /// Run copy jobs submitted over an HTTP API.
+    #[structopt(name = "serve")]
+    Serve {
+        #[structopt(flatten)]
+        command: serve::Opt,
+    },
 }
 
 pub(crate) fn run(ctx: Context, config: Configuration, opt: Opt) -> BoxFuture<()> {
     match opt.cmd {
+        Command::Auth { command } => auth::run(ctx, config, command).boxed(),
+        Command::Bench { command } => {
+            bench::run(ctx, config, opt.enable_unstable, command).boxed()
+        }
         Command::Config { command } => config::run(ctx, config, command).boxed(),
 
         Command::Count { command } => {
@@ -102,14 +238,72 @@ pub(crate) fn run(ctx: Context, config: Configuration, opt: Opt) -> BoxFuture<()
         Command::Cp { command } => {
             cp::run(ctx, config, opt.enable_unstable, command).boxed()
         }
+        Command::Doctor { command } => {
+            doctor::run(ctx, config, opt.enable_unstable, command).boxed()
+        }
+        Command::Estimate { command } => {
+            estimate::run(ctx, config, opt.enable_unstable, command).boxed()
+        }
+        Command::Export { command } => {
+            export::run(ctx, config, opt.enable_unstable, command).boxed()
+        }
         Command::Features { command } => {
             features::run(ctx, config, opt.enable_unstable, command).boxed()
         }
         Command::License { command } => {
             license::run(ctx, config, opt.enable_unstable, command).boxed()
         }
+        Command::Pipeline { command } => {
+            pipeline::run(ctx, config, opt.enable_unstable, command).boxed()
+        }
         Command::Schema { command } => {
             schema::run(ctx, config, opt.enable_unstable, command).boxed()
         }
+        Command::Serve { command } => {
+            serve::run(ctx, config, opt.enable_unstable, command).boxed()
+        }
+    }
+}
+
+/// If `args` contains `--profile NAME` or `--profile=NAME`, insert the
+/// arguments from that profile in `config` right after it. Any flags which
+/// appear in `args` override the same flags in the profile.
+pub(crate) fn args_with_profile(
+    config: &Configuration,
+    args: Vec<OsString>,
+) -> Result<Vec<OsString>> {
+    // Find the profile name, and where to insert its arguments.
+    let mut profile = None;
+    let mut iter = args.iter().enumerate();
+    while let Some((idx, arg)) = iter.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--" {
+            break;
+        } else if arg == "--profile" {
+            if let Some((_, name)) = iter.next() {
+                profile = Some((idx + 2, name.to_string_lossy().into_owned()));
+            }
+            break;
+        } else if let Some(name) = arg.strip_prefix("--profile=") {
+            profile = Some((idx + 1, name.to_owned()));
+            break;
+        }
+    }
+    let (insert_at, name) = match profile {
+        Some(profile) => profile,
+        None => return Ok(args),
+    };
+
+    let flags = args
+        .iter()
+        .filter_map(|arg| arg.to_str().and_then(flag_name))
+        .collect::<HashSet<_>>();
+    let mut expanded = args[..insert_at].to_vec();
+    for arg in config.profile_args(&name)? {
+        if flag_name(&arg).is_none_or(|flag| !flags.contains(flag)) {
+            expanded.push(OsString::from(arg));
+        }
     }
+    expanded.extend_from_slice(&args[insert_at..]);
+    Ok(expanded)
 }
@@ -0,0 +1,173 @@
+//! The `pipeline` subcommand.
+
+use common_failures::{display::DisplayCausesAndBacktraceExt, Result};
+use dbcrossbarlib::{
+    config::Configuration,
+    events::Event,
+    pipeline::{Pipeline, PipelineState, Step},
+    Context,
+};
+use failure::{format_err, ResultExt};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use slog::{error, info, o};
+use std::{convert::TryFrom, env, fs, path::PathBuf};
+use structopt::{self, StructOpt};
+use tokio::process::Command;
+
+/// Pipeline arguments.
+#[derive(Debug, StructOpt)]
+pub(crate) struct Opt {
+    /// Forget which steps finished during any earlier run, and run every step.
+    #[structopt(long = "restart")]
+    restart: bool,
+
+    /// Where to record which steps have finished. Defaults to the pipeline
+    /// file with `.state.json` appended.
+    #[structopt(long = "state", parse(from_os_str))]
+    state: Option<PathBuf>,
+
+    /// How many independent steps should we run in parallel?
+    #[structopt(long = "max-steps", short = "P", default_value = "1")]
+    max_steps: usize,
+
+    /// A JSON file describing the steps to run.
+    #[structopt(parse(from_os_str))]
+    pipeline: PathBuf,
+}
+
+/// Run each step of a pipeline after its dependencies, resuming after the
+/// last failure if possible.
+pub(crate) async fn run(
+    ctx: Context,
+    _config: Configuration,
+    _enable_unstable: bool,
+    opt: Opt,
+) -> Result<()> {
+    if opt.max_steps == 0 {
+        return Err(format_err!("--max-steps must be at least 1"));
+    }
+    let pipeline = Pipeline::from_path(&opt.pipeline)?;
+    let state_path = opt.state.clone().unwrap_or_else(|| {
+        let mut path = opt.pipeline.clone().into_os_string();
+        path.push(".state.json");
+        PathBuf::from(path)
+    });
+    let mut state = if opt.restart {
+        PipelineState::default()
+    } else {
+        PipelineState::load(&state_path)?
+    };
+
+    // Skip anything which finished during an earlier run.
+    let mut finished = state.finished_steps(&pipeline);
+    for step in pipeline.steps() {
+        if finished.contains(&step.name) {
+            info!(ctx.log(), "skipping step {} (already finished)", step.name);
+        }
+    }
+    let total = u64::try_from(pipeline.steps().len()).unwrap_or(u64::MAX);
+
+    // Start steps as soon as their dependencies finish, until something
+    // fails.
+    let mut started = finished.clone();
+    let mut running = FuturesUnordered::new();
+    let mut failed = vec![];
+    loop {
+        while failed.is_empty() && running.len() < opt.max_steps {
+            let next = pipeline.steps().iter().find(|step| {
+                !started.contains(&step.name)
+                    && step.depends_on.iter().all(|dep| finished.contains(dep))
+            });
+            let step = match next {
+                Some(step) => step,
+                None => break,
+            };
+            started.insert(step.name.clone());
+            let ctx = ctx.child(o!("step" => step.name.clone()));
+            running.push(
+                async move {
+                    let result = run_step(&ctx, step).await;
+                    (ctx, step, result)
+                }
+                .boxed(),
+            );
+        }
+
+        let (ctx, step, result) = match running.next().await {
+            Some(output) => output,
+            None => break,
+        };
+        match result {
+            Ok(()) => {
+                finished.insert(step.name.clone());
+                state.mark_completed(step);
+                state.save(&state_path)?;
+                ctx.emit(Event::Progress {
+                    message: format!("finished step {}", step.name),
+                    completed: u64::try_from(finished.len()).unwrap_or(u64::MAX),
+                    total: Some(total),
+                });
+            }
+            Err(err) => {
+                error!(
+                    ctx.log(),
+                    "step {} failed: {}",
+                    step.name,
+                    err.display_causes_without_backtrace(),
+                );
+                failed.push(step.name.clone());
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        // We've finished, so the next run should start from the beginning.
+        if state_path.exists() {
+            fs::remove_file(&state_path).with_context(|_| {
+                format!("could not remove {}", state_path.display())
+            })?;
+        }
+        Ok(())
+    } else {
+        let not_run = pipeline
+            .steps()
+            .iter()
+            .filter(|step| !started.contains(&step.name))
+            .map(|step| &step.name[..])
+            .collect::<Vec<_>>();
+        let mut message = format!("pipeline step(s) failed: {}", failed.join(", "));
+        if !not_run.is_empty() {
+            message.push_str(&format!(" (not run: {})", not_run.join(", ")));
+        }
+        Err(format_err!(
+            "{}; run the pipeline again to resume, or pass --restart to start over",
+            message,
+        ))
+    }
+}
+
+/// Run a single step, and wait for it to finish.
+async fn run_step(ctx: &Context, step: &Step) -> Result<()> {
+    info!(ctx.log(), "running step {}", step.name);
+    let mut command = if step.exec.is_empty() {
+        // Run `dbcrossbar` again, so that each step gets its own process.
+        let exe =
+            env::current_exe().context("could not find path to current executable")?;
+        let mut command = Command::new(exe);
+        command.args(&step.args);
+        command
+    } else {
+        let mut command = Command::new(&step.exec[0]);
+        command.args(&step.exec[1..]);
+        command
+    };
+    let status = command
+        .status()
+        .await
+        .with_context(|_| format!("could not start step {}", step.name))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format_err!("exited with {}", status))
+    }
+}
@@ -1,7 +1,13 @@
 //! The `conv` subcommand.
 
 use common_failures::Result;
-use dbcrossbarlib::{config::Configuration, Context, IfExists, UnparsedLocator};
+use dbcrossbarlib::{
+    config::Configuration,
+    limits::check_limits,
+    lossy_types::check_lossy_columns,
+    names::{report_names, NameRules},
+    Context, IfExists, UnparsedLocator,
+};
 use failure::format_err;
 use structopt::{self, StructOpt};
 
@@ -12,6 +18,17 @@ pub(crate) struct Opt {
     #[structopt(long = "if-exists", default_value = "error")]
     if_exists: IfExists,
 
+    /// Infer the types of CSV columns from up to this many rows of data,
+    /// instead of treating every column as text.
+    #[structopt(long = "infer-schema-rows")]
+    infer_schema_rows: Option<usize>,
+
+    /// Normalize table and column names for the destination, using a list of
+    /// `lowercase`, `replace-illegal`, `max-length=N` and
+    /// `reserved-suffix=SUFFIX`.
+    #[structopt(long = "normalize-names")]
+    normalize_names: Option<NameRules>,
+
     /// The input schema.
     from_locator: UnparsedLocator,
 
@@ -28,9 +45,20 @@ pub(crate) async fn run(
 ) -> Result<()> {
     let from_locator = opt.from_locator.parse(enable_unstable)?;
     let to_locator = opt.to_locator.parse(enable_unstable)?;
-    let schema = from_locator.schema(ctx.clone()).await?.ok_or_else(|| {
-        format_err!("don't know how to read schema from {}", from_locator)
-    })?;
+    let schema = from_locator
+        .schema(ctx.clone().with_infer_schema_rows(opt.infer_schema_rows))
+        .await?
+        .ok_or_else(|| {
+            format_err!("don't know how to read schema from {}", from_locator)
+        })?;
+    let renamed_schema = match &opt.normalize_names {
+        Some(rules) => rules.normalize_schema(to_locator.driver()?, &schema)?,
+        None => schema.clone(),
+    };
+    report_names(&ctx, to_locator.as_ref(), &schema, &renamed_schema)?;
+    let schema = renamed_schema;
+    check_limits(to_locator.as_ref(), &schema)?;
+    check_lossy_columns(&ctx, to_locator.as_ref(), &schema)?;
     to_locator.write_schema(ctx, schema, opt.if_exists).await?;
     Ok(())
 }
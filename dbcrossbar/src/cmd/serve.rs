@@ -0,0 +1,79 @@
+//! The `serve` subcommand.
+
+use common_failures::Result;
+use dbcrossbarlib::{config::Configuration, serve::JobManager, Context};
+use failure::{format_err, ResultExt};
+use slog::info;
+use std::{env, net::SocketAddr, path::PathBuf};
+use structopt::{self, StructOpt};
+
+/// Serve arguments.
+#[derive(Debug, StructOpt)]
+pub(crate) struct Opt {
+    /// The address to listen on. Use port 0 to pick any free port.
+    #[structopt(long = "listen", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+
+    /// How many copy jobs should we run at once?
+    #[structopt(long = "max-jobs", default_value = "2")]
+    max_jobs: usize,
+
+    /// A directory for job logs. Defaults to a temporary directory which is
+    /// deleted when the server exits.
+    #[structopt(long = "jobs-dir", parse(from_os_str))]
+    jobs_dir: Option<PathBuf>,
+}
+
+/// Run copy jobs submitted over HTTP.
+pub(crate) async fn run(
+    ctx: Context,
+    _config: Configuration,
+    enable_unstable: bool,
+    opt: Opt,
+) -> Result<()> {
+    if opt.max_jobs == 0 {
+        return Err(format_err!("--max-jobs must be at least 1"));
+    }
+
+    // Keep `temp_dir` around until we exit.
+    let mut temp_dir = None;
+    let jobs_dir = match opt.jobs_dir {
+        Some(jobs_dir) => {
+            std::fs::create_dir_all(&jobs_dir).with_context(|_| {
+                format!("could not create {}", jobs_dir.display())
+            })?;
+            jobs_dir
+        }
+        None => {
+            let dir = tempfile::TempDir::new()
+                .context("could not create temporary directory")?;
+            let path = dir.path().to_owned();
+            temp_dir = Some(dir);
+            path
+        }
+    };
+
+    let token = env::var("DBCROSSBAR_SERVE_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    if token.is_none() && !opt.listen.ip().is_loopback() {
+        return Err(format_err!(
+            "refusing to listen on {} without DBCROSSBAR_SERVE_TOKEN, because anyone who could connect could run jobs",
+            opt.listen,
+        ));
+    }
+    let exe = env::current_exe().context("could not find dbcrossbar executable")?;
+    // Jobs can use unstable features if we can.
+    let mut global_args = vec![];
+    if enable_unstable {
+        global_args.push("--enable-unstable".to_owned());
+    }
+    let jobs =
+        JobManager::new(ctx.clone(), exe, global_args, jobs_dir, opt.max_jobs, token);
+    let (addr, server) = dbcrossbarlib::serve::serve(opt.listen, jobs)?;
+    info!(ctx.log(), "listening on http://{}", addr);
+    println!("listening on http://{}", addr);
+    server.await?;
+    drop(temp_dir);
+    Ok(())
+}
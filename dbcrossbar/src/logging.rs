@@ -7,7 +7,7 @@ use std::{io::stderr, result, str::FromStr};
 
 /// A polymorphic log drain (which means we need to use `Box<dyn ...>`,
 /// because that's how Rust does runtime polymorphism).
-type BoxDrain = Box<dyn Drain<Ok = (), Err = Never> + Send + 'static>;
+pub(crate) type BoxDrain = Box<dyn Drain<Ok = (), Err = Never> + Send + 'static>;
 
 /// What log format we should use.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -17,6 +17,7 @@
 #![allow(clippy::multiple_crate_versions)]
 
 // Needed to prevent linker errors about OpenSSL.
+#[cfg(feature = "tls-native")]
 #[allow(unused_extern_crates)]
 extern crate openssl;
 
@@ -25,14 +26,27 @@ extern crate openssl;
 #[allow(unused_imports)]
 extern crate tokio;
 
-use common_failures::{quick_main, Result};
-use dbcrossbarlib::{config::Configuration, run_futures_with_runtime, Context};
-use slog::{debug, Drain};
+use common_failures::{display::DisplayCausesAndBacktraceExt, quick_main, Result};
+use dbcrossbarlib::{
+    config::Configuration,
+    events::{EventBus, JsonLinesSubscriber, SqlAuditLog, WarningSummary},
+    monitor::Monitor,
+    notify::{send_notifications, JobReport, MetricsRecorder},
+    run_futures_with_runtime, Context,
+};
+use failure::format_err;
+use slog::{debug, Discard, Drain, Duplicate};
 use slog_async::{self, OverflowStrategy};
+use std::{env, sync::Arc, time::Instant};
 use structopt::{self, StructOpt};
 
 mod cmd;
 mod logging;
+mod progress;
+mod tui;
+
+use crate::progress::ProgressDisplay;
+use crate::tui::TuiDisplay;
 
 quick_main!(run);
 
@@ -41,15 +55,50 @@ fn run() -> Result<()> {
     env_logger::init();
 
     // Find our system SSL configuration, even if we're statically linked.
+    #[cfg(feature = "tls-native")]
     openssl_probe::init_ssl_cert_env_vars();
 
-    // Parse our command-line arguments.
-    let opt = cmd::Opt::from_args();
+    // Load our configuration, and parse our command-line arguments, including
+    // any from a `--profile` in our configuration.
+    let config = Configuration::try_default()?;
+    let opt = cmd::Opt::from_iter(cmd::args_with_profile(
+        &config,
+        env::args_os().collect(),
+    )?);
 
     // Set up `slog`-based structured logging for our async code, because we
     // need to be able to untangle very complicated logs from many parallel
     // async tasks.
-    let base_drain = opt.log_format.create_drain();
+    //
+    // We also send log records to our event bus, so that subscribers can see
+    // them.
+    let events = EventBus::new();
+    if let Some(path) = &opt.event_log {
+        events.subscribe(Arc::new(JsonLinesSubscriber::create(path)?));
+    }
+    if let Some(path) = &opt.sql_audit_log {
+        events.subscribe(Arc::new(SqlAuditLog::open(path)?));
+    }
+    let warning_summary = Arc::new(WarningSummary::new());
+    events.subscribe(warning_summary.clone());
+    let metrics_recorder = MetricsRecorder::new();
+    events.subscribe(metrics_recorder.clone());
+    // With `--tui`, our display replaces the usual log output. With
+    // `--progress`, we print a summary line every so often, alongside our logs.
+    let tui = opt.tui;
+    let monitor = if tui || opt.progress {
+        let monitor = Monitor::new();
+        events.subscribe(monitor.clone());
+        Some(monitor)
+    } else {
+        None
+    };
+    let term_drain = if tui {
+        Box::new(Discard) as logging::BoxDrain
+    } else {
+        opt.log_format.create_drain()
+    };
+    let base_drain = Duplicate::new(term_drain, events.log_drain()).ignore_res();
     let filtered = slog_envlogger::new(base_drain);
     let drain = slog_async::Async::new(filtered)
         .chan_size(64)
@@ -65,18 +114,71 @@ fn run() -> Result<()> {
     // must be passed to all our background operations. The `worker_fut` will
     // return either success when all background workers have finished, or an
     // error as soon as one fails.
-    let (ctx, worker_fut) = Context::create(log);
+    let (ctx, worker_fut) = Context::create_with_events(log, events);
+    let deny_warnings = opt.deny_warnings;
+    let ctx = ctx
+        .with_deny_warnings(deny_warnings)
+        .with_file_io(opt.file_io);
 
     // Log our command-line options.
     debug!(ctx.log(), "{:?}", opt);
 
-    // Load our configuration.
-    let config = Configuration::try_default()?;
     debug!(ctx.log(), "{:?}", config);
 
     // Create a future to run our command.
+    let notify = opt.notify.clone();
+    let started = Instant::now();
     let cmd_fut = cmd::run(ctx, config, opt);
 
     // Run our futures.
-    run_futures_with_runtime(cmd_fut, worker_fut)
+    let (tui_display, progress_display) = match monitor {
+        Some(monitor) if tui => (Some(TuiDisplay::start(monitor)), None),
+        Some(monitor) => (None, Some(ProgressDisplay::start(monitor))),
+        None => (None, None),
+    };
+    let result = run_futures_with_runtime(cmd_fut, worker_fut);
+    if let Some(tui_display) = tui_display {
+        tui_display.stop();
+    }
+    if let Some(progress_display) = progress_display {
+        progress_display.stop();
+    }
+
+    // Summarize any warnings, so that they don't get lost in the logs.
+    let warnings = warning_summary.warnings();
+    if !warnings.is_empty() {
+        eprintln!("{} warnings:", warnings.len());
+        for warning in &warnings {
+            eprintln!("- {}", warning);
+        }
+    }
+    let result = result.and_then(|()| {
+        if deny_warnings && !warnings.is_empty() {
+            Err(format_err!(
+                "{} warnings, and --deny-warnings was specified",
+                warnings.len(),
+            ))
+        } else {
+            Ok(())
+        }
+    });
+
+    // Tell any webhooks how things went. If this fails, we still want to
+    // report the outcome of the actual command.
+    if !notify.is_empty() {
+        let mut report = JobReport::new(
+            env::args(),
+            started.elapsed(),
+            result
+                .as_ref()
+                .err()
+                .map(|err| format!("{}", err.display_causes_without_backtrace())),
+        );
+        report.warnings = warnings;
+        report.metrics = metrics_recorder.metrics();
+        if let Err(err) = send_notifications(&notify, &report) {
+            eprintln!("{}", err);
+        }
+    }
+    result
 }
@@ -0,0 +1,57 @@
+//! A periodic progress line for `--progress`, printed to standard error.
+
+use dbcrossbarlib::monitor::Monitor;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often should we check whether we've been asked to stop?
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How many `POLL_INTERVAL`s should we wait between progress lines?
+const POLLS_PER_LINE: u32 = 10;
+
+/// A background thread which prints a summary of a `Monitor` until stopped.
+///
+/// Unlike `TuiDisplay`, we print ordinary lines, so our output can be mixed
+/// with our usual logs, or redirected to a file.
+pub(crate) struct ProgressDisplay {
+    /// Set this to ask our thread to stop.
+    stop: Arc<AtomicBool>,
+    /// Our printing thread.
+    thread: JoinHandle<()>,
+}
+
+impl ProgressDisplay {
+    /// Start printing a summary of `monitor` in the background.
+    pub(crate) fn start(monitor: Arc<Monitor>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut polls = 0;
+                while !stop.load(Ordering::SeqCst) {
+                    thread::sleep(POLL_INTERVAL);
+                    polls += 1;
+                    if polls % POLLS_PER_LINE == 0 {
+                        eprintln!("progress: {}", monitor.summary());
+                    }
+                }
+                eprintln!("progress: {}", monitor.summary());
+            })
+        };
+        ProgressDisplay { stop, thread }
+    }
+
+    /// Print our summary one last time, and stop.
+    pub(crate) fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // If our thread panicked, there's nothing useful left to print.
+        let _ = self.thread.join();
+    }
+}
@@ -0,0 +1,90 @@
+//! A live status display for `--tui`, redrawn in place on standard error.
+
+use dbcrossbarlib::monitor::Monitor;
+use std::{
+    env,
+    io::{stderr, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often should we redraw the display?
+const REDRAW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The width to use if `COLUMNS` isn't set.
+const DEFAULT_WIDTH: usize = 100;
+
+/// A background thread which redraws a `Monitor` until stopped.
+pub(crate) struct TuiDisplay {
+    /// Set this to ask our thread to stop.
+    stop: Arc<AtomicBool>,
+    /// Our redraw thread.
+    thread: JoinHandle<()>,
+}
+
+impl TuiDisplay {
+    /// Start redrawing `monitor` in the background.
+    pub(crate) fn start(monitor: Arc<Monitor>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let width = terminal_width();
+                let mut height = 0;
+                loop {
+                    let stopping = stop.load(Ordering::SeqCst);
+                    height = draw(&monitor.render(width), height);
+                    if stopping {
+                        break;
+                    }
+                    thread::sleep(REDRAW_INTERVAL);
+                }
+            })
+        };
+        TuiDisplay { stop, thread }
+    }
+
+    /// Draw the display one last time, and stop.
+    pub(crate) fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // If our thread panicked, there's nothing useful left to display.
+        let _ = self.thread.join();
+    }
+}
+
+/// Get the width of the terminal from `COLUMNS`, if we can.
+fn terminal_width() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Replace the previous `height` lines of output with `lines`, and return the
+/// new height.
+fn draw(lines: &[String], height: usize) -> usize {
+    let mut out = String::new();
+    if height > 0 {
+        // Move to the start of our previous output.
+        out.push_str(&format!("\x1b[{}A", height));
+    }
+    for line in lines {
+        // Clear each line before drawing it.
+        out.push_str("\r\x1b[2K");
+        out.push_str(line);
+        out.push('\n');
+    }
+    // Clear anything left over from a taller display.
+    out.push_str("\x1b[J");
+    let stderr = stderr();
+    let mut stderr = stderr.lock();
+    // If we can't write to standard error, we have nowhere to report it.
+    let _ = stderr.write_all(out.as_bytes());
+    let _ = stderr.flush();
+    lines.len()
+}
@@ -0,0 +1,30 @@
+//! Tests for the `auth` subcommand.
+
+use cli_test_dir::*;
+
+#[test]
+fn auth_logout_without_login() {
+    let testdir = TestDir::new("dbcrossbar", "auth_logout_without_login");
+    let output = testdir
+        .cmd()
+        .env("DBCROSSBAR_CONFIG_DIR", testdir.path("config"))
+        .env_remove("GCLOUD_CLIENT_SECRET")
+        .args(["auth", "logout"])
+        .tee_output()
+        .expect_success();
+    assert!(output.stdout_str().contains("Not logged in"));
+}
+
+#[test]
+fn auth_login_rejects_invalid_client_secret() {
+    let testdir =
+        TestDir::new("dbcrossbar", "auth_login_rejects_invalid_client_secret");
+    testdir.create_file("secret.json", "{}");
+    testdir
+        .cmd()
+        .env("DBCROSSBAR_CONFIG_DIR", testdir.path("config"))
+        .args(["auth", "login", "--client-secret=secret.json"])
+        .tee_output()
+        .expect_failure();
+    assert!(!testdir.path("config/gcloud_client_secret.json").exists());
+}
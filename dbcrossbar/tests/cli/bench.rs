@@ -0,0 +1,17 @@
+//! Tests for the `bench` subcommand.
+
+use cli_test_dir::*;
+
+#[test]
+fn bench_csv() {
+    let testdir = TestDir::new("dbcrossbar", "bench_csv");
+    let output = testdir
+        .cmd()
+        .args(["bench", "--rows=100", "csv:out/"])
+        .expect_success();
+    let stdout = output.stdout_str();
+    for stage in &["extract", "transform", "load"] {
+        assert!(stdout.contains(stage), "missing {}: {}", stage, stdout);
+    }
+    testdir.expect_path("out/bench.csv");
+}
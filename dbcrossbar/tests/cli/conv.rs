@@ -109,6 +109,44 @@ fn conv_bq_schema_to_pg_sql() {
     testdir.expect_file_contents("output.sql", &expected);
 }
 
+#[test]
+fn conv_bigquery_table_to_pg_sql_replay() {
+    let testdir = TestDir::new("dbcrossbar", "conv_bigquery_table_to_pg_sql_replay");
+    let recording = testdir.src_path("fixtures/gcloud_replay/bigquery_schema.jsonl");
+    let output = testdir
+        .cmd()
+        .env("DBCROSSBAR_GCLOUD_REPLAY", &recording)
+        .args([
+            "schema",
+            "conv",
+            "bigquery:myproject:mydataset.people",
+            "postgres-sql:-",
+        ])
+        .expect_success();
+    assert_eq!(
+        output.stdout_str(),
+        r#"CREATE TABLE "mydataset"."people" (
+    "id" bigint NOT NULL,
+    "name" text,
+    "tags" text[]
+);
+"#,
+    );
+
+    // Requests which weren't recorded fail without touching the network.
+    let output = testdir
+        .cmd()
+        .env("DBCROSSBAR_GCLOUD_REPLAY", &recording)
+        .args([
+            "schema",
+            "conv",
+            "bigquery:myproject:mydataset.other",
+            "postgres-sql:-",
+        ])
+        .expect_failure();
+    assert!(output.stderr_str().contains("no recorded response"));
+}
+
 #[test]
 fn conv_ts_to_portable() {
     let testdir = TestDir::new("dbcrossbar", "conv_ts_to_portable");
@@ -132,3 +170,128 @@ fn conv_ts_to_portable() {
         serde_json::from_str::<serde_json::Value>(&expected).unwrap(),
     );
 }
+
+#[test]
+fn conv_warns_about_lossy_types() {
+    let testdir = TestDir::new("dbcrossbar", "conv_warns_about_lossy_types");
+    let sql = "CREATE TABLE payments (amount numeric);";
+    let output = testdir
+        .cmd()
+        .args(["schema", "conv", "postgres-sql:-", "bigquery-schema:-"])
+        .output_with_stdin(sql)
+        .expect_success();
+    assert!(output.stderr_str().contains("1 warnings"));
+    assert!(output.stderr_str().contains("NUMERIC"));
+
+    testdir
+        .cmd()
+        .args([
+            "--deny-warnings",
+            "schema",
+            "conv",
+            "postgres-sql:-",
+            "bigquery-schema:-",
+        ])
+        .output_with_stdin(sql)
+        .expect_failure();
+}
+
+#[test]
+fn conv_renames_reserved_words() {
+    let testdir = TestDir::new("dbcrossbar", "conv_renames_reserved_words");
+    let sql = r#"CREATE TABLE orders ("order" int, "Group" text, id int);"#;
+    let output = testdir
+        .cmd()
+        .args([
+            "schema",
+            "conv",
+            "--normalize-names=lowercase,reserved-suffix=_col",
+            "postgres-sql:-",
+            "postgres-sql:-",
+        ])
+        .output_with_stdin(sql)
+        .expect_success();
+    let stdout = output.stdout_str();
+    assert!(stdout.contains(r#""order_col""#), "{}", stdout);
+    assert!(stdout.contains(r#""group_col""#), "{}", stdout);
+    assert!(stdout.contains(r#""id""#), "{}", stdout);
+}
+
+#[test]
+fn conv_rejects_names_which_are_too_long() {
+    let testdir = TestDir::new("dbcrossbar", "conv_rejects_names_which_are_too_long");
+    let long_name = "x".repeat(64);
+    let sql = format!(r#"CREATE TABLE example ("{}" int, id int);"#, long_name);
+    let output = testdir
+        .cmd()
+        .args(["schema", "conv", "postgres-sql:-", "postgres-sql:-"])
+        .output_with_stdin(&sql)
+        .expect_failure();
+    let stderr = output.stderr_str();
+    assert!(stderr.contains(&long_name), "{}", stderr);
+    assert!(stderr.contains("max-length=63"), "{}", stderr);
+
+    testdir
+        .cmd()
+        .args([
+            "schema",
+            "conv",
+            "--normalize-names=max-length=63",
+            "postgres-sql:-",
+            "postgres-sql:-",
+        ])
+        .output_with_stdin(&sql)
+        .expect_success();
+}
+
+#[test]
+fn conv_csv_to_pg_sql_infer_schema_rows() {
+    let testdir = TestDir::new("dbcrossbar", "conv_csv_to_pg_sql_infer_schema_rows");
+    testdir.create_file(
+        "people.csv",
+        "id,score,active,born,name\n1,2.5,true,1990-01-31,Alice\n2,3,false,,Bob\n",
+    );
+    let output = testdir
+        .cmd()
+        .args([
+            "schema",
+            "conv",
+            "--infer-schema-rows=100",
+            "csv:people.csv",
+            "postgres-sql:-",
+        ])
+        .expect_success();
+    let stdout = output.stdout_str();
+    assert!(stdout.contains(r#""id" bigint"#), "{}", stdout);
+    assert!(stdout.contains(r#""score" double precision"#), "{}", stdout);
+    assert!(stdout.contains(r#""active" boolean"#), "{}", stdout);
+    assert!(stdout.contains(r#""born" date"#), "{}", stdout);
+    assert!(stdout.contains(r#""name" text"#), "{}", stdout);
+}
+
+#[test]
+fn conv_gzipped_csv_to_pg_sql() {
+    let testdir = TestDir::new("dbcrossbar", "conv_gzipped_csv_to_pg_sql");
+    testdir.create_file("people.csv", "id,name\n1,Alice\n");
+    let gzipped = std::process::Command::new("gzip")
+        .args(["-c", "people.csv"])
+        .current_dir(testdir.path("."))
+        .output()
+        .expect("could not run gzip");
+    assert!(gzipped.status.success());
+    fs::write(testdir.path("people.csv.gz"), &gzipped.stdout).unwrap();
+    let output = testdir
+        .cmd()
+        .args([
+            "schema",
+            "conv",
+            "--infer-schema-rows=100",
+            "csv:people.csv.gz",
+            "postgres-sql:-",
+        ])
+        .expect_success();
+    let stdout = output.stdout_str();
+    assert!(stdout.contains(r#"CREATE TABLE "people""#), "{}", stdout);
+    assert!(stdout.contains(r#""id" bigint"#), "{}", stdout);
+    assert!(stdout.contains(r#""name" text"#), "{}", stdout);
+}
@@ -69,3 +69,18 @@ fn count_postgres() {
 
     assert_eq!(output.stdout_str().trim(), "2");
 }
+
+#[test]
+fn count_synthetic() {
+    let testdir = TestDir::new("dbcrossbar", "count_synthetic");
+    let schema = testdir.src_path("fixtures/example.sql");
+    let output = testdir
+        .cmd()
+        .args([
+            "count",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "synthetic:1000",
+        ])
+        .expect_success();
+    assert_eq!(output.stdout_str().trim(), "1000");
+}
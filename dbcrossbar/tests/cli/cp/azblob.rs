@@ -0,0 +1,49 @@
+//! Azure Blob Storage-specific tests.
+
+use cli_test_dir::*;
+use difference::assert_diff;
+use std::fs;
+
+use super::*;
+
+#[test]
+#[ignore]
+fn cp_csv_to_azblob_to_csv() {
+    let _ = env_logger::try_init();
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_azblob_to_csv");
+    let src = testdir.src_path("fixtures/many_types.csv");
+    let schema = testdir.src_path("fixtures/many_types.sql");
+    let azblob_dir = azblob_test_dir_url("cp_csv_to_azblob_to_csv");
+
+    // CSV to Azure Blob Storage.
+    testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &azblob_dir,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // Azure Blob Storage to CSV.
+    testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &azblob_dir,
+            "csv:out/",
+        ])
+        .tee_output()
+        .expect_success();
+
+    let expected = fs::read_to_string(&src).unwrap();
+    let actual = fs::read_to_string(testdir.path("out/many_types.csv")).unwrap();
+    assert_diff!(&expected, &actual, ",", 0);
+}
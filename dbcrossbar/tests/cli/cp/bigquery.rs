@@ -488,3 +488,201 @@ fn bigquery_roundtrips_structs() {
         serde_json::from_reader(fs::File::open(&exported_schema).unwrap()).unwrap();
     assert_eq!(exported_schema, expected_schema_data);
 }
+
+#[test]
+#[ignore]
+fn cp_bigquery_to_bigquery_server_side() {
+    let testdir = TestDir::new("dbcrossbar", "cp_bigquery_to_bigquery_server_side");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let filtered = testdir.src_path("fixtures/posts_where_author_id_1.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let gs_temp_dir = gs_test_dir_url("cp_bigquery_to_bigquery_server_side");
+    let bq_temp_ds = bq_temp_dataset();
+    let bq_table = bq_test_table("cp_bigquery_to_bigquery_server_side");
+    let bq_table_2 = bq_test_table("cp_bigquery_to_bigquery_server_side_2");
+
+    // CSV to BigQuery.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", gs_temp_dir),
+            &format!("--temporary={}", bq_temp_ds),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &bq_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // BigQuery to BigQuery, with no `--temporary` storage, because the copy
+    // happens server-side.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--where",
+            "author_id = 1",
+            &bq_table,
+            &bq_table_2,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // BigQuery back to CSV.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            &format!("--temporary={}", gs_temp_dir),
+            &format!("--temporary={}", bq_temp_ds),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &bq_table_2,
+            "csv:out/",
+        ])
+        .tee_output()
+        .expect_success();
+
+    let expected = fs::read_to_string(&filtered).unwrap();
+    let actual = fs::read_to_string(testdir.path("out/000000000000.csv")).unwrap();
+    assert_diff!(&expected, &actual, ",", 0);
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_bigquery_files_per_load_job() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_bigquery_files_per_load_job");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let gs_temp_dir = gs_test_dir_url("cp_csv_to_bigquery_files_per_load_job");
+    let bq_temp_ds = bq_temp_dataset();
+    let bq_table = bq_test_table("cp_csv_to_bigquery_files_per_load_job");
+    testdir.create_file("in/a.csv", "author_id,title\n1,Welcome\n");
+    testdir.create_file("in/b.csv", "author_id,title\n2,My weekend\n");
+    testdir.create_file("in/c.csv", "author_id,title\n1,Hello again\n");
+
+    // CSV to BigQuery, using two load jobs for three files.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", gs_temp_dir),
+            &format!("--temporary={}", bq_temp_ds),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "--to-arg=files_per_load_job=2",
+            "csv:in/",
+            &bq_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    let output = testdir
+        .cmd()
+        .args(["count", &bq_table])
+        .tee_output()
+        .expect_success();
+    assert_eq!(output.stdout_str().trim(), "3");
+
+    // We should refuse to start a copy which would need too many load jobs.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", gs_temp_dir),
+            &format!("--temporary={}", bq_temp_ds),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "--to-arg=files_per_load_job=1",
+            "--to-arg=max_load_jobs=2",
+            "csv:in/",
+            &bq_table,
+        ])
+        .tee_output()
+        .expect_failure();
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_bigquery_streaming_insert() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_bigquery_streaming_insert");
+    let src = testdir.src_path("fixtures/many_types.csv");
+    let schema = testdir.src_path("fixtures/many_types.sql");
+    let bq_table = bq_test_table("cp_csv_to_bigquery_streaming_insert");
+
+    // Make sure our table doesn't exist, since streaming only supports
+    // `--if-exists=append` and `--if-exists=error`.
+    Command::new("bq")
+        .args(["rm", "-f", "-t", &bq_table["bigquery:".len()..]])
+        .status()
+        .expect("could not run bq");
+
+    // CSV to BigQuery, without any `--temporary` storage.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=append",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "--to-arg=streaming_insert_max_rows=100",
+            &format!("csv:{}", src.display()),
+            &bq_table,
+        ])
+        .tee_output()
+        .expect_success();
+}
+
+#[test]
+#[ignore]
+fn cp_bigquery_to_gs_max_files() {
+    let _ = env_logger::try_init();
+    let testdir = TestDir::new("dbcrossbar", "cp_bigquery_to_gs_max_files");
+    let src = testdir.src_path("fixtures/many_types.csv");
+    let schema = testdir.src_path("fixtures/many_types.sql");
+    let bq_temp_ds = bq_temp_dataset();
+    let gs_temp_dir = gs_test_dir_url("cp_bigquery_to_gs_max_files");
+    let gs_dest_dir = format!("{}out/", gs_temp_dir);
+    let bq_table = bq_test_table("cp_bigquery_to_gs_max_files");
+
+    // CSV to BigQuery.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", gs_temp_dir),
+            &format!("--temporary={}", bq_temp_ds),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &bq_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // BigQuery to a single composed file on gs.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", gs_temp_dir),
+            &format!("--temporary={}", bq_temp_ds),
+            "--to-arg=max_files=1",
+            &bq_table,
+            &gs_dest_dir,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // gs to CSV, so we can look at what we got.
+    testdir
+        .cmd()
+        .args(["cp", "--if-exists=overwrite", &gs_dest_dir, "csv:out/"])
+        .tee_output()
+        .expect_success();
+    let files = fs::read_dir(testdir.path("out")).unwrap().count();
+    assert_eq!(files, 1);
+    let actual = fs::read_to_string(testdir.path("out/000000000000.csv")).unwrap();
+    assert!(actual.starts_with("test_null,"));
+}
@@ -40,6 +40,289 @@ fn cp_csvs_to_csv() {
     testdir.expect_file_contents("out.csv", &expected);
 }
 
+#[test]
+fn cp_csvs_to_csv_dir_merged() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csvs_to_csv_dir_merged");
+    let schema = testdir.src_path("fixtures/concat.sql");
+    let concat_in = testdir.src_path("fixtures/concat_in");
+    let concat_out = testdir.src_path("fixtures/concat_out.csv");
+    testdir
+        .cmd()
+        .arg("cp")
+        .arg("--to-arg=merge=true")
+        .arg(format!("--schema=postgres-sql:{}", schema.display()))
+        .arg(format!("csv:{}", concat_in.display()))
+        .arg("csv:out/")
+        .expect_success();
+    let expected = fs::read_to_string(&concat_out).unwrap();
+    testdir.expect_file_contents("out/concat.csv", &expected);
+}
+
+#[test]
+fn cp_csvs_merged_rejects_stream_size() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csvs_merged_rejects_stream_size");
+    let schema = testdir.src_path("fixtures/concat.sql");
+    let concat_in = testdir.src_path("fixtures/concat_in");
+    let output = testdir
+        .cmd()
+        .arg("cp")
+        .arg("--to-arg=merge=true")
+        .arg("--stream-size=10")
+        .arg(format!("--schema=postgres-sql:{}", schema.display()))
+        .arg(format!("csv:{}", concat_in.display()))
+        .arg("csv:out/")
+        .expect_failure();
+    assert!(output
+        .stderr_str()
+        .contains("cannot be used with --stream-size"));
+}
+
+#[test]
+fn cp_csvs_with_different_headers() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csvs_with_different_headers");
+    testdir.create_file("schema.sql", "CREATE TABLE example (a text, b text);");
+    testdir.create_file("in/1.csv", "a,b\n1,2\n");
+    testdir.create_file("in/2.csv", "b,a\n4,3\n");
+
+    // Files with the same columns in a different order are reordered. We
+    // merge our streams so that they're written in a predictable order.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--to-arg=merge=true",
+            "csv:in/",
+            "csv:out.csv",
+        ])
+        .expect_success();
+    testdir.expect_file_contents("out.csv", "a,b\n1,2\n3,4\n");
+
+    // Files with different columns are all reported.
+    testdir.create_file("in/3.csv", "a,c\n5,6\n");
+    testdir.create_file("in/4.csv", "a\n7\n");
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--schema=postgres-sql:schema.sql",
+            "csv:in/",
+            "csv:out.csv",
+        ])
+        .expect_failure();
+    let stderr = output.stderr_str();
+    assert!(stderr.contains("2 of 4 CSV files"));
+    assert!(stderr.contains("3.csv: missing columns b; unexpected columns c"));
+    assert!(stderr.contains("4.csv: missing columns b"));
+}
+
+#[test]
+fn cp_vendor_csv_with_quirks() {
+    let testdir = TestDir::new("dbcrossbar", "cp_vendor_csv_with_quirks");
+    testdir.create_file("schema.sql", "CREATE TABLE example (a text, b text);");
+    testdir.create_file(
+        "in/vendor.csv",
+        "Daily report\n\na,b,\n# generated 2020-01-01\n1,2,\n3,,\n",
+    );
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--from-arg=skip_lines=2",
+            "--from-arg=comment=#",
+            "--from-arg=trailing_delimiter=true",
+            "csv:in/",
+            "csv:out.csv",
+        ])
+        .expect_success();
+    testdir.expect_file_contents("out.csv", "a,b\n1,2\n3,\n");
+}
+
+#[test]
+fn cp_vendor_csv_with_quirks_without_schema() {
+    let testdir =
+        TestDir::new("dbcrossbar", "cp_vendor_csv_with_quirks_without_schema");
+    testdir.create_file(
+        "vendor.csv",
+        "Daily report\n\na;b\n# generated 2020-01-01\n1;x\n3;\n",
+    );
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--infer-schema-rows=10",
+            "--from-arg=skip_lines=2",
+            "--from-arg=comment=#",
+            "--from-arg=delimiter=;",
+            "csv:vendor.csv",
+            "csv:out.csv",
+        ])
+        .expect_success();
+    testdir.expect_file_contents("out.csv", "a,b\n1,x\n3,\n");
+}
+
+#[test]
+fn cp_csv_with_custom_separators() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_with_custom_separators");
+    testdir.create_file("schema.sql", "CREATE TABLE example (a text, b text);");
+    testdir.create_file("in/mainframe.csv", "a||b\n1,5||2\n");
+    testdir.create_file("in/hadoop.csv", "a\x1fb\x1e3\x1f4\x1e");
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--from-arg=delimiter=||",
+            "csv:in/mainframe.csv",
+            "csv:mainframe_out.csv",
+        ])
+        .expect_success();
+    testdir.expect_file_contents("mainframe_out.csv", "a,b\n\"1,5\",2\n");
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--from-arg=delimiter=\\x1f",
+            "--from-arg=record_separator=\\x1e",
+            "csv:in/hadoop.csv",
+            "csv:hadoop_out.csv",
+        ])
+        .expect_success();
+    testdir.expect_file_contents("hadoop_out.csv", "a,b\n3,4\n");
+}
+
+#[test]
+fn cp_csv_strict() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_strict");
+    testdir.create_file("schema.sql", "CREATE TABLE example (a text, b text);");
+
+    // Well-formed input is copied unchanged.
+    let good_csv = "a,b\n\"x,\"\"y\"\"\",2\n";
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--from-arg=strict=true",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin(good_csv)
+        .expect_success();
+    assert_eq!(output.stdout_str(), good_csv);
+
+    // Stray quotes are reported with their location.
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--from-arg=strict=true",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin("a,b\n1,2\n3,4\"\n")
+        .expect_failure();
+    assert!(output
+        .stderr_str()
+        .contains("malformed CSV at byte 11 (row 3, line 3)"));
+}
+
+#[test]
+fn cp_gzipped_csv_without_extension() {
+    let testdir = TestDir::new("dbcrossbar", "cp_gzipped_csv_without_extension");
+    testdir.create_file("schema.sql", "CREATE TABLE example (a text, b text);");
+    testdir.create_file("plain.csv", "a,b\n1,2\n");
+    let gzipped = std::process::Command::new("gzip")
+        .args(["-c", "plain.csv"])
+        .current_dir(testdir.path("."))
+        .output()
+        .expect("could not run gzip");
+    assert!(gzipped.status.success());
+    fs::create_dir_all(testdir.path("in")).unwrap();
+    fs::write(testdir.path("in/data.csv"), &gzipped.stdout).unwrap();
+    testdir
+        .cmd()
+        .args(["cp", "--schema=postgres-sql:schema.sql", "csv:in/", "csv:out.csv"])
+        .expect_success();
+    testdir.expect_file_contents("out.csv", "a,b\n1,2\n");
+}
+
+#[test]
+fn cp_csv_to_csv_compression() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_compression");
+    testdir.create_file("schema.sql", "CREATE TABLE example (a text, b text);");
+    testdir.create_file("in/data.csv", "a,b\n1,2\n");
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--compression=gzip",
+            "--schema=postgres-sql:schema.sql",
+            "csv:in/",
+            "csv:gzipped/",
+        ])
+        .expect_success();
+    let gzipped = fs::read(testdir.path("gzipped/data.csv.gz")).unwrap();
+    assert_eq!(&gzipped[..2], &[0x1f, 0x8b]);
+
+    // We can read our compressed directory back in.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "csv:gzipped/",
+            "csv:out.csv",
+        ])
+        .expect_success();
+    testdir.expect_file_contents("out.csv", "a,b\n1,2\n");
+}
+
+#[test]
+fn cp_csv_to_csv_file_io_threads() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_file_io_threads");
+    let src = testdir.src_path("fixtures/example.csv");
+    testdir
+        .cmd()
+        .arg("--file-io=threads")
+        .arg("cp")
+        .arg(format!("csv:{}", src.display()))
+        .arg("csv:out/")
+        .expect_success();
+    let expected = fs::read_to_string(&src).unwrap();
+    testdir.expect_file_contents("out/example.csv", &expected);
+}
+
+#[test]
+fn cp_csv_archive_to_csv() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_archive_to_csv");
+    testdir.create_file("schema.sql", "CREATE TABLE example (a text, b text);");
+    testdir.create_file("in/1.csv", "a,b\n1,2\n");
+    testdir.create_file("in/2.csv", "a,b\n3,4\n");
+    testdir.create_file("in/README.txt", "Not a CSV file.\n");
+    let status = std::process::Command::new("tar")
+        .args(["-czf", "data.tar.gz", "-C", "in", "1.csv", "2.csv", "README.txt"])
+        .current_dir(testdir.path("."))
+        .status()
+        .expect("could not run tar");
+    assert!(status.success());
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "csv:data.tar.gz",
+            "csv:out/",
+        ])
+        .expect_success();
+    testdir.expect_file_contents("out/1.csv", "a,b\n1,2\n");
+    testdir.expect_file_contents("out/2.csv", "a,b\n3,4\n");
+}
+
 #[test]
 fn cp_csv_to_csv_piped() {
     let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv");
@@ -56,3 +339,834 @@ fn cp_csv_to_csv_piped() {
         .expect_success();
     assert_eq!(output.stdout_str(), EXAMPLE_CSV);
 }
+
+#[test]
+fn cp_csv_to_csv_with_crlf() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_with_crlf");
+    let src = testdir.src_path("fixtures/example.csv");
+    testdir
+        .cmd()
+        .arg("cp")
+        .arg(format!("csv:{}", src.display()))
+        .arg("csv:out.csv")
+        .arg("--to-arg=line_ending=crlf")
+        .expect_success();
+    let output = fs::read_to_string(testdir.path("out.csv")).unwrap();
+    assert!(output.ends_with("\r\n"));
+    assert_eq!(
+        output.replace("\r\n", "\n"),
+        fs::read_to_string(&src).unwrap()
+    );
+}
+
+#[test]
+fn cp_csv_to_csv_validate() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_validate");
+    let schema = testdir.src_path("fixtures/example.sql");
+    let bad_csv = "id,first_name,last_name\n1,John,Doe\nx,Jane,Doe\n";
+
+    // With `--validate=warn`, we copy the data anyway.
+    let output = testdir
+        .cmd()
+        .env("RUST_LOG", "warn")
+        .args([
+            "cp",
+            "--validate=warn",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin(bad_csv)
+        .expect_success();
+    assert_eq!(output.stdout_str(), bad_csv);
+    assert!(output.stderr_str().contains("\"id\""));
+
+    // With `--validate=error`, we fail.
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--validate=error",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin(bad_csv)
+        .expect_failure();
+    assert!(output.stderr_str().contains("line 3"));
+}
+
+#[test]
+fn cp_csv_to_csv_expectations() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_expectations");
+    testdir.create_file("schema.sql", "CREATE TABLE people (id int, status text);");
+    testdir.create_file(
+        "warn.json",
+        r#"[{ "column": "status", "rule": "in_set", "values": ["a"], "on_failure": "warn" }]"#,
+    );
+    testdir.create_file(
+        "error.json",
+        r#"[{ "column": "id", "rule": "between", "min": 1, "max": 10 }]"#,
+    );
+    let input = "id,status\n1,a\n20,b\n";
+
+    // Warnings don't stop the copy.
+    let output = testdir
+        .cmd()
+        .env("RUST_LOG", "warn")
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--expectations=warn.json",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin(input)
+        .expect_success();
+    assert_eq!(output.stdout_str(), input);
+    assert!(output.stderr_str().contains("\"status\""));
+
+    // Errors do.
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--expectations=error.json",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin(input)
+        .expect_failure();
+    assert!(output.stderr_str().contains("line 3"));
+}
+
+#[test]
+fn cp_csv_to_csv_add_provenance_columns() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_add_provenance_columns");
+    testdir.create_file("schema.sql", "CREATE TABLE people (id int);");
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--add-provenance-columns",
+            "--job-id=job-1",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin("id\n1\n")
+        .expect_success();
+    let mut lines = output.stdout_str().lines();
+    assert_eq!(
+        lines.next(),
+        Some("id,_dbcrossbar_loaded_at,_dbcrossbar_job_id,_source_file"),
+    );
+    let row = lines.next().unwrap();
+    assert!(row.starts_with("1,"), "{}", row);
+    assert!(row.ends_with(",job-1,data"), "{}", row);
+}
+
+#[test]
+fn cp_csv_to_csv_normalize_names() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_normalize_names");
+    testdir.create_file(
+        "schema.sql",
+        r#"CREATE TABLE people ("My Column (%)" text, "my column" text);"#,
+    );
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--normalize-names=lowercase,replace-illegal",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin("My Column (%),my column\na,b\n")
+        .expect_success();
+    assert_eq!(output.stdout_str(), "my_column,my_column_2\na,b\n");
+}
+
+#[test]
+fn cp_csv_to_csv_select() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_select");
+    let src = testdir.src_path("fixtures/example.csv");
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--select=last_name AS surname, id",
+            &format!("csv:{}", src.display()),
+            "csv:out.csv",
+        ])
+        .expect_success();
+    testdir.expect_file_contents("out.csv", "surname,id\nDoe,1\n");
+
+    // Columns are matched using the CSV headers, in any order.
+    testdir.create_file(
+        "schema.sql",
+        "CREATE TABLE people (id int, \"first name\" text);",
+    );
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--select=\"first name\" AS first_name",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin("id,first name\n1,Jane\n")
+        .expect_success();
+    assert_eq!(output.stdout_str(), "first_name\nJane\n");
+
+    // Unknown columns are rejected.
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--select=id, email",
+            &format!("csv:{}", src.display()),
+            "csv:-",
+        ])
+        .expect_failure();
+    assert!(output.stderr_str().contains("email"));
+}
+
+#[test]
+fn cp_csv_to_csv_shard() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_shard");
+    testdir.create_file("schema.sql", "CREATE TABLE parts (id int);");
+    for i in 0..10 {
+        testdir.create_file(format!("in/part-{}.csv", i), format!("id\n{}\n", i));
+    }
+
+    // Each file is copied by exactly one shard.
+    for shard in &["0/3", "1/3", "2/3"] {
+        testdir
+            .cmd()
+            .args([
+                "cp",
+                "--schema=postgres-sql:schema.sql",
+                &format!("--shard={}", shard),
+                "csv:in/",
+                "csv:out/{shard}/",
+            ])
+            .expect_success();
+    }
+    let mut copied = vec![];
+    for shard in 0..3 {
+        let dir = testdir.path(format!("out/{}", shard));
+        if !dir.exists() {
+            continue;
+        }
+        for entry in fs::read_dir(dir).unwrap() {
+            copied.push(entry.unwrap().file_name().into_string().unwrap());
+        }
+    }
+    copied.sort();
+    let mut expected = (0..10)
+        .map(|i| format!("part-{}.csv", i))
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(copied, expected);
+
+    // Shards can't overwrite each other.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--shard=0/3",
+            "--if-exists=overwrite",
+            "csv:in/",
+            "csv:out2/",
+        ])
+        .expect_failure();
+}
+
+#[test]
+fn cp_csv_to_csv_where() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_where");
+    testdir.create_file(
+        "schema.sql",
+        "CREATE TABLE events (id int, status text, created_at timestamp);",
+    );
+    testdir.create_file(
+        "in/events.csv",
+        "id,status,created_at\n\
+         1,paid,2020-05-31 23:59:59\n\
+         2,paid,2020-06-01 00:00:00\n\
+         3,refunded,2020-06-02 12:00:00\n\
+         10,shipped,\n\
+         11,shipped,2020-06-03 08:00:00\n",
+    );
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--where=created_at >= '2020-06-01' AND status IN ('paid', 'shipped')",
+            "csv:in/",
+            "csv:out.csv",
+        ])
+        .expect_success();
+    testdir.expect_file_contents(
+        "out.csv",
+        "id,status,created_at\n2,paid,2020-06-01 00:00:00\n11,shipped,2020-06-03 08:00:00\n",
+    );
+
+    // Comparing with the wrong type of value fails before copying anything.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--schema=postgres-sql:schema.sql",
+            "--where=status > 3",
+            "csv:in/",
+            "csv:out.csv",
+        ])
+        .expect_failure();
+}
+
+#[test]
+fn cp_schema_evolution_requires_append_and_support() {
+    let testdir = TestDir::new(
+        "dbcrossbar",
+        "cp_schema_evolution_requires_append_and_support",
+    );
+    let src = testdir.src_path("fixtures/example.csv");
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--schema-evolution=add-missing-columns",
+            &format!("csv:{}", src.display()),
+            "csv:out.csv",
+        ])
+        .expect_failure();
+    assert!(output
+        .stderr_str()
+        .contains("does not support --schema-evolution"));
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema-evolution=sometimes",
+            &format!("csv:{}", src.display()),
+            "csv:out.csv",
+        ])
+        .expect_failure();
+}
+
+#[test]
+fn cp_match_columns_requires_append_and_support() {
+    let testdir =
+        TestDir::new("dbcrossbar", "cp_match_columns_requires_append_and_support");
+    let src = testdir.src_path("fixtures/example.csv");
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--match-columns=snake-case",
+            &format!("csv:{}", src.display()),
+            "csv:out.csv",
+        ])
+        .expect_failure();
+    assert!(output
+        .stderr_str()
+        .contains("does not support --match-columns"));
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--match-columns=camel",
+            &format!("csv:{}", src.display()),
+            "csv:out.csv",
+        ])
+        .expect_failure();
+}
+
+#[test]
+fn cp_csv_to_csv_split_mb() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_split_mb");
+    let src = testdir.src_path("fixtures/example.csv");
+    let expected = fs::read_to_string(&src).unwrap();
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--from-arg=split_mb=1",
+            &format!("csv:{}", src.display()),
+            "csv:out/",
+        ])
+        .tee_output()
+        .expect_success();
+    testdir.expect_file_contents("out/example_0001.csv", &expected);
+
+    // We can't split a directory.
+    let schema = testdir.src_path("fixtures/example.sql");
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--from-arg=split_mb=1",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "csv:out/",
+            "csv:out2/",
+        ])
+        .expect_failure();
+    assert!(output.stderr_str().contains("single CSV file"));
+}
+
+#[test]
+fn cp_csv_to_csv_split_mb_with_quoted_newlines() {
+    let testdir =
+        TestDir::new("dbcrossbar", "cp_csv_to_csv_split_mb_with_quoted_newlines");
+    let mut data = "id,note\n".to_owned();
+    for i in 0..100_000 {
+        data.push_str(&format!("{},\"line\nbreak \"\"{}\"\"\"\n", i, i));
+    }
+    testdir.create_file("in.csv", &data);
+    testdir
+        .cmd()
+        .args(["cp", "--from-arg=split_mb=1", "csv:in.csv", "csv:out.csv"])
+        .tee_output()
+        .expect_success();
+    testdir.expect_file_contents("out.csv", &data);
+}
+
+#[test]
+fn cp_csv_to_csv_checkpoint() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_checkpoint");
+    let src = testdir.src_path("fixtures/example.csv");
+    let expected = fs::read_to_string(&src).unwrap();
+
+    // A checkpoint from a different copy is rejected.
+    testdir.create_file(
+        "other.json",
+        r#"{"source":"csv:other.csv","dest":"csv:out/","written_streams":[]}"#,
+    );
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--checkpoint=other.json",
+            &format!("csv:{}", src.display()),
+            "csv:out/",
+        ])
+        .expect_failure();
+    assert!(output.stderr_str().contains("is a checkpoint for copying"));
+
+    // A successful copy deletes its checkpoint.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--checkpoint=state.json",
+            &format!("csv:{}", src.display()),
+            "csv:out/",
+        ])
+        .tee_output()
+        .expect_success();
+    testdir.expect_file_contents("out/example.csv", &expected);
+    assert!(testdir.path("other.json").exists());
+    assert!(!testdir.path("state.json").exists());
+}
+
+#[test]
+fn cp_csv_dir_to_csv_dir_checkpoint() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_dir_to_csv_dir_checkpoint");
+    testdir.create_file("schema.sql", "CREATE TABLE t (id int);\n");
+    testdir.create_file("in/a.csv", "id\n1\n");
+    testdir.create_file("in/b.csv", "id\n2\n");
+
+    // We can't resume a copy to a single file.
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--checkpoint=state.json",
+            "csv:in/",
+            "csv:out.csv",
+        ])
+        .expect_failure();
+    assert!(output.stderr_str().contains("cannot use --checkpoint"));
+    assert!(!testdir.path("out.csv").exists());
+
+    // Each stream is written to its own file.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--checkpoint=state.json",
+            "csv:in/",
+            "csv:out/",
+        ])
+        .tee_output()
+        .expect_success();
+    testdir.expect_file_contents("out/a.csv", "id\n1\n");
+    testdir.expect_file_contents("out/b.csv", "id\n2\n");
+    assert!(!testdir.path("state.json").exists());
+
+    // When resuming, we skip streams which were already written.
+    testdir.create_file(
+        "state.json",
+        r#"{"source":"csv:in/","dest":"csv:resumed/","written_streams":["a"]}"#,
+    );
+    testdir.create_file("resumed/a.csv", "id\n3\n");
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--schema=postgres-sql:schema.sql",
+            "--checkpoint=state.json",
+            "csv:in/",
+            "csv:resumed/",
+        ])
+        .tee_output()
+        .expect_success();
+    testdir.expect_file_contents("resumed/a.csv", "id\n3\n");
+    testdir.expect_file_contents("resumed/b.csv", "id\n2\n");
+    assert!(!testdir.path("state.json").exists());
+}
+
+#[test]
+fn cp_csv_to_csv_profile() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_profile");
+    testdir.create_file(
+        "schema.sql",
+        r#"CREATE TABLE people ("My Column (%)" text, "my column" text);"#,
+    );
+    testdir.create_file(
+        "config/dbcrossbar.toml",
+        r#"
+[profiles.base]
+args = ["--schema=postgres-sql:schema.sql"]
+
+[profiles.nightly]
+inherits = "base"
+args = ["--normalize-names=lowercase,replace-illegal"]
+"#,
+    );
+    let output = testdir
+        .cmd()
+        .env("DBCROSSBAR_CONFIG_DIR", testdir.path("config"))
+        .args(["cp", "--profile=nightly", "csv:-", "csv:-"])
+        .output_with_stdin("My Column (%),my column\na,b\n")
+        .expect_success();
+    assert_eq!(output.stdout_str(), "my_column,my_column_2\na,b\n");
+
+    // Flags on the command line override the profile.
+    let output = testdir
+        .cmd()
+        .env("DBCROSSBAR_CONFIG_DIR", testdir.path("config"))
+        .args([
+            "cp",
+            "--profile",
+            "nightly",
+            "--normalize-names=lowercase",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin("My Column (%),my column\na,b\n")
+        .expect_success();
+    assert_eq!(output.stdout_str(), "my column (%),my column\na,b\n");
+
+    testdir
+        .cmd()
+        .env("DBCROSSBAR_CONFIG_DIR", testdir.path("config"))
+        .args(["cp", "--profile=missing", "csv:-", "csv:-"])
+        .output_with_stdin("My Column (%),my column\na,b\n")
+        .expect_failure();
+}
+
+#[test]
+fn cp_csv_to_csv_tui() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_tui");
+    testdir.create_file("schema.sql", "CREATE TABLE people (id int, name text);");
+    testdir.create_file("in.csv", "id,name\n1,a\n");
+    let output = testdir
+        .cmd()
+        .args([
+            "--tui",
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "csv:in.csv",
+            "csv:out.csv",
+        ])
+        .expect_success();
+    assert!(output.stderr_str().contains("1/1 streams done"));
+    testdir.expect_file_contents("out.csv", "id,name\n1,a\n");
+}
+
+#[test]
+fn cp_csv_to_csv_column_stats() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_column_stats");
+    testdir.create_file("schema.sql", "CREATE TABLE people (id int, name text);");
+    let input = "id,name\n10,b\n9,\n100,a\n";
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--column-stats=stats.json",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin(input)
+        .expect_success();
+    assert_eq!(output.stdout_str(), input);
+    let stats = serde_json::from_str::<serde_json::Value>(
+        &fs::read_to_string(testdir.path("stats.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(stats["rows"], 3);
+    assert_eq!(stats["columns"][0]["name"], "id");
+    assert_eq!(stats["columns"][0]["min"], "9");
+    assert_eq!(stats["columns"][0]["max"], "100");
+    assert_eq!(stats["columns"][1]["null_count"], 1);
+    assert_eq!(stats["columns"][1]["distinct_estimate"], 2);
+}
+
+#[test]
+fn cp_csv_to_csv_parse_bool_and_date() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_parse_bool_and_date");
+    testdir.create_file(
+        "schema.sql",
+        "CREATE TABLE people (id int, active boolean, born date, died date);",
+    );
+    let input = "id,active,born,died\n1,Oui,20/07/1969,07/20/2019\n2,non,,\n";
+    let expected = "id,active,born,died\n1,t,1969-07-20,2019-07-20\n2,f,,\n";
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--parse-bool=oui/non",
+            "--parse-date=born=%d/%m/%Y",
+            "--parse-date=%m/%d/%Y",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin(input)
+        .expect_success();
+    assert_eq!(output.stdout_str(), expected);
+}
+
+#[test]
+fn cp_csv_to_csv_cleanup() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_cleanup");
+    testdir.create_file(
+        "schema.sql",
+        "CREATE TABLE notes (id int, active boolean, note text);",
+    );
+    let input = "id,active,note\n1, yes ,\"  first\n line \"\n2,,\"   \"\n";
+    let expected = "id,active,note\n1,t,first line\n2,,\n";
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--cleanup=trim,empty-as-null",
+            "--cleanup=note=collapse-newlines",
+            "--parse-bool=yes/no",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin(input)
+        .expect_success();
+    assert_eq!(output.stdout_str(), expected);
+}
+
+#[test]
+fn cp_csv_to_csv_parse_number() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_parse_number");
+    testdir.create_file(
+        "schema.sql",
+        "CREATE TABLE prices (id int, price numeric, weight real);",
+    );
+    let input = "id,price,weight\n1,\"($1,234.56)\",\"1.234,5\"\n2,$12,\"0,5\"\n";
+    let expected = "id,price,weight\n1,-1234.56,1234.5\n2,12,0.5\n";
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--parse-number=price=$1,234.56",
+            "--parse-number=weight=1.234,56",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin(input)
+        .expect_success();
+    assert_eq!(output.stdout_str(), expected);
+}
+
+#[test]
+fn cp_csv_to_schema_only_locator_explains_error() {
+    let testdir =
+        TestDir::new("dbcrossbar", "cp_csv_to_schema_only_locator_explains_error");
+    testdir.create_file("schema.sql", "CREATE TABLE example (id int);");
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "csv:-",
+            "postgres-sql:out.sql",
+        ])
+        .output_with_stdin("id\n1\n")
+        .expect_failure();
+    assert!(output
+        .stderr_str()
+        .contains("cannot copy to postgres-sql:out.sql"));
+}
+
+#[test]
+fn cp_csv_with_no_temp() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_with_no_temp");
+    testdir.create_file("schema.sql", "CREATE TABLE example (id int);");
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--no-temp",
+            "--schema=postgres-sql:schema.sql",
+            "csv:-",
+            "csv:-",
+        ])
+        .output_with_stdin("id\n1\n")
+        .expect_success();
+    assert_eq!(output.stdout_str(), "id\n1\n");
+
+    // BigQuery needs to stage data in Cloud Storage, so this fails before
+    // trying to contact Google.
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--no-temp",
+            "--temporary=gs://example/",
+            "csv:-",
+            "bigquery:example:dataset.table",
+        ])
+        .output_with_stdin("id\n1\n")
+        .expect_failure();
+    assert!(output.stderr_str().contains("cannot be used with"));
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--no-temp",
+            "--schema=postgres-sql:schema.sql",
+            "csv:-",
+            "bigquery:example:dataset.table",
+        ])
+        .output_with_stdin("id\n1\n")
+        .expect_failure();
+    assert!(output.stderr_str().contains("but --no-temp was specified"));
+}
+
+#[test]
+fn cp_csv_writes_event_log() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_writes_event_log");
+    testdir.create_file("schema.sql", "CREATE TABLE example (id int);");
+    testdir
+        .cmd()
+        .args([
+            "--event-log=events.jsonl",
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "--validate=warn",
+            "csv:-",
+            "csv:out.csv",
+        ])
+        .output_with_stdin("id\n1\nx\n")
+        .expect_success();
+    testdir.expect_contains("events.jsonl", r#"{"type":"warning","message":"#);
+    testdir.expect_contains(
+        "events.jsonl",
+        r#"{"type":"progress","message":"wrote csv:out.csv","completed":1,"total":null}"#,
+    );
+    testdir.expect_contains(
+        "events.jsonl",
+        r#"{"type":"metric","name":"streams_written","value":1}"#,
+    );
+}
+
+#[test]
+fn cp_csv_reports_progress() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_reports_progress");
+    testdir.create_file("schema.sql", "CREATE TABLE example (id int);");
+    let output = testdir
+        .cmd()
+        .args([
+            "--progress",
+            "--event-log=events.jsonl",
+            "cp",
+            "--schema=postgres-sql:schema.sql",
+            "csv:-",
+            "csv:out.csv",
+        ])
+        .output_with_stdin("id\n1\n2\n")
+        .expect_success();
+    assert!(output.stderr_str().contains("1/1 streams done"));
+    assert!(output.stderr_str().contains("2 rows"));
+    testdir.expect_contains("events.jsonl", r#""rows":2,"finished":true}"#);
+}
+
+#[test]
+fn cp_csv_to_csv_dry_run() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_dry_run");
+    let src = testdir.src_path("fixtures/example.csv");
+    let output = testdir
+        .cmd()
+        .arg("cp")
+        .arg("--dry-run")
+        .arg(format!("csv:{}", src.display()))
+        .arg("csv:out/")
+        .expect_success();
+    assert!(output.stdout_str().contains("through this machine"));
+    assert!(output.stdout_str().contains("no cloud costs expected"));
+    assert!(!output.stdout_str().contains("statements:"));
+    assert!(!testdir.path("out").exists());
+}
+
+#[test]
+fn cp_csv_to_csv_verify() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_csv_verify");
+    let src = testdir.src_path("fixtures/example.csv");
+    for mode in &["count", "checksum"] {
+        testdir
+            .cmd()
+            .arg("cp")
+            .arg("--if-exists=overwrite")
+            .arg(format!("--verify={}", mode))
+            .arg(format!("csv:{}", src.display()))
+            .arg("csv:out/")
+            .expect_success();
+    }
+    let expected = fs::read_to_string(&src).unwrap();
+    testdir.expect_file_contents("out/example.csv", &expected);
+
+    // We can't read standard output back.
+    let output = testdir
+        .cmd()
+        .arg("cp")
+        .arg("--verify=count")
+        .arg(format!("csv:{}", src.display()))
+        .arg("csv:-")
+        .expect_failure();
+    assert!(output.stderr_str().contains("can't read it back"));
+}
@@ -8,3 +8,127 @@ fn cp_from_gs_to_exact_csv() {
     let gs_dir = gs_test_dir_url("cp_from_gs_to_exact_csv");
     assert_cp_to_exact_csv("cp_from_gs_to_exact_csv", &gs_dir);
 }
+
+#[test]
+#[ignore]
+fn cp_from_gs_to_gs() {
+    let testdir = TestDir::new("dbcrossbar", "cp_from_gs_to_gs");
+    let src = testdir.src_path("fixtures/example.csv");
+    let schema = testdir.src_path("fixtures/example.sql");
+    let gs_dir = gs_test_dir_url("cp_from_gs_to_gs");
+    let gs_dir_2 = gs_test_dir_url("cp_from_gs_to_gs_2");
+
+    // CSV to gs://.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &gs_dir,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // gs:// to gs://, copied server-side.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &gs_dir,
+            &gs_dir_2,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // gs:// to CSV.
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &gs_dir_2,
+            "csv:-",
+        ])
+        .tee_output()
+        .expect_success();
+    let expected = std::fs::read_to_string(&src).unwrap();
+    assert_eq!(
+        normalize_csv_data(output.stdout_str()),
+        normalize_csv_data(&expected),
+    );
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_gs_with_signed_urls() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_gs_with_signed_urls");
+    let src = testdir.src_path("fixtures/example.csv");
+    let schema = testdir.src_path("fixtures/example.sql");
+    let gs_dir = gs_test_dir_url("cp_csv_to_gs_with_signed_urls");
+
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "--to-arg=signed_url_ttl=1h",
+            "--to-arg=signed_url_manifest=urls.txt",
+            &format!("csv:{}", src.display()),
+            &gs_dir,
+        ])
+        .tee_output()
+        .expect_success();
+    testdir.expect_contains("urls.txt", "https://storage.googleapis.com/");
+    testdir.expect_contains("urls.txt", "Signature=");
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_gs_with_metadata() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_gs_with_metadata");
+    let src = testdir.src_path("fixtures/example.csv");
+    let schema = testdir.src_path("fixtures/example.sql");
+    let gs_dir = gs_test_dir_url("cp_csv_to_gs_with_metadata");
+
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "--to-arg=content_type=text/csv; charset=utf-8",
+            "--to-arg=cache_control=max-age=3600",
+            "--to-arg=metadata.owner=dbcrossbar_test",
+            &format!("csv:{}", src.display()),
+            &gs_dir,
+        ])
+        .tee_output()
+        .expect_success();
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_gs_with_storage_class() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_gs_with_storage_class");
+    let src = testdir.src_path("fixtures/example.csv");
+    let schema = testdir.src_path("fixtures/example.sql");
+    let gs_dir = gs_test_dir_url("cp_csv_to_gs_with_storage_class");
+
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "--to-arg=storage_class=NEARLINE",
+            &format!("csv:{}", src.display()),
+            &gs_dir,
+        ])
+        .tee_output()
+        .expect_success();
+}
@@ -0,0 +1,101 @@
+//! MySQL-specific tests.
+
+use cli_test_dir::*;
+use difference::assert_diff;
+use std::fs;
+
+use super::*;
+
+#[test]
+#[ignore]
+fn cp_csv_to_mysql_to_csv_with_where() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_mysql_to_csv_with_where");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let filtered = testdir.src_path("fixtures/posts_where_author_id_1.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let mysql_table = match mysql_test_table_url("cp_csv_to_mysql_to_csv_with_where") {
+        Some(mysql_table) => mysql_table,
+        None => {
+            eprintln!("SKIPPING MYSQL TEST - PLEASE SET `MYSQL_TEST_URL`!");
+            return;
+        }
+    };
+
+    // CSV to MySQL.
+    testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &mysql_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // MySQL back to CSV using --where.
+    testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "cp",
+            "--where",
+            "author_id = 1",
+            &mysql_table,
+            "csv:out/",
+        ])
+        .tee_output()
+        .expect_success();
+
+    let expected = fs::read_to_string(&filtered).unwrap();
+    let actual =
+        fs::read_to_string(testdir.path("out/cp_csv_to_mysql_to_csv_with_where.csv"))
+            .unwrap();
+    assert_diff!(&expected, &actual, ",", 0);
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_mysql_using_inserts() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_mysql_using_inserts");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let mysql_table = match mysql_test_table_url("cp_csv_to_mysql_using_inserts") {
+        Some(mysql_table) => mysql_table,
+        None => {
+            eprintln!("SKIPPING MYSQL TEST - PLEASE SET `MYSQL_TEST_URL`!");
+            return;
+        }
+    };
+
+    // CSV to MySQL, using small batches of `INSERT` statements.
+    testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "cp",
+            "--if-exists=overwrite",
+            "--to-arg=load_strategy=insert",
+            "--to-arg=batch_size=2",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &mysql_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // MySQL back to CSV.
+    testdir
+        .cmd()
+        .args(["--enable-unstable", "cp", &mysql_table, "csv:out/"])
+        .tee_output()
+        .expect_success();
+
+    let expected = fs::read_to_string(&src).unwrap();
+    let actual =
+        fs::read_to_string(testdir.path("out/cp_csv_to_mysql_using_inserts.csv"))
+            .unwrap();
+    assert_diff!(&expected, &actual, ",", 0);
+}
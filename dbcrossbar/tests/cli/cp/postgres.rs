@@ -48,6 +48,185 @@ fn cp_csv_to_postgres_append() {
         .expect_success();
 }
 
+#[test]
+#[ignore]
+fn cp_csv_to_postgres_dry_run() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_postgres_dry_run");
+    let src = testdir.src_path("fixtures/many_types.csv");
+    let schema = testdir.src_path("fixtures/many_types.sql");
+    let pg_table = post_test_table_url("cp_csv_to_postgres_dry_run");
+    Command::new("psql")
+        .arg(postgres_test_url())
+        .args([
+            "--command",
+            "DROP TABLE IF EXISTS cp_csv_to_postgres_dry_run;",
+        ])
+        .expect_success();
+
+    // Print our SQL without running it.
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--dry-run",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &pg_table,
+        ])
+        .tee_output()
+        .expect_success();
+    let stdout = output.stdout_str();
+    assert!(stdout.contains("statements:"));
+    assert!(stdout.contains("DROP TABLE IF EXISTS \"cp_csv_to_postgres_dry_run\";"));
+    assert!(stdout.contains("CREATE TABLE \"cp_csv_to_postgres_dry_run\""));
+    assert!(stdout.contains("COPY \"cp_csv_to_postgres_dry_run\""));
+
+    // Make sure we didn't create the table.
+    let output = Command::new("psql")
+        .arg(postgres_test_url())
+        .args([
+            "--tuples-only",
+            "--command",
+            "SELECT to_regclass('cp_csv_to_postgres_dry_run');",
+        ])
+        .expect_success();
+    assert_eq!(output.stdout_str().trim(), "");
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_postgres_verify() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_postgres_verify");
+    let src = testdir.src_path("fixtures/many_types.csv");
+    let schema = testdir.src_path("fixtures/many_types.sql");
+    let pg_table = post_test_table_url("cp_csv_to_postgres_verify");
+
+    // Overwrite, and compare the values we read back.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--verify=checksum",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &pg_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // Append, and only count the new rows.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=append",
+            "--verify=count",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &pg_table,
+        ])
+        .tee_output()
+        .expect_success();
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_postgres_schema_evolution() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_postgres_schema_evolution");
+    let pg_table = post_test_table_url("cp_csv_to_postgres_schema_evolution");
+    testdir.create_file("ab.sql", "CREATE TABLE t (a int, b text);");
+    testdir.create_file("ab.csv", "a,b\n1,x\n");
+    testdir.create_file("abc.sql", "CREATE TABLE t (a int, b text, c text);");
+    testdir.create_file("abc.csv", "a,b,c\n2,y,new\n");
+    testdir.create_file("ad.sql", "CREATE TABLE t (a int, d text);");
+    testdir.create_file("ad.csv", "a,d\n3,ignored\n");
+    let cp = |if_exists: &str, evolution: &str, name: &str| {
+        testdir
+            .cmd()
+            .args([
+                "cp",
+                &format!("--if-exists={}", if_exists),
+                &format!("--schema-evolution={}", evolution),
+                &format!("--schema=postgres-sql:{}.sql", name),
+                &format!("csv:{}.csv", name),
+                &pg_table,
+            ])
+            .tee_output()
+    };
+
+    cp("overwrite", "fail", "ab").expect_success();
+    // By default, we refuse to append columns the destination doesn't have.
+    cp("append", "fail", "abc").expect_failure();
+    cp("append", "add-missing-columns", "abc").expect_success();
+    cp("append", "ignore-extra", "ad").expect_success();
+
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:abc.sql",
+            &pg_table,
+            "csv:out.csv",
+        ])
+        .tee_output()
+        .expect_success();
+    let mut lines = fs::read_to_string(testdir.path("out.csv"))
+        .unwrap()
+        .lines()
+        .map(|l| l.to_owned())
+        .collect::<Vec<_>>();
+    lines.sort();
+    assert_eq!(lines, vec!["1,x,", "2,y,new", "3,,", "a,b,c"]);
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_postgres_match_columns() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_postgres_match_columns");
+    let pg_table = post_test_table_url("cp_csv_to_postgres_match_columns");
+    testdir.create_file("snake.sql", "CREATE TABLE t (user_id int, full_name text);");
+    testdir.create_file("snake.csv", "user_id,full_name\n1,Ann\n");
+    testdir.create_file("camel.sql", "CREATE TABLE t (fullName text, userId int);");
+    testdir.create_file("camel.csv", "fullName,userId\nBob,2\n");
+    let cp = |if_exists: &str, match_columns: &str, name: &str| {
+        testdir
+            .cmd()
+            .args([
+                "cp",
+                &format!("--if-exists={}", if_exists),
+                &format!("--match-columns={}", match_columns),
+                &format!("--schema=postgres-sql:{}.sql", name),
+                &format!("csv:{}.csv", name),
+                &pg_table,
+            ])
+            .tee_output()
+    };
+
+    cp("overwrite", "exact", "snake").expect_success();
+    cp("append", "exact", "camel").expect_failure();
+    cp("append", "snake-case", "camel").expect_success();
+
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--schema=postgres-sql:snake.sql",
+            &pg_table,
+            "csv:out.csv",
+        ])
+        .tee_output()
+        .expect_success();
+    let mut lines = fs::read_to_string(testdir.path("out.csv"))
+        .unwrap()
+        .lines()
+        .map(|l| l.to_owned())
+        .collect::<Vec<_>>();
+    lines.sort();
+    assert_eq!(lines, vec!["1,Ann", "2,Bob", "user_id,full_name"]);
+}
+
 #[test]
 #[ignore]
 fn cp_from_postgres_with_where() {
@@ -156,6 +335,83 @@ fn postgres_upsert() {
     assert_diff!(&expected, &actual, ",", 0);
 }
 
+#[test]
+#[ignore]
+fn postgres_upsert_multiple_streams() {
+    let _ = env_logger::try_init();
+    let testdir = TestDir::new("dbcrossbar", "postgres_upsert_multiple_streams");
+    let src = testdir.src_path("fixtures/upsert/upsert_1.csv");
+    let expected = testdir.src_path("fixtures/upsert/upsert_result.csv");
+    let schema = testdir.src_path("fixtures/upsert/upsert.sql");
+    let pg_table = post_test_table_url("postgres_upsert_multiple_streams");
+
+    // Split the rows we upsert between several files, so that we get one
+    // stream per file.
+    fs::create_dir_all(testdir.path("in")).unwrap();
+    let header = "key1,key2,value,more,select,camelCase\n";
+    fs::write(
+        testdir.path("in/1.csv"),
+        format!("{}1,2,c,\"[\"\"x\"\"]\",,\n", header),
+    )
+    .unwrap();
+    fs::write(
+        testdir.path("in/2.csv"),
+        format!("{}1,3,d,\"[\"\"x\"\",\"\"y\"\"]\",,\n", header),
+    )
+    .unwrap();
+
+    // CSV to PostgreSQL.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &pg_table,
+        ])
+        .tee_output()
+        .expect_success();
+    Command::new("psql")
+        .arg(postgres_test_url())
+        .args([
+            "--command",
+            "CREATE UNIQUE INDEX ON postgres_upsert_multiple_streams (key1, key2)",
+        ])
+        .expect_success();
+
+    // Upsert several streams at once.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=upsert-on:key1,key2",
+            "--max-streams=4",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}/", testdir.path("in").display()),
+            &pg_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // PostgreSQL to CSV.
+    testdir
+        .cmd()
+        .args(["cp", "--if-exists=overwrite", &pg_table, "csv:out.csv"])
+        .tee_output()
+        .expect_success();
+
+    let normalize_csv = |path: &Path| -> String {
+        let text = fs::read_to_string(path).unwrap();
+        let mut lines = text.lines().collect::<Vec<_>>();
+        lines.sort();
+        lines.join("\n")
+    };
+    let expected = normalize_csv(&expected);
+    let actual = normalize_csv(&testdir.path("out.csv"));
+    assert_diff!(&expected, &actual, ",", 0);
+}
+
 #[test]
 #[ignore]
 fn cp_pg_append_upsert_legacy_json() {
@@ -275,3 +531,188 @@ fn cp_pg_tricky_column_types() {
     let actual = fs::read_to_string(testdir.path("out.csv")).unwrap();
     assert_diff!(&expected, &actual, ",", 0);
 }
+
+#[test]
+#[ignore]
+fn cp_postgres_to_postgres_binary() {
+    let testdir = TestDir::new("dbcrossbar", "cp_postgres_to_postgres_binary");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let filtered = testdir.src_path("fixtures/posts_where_author_id_1.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let pg_table = post_test_table_url("cp_postgres_to_postgres_binary");
+    let pg_table_2 = post_test_table_url("cp_postgres_to_postgres_binary_2");
+
+    // CSV to Postgres.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &pg_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // Postgres to Postgres using --where, which should stream `BINARY` data
+    // directly between the two tables.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--where",
+            "author_id = 1",
+            &pg_table,
+            &pg_table_2,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // Postgres to CSV.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &pg_table_2,
+            "csv:out.csv",
+        ])
+        .tee_output()
+        .expect_success();
+
+    let expected = fs::read_to_string(&filtered).unwrap();
+    let actual = fs::read_to_string(testdir.path("out.csv")).unwrap();
+    assert_diff!(&expected, &actual, ",", 0);
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_postgres_with_lock() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_postgres_with_lock");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let pg_table = post_test_table_url("cp_csv_to_postgres_with_lock");
+    let cp_args = [
+        "cp",
+        "--lock=cp_csv_to_postgres_with_lock",
+        "--if-exists=overwrite",
+        &format!("--schema=postgres-sql:{}", schema.display()),
+        &format!("csv:{}", src.display()),
+        &pg_table,
+    ];
+
+    // Hold our lock from another session.
+    let mut holder = Command::new("psql")
+        .arg(postgres_test_url())
+        .args([
+            "--command",
+            "SELECT pg_advisory_lock(hashtext('dbcrossbar:cp_csv_to_postgres_with_lock')); SELECT pg_sleep(5);",
+        ])
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    let output = testdir.cmd().args(cp_args).expect_failure();
+    assert!(output.stderr_str().contains("is held by another process"));
+
+    // Once the other session has finished, we can copy.
+    assert!(holder.wait().unwrap().success());
+    testdir.cmd().args(cp_args).tee_output().expect_success();
+}
+
+#[test]
+#[ignore]
+fn cp_from_postgres_replica() {
+    let testdir = TestDir::new("dbcrossbar", "cp_from_postgres_replica");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let pg_table = post_test_table_url("cp_from_postgres_replica");
+
+    // CSV to Postgres.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &pg_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // Postgres to CSV using a read-only connection to our "replica", which is
+    // actually the same database.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            &format!("--from-arg=replica_url={}", postgres_test_url()),
+            "--from-arg=read_only=true",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &pg_table,
+            "csv:out.csv",
+        ])
+        .tee_output()
+        .expect_success();
+    let expected = fs::read_to_string(&src).unwrap();
+    let actual = fs::read_to_string(testdir.path("out.csv")).unwrap();
+    assert_diff!(&expected, &actual, ",", 0);
+
+    // An unavailable replica falls back to the primary with a warning.
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            "--from-arg=replica_url=postgres://postgres@127.0.0.1:1/postgres",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &pg_table,
+            "csv:out.csv",
+        ])
+        .tee_output()
+        .expect_success();
+    assert!(output.stderr_str().contains("could not connect to replica"));
+}
+
+#[test]
+#[ignore]
+fn cp_csv_to_postgres_load_id() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_postgres_load_id");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let pg_table = post_test_table_url("cp_csv_to_postgres_load_id");
+
+    let cp = |if_exists: &str, load_id: &str| {
+        testdir
+            .cmd()
+            .args([
+                "cp",
+                &format!("--if-exists={}", if_exists),
+                &format!("--to-arg=load_id={}", load_id),
+                &format!("--schema=postgres-sql:{}", schema.display()),
+                &format!("csv:{}", src.display()),
+                &pg_table,
+            ])
+            .tee_output()
+            .expect_success();
+    };
+    let count = || {
+        let output = testdir
+            .cmd()
+            .args(["count", &pg_table])
+            .tee_output()
+            .expect_success();
+        output.stdout_str().trim().to_owned()
+    };
+
+    // Re-running the same load doesn't load our chunk twice.
+    cp("overwrite", "first");
+    cp("append", "first");
+    assert_eq!(count(), "2");
+
+    // But a new load does.
+    cp("append", "second");
+    assert_eq!(count(), "4");
+}
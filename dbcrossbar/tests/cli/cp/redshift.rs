@@ -154,3 +154,81 @@ fn redshift_upsert() {
     let actual = normalize_csv(&testdir.path("out.csv"));
     assert_diff!(&expected, &actual, ",", 0);
 }
+
+#[test]
+#[ignore]
+fn cp_redshift_to_bigquery() {
+    let _ = env_logger::try_init();
+    let testdir = TestDir::new("dbcrossbar", "cp_redshift_to_bigquery");
+    let src = testdir.src_path("fixtures/redshift_types.csv");
+    let schema = testdir.src_path("fixtures/redshift_types.sql");
+    let s3_dir = s3_test_dir_url("cp_redshift_to_bigquery");
+    let gs_dir = gs_test_dir_url("cp_redshift_to_bigquery");
+    let bq_table = bq_test_table("cp_redshift_to_bigquery");
+    let redshift_table =
+        match redshift_test_table_url("public.cp_redshift_to_bigquery") {
+            Some(redshift_table) => redshift_table,
+            None => {
+                eprintln!("SKIPPING REDSHIFT TEST - PLEASE SET `REDSHIFT_TEST_URL`!");
+                return;
+            }
+        };
+    let iam_role =
+        env::var("REDSHIFT_TEST_IAM_ROLE").expect("Please set REDSHIFT_TEST_IAM_ROLE");
+    let region =
+        env::var("REDSHIFT_TEST_REGION").expect("Please set REDSHIFT_TEST_REGION");
+
+    // CSV to Redshift.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", s3_dir),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("--to-arg=iam_role={}", iam_role),
+            &format!("--to-arg=region={}", region),
+            &format!("csv:{}", src.display()),
+            &redshift_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // Redshift to BigQuery, staging the data in both S3 and Google Cloud
+    // Storage along the way.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", s3_dir),
+            &format!("--temporary={}", gs_dir),
+            &format!("--temporary={}", bq_temp_dataset()),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("--from-arg=iam_role={}", iam_role),
+            &format!("--from-arg=region={}", region),
+            &redshift_table,
+            &bq_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // BigQuery to CSV.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", gs_dir),
+            &format!("--temporary={}", bq_temp_dataset()),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &bq_table,
+            "csv:out.csv",
+        ])
+        .tee_output()
+        .expect_success();
+
+    let expected = fs::read_to_string(&src).unwrap();
+    let actual = fs::read_to_string(testdir.path("out.csv")).unwrap();
+    assert_eq!(normalize_csv_data(&expected), normalize_csv_data(&actual));
+}
@@ -0,0 +1,65 @@
+//! Snowflake-specific tests.
+
+use cli_test_dir::*;
+use difference::assert_diff;
+use std::fs;
+
+use super::*;
+
+#[test]
+#[ignore]
+fn cp_csv_to_snowflake_to_csv_with_where() {
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_snowflake_to_csv_with_where");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let filtered = testdir.src_path("fixtures/posts_where_author_id_1.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let s3_dir = s3_test_dir_url("cp_csv_to_snowflake_to_csv_with_where");
+    let snowflake_table =
+        match snowflake_test_table_url("cp_csv_to_snowflake_to_csv_with_where") {
+            Some(snowflake_table) => snowflake_table,
+            None => {
+                eprintln!(
+                    "SKIPPING SNOWFLAKE TEST - PLEASE SET `SNOWFLAKE_TEST_URL`!"
+                );
+                return;
+            }
+        };
+
+    // CSV to Snowflake, staged on S3.
+    testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", s3_dir),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &snowflake_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // Snowflake back to CSV using --where, via our user stage.
+    testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "cp",
+            "--where",
+            "\"author_id\" = 1",
+            &snowflake_table,
+            "csv:out.csv",
+        ])
+        .tee_output()
+        .expect_success();
+
+    let expected = fs::read_to_string(&filtered).unwrap();
+    let actual = fs::read_to_string(testdir.path("out.csv")).unwrap();
+    assert_diff!(
+        &normalize_csv_data(&expected),
+        &normalize_csv_data(&actual),
+        ",",
+        0
+    );
+}
@@ -0,0 +1,62 @@
+//! SQLite-specific tests.
+
+use cli_test_dir::*;
+use difference::assert_diff;
+use std::fs;
+
+#[test]
+#[ignore]
+fn cp_csv_to_sqlite_to_csv_with_where() {
+    // This only needs the `sqlite3` command-line tool, not a server.
+    let testdir = TestDir::new("dbcrossbar", "cp_csv_to_sqlite_to_csv_with_where");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let filtered = testdir.src_path("fixtures/posts_where_author_id_1.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+
+    // CSV to SQLite.
+    testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            "sqlite:test.sqlite3#posts",
+        ])
+        .tee_output()
+        .expect_success();
+
+    // We can read the schema back.
+    let output = testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "schema",
+            "conv",
+            "sqlite:test.sqlite3#posts",
+            "postgres-sql:-",
+        ])
+        .tee_output()
+        .expect_success();
+    assert!(output.stdout_str().contains(r#""author_id" bigint"#));
+    assert!(output.stdout_str().contains(r#""title" text"#));
+
+    // SQLite back to CSV using --where.
+    testdir
+        .cmd()
+        .args([
+            "--enable-unstable",
+            "cp",
+            "--where",
+            "author_id = 1",
+            "sqlite:test.sqlite3#posts",
+            "csv:out/",
+        ])
+        .tee_output()
+        .expect_success();
+
+    let expected = fs::read_to_string(&filtered).unwrap();
+    let actual = fs::read_to_string(testdir.path("out/posts.csv")).unwrap();
+    assert_diff!(&expected, &actual, ",", 0);
+}
@@ -0,0 +1,59 @@
+//! Tests for the `synthetic:` driver.
+
+use cli_test_dir::*;
+
+#[test]
+fn cp_synthetic_to_csv() {
+    let testdir = TestDir::new("dbcrossbar", "cp_synthetic_to_csv");
+    let schema = testdir.src_path("fixtures/many_types.sql");
+    let generate = || {
+        testdir
+            .cmd()
+            .args([
+                "cp",
+                "--validate=error",
+                &format!("--schema=postgres-sql:{}", schema.display()),
+                "--from-arg=seed=42",
+                "--from-arg=null_rate=0.5",
+                "synthetic:100",
+                "csv:-",
+            ])
+            .expect_success()
+            .stdout_str()
+            .to_owned()
+    };
+    let output = generate();
+    let mut rdr = csv::Reader::from_reader(output.as_bytes());
+    let rows = rdr.records().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(rows.len(), 100);
+    for row in &rows {
+        // `test_not_null` is declared `NOT NULL`.
+        assert!(!row[1].is_empty());
+    }
+
+    // The same seed always gives us the same data.
+    assert_eq!(generate(), output);
+}
+
+#[test]
+fn cp_synthetic_to_csv_column_options() {
+    let testdir = TestDir::new("dbcrossbar", "cp_synthetic_to_csv_column_options");
+    let schema = testdir.src_path("fixtures/example.sql");
+    let output = testdir
+        .cmd()
+        .args([
+            "cp",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            "--from-arg=null_rate=0",
+            "--from-arg=distribution=sequential",
+            "--from-arg=columns.first_name.cardinality=1",
+            "synthetic:3",
+            "csv:-",
+        ])
+        .expect_success();
+    assert_eq!(
+        output.stdout_str(),
+        "id,first_name,last_name\n0,first_name 0,last_name 0\n\
+         1,first_name 0,last_name 1\n2,first_name 0,last_name 2\n",
+    );
+}
@@ -0,0 +1,28 @@
+//! Tests for the `doctor` subcommand.
+
+use cli_test_dir::*;
+
+#[test]
+fn doctor_local_locators() {
+    let testdir = TestDir::new("dbcrossbar", "doctor_local_locators");
+    let src = testdir.src_path("fixtures/many_types.csv");
+    let output = testdir
+        .cmd()
+        .args(["doctor", &format!("csv:{}", src.display())])
+        .tee_output()
+        .expect_success();
+    assert!(output.stdout_str().contains("[ok  ] locator csv:"));
+}
+
+#[test]
+fn doctor_reports_fixes() {
+    let testdir = TestDir::new("dbcrossbar", "doctor_reports_fixes");
+    let output = testdir
+        .cmd()
+        .args(["doctor", "nosuchdriver:foo"])
+        .tee_output()
+        .expect_failure();
+    let stdout = output.stdout_str();
+    assert!(stdout.contains("[FAIL] locator nosuchdriver:foo"));
+    assert!(stdout.contains("fix: check the locator syntax"));
+}
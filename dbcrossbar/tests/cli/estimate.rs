@@ -0,0 +1,96 @@
+//! Tests for the `estimate` subcommand.
+
+use cli_test_dir::*;
+
+use super::cp::*;
+
+#[test]
+fn estimate_csv() {
+    let testdir = TestDir::new("dbcrossbar", "estimate_csv");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let output = testdir
+        .cmd()
+        .args(["estimate", "--json", &format!("csv:{}", src.display())])
+        .tee_output()
+        .expect_success();
+    let estimate: serde_json::Value =
+        serde_json::from_str(output.stdout_str()).unwrap();
+    assert_eq!(estimate["rows"], 2);
+    assert_eq!(estimate["bytes"], 39);
+    assert_eq!(estimate["files"], 1);
+}
+
+#[test]
+fn estimate_csv_stdin_is_unsupported() {
+    let testdir = TestDir::new("dbcrossbar", "estimate_csv_stdin_is_unsupported");
+    testdir.cmd().args(["estimate", "csv:-"]).expect_failure();
+}
+
+#[test]
+#[ignore]
+fn estimate_bigquery() {
+    let testdir = TestDir::new("dbcrossbar", "estimate_bigquery");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let gs_temp_dir = gs_test_dir_url("estimate_bigquery");
+    let bq_temp_ds = bq_temp_dataset();
+    let bq_table = bq_test_table("estimate_bigquery");
+
+    // CSV to BigQuery.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--temporary={}", gs_temp_dir),
+            &format!("--temporary={}", bq_temp_ds),
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &bq_table,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // Estimate BigQuery.
+    let output = testdir
+        .cmd()
+        .args(["estimate", &bq_table])
+        .tee_output()
+        .expect_success();
+    assert!(output.stdout_str().contains("rows: 2"));
+    assert!(output.stdout_str().contains("method: table statistics"));
+}
+
+#[test]
+#[ignore]
+fn estimate_s3() {
+    let testdir = TestDir::new("dbcrossbar", "estimate_s3");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let s3_dir = s3_test_dir_url("estimate_s3");
+
+    // CSV to S3.
+    testdir
+        .cmd()
+        .args([
+            "cp",
+            "--if-exists=overwrite",
+            &format!("--schema=postgres-sql:{}", schema.display()),
+            &format!("csv:{}", src.display()),
+            &s3_dir,
+        ])
+        .tee_output()
+        .expect_success();
+
+    // Estimate S3.
+    let output = testdir
+        .cmd()
+        .args(["estimate", "--json", &s3_dir])
+        .tee_output()
+        .expect_success();
+    let estimate: serde_json::Value =
+        serde_json::from_str(output.stdout_str()).unwrap();
+    assert_eq!(estimate["rows"], 2);
+    assert_eq!(estimate["bytes"], 39);
+    assert_eq!(estimate["files"], 1);
+}
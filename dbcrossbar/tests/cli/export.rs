@@ -0,0 +1,65 @@
+//! Tests for the `export` subcommand.
+
+use cli_test_dir::*;
+use std::fs;
+
+use super::cp::*;
+
+#[test]
+fn export_requires_table_placeholder() {
+    let testdir = TestDir::new("dbcrossbar", "export_requires_table_placeholder");
+    let output = testdir
+        .cmd()
+        .args(["export", "bigquery:project:dataset", "csv:out/"])
+        .expect_failure();
+    assert!(output.stderr_str().contains("{table}"));
+}
+
+#[test]
+#[ignore]
+fn export_bigquery_dataset() {
+    let testdir = TestDir::new("dbcrossbar", "export_bigquery_dataset");
+    let src = testdir.src_path("fixtures/posts.csv");
+    let schema = testdir.src_path("fixtures/posts.sql");
+    let gs_temp_dir = gs_test_dir_url("export_bigquery_dataset");
+    let bq_temp_ds = bq_temp_dataset();
+
+    // Load two tables with a shared prefix.
+    for name in &["export_bigquery_dataset_1", "export_bigquery_dataset_2"] {
+        testdir
+            .cmd()
+            .args([
+                "cp",
+                "--if-exists=overwrite",
+                &format!("--temporary={}", gs_temp_dir),
+                &format!("--temporary={}", bq_temp_ds),
+                &format!("--schema=postgres-sql:{}", schema.display()),
+                &format!("csv:{}", src.display()),
+                &bq_test_table(name),
+            ])
+            .tee_output()
+            .expect_success();
+    }
+
+    // Export both tables.
+    testdir
+        .cmd()
+        .args([
+            "export",
+            "--tables=export_bigquery_dataset_*",
+            &format!("--temporary={}", gs_temp_dir),
+            &format!("--temporary={}", bq_temp_ds),
+            &bq_temp_dataset(),
+            "csv:out/{table}/",
+        ])
+        .tee_output()
+        .expect_success();
+
+    let expected = fs::read_to_string(&src).unwrap();
+    for name in &["export_bigquery_dataset_1", "export_bigquery_dataset_2"] {
+        let actual =
+            fs::read_to_string(testdir.path(format!("out/{}/000000000000.csv", name)))
+                .unwrap();
+        assert_eq!(normalize_csv_data(&expected), normalize_csv_data(&actual));
+    }
+}
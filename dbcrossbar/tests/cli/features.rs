@@ -0,0 +1,23 @@
+//! Listing driver features.
+
+use cli_test_dir::*;
+
+#[test]
+fn features_json() {
+    let testdir = TestDir::new("dbcrossbar", "features_json");
+    let output = testdir
+        .cmd()
+        .args(["features", "--json", "bigquery"])
+        .expect_success();
+    let capabilities =
+        serde_json::from_str::<serde_json::Value>(output.stdout_str()).unwrap();
+    assert_eq!(capabilities["scheme"], "bigquery:");
+    assert!(capabilities["remote_sources"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::Value::from("gs:")));
+
+    let output = testdir.cmd().args(["features", "--json"]).expect_success();
+    let all = serde_json::from_str::<serde_json::Value>(output.stdout_str()).unwrap();
+    assert!(all.as_array().unwrap().len() > 1);
+}
@@ -3,6 +3,14 @@
 //! This is the top-level file for a single CLI integration test binary.
 
 pub(crate) mod about;
+pub(crate) mod auth;
+pub(crate) mod bench;
 pub(crate) mod conv;
 pub(crate) mod count;
 pub(crate) mod cp;
+pub(crate) mod doctor;
+pub(crate) mod estimate;
+pub(crate) mod export;
+pub(crate) mod features;
+pub(crate) mod pipeline;
+pub(crate) mod serve;
@@ -0,0 +1,86 @@
+//! Tests for the `pipeline` subcommand.
+
+use cli_test_dir::*;
+use std::fs;
+
+#[test]
+fn pipeline_runs_steps_after_dependencies() {
+    let testdir = TestDir::new("dbcrossbar", "pipeline_runs_steps_after_dependencies");
+    let src = testdir.src_path("fixtures/example.csv");
+    let pipeline = serde_json::json!({
+        "steps": [
+            {
+                "name": "report",
+                "depends_on": ["extract"],
+                "exec": ["sh", "-c", "wc -l < out.csv > report.txt"],
+            },
+            {
+                "name": "extract",
+                "args": ["cp", format!("csv:{}", src.display()), "csv:out.csv"],
+            },
+        ],
+    });
+    testdir.create_file("pipeline.json", pipeline.to_string());
+    testdir
+        .cmd()
+        .args(["pipeline", "pipeline.json"])
+        .expect_success();
+    let expected = fs::read_to_string(&src).unwrap();
+    testdir.expect_file_contents("out.csv", &expected);
+    testdir
+        .expect_file_contents("report.txt", format!("{}\n", expected.lines().count()));
+    assert!(!testdir.path("pipeline.json.state.json").exists());
+}
+
+#[test]
+fn pipeline_resumes_at_failed_step() {
+    let testdir = TestDir::new("dbcrossbar", "pipeline_resumes_at_failed_step");
+    let pipeline = serde_json::json!({
+        "steps": [
+            { "name": "extract", "exec": ["sh", "-c", "echo extract >> log.txt"] },
+            { "name": "validate", "depends_on": ["extract"], "exec": ["test", "-f", "ready"] },
+            { "name": "load", "depends_on": ["validate"], "exec": ["sh", "-c", "echo load >> log.txt"] },
+        ],
+    });
+    testdir.create_file("pipeline.json", pipeline.to_string());
+    let output = testdir
+        .cmd()
+        .args(["pipeline", "pipeline.json"])
+        .expect_failure();
+    assert!(output.stderr_str().contains("validate"));
+    testdir.expect_file_contents("log.txt", "extract\n");
+    testdir.expect_path("pipeline.json.state.json");
+
+    // Once the problem is fixed, we resume at the failed step.
+    testdir.create_file("ready", "");
+    testdir
+        .cmd()
+        .args(["pipeline", "pipeline.json"])
+        .expect_success();
+    testdir.expect_file_contents("log.txt", "extract\nload\n");
+    assert!(!testdir.path("pipeline.json.state.json").exists());
+
+    // Without any saved state, we run everything again.
+    testdir
+        .cmd()
+        .args(["pipeline", "pipeline.json"])
+        .expect_success();
+    testdir.expect_file_contents("log.txt", "extract\nload\nextract\nload\n");
+}
+
+#[test]
+fn pipeline_rejects_circular_dependencies() {
+    let testdir = TestDir::new("dbcrossbar", "pipeline_rejects_circular_dependencies");
+    let pipeline = serde_json::json!({
+        "steps": [
+            { "name": "a", "depends_on": ["b"], "exec": ["true"] },
+            { "name": "b", "depends_on": ["a"], "exec": ["true"] },
+        ],
+    });
+    testdir.create_file("pipeline.json", pipeline.to_string());
+    let output = testdir
+        .cmd()
+        .args(["pipeline", "pipeline.json"])
+        .expect_failure();
+    assert!(output.stderr_str().contains("circular"));
+}
@@ -0,0 +1,181 @@
+//! Tests for the `serve` subcommand.
+
+use cli_test_dir::*;
+use serde_json::Value;
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Child, Stdio},
+    thread::sleep,
+    time::Duration,
+};
+
+/// A running `dbcrossbar serve` process, which we kill when we're done.
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    /// Start a server in `testdir`, with an optional `token`.
+    fn start(testdir: &TestDir, token: Option<&str>) -> Server {
+        let mut cmd = testdir.cmd();
+        cmd.args(["serve", "--listen", "127.0.0.1:0"])
+            .stdout(Stdio::piped())
+            .env_remove("DBCROSSBAR_SERVE_TOKEN");
+        if let Some(token) = token {
+            cmd.env("DBCROSSBAR_SERVE_TOKEN", token);
+        }
+        let mut child = cmd.spawn().expect("could not start server");
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let addr = line
+            .trim()
+            .strip_prefix("listening on http://")
+            .unwrap_or_else(|| panic!("unexpected output: {:?}", line))
+            .to_owned();
+        Server { child, addr }
+    }
+
+    /// Make an HTTP request, returning the status code and body.
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+        body: &str,
+    ) -> (u16, String) {
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        let auth = token
+            .map(|t| format!("Authorization: Bearer {}\r\n", t))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            self.addr,
+            auth,
+            body.len(),
+            body,
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response
+            .split(' ')
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_else(|| panic!("unexpected response: {:?}", response));
+        let body = match response.find("\r\n\r\n") {
+            Some(idx) => response[idx + 4..].to_owned(),
+            None => String::new(),
+        };
+        (status, body)
+    }
+
+    /// Make a request and parse the response as JSON.
+    fn request_json(
+        &self,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+        body: &str,
+    ) -> (u16, Value) {
+        let (status, body) = self.request(method, path, token, body);
+        let value = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("expected JSON, found {:?}", body));
+        (status, value)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn serve_runs_copy_jobs() {
+    let testdir = TestDir::new("dbcrossbar", "serve_runs_copy_jobs");
+    let src = testdir.src_path("fixtures/example.csv");
+    let dest = testdir.path("out.csv");
+    let server = Server::start(&testdir, None);
+
+    let (status, _) = server.request_json("GET", "/health", None, "");
+    assert_eq!(status, 200);
+
+    let submit = serde_json::json!({
+        "args": [format!("csv:{}", src.display()), format!("csv:{}", dest.display())],
+    });
+    let (status, job) =
+        server.request_json("POST", "/jobs", None, &submit.to_string());
+    assert_eq!(status, 201);
+    let id = job["id"].as_u64().unwrap();
+
+    // Wait for the job to finish.
+    let mut job = job;
+    for _ in 0..100 {
+        if job["state"] != "queued" && job["state"] != "running" {
+            break;
+        }
+        sleep(Duration::from_millis(100));
+        job = server
+            .request_json("GET", &format!("/jobs/{}", id), None, "")
+            .1;
+    }
+    assert_eq!(job["state"], "succeeded", "{}", job);
+    assert_eq!(
+        fs::read_to_string(&dest).unwrap(),
+        fs::read_to_string(&src).unwrap(),
+    );
+
+    // Finished jobs are listed, but can't be cancelled.
+    let (_, jobs) = server.request_json("GET", "/jobs", None, "");
+    assert_eq!(jobs.as_array().unwrap().len(), 1);
+    let (status, _) =
+        server.request_json("DELETE", &format!("/jobs/{}", id), None, "");
+    assert_eq!(status, 409);
+    let (status, _) = server.request_json("GET", "/jobs/999", None, "");
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn serve_rejects_bad_jobs() {
+    let testdir = TestDir::new("dbcrossbar", "serve_rejects_bad_jobs");
+    let server = Server::start(&testdir, None);
+    let (status, body) = server.request_json("POST", "/jobs", None, "{\"from\": 1}");
+    assert_eq!(status, 400);
+    assert!(body["error"].is_string());
+}
+
+#[test]
+fn serve_requires_token_when_set() {
+    let testdir = TestDir::new("dbcrossbar", "serve_requires_token_when_set");
+    let server = Server::start(&testdir, Some("secret"));
+    let (status, _) = server.request_json("GET", "/health", None, "");
+    assert_eq!(status, 200);
+    let (status, _) = server.request_json("GET", "/jobs", None, "");
+    assert_eq!(status, 401);
+    let (status, _) = server.request_json("GET", "/jobs", Some("wrong"), "");
+    assert_eq!(status, 401);
+    let (status, jobs) = server.request_json("GET", "/jobs", Some("secret"), "");
+    assert_eq!(status, 200);
+    assert_eq!(jobs, serde_json::json!([]));
+}
+
+#[test]
+fn serve_requires_token_on_non_loopback_address() {
+    let testdir =
+        TestDir::new("dbcrossbar", "serve_requires_token_on_non_loopback_address");
+    let output = testdir
+        .cmd()
+        .args(["serve", "--listen", "0.0.0.0:0"])
+        .env_remove("DBCROSSBAR_SERVE_TOKEN")
+        .expect_failure();
+    assert!(output.stderr_str().contains("DBCROSSBAR_SERVE_TOKEN"));
+}
@@ -63,6 +63,11 @@ pub struct SharedArguments<S: ArgumentState> {
     /// How many streams should we process at once?
     max_streams: usize,
 
+    /// If set, our data will be split into streams of about this many bytes,
+    /// and each stream will only be produced after the previous one has been
+    /// read.
+    stream_size: Option<usize>,
+
     /// We need to include a reference to `ArgumentState` somewhere, so use a
     /// 0-byte phantom value.
     _phantom: PhantomData<S>,
@@ -76,6 +81,13 @@ impl<S: ArgumentState> SharedArguments<S> {
     pub fn max_streams(&self) -> usize {
         self.max_streams
     }
+
+    /// The approximate size of each stream, if we're splitting our data into
+    /// streams one at a time using `--stream-size`. Destinations which need
+    /// to see every stream before they write anything can't support this.
+    pub fn stream_size(&self) -> Option<usize> {
+        self.stream_size
+    }
 }
 
 // These methods are only available in the `Unverified` state.
@@ -90,10 +102,20 @@ impl SharedArguments<Unverified> {
             schema,
             temporary_storage,
             max_streams,
+            stream_size: None,
             _phantom: PhantomData,
         }
     }
 
+    /// Specify the approximate size of the streams we'll produce using
+    /// `--stream-size`, if any.
+    pub fn with_stream_size(self, stream_size: Option<usize>) -> Self {
+        Self {
+            stream_size,
+            ..self
+        }
+    }
+
     /// Verify that this structure only contains supported arguments. This uses
     /// the [type state][] pattern to keep track of whether our arguments have
     /// been verified to be supported.
@@ -105,6 +127,7 @@ impl SharedArguments<Unverified> {
             schema: self.schema,
             temporary_storage: self.temporary_storage,
             max_streams: self.max_streams,
+            stream_size: self.stream_size,
             _phantom: PhantomData,
         })
     }
@@ -219,6 +242,9 @@ impl SourceArguments<Verified> {
 #[derive(Debug, EnumSetType)]
 pub enum DestinationArgumentsFeatures {
     DriverArgs,
+    SchemaEvolution,
+    MatchColumns,
+    Compression,
 }
 
 impl fmt::Display for DisplayEnumSet<DestinationArgumentsFeatures> {
@@ -227,6 +253,18 @@ impl fmt::Display for DisplayEnumSet<DestinationArgumentsFeatures> {
         if self.0.contains(DestinationArgumentsFeatures::DriverArgs) {
             write!(f, "{}--to-arg=$NAME=$VALUE", sep.display())?;
         }
+        if self
+            .0
+            .contains(DestinationArgumentsFeatures::SchemaEvolution)
+        {
+            write!(f, "{}--schema-evolution=$POLICY", sep.display())?;
+        }
+        if self.0.contains(DestinationArgumentsFeatures::MatchColumns) {
+            write!(f, "{}--match-columns=$RULE", sep.display())?;
+        }
+        if self.0.contains(DestinationArgumentsFeatures::Compression) {
+            write!(f, "{}--compression=gzip", sep.display())?;
+        }
         Ok(())
     }
 }
@@ -239,6 +277,16 @@ pub struct DestinationArguments<ArgumentState> {
     /// What to do it the destination already exists.
     if_exists: IfExists,
 
+    /// What to do if we're appending to a destination which is missing some
+    /// of our columns.
+    schema_evolution: SchemaEvolution,
+
+    /// How to match our columns to the columns of an existing destination.
+    match_columns: ColumnMatching,
+
+    /// How to compress the files we write.
+    compression: OutputCompression,
+
     /// We need to include a reference to `ArgumentState` somewhere, so use a
     /// 0-byte phantom value.
     _phantom: PhantomData<ArgumentState>,
@@ -247,10 +295,19 @@ pub struct DestinationArguments<ArgumentState> {
 // These methods are only available in the `Unverified` state.
 impl DestinationArguments<Unverified> {
     /// Construct a new `DestinationArguments`.
-    pub fn new(driver_args: DriverArguments, if_exists: IfExists) -> Self {
+    pub fn new(
+        driver_args: DriverArguments,
+        if_exists: IfExists,
+        schema_evolution: SchemaEvolution,
+        match_columns: ColumnMatching,
+        compression: OutputCompression,
+    ) -> Self {
         DestinationArguments {
             driver_args,
             if_exists,
+            schema_evolution,
+            match_columns,
+            compression,
             _phantom: PhantomData,
         }
     }
@@ -258,7 +315,13 @@ impl DestinationArguments<Unverified> {
     /// Construct a new `DestinationArguments` with typical values for a
     /// temporary storage location.
     pub fn for_temporary() -> Self {
-        Self::new(DriverArguments::default(), IfExists::Overwrite)
+        Self::new(
+            DriverArguments::default(),
+            IfExists::Overwrite,
+            SchemaEvolution::default(),
+            ColumnMatching::default(),
+            OutputCompression::default(),
+        )
     }
 
     /// Verify that this structure only contains supported arguments. This uses
@@ -277,9 +340,51 @@ impl DestinationArguments<Unverified> {
             ));
         }
         self.if_exists.verify(features.dest_if_exists)?;
+        if self.schema_evolution != SchemaEvolution::default() {
+            if !features
+                .dest_args
+                .contains(DestinationArgumentsFeatures::SchemaEvolution)
+            {
+                return Err(format_err!(
+                    "this data destination does not support --schema-evolution"
+                ));
+            }
+            if !matches!(self.if_exists, IfExists::Append | IfExists::Upsert(_)) {
+                return Err(format_err!(
+                    "--schema-evolution requires --if-exists=append or --if-exists=upsert-on:..."
+                ));
+            }
+        }
+        if self.match_columns != ColumnMatching::default() {
+            if !features
+                .dest_args
+                .contains(DestinationArgumentsFeatures::MatchColumns)
+            {
+                return Err(format_err!(
+                    "this data destination does not support --match-columns"
+                ));
+            }
+            if !matches!(self.if_exists, IfExists::Append | IfExists::Upsert(_)) {
+                return Err(format_err!(
+                    "--match-columns requires --if-exists=append or --if-exists=upsert-on:..."
+                ));
+            }
+        }
+        if self.compression != OutputCompression::default()
+            && !features
+                .dest_args
+                .contains(DestinationArgumentsFeatures::Compression)
+        {
+            return Err(format_err!(
+                "this data destination does not support --compression"
+            ));
+        }
         Ok(DestinationArguments {
             driver_args: self.driver_args,
             if_exists: self.if_exists,
+            schema_evolution: self.schema_evolution,
+            match_columns: self.match_columns,
+            compression: self.compression,
             _phantom: PhantomData,
         })
     }
@@ -296,4 +401,20 @@ impl DestinationArguments<Verified> {
     pub fn if_exists(&self) -> &IfExists {
         &self.if_exists
     }
+
+    /// What to do if we're appending to a destination which is missing some
+    /// of our columns.
+    pub fn schema_evolution(&self) -> SchemaEvolution {
+        self.schema_evolution
+    }
+
+    /// How to match our columns to the columns of an existing destination.
+    pub fn match_columns(&self) -> ColumnMatching {
+        self.match_columns
+    }
+
+    /// How to compress the files we write.
+    pub fn compression(&self) -> OutputCompression {
+        self.compression
+    }
 }
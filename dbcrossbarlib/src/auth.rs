@@ -0,0 +1,26 @@
+//! Logging in to cloud services.
+
+use std::path::Path;
+
+use crate::clouds::gcloud::auth;
+use crate::common::*;
+
+/// Log in to Google Cloud interactively using a client secret, and cache the
+/// resulting tokens for BigQuery and Cloud Storage.
+///
+/// If `client_secret` is specified, it will be saved in our config directory
+/// for future use. Otherwise, we use the configured client secret.
+pub async fn gcloud_login(ctx: &Context, client_secret: Option<&Path>) -> Result<()> {
+    auth::login(ctx, client_secret).await
+}
+
+/// Delete any Google Cloud tokens cached by `gcloud_login`. Returns true if
+/// we were logged in.
+pub async fn gcloud_logout(ctx: &Context) -> Result<bool> {
+    auth::logout(ctx).await
+}
+
+/// Describe the Google Cloud credentials that we would use.
+pub async fn gcloud_credentials(ctx: &Context) -> Result<String> {
+    Ok(auth::find_credentials(ctx).await?.to_string())
+}
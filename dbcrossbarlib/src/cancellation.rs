@@ -0,0 +1,134 @@
+//! Cancelling running operations.
+//!
+//! Every `Context` has a `CancellationToken`, which is shared with all its
+//! children. Library users can call `cancel` on the token to stop a copy.
+//! Drivers check the token in their long-running loops, and the background
+//! worker future returned by `Context::create` fails as soon as the token is
+//! cancelled, which drops any work running in the same task.
+
+use std::{
+    error, fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::{sync::watch, time::Duration};
+
+use crate::common::*;
+
+/// The error returned by operations which have been cancelled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation was cancelled")
+    }
+}
+
+impl error::Error for Cancelled {}
+
+/// Shared state for a `CancellationToken`.
+#[derive(Debug)]
+struct Inner {
+    /// Have we been cancelled?
+    cancelled: AtomicBool,
+    /// Used to notify anybody waiting in `cancelled`.
+    sender: watch::Sender<bool>,
+    /// Cloned by anybody waiting in `cancelled`.
+    receiver: watch::Receiver<bool>,
+}
+
+/// A handle which can be used to cancel an operation. Cloning a
+/// `CancellationToken` returns another handle to the same token.
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+impl CancellationToken {
+    /// Create a new token which hasn't been cancelled.
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        CancellationToken {
+            inner: Arc::new(Inner {
+                cancelled: AtomicBool::new(false),
+                sender,
+                receiver,
+            }),
+        }
+    }
+
+    /// Cancel any operations using this token.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        // This can only fail if there are no receivers, but we own one.
+        let _ = self.inner.sender.broadcast(true);
+    }
+
+    /// Has this token been cancelled?
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Return `Err(Cancelled)` if this token has been cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Cancelled.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Wait until this token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.inner.receiver.clone();
+        while !self.is_cancelled() {
+            if receiver.recv().await.is_none() {
+                // Our sender is gone, so we'll never be cancelled.
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Sleep for `duration`, or return `Err(Cancelled)` if we're cancelled
+    /// first.
+    pub async fn delay_for(&self, duration: Duration) -> Result<()> {
+        let delay = tokio::time::delay_for(duration);
+        let cancelled = self.cancelled().boxed();
+        match futures::future::select(delay, cancelled).await {
+            futures::future::Either::Left(((), _)) => self.check(),
+            futures::future::Either::Right(((), _)) => Err(Cancelled.into()),
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn cancels_waiting_operations() {
+    let token = CancellationToken::new();
+    token.check().unwrap();
+
+    let waiter = token.clone();
+    let fut = async move {
+        let canceller = async {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+            token.cancel();
+        };
+        let (result, ()) =
+            futures::join!(waiter.delay_for(Duration::from_secs(60)), canceller);
+        let err = result.unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+        assert!(waiter.is_cancelled());
+        waiter.cancelled().await;
+        Ok::<(), Error>(())
+    };
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(fut.boxed()).unwrap();
+}
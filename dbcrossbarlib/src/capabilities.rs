@@ -0,0 +1,199 @@
+//! Describing what each driver can do.
+//!
+//! This is a machine-readable version of the information printed by
+//! `dbcrossbar features $DRIVER`, which allows tools to check whether a copy
+//! is possible before trying it.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::common::*;
+use crate::locator::LocatorDriver;
+use crate::schema::{DataType, Srid, StructField};
+
+/// What a driver can do.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Capabilities {
+    /// The scheme used by this driver, such as `"postgres:"`.
+    pub scheme: String,
+
+    /// Is this driver unstable?
+    pub unstable: bool,
+
+    /// Can we read schemas from this driver?
+    pub schema: bool,
+
+    /// Can we read the schema of an SQL query's results using this driver?
+    pub query_schema: bool,
+
+    /// Can we write schemas to this driver?
+    pub write_schema: bool,
+
+    /// Supported `--if-exists` values when writing schemas.
+    pub write_schema_if_exists: Vec<String>,
+
+    /// Can we count rows using this driver?
+    pub count: bool,
+
+    /// Can we estimate the size of data using this driver?
+    pub estimate: bool,
+
+    /// Can we read data from this driver?
+    pub local_data: bool,
+
+    /// Supported source arguments, such as `"from_arg"` and `"where"`.
+    pub source_args: Vec<String>,
+
+    /// Can we write data to this driver?
+    pub write_local_data: bool,
+
+    /// Supported destination arguments, such as `"to_arg"`.
+    pub dest_args: Vec<String>,
+
+    /// Supported `--if-exists` values when writing data.
+    pub if_exists: Vec<String>,
+
+    /// Can we use this driver to hold a `cp --lock`?
+    pub lock: bool,
+
+    /// Schemes which this driver may be able to copy from directly, without
+    /// passing data through `dbcrossbar`. Some drivers have extra
+    /// requirements, such as both BigQuery tables being in the same project.
+    pub remote_sources: Vec<String>,
+
+    /// Portable data types which this driver can write.
+    pub data_types: Vec<String>,
+}
+
+impl Capabilities {
+    /// Describe the capabilities of `driver`.
+    pub(crate) fn for_driver(driver: &dyn LocatorDriver) -> Capabilities {
+        let features = driver.features();
+        Capabilities {
+            scheme: driver.scheme().to_owned(),
+            unstable: driver.is_unstable(),
+            schema: features.locator.contains(LocatorFeatures::Schema),
+            query_schema: features.locator.contains(LocatorFeatures::QuerySchema),
+            write_schema: features.locator.contains(LocatorFeatures::WriteSchema),
+            write_schema_if_exists: if_exists_names(features.write_schema_if_exists),
+            count: features.locator.contains(LocatorFeatures::Count),
+            estimate: features.locator.contains(LocatorFeatures::Estimate),
+            local_data: features.locator.contains(LocatorFeatures::LocalData),
+            source_args: features
+                .source_args
+                .iter()
+                .map(|f| match f {
+                    SourceArgumentsFeatures::DriverArgs => "from_arg".to_owned(),
+                    SourceArgumentsFeatures::WhereClause => "where".to_owned(),
+                })
+                .collect(),
+            write_local_data: features
+                .locator
+                .contains(LocatorFeatures::WriteLocalData),
+            dest_args: features
+                .dest_args
+                .iter()
+                .map(|f| match f {
+                    DestinationArgumentsFeatures::DriverArgs => "to_arg".to_owned(),
+                    DestinationArgumentsFeatures::SchemaEvolution => {
+                        "schema_evolution".to_owned()
+                    }
+                    DestinationArgumentsFeatures::MatchColumns => {
+                        "match_columns".to_owned()
+                    }
+                    DestinationArgumentsFeatures::Compression => {
+                        "compression".to_owned()
+                    }
+                })
+                .collect(),
+            if_exists: if_exists_names(features.dest_if_exists),
+            lock: features.locator.contains(LocatorFeatures::Lock),
+            remote_sources: driver
+                .remote_sources()
+                .iter()
+                .map(|&s| s.to_owned())
+                .collect(),
+            data_types: example_data_types()
+                .iter()
+                .filter(|data_type| driver.can_write_data_type(data_type))
+                .map(data_type_name)
+                .collect(),
+        }
+    }
+}
+
+/// Convert a set of `IfExistsFeatures` into `--if-exists` values.
+fn if_exists_names(features: EnumSet<IfExistsFeatures>) -> Vec<String> {
+    features
+        .iter()
+        .map(|f| match f {
+            IfExistsFeatures::Error => "error".to_owned(),
+            IfExistsFeatures::Append => "append".to_owned(),
+            IfExistsFeatures::Overwrite => "overwrite".to_owned(),
+            IfExistsFeatures::Upsert => "upsert-on".to_owned(),
+        })
+        .collect()
+}
+
+/// One example of each kind of portable data type.
+fn example_data_types() -> Vec<DataType> {
+    vec![
+        DataType::Array(Box::new(DataType::Text)),
+        DataType::Bool,
+        DataType::Date,
+        DataType::Decimal,
+        DataType::Float32,
+        DataType::Float64,
+        DataType::GeoJson(Srid::wgs84()),
+        DataType::Int16,
+        DataType::Int32,
+        DataType::Int64,
+        DataType::Json,
+        DataType::Struct(vec![StructField {
+            name: "x".to_owned(),
+            is_nullable: true,
+            data_type: DataType::Text,
+        }]),
+        DataType::Text,
+        DataType::TimestampWithoutTimeZone,
+        DataType::TimestampWithTimeZone,
+        DataType::Uuid,
+    ]
+}
+
+/// The name of a data type, as used in our portable schemas.
+fn data_type_name(data_type: &DataType) -> String {
+    match serde_json::to_value(data_type).expect("could not serialize data type") {
+        Value::String(name) => name,
+        Value::Object(obj) => obj
+            .keys()
+            .next()
+            .expect("data type should have a name")
+            .to_owned(),
+        other => panic!("unexpected serialized data type {}", other),
+    }
+}
+
+#[test]
+fn describes_driver_capabilities() {
+    use crate::drivers::find_driver;
+
+    let csv = find_driver("csv:", false).unwrap().capabilities();
+    assert!(csv.local_data && csv.write_local_data && csv.schema);
+    assert!(csv.remote_sources.is_empty());
+    assert_eq!(csv.data_types.len(), example_data_types().len());
+    assert!(csv.data_types.contains(&"geo_json".to_owned()));
+
+    let bigquery = find_driver("bigquery:", false).unwrap().capabilities();
+    assert!(bigquery.if_exists.contains(&"upsert-on".to_owned()));
+    assert!(bigquery.remote_sources.contains(&"gs:".to_owned()));
+    assert!(bigquery.query_schema);
+    assert!(!csv.query_schema);
+    assert!(csv.estimate && bigquery.estimate);
+
+    let redshift = find_driver("redshift:", false).unwrap().capabilities();
+    assert!(redshift.remote_sources.contains(&"s3:".to_owned()));
+    assert!(!redshift.data_types.contains(&"uuid".to_owned()));
+    assert!(!redshift.estimate);
+    assert!(!csv.lock && !redshift.lock);
+}
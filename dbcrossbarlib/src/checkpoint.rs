@@ -0,0 +1,176 @@
+//! Recording which streams of a copy have been written, so that a failed copy
+//! can be resumed using `--checkpoint`.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::common::*;
+
+/// The contents of a checkpoint file.
+#[derive(Debug, Deserialize, Serialize)]
+struct CheckpointState {
+    /// The source locator of the copy.
+    source: String,
+    /// The destination locator of the copy.
+    dest: String,
+    /// The names of the streams which have been completely written.
+    written_streams: BTreeSet<String>,
+}
+
+/// A checkpoint file, recording which streams of a copy have been completely
+/// written. This is shared between all the streams of a copy.
+#[derive(Debug)]
+pub struct Checkpoint {
+    /// Where we store our checkpoint.
+    path: PathBuf,
+    /// Our current state.
+    state: Mutex<CheckpointState>,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint at `path` for a copy from `source` to `dest`, or
+    /// start a new checkpoint if `path` doesn't exist.
+    pub fn load_or_new(path: &Path, source: &str, dest: &str) -> Result<Checkpoint> {
+        let state = if path.exists() {
+            let json = fs::read_to_string(path)
+                .with_context(|_| format!("could not read {}", path.display()))?;
+            let state = serde_json::from_str::<CheckpointState>(&json)
+                .with_context(|_| format!("error parsing {}", path.display()))?;
+            if state.source != source || state.dest != dest {
+                return Err(format_err!(
+                    "{} is a checkpoint for copying {} to {}, not {} to {}",
+                    path.display(),
+                    state.source,
+                    state.dest,
+                    source,
+                    dest,
+                ));
+            }
+            state
+        } else {
+            CheckpointState {
+                source: source.to_owned(),
+                dest: dest.to_owned(),
+                written_streams: BTreeSet::new(),
+            }
+        };
+        Ok(Checkpoint {
+            path: path.to_owned(),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Have any streams been written by an earlier attempt at this copy?
+    pub fn is_resuming(&self) -> bool {
+        !self.lock().written_streams.is_empty()
+    }
+
+    /// Has the stream `name` been completely written?
+    pub fn is_written(&self, name: &str) -> bool {
+        self.lock().written_streams.contains(name)
+    }
+
+    /// Record that the stream `name` has been completely written, and save our
+    /// checkpoint.
+    pub fn mark_written(&self, name: &str) -> Result<()> {
+        let mut state = self.lock();
+        state.written_streams.insert(name.to_owned());
+        self.save(&state)
+    }
+
+    /// Delete our checkpoint file, once the copy has succeeded.
+    pub fn remove(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path).with_context(|_| {
+                format!("could not delete {}", self.path.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Given a stream of CSV streams, skip any which have already been
+    /// written. Skipped streams are still read to the end, because some
+    /// sources produce their streams one after another.
+    ///
+    /// Because we track streams by name, every stream must have a different
+    /// name.
+    pub fn filter_streams(
+        self: Arc<Self>,
+        ctx: Context,
+        streams: BoxStream<CsvStream>,
+    ) -> BoxStream<CsvStream> {
+        let mut seen = HashSet::new();
+        streams
+            .try_filter_map(move |stream| {
+                let is_duplicate = !seen.insert(stream.name.clone());
+                let checkpoint = self.clone();
+                let ctx = ctx.clone();
+                async move {
+                    if is_duplicate {
+                        Err(format_err!(
+                            "cannot use --checkpoint because more than one stream is named {:?}",
+                            stream.name,
+                        ))
+                    } else if checkpoint.is_written(&stream.name) {
+                        debug!(
+                            ctx.log(),
+                            "skipping stream {} (already written)", stream.name,
+                        );
+                        stream.data.try_for_each(|_| async { Ok(()) }).await?;
+                        Ok(None)
+                    } else {
+                        Ok(Some(stream))
+                    }
+                }
+            })
+            .boxed()
+    }
+
+    /// Lock our state.
+    fn lock(&self) -> std::sync::MutexGuard<'_, CheckpointState> {
+        self.state.lock().expect("checkpoint lock poisoned")
+    }
+
+    /// Save `state`. We write a temporary file and rename it, so that our
+    /// checkpoint is never left half-written.
+    fn save(&self, state: &CheckpointState) -> Result<()> {
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        fs::write(&temp_path, serde_json::to_vec_pretty(state)?)
+            .with_context(|_| format!("could not write {}", temp_path.display()))?;
+        fs::rename(&temp_path, &self.path)
+            .with_context(|_| format!("could not write {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+#[test]
+fn saves_and_resumes_checkpoints() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.json");
+
+    let checkpoint = Checkpoint::load_or_new(&path, "csv:in/", "gs://b/out/").unwrap();
+    assert!(!checkpoint.is_resuming());
+    checkpoint.mark_written("part-1").unwrap();
+    assert!(checkpoint.is_written("part-1"));
+
+    let checkpoint = Checkpoint::load_or_new(&path, "csv:in/", "gs://b/out/").unwrap();
+    assert!(checkpoint.is_resuming());
+    assert!(checkpoint.is_written("part-1"));
+    assert!(!checkpoint.is_written("part-2"));
+
+    // A checkpoint can't be used for a different copy.
+    assert!(Checkpoint::load_or_new(&path, "csv:in/", "gs://b/other/").is_err());
+
+    checkpoint.remove().unwrap();
+    assert!(!path.exists());
+    assert!(!Checkpoint::load_or_new(&path, "csv:in/", "gs://b/other/")
+        .unwrap()
+        .is_resuming());
+}
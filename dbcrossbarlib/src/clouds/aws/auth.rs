@@ -1,9 +1,31 @@
 //! AWS authentication.
 
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fmt,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+use tokio::{process::Command, sync::Mutex};
+
 use crate::common::*;
 use crate::credentials::CredentialsManager;
 
+/// How long before our assumed role credentials expire should we refresh them?
+const ROLE_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+lazy_static! {
+    /// Credentials for roles that we've already assumed, indexed by role ARN
+    /// and external ID.
+    static ref ASSUMED_ROLES: Mutex<HashMap<AwsRole, (AwsCredentials, Option<Instant>)>> =
+        Mutex::new(HashMap::new());
+}
+
 /// Credentials used to access S3.
+#[derive(Clone)]
 pub(crate) struct AwsCredentials {
     /// The value of `AWS_ACCESS_KEY_ID`.
     pub(crate) access_key_id: String,
@@ -11,6 +33,19 @@ pub(crate) struct AwsCredentials {
     pub(crate) secret_access_key: String,
     /// The value of `AWS_SESSION_TOKEN`.
     pub(crate) session_token: Option<String>,
+    /// The value of `AWS_DEFAULT_REGION`, if we know it. If we don't, `aws`
+    /// will use its own configuration.
+    pub(crate) default_region: Option<String>,
+}
+
+impl fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't print our secrets.
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("default_region", &self.default_region)
+            .finish()
+    }
 }
 
 impl AwsCredentials {
@@ -20,10 +55,206 @@ impl AwsCredentials {
         let access_key_id = creds.get_required("access_key_id")?.to_owned();
         let secret_access_key = creds.get_required("secret_access_key")?.to_owned();
         let session_token = creds.get_optional("session_token").map(|t| t.to_owned());
+        let default_region =
+            creds.get_optional("default_region").map(|r| r.to_owned());
         Ok(AwsCredentials {
             access_key_id,
             secret_access_key,
             session_token,
+            default_region,
         })
     }
+
+    /// Look up our default credentials, and if `role` is specified, use them
+    /// to assume `role`.
+    pub(crate) async fn for_role(
+        ctx: &Context,
+        role: Option<&AwsRole>,
+    ) -> Result<AwsCredentials> {
+        let role = match role {
+            Some(role) => role,
+            None => return Self::try_default().await,
+        };
+
+        // Hold our lock while assuming the role, so that parallel streams don't
+        // all call `sts assume-role` at once.
+        let mut assumed = ASSUMED_ROLES.lock().await;
+        if let Some((creds, expires)) = assumed.get(role) {
+            let fresh = expires
+                .map(|expires| expires > Instant::now() + ROLE_REFRESH_MARGIN)
+                .unwrap_or(true);
+            if fresh {
+                return Ok(creds.to_owned());
+            }
+        }
+        let base = Self::try_default().await?;
+        let (creds, expires) = assume_role(ctx, &base, role).await?;
+        assumed.insert(role.to_owned(), (creds.clone(), expires));
+        Ok(creds)
+    }
+
+    /// Set the `AWS_*` environment variables for `command` to use these
+    /// credentials.
+    pub(crate) fn set_env(&self, command: &mut Command) {
+        command.env("AWS_ACCESS_KEY_ID", &self.access_key_id);
+        command.env("AWS_SECRET_ACCESS_KEY", &self.secret_access_key);
+        if let Some(session_token) = &self.session_token {
+            command.env("AWS_SESSION_TOKEN", session_token);
+        } else {
+            command.env_remove("AWS_SESSION_TOKEN");
+        }
+        if let Some(default_region) = &self.default_region {
+            command.env("AWS_DEFAULT_REGION", default_region);
+        }
+    }
+}
+
+/// An IAM role to assume before accessing AWS.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct AwsRole {
+    /// The ARN of the role, such as `arn:aws:iam::123456789012:role/example`.
+    pub(crate) arn: String,
+    /// The external ID required by the role's trust policy, if any.
+    pub(crate) external_id: Option<String>,
+}
+
+impl AwsRole {
+    /// Build a role from the `assume_role_arn` and `external_id` driver
+    /// arguments, if a role was specified.
+    pub(crate) fn from_args(
+        assume_role_arn: Option<&str>,
+        external_id: Option<&str>,
+    ) -> Result<Option<AwsRole>> {
+        match (assume_role_arn, external_id) {
+            (Some(arn), external_id) => Ok(Some(AwsRole {
+                arn: arn.to_owned(),
+                external_id: external_id.map(|id| id.to_owned()),
+            })),
+            (None, Some(_)) => Err(format_err!(
+                "cannot specify external_id without assume_role_arn"
+            )),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// Temporary credentials, as returned by `aws sts assume-role` and `aws
+/// configure export-credentials`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct TemporaryCredentials {
+    pub(crate) access_key_id: String,
+    pub(crate) secret_access_key: String,
+    #[serde(default)]
+    pub(crate) session_token: Option<String>,
+    #[serde(default)]
+    pub(crate) expiration: Option<String>,
+}
+
+impl TemporaryCredentials {
+    /// Convert our expiration time into an `Instant`.
+    pub(crate) fn expires(&self) -> Result<Option<Instant>> {
+        match &self.expiration {
+            None => Ok(None),
+            Some(expiration) => {
+                let expiration = DateTime::parse_from_rfc3339(expiration)
+                    .with_context(|_| {
+                        format!("could not parse AWS expiration {:?}", expiration)
+                    })?
+                    .with_timezone(&Utc);
+                let remaining = (expiration - Utc::now())
+                    .to_std()
+                    .unwrap_or_else(|_| Duration::from_secs(0));
+                Ok(Some(Instant::now() + remaining))
+            }
+        }
+    }
+}
+
+/// The output of `aws sts assume-role`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AssumeRoleOutput {
+    credentials: TemporaryCredentials,
+}
+
+/// Use `base` credentials to assume `role`.
+async fn assume_role(
+    ctx: &Context,
+    base: &AwsCredentials,
+    role: &AwsRole,
+) -> Result<(AwsCredentials, Option<Instant>)> {
+    debug!(ctx.log(), "assuming AWS role {}", role.arn);
+    let mut command = Command::new("aws");
+    base.set_env(&mut command);
+    command.args([
+        "sts",
+        "assume-role",
+        "--output",
+        "json",
+        "--role-arn",
+        &role.arn,
+        "--role-session-name",
+        "dbcrossbar",
+    ]);
+    if let Some(external_id) = &role.external_id {
+        command.args(["--external-id", external_id]);
+    }
+    let output = command
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .await
+        .context("error running `aws sts assume-role`")?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "could not assume AWS role {}: `aws sts assume-role` returned {}",
+            role.arn,
+            output.status,
+        ));
+    }
+    let parsed = serde_json::from_slice::<AssumeRoleOutput>(&output.stdout)
+        .context("could not parse `aws sts assume-role` output")?;
+    let expires = parsed.credentials.expires()?;
+    let creds = AwsCredentials {
+        access_key_id: parsed.credentials.access_key_id,
+        secret_access_key: parsed.credentials.secret_access_key,
+        session_token: parsed.credentials.session_token,
+        default_region: base.default_region.clone(),
+    };
+    Ok((creds, expires))
+}
+
+#[test]
+fn parses_assume_role_output() {
+    let json = r#"{
+    "Credentials": {
+        "AccessKeyId": "ASIAEXAMPLE",
+        "SecretAccessKey": "secret",
+        "SessionToken": "token",
+        "Expiration": "2099-01-01T00:00:00+00:00"
+    },
+    "AssumedRoleUser": {
+        "AssumedRoleId": "AROAEXAMPLE:dbcrossbar",
+        "Arn": "arn:aws:sts::123456789012:assumed-role/example/dbcrossbar"
+    }
+}"#;
+    let parsed = serde_json::from_str::<AssumeRoleOutput>(json).unwrap();
+    assert_eq!(parsed.credentials.access_key_id, "ASIAEXAMPLE");
+    assert_eq!(parsed.credentials.session_token.as_deref(), Some("token"));
+    assert!(parsed.credentials.expires().unwrap().unwrap() > Instant::now());
+}
+
+#[test]
+fn builds_roles_from_args() {
+    assert_eq!(AwsRole::from_args(None, None).unwrap(), None);
+    assert_eq!(
+        AwsRole::from_args(Some("arn:aws:iam::123456789012:role/r"), Some("x"))
+            .unwrap(),
+        Some(AwsRole {
+            arn: "arn:aws:iam::123456789012:role/r".to_owned(),
+            external_id: Some("x".to_owned()),
+        }),
+    );
+    assert!(AwsRole::from_args(None, Some("x")).is_err());
 }
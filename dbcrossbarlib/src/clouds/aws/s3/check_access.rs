@@ -0,0 +1,39 @@
+//! Checking whether we can access an S3 bucket.
+
+use std::process::Stdio;
+
+use super::aws_s3_command;
+use crate::clouds::aws::AwsCredentials;
+use crate::common::*;
+
+/// Make sure that we can list the bucket containing the `s3://` URL `url`.
+///
+/// We list the bucket instead of `url` itself, because `aws s3 ls` fails when
+/// asked to list a directory which doesn't exist yet.
+pub(crate) async fn check_access(
+    ctx: &Context,
+    creds: &AwsCredentials,
+    url: &Url,
+) -> Result<()> {
+    debug!(ctx.log(), "checking access to {}", url);
+    let bucket = url
+        .host_str()
+        .ok_or_else(|| format_err!("could not find bucket in {}", url))?;
+    let bucket_url = format!("s3://{}/", bucket);
+    let output = aws_s3_command(ctx, creds)
+        .args(["ls", &bucket_url])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .context("error running `aws s3 ls`")?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "could not list {}: {}",
+            bucket_url,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ))
+    }
+}
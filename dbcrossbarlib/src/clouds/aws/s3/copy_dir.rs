@@ -0,0 +1,56 @@
+//! Copying data within S3.
+
+use std::process::Stdio;
+
+use super::aws_s3_command;
+use crate::clouds::aws::AwsCredentials;
+use crate::clouds::object_metadata::ObjectMetadata;
+use crate::common::*;
+
+/// Recursively copy the CSV files in the `s3://` directory `src_url` to
+/// `dest_url`. S3 performs the copy server-side, and `aws s3` copies object
+/// metadata along with the data, unless we specify new `metadata`.
+pub(crate) async fn copy_dir(
+    ctx: &Context,
+    creds: &AwsCredentials,
+    src_url: &Url,
+    dest_url: &Url,
+    metadata: &ObjectMetadata,
+) -> Result<()> {
+    debug!(ctx.log(), "copying {} to {}", src_url, dest_url);
+    for url in &[src_url, dest_url] {
+        if !url.path().ends_with('/') {
+            return Err(format_err!(
+                "can only copy s3:// URLs ending in '/', got {}",
+                url,
+            ));
+        }
+    }
+    let mut metadata_args = metadata.aws_s3_args()?;
+    if !metadata.is_empty_except_storage_class() {
+        metadata_args.push("--metadata-directive".to_owned());
+        metadata_args.push("REPLACE".to_owned());
+    }
+    let status = aws_s3_command(ctx, creds)
+        .args([
+            "cp",
+            "--recursive",
+            "--exclude",
+            "*",
+            "--include",
+            "*.csv",
+            src_url.as_str(),
+            dest_url.as_str(),
+        ])
+        .args(metadata_args)
+        // Throw away stdout so it doesn't corrupt our output.
+        .stdout(Stdio::null())
+        .status()
+        .await
+        .context("error running `aws s3`")?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format_err!("`aws s3` returned error: {}", status))
+    }
+}
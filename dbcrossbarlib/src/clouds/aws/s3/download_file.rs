@@ -4,17 +4,18 @@ use std::process::Stdio;
 use tokio::io::BufReader;
 
 use super::aws_s3_command;
+use crate::clouds::aws::AwsCredentials;
 use crate::common::*;
 use crate::tokio_glue::copy_reader_to_stream;
 
 /// Download the file at the specified URL as a stream.
 pub(crate) async fn download_file(
     ctx: &Context,
+    creds: &AwsCredentials,
     file_url: &Url,
 ) -> Result<BoxStream<BytesMut>> {
     debug!(ctx.log(), "streaming from {} using `aws s3 cp`", file_url);
-    let mut child = aws_s3_command()
-        .await?
+    let mut child = aws_s3_command(ctx, creds)
         .args(&["cp", file_url.as_str(), "-"])
         .stdout(Stdio::piped())
         .spawn()
@@ -6,17 +6,40 @@ use std::process::Stdio;
 use tokio::io::BufReader;
 
 use super::aws_s3_command;
+use crate::clouds::aws::AwsCredentials;
 use crate::common::*;
 
+/// An object listed by `aws s3 ls`.
+#[derive(Clone, Debug)]
+pub(crate) struct S3Object {
+    /// The `s3://` URL of the object.
+    pub(crate) url: Url,
+    /// The size of the object, in bytes.
+    pub(crate) size: u64,
+}
+
 /// List all the files at the specified `s2://` URL, recursively.
 pub(crate) async fn ls(
     ctx: &Context,
+    creds: &AwsCredentials,
     url: &Url,
 ) -> Result<impl Stream<Item = Result<Url>> + Send + Unpin + 'static> {
+    Ok(ls_objects(ctx, creds, url)
+        .await?
+        .map_ok(|object| object.url)
+        .boxed())
+}
+
+/// List all the files at the specified `s3://` URL, recursively, including
+/// their sizes.
+pub(crate) async fn ls_objects(
+    ctx: &Context,
+    creds: &AwsCredentials,
+    url: &Url,
+) -> Result<BoxStream<S3Object>> {
     // Start a child process to list files at that URL.
     debug!(ctx.log(), "listing {}", url);
-    let mut child = aws_s3_command()
-        .await?
+    let mut child = aws_s3_command(ctx, creds)
         .args(&["ls", "--recursive", url.as_str()])
         .stdout(Stdio::piped())
         .spawn()
@@ -40,8 +63,11 @@ pub(crate) async fn ls(
             async move {
                 trace!(ctx.log(), "`aws s3 ls` line: {}", line);
                 let bucket_url = bucket_url(&url)?;
-                let path = path_from_line(&line)?;
-                Ok(bucket_url.join(&path)?)
+                let (size, path) = size_and_path_from_line(&line)?;
+                Ok(S3Object {
+                    url: bucket_url.join(&path)?,
+                    size,
+                })
             }
         });
 
@@ -75,29 +101,36 @@ fn bucket_url_extracts_bucket() {
     }
 }
 
-/// Given a line of `aws s3 ls` output, extract the path.
-fn path_from_line(line: &str) -> Result<String> {
+/// Given a line of `aws s3 ls` output, extract the size and the path.
+fn size_and_path_from_line(line: &str) -> Result<(u64, String)> {
     lazy_static! {
-        static ref RE: Regex = Regex::new(r#"^[-0-9]+ [:0-9]+ +[0-9]+ ([^\r\n]+)"#)
+        static ref RE: Regex = Regex::new(r#"^[-0-9]+ [:0-9]+ +([0-9]+) ([^\r\n]+)"#)
             .expect("invalid regex in source");
     }
     let cap = RE
         .captures(line)
         .ok_or_else(|| format_err!("cannot parse S3 ls output: {:?}", line))?;
-    Ok(cap[1].to_owned())
+    let size = cap[1]
+        .parse::<u64>()
+        .with_context(|_| format!("cannot parse S3 ls output: {:?}", line))?;
+    Ok((size, cap[2].to_owned()))
 }
 
 #[test]
-fn path_from_line_returns_entire_path() {
+fn size_and_path_from_line_returns_entire_path() {
     let examples = &[
-        ("2013-09-02 21:37:53         10 a.txt", "a.txt"),
-        ("2013-09-02 21:37:53    2863288 foo.zip", "foo.zip"),
+        ("2013-09-02 21:37:53         10 a.txt", 10, "a.txt"),
+        ("2013-09-02 21:37:53    2863288 foo.zip", 2863288, "foo.zip"),
         (
             "2013-09-02 21:32:57         23 foo/bar/.baz/a",
+            23,
             "foo/bar/.baz/a",
         ),
     ];
-    for &(line, rel_path) in examples {
-        assert_eq!(path_from_line(line).unwrap(), rel_path);
+    for &(line, size, rel_path) in examples {
+        assert_eq!(
+            size_and_path_from_line(line).unwrap(),
+            (size, rel_path.to_owned()),
+        );
     }
 }
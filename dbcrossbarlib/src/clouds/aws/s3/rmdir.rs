@@ -3,10 +3,15 @@
 use std::process::Stdio;
 
 use super::aws_s3_command;
+use crate::clouds::aws::AwsCredentials;
 use crate::common::*;
 
 /// Recursively delete a `s3://` directory without deleting the bucket.
-pub(crate) async fn rmdir(ctx: &Context, url: &Url) -> Result<()> {
+pub(crate) async fn rmdir(
+    ctx: &Context,
+    creds: &AwsCredentials,
+    url: &Url,
+) -> Result<()> {
     // Delete all the files under `url`.
     debug!(ctx.log(), "deleting existing {}", url);
     if !url.path().ends_with('/') {
@@ -15,8 +20,7 @@ pub(crate) async fn rmdir(ctx: &Context, url: &Url) -> Result<()> {
             url,
         ));
     }
-    let status = aws_s3_command()
-        .await?
+    let status = aws_s3_command(ctx, creds)
         .args(&["rm", "--recursive", url.as_str()])
         // Throw away stdout so it doesn't corrupt our output.
         .stdout(Stdio::null())
@@ -3,20 +3,24 @@
 use std::process::Stdio;
 
 use super::aws_s3_command;
+use crate::clouds::aws::AwsCredentials;
+use crate::clouds::object_metadata::ObjectMetadata;
 use crate::common::*;
 use crate::tokio_glue::copy_stream_to_writer;
 
-/// Upload `data` as a file at `url`.
+/// Upload `data` as a file at `url`, with the specified `metadata`.
 pub(crate) async fn upload_file<'a>(
     ctx: &'a Context,
+    creds: &'a AwsCredentials,
     data: BoxStream<BytesMut>,
     file_url: &'a Url,
+    metadata: &'a ObjectMetadata,
 ) -> Result<()> {
     // Run `aws cp - $URL` as a background process.
     debug!(ctx.log(), "uploading stream to `aws s3`");
-    let mut child = aws_s3_command()
-        .await?
+    let mut child = aws_s3_command(ctx, creds)
         .args(&["cp", "-", file_url.as_str()])
+        .args(metadata.aws_s3_args()?)
         .stdin(Stdio::piped())
         // Throw away stdout so it doesn't corrupt our output.
         .stdout(Stdio::null())
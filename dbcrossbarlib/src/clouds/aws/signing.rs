@@ -47,6 +47,7 @@ fn signatures_are_valid() {
         access_key_id: "44CF9590006BF252F707".to_owned(),
         secret_access_key: "OtxrzxIsfpFjA7SwPzILwy8Bw21TLhquhboDYROV".to_owned(),
         session_token: None,
+        default_region: None,
     };
     let (signed_url, _x_amz_security_token) = sign_s3_url(
         &creds,
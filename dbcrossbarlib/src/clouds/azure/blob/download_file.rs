@@ -0,0 +1,22 @@
+//! Downloading Azure blobs.
+
+use reqwest::{header::HeaderMap, Method};
+
+use super::{parse_azblob_url, Client};
+use crate::common::*;
+use crate::tokio_glue::http_response_stream;
+
+/// Download the blob at the specified `azblob://` URL as a stream.
+pub(crate) async fn download_file(
+    ctx: &Context,
+    client: &Client,
+    file_url: &Url,
+) -> Result<BoxStream<BytesMut>> {
+    debug!(ctx.log(), "streaming from {}", file_url);
+    let (container, blob) = parse_azblob_url(file_url)?;
+    let url = client.blob_url(&container, &blob)?;
+    let resp = client
+        .send(ctx, Method::GET, &url, HeaderMap::default(), None)
+        .await?;
+    Ok(http_response_stream(resp))
+}
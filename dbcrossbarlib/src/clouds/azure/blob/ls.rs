@@ -0,0 +1,106 @@
+//! Listing Azure blobs.
+
+use reqwest::{header::HeaderMap, Method};
+
+use super::{parse_azblob_url, xml_elements, xml_unescape, Client};
+use crate::common::*;
+
+/// List all the blobs at the specified `azblob://` URL, recursively, sorted
+/// by name.
+pub(crate) async fn ls(ctx: &Context, client: &Client, url: &Url) -> Result<Vec<Url>> {
+    debug!(ctx.log(), "listing {}", url);
+    let (container, prefix) = parse_azblob_url(url)?;
+    let container_url = client.blob_url(&container, "")?;
+
+    // Fetch one page of results at a time, until we run out of pages.
+    let mut names = vec![];
+    let mut marker = String::new();
+    loop {
+        let mut list_url = container_url.clone();
+        list_url
+            .query_pairs_mut()
+            .append_pair("restype", "container")
+            .append_pair("comp", "list")
+            .append_pair("prefix", &prefix);
+        if !marker.is_empty() {
+            list_url.query_pairs_mut().append_pair("marker", &marker);
+        }
+        let body = client
+            .send(ctx, Method::GET, &list_url, HeaderMap::default(), None)
+            .await?
+            .text()
+            .await
+            .with_context(|_| format!("error listing {}", url))?;
+        let page = parse_list_blobs(&body);
+        names.extend(page.names);
+        match page.next_marker {
+            Some(next_marker) => marker = next_marker,
+            None => break,
+        }
+    }
+
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| {
+            let mut blob_url = url.clone();
+            blob_url.set_path(&format!("/{}", name));
+            Ok(blob_url)
+        })
+        .collect()
+}
+
+/// One page of results from "List Blobs".
+#[derive(Debug, PartialEq)]
+struct ListBlobsPage {
+    /// The names of the blobs on this page.
+    names: Vec<String>,
+    /// The marker to use when asking for the next page, if there is one.
+    next_marker: Option<String>,
+}
+
+/// Parse the XML returned by "List Blobs". Blobs ending in `/` are directory
+/// placeholders created by some tools, and we skip them.
+fn parse_list_blobs(xml: &str) -> ListBlobsPage {
+    let names = xml_elements(xml, "Blob")
+        .filter_map(|blob| xml_elements(blob, "Name").next())
+        .map(xml_unescape)
+        .filter(|name| !name.ends_with('/'))
+        .collect();
+    let next_marker = xml_elements(xml, "NextMarker")
+        .next()
+        .filter(|marker| !marker.is_empty())
+        .map(xml_unescape);
+    ListBlobsPage { names, next_marker }
+}
+
+#[test]
+fn parse_list_blobs_returns_names_and_marker() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<EnumerationResults ServiceEndpoint="https://myaccount.blob.core.windows.net/" ContainerName="mycontainer">
+  <Prefix>dir/</Prefix>
+  <Blobs>
+    <Blob><Name>dir/</Name><Properties><Content-Length>0</Content-Length></Properties></Blob>
+    <Blob><Name>dir/a.csv</Name><Properties><Content-Length>10</Content-Length></Properties></Blob>
+    <Blob><Name>dir/b &amp; c.csv</Name><Properties><Content-Length>20</Content-Length></Properties></Blob>
+  </Blobs>
+  <NextMarker>2!80!MDAwMDE2</NextMarker>
+</EnumerationResults>"#;
+    assert_eq!(
+        parse_list_blobs(xml),
+        ListBlobsPage {
+            names: vec!["dir/a.csv".to_owned(), "dir/b & c.csv".to_owned()],
+            next_marker: Some("2!80!MDAwMDE2".to_owned()),
+        },
+    );
+
+    let last_page =
+        "<EnumerationResults><Blobs></Blobs><NextMarker /></EnumerationResults>";
+    assert_eq!(
+        parse_list_blobs(last_page),
+        ListBlobsPage {
+            names: vec![],
+            next_marker: None,
+        },
+    );
+}
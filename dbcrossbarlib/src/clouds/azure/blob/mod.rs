@@ -0,0 +1,247 @@
+//! Interfaces to Azure Blob Storage, using the REST API.
+
+use bigml::wait::{wait, BackoffType, WaitOptions, WaitStatus};
+use bytes::Bytes;
+use chrono::Utc;
+use percent_encoding::{
+    percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC,
+};
+use reqwest::{
+    header::{HeaderMap, CONTENT_LENGTH},
+    Method,
+};
+use std::time::Duration;
+
+use super::{AzureStorageCredentials, AZURE_STORAGE_VERSION};
+use crate::clouds::http_client::reqwest_client;
+use crate::common::*;
+
+mod download_file;
+mod ls;
+mod rmdir;
+mod upload_file;
+
+pub(crate) use download_file::download_file;
+pub(crate) use ls::ls;
+pub(crate) use rmdir::rmdir;
+pub(crate) use upload_file::upload_file;
+
+/// Characters which must be escaped in blob names. We leave `/` alone, because
+/// Azure treats it as part of the name.
+const BLOB_NAME: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+/// Split an `azblob://` URL into a container and a blob name (or prefix).
+pub(crate) fn parse_azblob_url(url: &Url) -> Result<(String, String)> {
+    if url.scheme() != "azblob" {
+        return Err(format_err!("expected an azblob:// URL, found {}", url));
+    }
+    let container = url
+        .host_str()
+        .ok_or_else(|| format_err!("could not get container from {}", url))?
+        .to_owned();
+    let blob = percent_decode_str(&url.path()[1..])
+        .decode_utf8()
+        .with_context(|_| format!("could not decode blob name in {}", url))?
+        .into_owned();
+    Ok((container, blob))
+}
+
+/// An Azure Blob Storage REST client.
+#[derive(Clone)]
+pub(crate) struct Client {
+    /// Our credentials.
+    creds: AzureStorageCredentials,
+
+    /// Our HTTP client.
+    client: reqwest::Client,
+
+    /// The URL of our storage account, ending in `/`.
+    base_url: Url,
+}
+
+impl Client {
+    /// Create a new Azure Blob Storage client, using any
+    /// `azure_blob_endpoint` configured in `ctx`.
+    pub(crate) async fn new(ctx: &Context) -> Result<Client> {
+        let creds = AzureStorageCredentials::try_default().await?;
+        let base_url = match &ctx.endpoints().azure_blob_endpoint {
+            // Emulators use path-style URLs.
+            Some(endpoint) => {
+                endpoint.as_url().join(&format!("{}/", creds.account))?
+            }
+            None => format!("https://{}.blob.core.windows.net/", creds.account)
+                .parse::<Url>()
+                .context("could not build Azure Blob Storage URL")?,
+        };
+        let client = reqwest_client()?;
+        Ok(Client {
+            creds,
+            client,
+            base_url,
+        })
+    }
+
+    /// The REST API URL for `blob` in `container`. If `blob` is empty, this
+    /// is the URL of the container itself.
+    pub(crate) fn blob_url(&self, container: &str, blob: &str) -> Result<Url> {
+        let mut path = utf8_percent_encode(container, BLOB_NAME).to_string();
+        if !blob.is_empty() {
+            path.push('/');
+            path.push_str(&utf8_percent_encode(blob, BLOB_NAME).to_string());
+        }
+        Ok(self.base_url.join(&path)?)
+    }
+
+    /// Send a request, retrying temporary failures, and return the response
+    /// if it succeeded.
+    pub(crate) async fn send(
+        &self,
+        ctx: &Context,
+        method: Method,
+        url: &Url,
+        headers: HeaderMap,
+        body: Option<Bytes>,
+    ) -> Result<reqwest::Response> {
+        trace!(ctx.log(), "{} {}", method, url);
+        let wait_options = WaitOptions::default()
+            .backoff_type(BackoffType::Exponential)
+            .retry_interval(Duration::from_secs(10))
+            // Don't retry too much because we're probably classifying some
+            // permanent errors as temporary.
+            .allowed_errors(3);
+        let resp = wait(&wait_options, || {
+            let method = method.clone();
+            let headers = headers.clone();
+            let body = body.clone();
+            async move {
+                // We need to build and authorize a fresh request each time,
+                // because the signature includes the current time.
+                let req = match self.build_request(method.clone(), url, headers, body)
+                {
+                    Ok(req) => req,
+                    Err(err) => return WaitStatus::FailedPermanently(err),
+                };
+                match self.client.execute(req).await {
+                    // The HTTP request failed outright.
+                    Err(err) => {
+                        let temporary = err.is_request() || err.is_timeout();
+                        let err: Error = err.into();
+                        let err: Error = err
+                            .context(format!("could not {} {}", method, url))
+                            .into();
+                        if temporary {
+                            ctx.retrying(&err);
+                            WaitStatus::FailedTemporarily(err)
+                        } else {
+                            WaitStatus::FailedPermanently(err)
+                        }
+                    }
+                    Ok(resp) if resp.status().is_server_error() => {
+                        let err = response_error(&method, url, resp).await;
+                        ctx.retrying(&err);
+                        WaitStatus::FailedTemporarily(err)
+                    }
+                    Ok(resp) if !resp.status().is_success() => {
+                        WaitStatus::FailedPermanently(
+                            response_error(&method, url, resp).await,
+                        )
+                    }
+                    Ok(resp) => WaitStatus::Finished(resp),
+                }
+            }
+        })
+        .await?;
+        Ok(resp)
+    }
+
+    /// Build an authorized request. Any SAS token is only added to the URL
+    /// inside the request, so `url` is still safe to include in errors.
+    fn build_request(
+        &self,
+        method: Method,
+        url: &Url,
+        headers: HeaderMap,
+        body: Option<Bytes>,
+    ) -> Result<reqwest::Request> {
+        let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let content_length = body.as_ref().map(|body| body.len()).unwrap_or(0);
+        let mut builder = self
+            .client
+            .request(method, url.clone())
+            .headers(headers)
+            .header("x-ms-date", date)
+            .header("x-ms-version", AZURE_STORAGE_VERSION)
+            .header(CONTENT_LENGTH, content_length);
+        if let Some(body) = body {
+            builder = builder.body(body);
+        }
+        let mut req = builder
+            .build()
+            .with_context(|_| format!("could not build request for {}", url))?;
+        self.creds.authorize(&mut req)?;
+        Ok(req)
+    }
+}
+
+/// Turn an HTTP error response into an `Error`, including the `<Message>`
+/// from Azure's XML error body if there is one.
+async fn response_error(method: &Method, url: &Url, resp: reqwest::Response) -> Error {
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_default();
+    let message = xml_elements(&body, "Message").next().map(xml_unescape);
+    match message {
+        Some(message) => {
+            format_err!("{} {} failed: {}: {}", method, url, status, message)
+        }
+        None => format_err!("{} {} failed: {}", method, url, status),
+    }
+}
+
+/// Iterate over the raw text of each `<name>` element in `xml`. Azure's
+/// responses are simple enough that we don't need a real XML parser.
+fn xml_elements<'a>(xml: &'a str, name: &str) -> impl Iterator<Item = &'a str> + 'a {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let len = rest[start..].find(&close)?;
+        let text = &rest[start..start + len];
+        rest = &rest[start + len + close.len()..];
+        Some(text)
+    })
+}
+
+/// Replace the standard XML entities in `text`.
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[test]
+fn parse_azblob_url_splits_container_and_blob() {
+    let url = "azblob://container/dir/my%20file.csv"
+        .parse::<Url>()
+        .unwrap();
+    let (container, blob) = parse_azblob_url(&url).unwrap();
+    assert_eq!(container, "container");
+    assert_eq!(blob, "dir/my file.csv");
+    assert!(parse_azblob_url(&"s3://bucket/dir/".parse::<Url>().unwrap()).is_err());
+}
+
+#[test]
+fn xml_elements_finds_text_to_unescape() {
+    let xml = "<Error><Code>AuthenticationFailed</Code><Message>a &amp; b &lt;c&gt;</Message></Error>";
+    let messages = xml_elements(xml, "Message").collect::<Vec<_>>();
+    assert_eq!(messages, vec!["a &amp; b &lt;c&gt;"]);
+    assert_eq!(xml_unescape(messages[0]), "a & b <c>");
+    assert_eq!(xml_elements(xml, "Missing").count(), 0);
+}
@@ -0,0 +1,46 @@
+//! Deleting Azure blobs.
+
+use reqwest::{header::HeaderMap, Method};
+
+use super::{ls, parse_azblob_url, Client};
+use crate::common::*;
+use crate::tokio_glue::ConsumeWithParallelism;
+
+/// How many blobs should we try to delete at a time?
+const PARALLEL_DELETIONS: usize = 10;
+
+/// Recursively delete an `azblob://` directory without deleting the container.
+pub(crate) async fn rmdir(ctx: &Context, client: &Client, url: &Url) -> Result<()> {
+    debug!(ctx.log(), "deleting existing {}", url);
+    if !url.path().ends_with('/') {
+        return Err(format_err!(
+            "can only delete azblob:// URL ending in '/', got {}",
+            url,
+        ));
+    }
+
+    let blob_urls = ls(ctx, client, url).await?;
+    let ctx = ctx.clone();
+    let client = client.clone();
+    let del_fut_stream: BoxStream<BoxFuture<()>> = stream::iter(blob_urls)
+        .map(move |blob_url| {
+            let ctx = ctx.clone();
+            let client = client.clone();
+            async move {
+                trace!(ctx.log(), "deleting {}", blob_url);
+                let (container, blob) = parse_azblob_url(&blob_url)?;
+                let url = client.blob_url(&container, &blob)?;
+                client
+                    .send(&ctx, Method::DELETE, &url, HeaderMap::default(), None)
+                    .await?;
+                Ok(())
+            }
+            .boxed()
+        })
+        .map(Ok)
+        .boxed();
+    del_fut_stream
+        .consume_with_parallelism(PARALLEL_DELETIONS)
+        .await?;
+    Ok(())
+}
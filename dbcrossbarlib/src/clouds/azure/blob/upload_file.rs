@@ -0,0 +1,102 @@
+//! Uploading Azure blobs.
+
+use reqwest::{header::HeaderMap, Method};
+
+use super::{parse_azblob_url, Client};
+use crate::common::*;
+
+/// How much data should we upload in each block?
+///
+/// Azure allows at most 50,000 blocks per blob, so this limits us to blobs of
+/// about 195 GiB. We hold one block in memory per stream.
+#[cfg(not(debug_assertions))]
+const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+// Use a much smaller block size when testing to force our block code to be
+// used.
+#[cfg(debug_assertions)]
+const BLOCK_SIZE: usize = 128;
+
+/// Upload `data` as a blob at the specified `azblob://` URL, replacing any
+/// existing blob.
+///
+/// We upload our data using "Put Block", and then commit the blocks using "Put
+/// Block List", so that we never need to know the total size in advance.
+pub(crate) async fn upload_file(
+    ctx: &Context,
+    client: &Client,
+    mut data: BoxStream<BytesMut>,
+    file_url: &Url,
+) -> Result<()> {
+    debug!(ctx.log(), "streaming to {}", file_url);
+    let (container, blob) = parse_azblob_url(file_url)?;
+    let url = client.blob_url(&container, &blob)?;
+
+    // Upload our blocks.
+    let mut block_ids = vec![];
+    let mut buffer = BytesMut::with_capacity(BLOCK_SIZE);
+    let mut done = false;
+    while !done {
+        match data.next().await {
+            Some(chunk) => buffer.extend_from_slice(&chunk?),
+            None => done = true,
+        }
+        while buffer.len() >= BLOCK_SIZE || (done && !buffer.is_empty()) {
+            let len = buffer.len().min(BLOCK_SIZE);
+            let block = buffer.split_to(len).freeze();
+            let block_id = base64::encode(format!("{:06}", block_ids.len()));
+            let mut block_url = url.clone();
+            block_url
+                .query_pairs_mut()
+                .append_pair("comp", "block")
+                .append_pair("blockid", &block_id);
+            trace!(ctx.log(), "uploading block {} of {}", block_id, file_url);
+            client
+                .send(
+                    ctx,
+                    Method::PUT,
+                    &block_url,
+                    HeaderMap::default(),
+                    Some(block),
+                )
+                .await?;
+            block_ids.push(block_id);
+        }
+    }
+
+    // Commit our blocks. This replaces any existing blob.
+    let mut block_list_url = url.clone();
+    block_list_url
+        .query_pairs_mut()
+        .append_pair("comp", "blocklist");
+    client
+        .send(
+            ctx,
+            Method::PUT,
+            &block_list_url,
+            HeaderMap::default(),
+            Some(block_list_xml(&block_ids).into()),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Build the body of a "Put Block List" request. Our block IDs are base64,
+/// so they never need to be escaped.
+fn block_list_xml(block_ids: &[String]) -> String {
+    let mut xml = r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#.to_owned();
+    for block_id in block_ids {
+        xml.push_str(&format!("<Latest>{}</Latest>", block_id));
+    }
+    xml.push_str("</BlockList>");
+    xml
+}
+
+#[test]
+fn block_list_xml_lists_blocks_in_order() {
+    let ids = vec![base64::encode("000000"), base64::encode("000001")];
+    assert_eq!(
+        block_list_xml(&ids),
+        r#"<?xml version="1.0" encoding="utf-8"?><BlockList><Latest>MDAwMDAw</Latest><Latest>MDAwMDAx</Latest></BlockList>"#,
+    );
+}
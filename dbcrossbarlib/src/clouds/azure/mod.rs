@@ -0,0 +1,6 @@
+//! Interfaces to Microsoft Azure.
+
+mod auth;
+pub(crate) mod blob;
+
+pub(crate) use auth::*;
@@ -0,0 +1,224 @@
+//! Sending API requests to regional or private endpoints.
+//!
+//! Some networks block the public Google Cloud and AWS endpoints, and only
+//! allow access through regional endpoints, Private Service Connect, or VPC
+//! endpoints. Azure Storage emulators like Azurite also need an endpoint
+//! override. We let the user override the endpoint for each service using
+//! driver arguments, and we store those overrides in our `Context`.
+
+use serde::Deserialize;
+use std::{fmt, str::FromStr};
+
+use crate::common::*;
+use crate::driver_args::deserialize_opt_from_str;
+
+/// The public host used by the BigQuery API.
+const BIGQUERY_HOST: &str = "bigquery.googleapis.com";
+
+/// The public host used by the Google Cloud Storage API.
+const STORAGE_HOST: &str = "storage.googleapis.com";
+
+/// An `http://` or `https://` URL with no path, which should be used instead
+/// of a service's public endpoint.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct ApiEndpoint(Url);
+
+impl ApiEndpoint {
+    /// The URL of this endpoint.
+    pub(crate) fn as_url(&self) -> &Url {
+        &self.0
+    }
+
+    /// Replace the scheme, host and port of `url` with ours.
+    fn apply(&self, url: &mut Url) -> Result<()> {
+        url.set_scheme(self.0.scheme())
+            .map_err(|()| format_err!("could not set scheme of {}", url))?;
+        url.set_host(self.0.host_str())
+            .with_context(|_| format!("could not set host of {}", url))?;
+        url.set_port(self.0.port())
+            .map_err(|()| format_err!("could not set port of {}", url))?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for ApiEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for ApiEndpoint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let url = s
+            .parse::<Url>()
+            .with_context(|_| format!("could not parse endpoint {:?}", s))?;
+        if url.scheme() != "https" && url.scheme() != "http" {
+            return Err(format_err!("endpoint {} must use https://", url));
+        }
+        if url.host_str().is_none() {
+            return Err(format_err!("endpoint {} must include a host", url));
+        }
+        if url.path() != "/" || url.query().is_some() {
+            return Err(format_err!(
+                "endpoint {} must not include a path or query",
+                url,
+            ));
+        }
+        Ok(ApiEndpoint(url))
+    }
+}
+
+/// Endpoint overrides for the services we talk to.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(crate) struct ApiEndpoints {
+    /// Send BigQuery API requests here.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) bigquery_endpoint: Option<ApiEndpoint>,
+
+    /// Send Google Cloud Storage API requests here.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) storage_endpoint: Option<ApiEndpoint>,
+
+    /// Pass this to `aws s3 --endpoint-url`.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) s3_endpoint: Option<ApiEndpoint>,
+
+    /// Send Azure Blob Storage API requests here, using path-style URLs that
+    /// include the account name.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) azure_blob_endpoint: Option<ApiEndpoint>,
+}
+
+impl ApiEndpoints {
+    /// Extract any endpoint overrides from `args`, ignoring all other
+    /// arguments. This is used before we know which driver will see `args`.
+    pub(crate) fn from_args(args: &DriverArguments) -> Result<ApiEndpoints> {
+        Ok(args
+            .deserialize::<ApiEndpoints>()
+            .context("error parsing endpoint arguments")?)
+    }
+
+    /// Combine these endpoints with `other`, preferring those in `other`.
+    pub(crate) fn merged_with(&self, other: &ApiEndpoints) -> ApiEndpoints {
+        ApiEndpoints {
+            bigquery_endpoint: other
+                .bigquery_endpoint
+                .clone()
+                .or_else(|| self.bigquery_endpoint.clone()),
+            storage_endpoint: other
+                .storage_endpoint
+                .clone()
+                .or_else(|| self.storage_endpoint.clone()),
+            s3_endpoint: other
+                .s3_endpoint
+                .clone()
+                .or_else(|| self.s3_endpoint.clone()),
+            azure_blob_endpoint: other
+                .azure_blob_endpoint
+                .clone()
+                .or_else(|| self.azure_blob_endpoint.clone()),
+        }
+    }
+
+    /// Combine the endpoints for the source and destination of a server-side
+    /// copy, which can only talk to one endpoint per service.
+    pub(crate) fn for_copy(
+        source: &ApiEndpoints,
+        dest: &ApiEndpoints,
+    ) -> Result<ApiEndpoints> {
+        fn pick(
+            name: &str,
+            source: &Option<ApiEndpoint>,
+            dest: &Option<ApiEndpoint>,
+        ) -> Result<Option<ApiEndpoint>> {
+            match (source, dest) {
+                (Some(source), Some(dest)) if source != dest => Err(format_err!(
+                    "cannot copy between different {} values {} and {}",
+                    name,
+                    source,
+                    dest,
+                )),
+                (source, dest) => Ok(dest.clone().or_else(|| source.clone())),
+            }
+        }
+        Ok(ApiEndpoints {
+            bigquery_endpoint: pick(
+                "bigquery_endpoint",
+                &source.bigquery_endpoint,
+                &dest.bigquery_endpoint,
+            )?,
+            storage_endpoint: pick(
+                "storage_endpoint",
+                &source.storage_endpoint,
+                &dest.storage_endpoint,
+            )?,
+            s3_endpoint: pick("s3_endpoint", &source.s3_endpoint, &dest.s3_endpoint)?,
+            azure_blob_endpoint: pick(
+                "azure_blob_endpoint",
+                &source.azure_blob_endpoint,
+                &dest.azure_blob_endpoint,
+            )?,
+        })
+    }
+
+    /// Rewrite a Google Cloud API URL to use any overridden endpoint.
+    pub(crate) fn rewrite_gcloud_url(&self, url: &mut Url) -> Result<()> {
+        let endpoint = match url.host_str() {
+            Some(BIGQUERY_HOST) => self.bigquery_endpoint.as_ref(),
+            Some(STORAGE_HOST) => self.storage_endpoint.as_ref(),
+            _ => None,
+        };
+        if let Some(endpoint) = endpoint {
+            endpoint.apply(url)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn rewrites_gcloud_urls() {
+    let args = DriverArguments::from_cli_args(&[
+        "storage_endpoint=https://storage-example.p.googleapis.com",
+        "bigquery_endpoint=http://localhost:9050",
+        "unrelated=1",
+    ])
+    .unwrap();
+    let endpoints = ApiEndpoints::from_args(&args).unwrap();
+
+    let mut url = "https://storage.googleapis.com/storage/v1/b/bucket/o?x=1"
+        .parse::<Url>()
+        .unwrap();
+    endpoints.rewrite_gcloud_url(&mut url).unwrap();
+    assert_eq!(
+        url.as_str(),
+        "https://storage-example.p.googleapis.com/storage/v1/b/bucket/o?x=1",
+    );
+
+    let mut url = "https://bigquery.googleapis.com/bigquery/v2/projects/p/jobs"
+        .parse::<Url>()
+        .unwrap();
+    endpoints.rewrite_gcloud_url(&mut url).unwrap();
+    assert_eq!(
+        url.as_str(),
+        "http://localhost:9050/bigquery/v2/projects/p/jobs"
+    );
+
+    let mut url = "https://oauth2.googleapis.com/token"
+        .parse::<Url>()
+        .unwrap();
+    endpoints.rewrite_gcloud_url(&mut url).unwrap();
+    assert_eq!(url.as_str(), "https://oauth2.googleapis.com/token");
+
+    let other = ApiEndpoints::from_args(
+        &DriverArguments::from_cli_args(&["storage_endpoint=https://example.com"])
+            .unwrap(),
+    )
+    .unwrap();
+    assert!(ApiEndpoints::for_copy(&endpoints, &other).is_err());
+    assert!(ApiEndpoints::for_copy(&endpoints, &ApiEndpoints::default()).is_ok());
+
+    assert!("https://example.com/path".parse::<ApiEndpoint>().is_err());
+    assert!("ftp://example.com".parse::<ApiEndpoint>().is_err());
+}
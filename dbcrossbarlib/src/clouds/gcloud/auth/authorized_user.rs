@@ -0,0 +1,43 @@
+//! Refresh tokens created by `gcloud auth application-default login`.
+
+use serde::Deserialize;
+
+use super::{AccessToken, TokenResponse};
+use crate::common::*;
+
+/// The endpoint used to refresh user tokens.
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
+/// An `authorized_user` credentials file.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct AuthorizedUserKey {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+}
+
+/// Exchange our refresh token for an access token.
+pub(super) async fn fetch_token(
+    client: &reqwest::Client,
+    key: &AuthorizedUserKey,
+) -> Result<AccessToken> {
+    let resp = client
+        .post(TOKEN_URL)
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", &key.client_id),
+            ("client_secret", &key.client_secret),
+            ("refresh_token", &key.refresh_token),
+        ])
+        .send()
+        .await
+        .context("could not refresh Google Cloud user credentials")?
+        .error_for_status()
+        .context(
+            "could not refresh Google Cloud user credentials (try `gcloud auth application-default login`)",
+        )?
+        .json::<TokenResponse>()
+        .await
+        .context("could not parse refreshed token")?;
+    Ok(resp.into())
+}
@@ -0,0 +1,198 @@
+//! Workload identity federation using `external_account` credentials.
+//!
+//! These credentials files don't contain any secrets. Instead, they tell us
+//! where to find a token issued by another identity provider (such as a CI
+//! system or another cloud), which we exchange for a Google access token using
+//! the Security Token Service.
+//!
+//! Docs: https://cloud.google.com/iam/docs/workload-identity-federation
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, path::PathBuf};
+use tokio::fs;
+
+use super::{AccessToken, TokenResponse};
+use crate::common::*;
+
+/// An `external_account` credentials file.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct ExternalAccountKey {
+    audience: String,
+    subject_token_type: String,
+    token_url: String,
+    service_account_impersonation_url: Option<String>,
+    credential_source: CredentialSource,
+}
+
+/// Where to find the subject token issued by the other identity provider.
+#[derive(Clone, Debug, Deserialize)]
+struct CredentialSource {
+    /// Read the token from this file.
+    file: Option<PathBuf>,
+    /// Fetch the token from this URL.
+    url: Option<String>,
+    /// Headers to send when fetching `url`.
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    /// How the token is formatted.
+    #[serde(default)]
+    format: Option<SourceFormat>,
+}
+
+/// The format of a subject token.
+#[derive(Clone, Debug, Deserialize)]
+struct SourceFormat {
+    /// Either `text` or `json`.
+    #[serde(rename = "type")]
+    format_type: String,
+    /// For `json`, the field containing the token.
+    subject_token_field_name: Option<String>,
+}
+
+/// Request body for our service account impersonation request.
+#[derive(Debug, Serialize)]
+struct ImpersonationRequest<'a> {
+    scope: &'a [&'a str],
+}
+
+/// Response from our service account impersonation request.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImpersonationResponse {
+    access_token: String,
+    expire_time: DateTime<Utc>,
+}
+
+/// Exchange our external credentials for a Google access token.
+pub(super) async fn fetch_token(
+    client: &reqwest::Client,
+    key: &ExternalAccountKey,
+    scopes: &[&str],
+) -> Result<AccessToken> {
+    let subject_token = subject_token(client, &key.credential_source).await?;
+
+    // When impersonating a service account, the federated token only needs
+    // permission to call the IAM credentials API.
+    let sts_scopes = if key.service_account_impersonation_url.is_some() {
+        "https://www.googleapis.com/auth/cloud-platform".to_owned()
+    } else {
+        scopes.join(" ")
+    };
+    let sts_token: AccessToken = client
+        .post(&key.token_url)
+        .form(&[
+            (
+                "grant_type",
+                "urn:ietf:params:oauth:grant-type:token-exchange",
+            ),
+            ("audience", &key.audience),
+            ("scope", &sts_scopes),
+            (
+                "requested_token_type",
+                "urn:ietf:params:oauth:token-type:access_token",
+            ),
+            ("subject_token", &subject_token),
+            ("subject_token_type", &key.subject_token_type),
+        ])
+        .send()
+        .await
+        .context("could not contact Security Token Service")?
+        .error_for_status()
+        .context("Security Token Service rejected our external credentials")?
+        .json::<TokenResponse>()
+        .await
+        .context("could not parse Security Token Service response")?
+        .into();
+
+    match &key.service_account_impersonation_url {
+        None => Ok(sts_token),
+        Some(url) => {
+            let resp = client
+                .post(url)
+                .bearer_auth(sts_token.as_str())
+                .json(&ImpersonationRequest { scope: scopes })
+                .send()
+                .await
+                .context("could not impersonate service account")?
+                .error_for_status()
+                .context("could not impersonate service account")?
+                .json::<ImpersonationResponse>()
+                .await
+                .context("could not parse service account token")?;
+            Ok(AccessToken {
+                value: resp.access_token,
+                expires_at: Some(resp.expire_time),
+            })
+        }
+    }
+}
+
+/// Look up the token issued by the other identity provider.
+async fn subject_token(
+    client: &reqwest::Client,
+    source: &CredentialSource,
+) -> Result<String> {
+    let raw = match (&source.file, &source.url) {
+        (Some(path), None) => fs::read_to_string(path)
+            .await
+            .with_context(|_| format!("could not read {}", path.display()))?,
+        (None, Some(url)) => {
+            let mut req = client.get(url);
+            for (name, value) in &source.headers {
+                req = req.header(name.as_str(), value.as_str());
+            }
+            req.send()
+                .await
+                .with_context(|_| format!("could not fetch {}", url))?
+                .error_for_status()
+                .with_context(|_| format!("could not fetch {}", url))?
+                .text()
+                .await?
+        }
+        _ => {
+            return Err(format_err!(
+                "external_account credential_source must contain exactly one of `file` or `url`"
+            ))
+        }
+    };
+    parse_subject_token(&raw, source.format.as_ref())
+}
+
+/// Extract a subject token from `raw` using `format`.
+fn parse_subject_token(raw: &str, format: Option<&SourceFormat>) -> Result<String> {
+    match format {
+        Some(format) if format.format_type == "json" => {
+            let field = format.subject_token_field_name.as_ref().ok_or_else(|| {
+                format_err!("credential_source.format needs subject_token_field_name")
+            })?;
+            let value = serde_json::from_str::<Value>(raw)
+                .context("could not parse subject token as JSON")?;
+            value
+                .get(field)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_owned())
+                .ok_or_else(|| format_err!("no {:?} field in subject token", field))
+        }
+        Some(format) if format.format_type != "text" => Err(format_err!(
+            "unsupported credential_source format {:?}",
+            format.format_type,
+        )),
+        _ => Ok(raw.trim().to_owned()),
+    }
+}
+
+#[test]
+fn parses_subject_tokens() {
+    assert_eq!(parse_subject_token("abc\n", None).unwrap(), "abc");
+    let json_format = SourceFormat {
+        format_type: "json".to_owned(),
+        subject_token_field_name: Some("id_token".to_owned()),
+    };
+    assert_eq!(
+        parse_subject_token(r#"{"id_token":"xyz"}"#, Some(&json_format)).unwrap(),
+        "xyz",
+    );
+    assert!(parse_subject_token(r#"{"other":"xyz"}"#, Some(&json_format)).is_err());
+}
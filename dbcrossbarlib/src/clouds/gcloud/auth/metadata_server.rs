@@ -0,0 +1,65 @@
+//! Access tokens from the GCE and GKE metadata server.
+//!
+//! Compute Engine VMs, Cloud Run services and GKE pods using workload identity
+//! can fetch tokens for their attached service account from a local HTTP
+//! server, without any key files.
+
+use std::{env, time::Duration};
+
+use super::{AccessToken, TokenResponse};
+use crate::common::*;
+
+/// The default hostname of the metadata server.
+const DEFAULT_METADATA_HOST: &str = "metadata.google.internal";
+
+/// How long should we wait to see if a metadata server is available?
+const DETECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The metadata server host to use. This can be overridden using
+/// `GCE_METADATA_HOST`, which is also supported by Google's own tools.
+fn metadata_host() -> String {
+    env::var("GCE_METADATA_HOST").unwrap_or_else(|_| DEFAULT_METADATA_HOST.to_owned())
+}
+
+/// Is there a metadata server that we can talk to?
+pub(super) async fn is_available(client: &reqwest::Client) -> bool {
+    let url = format!("http://{}/computeMetadata/v1/", metadata_host());
+    let resp = client
+        .get(&url)
+        .header("Metadata-Flavor", "Google")
+        .timeout(DETECT_TIMEOUT)
+        .send()
+        .await;
+    match resp {
+        Ok(resp) => resp
+            .headers()
+            .get("Metadata-Flavor")
+            .map(|v| v == "Google")
+            .unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+/// Fetch an access token for the default service account.
+pub(super) async fn fetch_token(
+    client: &reqwest::Client,
+    scopes: &[&str],
+) -> Result<AccessToken> {
+    let url = format!(
+        "http://{}/computeMetadata/v1/instance/service-accounts/default/token",
+        metadata_host(),
+    );
+    let resp = client
+        .get(&url)
+        .header("Metadata-Flavor", "Google")
+        .query(&[("scopes", scopes.join(","))])
+        .send()
+        .await
+        .context("could not contact metadata server")?
+        .error_for_status()
+        .context("metadata server could not provide a token")?
+        .json::<TokenResponse>()
+        .await
+        .context("could not parse metadata server token")?;
+    Ok(resp.into())
+}
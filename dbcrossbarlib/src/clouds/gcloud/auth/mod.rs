@@ -0,0 +1,495 @@
+//! Authentication support for Google Cloud.
+
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fmt::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tokio::fs;
+use yup_oauth2::{
+    ApplicationSecret, ConsoleApplicationSecret, InstalledFlowReturnMethod,
+    ServiceAccountKey,
+};
+
+use super::SCOPES;
+use crate::clouds::http_client::{hyper_client, reqwest_client, HyperConnector};
+use crate::common::*;
+use crate::config::config_dir;
+use crate::credentials::CredentialsManager;
+
+mod authorized_user;
+mod external_account;
+mod metadata_server;
+
+use authorized_user::AuthorizedUserKey;
+use external_account::ExternalAccountKey;
+
+/// A `yup_oauth2` authenticator.
+type OAuth2Authenticator = yup_oauth2::authenticator::Authenticator<HyperConnector>;
+
+/// An OAuth2 access token.
+#[derive(Clone, Debug)]
+pub(crate) struct AccessToken {
+    value: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl AccessToken {
+    /// The token itself.
+    pub(crate) fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    /// Will this token expire in the next minute?
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|expires_at| expires_at - Duration::minutes(1) <= Utc::now())
+            .unwrap_or(false)
+    }
+}
+
+impl From<yup_oauth2::AccessToken> for AccessToken {
+    fn from(token: yup_oauth2::AccessToken) -> Self {
+        AccessToken {
+            value: token.as_str().to_owned(),
+            expires_at: token.expiration_time(),
+        }
+    }
+}
+
+/// A token returned by an OAuth2 token endpoint or the metadata server.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+impl From<TokenResponse> for AccessToken {
+    fn from(resp: TokenResponse) -> Self {
+        AccessToken {
+            value: resp.access_token,
+            expires_at: resp
+                .expires_in
+                .map(|secs| Utc::now() + Duration::seconds(secs)),
+        }
+    }
+}
+
+lazy_static! {
+    /// The most recent token fetched by a `TokenSource`. We create a new
+    /// `Authenticator` for each `Client`, so without this, we'd need to fetch
+    /// a new token for every request.
+    static ref CACHED_TOKEN: Mutex<Option<AccessToken>> = Mutex::new(None);
+}
+
+/// Ways to get access tokens, other than those supported by `yup_oauth2`.
+#[derive(Clone, Debug)]
+pub(crate) enum TokenSource {
+    /// Use the Compute Engine or GKE metadata server.
+    MetadataServer,
+    /// Use the refresh token from `gcloud auth application-default login`.
+    AuthorizedUser(AuthorizedUserKey),
+    /// Use workload identity federation.
+    ExternalAccount(ExternalAccountKey),
+}
+
+/// Something which can provide OAuth2 tokens.
+pub(crate) enum Authenticator {
+    /// Use `yup_oauth2` to manage tokens.
+    OAuth2(Box<OAuth2Authenticator>),
+    /// Fetch tokens ourselves.
+    TokenSource(reqwest::Client, Box<TokenSource>),
+}
+
+impl Authenticator {
+    /// Get a token which can be used with `scopes`.
+    pub(crate) async fn token(&self, scopes: &[&str]) -> Result<AccessToken> {
+        match self {
+            Authenticator::OAuth2(auth) => Ok(auth.token(scopes).await?.into()),
+            Authenticator::TokenSource(client, source) => {
+                if let Some(token) = CACHED_TOKEN
+                    .lock()
+                    .expect("lock poisoned, giving up")
+                    .as_ref()
+                    .filter(|token| !token.is_expired())
+                {
+                    return Ok(token.to_owned());
+                }
+                let token = match source.as_ref() {
+                    TokenSource::MetadataServer => {
+                        metadata_server::fetch_token(client, scopes).await?
+                    }
+                    TokenSource::AuthorizedUser(key) => {
+                        authorized_user::fetch_token(client, key).await?
+                    }
+                    TokenSource::ExternalAccount(key) => {
+                        external_account::fetch_token(client, key, scopes).await?
+                    }
+                };
+                *CACHED_TOKEN.lock().expect("lock poisoned, giving up") =
+                    Some(token.clone());
+                Ok(token)
+            }
+        }
+    }
+}
+
+/// Convert `s` into a hexadecimal digest using a hash function.
+///
+/// The details of this hash function don't matter. We only care that it returns
+/// something that's safe to include in a file name, and that has an extremely
+/// low probability of colliding.
+fn string_to_hex_digest(s: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(s);
+    let bytes = hasher.finalize();
+    let mut out = String::with_capacity(2 * bytes.len());
+    for b in bytes {
+        write!(&mut out, "{:02x}", b).expect("write should never fail");
+    }
+    out
+}
+
+/// The path to the file where we store our OAuth2 tokens.
+///
+/// This should be unique per `token_id` (at least with extremely high probability).
+async fn token_file_path(token_id: &str) -> Result<PathBuf> {
+    let data_local_dir = dirs::data_local_dir().ok_or_else(|| {
+        format_err!("cannot find directory to store authentication keys")
+    })?;
+    // `yup_oauth2` will fail with a cryptic error if the containing directory
+    // doesn't exist.
+    fs::create_dir_all(&data_local_dir)
+        .await
+        .with_context(|_| {
+            format!("could not create directory {}", data_local_dir.display())
+        })?;
+    let filename = format!("gcloud-oauth2-{}.json", string_to_hex_digest(token_id));
+    Ok(data_local_dir.join("dbcrossbar").join(filename))
+}
+
+/// Make sure the parent directory of `path` exists.
+async fn ensure_parent_directory(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    Ok(())
+}
+
+/// Get the service account key needed to connect a server app to BigQuery.
+pub(crate) async fn service_account_key() -> Result<ServiceAccountKey> {
+    let creds = CredentialsManager::singleton()
+        .get("gcloud_service_account_key")
+        .await?;
+    Ok(serde_json::from_str(creds.get_required("value")?)
+        .context("could not parse service account key")?)
+}
+
+/// Build an authenticator using service account credentials.
+async fn service_account_authenticator(
+    service_account_key: ServiceAccountKey,
+) -> Result<Authenticator> {
+    // We're going to use the private key ID to indentify our stored token. As far
+    // as I can tell, this is not especially sensitive information.
+    let key_id = service_account_key.private_key_id.as_ref().ok_or_else(|| {
+        format_err!("could not find private_key_id for GCloud service account key")
+    })?;
+    let token_file_path = token_file_path(key_id).await?;
+    ensure_parent_directory(&token_file_path).await?;
+    let auth = yup_oauth2::ServiceAccountAuthenticator::builder(service_account_key)
+        .hyper_client(hyper_client()?)
+        .persist_tokens_to_disk(token_file_path)
+        .build()
+        .await
+        .context("failed to create authenticator")?;
+    Ok(Authenticator::OAuth2(Box::new(auth)))
+}
+
+/// Get the application secret needed to connect an interactive app to Google Cloud.
+///
+/// This is intended for use in a CLI application that
+/// gets distributed to end users, but it's not actually enough to authenticate
+/// against Google Cloud itself. It needs to be used together with a
+/// browser-based OAuth2 confirmation.
+async fn application_secret() -> Result<ApplicationSecret> {
+    let creds = CredentialsManager::singleton()
+        .get("gcloud_client_secret")
+        .await?;
+    parse_application_secret(creds.get_required("value")?)
+}
+
+/// Parse a client secret.
+fn parse_application_secret(data: &str) -> Result<ApplicationSecret> {
+    serde_json::from_str::<ConsoleApplicationSecret>(data)
+        .context("could not parse client secret")?
+        .installed
+        .ok_or_else(|| format_err!("client secret does not contain `installed` key"))
+}
+
+/// Build an interactive authenticator for a CLI tool.
+async fn installed_flow_authenticator(
+    application_secret: ApplicationSecret,
+) -> Result<Authenticator> {
+    // Keying our token file path by the client ID seems to work here, because
+    // there might be multiple client IDs passed in as env vars at different
+    // times, but scoping session keys to the client ID seems reasonable.
+    let token_file_path = token_file_path(&application_secret.client_id).await?;
+    ensure_parent_directory(&token_file_path).await?;
+    let auth = yup_oauth2::InstalledFlowAuthenticator::builder(
+        application_secret,
+        InstalledFlowReturnMethod::HTTPRedirect,
+    )
+    .hyper_client(hyper_client()?)
+    .persist_tokens_to_disk(token_file_path)
+    .build()
+    .await
+    .context("failed to create authenticator")?;
+    Ok(Authenticator::OAuth2(Box::new(auth)))
+}
+
+/// The contents of an Application Default Credentials file.
+#[derive(Clone, Debug)]
+pub(crate) enum ApplicationDefaultKey {
+    ServiceAccount(ServiceAccountKey),
+    AuthorizedUser(AuthorizedUserKey),
+    ExternalAccount(ExternalAccountKey),
+}
+
+impl ApplicationDefaultKey {
+    /// Parse an Application Default Credentials file, using the `type` field
+    /// to decide what kind of credentials it contains.
+    fn parse(data: &str) -> Result<Self> {
+        let value = serde_json::from_str::<Value>(data)?;
+        let key_type = value
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default()
+            .to_owned();
+        match &key_type[..] {
+            "service_account" => Ok(ApplicationDefaultKey::ServiceAccount(
+                serde_json::from_value(value)?,
+            )),
+            "authorized_user" => Ok(ApplicationDefaultKey::AuthorizedUser(
+                serde_json::from_value(value)?,
+            )),
+            "external_account" => Ok(ApplicationDefaultKey::ExternalAccount(
+                serde_json::from_value(value)?,
+            )),
+            _ => Err(format_err!("unsupported credentials type {:?}", key_type)),
+        }
+    }
+}
+
+/// Where we found our Google Cloud credentials.
+pub(crate) enum CredentialSource {
+    /// A service account key configured for `dbcrossbar`.
+    ServiceAccountKey(ServiceAccountKey),
+    /// An Application Default Credentials file.
+    ApplicationDefault(PathBuf, ApplicationDefaultKey),
+    /// A client secret for interactive logins.
+    ClientSecret(ApplicationSecret),
+    /// The metadata server.
+    MetadataServer,
+}
+
+impl fmt::Display for CredentialSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialSource::ServiceAccountKey(_) => write!(f, "service account key"),
+            CredentialSource::ApplicationDefault(path, key) => {
+                let kind = match key {
+                    ApplicationDefaultKey::ServiceAccount(_) => "service account",
+                    ApplicationDefaultKey::AuthorizedUser(_) => "user credentials",
+                    ApplicationDefaultKey::ExternalAccount(_) => {
+                        "workload identity federation"
+                    }
+                };
+                write!(
+                    f,
+                    "application default credentials ({}) in {}",
+                    kind,
+                    path.display()
+                )
+            }
+            CredentialSource::ClientSecret(_) => write!(f, "client secret"),
+            CredentialSource::MetadataServer => write!(f, "metadata server"),
+        }
+    }
+}
+
+/// The path to the Application Default Credentials file, if we have one.
+///
+/// This is specified by `GOOGLE_APPLICATION_CREDENTIALS`, or created by
+/// `gcloud auth application-default login`.
+fn application_default_credentials_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("GOOGLE_APPLICATION_CREDENTIALS") {
+        return Some(PathBuf::from(path));
+    }
+    let path = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?).join("gcloud")
+    } else {
+        dirs::home_dir()?.join(".config").join("gcloud")
+    };
+    Some(path.join("application_default_credentials.json"))
+        .filter(|path| path.exists())
+}
+
+/// Find the credentials we should use. We look for:
+///
+/// 1. A service account key configured for `dbcrossbar`.
+/// 2. Application Default Credentials.
+/// 3. A client secret for interactive logins.
+/// 4. The metadata server, if we're running on Google Cloud.
+pub(crate) async fn find_credentials(ctx: &Context) -> Result<CredentialSource> {
+    match service_account_key().await {
+        Ok(key) => return Ok(CredentialSource::ServiceAccountKey(key)),
+        Err(err) => trace!(ctx.log(), "no service account key: {}", err),
+    }
+    if let Some(path) = application_default_credentials_path() {
+        let data = fs::read_to_string(&path)
+            .await
+            .with_context(|_| format!("could not read {}", path.display()))?;
+        let key = ApplicationDefaultKey::parse(&data)
+            .with_context(|_| format!("could not parse {}", path.display()))?;
+        return Ok(CredentialSource::ApplicationDefault(path, key));
+    }
+    match application_secret().await {
+        Ok(secret) => return Ok(CredentialSource::ClientSecret(secret)),
+        Err(err) => trace!(ctx.log(), "no client secret: {}", err),
+    }
+    if metadata_server::is_available(&reqwest_client()?).await {
+        return Ok(CredentialSource::MetadataServer);
+    }
+    Err(format_err!(
+        "could not find Google Cloud credentials (set GCLOUD_SERVICE_ACCOUNT_KEY, GOOGLE_APPLICATION_CREDENTIALS or GCLOUD_CLIENT_SECRET, or run `gcloud auth application-default login`)"
+    ))
+}
+
+/// Create an authenticator using the first credentials we can find.
+pub(crate) async fn authenticator(ctx: &Context) -> Result<Authenticator> {
+    let source = find_credentials(ctx).await?;
+    debug!(ctx.log(), "authenticating using {}", source);
+    let client = reqwest_client()?;
+    match source {
+        CredentialSource::ServiceAccountKey(key)
+        | CredentialSource::ApplicationDefault(
+            _,
+            ApplicationDefaultKey::ServiceAccount(key),
+        ) => service_account_authenticator(key).await,
+        CredentialSource::ApplicationDefault(
+            _,
+            ApplicationDefaultKey::AuthorizedUser(key),
+        ) => Ok(Authenticator::TokenSource(
+            client,
+            Box::new(TokenSource::AuthorizedUser(key)),
+        )),
+        CredentialSource::ApplicationDefault(
+            _,
+            ApplicationDefaultKey::ExternalAccount(key),
+        ) => Ok(Authenticator::TokenSource(
+            client,
+            Box::new(TokenSource::ExternalAccount(key)),
+        )),
+        CredentialSource::ClientSecret(secret) => {
+            installed_flow_authenticator(secret).await
+        }
+        CredentialSource::MetadataServer => Ok(Authenticator::TokenSource(
+            client,
+            Box::new(TokenSource::MetadataServer),
+        )),
+    }
+}
+
+/// Log in interactively using a client secret, and cache the resulting
+/// tokens. If `client_secret_path` is specified, copy that client secret into
+/// our config directory first, so that we can use it later.
+pub(crate) async fn login(
+    ctx: &Context,
+    client_secret_path: Option<&Path>,
+) -> Result<()> {
+    let secret = match client_secret_path {
+        Some(path) => {
+            let data = fs::read_to_string(path)
+                .await
+                .with_context(|_| format!("could not read {}", path.display()))?;
+            let secret = parse_application_secret(&data)
+                .with_context(|_| format!("could not parse {}", path.display()))?;
+            let dest = config_dir()?.join("gcloud_client_secret.json");
+            ensure_parent_directory(&dest).await?;
+            fs::write(&dest, data)
+                .await
+                .with_context(|_| format!("could not write {}", dest.display()))?;
+            debug!(ctx.log(), "saved client secret to {}", dest.display());
+            secret
+        }
+        None => application_secret().await.context(
+            "need a client secret to log in (pass --client-secret or set GCLOUD_CLIENT_SECRET)",
+        )?,
+    };
+
+    // Getting a token will open the OAuth2 flow if we don't have a cached one.
+    let auth = installed_flow_authenticator(secret).await?;
+    auth.token(SCOPES).await?;
+
+    // Tell the user if some other credentials would take priority.
+    match find_credentials(ctx).await? {
+        CredentialSource::ClientSecret(_) => {}
+        other => warn!(
+            ctx.log(),
+            "logged in, but {} will be used instead of your login", other,
+        ),
+    }
+    Ok(())
+}
+
+/// Delete any tokens cached by `login`. Returns true if we deleted anything.
+pub(crate) async fn logout(ctx: &Context) -> Result<bool> {
+    let secret = match application_secret().await {
+        Ok(secret) => secret,
+        Err(err) => {
+            trace!(ctx.log(), "no client secret, so not logged in: {}", err);
+            return Ok(false);
+        }
+    };
+    let path = token_file_path(&secret.client_id).await?;
+    if path.exists() {
+        fs::remove_file(&path)
+            .await
+            .with_context(|_| format!("could not delete {}", path.display()))?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+#[test]
+fn parses_application_default_keys() {
+    let user = r#"{
+        "type": "authorized_user",
+        "client_id": "id",
+        "client_secret": "secret",
+        "refresh_token": "token"
+    }"#;
+    assert!(matches!(
+        ApplicationDefaultKey::parse(user).unwrap(),
+        ApplicationDefaultKey::AuthorizedUser(_),
+    ));
+    let external = r#"{
+        "type": "external_account",
+        "audience": "//iam.googleapis.com/projects/1/locations/global/workloadIdentityPools/p/providers/q",
+        "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+        "token_url": "https://sts.googleapis.com/v1/token",
+        "credential_source": { "file": "/var/run/token" }
+    }"#;
+    assert!(matches!(
+        ApplicationDefaultKey::parse(external).unwrap(),
+        ApplicationDefaultKey::ExternalAccount(_),
+    ));
+    assert!(ApplicationDefaultKey::parse(r#"{"type": "other"}"#).is_err());
+}
@@ -0,0 +1,48 @@
+//! Support for looking up BigQuery datasets.
+
+use serde::Deserialize;
+
+use super::super::{percent_encode, Client, NoQuery};
+use crate::common::*;
+use crate::drivers::bigquery_shared::TableName;
+
+/// Information about a dataset.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Dataset {
+    /// The geographic location of this dataset, like `US` or `europe-west2`.
+    location: String,
+}
+
+/// Look up the geographic location of the dataset containing `name`.
+pub(crate) async fn dataset_location(
+    ctx: &Context,
+    name: &TableName,
+) -> Result<String> {
+    trace!(ctx.log(), "fetching dataset location for {:?}", name);
+    let url = format!(
+        "https://bigquery.googleapis.com/bigquery/v2/projects/{}/datasets/{}",
+        percent_encode(name.project()),
+        percent_encode(name.dataset()),
+    );
+    let client = Client::new(ctx).await?;
+    let dataset = client.get::<Dataset, _, _>(ctx, &url, NoQuery).await?;
+    Ok(dataset.location)
+}
+
+/// Are two dataset locations the same? BigQuery treats location names as
+/// case-insensitive.
+pub(crate) fn is_same_location(location1: &str, location2: &str) -> bool {
+    location1.eq_ignore_ascii_case(location2)
+}
+
+#[test]
+fn parses_dataset_locations() {
+    let dataset = serde_json::from_str::<Dataset>(
+        r#"{"kind": "bigquery#dataset", "id": "project:dataset", "location": "EU"}"#,
+    )
+    .unwrap();
+    assert_eq!(dataset.location, "EU");
+    assert!(is_same_location("EU", "eu"));
+    assert!(!is_same_location("US", "us-east1"));
+}
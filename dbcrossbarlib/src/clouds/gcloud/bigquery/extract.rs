@@ -8,11 +8,13 @@ use super::{
 use crate::common::*;
 use crate::drivers::bigquery_shared::TableName;
 
-/// Extract a table from BigQuery to Google Cloud Storage.
+/// Extract a table from BigQuery to Google Cloud Storage. If `print_header` is
+/// false, the files will not contain header rows.
 pub(crate) async fn extract(
     ctx: &Context,
     source_table: &TableName,
     dest_gs_url: &Url,
+    print_header: bool,
     labels: &Labels,
 ) -> Result<()> {
     trace!(ctx.log(), "extract {} into {}", source_table, dest_gs_url);
@@ -21,6 +23,7 @@ pub(crate) async fn extract(
     let config = JobConfigurationExtract {
         destination_uris: vec![format!("{}/*.csv", dest_gs_url)],
         source_table: TableReference::from(source_table),
+        print_header: if print_header { None } else { Some(false) },
     };
 
     // Run our job.
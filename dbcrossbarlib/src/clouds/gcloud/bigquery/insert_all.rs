@@ -0,0 +1,139 @@
+//! Streaming inserts into BigQuery.
+
+use bigml::wait::{wait, BackoffType, WaitOptions, WaitStatus};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+use super::{
+    super::{
+        gcloud_error_kind, is_retryable, percent_encode, Client, GCloudErrorKind,
+        NoQuery,
+    },
+    BigQueryError,
+};
+use crate::common::*;
+use crate::drivers::bigquery_shared::TableName;
+
+/// The number of rows to send in each request. Google recommends 500.
+const ROWS_PER_REQUEST: usize = 500;
+
+/// Request body for `insertAll`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InsertAllRequest<'a> {
+    skip_invalid_rows: bool,
+    ignore_unknown_values: bool,
+    rows: Vec<InsertRow<'a>>,
+}
+
+/// A row to insert.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InsertRow<'a> {
+    /// A unique ID for this row, which BigQuery uses to discard duplicates if
+    /// we need to retry a request.
+    insert_id: String,
+    /// The row to insert, as a JSON object.
+    json: &'a Value,
+}
+
+/// Response body for `insertAll`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InsertAllResponse {
+    #[serde(default)]
+    insert_errors: Vec<InsertError>,
+}
+
+/// Errors for a single row.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InsertError {
+    index: usize,
+    errors: Vec<BigQueryError>,
+}
+
+/// Insert `rows` into `table_name` using BigQuery's streaming insert API.
+///
+/// Each row should be a JSON object with one key per column. Rows inserted
+/// this way are visible to queries within a few seconds, but BigQuery may take
+/// longer to copy them to permanent storage.
+pub(crate) async fn insert_all(
+    ctx: &Context,
+    table_name: &TableName,
+    rows: &[Value],
+) -> Result<()> {
+    debug!(
+        ctx.log(),
+        "streaming {} rows into {}",
+        rows.len(),
+        table_name,
+    );
+    let url = format!(
+        "https://bigquery.googleapis.com/bigquery/v2/projects/{}/datasets/{}/tables/{}/insertAll",
+        percent_encode(table_name.project()),
+        percent_encode(table_name.dataset()),
+        percent_encode(table_name.table()),
+    );
+    let client = Client::new(ctx).await?;
+    let id_prefix = TemporaryStorage::random_tag();
+    for (chunk_idx, chunk) in rows.chunks(ROWS_PER_REQUEST).enumerate() {
+        let first_idx = chunk_idx * ROWS_PER_REQUEST;
+        let req = InsertAllRequest {
+            skip_invalid_rows: false,
+            ignore_unknown_values: false,
+            rows: chunk
+                .iter()
+                .enumerate()
+                .map(|(idx, json)| InsertRow {
+                    insert_id: format!("{}-{}", id_prefix, first_idx + idx),
+                    json,
+                })
+                .collect(),
+        };
+
+        // A newly-created table may not be visible to `insertAll` right away,
+        // so retry "not found" errors for a little while, along with rate
+        // limits and server errors.
+        let wait_options = WaitOptions::default()
+            .backoff_type(BackoffType::Exponential)
+            .retry_interval(Duration::from_secs(2))
+            .allowed_errors(4);
+        let resp = wait(&wait_options, || async {
+            let result = client
+                .post::<InsertAllResponse, _, _, _>(ctx, &url, NoQuery, &req)
+                .await;
+            match result {
+                Ok(resp) => WaitStatus::Finished(resp),
+                Err(err) if is_not_found(&err) || is_retryable(&err) => {
+                    ctx.retrying(&err);
+                    WaitStatus::FailedTemporarily(err)
+                }
+                Err(err) => WaitStatus::FailedPermanently(err),
+            }
+        })
+        .await?;
+
+        if let Some(insert_error) = resp.insert_errors.first() {
+            let messages = insert_error
+                .errors
+                .iter()
+                .map(|err| err.to_string())
+                .join("; ");
+            return Err(format_err!(
+                "could not insert row {} into {}: {}",
+                first_idx + insert_error.index,
+                table_name,
+                messages,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Is `err` a Google Cloud "not found" error?
+pub(crate) fn is_not_found(err: &Error) -> bool {
+    gcloud_error_kind(err) == Some(GCloudErrorKind::NotFound)
+}
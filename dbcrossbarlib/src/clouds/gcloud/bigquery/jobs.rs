@@ -2,9 +2,14 @@
 //!
 //! These use a number of closely-related types.
 
+use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, convert::TryFrom};
-use tokio::time::{delay_for, Duration};
+use std::{
+    cmp::min,
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+};
+use tokio::time::Duration;
 
 use super::{
     super::{Client, NoQuery},
@@ -12,6 +17,7 @@ use super::{
 };
 use crate::common::*;
 use crate::drivers::bigquery_shared::TableName;
+use crate::events::Event;
 
 /// Key/value pairs. See [JobConfiguration][config].
 ///
@@ -39,6 +45,10 @@ pub(crate) struct Job {
     /// Output only. The status of this job.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) status: Option<JobStatus>,
+
+    /// Output only. Statistics about this job.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) statistics: Option<JobStatistics>,
 }
 
 impl Job {
@@ -50,6 +60,7 @@ impl Job {
             configuration,
             job_reference: None,
             status: None,
+            statistics: None,
         }
     }
 
@@ -179,6 +190,9 @@ impl JobConfigurationQuery {
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct JobConfigurationLoad {
+    /// Where to load data from. This is empty when uploading data with the
+    /// job.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub(crate) source_uris: Vec<String>,
     pub(crate) schema: Option<TableSchema>,
     pub(crate) destination_table: TableReference,
@@ -186,6 +200,22 @@ pub(crate) struct JobConfigurationLoad {
     pub(crate) write_disposition: Option<WriteDisposition>,
     pub(crate) skip_leading_rows: Option<i32>,
     pub(crate) allow_quoted_newlines: Option<bool>,
+
+    /// Should we ignore extra values at the end of each CSV row?
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) ignore_unknown_values: Option<bool>,
+
+    /// Should we treat missing values at the end of each CSV row as `NULL`?
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) allow_jagged_rows: Option<bool>,
+
+    /// How may we change the schema of the destination table when appending?
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) schema_update_options: Vec<SchemaUpdateOption>,
+
+    /// Any other fields, normally set using `--to-arg=load_job.*`.
+    #[serde(flatten)]
+    pub(crate) other: serde_json::Map<String, serde_json::Value>,
 }
 
 /// Configuration for data extraction jobs.
@@ -197,6 +227,10 @@ pub(crate) struct JobConfigurationExtract {
 
     /// The location of our data.
     pub(crate) source_table: TableReference,
+
+    /// Should we print a header row in each file? Defaults to true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) print_header: Option<bool>,
 }
 
 /// The status of a job.
@@ -226,6 +260,99 @@ impl JobStatus {
     }
 }
 
+/// Statistics about a job. BigQuery returns 64-bit integers as strings, so
+/// we keep them that way until we need them.
+///
+/// Docs: https://cloud.google.com/bigquery/docs/reference/rest/v2/Job#jobstatistics
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JobStatistics {
+    /// When this job was created, in milliseconds since the epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    creation_time: Option<String>,
+
+    /// When this job started running, in milliseconds since the epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_time: Option<String>,
+
+    /// When this job finished, in milliseconds since the epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_time: Option<String>,
+
+    /// The number of bytes processed by this job. For dry runs, this is the
+    /// number of bytes the job would process.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) total_bytes_processed: Option<String>,
+
+    /// The number of slot-milliseconds used by this job.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_slot_ms: Option<String>,
+
+    /// Statistics for load jobs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    load: Option<JobStatisticsLoad>,
+
+    /// Statistics for query jobs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) query: Option<JobStatisticsQuery>,
+}
+
+impl JobStatistics {
+    /// Summarize these statistics as named counts, omitting any which BigQuery
+    /// didn't report.
+    pub(crate) fn to_counts(&self) -> BTreeMap<String, u64> {
+        let mut counts = BTreeMap::new();
+        let mut add = |name: &str, value: &Option<String>| {
+            if let Some(value) = value.as_ref().and_then(|v| v.parse::<u64>().ok()) {
+                counts.insert(name.to_owned(), value);
+            }
+        };
+        add("bytes_processed", &self.total_bytes_processed);
+        add("slot_ms", &self.total_slot_ms);
+        if let Some(load) = &self.load {
+            add("input_file_bytes", &load.input_file_bytes);
+            add("output_rows", &load.output_rows);
+        }
+
+        // Compute how long we spent waiting and running.
+        let time = |t: &Option<String>| t.as_ref().and_then(|t| t.parse::<u64>().ok());
+        let (created, started, ended) = (
+            time(&self.creation_time),
+            time(&self.start_time),
+            time(&self.end_time),
+        );
+        if let (Some(created), Some(started)) = (created, started) {
+            counts.insert("pending_ms".to_owned(), started.saturating_sub(created));
+        }
+        if let (Some(started), Some(ended)) = (started, ended) {
+            counts.insert("running_ms".to_owned(), ended.saturating_sub(started));
+        }
+        counts
+    }
+}
+
+/// Statistics for a load job.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JobStatisticsLoad {
+    /// The number of bytes in our source files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_file_bytes: Option<String>,
+
+    /// The number of rows loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_rows: Option<String>,
+}
+
+/// Statistics for a query job.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JobStatisticsQuery {
+    /// The schema of the query's results. This is only reported for dry runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) schema: Option<TableSchema>,
+}
+
 /// The state of a job.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Serialize, PartialEq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
@@ -292,6 +419,16 @@ impl TryFrom<&IfExists> for WriteDisposition {
     }
 }
 
+/// How may a load job change the schema of an existing table?
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Serialize, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum SchemaUpdateOption {
+    /// Add new `NULLABLE` columns.
+    AllowFieldAddition,
+    /// Change `REQUIRED` columns to `NULLABLE`.
+    AllowFieldRelaxation,
+}
+
 /// Run a BigQuery job.
 pub(crate) async fn run_job(
     ctx: &Context,
@@ -314,24 +451,127 @@ pub(crate) async fn run_job(
     job = client
         .post::<Job, _, _, _>(ctx, &insert_url, NoQuery, job)
         .await?;
+    wait_for_job(ctx, client, job).await
+}
 
+/// Validate a BigQuery job without running it. BigQuery responds
+/// immediately, and the returned job contains statistics describing what the
+/// job would have done.
+pub(crate) async fn dry_run_job(
+    ctx: &Context,
+    client: &Client,
+    project_id: &str,
+    mut job: Job,
+) -> Result<Job> {
+    trace!(ctx.log(), "dry run of BigQuery job on {} {:?}", project_id, job);
+    job.configuration.dry_run = Some(true);
+    let insert_url = format!(
+        "https://bigquery.googleapis.com/bigquery/v2/projects/{}/jobs",
+        project_id,
+    );
+    client
+        .post::<Job, _, _, _>(ctx, &insert_url, NoQuery, job)
+        .await
+}
+
+/// Parameters for a job upload.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadQuery {
+    /// The type of the upload we're performing.
+    upload_type: &'static str,
+}
+
+/// Run a BigQuery load job, uploading `csv_data` along with the job.
+pub(crate) async fn run_upload_job(
+    ctx: &Context,
+    client: &Client,
+    project_id: &str,
+    mut job: Job,
+    csv_data: Vec<u8>,
+) -> Result<Job> {
+    trace!(
+        ctx.log(),
+        "starting BigQuery upload job on {} {:?}",
+        project_id,
+        job,
+    );
+
+    // Create our job.
+    let upload_url = format!(
+        "https://bigquery.googleapis.com/upload/bigquery/v2/projects/{}/jobs",
+        project_id,
+    );
+    let query = UploadQuery {
+        upload_type: "multipart",
+    };
+    job = client
+        .post_multipart::<Job, _, _, _>(
+            ctx,
+            &upload_url,
+            query,
+            job,
+            "text/csv",
+            csv_data,
+        )
+        .await?;
+    wait_for_job(ctx, client, job).await
+}
+
+/// Exponential backoff with random jitter, used when polling jobs. The jitter
+/// prevents many parallel copies from polling BigQuery in lockstep.
+#[derive(Debug)]
+struct PollBackoff {
+    /// The maximum delay before our next poll, in milliseconds.
+    next_ms: u64,
+}
+
+impl PollBackoff {
+    /// Our initial maximum delay, in milliseconds.
+    const INITIAL_MS: u64 = 1_000;
+
+    /// The longest we'll ever wait between polls, in milliseconds.
+    const MAX_MS: u64 = 16_000;
+
+    /// Create a new backoff, starting with short delays.
+    fn new() -> Self {
+        PollBackoff {
+            next_ms: Self::INITIAL_MS,
+        }
+    }
+
+    /// How long should we wait before polling again? We always wait at least
+    /// half the current maximum delay, so that we never poll too quickly.
+    fn next_delay<R: Rng>(&mut self, rng: &mut R) -> Duration {
+        let half = self.next_ms / 2;
+        let delay = half + rng.gen_range(0, self.next_ms - half + 1);
+        self.next_ms = min(self.next_ms * 2, Self::MAX_MS);
+        Duration::from_millis(delay)
+    }
+}
+
+/// Wait for a BigQuery job to finish.
+async fn wait_for_job(ctx: &Context, client: &Client, mut job: Job) -> Result<Job> {
     // Get the URL for polling the job.
     let job_url = job.url()?;
+    let job_id = job
+        .reference()
+        .map(|r| r.job_id.clone())
+        .unwrap_or_else(|_| "(unknown)".to_owned());
 
     // Check our current job status.
-    let mut sleep_duration = Duration::from_secs(2);
+    let mut backoff = PollBackoff::new();
     loop {
         // Check to see if the job is done.
         let state = job.status.as_ref().map(|s| s.state);
         if state == Some(JobState::Done) {
             break;
         }
+        debug!(ctx.log(), "BigQuery job {} is {:?}", job_id, state);
 
-        // Wait for a while.
-        delay_for(sleep_duration).await;
-        if sleep_duration < Duration::from_secs(16) {
-            sleep_duration *= 2;
-        }
+        // Wait for a while, unless we're cancelled first.
+        let delay = backoff.next_delay(&mut thread_rng());
+        ctx.cancellation().delay_for(delay).await?;
 
         // Update our job.
         job = client
@@ -339,6 +579,25 @@ pub(crate) async fn run_job(
             .await?;
     }
 
+    // Report what the job did, even if it failed.
+    if let Some(statistics) = &job.statistics {
+        let statistics = statistics.to_counts();
+        info!(
+            ctx.log(),
+            "BigQuery job {} finished: {}",
+            job_id,
+            statistics
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        ctx.emit(Event::JobFinished {
+            job_id: job_id.clone(),
+            statistics,
+        });
+    }
+
     // Return either an error or a finished job.
     job.status
         .as_ref()
@@ -346,3 +605,39 @@ pub(crate) async fn run_job(
         .check_for_error()?;
     Ok(job)
 }
+
+#[test]
+fn poll_backoff_grows_with_jitter() {
+    let mut rng = thread_rng();
+    for _ in 0..100 {
+        let mut backoff = PollBackoff::new();
+        let mut max_ms = PollBackoff::INITIAL_MS;
+        for _ in 0..10 {
+            let delay = backoff.next_delay(&mut rng);
+            assert!(delay >= Duration::from_millis(max_ms / 2));
+            assert!(delay <= Duration::from_millis(max_ms));
+            max_ms = min(max_ms * 2, PollBackoff::MAX_MS);
+        }
+    }
+}
+
+#[test]
+fn job_statistics_to_counts() {
+    let statistics: JobStatistics = serde_json::from_str(
+        r#"{
+  "creationTime": "1000",
+  "startTime": "1500",
+  "endTime": "4500",
+  "totalSlotMs": "1234",
+  "load": { "inputFileBytes": "2048", "outputRows": "10" }
+}"#,
+    )
+    .unwrap();
+    let counts = statistics.to_counts();
+    assert_eq!(counts.get("bytes_processed"), None);
+    assert_eq!(counts["slot_ms"], 1234);
+    assert_eq!(counts["input_file_bytes"], 2048);
+    assert_eq!(counts["output_rows"], 10);
+    assert_eq!(counts["pending_ms"], 500);
+    assert_eq!(counts["running_ms"], 3000);
+}
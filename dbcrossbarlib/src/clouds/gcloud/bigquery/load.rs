@@ -1,48 +1,303 @@
 //! Load data from Google Cloud Storage into BigQuery.
 
+use serde_json::{Map, Value};
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+    path::Path,
+};
+
 use super::{
     super::Client,
     jobs::{
-        run_job, CreateDisposition, Job, JobConfigurationLoad, Labels, TableReference,
-        WriteDisposition,
+        run_job, run_upload_job, CreateDisposition, Job, JobConfigurationLoad, Labels,
+        SchemaUpdateOption, TableReference, WriteDisposition,
     },
     TableSchema,
 };
 use crate::common::*;
-use crate::drivers::bigquery_shared::BqTable;
-use std::convert::TryFrom;
+use crate::drivers::bigquery_shared::{BqTable, TableName};
+
+/// The maximum number of source URIs which BigQuery allows in a single load
+/// job.
+pub(crate) const MAX_SOURCE_URIS_PER_LOAD_JOB: usize = 10_000;
+
+/// The default maximum number of load jobs a single copy may run against a
+/// table. This is BigQuery's daily limit for each table, which also counts
+/// failed jobs.
+pub(crate) const DEFAULT_MAX_LOAD_JOBS: u32 = 1_500;
+
+/// Fail if loading into `table` would take more than `max` load jobs.
+///
+/// This only limits the jobs needed by one copy. We don't know how many jobs
+/// have already been run against `table` today, by `dbcrossbar` or anything
+/// else, so a copy which passes this check may still hit BigQuery's daily
+/// limit. But a copy which fails it would certainly hit that limit part way
+/// through, so we refuse to start it.
+pub(crate) fn check_load_job_count(
+    table: &TableName,
+    count: u32,
+    max: u32,
+) -> Result<()> {
+    if count > max {
+        return Err(format_err!(
+            "loading into {} would need {} load jobs, but --to-arg=max_load_jobs is {} (try increasing --to-arg=files_per_load_job)",
+            table,
+            count,
+            max,
+        ));
+    }
+    Ok(())
+}
+
+/// Load job fields which we always set ourselves, and which can't be
+/// overridden.
+const PROTECTED_LOAD_FIELDS: &[&str] = &["sourceUris", "destinationTable"];
+
+/// Extra options for load jobs.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LoadOptions {
+    /// Fields to set in `configuration.load`, replacing any values we would
+    /// normally use.
+    pub(crate) overrides: Map<String, Value>,
+
+    /// Should we ignore extra values at the end of each CSV row?
+    pub(crate) ignore_unknown_values: Option<bool>,
 
-/// Load data from `gs_url` into `dest_table`.
+    /// Should we treat missing values at the end of each CSV row as `NULL`?
+    pub(crate) allow_jagged_rows: Option<bool>,
+
+    /// How may we change the schema of the destination table when appending?
+    pub(crate) schema_update_options: Vec<SchemaUpdateOption>,
+
+    /// Print each job's configuration to standard output instead of running
+    /// it.
+    pub(crate) audit: bool,
+}
+
+impl LoadOptions {
+    /// Build options from `load_job.*` driver arguments, which are always
+    /// strings. Strings which are valid JSON are parsed, so that
+    /// `maxBadRecords=10` sets a number, but `fieldDelimiter=|` sets a string.
+    pub(crate) fn from_args(overrides: &Map<String, Value>, audit: bool) -> Self {
+        let overrides = overrides
+            .iter()
+            .map(|(name, value)| (name.to_owned(), parse_override_value(value)))
+            .collect();
+        LoadOptions {
+            overrides,
+            audit,
+            ..LoadOptions::default()
+        }
+    }
+
+    /// Apply our overrides to `config`.
+    fn apply(&self, config: JobConfigurationLoad) -> Result<JobConfigurationLoad> {
+        if self.overrides.is_empty() {
+            return Ok(config);
+        }
+        let mut json = serde_json::to_value(config)?;
+        let fields = json
+            .as_object_mut()
+            .expect("load configuration should be an object");
+        for (name, value) in &self.overrides {
+            if PROTECTED_LOAD_FIELDS.contains(&&name[..]) {
+                return Err(format_err!("cannot override load_job.{}", name));
+            }
+            fields.insert(name.to_owned(), value.to_owned());
+        }
+        Ok(serde_json::from_value(json)
+            .context("invalid --to-arg=load_job.* value")?)
+    }
+}
+
+/// Parse `value` as JSON if it's a string containing valid JSON, and
+/// otherwise leave it alone.
+fn parse_override_value(value: &Value) -> Value {
+    match value {
+        Value::String(s) => serde_json::from_str(s).unwrap_or_else(|_| value.clone()),
+        Value::Array(values) => {
+            Value::Array(values.iter().map(parse_override_value).collect())
+        }
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.to_owned(), parse_override_value(value)))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// Load data from `source_uris` into `dest_table` using a single load job.
 pub(crate) async fn load(
     ctx: &Context,
-    gs_url: &Url,
+    source_uris: &[String],
     dest_table: &BqTable,
     if_exists: &IfExists,
     labels: &Labels,
+    options: &LoadOptions,
 ) -> Result<()> {
-    trace!(ctx.log(), "loading {} into {}", gs_url, dest_table.name);
+    trace!(
+        ctx.log(),
+        "loading {} URIs into {}",
+        source_uris.len(),
+        dest_table.name,
+    );
+    if source_uris.is_empty() || source_uris.len() > MAX_SOURCE_URIS_PER_LOAD_JOB {
+        return Err(format_err!(
+            "a BigQuery load job needs between 1 and {} source URIs, not {}",
+            MAX_SOURCE_URIS_PER_LOAD_JOB,
+            source_uris.len(),
+        ));
+    }
+
+    let job = load_job(
+        source_uris.to_owned(),
+        dest_table,
+        if_exists,
+        labels,
+        options,
+    )?;
+
+    // In audit mode, print the job instead of running it.
+    if options.audit {
+        return audit_job(&job);
+    }
+
+    // Run our job.
+    let client = Client::new(ctx).await?;
+    run_job(ctx, &client, dest_table.name.project(), job).await?;
+    Ok(())
+}
 
-    // Configure our job.
+/// Load data from the local CSV file `path` into `dest_table`, uploading the
+/// file along with the load job. This should only be used with small files.
+pub(crate) async fn load_file(
+    ctx: &Context,
+    path: &Path,
+    dest_table: &BqTable,
+    if_exists: &IfExists,
+    labels: &Labels,
+    options: &LoadOptions,
+) -> Result<()> {
+    trace!(
+        ctx.log(),
+        "uploading {} into {}",
+        path.display(),
+        dest_table.name,
+    );
+    let job = load_job(vec![], dest_table, if_exists, labels, options)?;
+    if options.audit {
+        return audit_job(&job);
+    }
+    let csv_data = tokio::fs::read(path)
+        .await
+        .with_context(|_| format!("could not read {}", path.display()))?;
+    let client = Client::new(ctx).await?;
+    run_upload_job(ctx, &client, dest_table.name.project(), job, csv_data).await?;
+    Ok(())
+}
+
+/// Build a load job which reads from `source_uris`, or from uploaded data if
+/// `source_uris` is empty.
+fn load_job(
+    source_uris: Vec<String>,
+    dest_table: &BqTable,
+    if_exists: &IfExists,
+    labels: &Labels,
+    options: &LoadOptions,
+) -> Result<Job> {
+    let write_disposition = WriteDisposition::try_from(if_exists)?;
+    if !options.schema_update_options.is_empty()
+        && write_disposition != WriteDisposition::WriteAppend
+    {
+        return Err(format_err!(
+            "allow_field_addition and allow_field_relaxation can only be used when loading directly into {} with --if-exists=append",
+            dest_table.name,
+        ));
+    }
     let config = JobConfigurationLoad {
-        source_uris: vec![gs_url.to_string()],
+        source_uris,
         schema: Some(TableSchema {
             fields: dest_table.columns.clone(),
         }),
         destination_table: TableReference::from(&dest_table.name),
         create_disposition: Some(CreateDisposition::CreateIfNeeded),
-        write_disposition: Some(WriteDisposition::try_from(if_exists)?),
+        write_disposition: Some(write_disposition),
         skip_leading_rows: Some(1),
         allow_quoted_newlines: Some(true),
+        ignore_unknown_values: options.ignore_unknown_values,
+        allow_jagged_rows: options.allow_jagged_rows,
+        schema_update_options: options.schema_update_options.clone(),
+        other: Map::new(),
     };
+    Ok(Job::new_load(options.apply(config)?, labels.to_owned()))
+}
 
-    // Run our job.
-    let client = Client::new(ctx).await?;
-    run_job(
-        ctx,
-        &client,
-        dest_table.name.project(),
-        Job::new_load(config, labels.to_owned()),
-    )
-    .await?;
+/// Print `job` to standard output instead of running it.
+fn audit_job(job: &Job) -> Result<()> {
+    let json = serde_json::to_string(&job.configuration)?;
+    writeln!(io::stdout().lock(), "{}", json)?;
     Ok(())
 }
+
+#[test]
+fn check_load_job_count_enforces_max() {
+    let table = "project:dataset.table".parse::<TableName>().unwrap();
+    check_load_job_count(&table, 5, 5).unwrap();
+    // Each check is independent, so earlier copies don't count.
+    check_load_job_count(&table, 5, 5).unwrap();
+    assert!(check_load_job_count(&table, 6, 5).is_err());
+}
+
+#[test]
+fn load_options_override_fields() {
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    let config = || JobConfigurationLoad {
+        source_uris: vec!["gs://bucket/*.csv".to_owned()],
+        schema: None,
+        destination_table: TableReference {
+            project_id: "p".to_owned(),
+            dataset_id: "d".to_owned(),
+            table_id: "t".to_owned(),
+        },
+        create_disposition: Some(CreateDisposition::CreateIfNeeded),
+        write_disposition: Some(WriteDisposition::WriteTruncate),
+        skip_leading_rows: Some(1),
+        allow_quoted_newlines: Some(true),
+        ignore_unknown_values: None,
+        allow_jagged_rows: None,
+        schema_update_options: vec![],
+        other: Map::new(),
+    };
+
+    let args = DriverArguments::from_cli_args(&[
+        "load_job.writeDisposition=WRITE_APPEND",
+        "load_job.maxBadRecords=10",
+        "load_job.fieldDelimiter=|",
+    ])
+    .unwrap()
+    .deserialize::<HashMap<String, Map<String, Value>>>()
+    .unwrap();
+    let options = LoadOptions::from_args(&args["load_job"], false);
+    let json = serde_json::to_value(options.apply(config()).unwrap()).unwrap();
+    assert_eq!(json["writeDisposition"], json!("WRITE_APPEND"));
+    assert_eq!(json["maxBadRecords"], json!(10));
+    assert_eq!(json["fieldDelimiter"], json!("|"));
+    assert_eq!(json["skipLeadingRows"], json!(1));
+
+    for arg in &[
+        "load_job.sourceUris[]=gs://x/y",
+        "load_job.writeDisposition=NOPE",
+    ] {
+        let args = DriverArguments::from_cli_args(&[arg])
+            .unwrap()
+            .deserialize::<HashMap<String, Map<String, Value>>>()
+            .unwrap();
+        let options = LoadOptions::from_args(&args["load_job"], false);
+        assert!(options.apply(config()).is_err());
+    }
+}
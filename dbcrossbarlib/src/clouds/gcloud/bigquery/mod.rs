@@ -6,13 +6,17 @@ use std::{error, fmt};
 use crate::common::*;
 use crate::drivers::bigquery_shared::{BqColumn, TableName};
 
+mod datasets;
 mod extract;
+mod insert_all;
 pub(crate) mod jobs;
 mod load;
 mod queries;
 mod schema;
 
+pub(crate) use datasets::*;
 pub(crate) use extract::*;
+pub(crate) use insert_all::*;
 pub(crate) use jobs::Labels;
 pub(crate) use load::*;
 pub(crate) use queries::*;
@@ -1,18 +1,19 @@
 //! Running queries against BigQuery.
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::convert::TryFrom;
+use std::{convert::TryFrom, io};
 
 use super::{
     super::client::{percent_encode, Client},
     jobs::{
-        run_job, CreateDisposition, Job, JobConfigurationQuery, Labels,
+        dry_run_job, run_job, CreateDisposition, Job, JobConfigurationQuery, Labels,
         TableReference, WriteDisposition,
     },
     TableSchema,
 };
 use crate::common::*;
 use crate::drivers::bigquery_shared::{BqColumn, TableName};
+use crate::tokio_glue::{bytes_channel, SendResultExt};
 
 /// Execute an SQL statement.
 pub(crate) async fn execute_sql(
@@ -24,13 +25,13 @@ pub(crate) async fn execute_sql(
     trace!(ctx.log(), "executing SQL: {}", sql);
     let config = JobConfigurationQuery::new(sql);
     let client = Client::new(ctx).await?;
-    run_job(
+    let job = run_job(
         ctx,
         &client,
         project,
         Job::new_query(config, labels.to_owned()),
-    )
-    .await?;
+    );
+    ctx.audit_sql(project, sql, job).await?;
     Ok(())
 }
 
@@ -53,14 +54,68 @@ pub(crate) async fn query_to_table(
 
     // Run our query.
     let client = Client::new(ctx).await?;
-    run_job(
+    let job = run_job(
+        ctx,
+        &client,
+        project,
+        Job::new_query(config, labels.to_owned()),
+    );
+    ctx.audit_sql(&dest_table.to_string(), sql, job).await?;
+    Ok(())
+}
+
+/// Look up the columns that `sql` would return, using a dry run so that we
+/// don't actually run the query.
+pub(crate) async fn query_schema(
+    ctx: &Context,
+    project: &str,
+    sql: &str,
+    labels: &Labels,
+) -> Result<Vec<BqColumn>> {
+    trace!(ctx.log(), "looking up schema of query: {}", sql);
+    let config = JobConfigurationQuery::new(sql);
+    let client = Client::new(ctx).await?;
+    let job = dry_run_job(
         ctx,
         &client,
         project,
         Job::new_query(config, labels.to_owned()),
     )
     .await?;
-    Ok(())
+    job.statistics
+        .and_then(|stats| stats.query)
+        .and_then(|query| query.schema)
+        .map(|schema| schema.fields)
+        .ok_or_else(|| format_err!("BigQuery did not report a schema for query"))
+}
+
+/// Look up how many bytes `sql` would scan, using a dry run so that we don't
+/// actually run the query. BigQuery's on-demand pricing is based on this.
+pub(crate) async fn query_bytes_processed(
+    ctx: &Context,
+    project: &str,
+    sql: &str,
+    labels: &Labels,
+) -> Result<u64> {
+    trace!(ctx.log(), "estimating bytes processed by query: {}", sql);
+    let config = JobConfigurationQuery::new(sql);
+    let client = Client::new(ctx).await?;
+    let job = dry_run_job(
+        ctx,
+        &client,
+        project,
+        Job::new_query(config, labels.to_owned()),
+    )
+    .await?;
+    let bytes = job
+        .statistics
+        .and_then(|stats| stats.total_bytes_processed)
+        .ok_or_else(|| {
+            format_err!("BigQuery did not report bytes processed for query")
+        })?;
+    Ok(bytes
+        .parse::<u64>()
+        .with_context(|_| format!("could not parse bytes processed {:?}", bytes))?)
 }
 
 /// Parameters used to look up information about a query.
@@ -73,6 +128,10 @@ pub(crate) async fn query_to_table(
 struct QueryResultsQuery {
     /// Geographic location. Mandatory outside of US and Europe.
     location: String,
+
+    /// Which page of results to return.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_token: Option<String>,
 }
 
 /// Results of a query.
@@ -83,13 +142,43 @@ struct QueryResults {
     schema: TableSchema,
 
     /// Rows returned from the query.
+    #[serde(default)]
     rows: Vec<Row>,
 
     /// Has this query completed?
     job_complete: bool,
+
+    /// A token for fetching the next page of results, if there is one.
+    #[serde(default)]
+    page_token: Option<String>,
 }
 
 impl QueryResults {
+    /// Convert this page of results to CSV.
+    fn to_csv(&self, include_headers: bool) -> Result<BytesMut> {
+        if !self.job_complete {
+            return Err(format_err!(
+                "expected query to have finished, but it hasn't",
+            ));
+        }
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        if include_headers {
+            wtr.write_record(
+                self.schema
+                    .fields
+                    .iter()
+                    .map(|col| col.name.to_portable_name()),
+            )?;
+        }
+        for row in &self.rows {
+            row.write_csv(&mut wtr)?;
+        }
+        let bytes = wtr
+            .into_inner()
+            .map_err(|err| format_err!("could not write CSV: {}", err))?;
+        Ok(BytesMut::from(&bytes[..]))
+    }
+
     fn to_json_objects(&self, ctx: &Context) -> Result<Vec<serde_json::Value>> {
         let objects = self
             .rows
@@ -136,6 +225,25 @@ impl Row {
         }
         Ok(serde_json::Value::Object(obj))
     }
+
+    /// Write this row as CSV. We expect every value to be either a string or
+    /// NULL, which we write as an empty cell.
+    fn write_csv<W: io::Write>(&self, wtr: &mut csv::Writer<W>) -> Result<()> {
+        for value in &self.fields {
+            match &value.value {
+                serde_json::Value::Null => wtr.write_field("")?,
+                serde_json::Value::String(s) => wtr.write_field(s)?,
+                other => {
+                    return Err(format_err!(
+                        "cannot convert query results to CSV: {}",
+                        other,
+                    ))
+                }
+            }
+        }
+        wtr.write_record(None::<&[u8]>)?;
+        Ok(())
+    }
 }
 
 /// A value returned in query results.
@@ -185,6 +293,7 @@ async fn query_all_json(
     );
     let query = QueryResultsQuery {
         location: reference.location.clone(),
+        page_token: None,
     };
     let results = client.get::<QueryResults, _, _>(ctx, &url, query).await?;
     if results.job_complete {
@@ -196,6 +305,76 @@ async fn query_all_json(
     }
 }
 
+/// Run a query, and return its results as CSV, fetching them one page at a
+/// time. This doesn't need any temporary storage, but it's slower than
+/// extracting data to Google Cloud Storage.
+///
+/// BigQuery returns scalar values as strings, which we copy as-is. Nested
+/// values like `ARRAY` and `STRUCT` must be converted to JSON strings first,
+/// as our export SQL does.
+pub(crate) async fn query_to_csv(
+    ctx: &Context,
+    project: &str,
+    sql: &str,
+    labels: &Labels,
+) -> Result<BoxStream<BytesMut>> {
+    trace!(ctx.log(), "executing SQL: {}", sql);
+
+    // Run our query.
+    let config = JobConfigurationQuery::new(sql);
+    let client = Client::new(ctx).await?;
+    let job = run_job(
+        ctx,
+        &client,
+        project,
+        Job::new_query(config, labels.to_owned()),
+    )
+    .await?;
+    let reference = job.reference()?;
+    let url = format!(
+        "https://bigquery.googleapis.com/bigquery/v2/projects/{}/queries/{}",
+        percent_encode(project),
+        percent_encode(&reference.job_id),
+    );
+    let location = reference.location.clone();
+
+    // Fetch pages of results in the background.
+    let worker_ctx = ctx.clone();
+    let (mut sender, receiver) = bytes_channel(1);
+    let worker = async move {
+        let mut page_token = None;
+        let mut include_headers = true;
+        loop {
+            worker_ctx.check_cancelled()?;
+            let query = QueryResultsQuery {
+                location: location.clone(),
+                page_token: page_token.take(),
+            };
+            let csv = client
+                .get::<QueryResults, _, _>(&worker_ctx, &url, query)
+                .await
+                .and_then(|results| {
+                    let csv = results.to_csv(include_headers)?;
+                    page_token = results.page_token;
+                    Ok(csv)
+                });
+            match csv {
+                Ok(csv) => sender.send(Ok(csv)).await.map_send_err()?,
+                Err(err) => {
+                    sender.send(Err(err)).await.map_send_err()?;
+                    return Ok(());
+                }
+            }
+            include_headers = false;
+            if page_token.is_none() {
+                return Ok::<_, Error>(());
+            }
+        }
+    };
+    ctx.spawn_worker(worker.boxed());
+    Ok(receiver.boxed())
+}
+
 /// Run a query that should return a small number of records, and deserialize them.
 pub(crate) async fn query_all<T>(
     ctx: &Context,
@@ -232,3 +411,27 @@ where
         Err(format_err!("expected 1 row, found {}", rows.len()))
     }
 }
+
+#[test]
+fn converts_query_results_to_csv() {
+    let results = serde_json::from_str::<QueryResults>(
+        r#"{
+            "schema": {"fields": [
+                {"name": "id", "type": "INT64"},
+                {"name": "tags", "type": "STRING"}
+            ]},
+            "rows": [
+                {"f": [{"v": "1"}, {"v": "[\"a\",\"b\"]"}]},
+                {"f": [{"v": "2"}, {"v": null}]}
+            ],
+            "jobComplete": true,
+            "pageToken": "next"
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(results.page_token.as_deref(), Some("next"));
+    let csv = results.to_csv(true).unwrap();
+    assert_eq!(&csv[..], &b"id,tags\n1,\"[\"\"a\"\",\"\"b\"\"]\"\n2,\n"[..]);
+    let csv = results.to_csv(false).unwrap();
+    assert!(csv.starts_with(b"1,"));
+}
@@ -1,9 +1,11 @@
 //! Support for looking up BigQuery schemas.
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::{
     super::{percent_encode, Client, NoQuery},
+    is_not_found,
+    jobs::TableReference,
     TableSchema,
 };
 use crate::common::*;
@@ -14,11 +16,122 @@ use crate::drivers::bigquery_shared::{BqTable, TableName};
 #[serde(rename_all = "camelCase")]
 struct Table {
     schema: TableSchema,
+
+    /// What kind of table is this?
+    #[serde(rename = "type")]
+    table_type: Option<TableType>,
+
+    /// The number of rows in this table. Not reported for views.
+    num_rows: Option<String>,
+
+    /// The size of this table in bytes. Not reported for views.
+    num_bytes: Option<String>,
+}
+
+/// BigQuery's statistics about the size of a table.
+#[derive(Debug, Default)]
+pub(crate) struct TableSize {
+    /// The number of rows, if known.
+    pub(crate) rows: Option<u64>,
+    /// The number of bytes, if known.
+    pub(crate) bytes: Option<u64>,
+}
+
+/// The kinds of tables supported by BigQuery.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum TableType {
+    /// A regular table.
+    Table,
+    /// A view defined by an SQL query.
+    View,
+    /// A view whose results are precomputed.
+    MaterializedView,
+    /// A table stored outside of BigQuery.
+    External,
+    /// A read-only copy of a table.
+    Snapshot,
+    /// A type that we don't know about.
+    #[serde(other)]
+    Other,
+}
+
+impl TableType {
+    /// Can we run an extract job directly against this table? BigQuery only
+    /// allows extracting regular tables, so everything else needs to be
+    /// queried into a temporary table first.
+    pub(crate) fn can_extract(self) -> bool {
+        self == TableType::Table
+    }
 }
 
 /// Look up the schema of the specified table.
+///
+/// For wildcard tables like `dataset.events_*`, we use the schema of the last
+/// matching table in alphabetical order, which will be the newest table in a
+/// set of date-sharded tables. BigQuery itself uses the schema of the most
+/// recently created table, which is normally the same thing.
 pub(crate) async fn schema(ctx: &Context, name: &TableName) -> Result<BqTable> {
     trace!(ctx.log(), "fetching schema for {:?}", name);
+    let table = if name.is_wildcard() {
+        let last_match = last_matching_table(ctx, name).await?;
+        debug!(ctx.log(), "using schema of {} for {}", last_match, name);
+        get_table(ctx, &last_match).await?
+    } else {
+        get_table(ctx, name).await?
+    };
+    Ok(BqTable {
+        name: name.to_owned(),
+        columns: table.schema.fields,
+    })
+}
+
+/// Look up the schema of the specified table, returning `None` if it doesn't
+/// exist. Wildcard tables are not supported.
+pub(crate) async fn schema_if_exists(
+    ctx: &Context,
+    name: &TableName,
+) -> Result<Option<BqTable>> {
+    trace!(ctx.log(), "fetching schema for {:?} if it exists", name);
+    match get_table(ctx, name).await {
+        Ok(table) => Ok(Some(BqTable {
+            name: name.to_owned(),
+            columns: table.schema.fields,
+        })),
+        Err(err) if is_not_found(&err) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Look up what kind of table `name` is.
+pub(crate) async fn table_type(ctx: &Context, name: &TableName) -> Result<TableType> {
+    trace!(ctx.log(), "fetching table type for {:?}", name);
+    let table = get_table(ctx, name).await?;
+    Ok(table.table_type.unwrap_or(TableType::Other))
+}
+
+/// Look up BigQuery's statistics about the size of `name`. These are not
+/// available for views.
+pub(crate) async fn table_size(ctx: &Context, name: &TableName) -> Result<TableSize> {
+    trace!(ctx.log(), "fetching table size for {:?}", name);
+    let table = get_table(ctx, name).await?;
+    let parse = |value: Option<String>| -> Result<Option<u64>> {
+        value
+            .map(|v| {
+                v.parse::<u64>()
+                    .with_context(|_| format!("could not parse size {:?}", v))
+            })
+            .transpose()
+            .map_err(Error::from)
+    };
+    Ok(TableSize {
+        rows: parse(table.num_rows)?,
+        bytes: parse(table.num_bytes)?,
+    })
+}
+
+/// Fetch the metadata for the specified table.
+async fn get_table(ctx: &Context, name: &TableName) -> Result<Table> {
 
     // Build our URL.
     let url = format!(
@@ -30,9 +143,79 @@ pub(crate) async fn schema(ctx: &Context, name: &TableName) -> Result<BqTable> {
 
     // Look up our schema.
     let client = Client::new(ctx).await?;
-    let table = client.get::<Table, _, _>(ctx, &url, NoQuery).await?;
-    Ok(BqTable {
-        name: name.to_owned(),
-        columns: table.schema.fields,
-    })
+    client.get::<Table, _, _>(ctx, &url, NoQuery).await
+}
+
+/// URL query parameters for listing tables.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListTablesQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_token: Option<String>,
+}
+
+/// A page of tables in a dataset.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TableList {
+    /// The tables on this page. This may be missing if there are none.
+    #[serde(default)]
+    tables: Vec<TableListItem>,
+
+    /// A token which can be used to fetch the next page.
+    next_page_token: Option<String>,
+}
+
+/// A table in a `TableList`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TableListItem {
+    /// The name of this table.
+    table_reference: TableReference,
+}
+
+/// Find the last table in alphabetical order matching the wildcard table
+/// `name`.
+async fn last_matching_table(ctx: &Context, name: &TableName) -> Result<TableName> {
+    let url = format!(
+        "https://bigquery.googleapis.com/bigquery/v2/projects/{}/datasets/{}/tables",
+        percent_encode(name.project()),
+        percent_encode(name.dataset()),
+    );
+    let client = Client::new(ctx).await?;
+    let prefix = name.table_prefix();
+    let mut last_match: Option<String> = None;
+    let mut page_token = None;
+    loop {
+        ctx.check_cancelled()?;
+        let query = ListTablesQuery {
+            page_token: page_token.take(),
+        };
+        let list = client.get::<TableList, _, _>(ctx, &url, query).await?;
+        for item in list.tables {
+            let table_id = item.table_reference.table_id;
+            if table_id.starts_with(prefix)
+                && last_match.as_ref().is_none_or(|last| &table_id > last)
+            {
+                last_match = Some(table_id);
+            }
+        }
+        page_token = list.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+    let last_match = last_match
+        .ok_or_else(|| format_err!("no BigQuery tables match {}", name))?;
+    Ok(name.sibling(&last_match))
+}
+
+#[test]
+fn parses_table_types() {
+    let parse = |json: &str| serde_json::from_str::<TableType>(json).unwrap();
+    assert_eq!(parse(r#""TABLE""#), TableType::Table);
+    assert_eq!(parse(r#""MATERIALIZED_VIEW""#), TableType::MaterializedView);
+    assert_eq!(parse(r#""SOMETHING_NEW""#), TableType::Other);
+    assert!(TableType::Table.can_extract());
+    assert!(!TableType::View.can_extract());
 }
@@ -7,19 +7,21 @@ use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use reqwest::{
     self,
     header::{HeaderMap, CONTENT_TYPE},
-    IntoUrl,
+    IntoUrl, StatusCode,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{error, fmt, time::Duration};
 
 use super::auth::{authenticator, AccessToken, Authenticator};
+use super::recording::Recording;
+use crate::clouds::http_client::reqwest_client;
 use crate::common::*;
 use crate::tokio_glue::IdiomaticBytesStream;
 
 /// The OAuth2 scopes that we'll need.
 ///
 /// TODO: For pure storage operations, consider having a storage-only scope.
-static SCOPES: &[&str] = &[
+pub(crate) static SCOPES: &[&str] = &[
     "https://www.googleapis.com/auth/devstorage.read_write",
     "https://www.googleapis.com/auth/bigquery",
 ];
@@ -43,24 +45,68 @@ pub(crate) enum Alt {
 
 /// A Google Cloud REST client using OAuth2.
 pub(crate) struct Client {
-    /// An authenticator that provides OAuth2 tokens.
-    authenticator: Authenticator,
+    /// An authenticator that provides OAuth2 tokens. This will be `None` if
+    /// we're replaying recorded interactions.
+    authenticator: Option<Authenticator>,
 
     /// Our HTTP client.
     client: reqwest::Client,
+
+    /// Recorded HTTP interactions, if we're recording or replaying.
+    recording: Option<&'static Recording>,
 }
 
 impl Client {
     /// Create a new Google Cloud client.
     pub(crate) async fn new(ctx: &Context) -> Result<Client> {
-        let authenticator = authenticator(ctx).await?;
-        let client = reqwest::Client::new();
+        let recording = Recording::singleton()?;
+        let authenticator = match recording {
+            Some(recording) if recording.is_replay() => None,
+            _ => Some(authenticator(ctx).await?),
+        };
+        let client = reqwest_client()?;
         Ok(Client {
             authenticator,
             client,
+            recording,
         })
     }
 
+    /// Look up a recorded response to a request, if we're replaying.
+    fn replay_response(
+        &self,
+        ctx: &Context,
+        method: &str,
+        url: &Url,
+        request_body: Option<&serde_json::Value>,
+    ) -> Result<Option<reqwest::Response>> {
+        match self.recording {
+            Some(recording) => {
+                recording.replay_response(ctx, method, url, request_body)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Record a response to a request, if we're recording.
+    async fn record_response(
+        &self,
+        ctx: &Context,
+        method: &str,
+        url: &Url,
+        request_body: Option<serde_json::Value>,
+        http_resp: reqwest::Response,
+    ) -> Result<reqwest::Response> {
+        match self.recording {
+            Some(recording) => {
+                recording
+                    .record_response(ctx, method, url, request_body, http_resp)
+                    .await
+            }
+            None => Ok(http_resp),
+        }
+    }
+
     /// Make an HTTP GET request and return the response.
     async fn get_helper(
         &self,
@@ -69,6 +115,9 @@ impl Client {
         headers: HeaderMap,
     ) -> Result<reqwest::Response> {
         trace!(ctx.log(), "GET {}", url);
+        if let Some(http_resp) = self.replay_response(ctx, "GET", url, None)? {
+            return Ok(http_resp);
+        }
         let token = self.token().await?;
         let wait_options = WaitOptions::default()
             .backoff_type(BackoffType::Exponential)
@@ -76,7 +125,7 @@ impl Client {
             // Don't retry too much because we're probably classifying some
             // permanent errors as temporary.
             .allowed_errors(3);
-        wait(&wait_options, move || {
+        let http_resp = wait(&wait_options, move || {
             let token = token.clone();
             let headers = headers.clone();
             async move {
@@ -98,25 +147,32 @@ impl Client {
                         let err: Error = err.into();
                         let err = err.context(format!("could not GET {}", url));
                         if temporary {
-                            WaitStatus::FailedTemporarily(err.into())
+                            let err: Error = err.into();
+                            ctx.retrying(&err);
+                            WaitStatus::FailedTemporarily(err)
                         } else {
                             WaitStatus::FailedPermanently(err.into())
                         }
                     }
-                    // We talked to the server and it returned a server-side
-                    // error (50-599). There's a chance that things might work
-                    // next time, we hope.
-                    Ok(resp) if resp.status().is_server_error() => {
-                        WaitStatus::FailedTemporarily(
-                            self.handle_error(ctx, "GET", url, resp).await,
-                        )
+                    // We talked to the server and it returned an error which
+                    // might go away if we wait, like a rate limit or a
+                    // server-side error (500-599).
+                    Ok(resp) if might_be_retryable(resp.status()) => {
+                        let err = self.handle_error(ctx, "GET", url, resp).await;
+                        if is_retryable(&err) {
+                            ctx.retrying(&err);
+                            WaitStatus::FailedTemporarily(err)
+                        } else {
+                            WaitStatus::FailedPermanently(err)
+                        }
                     }
                     Ok(resp) => WaitStatus::Finished(resp),
                 }
             }
             .boxed()
         })
-        .await
+        .await?;
+        self.record_response(ctx, "GET", url, None, http_resp).await
     }
 
     /// Make an HTTP GET request with the specified URL and query parameters,
@@ -132,7 +188,7 @@ impl Client {
         U: IntoUrl,
         Query: fmt::Debug + Serialize,
     {
-        let url = build_url(url, query)?;
+        let url = build_url(ctx, url, query)?;
         let headers = HeaderMap::default();
         let http_resp = self.get_helper(ctx, &url, headers).await?;
         self.handle_response(ctx, "GET", &url, http_resp).await
@@ -151,7 +207,7 @@ impl Client {
         U: IntoUrl,
         Query: fmt::Debug + Serialize,
     {
-        let url = build_url(url, query)?;
+        let url = build_url(ctx, url, query)?;
         let http_resp = self.get_helper(ctx, &url, headers).await?;
         if http_resp.status().is_success() {
             Ok(http_resp)
@@ -174,9 +230,15 @@ impl Client {
         Query: fmt::Debug + Serialize,
         Body: fmt::Debug + Serialize,
     {
-        let url = build_url(url, query)?;
+        let url = build_url(ctx, url, query)?;
         trace!(ctx.log(), "POST {} {:?}", url, body);
         trace!(ctx.log(), "serialied {}", serde_json::to_string(&body)?);
+        let body_value = serde_json::to_value(&body)?;
+        if let Some(http_resp) =
+            self.replay_response(ctx, "POST", &url, Some(&body_value))?
+        {
+            return self.handle_response(ctx, "POST", &url, http_resp).await;
+        }
         let token = self.token().await?;
         let http_resp = self
             .client
@@ -186,34 +248,156 @@ impl Client {
             .send()
             .await
             .with_context(|_| format!("could not POST {}", url))?;
+        let http_resp = self
+            .record_response(ctx, "POST", &url, Some(body_value), http_resp)
+            .await?;
         self.handle_response(ctx, "POST", &url, http_resp).await
     }
 
-    /// Post a stream of data to the specified URL.
-    pub(crate) async fn post_stream<U, Query>(
+    /// Make an HTTP POST request containing both a JSON `body` and `media`
+    /// data, using a `multipart/related` upload.
+    ///
+    /// The entire upload is held in memory, so this should only be used for
+    /// small amounts of data.
+    pub(crate) async fn post_multipart<Output, U, Query, Body>(
         &self,
-        // Pass `ctx` by value, not reference, because of a weird async lifetime error.
-        ctx: Context,
+        ctx: &Context,
         url: U,
         query: Query,
-        stream: IdiomaticBytesStream,
-    ) -> Result<()>
+        body: Body,
+        media_type: &str,
+        media: Vec<u8>,
+    ) -> Result<Output>
     where
+        Output: fmt::Debug + DeserializeOwned,
         U: IntoUrl,
         Query: fmt::Debug + Serialize,
+        Body: fmt::Debug + Serialize,
     {
-        let url = build_url(url, query)?;
-        trace!(ctx.log(), "POST {} with stream", url);
-        let body = reqwest::Body::wrap_stream(stream);
+        let url = build_url(ctx, url, query)?;
+        trace!(
+            ctx.log(),
+            "POST {} {:?} with {} bytes",
+            url,
+            body,
+            media.len()
+        );
+        let body_value = serde_json::to_value(&body)?;
+        if let Some(http_resp) =
+            self.replay_response(ctx, "POST", &url, Some(&body_value))?
+        {
+            return self.handle_response(ctx, "POST", &url, http_resp).await;
+        }
+
+        // Build our upload. We choose a boundary that's very unlikely to
+        // appear in our media.
+        let boundary = format!("dbcrossbar_{}", TemporaryStorage::random_tag());
+        let mut upload = format!(
+            "--{boundary}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{json}\r\n--{boundary}\r\nContent-Type: {media_type}\r\n\r\n",
+            boundary = boundary,
+            json = serde_json::to_string(&body)?,
+            media_type = media_type,
+        )
+        .into_bytes();
+        upload.extend_from_slice(&media);
+        upload.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
         let token = self.token().await?;
         let http_resp = self
             .client
             .post(url.as_str())
             .bearer_auth(token.as_str())
-            .body(body)
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/related; boundary={}", boundary),
+            )
+            .body(upload)
             .send()
             .await
             .with_context(|_| format!("could not POST {}", url))?;
+        let http_resp = self
+            .record_response(ctx, "POST", &url, Some(body_value), http_resp)
+            .await?;
+        self.handle_response(ctx, "POST", &url, http_resp).await
+    }
+
+    /// Patch the resource at the specified URL, returning the updated
+    /// resource.
+    pub(crate) async fn patch<Output, U, Query, Body>(
+        &self,
+        ctx: &Context,
+        url: U,
+        query: Query,
+        body: Body,
+    ) -> Result<Output>
+    where
+        Output: fmt::Debug + DeserializeOwned,
+        U: IntoUrl,
+        Query: fmt::Debug + Serialize,
+        Body: fmt::Debug + Serialize,
+    {
+        let url = build_url(ctx, url, query)?;
+        trace!(ctx.log(), "PATCH {} {:?}", url, body);
+        let body_value = serde_json::to_value(&body)?;
+        if let Some(http_resp) =
+            self.replay_response(ctx, "PATCH", &url, Some(&body_value))?
+        {
+            return self.handle_response(ctx, "PATCH", &url, http_resp).await;
+        }
+        let token = self.token().await?;
+        let http_resp = self
+            .client
+            .patch(url.as_str())
+            .bearer_auth(token.as_str())
+            .json(&body)
+            .send()
+            .await
+            .with_context(|_| format!("could not PATCH {}", url))?;
+        let http_resp = self
+            .record_response(ctx, "PATCH", &url, Some(body_value), http_resp)
+            .await?;
+        self.handle_response(ctx, "PATCH", &url, http_resp).await
+    }
+
+    /// Post a stream of data to the specified URL.
+    pub(crate) async fn post_stream<U, Query>(
+        &self,
+        // Pass `ctx` by value, not reference, because of a weird async lifetime error.
+        ctx: Context,
+        url: U,
+        query: Query,
+        stream: IdiomaticBytesStream,
+    ) -> Result<()>
+    where
+        U: IntoUrl,
+        Query: fmt::Debug + Serialize,
+    {
+        let url = build_url(&ctx, url, query)?;
+        trace!(ctx.log(), "POST {} with stream", url);
+        let http_resp = match self.replay_response(&ctx, "POST", &url, None)? {
+            Some(http_resp) => {
+                // Consume our stream, just like a real upload would.
+                stream
+                    .try_for_each(|_| async { Ok(()) })
+                    .await
+                    .map_err(|err| format_err!("could not POST {}: {}", url, err))?;
+                http_resp
+            }
+            None => {
+                let body = reqwest::Body::wrap_stream(stream);
+                let token = self.token().await?;
+                let http_resp = self
+                    .client
+                    .post(url.as_str())
+                    .bearer_auth(token.as_str())
+                    .body(body)
+                    .send()
+                    .await
+                    .with_context(|_| format!("could not POST {}", url))?;
+                self.record_response(&ctx, "POST", &url, None, http_resp)
+                    .await?
+            }
+        };
         if http_resp.status().is_success() {
             Ok(())
         } else {
@@ -232,16 +416,23 @@ impl Client {
         U: IntoUrl,
         Query: fmt::Debug + Serialize,
     {
-        let url = build_url(url, query)?;
+        let url = build_url(ctx, url, query)?;
         trace!(ctx.log(), "DELETE {}", url);
-        let token = self.token().await?;
-        let http_resp = self
-            .client
-            .delete(url.as_str())
-            .bearer_auth(token.as_str())
-            .send()
-            .await
-            .with_context(|_| format!("error deleting {}", url))?;
+        let http_resp = match self.replay_response(ctx, "DELETE", &url, None)? {
+            Some(http_resp) => http_resp,
+            None => {
+                let token = self.token().await?;
+                let http_resp = self
+                    .client
+                    .delete(url.as_str())
+                    .bearer_auth(token.as_str())
+                    .send()
+                    .await
+                    .with_context(|_| format!("error deleting {}", url))?;
+                self.record_response(ctx, "DELETE", &url, None, http_resp)
+                    .await?
+            }
+        };
         if http_resp.status().is_success() {
             Ok(())
         } else {
@@ -255,8 +446,10 @@ impl Client {
 
     /// Get an access token.
     async fn token(&self) -> Result<AccessToken> {
-        Ok(self
-            .authenticator
+        let authenticator = self.authenticator.as_ref().ok_or_else(|| {
+            format_err!("cannot authenticate while replaying recorded requests")
+        })?;
+        Ok(authenticator
             .token(SCOPES)
             .await
             .context("could not get Google Cloud OAuth2 token")?)
@@ -295,6 +488,7 @@ impl Client {
         // Decide if we should even try to parse this response as JSON before we
         // consume our http_resp.
         let should_parse_as_json = response_claims_to_be_json(ctx, &http_resp);
+        let status = http_resp.status();
 
         // Fetch the error body.
         let err_body_result = http_resp
@@ -317,7 +511,8 @@ impl Client {
 
         // We've run afoul of
         // https://github.com/googleapis/google-cloud-ruby/issues/5180 or
-        // something equally terrible, so just report whatever we have.
+        // something equally terrible, so just report whatever we have. We
+        // still return a `GCloudError` so that we can classify it by status.
         let raw_err = String::from_utf8_lossy(&err_body);
         trace!(
             ctx.log(),
@@ -326,14 +521,20 @@ impl Client {
             url,
             raw_err,
         );
-        let err = format_err!("expected JSON describing error, but got {:?}", raw_err);
+        let err: Error = GCloudError {
+            code: i32::from(status.as_u16()),
+            message: format!("expected JSON describing error, but got {:?}", raw_err),
+            errors: vec![],
+        }
+        .into();
         err.context(format!("{} error {}", method, url)).into()
     }
 }
 
 /// Construct a URL from something we can convert to URL, and something that we
-/// can serialize as a query string.
-fn build_url<U, Query>(url: U, query: Query) -> Result<Url>
+/// can serialize as a query string. This also applies any endpoint overrides in
+/// `ctx`.
+fn build_url<U, Query>(ctx: &Context, url: U, query: Query) -> Result<Url>
 where
     U: IntoUrl,
     Query: fmt::Debug + Serialize,
@@ -343,6 +544,7 @@ where
     if !query_str.is_empty() {
         url.set_query(Some(&query_str));
     }
+    ctx.endpoints().rewrite_gcloud_url(&mut url)?;
     Ok(url)
 }
 
@@ -369,15 +571,114 @@ impl fmt::Display for GCloudError {
 
 impl error::Error for GCloudError {}
 
+impl GCloudError {
+    /// Classify this error using the `reason` of each detail, falling back to
+    /// the HTTP status code.
+    pub(crate) fn kind(&self) -> GCloudErrorKind {
+        self.errors
+            .iter()
+            .find_map(|detail| GCloudErrorKind::from_reason(&detail.reason))
+            .unwrap_or_else(|| GCloudErrorKind::from_code(self.code))
+    }
+}
+
+/// The kinds of Google Cloud errors that we handle differently.
+///
+/// See the [BigQuery][bq] and [Cloud Storage][gcs] error references.
+///
+/// [bq]: https://cloud.google.com/bigquery/docs/error-messages
+/// [gcs]: https://cloud.google.com/storage/docs/json_api/v1/status-codes
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum GCloudErrorKind {
+    /// We're making too many requests, and we should slow down.
+    RateLimited,
+    /// The server had a problem, which may go away.
+    ServerError,
+    /// The resource does not exist.
+    NotFound,
+    /// A precondition, like `ifGenerationMatch`, was not met.
+    PreconditionFailed,
+    /// We're not allowed to do this.
+    PermissionDenied,
+    /// We've used up a quota, which won't reset soon enough to retry.
+    QuotaExceeded,
+    /// Any other error.
+    Other,
+}
+
+impl GCloudErrorKind {
+    /// Classify an error `reason` from an error detail, if we recognize it.
+    fn from_reason(reason: &str) -> Option<GCloudErrorKind> {
+        match reason {
+            "rateLimitExceeded" | "userRateLimitExceeded" | "tooManyRequests" => {
+                Some(GCloudErrorKind::RateLimited)
+            }
+            "backendError" | "internalError" => Some(GCloudErrorKind::ServerError),
+            "notFound" => Some(GCloudErrorKind::NotFound),
+            "conditionNotMet" => Some(GCloudErrorKind::PreconditionFailed),
+            "accessDenied" | "forbidden" | "insufficientPermissions" => {
+                Some(GCloudErrorKind::PermissionDenied)
+            }
+            "quotaExceeded" | "dailyLimitExceeded" => {
+                Some(GCloudErrorKind::QuotaExceeded)
+            }
+            _ => None,
+        }
+    }
+
+    /// Classify an error using only its HTTP status code.
+    fn from_code(code: i32) -> GCloudErrorKind {
+        match code {
+            429 => GCloudErrorKind::RateLimited,
+            500..=599 => GCloudErrorKind::ServerError,
+            404 => GCloudErrorKind::NotFound,
+            412 => GCloudErrorKind::PreconditionFailed,
+            401 | 403 => GCloudErrorKind::PermissionDenied,
+            _ => GCloudErrorKind::Other,
+        }
+    }
+
+    /// Might this error go away if we wait and try again?
+    pub(crate) fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            GCloudErrorKind::RateLimited | GCloudErrorKind::ServerError
+        )
+    }
+}
+
+/// If `err` was caused by a Google Cloud API error, return its kind.
+pub(crate) fn gcloud_error_kind(err: &Error) -> Option<GCloudErrorKind> {
+    err.iter_chain().find_map(|cause| {
+        cause
+            .downcast_ref::<GCloudError>()
+            .map(|gcloud_err| gcloud_err.kind())
+    })
+}
+
+/// Is `err` a Google Cloud error that might go away if we try again?
+pub(crate) fn is_retryable(err: &Error) -> bool {
+    gcloud_error_kind(err)
+        .map(|kind| kind.is_retryable())
+        .unwrap_or(false)
+}
+
+/// Could a response with `status` be a retryable error? Rate limits are
+/// sometimes reported as `403 Forbidden`, so we need to check the body to be
+/// sure.
+fn might_be_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::FORBIDDEN
+}
+
 /// Details about an individial GCloud error.
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ErrorDetail {
-    pub(crate) domain: String,
+    /// A short code like `"rateLimitExceeded"`, which we use to classify
+    /// errors.
     pub(crate) reason: String,
-    pub(crate) message: String,
-    pub(crate) location_type: Option<String>,
-    pub(crate) location: Option<String>,
 }
 
 /// Percent-encode a string for use as a URL path component.
@@ -417,3 +718,44 @@ pub(crate) fn response_claims_to_be_json(
     content_type_mime.type_() == mime::APPLICATION
         && content_type_mime.subtype() == mime::JSON
 }
+
+#[test]
+fn gcloud_errors_are_classified_by_reason_then_code() {
+    let error = |code: i32, reason: Option<&str>| GCloudError {
+        code,
+        message: "oops".to_owned(),
+        errors: reason
+            .into_iter()
+            .map(|reason| ErrorDetail {
+                reason: reason.to_owned(),
+            })
+            .collect(),
+    };
+
+    // BigQuery reports both rate limits and quotas as 403 errors.
+    let rate_limited = error(403, Some("rateLimitExceeded"));
+    assert_eq!(rate_limited.kind(), GCloudErrorKind::RateLimited);
+    let quota = error(403, Some("quotaExceeded"));
+    assert_eq!(quota.kind(), GCloudErrorKind::QuotaExceeded);
+    assert_eq!(
+        error(403, Some("accessDenied")).kind(),
+        GCloudErrorKind::PermissionDenied,
+    );
+    assert_eq!(error(403, None).kind(), GCloudErrorKind::PermissionDenied);
+    assert_eq!(
+        error(404, Some("notFound")).kind(),
+        GCloudErrorKind::NotFound
+    );
+    assert_eq!(error(412, None).kind(), GCloudErrorKind::PreconditionFailed);
+    assert_eq!(error(503, None).kind(), GCloudErrorKind::ServerError);
+    assert_eq!(error(400, Some("invalid")).kind(), GCloudErrorKind::Other);
+
+    // We can find errors anywhere in a chain of causes.
+    let err: Error = rate_limited.into();
+    let err: Error = err.context("GET error https://example.com/").into();
+    assert_eq!(gcloud_error_kind(&err), Some(GCloudErrorKind::RateLimited));
+    assert!(is_retryable(&err));
+    let err: Error = quota.into();
+    assert!(!is_retryable(&err));
+    assert!(!is_retryable(&format_err!("not a Google Cloud error")));
+}
@@ -0,0 +1,314 @@
+//! Recording and replaying Google Cloud HTTP interactions.
+//!
+//! If `DBCROSSBAR_GCLOUD_RECORD` is set to a file name, we append each request
+//! and response made by our Google Cloud [`Client`](super::Client) to that file,
+//! one JSON object per line. If `DBCROSSBAR_GCLOUD_REPLAY` is set instead, we
+//! never touch the network or ask for credentials. Instead, we answer each
+//! request using a matching interaction from the file.
+//!
+//! This allows running BigQuery and GCS tests without cloud credentials, and it
+//! allows users to capture failing interactions for bug reports. We never
+//! record OAuth2 tokens, but URLs and request bodies will include the names of
+//! projects, buckets and tables.
+
+use lazy_static::lazy_static;
+use reqwest::{header::CONTENT_TYPE, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::common::*;
+
+/// The environment variable specifying where to record interactions.
+const RECORD_VAR: &str = "DBCROSSBAR_GCLOUD_RECORD";
+
+/// The environment variable specifying where to replay interactions from.
+const REPLAY_VAR: &str = "DBCROSSBAR_GCLOUD_REPLAY";
+
+lazy_static! {
+    /// Our global recording, if any. We store errors as strings, because
+    /// `Error` can't be shared between threads.
+    static ref RECORDING: Result<Option<Recording>, String> =
+        Recording::from_env().map_err(|err| format!("{}", err));
+}
+
+/// A counter used to generate deterministic temporary names.
+static NEXT_TAG: AtomicUsize = AtomicUsize::new(0);
+
+/// A single recorded HTTP request and response.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Interaction {
+    /// The HTTP method.
+    pub(crate) method: String,
+
+    /// The URL, including any query parameters.
+    pub(crate) url: String,
+
+    /// The JSON body of our request, if any. Streamed request bodies are not
+    /// recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) request_body: Option<Value>,
+
+    /// The HTTP status code of the response.
+    pub(crate) status: u16,
+
+    /// The `Content-Type` of the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) content_type: Option<String>,
+
+    /// The body of the response.
+    #[serde(default)]
+    pub(crate) response_body: String,
+}
+
+impl Interaction {
+    /// Does this interaction match the specified request?
+    fn matches(&self, method: &str, url: &Url, request_body: Option<&Value>) -> bool {
+        self.method == method
+            && self.url == url.as_str()
+            && self.request_body.as_ref() == request_body
+    }
+
+    /// Convert this interaction into an HTTP response.
+    fn to_response(&self) -> Result<reqwest::Response> {
+        let status = StatusCode::from_u16(self.status)
+            .with_context(|_| format!("bad recorded HTTP status {}", self.status))?;
+        let mut builder = hyper::Response::builder().status(status);
+        if let Some(content_type) = &self.content_type {
+            builder = builder.header(CONTENT_TYPE, content_type.as_str());
+        }
+        let resp = builder
+            .body(self.response_body.clone().into_bytes())
+            .context("could not build recorded HTTP response")?;
+        Ok(reqwest::Response::from(resp))
+    }
+}
+
+/// Recorded HTTP interactions.
+pub(crate) enum Recording {
+    /// Append new interactions to a file.
+    Record {
+        /// The file we're recording to.
+        path: PathBuf,
+        /// An open handle to our file.
+        file: Mutex<File>,
+    },
+    /// Answer requests using previously recorded interactions.
+    Replay {
+        /// The file we're replaying from.
+        path: PathBuf,
+        /// Our recorded interactions. We remove each interaction when it's
+        /// used, so that repeated requests (like polling a job) get the
+        /// responses in the order they were recorded.
+        interactions: Mutex<Vec<Option<Interaction>>>,
+    },
+}
+
+impl Recording {
+    /// Get our global recording, if any.
+    pub(crate) fn singleton() -> Result<Option<&'static Recording>> {
+        match &*RECORDING {
+            Ok(recording) => Ok(recording.as_ref()),
+            Err(err) => Err(format_err!("{}", err)),
+        }
+    }
+
+    /// Are we recording or replaying interactions?
+    pub(crate) fn is_active() -> bool {
+        matches!(&*RECORDING, Ok(Some(_)))
+    }
+
+    /// Generate a deterministic tag for use in temporary names, if we're
+    /// recording or replaying interactions. This allows the URLs of temporary
+    /// tables and directories to match between runs.
+    pub(crate) fn deterministic_tag() -> Option<String> {
+        if Recording::is_active() {
+            Some(format!("rec{:07}", NEXT_TAG.fetch_add(1, Ordering::SeqCst)))
+        } else {
+            None
+        }
+    }
+
+    /// Create a recording from our environment variables.
+    fn from_env() -> Result<Option<Recording>> {
+        match (env::var_os(RECORD_VAR), env::var_os(REPLAY_VAR)) {
+            (Some(_), Some(_)) => Err(format_err!(
+                "cannot set both {} and {}",
+                RECORD_VAR,
+                REPLAY_VAR,
+            )),
+            (Some(path), None) => Ok(Some(Recording::record(Path::new(&path))?)),
+            (None, Some(path)) => Ok(Some(Recording::replay(Path::new(&path))?)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Record interactions to `path`.
+    fn record(path: &Path) -> Result<Recording> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|_| format!("cannot open {}", path.display()))?;
+        Ok(Recording::Record {
+            path: path.to_owned(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Replay interactions from `path`.
+    fn replay(path: &Path) -> Result<Recording> {
+        let file = File::open(path)
+            .with_context(|_| format!("cannot open {}", path.display()))?;
+        let mut interactions = vec![];
+        for (idx, line) in BufReader::new(file).lines().enumerate() {
+            let line =
+                line.with_context(|_| format!("cannot read {}", path.display()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let interaction = serde_json::from_str::<Interaction>(&line)
+                .with_context(|_| format!("{} line {}", path.display(), idx + 1))?;
+            interactions.push(Some(interaction));
+        }
+        Ok(Recording::Replay {
+            path: path.to_owned(),
+            interactions: Mutex::new(interactions),
+        })
+    }
+
+    /// Are we replaying interactions?
+    pub(crate) fn is_replay(&self) -> bool {
+        matches!(self, Recording::Replay { .. })
+    }
+
+    /// If we're replaying, look up the response to the specified request.
+    pub(crate) fn replay_response(
+        &self,
+        ctx: &Context,
+        method: &str,
+        url: &Url,
+        request_body: Option<&Value>,
+    ) -> Result<Option<reqwest::Response>> {
+        if let Recording::Replay { path, interactions } = self {
+            trace!(ctx.log(), "replaying {} {}", method, url);
+            let mut interactions =
+                interactions.lock().expect("lock poisoned, giving up");
+            let found = interactions.iter_mut().find(|i| match i {
+                Some(i) => i.matches(method, url, request_body),
+                None => false,
+            });
+            match found.and_then(|i| i.take()) {
+                Some(interaction) => Ok(Some(interaction.to_response()?)),
+                None => Err(format_err!(
+                    "no recorded response to {} {} in {}",
+                    method,
+                    url,
+                    path.display(),
+                )),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// If we're recording, record `http_resp` as the response to the specified
+    /// request. Since this reads the entire body of `http_resp`, we return a
+    /// new response with the same contents.
+    pub(crate) async fn record_response(
+        &self,
+        ctx: &Context,
+        method: &str,
+        url: &Url,
+        request_body: Option<Value>,
+        http_resp: reqwest::Response,
+    ) -> Result<reqwest::Response> {
+        if let Recording::Record { path, file } = self {
+            trace!(ctx.log(), "recording {} {}", method, url);
+            let status = http_resp.status().as_u16();
+            let content_type = http_resp
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|ct| ct.to_str().ok())
+                .map(|ct| ct.to_owned());
+            let body = http_resp
+                .bytes()
+                .await
+                .with_context(|_| format!("error reading response from {}", url))?;
+            let interaction = Interaction {
+                method: method.to_owned(),
+                url: url.as_str().to_owned(),
+                request_body,
+                status,
+                content_type,
+                response_body: String::from_utf8(body.to_vec()).with_context(
+                    |_| format!("cannot record non-UTF-8 response from {}", url),
+                )?,
+            };
+            let mut line = serde_json::to_string(&interaction)?;
+            line.push('\n');
+            let mut file = file.lock().expect("lock poisoned, giving up");
+            file.write_all(line.as_bytes())
+                .with_context(|_| format!("cannot write to {}", path.display()))?;
+            interaction.to_response()
+        } else {
+            Ok(http_resp)
+        }
+    }
+}
+
+#[test]
+fn record_and_replay() {
+    use tempfile::tempdir;
+
+    let (ctx, worker_fut) = Context::create_for_test("record_and_replay");
+    let cmd_fut = async move {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("recording.jsonl");
+        let url = Url::parse("https://example.com/a?b=c").unwrap();
+        let body = serde_json::json!({ "x": 1 });
+
+        // Record a response.
+        let recording = Recording::record(&path).unwrap();
+        let http_resp = reqwest::Response::from(
+            hyper::Response::builder()
+                .status(200)
+                .header(CONTENT_TYPE, "application/json")
+                .body(b"{\"ok\":true}".to_vec())
+                .unwrap(),
+        );
+        let resp = recording
+            .record_response(&ctx, "POST", &url, Some(body.clone()), http_resp)
+            .await
+            .unwrap();
+        assert_eq!(resp.text().await.unwrap(), "{\"ok\":true}");
+
+        // Replay it.
+        let recording = Recording::replay(&path).unwrap();
+        assert!(recording.is_replay());
+        assert!(recording.replay_response(&ctx, "POST", &url, None).is_err());
+        let resp = recording
+            .replay_response(&ctx, "POST", &url, Some(&body))
+            .unwrap()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.text().await.unwrap(), "{\"ok\":true}");
+
+        // Each interaction can only be used once.
+        assert!(recording
+            .replay_response(&ctx, "POST", &url, Some(&body))
+            .is_err());
+        Ok(())
+    };
+    run_futures_with_runtime(cmd_fut.boxed(), worker_fut).unwrap();
+}
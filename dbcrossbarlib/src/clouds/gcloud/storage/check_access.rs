@@ -0,0 +1,42 @@
+//! Checking whether we can access a Google Cloud Storage bucket.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    super::{percent_encode, Client},
+    parse_gs_url,
+};
+use crate::common::*;
+
+/// URL query parameters.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListQuery<'a> {
+    prefix: &'a str,
+    max_results: u32,
+}
+
+/// Response body. We don't care about the contents, only that the request
+/// succeeded.
+#[derive(Debug, Deserialize)]
+struct ListResponse {}
+
+/// Make sure that we can list files at the specified `gs://` URL, without
+/// reading more than a single result.
+pub(crate) async fn check_access(ctx: &Context, url: &Url) -> Result<()> {
+    debug!(ctx.log(), "checking access to {}", url);
+    let (bucket, object) = parse_gs_url(url)?;
+    let req_url = format!(
+        "https://storage.googleapis.com/storage/v1/b/{}/o",
+        percent_encode(&bucket),
+    );
+    let query = ListQuery {
+        prefix: &object,
+        max_results: 1,
+    };
+    let client = Client::new(ctx).await?;
+    client
+        .get::<ListResponse, _, _>(ctx, &req_url, query)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! Concatenating objects within Google Cloud Storage.
+
+use serde::Serialize;
+
+use super::{
+    super::{percent_encode, Client, NoQuery},
+    parse_gs_url, StorageObject,
+};
+use crate::common::*;
+
+/// The maximum number of objects which can be composed in a single request.
+const MAX_COMPONENTS: usize = 32;
+
+/// Request body for a compose request.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ComposeRequest {
+    source_objects: Vec<SourceObject>,
+    destination: Destination,
+}
+
+/// An object to concatenate.
+#[derive(Debug, Serialize)]
+struct SourceObject {
+    name: String,
+}
+
+/// Metadata for the new object.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Destination {
+    content_type: &'static str,
+}
+
+/// Concatenate the CSV files at `src_urls` to create `dest_url`, without
+/// downloading them. All the files must be in the same bucket as `dest_url`.
+///
+/// Google only allows composing 32 objects at a time, so if we have more than
+/// that, we compose them in several rounds using intermediate objects, which
+/// we delete afterwards.
+///
+/// Docs: https://cloud.google.com/storage/docs/json_api/v1/objects/compose
+pub(crate) async fn compose(
+    ctx: &Context,
+    src_urls: &[Url],
+    dest_url: &Url,
+) -> Result<StorageObject> {
+    debug!(
+        ctx.log(),
+        "composing {} objects into {}",
+        src_urls.len(),
+        dest_url,
+    );
+    let (dest_bucket, dest_object) = parse_gs_url(dest_url)?;
+    let mut names = vec![];
+    for src_url in src_urls {
+        let (bucket, object) = parse_gs_url(src_url)?;
+        if bucket != dest_bucket {
+            return Err(format_err!(
+                "cannot compose {} into {}, because they're in different buckets",
+                src_url,
+                dest_url,
+            ));
+        }
+        names.push(object);
+    }
+    if names.is_empty() {
+        return Err(format_err!("no objects to compose into {}", dest_url));
+    }
+
+    let client = Client::new(ctx).await?;
+    let mut intermediates = vec![];
+    let mut round = 0;
+    while names.len() > MAX_COMPONENTS {
+        let mut next_names = vec![];
+        for (idx, chunk) in names.chunks(MAX_COMPONENTS).enumerate() {
+            let name = format!("{}.compose-{}-{}", dest_object, round, idx);
+            compose_once(ctx, &client, &dest_bucket, chunk, &name).await?;
+            intermediates.push(name.clone());
+            next_names.push(name);
+        }
+        names = next_names;
+        round += 1;
+    }
+    let obj = compose_once(ctx, &client, &dest_bucket, &names, &dest_object).await?;
+
+    for name in intermediates {
+        let url = format!(
+            "https://storage.googleapis.com/storage/v1/b/{}/o/{}",
+            percent_encode(&dest_bucket),
+            percent_encode(&name),
+        );
+        client.delete(ctx, &url, NoQuery).await?;
+    }
+    Ok(obj)
+}
+
+/// Compose at most `MAX_COMPONENTS` objects in `bucket`.
+async fn compose_once(
+    ctx: &Context,
+    client: &Client,
+    bucket: &str,
+    names: &[String],
+    dest_name: &str,
+) -> Result<StorageObject> {
+    trace!(ctx.log(), "composing {:?} into {}", names, dest_name);
+    let url = format!(
+        "https://storage.googleapis.com/storage/v1/b/{}/o/{}/compose",
+        percent_encode(bucket),
+        percent_encode(dest_name),
+    );
+    let req = ComposeRequest {
+        source_objects: names
+            .iter()
+            .map(|name| SourceObject {
+                name: name.to_owned(),
+            })
+            .collect(),
+        destination: Destination {
+            content_type: "text/csv",
+        },
+    };
+    client.post(ctx, &url, NoQuery, req).await
+}
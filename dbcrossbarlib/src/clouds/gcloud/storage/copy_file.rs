@@ -0,0 +1,88 @@
+//! Copying files within Google Cloud Storage.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    super::{percent_encode, Client},
+    parse_gs_url, StorageObject,
+};
+use crate::common::*;
+
+/// Parameters for a rewrite query.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RewriteQuery {
+    /// A token returned by a previous, unfinished call to `rewrite`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rewrite_token: Option<String>,
+}
+
+/// Metadata overrides for the rewritten object. Anything we leave out will be
+/// copied from the source object.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RewriteBody<'a> {
+    /// The storage class to use for the new object.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    storage_class: Option<&'a str>,
+}
+
+/// Response body for a rewrite query.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RewriteResponse {
+    /// Has this rewrite finished?
+    done: bool,
+
+    /// If `done` is false, pass this to the next call to `rewrite`.
+    rewrite_token: Option<String>,
+
+    /// The object we created, if `done` is true.
+    resource: Option<StorageObject>,
+}
+
+/// Copy the file at `src_url` to `dest_url`, without downloading it. This uses
+/// Google's "rewrite" API, which can copy objects server-side between buckets,
+/// regions and storage classes, and which preserves object metadata. If
+/// `storage_class` is specified, the new object will use it.
+///
+/// Docs: https://cloud.google.com/storage/docs/json_api/v1/objects/rewrite
+pub(crate) async fn copy_file(
+    ctx: &Context,
+    src_url: &Url,
+    dest_url: &Url,
+    storage_class: Option<&str>,
+) -> Result<StorageObject> {
+    debug!(ctx.log(), "copying {} to {}", src_url, dest_url);
+    let (src_bucket, src_object) = parse_gs_url(src_url)?;
+    let (dest_bucket, dest_object) = parse_gs_url(dest_url)?;
+    let url = format!(
+        "https://storage.googleapis.com/storage/v1/b/{}/o/{}/rewriteTo/b/{}/o/{}",
+        percent_encode(&src_bucket),
+        percent_encode(&src_object),
+        percent_encode(&dest_bucket),
+        percent_encode(&dest_object),
+    );
+    let client = Client::new(ctx).await?;
+
+    // Large copies between locations may need several calls to finish.
+    let mut rewrite_token = None;
+    loop {
+        ctx.check_cancelled()?;
+        let query = RewriteQuery {
+            rewrite_token: rewrite_token.take(),
+        };
+        let resp: RewriteResponse = client
+            .post(ctx, &url, query, RewriteBody { storage_class })
+            .await?;
+        if resp.done {
+            return resp.resource.ok_or_else(|| {
+                format_err!("finished copying {}, but got no object", dest_url)
+            });
+        }
+        trace!(ctx.log(), "still copying {} to {}", src_url, dest_url);
+        rewrite_token = Some(resp.rewrite_token.ok_or_else(|| {
+            format_err!("unfinished copy to {} has no rewriteToken", dest_url)
+        })?);
+    }
+}
@@ -0,0 +1,22 @@
+//! Deleting a single file from Google Cloud Storage.
+
+use super::{
+    super::{percent_encode, Client, NoQuery},
+    parse_gs_url,
+};
+use crate::common::*;
+
+/// Delete the file at `file_url`.
+///
+/// Docs: https://cloud.google.com/storage/docs/json_api/v1/objects/delete
+pub(crate) async fn delete_file(ctx: &Context, file_url: &Url) -> Result<()> {
+    debug!(ctx.log(), "deleting {}", file_url);
+    let (bucket, object) = parse_gs_url(file_url)?;
+    let req_url = format!(
+        "https://storage.googleapis.com/storage/v1/b/{}/o/{}",
+        percent_encode(&bucket),
+        percent_encode(&object),
+    );
+    let client = Client::new(ctx).await?;
+    client.delete(ctx, &req_url, NoQuery).await
+}
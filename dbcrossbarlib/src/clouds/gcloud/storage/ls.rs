@@ -94,6 +94,8 @@ pub(crate) async fn ls(
         );
         let mut page_token = None;
         loop {
+            try_and_forward_errors!(worker_ctx, worker_ctx.check_cancelled(), sender);
+
             // Set up our request.
             let query = ListQuery {
                 prefix: &object,
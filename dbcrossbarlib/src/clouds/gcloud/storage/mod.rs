@@ -8,14 +8,26 @@ use std::{fmt, marker::PhantomData, str::FromStr};
 
 use crate::common::*;
 
+mod check_access;
+mod compose;
+mod copy_file;
+mod delete_file;
 mod download_file;
 mod ls;
 mod rmdir;
+mod sign_url;
+mod update_metadata;
 mod upload_file;
 
+pub(crate) use check_access::check_access;
+pub(crate) use compose::compose;
+pub(crate) use copy_file::copy_file;
+pub(crate) use delete_file::delete_file;
 pub(crate) use download_file::download_file;
 pub(crate) use ls::ls;
 pub(crate) use rmdir::rmdir;
+pub(crate) use sign_url::sign_url;
+pub(crate) use update_metadata::update_metadata;
 pub(crate) use upload_file::upload_file;
 
 /// Chunk size to use when working with Google Cloud Storage.
@@ -0,0 +1,38 @@
+//! Updating the metadata of objects in Google Cloud Storage.
+
+use super::{
+    super::{percent_encode, Client, NoQuery},
+    copy_file, parse_gs_url, StorageObject,
+};
+use crate::clouds::object_metadata::ObjectMetadata;
+use crate::common::*;
+
+/// Set `metadata` on the object at `url`. Fields which are not specified in
+/// `metadata` are left unchanged.
+///
+/// Google can't change the storage class of an existing object in place, so if
+/// `metadata` specifies one, we rewrite the object on top of itself first.
+/// This happens server-side.
+///
+/// Docs: https://cloud.google.com/storage/docs/json_api/v1/objects/patch
+pub(crate) async fn update_metadata(
+    ctx: &Context,
+    url: &Url,
+    metadata: &ObjectMetadata,
+) -> Result<StorageObject> {
+    debug!(ctx.log(), "updating metadata for {}", url);
+    if let Some(storage_class) = &metadata.storage_class {
+        let obj = copy_file(ctx, url, url, Some(storage_class)).await?;
+        if metadata.is_empty_except_storage_class() {
+            return Ok(obj);
+        }
+    }
+    let (bucket, object) = parse_gs_url(url)?;
+    let req_url = format!(
+        "https://storage.googleapis.com/storage/v1/b/{}/o/{}",
+        percent_encode(&bucket),
+        percent_encode(&object),
+    );
+    let client = Client::new(ctx).await?;
+    client.patch(ctx, &req_url, NoQuery, metadata).await
+}
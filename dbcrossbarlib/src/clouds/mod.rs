@@ -1,4 +1,9 @@
 //! Interfaces to various clouds.
 
 pub(crate) mod aws;
+pub(crate) mod azure;
+pub(crate) mod endpoints;
 pub(crate) mod gcloud;
+pub(crate) mod http_client;
+pub(crate) mod object_metadata;
+pub(crate) mod signed_urls;
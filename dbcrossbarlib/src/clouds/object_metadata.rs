@@ -0,0 +1,110 @@
+//! Metadata for objects we write to cloud storage.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::common::*;
+
+/// Metadata to set on each object we write.
+///
+/// This serializes to the field names used by Google Cloud Storage, so it can
+/// be sent as the body of an object `PATCH` request.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ObjectMetadata {
+    /// The `Content-Type` to serve the object with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) content_type: Option<String>,
+
+    /// The `Content-Encoding` to serve the object with. This does not compress
+    /// the data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) content_encoding: Option<String>,
+
+    /// The `Cache-Control` header to serve the object with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cache_control: Option<String>,
+
+    /// Custom key-value metadata.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) metadata: BTreeMap<String, String>,
+
+    /// The storage class to use, such as `NEARLINE` on Google Cloud Storage or
+    /// `STANDARD_IA` on S3. We don't send this as part of a `PATCH` request,
+    /// because Google Cloud Storage requires a rewrite to change it.
+    #[serde(skip)]
+    pub(crate) storage_class: Option<String>,
+}
+
+impl ObjectMetadata {
+    /// Is there any metadata to set?
+    pub(crate) fn is_empty(&self) -> bool {
+        self.storage_class.is_none() && self.is_empty_except_storage_class()
+    }
+
+    /// Is there any metadata to set, other than `storage_class`?
+    pub(crate) fn is_empty_except_storage_class(&self) -> bool {
+        self.content_type.is_none()
+            && self.content_encoding.is_none()
+            && self.cache_control.is_none()
+            && self.metadata.is_empty()
+    }
+
+    /// Arguments to pass to `aws s3 cp` to set this metadata.
+    pub(crate) fn aws_s3_args(&self) -> Result<Vec<String>> {
+        let mut args = vec![];
+        let headers = [
+            ("--content-type", &self.content_type),
+            ("--content-encoding", &self.content_encoding),
+            ("--cache-control", &self.cache_control),
+            ("--storage-class", &self.storage_class),
+        ];
+        for (flag, value) in &headers {
+            if let Some(value) = value {
+                args.push((*flag).to_owned());
+                args.push(value.to_owned());
+            }
+        }
+        if !self.metadata.is_empty() {
+            args.push("--metadata".to_owned());
+            args.push(serde_json::to_string(&self.metadata)?);
+        }
+        Ok(args)
+    }
+}
+
+#[test]
+fn metadata_to_aws_s3_args() {
+    let mut metadata = ObjectMetadata::default();
+    assert!(metadata.is_empty());
+    assert!(metadata.aws_s3_args().unwrap().is_empty());
+
+    metadata.content_type = Some("text/csv; charset=utf-8".to_owned());
+    metadata.cache_control = Some("max-age=3600".to_owned());
+    metadata.storage_class = Some("STANDARD_IA".to_owned());
+    metadata
+        .metadata
+        .insert("owner".to_owned(), "data,team".to_owned());
+    assert!(!metadata.is_empty());
+    assert_eq!(
+        metadata.aws_s3_args().unwrap(),
+        vec![
+            "--content-type",
+            "text/csv; charset=utf-8",
+            "--cache-control",
+            "max-age=3600",
+            "--storage-class",
+            "STANDARD_IA",
+            "--metadata",
+            r#"{"owner":"data,team"}"#,
+        ],
+    );
+    assert_eq!(
+        serde_json::to_value(&metadata).unwrap(),
+        serde_json::json!({
+            "contentType": "text/csv; charset=utf-8",
+            "cacheControl": "max-age=3600",
+            "metadata": { "owner": "data,team" },
+        }),
+    );
+}
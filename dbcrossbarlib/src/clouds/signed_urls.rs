@@ -0,0 +1,73 @@
+//! Reporting signed URLs for files we've written to cloud storage.
+
+use chrono::{DateTime, Utc};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::common::*;
+
+/// Where to send signed URLs, and when they should expire.
+pub(crate) struct SignedUrlWriter {
+    /// When our URLs should expire.
+    expires: DateTime<Utc>,
+    /// A local file to write our URLs to. If this is `None`, we print them to
+    /// standard output.
+    manifest: Option<Mutex<File>>,
+}
+
+impl SignedUrlWriter {
+    /// Build a `SignedUrlWriter` from the `signed_url_ttl` and
+    /// `signed_url_manifest` driver arguments. Returns `None` if no `ttl` was
+    /// specified.
+    pub(crate) fn from_args(
+        ttl: Option<Duration>,
+        manifest: Option<&Path>,
+    ) -> Result<Option<Arc<SignedUrlWriter>>> {
+        let ttl = match (ttl, manifest) {
+            (Some(ttl), _) => ttl,
+            (None, Some(_)) => {
+                return Err(format_err!(
+                    "--to-arg=signed_url_manifest requires --to-arg=signed_url_ttl"
+                ))
+            }
+            (None, None) => return Ok(None),
+        };
+        let ttl = chrono::Duration::from_std(ttl)
+            .map_err(|_| format_err!("signed_url_ttl is too long"))?;
+        let manifest = match manifest {
+            Some(path) => {
+                Some(Mutex::new(File::create(path).with_context(|_| {
+                    format!("could not create {}", path.display())
+                })?))
+            }
+            None => None,
+        };
+        Ok(Some(Arc::new(SignedUrlWriter {
+            expires: Utc::now() + ttl,
+            manifest,
+        })))
+    }
+
+    /// When should our signed URLs expire?
+    pub(crate) fn expires(&self) -> DateTime<Utc> {
+        self.expires
+    }
+
+    /// Record a signed URL.
+    pub(crate) fn write(&self, signed_url: &Url) -> Result<()> {
+        match &self.manifest {
+            Some(manifest) => {
+                let mut manifest = manifest.lock().expect("lock poisoned, giving up");
+                writeln!(manifest, "{}", signed_url)?;
+                manifest.flush()?;
+            }
+            None => writeln!(io::stdout().lock(), "{}", signed_url)?,
+        }
+        Ok(())
+    }
+}
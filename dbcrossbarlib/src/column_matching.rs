@@ -0,0 +1,275 @@
+//! Matching our columns against an existing destination table whose column
+//! names follow different conventions.
+
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use crate::common::*;
+use crate::select::ColumnSelection;
+
+/// How to match source columns to the columns of an existing destination
+/// table when appending or upserting.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum ColumnMatching {
+    /// Column names must match exactly.
+    #[default]
+    Exact,
+    /// Ignore differences in case, so that `userId` matches `userid`.
+    CaseInsensitive,
+    /// Ignore differences in case, and treat `camelCase` and `snake_case`
+    /// names as the same, so that `userId` matches `user_id`.
+    SnakeCase,
+}
+
+impl ColumnMatching {
+    /// Fold `name` into a key which is the same for all the names we consider
+    /// to be equivalent.
+    fn key(self, name: &str) -> String {
+        match self {
+            ColumnMatching::Exact => name.to_owned(),
+            ColumnMatching::CaseInsensitive => name.to_lowercase(),
+            ColumnMatching::SnakeCase => {
+                let mut key = String::with_capacity(name.len() + 4);
+                let mut prev_is_lower_or_digit = false;
+                for c in name.chars() {
+                    if c.is_uppercase() && prev_is_lower_or_digit {
+                        key.push('_');
+                    }
+                    prev_is_lower_or_digit = c.is_lowercase() || c.is_numeric();
+                    key.extend(c.to_lowercase());
+                }
+                key
+            }
+        }
+    }
+
+    /// Compare the columns in `schema` against `dest_columns`, the column
+    /// names of the existing table `dest_name`, and decide which of our
+    /// columns need to be renamed. Columns without any match are left alone.
+    pub(crate) fn plan<'a>(
+        self,
+        schema: &Table,
+        dest_name: &str,
+        dest_columns: impl IntoIterator<Item = &'a str>,
+    ) -> Result<ColumnRenames> {
+        let mut renames = ColumnRenames::default();
+        if self == ColumnMatching::Exact {
+            return Ok(renames);
+        }
+
+        // Index the destination columns by key, keeping track of any
+        // ambiguous keys.
+        let dest_columns = dest_columns.into_iter().collect::<Vec<_>>();
+        let mut by_key = HashMap::<String, Vec<&str>>::new();
+        for &name in &dest_columns {
+            by_key.entry(self.key(name)).or_default().push(name);
+        }
+
+        let mut matched = HashMap::<&str, &str>::new();
+        for column in &schema.columns {
+            // Exact matches always win.
+            let dest = if dest_columns.iter().any(|&d| d == column.name) {
+                &column.name[..]
+            } else {
+                match by_key.get(&self.key(&column.name)).map(|v| &v[..]) {
+                    None => continue,
+                    Some([dest]) => *dest,
+                    Some(candidates) => {
+                        return Err(format_err!(
+                            "column {:?} matches more than one column in {}: {}",
+                            column.name,
+                            dest_name,
+                            candidates.join(", "),
+                        ));
+                    }
+                }
+            };
+            if let Some(prev) = matched.insert(dest, &column.name[..]) {
+                return Err(format_err!(
+                    "columns {:?} and {:?} both match column {:?} in {}",
+                    prev,
+                    column.name,
+                    dest,
+                    dest_name,
+                ));
+            }
+            if dest != column.name {
+                renames.renames.push((column.name.clone(), dest.to_owned()));
+            }
+        }
+        Ok(renames)
+    }
+}
+
+impl fmt::Display for ColumnMatching {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnMatching::Exact => "exact".fmt(f),
+            ColumnMatching::CaseInsensitive => "case-insensitive".fmt(f),
+            ColumnMatching::SnakeCase => "snake-case".fmt(f),
+        }
+    }
+}
+
+impl FromStr for ColumnMatching {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ColumnMatching> {
+        match s {
+            "exact" => Ok(ColumnMatching::Exact),
+            "case-insensitive" => Ok(ColumnMatching::CaseInsensitive),
+            "snake-case" => Ok(ColumnMatching::SnakeCase),
+            _ => Err(format_err!("unknown column matching rule: {}", s)),
+        }
+    }
+}
+
+/// Columns to rename so that they match an existing table.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ColumnRenames {
+    /// Pairs of `(source_name, dest_name)`.
+    renames: Vec<(String, String)>,
+}
+
+impl ColumnRenames {
+    /// Do we need to rename any columns?
+    pub(crate) fn is_empty(&self) -> bool {
+        self.renames.is_empty()
+    }
+
+    /// The destination name for the source column `name`.
+    fn dest_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.renames
+            .iter()
+            .find(|(source, _)| source == name)
+            .map_or(name, |(_, dest)| &dest[..])
+    }
+
+    /// The source name for the destination column `name`.
+    pub(crate) fn source_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.renames
+            .iter()
+            .find(|(_, dest)| dest == name)
+            .map_or(name, |(source, _)| &source[..])
+    }
+
+    /// Return `schema` with any matched columns renamed.
+    pub(crate) fn apply_to_schema(&self, schema: &Table) -> Table {
+        let mut schema = schema.to_owned();
+        for column in &mut schema.columns {
+            column.name = self.dest_name(&column.name).to_owned();
+        }
+        schema
+    }
+
+    /// Rename any upsert keys in `if_exists`, which should refer to source
+    /// columns.
+    pub(crate) fn apply_to_if_exists(&self, if_exists: &IfExists) -> IfExists {
+        match if_exists {
+            IfExists::Upsert(keys) => IfExists::Upsert(
+                keys.iter().map(|k| self.dest_name(k).to_owned()).collect(),
+            ),
+            other => other.to_owned(),
+        }
+    }
+
+    /// Rename any matched columns in the headers of `data`, which should
+    /// match `schema`.
+    pub(crate) fn apply_to_data(
+        &self,
+        ctx: &Context,
+        schema: &Table,
+        data: BoxStream<CsvStream>,
+    ) -> BoxStream<CsvStream> {
+        if self.is_empty() {
+            data
+        } else {
+            // Build a selection like `a, b AS c`.
+            let selection = schema
+                .columns
+                .iter()
+                .map(|c| (c.name.clone(), self.dest_name(&c.name).to_owned()))
+                .collect::<Vec<_>>();
+            ColumnSelection::from_renames(selection)
+                .select_csv_columns(ctx.to_owned(), data)
+        }
+    }
+}
+
+#[test]
+fn parse_and_display() {
+    for &rule in &[
+        ColumnMatching::Exact,
+        ColumnMatching::CaseInsensitive,
+        ColumnMatching::SnakeCase,
+    ] {
+        assert_eq!(rule.to_string().parse::<ColumnMatching>().unwrap(), rule);
+    }
+    assert!("snake_case".parse::<ColumnMatching>().is_err());
+}
+
+#[test]
+fn folds_names_into_keys() {
+    let examples = &[
+        ("userId", "userid", "user_id"),
+        ("UserID", "userid", "user_id"),
+        ("user_id", "user_id", "user_id"),
+        ("address2Line", "address2line", "address2_line"),
+    ];
+    for &(name, case_insensitive, snake_case) in examples {
+        assert_eq!(ColumnMatching::CaseInsensitive.key(name), case_insensitive);
+        assert_eq!(ColumnMatching::SnakeCase.key(name), snake_case);
+    }
+}
+
+#[test]
+fn plans_column_renames() {
+    use crate::schema::{Column, DataType};
+
+    let column = |name: &str| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type: DataType::Text,
+        comment: None,
+    };
+    let schema = Table {
+        name: "t".to_owned(),
+        columns: vec![column("id"), column("userId"), column("createdAt")],
+    };
+    let dest = &["ID", "user_id", "createdat"];
+
+    let renames = ColumnMatching::Exact.plan(&schema, "dest", dest.to_vec());
+    assert!(renames.unwrap().is_empty());
+
+    let renames = ColumnMatching::CaseInsensitive
+        .plan(&schema, "dest", dest.to_vec())
+        .unwrap();
+    assert_eq!(
+        renames.apply_to_schema(&schema).columns,
+        vec![column("ID"), column("userId"), column("createdat")],
+    );
+
+    let renames = ColumnMatching::SnakeCase
+        .plan(&schema, "dest", dest.to_vec())
+        .unwrap();
+    assert_eq!(
+        renames.apply_to_schema(&schema).columns,
+        vec![column("ID"), column("user_id"), column("createdAt")],
+    );
+    assert_eq!(renames.source_name("user_id"), "userId");
+    assert_eq!(
+        renames.apply_to_if_exists(&IfExists::Upsert(vec!["userId".to_owned()])),
+        IfExists::Upsert(vec!["user_id".to_owned()]),
+    );
+
+    // Ambiguous matches are errors.
+    assert!(ColumnMatching::CaseInsensitive
+        .plan(&schema, "dest", vec!["userid", "USERID"])
+        .is_err());
+    let schema = Table {
+        name: "t".to_owned(),
+        columns: vec![column("userid"), column("USERID")],
+    };
+    assert!(ColumnMatching::CaseInsensitive
+        .plan(&schema, "dest", vec!["UserId"])
+        .is_err());
+}
@@ -0,0 +1,427 @@
+//! Per-column statistics, computed from a stream of CSV streams.
+
+use serde::Serialize;
+use std::{
+    cmp::Ordering,
+    collections::hash_map::DefaultHasher,
+    convert::TryFrom,
+    fs::File,
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::common::*;
+use crate::schema::{Column, DataType};
+use crate::transform::spawn_sync_transform;
+
+/// How many bits of each hash should we use to pick a HyperLogLog register?
+/// 12 bits gives us 4,096 registers, and a typical error of about 1.6%.
+const HLL_PRECISION: u32 = 12;
+
+/// The number of registers in each HyperLogLog sketch.
+const HLL_REGISTERS: usize = 1 << HLL_PRECISION;
+
+/// Statistics for a whole copy, shared between all our streams.
+#[derive(Clone, Debug)]
+pub struct ColumnStatsCollector {
+    /// The statistics collected so far.
+    report: Arc<Mutex<ColumnStatsReport>>,
+}
+
+impl ColumnStatsCollector {
+    /// Create a collector for the columns in `schema`.
+    pub fn new(schema: &Table) -> Self {
+        let columns = schema.columns.iter().map(ColumnStats::new).collect();
+        ColumnStatsCollector {
+            report: Arc::new(Mutex::new(ColumnStatsReport { rows: 0, columns })),
+        }
+    }
+
+    /// Given a stream of CSV streams, collect statistics about each column
+    /// while passing the data through unchanged.
+    pub fn collect_from(
+        &self,
+        ctx: Context,
+        streams: BoxStream<CsvStream>,
+    ) -> BoxStream<CsvStream> {
+        let ctx = ctx.child(o!("streams_transform" => "column_stats"));
+        let collector = self.clone();
+        streams
+            .and_then(move |stream| {
+                let ctx = ctx.clone();
+                let collector = collector.clone();
+                async move {
+                    let name = stream.name.clone();
+                    let data = spawn_sync_transform(
+                        ctx,
+                        format!("column stats {}", name),
+                        stream.data,
+                        move |_ctx, rdr, wtr| collector.collect_csv(&name, rdr, wtr),
+                    )?;
+                    Ok(CsvStream {
+                        name: stream.name,
+                        data,
+                    })
+                }
+            })
+            .boxed()
+    }
+
+    /// Copy CSV data from `rdr` to `wtr`, and add statistics about it to our
+    /// report.
+    fn collect_csv(
+        &self,
+        stream_name: &str,
+        rdr: impl Read,
+        wtr: impl Write,
+    ) -> Result<()> {
+        let mut stream_report = self.empty_report();
+        let mut rdr = csv::Reader::from_reader(rdr);
+        let mut wtr = csv::Writer::from_writer(wtr);
+        let hdr = rdr
+            .byte_headers()
+            .with_context(|_| format!("cannot read headers of {}", stream_name))?
+            .to_owned();
+        if hdr.len() != stream_report.columns.len() {
+            return Err(format_err!(
+                "{} has {} columns, but the schema has {}",
+                stream_name,
+                hdr.len(),
+                stream_report.columns.len(),
+            ));
+        }
+        wtr.write_byte_record(&hdr)?;
+
+        let mut record = csv::StringRecord::new();
+        while rdr
+            .read_record(&mut record)
+            .with_context(|_| format!("cannot read row from {}", stream_name))?
+        {
+            stream_report.add_row(&record);
+            wtr.write_record(&record)?;
+        }
+        wtr.flush()?;
+
+        self.report
+            .lock()
+            .expect("lock poisoned, giving up")
+            .merge(stream_report);
+        Ok(())
+    }
+
+    /// An empty report with the same columns as ours.
+    fn empty_report(&self) -> ColumnStatsReport {
+        let report = self.report.lock().expect("lock poisoned, giving up");
+        ColumnStatsReport {
+            rows: 0,
+            columns: report.columns.iter().map(ColumnStats::empty_like).collect(),
+        }
+    }
+
+    /// Our statistics so far.
+    pub fn report(&self) -> ColumnStatsReport {
+        self.report
+            .lock()
+            .expect("lock poisoned, giving up")
+            .clone()
+    }
+
+    /// Write our statistics to `path` as JSON.
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let f = File::create(path)
+            .with_context(|_| format!("could not create {}", path.display()))?;
+        serde_json::to_writer_pretty(f, &self.report())
+            .with_context(|_| format!("could not write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Statistics about all the data we've seen.
+#[derive(Clone, Debug, Serialize)]
+pub struct ColumnStatsReport {
+    /// The number of rows we've seen.
+    pub rows: u64,
+    /// Statistics for each column.
+    pub columns: Vec<ColumnStats>,
+}
+
+impl ColumnStatsReport {
+    /// Add a single row to our statistics.
+    fn add_row(&mut self, record: &csv::StringRecord) {
+        self.rows += 1;
+        for (stats, cell) in self.columns.iter_mut().zip(record.iter()) {
+            stats.add_cell(cell);
+        }
+    }
+
+    /// Merge the statistics in `other` into ours.
+    fn merge(&mut self, other: ColumnStatsReport) {
+        self.rows += other.rows;
+        for (stats, other_stats) in self.columns.iter_mut().zip(other.columns) {
+            stats.merge(other_stats);
+        }
+    }
+}
+
+/// Statistics about a single column.
+#[derive(Clone, Debug, Serialize)]
+pub struct ColumnStats {
+    /// The name of this column.
+    pub name: String,
+    /// The type of this column.
+    #[serde(skip)]
+    data_type: DataType,
+    /// The number of `NULL` values in this column. Empty CSV cells are always
+    /// counted as `NULL`.
+    pub null_count: u64,
+    /// The smallest non-`NULL` value, if this column's type can be ordered.
+    pub min: Option<String>,
+    /// The largest non-`NULL` value, if this column's type can be ordered.
+    pub max: Option<String>,
+    /// An estimate of the number of distinct non-`NULL` values.
+    pub distinct_estimate: u64,
+    /// A HyperLogLog sketch of our non-`NULL` values.
+    #[serde(skip)]
+    sketch: HyperLogLog,
+}
+
+impl ColumnStats {
+    /// Create empty statistics for `column`.
+    fn new(column: &Column) -> Self {
+        ColumnStats {
+            name: column.name.clone(),
+            data_type: column.data_type.clone(),
+            null_count: 0,
+            min: None,
+            max: None,
+            distinct_estimate: 0,
+            sketch: HyperLogLog::default(),
+        }
+    }
+
+    /// Create empty statistics for the same column as `self`.
+    fn empty_like(&self) -> Self {
+        ColumnStats {
+            name: self.name.clone(),
+            data_type: self.data_type.clone(),
+            null_count: 0,
+            min: None,
+            max: None,
+            distinct_estimate: 0,
+            sketch: HyperLogLog::default(),
+        }
+    }
+
+    /// Add a single cell to our statistics.
+    fn add_cell(&mut self, cell: &str) {
+        if cell.is_empty() {
+            self.null_count += 1;
+            return;
+        }
+        // We update `distinct_estimate` in `merge`, because it's expensive.
+        self.sketch.insert(cell);
+        self.update_min_max(cell);
+    }
+
+    /// Update `min` and `max` using `value`.
+    fn update_min_max(&mut self, value: &str) {
+        let data_type = &self.data_type;
+        if !is_ordered(data_type) {
+            return;
+        }
+        match &self.min {
+            Some(min) if compare_values(data_type, value, min) != Ordering::Less => {}
+            _ => self.min = Some(value.to_owned()),
+        }
+        match &self.max {
+            Some(max)
+                if compare_values(data_type, value, max) != Ordering::Greater => {}
+            _ => self.max = Some(value.to_owned()),
+        }
+    }
+
+    /// Merge the statistics in `other` into ours.
+    fn merge(&mut self, other: ColumnStats) {
+        self.null_count += other.null_count;
+        for value in other.min.iter().chain(other.max.iter()) {
+            self.update_min_max(value);
+        }
+        self.sketch.merge(&other.sketch);
+        self.distinct_estimate = self.sketch.estimate();
+    }
+}
+
+/// Does it make sense to report `min` and `max` for `data_type`?
+fn is_ordered(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Array(_)
+        | DataType::Bool
+        | DataType::GeoJson(_)
+        | DataType::Json
+        | DataType::Struct(_)
+        | DataType::Uuid => false,
+        DataType::Date
+        | DataType::Decimal
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::Text
+        | DataType::TimestampWithoutTimeZone
+        | DataType::TimestampWithTimeZone => true,
+    }
+}
+
+/// Compare two non-`NULL` CSV values of type `data_type`.
+///
+/// Numbers are compared numerically. Everything else is compared as a string,
+/// which works for the ISO 8601 dates and timestamps we normally see. If a
+/// number can't be parsed, we fall back to comparing strings.
+fn compare_values(data_type: &DataType, a: &str, b: &str) -> Ordering {
+    match data_type {
+        DataType::Decimal
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64 => match (a.parse::<f64>(), b.parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            _ => a.cmp(b),
+        },
+        _ => a.cmp(b),
+    }
+}
+
+/// A HyperLogLog sketch, used to estimate the number of distinct values.
+///
+/// See [Flajolet et al.][hll]. We use the standard small-range correction,
+/// but our hashes are 64 bits, so we don't need a large-range correction.
+///
+/// [hll]: http://algo.inria.fr/flajolet/Publications/FlFuGaMe07.pdf
+#[derive(Clone, Debug)]
+struct HyperLogLog {
+    /// The highest "rank" we've seen for each register.
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog {
+            registers: vec![0; HLL_REGISTERS],
+        }
+    }
+}
+
+impl HyperLogLog {
+    /// Add `value` to our sketch.
+    fn insert(&mut self, value: &str) {
+        // `DefaultHasher::new` always uses the same keys, so this is stable
+        // across streams.
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let idx = usize::try_from(hash >> (64 - HLL_PRECISION))
+            .expect("register index should fit in usize");
+        let rest = hash << HLL_PRECISION;
+        let rank =
+            u8::try_from((rest.leading_zeros() + 1).min(64 - HLL_PRECISION + 1))
+                .expect("rank should fit in u8");
+        if rank > self.registers[idx] {
+            self.registers[idx] = rank;
+        }
+    }
+
+    /// Merge `other` into our sketch.
+    fn merge(&mut self, other: &HyperLogLog) {
+        for (reg, other_reg) in self.registers.iter_mut().zip(&other.registers) {
+            *reg = (*reg).max(*other_reg);
+        }
+    }
+
+    /// Estimate the number of distinct values we've seen.
+    fn estimate(&self) -> u64 {
+        let m = f64::from(1u32 << HLL_PRECISION);
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|&reg| (-f64::from(reg)).exp2())
+            .sum::<f64>();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&reg| reg == 0).count();
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            let zeros = f64::from(
+                u32::try_from(zeros).expect("register count should fit in u32"),
+            );
+            m * (m / zeros).ln()
+        } else {
+            raw
+        };
+        // Our estimate is always positive, and far smaller than `u64::MAX`.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let estimate = estimate.round() as u64;
+        estimate
+    }
+}
+
+#[test]
+fn hyper_log_log_estimates_distinct_values() {
+    let mut a = HyperLogLog::default();
+    let mut b = HyperLogLog::default();
+    assert_eq!(a.estimate(), 0);
+    for i in 0..10_000 {
+        a.insert(&i.to_string());
+        b.insert(&(i + 5_000).to_string());
+    }
+    a.merge(&b);
+    let estimate = a.estimate();
+    assert!(14_000 < estimate && estimate < 16_000, "{}", estimate);
+}
+
+#[test]
+fn collects_column_stats() {
+    use crate::schema::Column;
+
+    let schema = Table {
+        name: "test".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int32,
+                comment: None,
+            },
+            Column {
+                name: "name".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Text,
+                comment: None,
+            },
+        ],
+    };
+    let collector = ColumnStatsCollector::new(&schema);
+    let input = "id,name\n10,b\n9,\n100,a\n";
+    let mut output = vec![];
+    collector
+        .collect_csv("test", input.as_bytes(), &mut output)
+        .unwrap();
+    collector
+        .collect_csv("test", "id,name\n-1,c\n".as_bytes(), vec![])
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), input);
+
+    let report = collector.report();
+    assert_eq!(report.rows, 4);
+    let id = &report.columns[0];
+    assert_eq!(id.null_count, 0);
+    assert_eq!(id.min.as_deref(), Some("-1"));
+    assert_eq!(id.max.as_deref(), Some("100"));
+    assert_eq!(id.distinct_estimate, 4);
+    let name = &report.columns[1];
+    assert_eq!(name.null_count, 1);
+    assert_eq!(name.min.as_deref(), Some("a"));
+    assert_eq!(name.max.as_deref(), Some("c"));
+    assert_eq!(name.distinct_estimate, 3);
+}
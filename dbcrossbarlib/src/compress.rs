@@ -0,0 +1,101 @@
+//! Compressing output streams.
+
+use flate2::{write::GzEncoder, Compression};
+use std::{fmt, io, str::FromStr};
+
+use crate::common::*;
+use crate::transform::spawn_sync_transform;
+
+/// How to compress the files we write.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum OutputCompression {
+    /// Write uncompressed files.
+    #[default]
+    None,
+    /// Compress files using `gzip`.
+    Gzip,
+}
+
+impl OutputCompression {
+    /// The extension to add after `.csv` when naming files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputCompression::None => "",
+            OutputCompression::Gzip => ".gz",
+        }
+    }
+
+    /// Compress `data`. Compression runs on a background thread, so we never
+    /// need to hold a whole file in memory, and we don't depend on any external
+    /// tools.
+    pub(crate) fn compress_stream(
+        self,
+        ctx: &Context,
+        data: BoxStream<BytesMut>,
+    ) -> Result<BoxStream<BytesMut>> {
+        match self {
+            OutputCompression::None => Ok(data),
+            OutputCompression::Gzip => {
+                debug!(ctx.log(), "compressing data using gzip");
+                spawn_sync_transform(
+                    ctx.clone(),
+                    "gzip".to_owned(),
+                    data,
+                    |_ctx, mut rdr, wtr| {
+                        let mut encoder = GzEncoder::new(wtr, Compression::default());
+                        io::copy(&mut rdr, &mut encoder)
+                            .context("error compressing data")?;
+                        encoder.finish().context("error compressing data")?;
+                        Ok(())
+                    },
+                )
+            }
+        }
+    }
+
+    /// Compress the data in `stream`.
+    pub(crate) fn compress_csv_stream(
+        self,
+        ctx: &Context,
+        stream: CsvStream,
+    ) -> Result<CsvStream> {
+        Ok(CsvStream {
+            name: stream.name,
+            data: self.compress_stream(ctx, stream.data)?,
+        })
+    }
+}
+
+impl fmt::Display for OutputCompression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputCompression::None => "none".fmt(f),
+            OutputCompression::Gzip => "gzip".fmt(f),
+        }
+    }
+}
+
+impl FromStr for OutputCompression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<OutputCompression> {
+        match s {
+            "none" => Ok(OutputCompression::None),
+            "gzip" => Ok(OutputCompression::Gzip),
+            _ => Err(format_err!("unknown compression format: {}", s)),
+        }
+    }
+}
+
+#[test]
+fn parses_and_displays_output_compression() {
+    for compression in &[OutputCompression::None, OutputCompression::Gzip] {
+        let parsed = compression
+            .to_string()
+            .parse::<OutputCompression>()
+            .unwrap();
+        assert_eq!(&parsed, compression);
+    }
+    assert_eq!(OutputCompression::Gzip.extension(), ".gz");
+    assert!("zip".parse::<OutputCompression>().is_err());
+}
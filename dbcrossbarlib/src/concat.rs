@@ -59,6 +59,52 @@ pub(crate) fn concatenate_csv_streams(
     Ok(new_csv_stream)
 }
 
+/// Wait until `csv_streams` has produced all its streams, and return them
+/// sorted by name, so that concatenating them gives the same results every
+/// time.
+///
+/// This won't work for sources that only produce each stream after the
+/// previous one has been read, like `--stream-size`.
+pub(crate) async fn sort_csv_streams_by_name(
+    ctx: &Context,
+    csv_streams: BoxStream<CsvStream>,
+) -> Result<BoxStream<CsvStream>> {
+    let mut csv_streams = csv_streams.try_collect::<Vec<_>>().await?;
+    csv_streams.sort_by(|a, b| a.name.cmp(&b.name));
+    debug!(
+        ctx.log(),
+        "sorted {} CSV streams by name",
+        csv_streams.len()
+    );
+    Ok(stream::iter(csv_streams).map(Ok).boxed())
+}
+
+#[test]
+fn sort_csv_streams_by_name_sorts_streams() {
+    let (ctx, worker_fut) = Context::create_for_test("sort_csv_streams_by_name");
+
+    let cmd_fut = async move {
+        let mut streams = vec![];
+        for (name, data) in &[("b", &b"a\n2\n"[..]), ("a", &b"a\n1\n"[..])] {
+            let mut csv_stream = CsvStream::from_bytes(*data).await;
+            csv_stream.name = (*name).to_owned();
+            streams.push(Ok(csv_stream));
+        }
+        let sorted = sort_csv_streams_by_name(&ctx, stream::iter(streams).boxed())
+            .await
+            .unwrap();
+        let combined = concatenate_csv_streams(ctx.clone(), sorted)
+            .unwrap()
+            .into_bytes(ctx)
+            .await
+            .unwrap();
+        assert_eq!(combined, &b"a\n1\n2\n"[..]);
+        Ok(())
+    };
+
+    run_futures_with_runtime(cmd_fut.boxed(), worker_fut).unwrap();
+}
+
 #[test]
 fn concatenate_csv_streams_strips_all_but_first_header() {
     let input_1 = b"a,b\n1,2\n";
@@ -7,7 +7,7 @@ use std::{
     io::{self, Read},
     path::{Path, PathBuf},
 };
-use toml_edit::{Array, Document, Item, Value};
+use toml_edit::{Array, Document, Item, Table, Value};
 
 use crate::common::*;
 
@@ -172,10 +172,85 @@ impl Configuration {
         self.string_array(&Key::global("temporary"))
     }
 
+    /// Return the command-line arguments for the profile `name`, including
+    /// any arguments it inherits from other profiles. A profile's own
+    /// arguments override any inherited arguments which use the same flag.
+    pub fn profile_args(&self, name: &str) -> Result<Vec<String>> {
+        // Find `name` and each profile it inherits from.
+        let mut chain = vec![];
+        let mut next = Some(name.to_owned());
+        while let Some(name) = next {
+            if chain.contains(&name) {
+                return Err(format_err!(
+                    "profile {:?} inherits from itself in {}",
+                    name,
+                    self.path.display(),
+                ));
+            }
+            let profile = self.profile(&name)?;
+            next = match profile.get("inherits") {
+                Some(raw_value) => Some(
+                    raw_value
+                        .as_str()
+                        .ok_or_else(|| {
+                            format_err!(
+                                "expected string, found {:?} in {}",
+                                raw_value,
+                                self.path.display(),
+                            )
+                        })?
+                        .to_owned(),
+                ),
+                None => None,
+            };
+            chain.push(name);
+        }
+
+        // Apply each profile's arguments, starting with the most distant
+        // ancestor.
+        let mut args = vec![];
+        for name in chain.iter().rev() {
+            let profile = self.profile(name)?;
+            let overrides = self.item_string_array(profile.get("args"))?;
+            for arg in &overrides {
+                if flag_name(arg).is_none() || flag_name(arg) == Some("--profile") {
+                    return Err(format_err!(
+                        "expected an argument like `--flag` or `--flag=value` in \
+                         profile {:?}, found {:?} in {}",
+                        name,
+                        arg,
+                        self.path.display(),
+                    ));
+                }
+            }
+            args = override_args(&args, &overrides);
+        }
+        Ok(args)
+    }
+
+    /// Look up the profile `name`.
+    fn profile(&self, name: &str) -> Result<&Table> {
+        self.doc
+            .as_table()
+            .get("profiles")
+            .and_then(|profiles| profiles.as_table())
+            .and_then(|profiles| profiles.get(name))
+            .and_then(|profile| profile.as_table())
+            .ok_or_else(|| {
+                format_err!("no profile {:?} in {}", name, self.path.display())
+            })
+    }
+
     /// Get an array of strings from our config file.
     fn string_array(&self, key: &Key<'_>) -> Result<Vec<String>> {
+        self.item_string_array(self.doc.as_table().get(key.key))
+    }
+
+    /// Get an array of strings from an item in our config file, or an empty
+    /// array if the item is missing.
+    fn item_string_array(&self, raw_value: Option<&Item>) -> Result<Vec<String>> {
         let mut temps = vec![];
-        if let Some(raw_value) = self.doc.as_table().get(key.key) {
+        if let Some(raw_value) = raw_value {
             if let Some(raw_array) = raw_value.as_array() {
                 for raw_item in raw_array.iter() {
                     if let Some(temp) = raw_item.as_str() {
@@ -261,6 +336,30 @@ impl Configuration {
     }
 }
 
+/// The name of the flag in a command-line argument like `--flag` or
+/// `--flag=value`, or `None` if `arg` isn't a long flag.
+pub fn flag_name(arg: &str) -> Option<&str> {
+    if arg.starts_with("--") && arg.len() > 2 {
+        arg.split('=').next()
+    } else {
+        None
+    }
+}
+
+/// Combine `base` and `overrides`, dropping any arguments in `base` which use a
+/// flag that also appears in `overrides`.
+fn override_args(base: &[String], overrides: &[String]) -> Vec<String> {
+    let overridden = overrides
+        .iter()
+        .filter_map(|arg| flag_name(arg))
+        .collect::<Vec<_>>();
+    base.iter()
+        .filter(|arg| flag_name(arg).is_none_or(|flag| !overridden.contains(&flag)))
+        .chain(overrides)
+        .cloned()
+        .collect()
+}
+
 #[test]
 fn temporaries_can_be_added_and_removed() {
     let temp = tempfile::Builder::new()
@@ -282,3 +381,35 @@ fn temporaries_can_be_added_and_removed() {
         .unwrap();
     assert_eq!(config.temporaries().unwrap(), Vec::<String>::new());
 }
+
+#[test]
+fn profiles_can_inherit_arguments() {
+    let config = r#"
+[profiles.base]
+args = ["--temporary=gs://bucket/", "--max-streams=8"]
+
+[profiles.nightly]
+inherits = "base"
+args = ["--max-streams=16", "--if-exists=overwrite"]
+
+[profiles.loop]
+inherits = "loop"
+
+[profiles.bad]
+args = ["-J", "8"]
+"#;
+    let config =
+        Configuration::from_reader(PathBuf::from("test.toml"), config.as_bytes())
+            .unwrap();
+    assert_eq!(
+        config.profile_args("nightly").unwrap(),
+        &[
+            "--temporary=gs://bucket/",
+            "--max-streams=16",
+            "--if-exists=overwrite"
+        ],
+    );
+    assert!(config.profile_args("loop").is_err());
+    assert!(config.profile_args("bad").is_err());
+    assert!(config.profile_args("missing").is_err());
+}
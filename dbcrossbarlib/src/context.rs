@@ -1,9 +1,16 @@
 //! Logging and error-handling context.
 
+use chrono::Utc;
 use slog::{OwnedKV, SendSyncRefUnwindSafeKV};
+use std::{convert::TryFrom, fmt, sync::Arc, time::Instant};
 use tokio::process::Child;
 
+use crate::cancellation::{CancellationToken, Cancelled};
+use crate::clouds::endpoints::ApiEndpoints;
 use crate::common::*;
+use crate::events::{Event, EventBus};
+use crate::file_io::FileIo;
+use crate::progress::ProgressCounter;
 
 /// Context shared by our various asynchronous operations.
 #[derive(Debug, Clone)]
@@ -13,6 +20,22 @@ pub struct Context {
     /// To report asynchronous errors anywhere in the application, send them to
     /// this channel.
     error_sender: mpsc::Sender<Error>,
+    /// Regional or private API endpoints to use instead of the public ones.
+    endpoints: Arc<ApiEndpoints>,
+    /// Where to send structured events. This is shared by all our children.
+    events: EventBus,
+    /// Used to cancel this context and all of its children.
+    cancellation: CancellationToken,
+    /// Should warnings about data loss be treated as errors?
+    deny_warnings: bool,
+    /// If set, drivers which can only read column names should infer column
+    /// types from this many rows of data.
+    infer_schema_rows: Option<usize>,
+    /// `--from-arg` values for drivers which need them to read a schema, like
+    /// the delimiter of a `csv:` file.
+    schema_source_args: Arc<DriverArguments>,
+    /// How should drivers like `csv:` read and write local files?
+    file_io: FileIo,
 }
 
 impl Context {
@@ -20,15 +43,37 @@ impl Context {
     /// returning `()` if they all succeed, or an `Error` as soon as one of them
     /// fails.
     pub fn create(log: Logger) -> (Self, BoxFuture<()>) {
+        Self::create_with_events(log, EventBus::new())
+    }
+
+    /// Like `create`, but send structured events to `events`. This allows
+    /// callers to subscribe to `events` before creating the `log`, so that
+    /// drains from `EventBus::log_drain` can be used.
+    pub fn create_with_events(log: Logger, events: EventBus) -> (Self, BoxFuture<()>) {
         let (error_sender, mut receiver) = mpsc::channel(1);
-        let context = Context { log, error_sender };
+        let context = Context {
+            log,
+            error_sender,
+            endpoints: Arc::new(ApiEndpoints::default()),
+            events,
+            cancellation: CancellationToken::new(),
+            deny_warnings: false,
+            infer_schema_rows: None,
+            schema_source_args: Arc::new(DriverArguments::default()),
+            file_io: FileIo::default(),
+        };
+        let cancellation = context.cancellation.clone();
         let worker_future = async move {
-            match receiver.next().await {
+            let next_error = receiver.next().boxed();
+            let cancelled = cancellation.cancelled().boxed();
+            match futures::future::select(next_error, cancelled).await {
                 // All senders have shut down correctly.
-                None => Ok(()),
+                futures::future::Either::Left((None, _)) => Ok(()),
                 // We received an error from a background worker, so report that
                 // as the result for all our background workers.
-                Some(err) => Err(err),
+                futures::future::Either::Left((Some(err), _)) => Err(err),
+                // Somebody cancelled us, so fail immediately.
+                futures::future::Either::Right(((), _)) => Err(Cancelled.into()),
             }
         };
         (context, worker_future.boxed())
@@ -67,9 +112,176 @@ impl Context {
         Context {
             log: self.log.new(log_kv),
             error_sender: self.error_sender.clone(),
+            endpoints: self.endpoints.clone(),
+            events: self.events.clone(),
+            cancellation: self.cancellation.clone(),
+            deny_warnings: self.deny_warnings,
+            infer_schema_rows: self.infer_schema_rows,
+            schema_source_args: self.schema_source_args.clone(),
+            file_io: self.file_io,
+        }
+    }
+
+    /// Get the event bus associated with this context.
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Send `event` to everybody subscribed to our event bus.
+    pub fn emit(&self, event: Event) {
+        self.events.emit(event);
+    }
+
+    /// Create a counter which reports the bytes and rows sent by `stream` as
+    /// `Event::StreamProgress`. Drivers which move data without passing it
+    /// through a local `CsvStream` can use this to report their progress.
+    pub fn progress_counter(&self, stream: &str) -> Arc<ProgressCounter> {
+        ProgressCounter::new(self.clone(), stream)
+    }
+
+    /// Get the cancellation token associated with this context. Calling
+    /// `cancel` on this token will cancel this context and all its children.
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Return `Err(Cancelled)` if this context has been cancelled. Long-running
+    /// loops should call this regularly.
+    pub fn check_cancelled(&self) -> Result<()> {
+        self.cancellation.check()
+    }
+
+    /// Log a warning, and also emit it as an `Event::Warning`.
+    pub fn warning<S: Into<String>>(&self, message: S) {
+        let message = message.into();
+        warn!(self.log, "{}", message);
+        self.emit(Event::Warning { message });
+    }
+
+    /// Log that something failed with what may be a temporary error, and emit
+    /// an `Event::Retry`.
+    pub(crate) fn retrying(&self, err: &Error) {
+        debug!(self.log, "may retry after error: {}", err);
+        self.emit(Event::Retry {
+            message: err.to_string(),
+        });
+    }
+
+    /// Run `fut`, which executes `sql` against `target`, and emit an
+    /// `Event::SqlExecuted` describing how it went. Callers must remove any
+    /// credentials from `sql` first.
+    pub async fn audit_sql<T, E, F>(
+        &self,
+        target: &str,
+        sql: &str,
+        fut: F,
+    ) -> Result<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: fmt::Display,
+    {
+        let started_at = Utc::now();
+        let start = Instant::now();
+        let result = fut.await;
+        self.emit(Event::SqlExecuted {
+            target: target.to_owned(),
+            sql: sql.to_owned(),
+            started_at: started_at.to_rfc3339(),
+            duration_ms: u64::try_from(start.elapsed().as_millis())
+                .unwrap_or(u64::MAX),
+            error: result.as_ref().err().map(|err| err.to_string()),
+        });
+        result
+    }
+
+    /// Convert this context into one which treats warnings about data loss as
+    /// errors. This is shared by all our children.
+    ///
+    /// This consumes `self`, because any leftover copy of a `Context` would
+    /// keep our background workers from finishing.
+    pub fn with_deny_warnings(self, deny_warnings: bool) -> Self {
+        Context {
+            deny_warnings,
+            ..self
+        }
+    }
+
+    /// Should warnings about data loss be treated as errors?
+    pub fn deny_warnings(&self) -> bool {
+        self.deny_warnings
+    }
+
+    /// Convert this context into one which asks drivers like `csv:` to infer
+    /// column types from the first `rows` rows of data, instead of treating
+    /// every column as text.
+    pub fn with_infer_schema_rows(self, rows: Option<usize>) -> Self {
+        Context {
+            infer_schema_rows: rows,
+            ..self
+        }
+    }
+
+    /// How many rows should drivers look at when inferring column types, if
+    /// they should infer them at all?
+    pub fn infer_schema_rows(&self) -> Option<usize> {
+        self.infer_schema_rows
+    }
+
+    /// Convert this context into one which passes `args` to `Locator::schema`,
+    /// for use when we're reading the schema from our source.
+    pub fn with_schema_source_args(self, args: DriverArguments) -> Self {
+        Context {
+            schema_source_args: Arc::new(args),
+            ..self
+        }
+    }
+
+    /// The `--from-arg` values to use when reading a schema from our source.
+    pub(crate) fn schema_source_args(&self) -> &DriverArguments {
+        &self.schema_source_args
+    }
+
+    /// Convert this context into one which reads and writes local files using
+    /// `file_io`. This is shared by all our children.
+    pub fn with_file_io(self, file_io: FileIo) -> Self {
+        Context { file_io, ..self }
+    }
+
+    /// How should drivers read and write local files?
+    pub fn file_io(&self) -> FileIo {
+        self.file_io
+    }
+
+    /// Get the API endpoints which should be used in this context.
+    pub(crate) fn endpoints(&self) -> &ApiEndpoints {
+        &self.endpoints
+    }
+
+    /// Create a child context which uses any endpoints specified in
+    /// `endpoints`, in addition to our existing endpoints.
+    pub(crate) fn with_endpoints(&self, endpoints: &ApiEndpoints) -> Self {
+        Context {
+            log: self.log.clone(),
+            error_sender: self.error_sender.clone(),
+            endpoints: Arc::new(self.endpoints.merged_with(endpoints)),
+            events: self.events.clone(),
+            cancellation: self.cancellation.clone(),
+            deny_warnings: self.deny_warnings,
+            infer_schema_rows: self.infer_schema_rows,
+            schema_source_args: self.schema_source_args.clone(),
+            file_io: self.file_io,
         }
     }
 
+    /// Create a child context which uses any endpoints specified by
+    /// `bigquery_endpoint`, `storage_endpoint` or `s3_endpoint` in `args`.
+    ///
+    /// Drivers do this themselves, but callers may need to do it before
+    /// calling `Locator::schema`, which doesn't receive any arguments.
+    pub fn with_endpoints_from_args(&self, args: &DriverArguments) -> Result<Self> {
+        Ok(self.with_endpoints(&ApiEndpoints::from_args(args)?))
+    }
+
     /// Spawn an async worker in this context, and report any errors to the
     /// future returned by `create`.
     pub fn spawn_worker<W>(&self, worker: W)
@@ -0,0 +1,210 @@
+//! Explaining why we can't copy data between two locators.
+//!
+//! Copies either go directly from one service to another using
+//! `write_remote_data`, or through `dbcrossbar` using `local_data` and
+//! `write_local_data`, which often need temporary storage. When neither path
+//! works, we try to explain what would make the copy possible.
+
+use itertools::Itertools;
+use std::{error, fmt};
+
+use crate::common::*;
+
+/// Why we couldn't find a way to copy data.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NoCopyPathError {
+    /// We need to stage data in temporary storage with this scheme, but none
+    /// was specified.
+    MissingTemporary {
+        /// The scheme we need, such as `"gs:"`.
+        scheme: String,
+    },
+    /// We would need to stage data in temporary storage with this scheme, but
+    /// we were told not to use temporary storage.
+    TemporaryForbidden {
+        /// The scheme we would need, such as `"gs:"`.
+        scheme: String,
+    },
+    /// We can't read data from `source`, and `dest` can't copy it directly.
+    CannotRead {
+        /// The locator we're copying from.
+        source: String,
+        /// The locator we're copying to.
+        dest: String,
+        /// Schemes which `dest` can copy directly from.
+        remote_sources: Vec<String>,
+        /// Could `dest` have copied directly from `source`, if we hadn't
+        /// needed to transform the data locally?
+        remote_disabled: bool,
+    },
+    /// We can't write data to `dest`, and it can't copy from `source`
+    /// directly.
+    CannotWrite {
+        /// The locator we're copying to.
+        dest: String,
+        /// Schemes which `dest` can copy directly from.
+        remote_sources: Vec<String>,
+    },
+}
+
+impl NoCopyPathError {
+    /// Create an error explaining that we need `--temporary=$SCHEME...`.
+    pub(crate) fn missing_temporary(scheme: &str) -> NoCopyPathError {
+        NoCopyPathError::MissingTemporary {
+            scheme: scheme.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for NoCopyPathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NoCopyPathError::MissingTemporary { scheme } => {
+                let (service, example) = match &scheme[..] {
+                    "gs:" => ("Google Cloud Storage", "gs://BUCKET/TEMP/"),
+                    "s3:" => ("S3", "s3://BUCKET/TEMP/"),
+                    "azblob:" => ("Azure Blob Storage", "azblob://CONTAINER/TEMP/"),
+                    _ => ("temporary storage", "..."),
+                };
+                write!(
+                    f,
+                    "need `--temporary={scheme}//...` argument: this copy stages data \
+                     in {service}, so pass `--temporary={example}`, or set a default \
+                     using `dbcrossbar config add temporary {example}`",
+                    scheme = scheme,
+                    service = service,
+                    example = example,
+                )?;
+                if scheme == "gs:" {
+                    write!(
+                        f,
+                        ". For small BigQuery copies, you can also use \
+                         `--temporary=file:/tmp/dbcrossbar` instead",
+                    )?;
+                }
+                Ok(())
+            }
+            NoCopyPathError::TemporaryForbidden { scheme } => write!(
+                f,
+                "this copy would need to stage data in a temporary {} location, \
+                 but --no-temp was specified",
+                scheme,
+            ),
+            NoCopyPathError::CannotRead {
+                source,
+                dest,
+                remote_sources,
+                remote_disabled,
+            } => {
+                write!(
+                    f,
+                    "cannot copy from {} to {}, because dbcrossbar cannot read data \
+                     from {}",
+                    source, dest, source,
+                )?;
+                if *remote_disabled {
+                    write!(
+                        f,
+                        ". {} could copy it directly, but not when using options \
+                         which transform data locally, like --validate",
+                        dest,
+                    )
+                } else if !remote_sources.is_empty() {
+                    write!(
+                        f,
+                        ". {} can copy directly from {}, so try copying the data to \
+                         one of those first",
+                        dest,
+                        remote_sources.iter().join(" "),
+                    )
+                } else {
+                    Ok(())
+                }
+            }
+            NoCopyPathError::CannotWrite {
+                dest,
+                remote_sources,
+            } => {
+                write!(
+                    f,
+                    "cannot copy to {}, because dbcrossbar cannot write data there",
+                    dest,
+                )?;
+                if !remote_sources.is_empty() {
+                    write!(
+                        f,
+                        ". It can only copy directly from {}, so try copying the data \
+                         to one of those first",
+                        remote_sources.iter().join(" "),
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for NoCopyPathError {}
+
+/// Make sure that we have some way to copy from `source` to `dest`.
+/// `use_remote` should be true if we're planning to call
+/// `dest.write_remote_data(source, ...)`.
+pub fn check_copy_path(
+    source: &dyn Locator,
+    dest: &dyn Locator,
+    use_remote: bool,
+) -> Result<()> {
+    if use_remote {
+        return Ok(());
+    }
+    let source_capabilities = source.capabilities()?;
+    let dest_capabilities = dest.capabilities()?;
+    if !source_capabilities.local_data {
+        return Err(NoCopyPathError::CannotRead {
+            source: source.to_string(),
+            dest: dest.to_string(),
+            remote_sources: dest_capabilities.remote_sources,
+            remote_disabled: dest.supports_write_remote_data(source),
+        }
+        .into());
+    }
+    if !dest_capabilities.write_local_data {
+        return Err(NoCopyPathError::CannotWrite {
+            dest: dest.to_string(),
+            remote_sources: dest_capabilities.remote_sources,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[test]
+fn explains_missing_copy_paths() {
+    use crate::UnparsedLocator;
+
+    let parse = |s: &str| s.parse::<UnparsedLocator>().unwrap().parse(false).unwrap();
+    let csv = parse("csv:out.csv");
+    let sql = parse("postgres-sql:table.sql");
+    let gs = parse("gs://bucket/dir/");
+
+    check_copy_path(csv.as_ref(), gs.as_ref(), false).unwrap();
+
+    let err = check_copy_path(sql.as_ref(), csv.as_ref(), false).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NoCopyPathError>(),
+        Some(&NoCopyPathError::CannotRead {
+            source: "postgres-sql:table.sql".to_owned(),
+            dest: "csv:out.csv".to_owned(),
+            remote_sources: vec![],
+            remote_disabled: false,
+        }),
+    );
+
+    let err = check_copy_path(csv.as_ref(), sql.as_ref(), false).unwrap_err();
+    assert!(err.to_string().contains("cannot write data there"));
+
+    let err = NoCopyPathError::missing_temporary("gs:");
+    assert!(err
+        .to_string()
+        .contains("dbcrossbar config add temporary gs://BUCKET/TEMP/"));
+}
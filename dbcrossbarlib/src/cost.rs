@@ -0,0 +1,261 @@
+//! Estimating what a copy will cost in cloud fees, before we run it.
+//!
+//! These estimates use published on-demand list prices in the US multi-region,
+//! and ignore free tiers, flat-rate reservations and negotiated discounts. They
+//! are intended to flag expensive copies for review, not to predict a bill.
+
+use std::fmt;
+
+use crate::common::*;
+use crate::drivers::{
+    bigquery::{query_bytes_scanned, BigQueryLocator},
+    gs::GsLocator,
+};
+
+/// BigQuery on-demand query pricing, in US dollars per TiB scanned.
+const BIGQUERY_QUERY_USD_PER_TIB: f64 = 5.0;
+
+/// BigQuery active storage pricing, in US dollars per GiB per month.
+const BIGQUERY_STORAGE_USD_PER_GIB_MONTH: f64 = 0.02;
+
+/// Cloud Storage standard storage pricing, in US dollars per GiB per month.
+const GCS_STORAGE_USD_PER_GIB_MONTH: f64 = 0.02;
+
+/// Cloud Storage egress pricing to the internet, in US dollars per GiB. Egress
+/// within the same region is free, so this is a worst case.
+const GCS_EGRESS_USD_PER_GIB: f64 = 0.12;
+
+/// How long we assume temporary files stay in Cloud Storage, in months.
+const TEMPORARY_STORAGE_MONTHS: f64 = 1.0 / 30.0;
+
+/// One GiB, in bytes.
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// One TiB, in bytes.
+const TIB: f64 = GIB * 1024.0;
+
+/// Convert a byte count to `f64` for use in an estimate.
+fn as_f64(bytes: u64) -> f64 {
+    // Losing precision above 2^53 bytes doesn't matter for a rough estimate.
+    #[allow(clippy::cast_precision_loss)]
+    let bytes = bytes as f64;
+    bytes
+}
+
+/// A single billable operation in a copy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostItem {
+    /// What we'll be charged for, such as `"BigQuery bytes scanned"`.
+    pub description: String,
+    /// How many bytes the operation involves, if we know.
+    pub bytes: Option<u64>,
+    /// The approximate cost in US dollars, if we know.
+    pub usd: Option<f64>,
+}
+
+impl CostItem {
+    /// Create a new item costing `usd_per_gib` for each GiB of `bytes`.
+    fn per_gib(description: &str, bytes: Option<u64>, usd_per_gib: f64) -> Self {
+        CostItem {
+            description: description.to_owned(),
+            bytes,
+            usd: bytes.map(|b| as_f64(b) / GIB * usd_per_gib),
+        }
+    }
+}
+
+/// The approximate cost of a copy.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CostEstimate {
+    /// The individual operations we expect to pay for.
+    pub items: Vec<CostItem>,
+}
+
+impl CostEstimate {
+    /// The total cost in US dollars, or `None` if we don't know the cost of
+    /// some item.
+    pub fn total_usd(&self) -> Option<f64> {
+        self.items.iter().map(|item| item.usd).sum()
+    }
+}
+
+impl fmt::Display for CostEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.items.is_empty() {
+            return writeln!(f, "no cloud costs expected");
+        }
+        for item in &self.items {
+            write!(f, "- {}", item.description)?;
+            if let Some(bytes) = item.bytes {
+                write!(f, " ({:.3} GiB)", as_f64(bytes) / GIB)?;
+            }
+            match item.usd {
+                Some(usd) => writeln!(f, ": ${:.2}", usd)?,
+                None => writeln!(f, ": unknown")?,
+            }
+        }
+        match self.total_usd() {
+            Some(usd) => writeln!(f, "total: ${:.2}", usd),
+            None => writeln!(f, "total: unknown"),
+        }
+    }
+}
+
+/// Estimate the cloud fees for copying `from_locator` to `to_locator`, using
+/// `write_remote_data` if `use_remote` is true.
+pub async fn estimate_copy_cost(
+    ctx: &Context,
+    from_locator: &dyn Locator,
+    to_locator: &dyn Locator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+    use_remote: bool,
+) -> Result<CostEstimate> {
+    let from_bigquery = from_locator.as_any().downcast_ref::<BigQueryLocator>();
+    let from_gs = from_locator.as_any().is::<GsLocator>();
+    let to_bigquery = to_locator.as_any().is::<BigQueryLocator>();
+    let to_gs = to_locator.as_any().is::<GsLocator>();
+    let uses_gs_temp = shared_args
+        .clone()
+        .verify(Features::empty())?
+        .temporary_storage()
+        .find_scheme(GsLocator::scheme())
+        .is_some();
+    let dest_args = dest_args.verify(to_locator.driver()?.features())?;
+
+    // Find out how much data we're copying. This is usually measured in a
+    // different format than the data we'll actually write, so it's rough.
+    let estimate = from_locator
+        .estimate(ctx.clone(), source_args.clone())
+        .await?;
+    let bytes = estimate.and_then(|e| e.bytes);
+    debug!(ctx.log(), "estimating costs for {:?} bytes", bytes);
+
+    let mut items = vec![];
+
+    // Reading from BigQuery may require a query.
+    let mut staged_in_gs = false;
+    if let Some(from_bigquery) = from_bigquery {
+        let may_extract = (!use_remote && uses_gs_temp) || (use_remote && to_gs);
+        let scanned = query_bytes_scanned(
+            ctx,
+            from_bigquery,
+            shared_args.clone(),
+            source_args,
+            may_extract,
+        )
+        .await?;
+        items.push(CostItem {
+            description: "BigQuery bytes scanned".to_owned(),
+            bytes: Some(scanned),
+            usd: Some(as_f64(scanned) / TIB * BIGQUERY_QUERY_USD_PER_TIB),
+        });
+        staged_in_gs = !use_remote && uses_gs_temp;
+        if may_extract {
+            items.push(CostItem {
+                description: "BigQuery extract jobs".to_owned(),
+                bytes,
+                usd: Some(0.0),
+            });
+        }
+    }
+
+    // Writing to BigQuery from anywhere except BigQuery usually goes through
+    // Cloud Storage and load jobs.
+    if to_bigquery && from_bigquery.is_none() {
+        if !from_gs && uses_gs_temp {
+            staged_in_gs = true;
+        }
+        items.push(CostItem {
+            description: "BigQuery load jobs".to_owned(),
+            bytes,
+            usd: Some(0.0),
+        });
+    }
+    if to_bigquery {
+        items.push(CostItem::per_gib(
+            "BigQuery storage per month",
+            bytes,
+            BIGQUERY_STORAGE_USD_PER_GIB_MONTH,
+        ));
+        if let IfExists::Upsert(_) = dest_args.if_exists() {
+            // A `MERGE` scans both the new data and the existing table. If we
+            // can't look up the table, it probably doesn't exist yet.
+            let existing = match to_locator
+                .estimate(
+                    ctx.clone(),
+                    SourceArguments::new(DriverArguments::default(), None),
+                )
+                .await
+            {
+                Ok(estimate) => estimate.and_then(|e| e.bytes),
+                Err(_) => Some(0),
+            };
+            let merged = match (bytes, existing) {
+                (Some(bytes), Some(existing)) => Some(bytes + existing),
+                _ => None,
+            };
+            items.push(CostItem {
+                description: "BigQuery bytes scanned by MERGE".to_owned(),
+                bytes: merged,
+                usd: merged.map(|b| as_f64(b) / TIB * BIGQUERY_QUERY_USD_PER_TIB),
+            });
+        }
+    }
+
+    // Temporary files in Cloud Storage.
+    if staged_in_gs {
+        items.push(CostItem::per_gib(
+            "Cloud Storage temporary storage",
+            bytes,
+            GCS_STORAGE_USD_PER_GIB_MONTH * TEMPORARY_STORAGE_MONTHS,
+        ));
+    }
+
+    // Downloading from Cloud Storage to this machine.
+    if !use_remote && (from_gs || (from_bigquery.is_some() && uses_gs_temp)) {
+        items.push(CostItem::per_gib(
+            "Cloud Storage egress",
+            bytes,
+            GCS_EGRESS_USD_PER_GIB,
+        ));
+    }
+
+    // Final files in Cloud Storage.
+    if to_gs {
+        items.push(CostItem::per_gib(
+            "Cloud Storage storage per month",
+            bytes,
+            GCS_STORAGE_USD_PER_GIB_MONTH,
+        ));
+    }
+
+    Ok(CostEstimate { items })
+}
+
+#[test]
+fn totals_cost_items() {
+    let mut estimate = CostEstimate {
+        items: vec![
+            CostItem::per_gib("egress", Some(10 * 1024 * 1024 * 1024), 0.12),
+            CostItem {
+                description: "load jobs".to_owned(),
+                bytes: None,
+                usd: Some(0.0),
+            },
+        ],
+    };
+    assert!((estimate.total_usd().unwrap() - 1.2).abs() < 1e-9);
+    assert!(estimate
+        .to_string()
+        .contains("- egress (10.000 GiB): $1.20"));
+
+    estimate.items.push(CostItem::per_gib(
+        "storage",
+        None,
+        GCS_STORAGE_USD_PER_GIB_MONTH,
+    ));
+    assert_eq!(estimate.total_usd(), None);
+    assert!(estimate.to_string().contains("total: unknown"));
+}
@@ -7,10 +7,12 @@ use std::{
     collections::HashMap,
     env, fmt,
     path::PathBuf,
+    process::Stdio,
     time::{Duration, Instant},
 };
-use tokio::{fs, sync::Mutex};
+use tokio::{fs, process::Command, sync::Mutex};
 
+use crate::clouds::aws::TemporaryCredentials;
 use crate::common::*;
 use crate::config::config_dir;
 
@@ -84,11 +86,15 @@ impl CredentialsManager {
         let config_dir = config_dir()?;
 
         // Specify how to connect to AWS.
-        let aws = EnvCredentialsSource::new(vec![
-            EnvMapping::required("access_key_id", "AWS_ACCESS_KEY_ID"),
-            EnvMapping::required("secret_access_key", "AWS_SECRET_ACCESS_KEY"),
-            EnvMapping::optional("session_token", "AWS_SESSION_TOKEN"),
-            EnvMapping::required("default_region", "AWS_DEFAULT_REGION"),
+        let aws = CredentialsSources::new(vec![
+            EnvCredentialsSource::new(vec![
+                EnvMapping::required("access_key_id", "AWS_ACCESS_KEY_ID"),
+                EnvMapping::required("secret_access_key", "AWS_SECRET_ACCESS_KEY"),
+                EnvMapping::optional("session_token", "AWS_SESSION_TOKEN"),
+                EnvMapping::optional("default_region", "AWS_DEFAULT_REGION"),
+            ])
+            .boxed(),
+            AwsCliCredentialsSource.boxed(),
         ]);
         sources.insert("aws".to_owned(), Mutex::new(aws.boxed()));
 
@@ -135,6 +141,18 @@ impl CredentialsManager {
         )]);
         sources.insert("shopify".to_owned(), Mutex::new(shopify_secret.boxed()));
 
+        // Specify how to connect to Azure Storage. We need either an account
+        // key or a SAS token, which `AzureStorageCredentials` checks for us.
+        let azure_storage = EnvCredentialsSource::new(vec![
+            EnvMapping::required("account", "AZURE_STORAGE_ACCOUNT"),
+            EnvMapping::optional("account_key", "AZURE_STORAGE_KEY"),
+            EnvMapping::optional("sas_token", "AZURE_STORAGE_SAS_TOKEN"),
+        ]);
+        sources.insert(
+            "azure_storage".to_owned(),
+            Mutex::new(azure_storage.boxed()),
+        );
+
         let cache = Mutex::new(HashMap::new());
         Ok(CredentialsManager { sources, cache })
     }
@@ -365,6 +383,66 @@ impl CredentialsSource for FileCredentialsSource {
     }
 }
 
+/// Ask the `aws` CLI for credentials, which handles profiles, SSO, instance
+/// metadata and the rest of the standard AWS credential chain.
+#[derive(Debug)]
+struct AwsCliCredentialsSource;
+
+impl fmt::Display for AwsCliCredentialsSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "- The AWS CLI's credential chain (`aws configure export-credentials`)"
+        )
+    }
+}
+
+#[async_trait]
+impl CredentialsSource for AwsCliCredentialsSource {
+    async fn get_credentials(&self) -> Result<Option<Credentials>> {
+        // If `aws` isn't installed, or it can't find any credentials, then
+        // this source has nothing for us.
+        let output = Command::new("aws")
+            .args(["configure", "export-credentials", "--format", "process"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .await;
+        let output = match output {
+            Ok(output) if output.status.success() => output,
+            _ => return Ok(None),
+        };
+        let exported = serde_json::from_slice::<TemporaryCredentials>(&output.stdout)
+            .context("could not parse `aws configure export-credentials` output")?;
+
+        let mut data = HashMap::new();
+        data.insert("access_key_id".to_owned(), exported.access_key_id.clone());
+        data.insert(
+            "secret_access_key".to_owned(),
+            exported.secret_access_key.clone(),
+        );
+        if let Some(session_token) = &exported.session_token {
+            data.insert("session_token".to_owned(), session_token.to_owned());
+        }
+        let region = Command::new("aws")
+            .args(["configure", "get", "region"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .await;
+        if let Ok(region) = region {
+            let region = String::from_utf8_lossy(&region.stdout).trim().to_owned();
+            if !region.is_empty() {
+                data.insert("default_region".to_owned(), region);
+            }
+        }
+        Ok(Some(Credentials {
+            data,
+            expires: exported.expires()?,
+        }))
+    }
+}
+
 /// Look in multiple places for credentials.
 #[derive(Debug)]
 struct CredentialsSources {
@@ -0,0 +1,283 @@
+//! Detecting and decompressing compressed input streams.
+//!
+//! Files in data lakes are often compressed without a matching extension, so
+//! we look at the first few bytes of each stream instead of its name.
+
+use flate2::read::MultiGzDecoder;
+use std::{
+    fs::File,
+    io::{self, Seek, SeekFrom},
+    path::Path,
+    process::{self, Stdio},
+};
+use tokio::{io::BufReader, process::Command};
+
+use crate::common::*;
+use crate::tokio_glue::copy_reader_to_stream;
+use crate::transform::spawn_sync_transform;
+
+/// How many bytes we need to see to recognize any of our formats.
+const MAGIC_LEN: usize = 10;
+
+/// A compression format that we can recognize.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Recognize a compression format from the first bytes of a stream, or
+    /// return `None` if the data doesn't look compressed.
+    pub(crate) fn detect(prefix: &[u8]) -> Option<Compression> {
+        if prefix.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if prefix.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if prefix.len() >= MAGIC_LEN
+            && prefix.starts_with(b"BZh")
+            && (b'1'..=b'9').contains(&prefix[3])
+            && &prefix[4..MAGIC_LEN] == b"1AY&SY"
+        {
+            // `BZh` is followed by a block size and the magic number of the
+            // first block, which keeps us from mistaking a CSV header like
+            // `BZh,...` for compressed data.
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    /// The external command that can decompress this format. We never run
+    /// `gzip`, because we decompress `gzip` data ourselves, but `zstd` and
+    /// `bzip2` data can only be read if the matching command is installed.
+    fn command(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+}
+
+/// If `data` is compressed, return a stream of decompressed data. Otherwise
+/// return the original data.
+pub(crate) async fn decompress_stream(
+    ctx: &Context,
+    mut data: BoxStream<BytesMut>,
+) -> Result<BoxStream<BytesMut>> {
+    // Read enough of our stream to recognize a compression format.
+    let mut prefix = BytesMut::new();
+    while prefix.len() < MAGIC_LEN {
+        match data.next().await {
+            Some(chunk) => prefix.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    let compression = Compression::detect(&prefix);
+    let prefix = if prefix.is_empty() {
+        None
+    } else {
+        Some(Ok(prefix))
+    };
+    let data = stream::iter(prefix).chain(data).boxed();
+
+    match compression {
+        None => Ok(data),
+        Some(Compression::Gzip) => {
+            debug!(ctx.log(), "decompressing gzip data");
+            spawn_sync_transform(
+                ctx.clone(),
+                "gunzip".to_owned(),
+                data,
+                |_ctx, rdr, mut wtr| {
+                    // `gzip -dc` accepts concatenated members, so we do, too.
+                    let mut decoder = MultiGzDecoder::new(rdr);
+                    io::copy(&mut decoder, &mut wtr)
+                        .context("error decompressing gzip data")?;
+                    wtr.flush().context("error decompressing gzip data")?;
+                    Ok(())
+                },
+            )
+        }
+        Some(compression) => {
+            debug!(
+                ctx.log(),
+                "decompressing {:?} data using `{}`",
+                compression,
+                compression.command(),
+            );
+            pipe_through_command(ctx, data, compression.command(), "-dc")
+        }
+    }
+}
+
+/// Run `command arg`, feeding it `data` on standard input, and return its
+/// standard output as a stream.
+fn pipe_through_command(
+    ctx: &Context,
+    mut data: BoxStream<BytesMut>,
+    command: &'static str,
+    arg: &'static str,
+) -> Result<BoxStream<BytesMut>> {
+    let mut child = Command::new(command)
+        .arg(arg)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| missing_tool_error(err, command, "decompress this data"))
+        .with_context(|_| format!("error running `{} {}`", command, arg))?;
+
+    // Feed our data to the child process in the background.
+    let mut child_stdin = child.stdin.take().expect("child should have stdin");
+    let worker_ctx = ctx.clone();
+    let worker: BoxFuture<()> = async move {
+        while let Some(chunk) = data.next().await {
+            child_stdin
+                .write_all(&chunk?)
+                .await
+                .with_context(|_| format!("error piping to `{}`", command))?;
+        }
+        child_stdin
+            .shutdown()
+            .await
+            .with_context(|_| format!("error shutting down pipe to `{}`", command))?;
+        trace!(worker_ctx.log(), "finished writing data to `{}`", command);
+        Ok(())
+    }
+    .boxed();
+    ctx.spawn_worker(worker);
+
+    let child_stdout = child.stdout.take().expect("child should have stdout");
+    let child_stdout = BufReader::with_capacity(BUFFER_SIZE, child_stdout);
+    let output = copy_reader_to_stream(ctx.clone(), child_stdout)?;
+    ctx.spawn_process(format!("{} {}", command, arg), child);
+    Ok(output.boxed())
+}
+
+/// Convert an error from running the external command `tool` into a friendlier
+/// error if `tool` isn't installed, as in our `FROM scratch` image.
+pub(crate) fn missing_tool_error(err: io::Error, tool: &str, purpose: &str) -> Error {
+    if err.kind() == io::ErrorKind::NotFound {
+        format_err!(
+            "cannot {} because the `{}` command is not installed (static builds \
+             and `FROM scratch` images can only read uncompressed or gzip data)",
+            purpose,
+            tool,
+        )
+    } else {
+        err.into()
+    }
+}
+
+/// Open `path` for synchronous reading, decompressing it if necessary. This is
+/// intended for reading small amounts of data, such as CSV headers.
+pub(crate) fn open_decompressed(path: &Path) -> Result<Box<dyn Read>> {
+    let mut file = File::open(path)
+        .with_context(|_| format!("error opening {}", path.display()))?;
+    let mut prefix = vec![];
+    (&mut file)
+        .take(MAGIC_LEN as u64)
+        .read_to_end(&mut prefix)
+        .with_context(|_| format!("error reading {}", path.display()))?;
+    file.seek(SeekFrom::Start(0))?;
+    match Compression::detect(&prefix) {
+        None => Ok(Box::new(file)),
+        Some(Compression::Gzip) => Ok(Box::new(MultiGzDecoder::new(file))),
+        Some(compression) => {
+            let command = compression.command();
+            let child = process::Command::new(command)
+                .arg("-dc")
+                .stdin(file)
+                .stdout(Stdio::piped())
+                .spawn()
+                .map_err(|err| {
+                    missing_tool_error(err, command, "decompress this data")
+                })
+                .with_context(|_| format!("error running `{} -dc`", command))?;
+            Ok(Box::new(ChildReader { child }))
+        }
+    }
+}
+
+/// Reads the standard output of a child process, and kills the child process
+/// when dropped, in case we didn't read all its output.
+struct ChildReader {
+    child: process::Child,
+}
+
+impl Read for ChildReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.child
+            .stdout
+            .as_mut()
+            .expect("child should have stdout")
+            .read(buf)
+    }
+}
+
+impl Drop for ChildReader {
+    fn drop(&mut self) {
+        // Errors here just mean that the child has already exited.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn detects_compression_formats() {
+    assert_eq!(
+        Compression::detect(&[0x1f, 0x8b, 0x08, 0x00]),
+        Some(Compression::Gzip),
+    );
+    assert_eq!(
+        Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x24]),
+        Some(Compression::Zstd),
+    );
+    assert_eq!(
+        Compression::detect(b"BZh91AY&SY\x00"),
+        Some(Compression::Bzip2),
+    );
+    assert_eq!(Compression::detect(b"BZh,name\n1,x\n"), None);
+    assert_eq!(Compression::detect(b"id,name\n"), None);
+    assert_eq!(Compression::detect(b""), None);
+}
+
+#[test]
+fn opens_concatenated_gzip_files() {
+    use flate2::{write::GzEncoder, Compression as GzCompression};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.csv.gz");
+    let mut file = File::create(&path).unwrap();
+    for chunk in &["id,name\n", "1,x\n"] {
+        let mut encoder = GzEncoder::new(&mut file, GzCompression::default());
+        encoder.write_all(chunk.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+    }
+    drop(file);
+
+    let mut data = String::new();
+    open_decompressed(&path)
+        .unwrap()
+        .read_to_string(&mut data)
+        .unwrap();
+    assert_eq!(data, "id,name\n1,x\n");
+}
+
+#[test]
+fn explains_missing_tools() {
+    let err = missing_tool_error(
+        io::Error::new(io::ErrorKind::NotFound, "not found"),
+        "zstd",
+        "decompress this data",
+    );
+    assert!(format!("{}", err).contains("`zstd` command is not installed"));
+    let err = missing_tool_error(
+        io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+        "zstd",
+        "decompress this data",
+    );
+    assert_eq!(format!("{}", err), "denied");
+}
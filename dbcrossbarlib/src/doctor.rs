@@ -0,0 +1,427 @@
+//! Diagnose common problems with credentials, tools and connectivity.
+
+use std::{fmt, process::Stdio, time::Duration};
+use tokio::{net::TcpStream, process::Command, time::timeout};
+
+use crate::clouds::{
+    aws::{s3, AwsCredentials},
+    azure::{blob, AzureStorageCredentials},
+    gcloud::{auth, storage},
+};
+use crate::common::*;
+use crate::config::{config_file, Configuration};
+use crate::credentials::CredentialsManager;
+use crate::drivers::find_driver;
+
+/// How long should we wait for any single remote check?
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The result of a single check.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CheckStatus {
+    /// Everything looks fine.
+    Ok,
+    /// Something is missing, but we don't need it for the locators we were
+    /// asked about.
+    Warning,
+    /// Something we need is missing or broken.
+    Failed,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Use `pad` so that callers can align our output.
+        f.pad(match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warning => "WARN",
+            CheckStatus::Failed => "FAIL",
+        })
+    }
+}
+
+/// A single diagnostic check.
+#[derive(Clone, Debug)]
+pub struct Check {
+    /// What did we check?
+    pub name: String,
+    /// Did the check pass?
+    pub status: CheckStatus,
+    /// What did we find?
+    pub message: String,
+    /// How can the user fix this, if the check didn't pass?
+    pub fix: Option<String>,
+}
+
+impl Check {
+    /// Construct a check which passed.
+    fn ok<S: Into<String>>(name: S, message: S) -> Check {
+        Check {
+            name: name.into(),
+            status: CheckStatus::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    /// Construct a check which failed with `err`. If `required` is false, this
+    /// will only be reported as a warning.
+    fn problem<S: Into<String>>(
+        name: S,
+        required: bool,
+        err: &Error,
+        fix: S,
+    ) -> Check {
+        Check {
+            name: name.into(),
+            status: if required {
+                CheckStatus::Failed
+            } else {
+                CheckStatus::Warning
+            },
+            message: format_error(err),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Format `err` and its causes on a single line.
+fn format_error(err: &Error) -> String {
+    err.iter_chain()
+        .map(|cause| cause.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Check our configuration, credentials and external tools, plus any
+/// `temporaries` and `locators` that we were asked about.
+pub async fn run_checks(
+    ctx: &Context,
+    config: &Configuration,
+    temporaries: &[String],
+    locators: &[String],
+    enable_unstable: bool,
+) -> Vec<Check> {
+    // Combine our temporaries with those in our config file.
+    let mut checks = vec![];
+    let mut all_temporaries = temporaries.to_owned();
+    match config.temporaries() {
+        Ok(config_temporaries) => all_temporaries.extend(config_temporaries),
+        Err(err) => checks.push(Check::problem(
+            "config",
+            true,
+            &err,
+            "fix the `temporary` key in your dbcrossbar.toml",
+        )),
+    }
+    match config_file() {
+        Ok(path) => checks.push(Check::ok(
+            "config".to_owned(),
+            format!("using {}", path.display()),
+        )),
+        Err(err) => checks.push(Check::problem(
+            "config",
+            true,
+            &err,
+            "set DBCROSSBAR_CONFIG_DIR to a writable directory",
+        )),
+    }
+
+    // Figure out what we need to check.
+    let all = all_temporaries
+        .iter()
+        .chain(locators.iter())
+        .collect::<Vec<_>>();
+    let uses = |schemes: &[&str]| {
+        all.iter()
+            .any(|l| schemes.iter().any(|scheme| l.starts_with(scheme)))
+    };
+    let needs_aws = uses(&["s3:", "redshift:"]);
+    let needs_gcloud = uses(&["gs:", "bigquery:"]);
+    let needs_shopify = uses(&["shopify:"]);
+    let needs_azure = uses(&["azblob:"]);
+
+    // Check our credentials and tools.
+    checks.push(check_aws_cli(needs_aws).await);
+    checks.push(check_credentials("aws", "aws", needs_aws).await);
+    checks.push(check_gcloud_credentials(ctx, needs_gcloud).await);
+    if needs_shopify {
+        checks.push(check_credentials("shopify", "shopify", true).await);
+    }
+    if needs_azure {
+        checks.push(check_azure_credentials().await);
+    }
+
+    // Check each of our temporaries and locators.
+    for temporary in &all_temporaries {
+        checks.push(check_temporary(ctx, temporary).await);
+    }
+    for locator in locators {
+        checks.extend(check_locator(ctx, locator, enable_unstable).await);
+    }
+    checks
+}
+
+/// Check that we can run `aws`.
+async fn check_aws_cli(required: bool) -> Check {
+    let result = async {
+        let output = Command::new("aws")
+            .arg("--version")
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .context("could not run `aws --version`")?;
+        if output.status.success() {
+            // Older versions print to stderr, newer ones to stdout.
+            let mut version = String::from_utf8_lossy(&output.stdout).into_owned();
+            version.push_str(&String::from_utf8_lossy(&output.stderr));
+            Ok(version.trim().to_owned())
+        } else {
+            Err(format_err!("`aws --version` failed: {}", output.status))
+        }
+    };
+    match result.await {
+        Ok(version) => Check::ok("aws CLI".to_owned(), version),
+        Err(err) => Check::problem(
+            "aws CLI",
+            required,
+            &err,
+            "install the AWS CLI (needed for s3: and redshift:), and make sure `aws` is on your PATH",
+        ),
+    }
+}
+
+/// Check that we can find the credentials `name`.
+async fn check_credentials(label: &str, name: &str, required: bool) -> Check {
+    let label = format!("{} credentials", label);
+    match CredentialsManager::singleton().get(name).await {
+        Ok(_) => Check::ok(label, "found".to_owned()),
+        Err(err) => {
+            let fix = format!(
+                "set up {} credentials as described in the driver documentation",
+                name
+            );
+            Check::problem(label, required, &err, fix)
+        }
+    }
+}
+
+/// Check that we can find Google Cloud credentials.
+async fn check_gcloud_credentials(ctx: &Context, required: bool) -> Check {
+    let label = "gcloud credentials";
+    match timeout(REMOTE_TIMEOUT, auth::find_credentials(ctx)).await {
+        Ok(Ok(source)) => Check::ok(label.to_owned(), format!("found {}", source)),
+        Ok(Err(err)) => Check::problem(
+            label,
+            required,
+            &err,
+            "set GCLOUD_SERVICE_ACCOUNT_KEY or GOOGLE_APPLICATION_CREDENTIALS, run `gcloud auth application-default login`, or see the gs: driver documentation",
+        ),
+        Err(_) => Check::problem(
+            label,
+            required,
+            &format_err!("timed out after {:?}", REMOTE_TIMEOUT),
+            "check your network connection",
+        ),
+    }
+}
+
+/// Check that we can find Azure Storage credentials.
+async fn check_azure_credentials() -> Check {
+    let label = "azure credentials";
+    match AzureStorageCredentials::try_default().await {
+        Ok(creds) => Check::ok(
+            label.to_owned(),
+            format!("found account {}", creds.account),
+        ),
+        Err(err) => Check::problem(
+            label,
+            true,
+            &err,
+            "set AZURE_STORAGE_ACCOUNT and either AZURE_STORAGE_KEY or AZURE_STORAGE_SAS_TOKEN",
+        ),
+    }
+}
+
+/// Check that we can access a `--temporary` location.
+async fn check_temporary(ctx: &Context, temporary: &str) -> Check {
+    let name = format!("temporary {}", temporary);
+    let fix = "make sure the bucket exists, and that your credentials can list and write to it";
+    let result = if temporary.starts_with("gs:") {
+        check_bucket_access(ctx, temporary, false).await
+    } else if temporary.starts_with("s3:") {
+        check_bucket_access(ctx, temporary, true).await
+    } else if temporary.starts_with("azblob:") {
+        check_azblob_access(ctx, temporary).await
+    } else if temporary.starts_with("bigquery:") {
+        return Check::ok(
+            name,
+            "not checked (BigQuery datasets are checked when used)".to_owned(),
+        );
+    } else {
+        Err(format_err!("unknown kind of temporary storage"))
+    };
+    match result {
+        Ok(()) => Check::ok(name, "accessible".to_owned()),
+        Err(err) => Check::problem(name, true, &err, fix.to_owned()),
+    }
+}
+
+/// Check that we can list a `gs://` or `s3://` URL.
+async fn check_bucket_access(ctx: &Context, url: &str, is_s3: bool) -> Result<()> {
+    let url = url
+        .parse::<Url>()
+        .with_context(|_| format!("cannot parse {}", url))?;
+    let fut = async {
+        if is_s3 {
+            let creds = AwsCredentials::try_default().await?;
+            s3::check_access(ctx, &creds, &url).await
+        } else {
+            storage::check_access(ctx, &url).await
+        }
+    };
+    timeout(REMOTE_TIMEOUT, fut)
+        .await
+        .map_err(|_| format_err!("timed out after {:?}", REMOTE_TIMEOUT))?
+}
+
+/// Check that we can list the blobs in an `azblob://` location.
+async fn check_azblob_access(ctx: &Context, url: &str) -> Result<()> {
+    let url = url
+        .parse::<Url>()
+        .with_context(|_| format!("cannot parse {}", url))?;
+    let fut = async {
+        let client = blob::Client::new(ctx).await?;
+        blob::ls(ctx, &client, &url).await
+    };
+    timeout(REMOTE_TIMEOUT, fut)
+        .await
+        .map_err(|_| format_err!("timed out after {:?}", REMOTE_TIMEOUT))??;
+    Ok(())
+}
+
+/// Check that we can parse `locator`, and that it looks reachable.
+async fn check_locator(
+    ctx: &Context,
+    locator: &str,
+    enable_unstable: bool,
+) -> Vec<Check> {
+    let name = format!("locator {}", locator);
+    let parsed = match locator.parse::<crate::UnparsedLocator>() {
+        Ok(unparsed) => unparsed.parse(enable_unstable),
+        Err(err) => Err(err),
+    };
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            return vec![Check::problem(
+                name,
+                true,
+                &err,
+                "check the locator syntax, and run `dbcrossbar features` to list drivers (some need --enable-unstable)".to_owned(),
+            )]
+        }
+    };
+    let mut checks = vec![Check::ok(name.clone(), "parsed".to_owned())];
+
+    // For database servers, make sure we can open a network connection.
+    if let Some(default_port) = default_port(locator) {
+        let result = check_tcp(locator, default_port).await;
+        checks.push(match result {
+            Ok(addr) => Check::ok(name.clone(), format!("connected to {}", addr)),
+            Err(err) => Check::problem(
+                name.clone(),
+                true,
+                &err,
+                "make sure the server is running, and that no firewall is blocking the connection".to_owned(),
+            ),
+        });
+    }
+
+    // For cloud directories, make sure we can list files.
+    if locator.starts_with("gs:") || locator.starts_with("s3:") {
+        let result =
+            check_bucket_access(ctx, locator, locator.starts_with("s3:")).await;
+        checks.push(match result {
+            Ok(()) => Check::ok(name.clone(), "accessible".to_owned()),
+            Err(err) => Check::problem(
+                name.clone(),
+                true,
+                &err,
+                "make sure the bucket exists, and that your credentials can access it"
+                    .to_owned(),
+            ),
+        });
+    }
+
+    // If this driver can read schemas, try it. This checks authentication, and
+    // that the table exists.
+    let can_read_schema = find_driver(parsed_scheme(locator), enable_unstable)
+        .map(|driver| driver.features().locator.contains(LocatorFeatures::Schema))
+        .unwrap_or(false);
+    if can_read_schema {
+        let result = timeout(REMOTE_TIMEOUT, parsed.schema(ctx.clone()))
+            .await
+            .map_err(|_| format_err!("timed out after {:?}", REMOTE_TIMEOUT))
+            .and_then(|result| result);
+        checks.push(match result {
+            Ok(Some(table)) => Check::ok(
+                name,
+                format!("read schema with {} columns", table.columns.len()),
+            ),
+            Ok(None) => Check::ok(name, "no schema available".to_owned()),
+            Err(err) => Check::problem(
+                name,
+                false,
+                &err,
+                "make sure the table exists and that your credentials can read it (this is expected if `cp` will create it)".to_owned(),
+            ),
+        });
+    }
+    checks
+}
+
+/// Get the scheme of `locator`, including the trailing colon.
+fn parsed_scheme(locator: &str) -> &str {
+    match locator.find(':') {
+        Some(idx) => &locator[..=idx],
+        None => locator,
+    }
+}
+
+/// If `locator` refers to a database server, return its default port.
+fn default_port(locator: &str) -> Option<u16> {
+    match parsed_scheme(locator) {
+        "mysql:" => Some(3306),
+        "postgres:" => Some(5432),
+        "redshift:" => Some(5439),
+        _ => None,
+    }
+}
+
+/// Try to open a TCP connection to the server in `locator`.
+async fn check_tcp(locator: &str, default_port: u16) -> Result<String> {
+    let url = locator
+        .parse::<Url>()
+        .with_context(|_| format!("cannot parse {}", locator))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| format_err!("no host in locator"))?;
+    let addr = format!("{}:{}", host, url.port().unwrap_or(default_port));
+    timeout(REMOTE_TIMEOUT, TcpStream::connect(&addr))
+        .await
+        .map_err(|_| format_err!("timed out connecting to {}", addr))?
+        .with_context(|_| format!("could not connect to {}", addr))?;
+    Ok(addr)
+}
+
+#[test]
+fn locator_helpers() {
+    assert_eq!(parsed_scheme("postgres://localhost/db#t"), "postgres:");
+    assert_eq!(default_port("postgres://localhost/db#t"), Some(5432));
+    assert_eq!(default_port("redshift://example.com/db#t"), Some(5439));
+    assert_eq!(default_port("mysql://localhost/db#t"), Some(3306));
+    assert_eq!(default_port("csv:foo.csv"), None);
+}
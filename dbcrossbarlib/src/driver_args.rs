@@ -1,6 +1,9 @@
-use serde::de::DeserializeOwned;
+use serde::{
+    de::{self, DeserializeOwned},
+    Deserialize, Deserializer,
+};
 use serde_json::{Map, Value};
-use std::{ops::Range, str::FromStr, sync::Arc};
+use std::{fmt, ops::Range, str::FromStr, sync::Arc, time::Duration};
 
 use crate::common::*;
 use crate::parse_error::{Annotation, FileInfo, ParseError};
@@ -54,6 +57,59 @@ impl DriverArguments {
     }
 }
 
+/// Driver arguments are always passed as strings, so parse numbers and other
+/// values from strings. Use this with `#[serde(default, deserialize_with =
+/// "deserialize_opt_from_str")]`.
+pub(crate) fn deserialize_opt_from_str<'de, T, D>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error>
+where
+    T: FromStr,
+    <T as FromStr>::Err: fmt::Display,
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse::<T>()
+        .map(Some)
+        .map_err(|err| de::Error::custom(format!("cannot parse {:?}: {}", s, err)))
+}
+
+/// Deserialize a duration like `"90s"`, `"30m"`, `"24h"` or `"7d"`. Use with
+/// `#[serde(default, deserialize_with = "deserialize_opt_duration")]`.
+pub(crate) fn deserialize_opt_duration<'de, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_duration(&s)
+        .map(Some)
+        .map_err(|err| de::Error::custom(format!("cannot parse {:?}: {}", s, err)))
+}
+
+/// Parse a number followed by a unit of `s`, `m`, `h` or `d`.
+fn parse_duration(s: &str) -> Result<Duration> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format_err!("expected a unit of s, m, h or d"))?;
+    let (count, unit) = s.split_at(split);
+    let count = count
+        .parse::<u64>()
+        .map_err(|_| format_err!("expected a number followed by a unit"))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format_err!("unknown unit {:?}", unit)),
+    };
+    let secs = count
+        .checked_mul(unit_secs)
+        .ok_or_else(|| format_err!("duration is too long"))?;
+    Ok(Duration::from_secs(secs))
+}
+
 #[test]
 fn to_json_handles_nested_keys() {
     use serde_json::json;
@@ -230,3 +286,14 @@ fn insert_into_json(
         insert_into_json(place, file_info, &path[1..], value)
     }
 }
+
+#[test]
+fn parses_durations() {
+    assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+    assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1_800));
+    assert_eq!(parse_duration("24h").unwrap(), Duration::from_secs(86_400));
+    assert_eq!(parse_duration("7d").unwrap(), Duration::from_secs(604_800));
+    for bad in &["", "24", "h", "1.5h", "2w", "-1h"] {
+        assert!(parse_duration(bad).is_err(), "should not parse {:?}", bad);
+    }
+}
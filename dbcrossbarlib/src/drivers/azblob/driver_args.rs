@@ -0,0 +1,26 @@
+//! Driver arguments for `azblob://` sources and destinations.
+
+use serde::Deserialize;
+
+use crate::clouds::endpoints::{ApiEndpoint, ApiEndpoints};
+use crate::driver_args::deserialize_opt_from_str;
+
+/// Parsed version of `--from-arg` and `--to-arg` for `azblob://` locators.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AzblobArguments {
+    /// Send requests here instead of `https://$ACCOUNT.blob.core.windows.net/`,
+    /// for emulators like Azurite.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    azure_blob_endpoint: Option<ApiEndpoint>,
+}
+
+impl AzblobArguments {
+    /// Any API endpoint overrides specified by these arguments.
+    pub(crate) fn endpoints(&self) -> ApiEndpoints {
+        ApiEndpoints {
+            azure_blob_endpoint: self.azure_blob_endpoint.clone(),
+            ..ApiEndpoints::default()
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Reading data from Azure Blob Storage.
+
+use super::{driver_args::AzblobArguments, AzblobLocator};
+use crate::clouds::azure::blob::{self, Client};
+use crate::common::*;
+use crate::csv_stream::csv_stream_name;
+use crate::decompress::decompress_stream;
+
+/// Implementation of `local_data`, but as a real `async` function.
+pub(crate) async fn local_data_helper(
+    ctx: Context,
+    url: Url,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<BoxStream<CsvStream>>> {
+    let _shared_args = shared_args.verify(AzblobLocator::features())?;
+    let source_args = source_args.verify(AzblobLocator::features())?;
+    let azblob_args = source_args
+        .driver_args()
+        .deserialize::<AzblobArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&azblob_args.endpoints());
+    let client = Client::new(&ctx).await?;
+
+    debug!(ctx.log(), "getting CSV files from {}", url);
+
+    // List our blobs, which are already sorted by name.
+    let file_urls = blob::ls(&ctx, &client, &url).await?;
+
+    // Convert into `CsvStream` values lazily, downloading each file when our
+    // consumer asks for it.
+    let csv_streams = stream::iter(file_urls).map(Ok).and_then(move |file_url| {
+        let ctx = ctx.clone();
+        let url = url.clone();
+        let client = client.clone();
+        async move {
+            // Stream the file from the cloud.
+            let name = csv_stream_name(url.as_str(), file_url.as_str())?.to_owned();
+            let ctx = ctx.child(
+                o!("stream" => name.clone(), "url" => file_url.as_str().to_owned()),
+            );
+            let data = blob::download_file(&ctx, &client, &file_url).await?;
+            let data = decompress_stream(&ctx, data).await?;
+
+            // Assemble everything into a CSV stream.
+            Ok(CsvStream { name, data })
+        }
+        .boxed()
+    });
+
+    Ok(Some(csv_streams.boxed()))
+}
@@ -0,0 +1,133 @@
+//! Support for Azure Blob Storage.
+
+use std::{fmt, str::FromStr};
+
+use crate::common::*;
+use crate::copy_path::NoCopyPathError;
+
+mod driver_args;
+mod local_data;
+mod prepare_as_destination;
+mod write_local_data;
+
+use local_data::local_data_helper;
+use prepare_as_destination::prepare_as_destination_helper;
+use write_local_data::write_local_data_helper;
+
+/// Locator scheme for Azure Blob Storage.
+///
+/// The URL host is the container name, and the path is a blob prefix.
+#[derive(Clone, Debug)]
+pub(crate) struct AzblobLocator {
+    url: Url,
+}
+
+impl fmt::Display for AzblobLocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.url.fmt(f)
+    }
+}
+
+impl FromStr for AzblobLocator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.starts_with(Self::scheme()) {
+            let url = s
+                .parse::<Url>()
+                .with_context(|_| format!("cannot parse {}", s))?;
+            if url.host_str().map(|host| host.is_empty()).unwrap_or(true) {
+                Err(format_err!("{} must include a container name", url))
+            } else if !url.path().ends_with('/') {
+                Err(format_err!("{} must end with a '/'", url))
+            } else {
+                Ok(AzblobLocator { url })
+            }
+        } else {
+            Err(format_err!("expected {} to begin with azblob://", s))
+        }
+    }
+}
+
+#[test]
+fn from_str_requires_container_and_trailing_slash() {
+    let locator = "azblob://container/dir/".parse::<AzblobLocator>().unwrap();
+    assert_eq!(locator.to_string(), "azblob://container/dir/");
+    assert!("azblob://container/".parse::<AzblobLocator>().is_ok());
+    assert!("azblob://container/file.csv"
+        .parse::<AzblobLocator>()
+        .is_err());
+    assert!("azblob:///dir/".parse::<AzblobLocator>().is_err());
+    assert!("s3://bucket/dir/".parse::<AzblobLocator>().is_err());
+}
+
+impl Locator for AzblobLocator {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn local_data(
+        &self,
+        ctx: Context,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<BoxStream<CsvStream>>> {
+        local_data_helper(ctx, self.url.clone(), shared_args, source_args).boxed()
+    }
+
+    fn write_local_data(
+        &self,
+        ctx: Context,
+        data: BoxStream<CsvStream>,
+        shared_args: SharedArguments<Unverified>,
+        dest_args: DestinationArguments<Unverified>,
+    ) -> BoxFuture<BoxStream<BoxFuture<BoxLocator>>> {
+        write_local_data_helper(ctx, self.url.clone(), data, shared_args, dest_args)
+            .boxed()
+    }
+}
+
+impl LocatorStatic for AzblobLocator {
+    fn scheme() -> &'static str {
+        "azblob:"
+    }
+
+    fn features() -> Features {
+        Features {
+            locator: LocatorFeatures::LocalData | LocatorFeatures::WriteLocalData,
+            write_schema_if_exists: EnumSet::empty(),
+            source_args: SourceArgumentsFeatures::DriverArgs.into(),
+            dest_args: DestinationArgumentsFeatures::DriverArgs
+                | DestinationArgumentsFeatures::Compression,
+            dest_if_exists: IfExistsFeatures::Overwrite | IfExistsFeatures::Append,
+            _placeholder: (),
+        }
+    }
+
+    /// This locator type is currently unstable.
+    fn is_unstable() -> bool {
+        true
+    }
+}
+
+/// Given a `TemporaryStorage`, extract a unique `azblob://` temporary
+/// directory, including a random component.
+///
+/// No driver loads data from Azure Blob Storage yet, but warehouses like
+/// Azure Synapse will need this.
+#[allow(dead_code)]
+pub(crate) fn find_azblob_temp_dir(
+    temporary_storage: &TemporaryStorage,
+) -> Result<AzblobLocator> {
+    temporary_storage.check_allowed(AzblobLocator::scheme())?;
+    let mut temp = temporary_storage
+        .find_scheme(AzblobLocator::scheme())
+        .ok_or_else(|| NoCopyPathError::missing_temporary(AzblobLocator::scheme()))?
+        .to_owned();
+    if !temp.ends_with('/') {
+        temp.push('/');
+    }
+    temp.push_str(&TemporaryStorage::random_tag());
+    temp.push('/');
+    AzblobLocator::from_str(&temp)
+}
@@ -0,0 +1,24 @@
+//! Preparing container directories as output destinations.
+
+use crate::clouds::azure::blob::{self, Client};
+use crate::common::*;
+
+/// Prepare the target of this locator for use as a destination.
+pub(crate) async fn prepare_as_destination_helper(
+    ctx: Context,
+    client: &Client,
+    azblob_url: Url,
+    if_exists: IfExists,
+) -> Result<()> {
+    match if_exists {
+        // Delete all the blobs under `azblob_url`.
+        IfExists::Overwrite => blob::rmdir(&ctx, client, &azblob_url).await,
+        // Leave any existing blobs alone. Blobs with the same names as our
+        // output will still be replaced.
+        IfExists::Append => Ok(()),
+        _ => Err(format_err!(
+            "must specify `overwrite` or `append` for {} destination",
+            azblob_url,
+        )),
+    }
+}
@@ -0,0 +1,52 @@
+//! Writing data to Azure Blob Storage.
+
+use super::{
+    driver_args::AzblobArguments, prepare_as_destination_helper, AzblobLocator,
+};
+use crate::clouds::azure::blob::{self, Client};
+use crate::common::*;
+
+/// Implementation of `write_local_data`, but as a real `async` function.
+pub(crate) async fn write_local_data_helper(
+    ctx: Context,
+    url: Url,
+    data: BoxStream<CsvStream>,
+    shared_args: SharedArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<BoxStream<BoxFuture<BoxLocator>>> {
+    let _shared_args = shared_args.verify(AzblobLocator::features())?;
+    let dest_args = dest_args.verify(AzblobLocator::features())?;
+
+    // Look up our arguments.
+    let if_exists = dest_args.if_exists().to_owned();
+    let compression = dest_args.compression();
+    let azblob_args = dest_args
+        .driver_args()
+        .deserialize::<AzblobArguments>()
+        .context("error parsing --to-args")?;
+    let ctx = ctx.with_endpoints(&azblob_args.endpoints());
+    let client = Client::new(&ctx).await?;
+
+    // Delete the existing output, if it exists.
+    prepare_as_destination_helper(ctx.clone(), &client, url.clone(), if_exists)
+        .await?;
+
+    // Spawn our uploader threads.
+    let written = data.map_ok(move |stream| {
+        let url = url.clone();
+        let ctx = ctx.clone();
+        let client = client.clone();
+        async move {
+            let url =
+                url.join(&format!("{}.csv{}", stream.name, compression.extension()))?;
+            let ctx = ctx
+                .child(o!("stream" => stream.name.clone(), "url" => url.to_string()));
+            let stream = compression.compress_csv_stream(&ctx, stream)?;
+            blob::upload_file(&ctx, &client, stream.data, &url).await?;
+            Ok(AzblobLocator { url }.boxed())
+        }
+        .boxed()
+    });
+
+    Ok(written.boxed())
+}
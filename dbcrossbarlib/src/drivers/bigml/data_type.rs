@@ -10,6 +10,10 @@ pub(crate) trait OptypeExt {
     /// Convert a portable `DateType` into a BigML-specific one.
     fn for_data_type(data_type: &DataType, optype_for_text: Optype) -> Result<Optype>;
 
+    /// If BigML can't store `data_type` without losing information, describe
+    /// what we'll store instead.
+    fn lossy_for_data_type(data_type: &DataType) -> Option<String>;
+
     /// Convert a BigML-specific type into a portable one.
     fn to_data_type(&self) -> Result<DataType>;
 }
@@ -36,6 +40,24 @@ impl OptypeExt for Optype {
         }
     }
 
+    fn lossy_for_data_type(data_type: &DataType) -> Option<String> {
+        match data_type {
+            DataType::Array(_)
+            | DataType::GeoJson(_)
+            | DataType::Json
+            | DataType::Struct(_) => {
+                Some("text, so BigML won't see the structure of each value".to_owned())
+            }
+            DataType::Decimal | DataType::Int64 => Some(
+                "numeric, which BigML stores as a floating point number".to_owned(),
+            ),
+            DataType::TimestampWithTimeZone => {
+                Some("datetime, which doesn't keep the time zone".to_owned())
+            }
+            _ => None,
+        }
+    }
+
     fn to_data_type(&self) -> Result<DataType> {
         match self {
             Optype::Categorical | Optype::DateTime | Optype::Items | Optype::Text => {
@@ -1,10 +1,10 @@
 //! Support for BigML data sets.
 
-use bigml::resource::{Dataset, Id, Source};
+use bigml::resource::{source::Optype, Dataset, Id, Source};
 use std::{fmt, str::FromStr};
 
 use crate::common::*;
-use crate::schema::Table;
+use crate::schema::{DataType, Table};
 
 mod data_type;
 mod local_data;
@@ -12,6 +12,7 @@ mod schema;
 mod source;
 mod write_local_data;
 
+use data_type::OptypeExt;
 use local_data::local_data_helper;
 use schema::schema_helper;
 use write_local_data::write_local_data_helper;
@@ -218,4 +219,8 @@ impl LocatorStatic for BigMlLocator {
             _placeholder: (),
         }
     }
+
+    fn lossy_data_type(data_type: &DataType) -> Option<String> {
+        Optype::lossy_for_data_type(data_type)
+    }
 }
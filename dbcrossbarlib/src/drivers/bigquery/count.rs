@@ -20,12 +20,12 @@ pub(crate) async fn count_helper(
     let source_args = source_args.verify(BigQueryLocator::features())?;
 
     // Get our billing labels.
-    let job_labels = source_args
+    let gcloud_args = source_args
         .driver_args()
         .deserialize::<GCloudDriverArguments>()
-        .context("error parsing --from-args")?
-        .job_labels
-        .to_owned();
+        .context("error parsing --from-args")?;
+    let job_labels = gcloud_args.job_labels.to_owned();
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
 
     // Look up the arguments we need.
     let schema = shared_args.schema();
@@ -0,0 +1,189 @@
+//! Implementation of `BigQueryLocator::dry_run_read` and `dry_run_write`.
+
+use super::{
+    local_data::export_sql,
+    write_remote_data::{
+        add_columns_sql, match_table_columns, needs_temp_table, plan_table_evolution,
+    },
+    BigQueryLocator,
+};
+use crate::common::*;
+use crate::drivers::{
+    bigquery_shared::{BqTable, GCloudDriverArguments, Usage},
+    gs::{find_gs_temp_dir, GsLocator},
+};
+
+/// Describe how `local_data` would read our table.
+pub(crate) async fn dry_run_read_helper(
+    ctx: Context,
+    source: BigQueryLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Vec<String>> {
+    let source_args_v = source_args.clone().verify(BigQueryLocator::features())?;
+    let gcloud_args = source_args_v
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
+
+    // Without a `gs://` bucket, `local_data` downloads query results directly.
+    let shared_args_v = shared_args.clone().verify(BigQueryLocator::features())?;
+    let temporary_storage = shared_args_v.temporary_storage();
+    if temporary_storage.find_scheme(GsLocator::scheme()).is_none()
+        && temporary_storage.find_file_temp_dir().is_some()
+    {
+        let sql = export_sql(
+            &ctx,
+            source.as_table_name(),
+            shared_args_v.schema(),
+            &source_args_v,
+        )
+        .await?;
+        return Ok(vec![format!("-- download query results directly\n{}", sql)]);
+    }
+
+    // Otherwise, we extract to `gs://` and download the files.
+    let gs_temp = find_gs_temp_dir(temporary_storage)?;
+    let mut sql = gs_temp
+        .dry_run_write(
+            ctx,
+            Some(Box::new(source)),
+            shared_args,
+            source_args,
+            DestinationArguments::for_temporary(),
+        )
+        .await?;
+    sql.push(format!("-- download CSV files from {}", gs_temp));
+    Ok(sql)
+}
+
+/// Describe the load jobs and SQL that `write_local_data` would run, or that
+/// `write_remote_data` would run if we have a `source`.
+pub(crate) async fn dry_run_write_helper(
+    ctx: Context,
+    dest: BigQueryLocator,
+    source: Option<BoxLocator>,
+    shared_args: SharedArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<String>> {
+    dest.check_writable()?;
+
+    let shared_args = shared_args.verify(BigQueryLocator::features())?;
+    let dest_args = dest_args.verify(BigQueryLocator::features())?;
+    let gcloud_args = dest_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --to-args")?;
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
+
+    // Figure out where our load jobs would read from.
+    let mut sql = vec![];
+    let load_source = match source {
+        Some(source) => {
+            if let Some(source) = source.as_any().downcast_ref::<BigQueryLocator>() {
+                return Ok(vec![format!(
+                    "-- copy {} to {} server-side, or through temporary gs:// storage if their datasets are in different locations",
+                    source, dest,
+                )]);
+            }
+            let mut source_url = source
+                .as_any()
+                .downcast_ref::<GsLocator>()
+                .ok_or_else(|| format_err!("not a gs:// locator: {}", source))?
+                .as_url()
+                .to_owned();
+            if source_url.as_str().ends_with('/') {
+                source_url = source_url.join("*.csv")?;
+            }
+            source_url.to_string()
+        }
+        None => {
+            if let Some(max_rows) = gcloud_args.streaming_insert_max_rows {
+                sql.push(format!(
+                    "-- inputs with at most {} rows may use streaming inserts instead",
+                    max_rows,
+                ));
+            }
+            let temporary_storage = shared_args.temporary_storage();
+            match (
+                temporary_storage.find_scheme(GsLocator::scheme()),
+                temporary_storage.find_file_temp_dir(),
+            ) {
+                (None, Some(file_temp)) => {
+                    sql.push(format!("-- write CSV files to {}", file_temp.display()));
+                    format!("each CSV file in {}", file_temp.display())
+                }
+                _ => {
+                    let gs_temp = find_gs_temp_dir(temporary_storage)?;
+                    sql.push(format!("-- upload CSV files to {}", gs_temp));
+                    gs_temp.as_url().join("*.csv")?.to_string()
+                }
+            }
+        }
+    };
+
+    // Rename and evolve our schema the same way `load_batches` would, but
+    // without changing the destination table.
+    let renames = match_table_columns(
+        &ctx,
+        &dest,
+        shared_args.schema(),
+        dest_args.match_columns(),
+    )
+    .await?;
+    let schema = renames.apply_to_schema(shared_args.schema());
+    let if_exists = renames.apply_to_if_exists(dest_args.if_exists());
+    let deletes = gcloud_args.delete_propagation()?;
+    if let Some(deletes) = &deletes {
+        deletes.check_if_exists(&if_exists)?;
+    }
+    let schema_evolution = dest_args.schema_evolution();
+    let changes = plan_table_evolution(&ctx, &dest, &schema, schema_evolution).await?;
+    if !changes.add_columns.is_empty() {
+        sql.push(add_columns_sql(&dest, &changes.add_columns)?);
+    }
+    let dest_schema = changes.apply_to_schema(&schema);
+
+    // Describe our load jobs, and any SQL we'd run afterwards.
+    let use_temp = needs_temp_table(
+        &ctx,
+        &dest,
+        &schema,
+        &if_exists,
+        schema_evolution,
+        &gcloud_args,
+    )
+    .await?;
+    if use_temp {
+        let initial_table_name = dest
+            .table_name
+            .temporary_table_name(shared_args.temporary_storage())?;
+        sql.push(format!(
+            "-- load job: {} into temporary table {}",
+            load_source, initial_table_name,
+        ));
+        let dest_table = BqTable::for_table_name_and_columns(
+            dest.table_name.clone(),
+            &dest_schema.columns,
+            Usage::FinalTable,
+        )?;
+        let mut query = Vec::new();
+        dest_table.write_import_sql(
+            &initial_table_name,
+            &if_exists,
+            deletes.as_ref(),
+            &mut query,
+        )?;
+        sql.push(
+            String::from_utf8(query).expect("generated SQL should always be UTF-8"),
+        );
+        sql.push(format!("-- drop temporary table {}", initial_table_name));
+    } else {
+        sql.push(format!(
+            "-- load job: {} into {} (--if-exists={})",
+            load_source, dest.table_name, if_exists,
+        ));
+    }
+    Ok(sql)
+}
@@ -0,0 +1,143 @@
+//! Implementation of `estimate`.
+
+use serde::Deserialize;
+
+use super::BigQueryLocator;
+use crate::clouds::gcloud::bigquery;
+use crate::common::*;
+use crate::drivers::bigquery_shared::{BqTable, GCloudDriverArguments, Ident, Usage};
+
+/// A summary row from `__TABLES__`. BigQuery returns integers as strings.
+#[derive(Debug, Deserialize)]
+struct TablesRow {
+    row_count: Option<String>,
+    size_bytes: Option<String>,
+    table_count: String,
+}
+
+/// Implementation of `estimate`, but as a real `async` function.
+pub(crate) async fn estimate_helper(
+    ctx: Context,
+    locator: BigQueryLocator,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<Estimate>> {
+    let source_args = source_args.verify(BigQueryLocator::features())?;
+    let gcloud_args = source_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
+    let table_name = &locator.table_name;
+
+    if !table_name.is_wildcard() {
+        // Ordinary tables have statistics in their metadata.
+        let size = bigquery::table_size(&ctx, table_name).await?;
+        return Ok(Some(Estimate {
+            rows: size.rows,
+            bytes: size.bytes,
+            files: None,
+            method: "table statistics".to_owned(),
+        }));
+    }
+
+    // For wildcard tables, add up the statistics for all the matching tables
+    // using the legacy `__TABLES__` metadata view, which costs nothing to query.
+    let prefix = table_name.table_prefix();
+    let suffix_expr = format!("SUBSTR(table_id, {})", prefix.chars().count() + 1);
+    let mut sql = format!(
+        "SELECT CAST(SUM(row_count) AS STRING) AS row_count, CAST(SUM(size_bytes) AS STRING) AS size_bytes, CAST(COUNT(*) AS STRING) AS table_count FROM {}.{}.__TABLES__ WHERE STARTS_WITH(table_id, {})",
+        Ident(table_name.project()),
+        Ident(table_name.dataset()),
+        string_literal(prefix),
+    );
+    if let Some(filter) =
+        gcloud_args.table_suffix_filter_on(table_name, &suffix_expr)?
+    {
+        sql.push_str(" AND ");
+        sql.push_str(&filter);
+    }
+    debug!(ctx.log(), "estimate SQL: {}", sql);
+    let row = bigquery::query_one::<TablesRow>(
+        &ctx,
+        table_name.project(),
+        &sql,
+        &gcloud_args.job_labels,
+    )
+    .await?;
+    Ok(Some(Estimate {
+        rows: parse_optional_u64(row.row_count)?,
+        bytes: parse_optional_u64(row.size_bytes)?,
+        files: None,
+        method: format!("table statistics for {} tables", row.table_count),
+    }))
+}
+
+/// Estimate how many bytes BigQuery will scan while reading `locator`. If
+/// `may_extract` is true, we'll be extracting our data to Google Cloud Storage,
+/// which doesn't need a query for plain tables that export unchanged.
+pub(crate) async fn query_bytes_scanned(
+    ctx: &Context,
+    locator: &BigQueryLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    may_extract: bool,
+) -> Result<u64> {
+    let shared_args = shared_args.verify(BigQueryLocator::features())?;
+    let source_args = source_args.verify(BigQueryLocator::features())?;
+    let gcloud_args = source_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
+    let table_name = &locator.table_name;
+
+    // Build our export SQL, the same way we would when copying.
+    let source_table = BqTable::for_table_name_and_columns(
+        table_name.to_owned(),
+        &shared_args.schema().columns,
+        Usage::FinalTable,
+    )?;
+    let full_source_table = BqTable::read_from_table(&ctx, table_name).await?;
+    let real_source_table = full_source_table.aligned_with(&source_table)?;
+    if may_extract && !table_name.is_wildcard() {
+        let table_type = bigquery::table_type(&ctx, table_name).await?;
+        if table_type.can_extract()
+            && full_source_table.extracts_same_as(&real_source_table, &source_args)?
+        {
+            return Ok(0);
+        }
+    }
+    let mut export_sql_data = vec![];
+    real_source_table.write_export_sql(&source_args, &mut export_sql_data)?;
+    let export_sql =
+        String::from_utf8(export_sql_data).expect("should always be UTF-8");
+    bigquery::query_bytes_processed(
+        &ctx,
+        locator.project(),
+        &export_sql,
+        &gcloud_args.job_labels,
+    )
+    .await
+}
+
+/// Parse an integer returned by BigQuery as a string.
+fn parse_optional_u64(value: Option<String>) -> Result<Option<u64>> {
+    value
+        .map(|v| {
+            v.parse::<u64>()
+                .with_context(|_| format!("could not parse integer {:?}", v))
+                .map_err(Error::from)
+        })
+        .transpose()
+}
+
+/// Quote `s` as a BigQuery string literal.
+fn string_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[test]
+fn quotes_string_literals() {
+    assert_eq!(string_literal("events_"), "'events_'");
+    assert_eq!(string_literal("it's"), "'it\\'s'");
+}
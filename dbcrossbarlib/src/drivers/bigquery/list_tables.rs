@@ -0,0 +1,97 @@
+//! Listing the tables in a BigQuery dataset.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::BigQueryLocator;
+use crate::clouds::gcloud::bigquery;
+use crate::common::*;
+use crate::drivers::bigquery_shared::{GCloudDriverArguments, Ident, TableName};
+
+/// A row of `INFORMATION_SCHEMA.TABLES`.
+#[derive(Debug, Deserialize)]
+struct TableRow {
+    table_name: String,
+}
+
+/// List the tables and views in `dataset`, which should look like
+/// `bigquery:project:dataset`, using `INFORMATION_SCHEMA.TABLES`.
+///
+/// If `pattern` is specified, only return tables whose names match it. The
+/// pattern may contain `*` (any characters) and `?` (a single character).
+/// `args` may contain `--from-arg` values like `job_labels`.
+pub async fn list_tables(
+    ctx: Context,
+    dataset: &str,
+    pattern: Option<&str>,
+    args: &DriverArguments,
+) -> Result<Vec<BigQueryLocator>> {
+    lazy_static! {
+        static ref DATASET_RE: Regex =
+            Regex::new("^bigquery:([^:.`]+):([^:.`]+)$").expect("invalid regex in source");
+    }
+
+    let cap = DATASET_RE.captures(dataset).ok_or_else(|| {
+        format_err!("expected bigquery:$PROJECT:$DATASET, found {:?}", dataset)
+    })?;
+    let (project, dataset) = (&cap[1], &cap[2]);
+    let pattern = pattern.map(glob_to_regex).transpose()?;
+
+    let gcloud_args = args
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
+
+    // Base tables and views can be exported, but other types (like external
+    // tables) may need special permissions, so we skip them.
+    let sql = format!(
+        "SELECT table_name FROM {}.{}.INFORMATION_SCHEMA.TABLES WHERE table_type IN ('BASE TABLE', 'VIEW', 'MATERIALIZED VIEW') ORDER BY table_name",
+        Ident(project),
+        Ident(dataset),
+    );
+    debug!(ctx.log(), "listing tables: {}", sql);
+    let rows =
+        bigquery::query_all::<TableRow>(&ctx, project, &sql, &gcloud_args.job_labels)
+            .await?;
+
+    rows.into_iter()
+        .filter(|row| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&row.table_name))
+        })
+        .map(|row| {
+            let table_name = format!("{}:{}.{}", project, dataset, row.table_name)
+                .parse::<TableName>()?;
+            Ok(BigQueryLocator { table_name })
+        })
+        .collect()
+}
+
+/// Convert a pattern like `events_*` into an anchored regular expression.
+fn glob_to_regex(pattern: &str) -> Result<Regex> {
+    let mut re = String::with_capacity(pattern.len() + 2);
+    re.push('^');
+    for c in pattern.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Ok(Regex::new(&re)?)
+}
+
+#[test]
+fn glob_patterns_match_table_names() {
+    let re = glob_to_regex("events_2020*").unwrap();
+    assert!(re.is_match("events_20200101"));
+    assert!(!re.is_match("old_events_20200101"));
+    assert!(!re.is_match("events_2019"));
+
+    let re = glob_to_regex("t?.x").unwrap();
+    assert!(re.is_match("t1.x"));
+    assert!(!re.is_match("t1_x"));
+}
@@ -1,7 +1,12 @@
 //! Helper for reading data from BigQuery.
 
+use crate::clouds::gcloud::bigquery;
 use crate::common::*;
-use crate::drivers::{bigquery::BigQueryLocator, gs::find_gs_temp_dir};
+use crate::drivers::{
+    bigquery::BigQueryLocator,
+    bigquery_shared::{BqTable, GCloudDriverArguments, TableName, Usage},
+    gs::{find_gs_temp_dir, GsLocator},
+};
 
 /// Implementation of `local_data`, but as a real `async` function.
 pub(crate) async fn local_data_helper(
@@ -10,9 +15,34 @@ pub(crate) async fn local_data_helper(
     shared_args: SharedArguments<Unverified>,
     source_args: SourceArguments<Unverified>,
 ) -> Result<Option<BoxStream<CsvStream>>> {
-    // Build a temporary location.
+    // Use any endpoints we were given for our temporary storage, too.
+    let source_args_v = source_args.clone().verify(BigQueryLocator::features())?;
+    let gcloud_args = source_args_v
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
+
+    // If we don't have a `gs://` bucket, but we do have a local temporary
+    // directory, we're probably copying a small amount of data. So just
+    // download the query results directly.
     let shared_args_v = shared_args.clone().verify(BigQueryLocator::features())?;
-    let gs_temp = find_gs_temp_dir(shared_args_v.temporary_storage())?;
+    let temporary_storage = shared_args_v.temporary_storage();
+    if temporary_storage.find_scheme(GsLocator::scheme()).is_none()
+        && temporary_storage.find_file_temp_dir().is_some()
+    {
+        return query_results_to_csv(
+            ctx,
+            source,
+            &shared_args_v,
+            source_args,
+            &gcloud_args,
+        )
+        .await;
+    }
+
+    // Build a temporary location.
+    let gs_temp = find_gs_temp_dir(temporary_storage)?;
     let gs_dest_args = DestinationArguments::for_temporary();
     let gs_source_args = SourceArguments::for_temporary();
 
@@ -34,3 +64,54 @@ pub(crate) async fn local_data_helper(
         .local_data(from_temp_ctx, shared_args, gs_source_args)
         .await
 }
+
+/// Read `source` by running our export SQL and fetching the results page by
+/// page, without extracting them to Google Cloud Storage.
+async fn query_results_to_csv(
+    ctx: Context,
+    source: BigQueryLocator,
+    shared_args: &SharedArguments<Verified>,
+    source_args: SourceArguments<Unverified>,
+    gcloud_args: &GCloudDriverArguments,
+) -> Result<Option<BoxStream<CsvStream>>> {
+    let source_args = source_args.verify(BigQueryLocator::features())?;
+    let source_table_name = source.as_table_name();
+    let ctx = ctx.child(o!("query_results" => source_table_name.to_string()));
+
+    let export_sql =
+        export_sql(&ctx, source_table_name, shared_args.schema(), &source_args)
+            .await?;
+    debug!(ctx.log(), "export SQL: {}", export_sql);
+
+    let data = bigquery::query_to_csv(
+        &ctx,
+        source.project(),
+        &export_sql,
+        &gcloud_args.job_labels,
+    )
+    .await?;
+    Ok(Some(box_stream_once(Ok(CsvStream {
+        name: source_table_name.table_prefix().to_owned(),
+        data,
+    }))))
+}
+
+/// Build our export SQL, using our real table schema as in `gs://` extracts.
+pub(crate) async fn export_sql(
+    ctx: &Context,
+    source_table_name: &TableName,
+    schema: &Table,
+    source_args: &SourceArguments<Verified>,
+) -> Result<String> {
+    let source_table = BqTable::for_table_name_and_columns(
+        source_table_name.to_owned(),
+        &schema.columns,
+        Usage::FinalTable,
+    )?;
+    let real_source_table = BqTable::read_from_table(ctx, source_table_name)
+        .await?
+        .aligned_with(&source_table)?;
+    let mut export_sql_data = vec![];
+    real_source_table.write_export_sql(source_args, &mut export_sql_data)?;
+    Ok(String::from_utf8(export_sql_data).expect("should always be UTF-8"))
+}
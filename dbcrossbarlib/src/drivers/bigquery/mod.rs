@@ -3,20 +3,38 @@
 use std::{fmt, str::FromStr};
 
 use crate::common::*;
-use crate::drivers::{bigquery_shared::TableName, gs::GsLocator};
+use crate::drivers::{
+    bigquery_shared::{DataTypeBigQueryExt, TableName},
+    gs::GsLocator,
+};
+use crate::limits::{Limits, NameLength};
+use crate::reserved_words;
+use crate::schema::DataType;
 
 mod count;
+mod dry_run;
+mod estimate;
+mod list_tables;
 mod local_data;
+mod query_schema;
+mod row_limits;
 mod schema;
+mod streaming;
 mod write_local_data;
 mod write_remote_data;
 
 use self::count::count_helper;
+use self::dry_run::{dry_run_read_helper, dry_run_write_helper};
+use self::estimate::estimate_helper;
 use self::local_data::local_data_helper;
+use self::query_schema::query_schema_helper;
 use self::schema::schema_helper;
 use self::write_local_data::write_local_data_helper;
 use self::write_remote_data::write_remote_data_helper;
 
+pub(crate) use self::estimate::query_bytes_scanned;
+pub use self::list_tables::list_tables;
+
 /// A locator for a BigQuery table.
 #[derive(Debug, Clone)]
 pub struct BigQueryLocator {
@@ -34,6 +52,16 @@ impl BigQueryLocator {
     pub(crate) fn project(&self) -> &str {
         self.table_name.project()
     }
+
+    /// Return an error if we can't write to this locator. Wildcard tables like
+    /// `dataset.events_*` may only be used as sources.
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.table_name.is_wildcard() {
+            Err(format_err!("cannot write to wildcard table {}", self))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl fmt::Display for BigQueryLocator {
@@ -63,6 +91,15 @@ impl Locator for BigQueryLocator {
         schema_helper(ctx, self.to_owned()).boxed()
     }
 
+    fn query_schema(
+        &self,
+        ctx: Context,
+        sql: String,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<Table>> {
+        query_schema_helper(ctx, self.to_owned(), sql, source_args).boxed()
+    }
+
     fn count(
         &self,
         ctx: Context,
@@ -72,6 +109,14 @@ impl Locator for BigQueryLocator {
         count_helper(ctx, self.to_owned(), shared_args, source_args).boxed()
     }
 
+    fn estimate(
+        &self,
+        ctx: Context,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<Estimate>> {
+        estimate_helper(ctx, self.to_owned(), source_args).boxed()
+    }
+
     fn local_data(
         &self,
         ctx: Context,
@@ -93,9 +138,16 @@ impl Locator for BigQueryLocator {
     }
 
     fn supports_write_remote_data(&self, source: &dyn Locator) -> bool {
-        // We can only do `write_remote_data` if `source` is a `GsLocator`.
-        // Otherwise, we need to do `write_local_data` like normal.
-        source.as_any().is::<GsLocator>()
+        // We can only do `write_remote_data` if `source` is a `GsLocator`, or
+        // a `BigQueryLocator` in the same project, which we can copy using
+        // SQL. (If the datasets are in different locations, we stage the data
+        // in `gs://` instead.) Otherwise, we need to do `write_local_data` like
+        // normal.
+        if let Some(source) = source.as_any().downcast_ref::<BigQueryLocator>() {
+            source.project() == self.project()
+        } else {
+            source.as_any().is::<GsLocator>()
+        }
     }
 
     fn write_remote_data(
@@ -116,6 +168,27 @@ impl Locator for BigQueryLocator {
         )
         .boxed()
     }
+
+    fn dry_run_read(
+        &self,
+        ctx: Context,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        dry_run_read_helper(ctx, self.to_owned(), shared_args, source_args).boxed()
+    }
+
+    fn dry_run_write(
+        &self,
+        ctx: Context,
+        source: Option<BoxLocator>,
+        shared_args: SharedArguments<Unverified>,
+        _source_args: SourceArguments<Unverified>,
+        dest_args: DestinationArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        dry_run_write_helper(ctx, self.to_owned(), source, shared_args, dest_args)
+            .boxed()
+    }
 }
 
 impl LocatorStatic for BigQueryLocator {
@@ -126,13 +199,17 @@ impl LocatorStatic for BigQueryLocator {
     fn features() -> Features {
         Features {
             locator: LocatorFeatures::Schema
+                | LocatorFeatures::QuerySchema
                 | LocatorFeatures::LocalData
                 | LocatorFeatures::WriteLocalData
-                | LocatorFeatures::Count,
+                | LocatorFeatures::Count
+                | LocatorFeatures::Estimate,
             write_schema_if_exists: EnumSet::empty(),
             source_args: SourceArgumentsFeatures::DriverArgs
                 | SourceArgumentsFeatures::WhereClause,
-            dest_args: DestinationArgumentsFeatures::DriverArgs.into(),
+            dest_args: DestinationArgumentsFeatures::DriverArgs
+                | DestinationArgumentsFeatures::SchemaEvolution
+                | DestinationArgumentsFeatures::MatchColumns,
             dest_if_exists: IfExistsFeatures::Error
                 | IfExistsFeatures::Overwrite
                 | IfExistsFeatures::Append
@@ -140,4 +217,23 @@ impl LocatorStatic for BigQueryLocator {
             _placeholder: (),
         }
     }
+
+    fn remote_sources() -> &'static [&'static str] {
+        &["bigquery:", "gs:"]
+    }
+
+    fn lossy_data_type(data_type: &DataType) -> Option<String> {
+        data_type.bigquery_lossy_data_type()
+    }
+
+    fn is_reserved_word(name: &str) -> bool {
+        reserved_words::is_reserved(reserved_words::BIGQUERY, name)
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            max_columns: Some(10000),
+            max_name_length: Some(NameLength::Chars(300)),
+        }
+    }
 }
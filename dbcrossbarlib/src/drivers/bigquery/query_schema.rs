@@ -0,0 +1,39 @@
+//! Implementation of `query_schema`.
+
+use super::BigQueryLocator;
+use crate::clouds::gcloud::bigquery;
+use crate::common::*;
+use crate::drivers::bigquery_shared::{BqTable, GCloudDriverArguments};
+use crate::schema::Table;
+
+/// Implementation of `query_schema`, but as a real `async` function.
+pub(crate) async fn query_schema_helper(
+    ctx: Context,
+    locator: BigQueryLocator,
+    sql: String,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<Table>> {
+    let source_args = source_args.verify(BigQueryLocator::features())?;
+
+    // Get our billing labels.
+    let gcloud_args = source_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
+
+    // Ask BigQuery what columns our query would return.
+    let columns = bigquery::query_schema(
+        &ctx,
+        locator.project(),
+        &sql,
+        &gcloud_args.job_labels,
+    )
+    .await
+    .with_context(|_| format!("could not describe query using {}", locator))?;
+    let bq_table = BqTable {
+        name: locator.table_name.clone(),
+        columns,
+    };
+    Ok(Some(bq_table.to_table()?))
+}
@@ -0,0 +1,109 @@
+//! Checking CSV data against BigQuery's size limits before we stage it.
+//!
+//! BigQuery refuses to load CSV rows larger than 100 MB, but we only find out
+//! once the load job runs, possibly long after we started copying, and the
+//! error doesn't say which row was too large. So we check each row as it goes
+//! by, and report exactly where the problem is.
+
+use crate::common::*;
+use crate::transform::spawn_sync_transform;
+
+/// The largest CSV row (and therefore the largest cell) that BigQuery will
+/// load. See https://cloud.google.com/bigquery/quotas#load_jobs.
+const MAX_CSV_ROW_BYTES: u64 = 100_000_000;
+
+/// Check that every row in `streams` is small enough for BigQuery to load.
+/// The data itself is passed through unchanged.
+pub(crate) fn check_row_sizes(
+    ctx: Context,
+    streams: BoxStream<CsvStream>,
+) -> BoxStream<CsvStream> {
+    let ctx = ctx.child(o!("streams_transform" => "check_row_sizes"));
+    streams
+        .and_then(move |stream| {
+            let ctx = ctx.clone();
+            async move {
+                let name = stream.name.clone();
+                let data = spawn_sync_transform(
+                    ctx,
+                    format!("check row sizes {}", name),
+                    stream.data,
+                    move |_ctx, rdr, wtr| {
+                        check_csv_row_sizes(&name, MAX_CSV_ROW_BYTES, rdr, wtr)
+                    },
+                )?;
+                Ok(CsvStream {
+                    name: stream.name,
+                    data,
+                })
+            }
+        })
+        .boxed()
+}
+
+/// Copy CSV data from `rdr` to `wtr`, failing if any row is larger than
+/// `max_row_bytes`.
+fn check_csv_row_sizes(
+    stream_name: &str,
+    max_row_bytes: u64,
+    rdr: impl Read,
+    wtr: impl Write,
+) -> Result<()> {
+    let mut rdr = csv::Reader::from_reader(rdr);
+    let mut wtr = csv::Writer::from_writer(wtr);
+    let hdr = rdr
+        .byte_headers()
+        .with_context(|_| format!("cannot read headers of {}", stream_name))?
+        .to_owned();
+    wtr.write_byte_record(&hdr)?;
+
+    let mut record = csv::ByteRecord::new();
+    loop {
+        let start = rdr.position().byte();
+        if !rdr
+            .read_byte_record(&mut record)
+            .with_context(|_| format!("cannot read row from {}", stream_name))?
+        {
+            break;
+        }
+        let row_bytes = rdr.position().byte() - start;
+        if row_bytes > max_row_bytes {
+            let (largest_idx, largest) = record
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, cell)| cell.len())
+                .expect("a CSV record should have at least one cell");
+            let column = hdr
+                .get(largest_idx)
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .unwrap_or_else(|| format!("#{}", largest_idx + 1));
+            return Err(format_err!(
+                "row starting on line {} of {} is {} bytes, but BigQuery can only load CSV rows up to {} bytes (its largest column is {:?}, with {} bytes)",
+                record.position().map(|pos| pos.line()).unwrap_or(0),
+                stream_name,
+                row_bytes,
+                max_row_bytes,
+                column,
+                largest.len(),
+            ));
+        }
+        wtr.write_byte_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[test]
+fn reports_rows_which_are_too_large() {
+    let input = b"id,notes\n1,short\n2,\"this is a\nvery long note\"\n";
+    let mut output: Vec<u8> = vec![];
+    check_csv_row_sizes("test", 100, &input[..], &mut output).unwrap();
+    assert_eq!(output, input.to_vec());
+
+    let err =
+        check_csv_row_sizes("test", 20, &input[..], Vec::<u8>::new()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "row starting on line 3 of test is 29 bytes, but BigQuery can only load CSV rows up to 20 bytes (its largest column is \"notes\", with 24 bytes)",
+    );
+}
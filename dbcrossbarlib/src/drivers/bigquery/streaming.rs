@@ -0,0 +1,323 @@
+//! Copying small tables into BigQuery using streaming inserts.
+//!
+//! Loading data through Google Cloud Storage requires an upload, at least one
+//! load job and usually a query job, which can take a minute or more even for
+//! a handful of rows. For small tables, we can skip all of that and send rows
+//! directly to BigQuery's streaming insert API.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde_json::{Map, Value};
+
+use crate::common::*;
+use crate::from_csv_cell::FromCsvCell;
+use crate::from_json_value::FromJsonValue;
+use crate::schema::{Column, DataType, Table};
+use crate::validate::check_value;
+
+/// Our input data, after we've tried to buffer it.
+pub(crate) enum BufferedInput {
+    /// Our input had at most the maximum number of rows, so we've parsed all
+    /// of it into JSON rows.
+    Small(Vec<Value>),
+    /// Our input might have more than the maximum number of rows, so here's a
+    /// stream containing all our original data.
+    Large(BoxStream<CsvStream>),
+}
+
+/// Can we stream data matching `schema` into BigQuery using `if_exists`?
+///
+/// We don't support `Overwrite`, because BigQuery may drop rows streamed into
+/// a table shortly after it has been deleted and recreated, and we don't
+/// support `Upsert`, because the streaming API can only append.
+pub(crate) fn can_stream(schema: &Table, if_exists: &IfExists) -> bool {
+    let if_exists_ok = matches!(if_exists, IfExists::Append | IfExists::Error);
+    if_exists_ok && schema.columns.iter().all(|c| can_stream_type(&c.data_type))
+}
+
+/// Can we convert values of `data_type` to the JSON values expected by
+/// BigQuery's streaming API?
+fn can_stream_type(data_type: &DataType) -> bool {
+    match data_type {
+        // Nested arrays are represented as arrays of structs in BigQuery,
+        // which we don't try to build here.
+        DataType::Array(elem_type) => {
+            !matches!(elem_type.as_ref(), DataType::Array(_) | DataType::Json)
+                && can_stream_type(elem_type)
+        }
+        DataType::Struct(fields) => {
+            fields.iter().all(|f| can_stream_type(&f.data_type))
+        }
+        _ => true,
+    }
+}
+
+/// Read `data` into memory and convert it to JSON rows, unless it has more
+/// than `max_rows` rows.
+///
+/// We estimate the row count using the number of newlines while reading, so
+/// that we can stop reading as soon as we know our input is too large.
+pub(crate) async fn buffer_if_small(
+    ctx: &Context,
+    schema: &Table,
+    mut data: BoxStream<CsvStream>,
+    max_rows: usize,
+) -> Result<BufferedInput> {
+    let mut buffered: Vec<(String, Vec<BytesMut>)> = vec![];
+    let mut newlines = 0;
+    while let Some(stream) = data.try_next().await? {
+        let mut chunks = vec![];
+        let mut stream_data = stream.data;
+        while let Some(chunk) = stream_data.try_next().await? {
+            newlines += chunk.iter().filter(|&&b| b == b'\n').count();
+            chunks.push(chunk);
+
+            // Allow one header line for each stream we've seen, including
+            // this one. Quoted newlines may make us overestimate our rows, but
+            // that only means we'll use the regular load path.
+            if newlines > max_rows + buffered.len() + 1 {
+                debug!(ctx.log(), "too much data to use streaming inserts");
+                let partial = CsvStream {
+                    name: stream.name,
+                    data: stream::iter(chunks.into_iter().map(Ok))
+                        .chain(stream_data)
+                        .boxed(),
+                };
+                return Ok(BufferedInput::Large(
+                    rebuild_streams(buffered)
+                        .chain(stream::once(async { Ok(partial) }))
+                        .chain(data)
+                        .boxed(),
+                ));
+            }
+        }
+        buffered.push((stream.name, chunks));
+    }
+
+    // Parse our rows, and make sure we really don't have too many.
+    let mut rows = vec![];
+    for (name, chunks) in &buffered {
+        let bytes = chunks
+            .iter()
+            .flat_map(|c| c.iter().copied())
+            .collect::<Vec<u8>>();
+        parse_rows(schema, &bytes, &mut rows)
+            .with_context(|_| format!("error parsing CSV stream {}", name))?;
+    }
+    if rows.len() > max_rows {
+        Ok(BufferedInput::Large(rebuild_streams(buffered).boxed()))
+    } else {
+        Ok(BufferedInput::Small(rows))
+    }
+}
+
+/// Convert buffered data back into a stream of `CsvStream` values.
+fn rebuild_streams(
+    buffered: Vec<(String, Vec<BytesMut>)>,
+) -> impl Stream<Item = Result<CsvStream>> + Send + 'static {
+    stream::iter(buffered.into_iter().map(|(name, chunks)| {
+        Ok(CsvStream {
+            name,
+            data: stream::iter(chunks.into_iter().map(Ok)).boxed(),
+        })
+    }))
+}
+
+/// Parse the CSV data in `bytes` and append a JSON object to `rows` for each
+/// row.
+fn parse_rows(schema: &Table, bytes: &[u8], rows: &mut Vec<Value>) -> Result<()> {
+    let mut rdr = csv::Reader::from_reader(bytes);
+    let columns = rdr
+        .headers()?
+        .iter()
+        .map(|header| {
+            schema
+                .columns
+                .iter()
+                .find(|c| c.name == header)
+                .ok_or_else(|| format_err!("unknown column {:?}", header))
+        })
+        .collect::<Result<Vec<&Column>>>()?;
+    for record in rdr.records() {
+        let record = record?;
+        let mut row = Map::new();
+        for (column, cell) in columns.iter().zip(record.iter()) {
+            let value = cell_to_json(&column.data_type, cell)
+                .with_context(|_| format!("error in column {:?}", column.name))?;
+            row.insert(column.name.clone(), value);
+        }
+        rows.push(Value::Object(row));
+    }
+    Ok(())
+}
+
+/// Convert a CSV cell to a value for BigQuery's streaming API.
+fn cell_to_json(data_type: &DataType, cell: &str) -> Result<Value> {
+    if cell.is_empty() {
+        return Ok(Value::Null);
+    }
+    match data_type {
+        DataType::Array(_) | DataType::Struct(_) => {
+            json_to_json(data_type, &Value::from_csv_cell(cell)?)
+        }
+        // These are stored as strings in BigQuery.
+        DataType::Json | DataType::GeoJson(_) => {
+            check_value(data_type, cell)?;
+            Ok(Value::String(cell.to_owned()))
+        }
+        DataType::Bool => Ok(Value::Bool(bool::from_csv_cell(cell)?)),
+        DataType::Date => Ok(Value::String(
+            NaiveDate::from_csv_cell(cell)?
+                .format("%Y-%m-%d")
+                .to_string(),
+        )),
+        DataType::TimestampWithoutTimeZone => Ok(Value::String(
+            NaiveDateTime::from_csv_cell(cell)?
+                .format("%Y-%m-%d %H:%M:%S%.f")
+                .to_string(),
+        )),
+        DataType::TimestampWithTimeZone => Ok(Value::String(
+            DateTime::<Utc>::from_csv_cell(cell)?.to_rfc3339(),
+        )),
+        // BigQuery accepts strings for all of these, which avoids any loss of
+        // precision.
+        DataType::Decimal
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::Text
+        | DataType::Uuid => {
+            check_value(data_type, cell)?;
+            Ok(Value::String(cell.to_owned()))
+        }
+    }
+}
+
+/// Convert a JSON value found inside an array or struct to a value for
+/// BigQuery's streaming API.
+fn json_to_json(data_type: &DataType, value: &Value) -> Result<Value> {
+    match (data_type, value) {
+        (_, Value::Null) => Ok(Value::Null),
+        (DataType::Array(elem_type), Value::Array(elems)) => Ok(Value::Array(
+            elems
+                .iter()
+                .map(|elem| json_to_json(elem_type, elem))
+                .collect::<Result<Vec<_>>>()?,
+        )),
+        (DataType::Struct(fields), Value::Object(obj)) => {
+            let mut row = Map::new();
+            for field in fields {
+                let field_value = obj.get(&field.name).unwrap_or(&Value::Null);
+                row.insert(
+                    field.name.clone(),
+                    json_to_json(&field.data_type, field_value)?,
+                );
+            }
+            Ok(Value::Object(row))
+        }
+        (DataType::Json, _) | (DataType::GeoJson(_), Value::Object(_)) => {
+            Ok(Value::String(serde_json::to_string(value)?))
+        }
+        (DataType::Bool, _) => Ok(Value::Bool(bool::from_json_value(value)?)),
+        (DataType::Array(_), _) | (DataType::Struct(_), _) => {
+            Err(format_err!("cannot convert {} to {:?}", value, data_type))
+        }
+        (_, Value::String(s)) => cell_to_json(data_type, s),
+        (
+            DataType::Decimal
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64,
+            Value::Number(_),
+        ) => Ok(value.to_owned()),
+        _ => Err(format_err!("cannot convert {} to {:?}", value, data_type)),
+    }
+}
+
+#[test]
+fn converts_cells_to_bigquery_json() {
+    use crate::schema::StructField;
+    use serde_json::json;
+
+    let examples = vec![
+        (DataType::Bool, "t", json!(true)),
+        (DataType::Date, "1969-07-20", json!("1969-07-20")),
+        (DataType::Int64, "", Value::Null),
+        (DataType::Int64, "-17", json!("-17")),
+        (DataType::Json, r#"{"a":1}"#, json!(r#"{"a":1}"#)),
+        (
+            DataType::TimestampWithTimeZone,
+            "1969-07-20 21:17:39+01",
+            json!("1969-07-20T20:17:39+00:00"),
+        ),
+        (
+            DataType::Array(Box::new(DataType::Date)),
+            r#"["1969-07-20",null]"#,
+            json!(["1969-07-20", null]),
+        ),
+        (
+            DataType::Struct(vec![
+                StructField {
+                    name: "x".to_owned(),
+                    is_nullable: true,
+                    data_type: DataType::Float64,
+                },
+                StructField {
+                    name: "j".to_owned(),
+                    is_nullable: true,
+                    data_type: DataType::Json,
+                },
+            ]),
+            r#"{"x":1.5,"j":{"b":[]}}"#,
+            json!({"x": 1.5, "j": r#"{"b":[]}"#}),
+        ),
+    ];
+    for (data_type, cell, expected) in examples {
+        assert_eq!(cell_to_json(&data_type, cell).unwrap(), expected);
+    }
+    assert!(cell_to_json(&DataType::Int32, "1.5").is_err());
+    assert!(!can_stream_type(&DataType::Array(Box::new(
+        DataType::Array(Box::new(DataType::Int32))
+    ))));
+}
+
+#[test]
+fn buffers_only_small_inputs() {
+    let (ctx, worker_fut) = Context::create_for_test("buffers_only_small_inputs");
+    let cmd_fut = async move {
+        let schema = Table {
+            name: "example".to_owned(),
+            columns: vec![Column {
+                name: "id".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Int32,
+                comment: None,
+            }],
+        };
+        let input = || {
+            box_stream_once(Ok(CsvStream {
+                name: "example".to_owned(),
+                data: box_stream_once(Ok(BytesMut::from("id\n1\n2\n3\n"))),
+            }))
+        };
+
+        match buffer_if_small(&ctx, &schema, input(), 3).await.unwrap() {
+            BufferedInput::Small(rows) => assert_eq!(rows.len(), 3),
+            BufferedInput::Large(_) => panic!("expected small input"),
+        }
+
+        match buffer_if_small(&ctx, &schema, input(), 2).await.unwrap() {
+            BufferedInput::Small(_) => panic!("expected large input"),
+            BufferedInput::Large(mut streams) => {
+                let stream = streams.try_next().await.unwrap().unwrap();
+                let data = stream.data.try_concat().await.unwrap();
+                assert_eq!(&data[..], b"id\n1\n2\n3\n");
+            }
+        }
+        Ok(())
+    };
+    run_futures_with_runtime(cmd_fut.boxed(), worker_fut).unwrap();
+}
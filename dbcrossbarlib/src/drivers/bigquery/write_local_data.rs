@@ -1,7 +1,19 @@
 //! Implementation of `write_local_data` for BigQuery.
 
+use std::{ffi::OsStr, path::Path};
+use tokio::fs;
+
+use super::row_limits::check_row_sizes;
+use super::streaming::{buffer_if_small, can_stream, BufferedInput};
+use super::write_remote_data::{load_batches, LoadBatch};
+use crate::clouds::gcloud::bigquery;
 use crate::common::*;
-use crate::drivers::{bigquery::BigQueryLocator, gs::find_gs_temp_dir};
+use crate::drivers::{
+    bigquery::BigQueryLocator,
+    bigquery_shared::{BqTable, GCloudDriverArguments, Usage},
+    csv::CsvLocator,
+    gs::{find_gs_temp_dir, GsLocator},
+};
 use crate::tokio_glue::ConsumeWithParallelism;
 
 /// Implementation of `write_local_data`, but as a real `async` function.
@@ -12,9 +24,82 @@ pub(crate) async fn write_local_data_helper(
     shared_args: SharedArguments<Unverified>,
     dest_args: DestinationArguments<Unverified>,
 ) -> Result<BoxStream<BoxFuture<BoxLocator>>> {
-    // Build a temporary location.
+    dest.check_writable()?;
+
+    // If we have a small amount of data, we may be able to skip Google Cloud
+    // Storage entirely.
     let shared_args_v = shared_args.clone().verify(BigQueryLocator::features())?;
-    let gs_temp = find_gs_temp_dir(shared_args_v.temporary_storage())?;
+    let dest_args_v = dest_args.clone().verify(BigQueryLocator::features())?;
+    let gcloud_args = dest_args_v
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --to-args")?;
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
+    let mut data = data;
+    // Audit mode prints load jobs, so don't bypass them.
+    let streaming_insert_max_rows = gcloud_args
+        .streaming_insert_max_rows
+        .filter(|_| !gcloud_args.load_job_audit());
+    if let Some(max_rows) = streaming_insert_max_rows {
+        let schema = shared_args_v.schema();
+        if can_stream(schema, dest_args_v.if_exists())
+            && dest_args_v.schema_evolution() == SchemaEvolution::Fail
+            && dest_args_v.match_columns() == ColumnMatching::Exact
+        {
+            match buffer_if_small(&ctx, schema, data, max_rows).await? {
+                BufferedInput::Small(rows) => {
+                    stream_rows(&ctx, &dest, &shared_args_v, &dest_args_v, &rows)
+                        .await?;
+                    let fut = async { Ok(dest.boxed()) }.boxed();
+                    return Ok(box_stream_once(Ok(fut)));
+                }
+                BufferedInput::Large(all_data) => data = all_data,
+            }
+        } else {
+            debug!(
+                ctx.log(),
+                "cannot use streaming inserts with this schema, --if-exists, --schema-evolution and --match-columns"
+            );
+        }
+    }
+
+    // Make sure BigQuery will be able to load every row, before we spend time
+    // staging our data.
+    let data = check_row_sizes(ctx.clone(), data);
+
+    // If we don't have a `gs://` bucket, but we do have a local temporary
+    // directory, spool our data through local disk.
+    let temporary_storage = shared_args_v.temporary_storage();
+    if temporary_storage.find_scheme(GsLocator::scheme()).is_none() {
+        if let Some(file_temp) = temporary_storage.find_file_temp_dir() {
+            let result = write_via_file_temp(
+                &ctx,
+                &dest,
+                data,
+                shared_args.clone(),
+                &shared_args_v,
+                &dest_args_v,
+                &gcloud_args,
+                &file_temp,
+            )
+            .await;
+            debug!(ctx.log(), "removing {}", file_temp.display());
+            if let Err(err) = fs::remove_dir_all(&file_temp).await {
+                warn!(
+                    ctx.log(),
+                    "could not remove {}: {}",
+                    file_temp.display(),
+                    err,
+                );
+            }
+            result?;
+            let fut = async { Ok(dest.boxed()) }.boxed();
+            return Ok(box_stream_once(Ok(fut)));
+        }
+    }
+
+    // Build a temporary location.
+    let gs_temp = find_gs_temp_dir(temporary_storage)?;
     let gs_dest_args = DestinationArguments::for_temporary();
     let gs_source_args = SourceArguments::for_temporary();
 
@@ -49,3 +134,89 @@ pub(crate) async fn write_local_data_helper(
     let fut = async { Ok(dest.boxed()) }.boxed();
     Ok(box_stream_once(Ok(fut)))
 }
+
+/// Write `data` to CSV files in the local directory `file_temp`, and upload
+/// each file to `dest` using a separate load job.
+#[allow(clippy::too_many_arguments)]
+async fn write_via_file_temp(
+    ctx: &Context,
+    dest: &BigQueryLocator,
+    data: BoxStream<CsvStream>,
+    shared_args: SharedArguments<Unverified>,
+    shared_args_v: &SharedArguments<Verified>,
+    dest_args_v: &DestinationArguments<Verified>,
+    gcloud_args: &GCloudDriverArguments,
+    file_temp: &Path,
+) -> Result<()> {
+    // Write our data to local CSV files.
+    fs::create_dir_all(file_temp)
+        .await
+        .with_context(|_| format!("could not create {}", file_temp.display()))?;
+    let csv_temp = format!("csv:{}/", file_temp.display()).parse::<CsvLocator>()?;
+    let to_temp_ctx = ctx.child(o!("to_temp" => csv_temp.to_string()));
+    csv_temp
+        .write_local_data(
+            to_temp_ctx,
+            data,
+            shared_args,
+            DestinationArguments::for_temporary(),
+        )
+        .await?
+        .consume_with_parallelism(shared_args_v.max_streams())
+        .await?;
+
+    // Find the files we wrote, and upload each one.
+    let mut paths = vec![];
+    let mut entries = fs::read_dir(file_temp).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension() == Some(OsStr::new("csv")) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format_err!("no CSV files found in {}", file_temp.display()));
+    }
+    let batches = paths.into_iter().map(LoadBatch::File).collect::<Vec<_>>();
+    let from_temp_ctx = ctx.child(o!("from_temp" => csv_temp.to_string()));
+    load_batches(
+        &from_temp_ctx,
+        dest,
+        shared_args_v,
+        dest_args_v,
+        gcloud_args,
+        batches,
+    )
+    .await
+}
+
+/// Create our destination table if needed, and insert `rows` using the
+/// streaming API.
+async fn stream_rows(
+    ctx: &Context,
+    dest: &BigQueryLocator,
+    shared_args: &SharedArguments<Verified>,
+    dest_args: &DestinationArguments<Verified>,
+    rows: &[serde_json::Value],
+) -> Result<()> {
+    let ctx = ctx.child(o!("streaming_insert" => dest.to_string()));
+    let job_labels = dest_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --to-args")?
+        .job_labels;
+    let dest_table = BqTable::for_table_name_and_columns(
+        dest.as_table_name().to_owned(),
+        &shared_args.schema().columns,
+        Usage::FinalTable,
+    )?;
+
+    let mut sql = Vec::new();
+    dest_table.write_create_sql(dest_args.if_exists(), &mut sql)?;
+    let sql = String::from_utf8(sql).expect("generated SQL should always be UTF-8");
+    debug!(ctx.log(), "create sql: {}", sql);
+    bigquery::execute_sql(&ctx, dest.project(), &sql, &job_labels).await?;
+
+    bigquery::insert_all(&ctx, dest_table.name(), rows).await
+}
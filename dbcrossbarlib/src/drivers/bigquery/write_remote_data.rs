@@ -1,12 +1,23 @@
 //! Implementation of `BigQueryLocator::write_remote_data`.
 
+use std::path::PathBuf;
+
 use super::BigQueryLocator;
-use crate::clouds::gcloud::bigquery;
+use crate::clouds::endpoints::ApiEndpoints;
+use crate::clouds::gcloud::{
+    bigquery::{self, Labels, DEFAULT_MAX_LOAD_JOBS, MAX_SOURCE_URIS_PER_LOAD_JOB},
+    storage,
+};
+use crate::column_matching::ColumnRenames;
 use crate::common::*;
 use crate::drivers::{
-    bigquery_shared::{BqTable, GCloudDriverArguments, TableBigQueryExt, Usage},
-    gs::GsLocator,
+    bigquery_shared::{
+        BqTable, ColumnName, GCloudDriverArguments, TableBigQueryExt, Usage,
+    },
+    gs::{find_gs_temp_dir, GsLocator},
 };
+use crate::schema::Column;
+use crate::schema_evolution::SchemaChanges;
 
 /// Copy `source` to `dest` using `schema`.
 ///
@@ -21,6 +32,35 @@ pub(crate) async fn write_remote_data_helper(
     source_args: SourceArguments<Unverified>,
     dest_args: DestinationArguments<Unverified>,
 ) -> Result<Vec<BoxLocator>> {
+    dest.check_writable()?;
+
+    // If our source is another BigQuery table in the same location, we can copy
+    // it server-side. Otherwise, we need to stage it in `gs://`.
+    if let Some(source) = source.as_any().downcast_ref::<BigQueryLocator>() {
+        let source = source.to_owned();
+        if in_same_location(&ctx, &source, &dest, &source_args, &dest_args).await? {
+            return copy_from_bigquery(
+                ctx,
+                source,
+                dest,
+                shared_args,
+                source_args,
+                dest_args,
+            )
+            .await;
+        } else {
+            return copy_through_gs(
+                ctx,
+                source,
+                dest,
+                shared_args,
+                source_args,
+                dest_args,
+            )
+            .await;
+        }
+    }
+
     // Convert the source locator into the underlying `gs://` URL. This is a bit
     // fiddly because we're downcasting `source` and relying on knowledge about
     // the `GsLocator` type, and Rust doesn't make that especially easy.
@@ -36,32 +76,92 @@ pub(crate) async fn write_remote_data_helper(
     let _source_args = source_args.verify(Features::empty())?;
     let dest_args = dest_args.verify(BigQueryLocator::features())?;
 
-    // Get the arguments we care about.
-    let schema = shared_args.schema();
-    let temporary_storage = shared_args.temporary_storage();
-    let if_exists = dest_args.if_exists();
-
-    // Get our billing labels.
-    let job_labels = dest_args
+    // Get our billing labels and load job options.
+    let gcloud_args = dest_args
         .driver_args()
         .deserialize::<GCloudDriverArguments>()
-        .context("error parsing --to-args")?
-        .job_labels
-        .to_owned();
+        .context("error parsing --to-args")?;
+    let ctx = ctx.with_endpoints(&gcloud_args.endpoints());
 
-    // If our URL looks like a directory, add a glob.
-    //
-    // TODO: Is this the right way to default this? Or should we make users
-    // always specify `*.csv`? This should probably be part of some larger
-    // `dbcrossbar` property. Elsewhere, we're trying to default to adding
-    // `**/*.csv`, but that's not supported by BigQuery.
-    if source_url.as_str().ends_with('/') {
-        source_url = source_url.join("*.csv")?;
-    }
+    // Decide which files each load job should read.
     let ctx = ctx.child(o!("source_url" => source_url.as_str().to_owned()));
+    let source_uri_batches = match gcloud_args.files_per_load_job {
+        Some(files_per_load_job) => {
+            batch_source_uris(&ctx, &source_url, files_per_load_job).await?
+        }
+        None => {
+            // If our URL looks like a directory, add a glob.
+            //
+            // TODO: Is this the right way to default this? Or should we make
+            // users always specify `*.csv`? This should probably be part of
+            // some larger `dbcrossbar` property. Elsewhere, we're trying to
+            // default to adding `**/*.csv`, but that's not supported by
+            // BigQuery.
+            if source_url.as_str().ends_with('/') {
+                source_url = source_url.join("*.csv")?;
+            }
+            vec![vec![source_url.to_string()]]
+        }
+    };
+    let batches = source_uri_batches
+        .into_iter()
+        .map(LoadBatch::Uris)
+        .collect::<Vec<_>>();
+    load_batches(&ctx, &dest, &shared_args, &dest_args, &gcloud_args, batches).await?;
+    Ok(vec![dest.boxed()])
+}
+
+/// Data to load using a single BigQuery load job.
+#[derive(Clone, Debug)]
+pub(crate) enum LoadBatch {
+    /// Load from these `gs://` URIs.
+    Uris(Vec<String>),
+    /// Upload this local CSV file.
+    File(PathBuf),
+}
+
+/// Load `batches` into `dest`, using a temporary table if we need to
+/// transform the data or upsert it.
+pub(crate) async fn load_batches(
+    ctx: &Context,
+    dest: &BigQueryLocator,
+    shared_args: &SharedArguments<Verified>,
+    dest_args: &DestinationArguments<Verified>,
+    gcloud_args: &GCloudDriverArguments,
+    batches: Vec<LoadBatch>,
+) -> Result<()> {
+    let temporary_storage = shared_args.temporary_storage();
+    let job_labels = gcloud_args.job_labels.to_owned();
+
+    // Rename our columns to match an existing destination table, if asked. We
+    // load our CSV files by position, so we only need to rename our schema.
+    let renames = if gcloud_args.load_job_audit() {
+        ColumnRenames::default()
+    } else {
+        match_table_columns(ctx, dest, shared_args.schema(), dest_args.match_columns())
+            .await?
+    };
+    let schema = &renames.apply_to_schema(shared_args.schema());
+    let if_exists = &renames.apply_to_if_exists(dest_args.if_exists());
+    let deletes = gcloud_args.delete_propagation()?;
+    if let Some(deletes) = &deletes {
+        deletes.check_if_exists(if_exists)?;
+    }
+
+    // Update an existing destination table to match our schema, if asked. In
+    // audit mode, we only print our load jobs, so leave the table alone.
+    let schema_evolution = dest_args.schema_evolution();
+    let changes = if gcloud_args.load_job_audit() {
+        SchemaChanges::default()
+    } else {
+        evolve_table(ctx, dest, schema, schema_evolution, &job_labels).await?
+    };
+    let dest_schema = changes.apply_to_schema(schema);
 
     // Decide if we need to use a temp table.
-    let use_temp = !schema.bigquery_can_import_from_csv()? || if_exists.is_upsert();
+    let use_temp =
+        needs_temp_table(ctx, dest, schema, if_exists, schema_evolution, gcloud_args)
+            .await?;
     let initial_table_name = if use_temp {
         let initial_table_name =
             dest.table_name.temporary_table_name(temporary_storage)?;
@@ -97,15 +197,63 @@ pub(crate) async fn write_remote_data_helper(
         if_exists
     };
 
-    // Load our data.
-    bigquery::load(
-        &ctx,
-        &source_url,
-        &initial_table,
-        if_initial_table_exists,
-        &job_labels,
-    )
-    .await?;
+    // Refuse to start a copy which needs more load jobs than we allow, so that
+    // we don't hit BigQuery's daily limit part way through it.
+    let job_count =
+        u32::try_from(batches.len()).context("too many BigQuery load jobs")?;
+    bigquery::check_load_job_count(
+        initial_table.name(),
+        job_count,
+        gcloud_args.max_load_jobs.unwrap_or(DEFAULT_MAX_LOAD_JOBS),
+    )?;
+    debug!(
+        ctx.log(),
+        "loading into {} with {} jobs",
+        initial_table.name(),
+        job_count,
+    );
+
+    // Load our data. Only the first job replaces any existing data, and the
+    // rest append to it.
+    let load_options = gcloud_args.load_options()?;
+    for (idx, batch) in batches.iter().enumerate() {
+        let if_batch_exists = if idx == 0 {
+            if_initial_table_exists
+        } else {
+            &IfExists::Append
+        };
+        match batch {
+            LoadBatch::Uris(source_uris) => {
+                bigquery::load(
+                    ctx,
+                    source_uris,
+                    &initial_table,
+                    if_batch_exists,
+                    &job_labels,
+                    &load_options,
+                )
+                .await?
+            }
+            LoadBatch::File(path) => {
+                bigquery::load_file(
+                    ctx,
+                    path,
+                    &initial_table,
+                    if_batch_exists,
+                    &job_labels,
+                    &load_options,
+                )
+                .await?
+            }
+        }
+    }
+
+    // In audit mode, we've only printed our load jobs, so there's nothing more
+    // to do.
+    if load_options.audit {
+        debug!(ctx.log(), "audit mode: skipping remaining steps");
+        return Ok(());
+    }
 
     // If `use_temp` is false, then we're done. Otherwise, run the update SQL to
     // build the final table (if needed).
@@ -113,7 +261,7 @@ pub(crate) async fn write_remote_data_helper(
         // Build a `BqTable` for our final table.
         let dest_table = BqTable::for_table_name_and_columns(
             dest.table_name.clone(),
-            &schema.columns,
+            &dest_schema.columns,
             Usage::FinalTable,
         )?;
         debug!(
@@ -124,15 +272,355 @@ pub(crate) async fn write_remote_data_helper(
 
         // Generate and run our import SQL.
         let mut query = Vec::new();
-        dest_table.write_import_sql(initial_table.name(), if_exists, &mut query)?;
+        dest_table.write_import_sql(
+            initial_table.name(),
+            if_exists,
+            deletes.as_ref(),
+            &mut query,
+        )?;
         let query =
             String::from_utf8(query).expect("generated SQL should always be UTF-8");
         debug!(ctx.log(), "import sql: {}", query);
-        bigquery::execute_sql(&ctx, dest.project(), &query, &job_labels).await?;
+        bigquery::execute_sql(ctx, dest.project(), &query, &job_labels).await?;
 
         // Delete temp table.
-        bigquery::drop_table(&ctx, initial_table.name(), &job_labels).await?;
+        bigquery::drop_table(ctx, initial_table.name(), &job_labels).await?;
+    }
+
+    Ok(())
+}
+
+/// Decide if we need to load into a temp table. If the destination's columns
+/// may differ from ours, or appear in a different order, we always use one, and
+/// insert the columns by name.
+pub(crate) async fn needs_temp_table(
+    ctx: &Context,
+    dest: &BigQueryLocator,
+    schema: &Table,
+    if_exists: &IfExists,
+    schema_evolution: SchemaEvolution,
+    gcloud_args: &GCloudDriverArguments,
+) -> Result<bool> {
+    let mut use_temp = !schema.bigquery_can_import_from_csv()?
+        || if_exists.is_upsert()
+        || schema_evolution != SchemaEvolution::Fail;
+    if !use_temp
+        && *if_exists == IfExists::Append
+        && !gcloud_args.load_job_audit()
+        && dest_columns_are_reordered(ctx, dest, schema).await?
+    {
+        debug!(
+            ctx.log(),
+            "{} has columns in a different order, so inserting by name",
+            dest.table_name,
+        );
+        use_temp = true;
+    }
+    Ok(use_temp)
+}
+
+/// If `dest` already exists, decide which columns in `schema` need to be
+/// renamed to match its columns using `column_matching`.
+pub(crate) async fn match_table_columns(
+    ctx: &Context,
+    dest: &BigQueryLocator,
+    schema: &Table,
+    column_matching: ColumnMatching,
+) -> Result<ColumnRenames> {
+    if column_matching == ColumnMatching::Exact {
+        return Ok(ColumnRenames::default());
+    }
+    match bigquery::schema_if_exists(ctx, &dest.table_name).await? {
+        Some(existing) => column_matching.plan(
+            schema,
+            &dest.table_name.to_string(),
+            existing.columns.iter().map(|c| c.name.as_str()),
+        ),
+        None => Ok(ColumnRenames::default()),
+    }
+}
+
+/// If `dest` already exists, compare it to `schema` and decide what
+/// `schema_evolution` would change, without changing anything.
+pub(crate) async fn plan_table_evolution(
+    ctx: &Context,
+    dest: &BigQueryLocator,
+    schema: &Table,
+    schema_evolution: SchemaEvolution,
+) -> Result<SchemaChanges> {
+    // With `fail`, BigQuery will report any missing columns itself.
+    if schema_evolution == SchemaEvolution::Fail {
+        return Ok(SchemaChanges::default());
+    }
+    let existing = match bigquery::schema_if_exists(ctx, &dest.table_name).await? {
+        Some(existing) => existing,
+        None => return Ok(SchemaChanges::default()),
+    };
+    // BigQuery column names are case-insensitive, which `ColumnName` handles.
+    schema_evolution.plan(schema, &dest.table_name.to_string(), |name| {
+        ColumnName::try_from(name)
+            .map(|name| existing.columns.iter().any(|c| c.name == name))
+            .unwrap_or(false)
+    })
+}
+
+/// Generate SQL adding `columns` to `dest`.
+pub(crate) fn add_columns_sql(
+    dest: &BigQueryLocator,
+    columns: &[Column],
+) -> Result<String> {
+    let add_table = BqTable::for_table_name_and_columns(
+        dest.table_name.clone(),
+        columns,
+        Usage::FinalTable,
+    )?;
+    let mut sql = vec![];
+    add_table.write_add_columns_sql(&mut sql)?;
+    Ok(String::from_utf8(sql).expect("generated SQL should always be UTF-8"))
+}
+
+/// If `dest` already exists, compare it to `schema` and apply
+/// `schema_evolution`, adding any missing columns. The caller is responsible
+/// for leaving out any ignored columns.
+async fn evolve_table(
+    ctx: &Context,
+    dest: &BigQueryLocator,
+    schema: &Table,
+    schema_evolution: SchemaEvolution,
+    job_labels: &Labels,
+) -> Result<SchemaChanges> {
+    let changes = plan_table_evolution(ctx, dest, schema, schema_evolution).await?;
+    if !changes.add_columns.is_empty() {
+        let sql = add_columns_sql(dest, &changes.add_columns)?;
+        debug!(ctx.log(), "adding columns: {}", sql);
+        bigquery::execute_sql(ctx, dest.project(), &sql, job_labels).await?;
+    }
+    Ok(changes)
+}
+
+/// Does `dest` already exist with the same columns as `schema`, but in a
+/// different order? If so, loading our CSV files directly would put values in
+/// the wrong columns.
+async fn dest_columns_are_reordered(
+    ctx: &Context,
+    dest: &BigQueryLocator,
+    schema: &Table,
+) -> Result<bool> {
+    let existing = match bigquery::schema_if_exists(ctx, &dest.table_name).await? {
+        Some(existing) => existing,
+        None => return Ok(false),
+    };
+    let names = schema
+        .columns
+        .iter()
+        .map(|c| ColumnName::try_from(&c.name))
+        .collect::<Result<Vec<_>>>()?;
+    let existing_names = existing.columns.iter().map(|c| &c.name).collect::<Vec<_>>();
+    let same_columns = existing_names.len() == names.len()
+        && names.iter().all(|n| existing_names.contains(&n));
+    Ok(same_columns && existing_names.into_iter().ne(names.iter()))
+}
+
+/// List the CSV files in the `gs://` directory `source_url`, and split them into
+/// batches of `files_per_load_job` URIs, one for each load job.
+async fn batch_source_uris(
+    ctx: &Context,
+    source_url: &Url,
+    files_per_load_job: usize,
+) -> Result<Vec<Vec<String>>> {
+    if files_per_load_job == 0 || files_per_load_job > MAX_SOURCE_URIS_PER_LOAD_JOB {
+        return Err(format_err!(
+            "files_per_load_job must be between 1 and {}",
+            MAX_SOURCE_URIS_PER_LOAD_JOB,
+        ));
+    }
+    if !source_url.as_str().ends_with('/') {
+        return Err(format_err!(
+            "files_per_load_job requires a gs:// directory ending in '/', not {}",
+            source_url,
+        ));
+    }
+    let uris = storage::ls(ctx, source_url)
+        .await?
+        .map_ok(|obj| obj.to_url_string())
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .filter(|uri| uri.ends_with(".csv"))
+        .collect::<Vec<_>>();
+    if uris.is_empty() {
+        return Err(format_err!("no CSV files found in {}", source_url));
+    }
+    Ok(uris
+        .chunks(files_per_load_job)
+        .map(|chunk| chunk.to_owned())
+        .collect())
+}
+
+/// Copy `source` to `dest` using a single BigQuery query job, without
+/// extracting the data to Google Cloud Storage.
+/// Are the datasets containing `source` and `dest` in the same location? A
+/// BigQuery query can't read from one location and write to another, so we can
+/// only use `copy_from_bigquery` if they are.
+async fn in_same_location(
+    ctx: &Context,
+    source: &BigQueryLocator,
+    dest: &BigQueryLocator,
+    source_args: &SourceArguments<Unverified>,
+    dest_args: &DestinationArguments<Unverified>,
+) -> Result<bool> {
+    let source_gcloud_args = source_args
+        .clone()
+        .verify(BigQueryLocator::features())?
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let dest_gcloud_args = dest_args
+        .clone()
+        .verify(BigQueryLocator::features())?
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --to-args")?;
+    let source_location = bigquery::dataset_location(
+        &ctx.with_endpoints(&source_gcloud_args.endpoints()),
+        source.as_table_name(),
+    )
+    .await?;
+    let dest_location = bigquery::dataset_location(
+        &ctx.with_endpoints(&dest_gcloud_args.endpoints()),
+        dest.as_table_name(),
+    )
+    .await?;
+    if bigquery::is_same_location(&source_location, &dest_location) {
+        Ok(true)
+    } else {
+        debug!(
+            ctx.log(),
+            "cannot copy server-side from {} in {} to {} in {}",
+            source.as_table_name(),
+            source_location,
+            dest.as_table_name(),
+            dest_location,
+        );
+        Ok(false)
     }
+}
+
+/// Copy `source` to `dest` by extracting it to temporary `gs://` storage and
+/// loading it from there. We use this when the two tables are in different
+/// locations.
+async fn copy_through_gs(
+    ctx: Context,
+    source: BigQueryLocator,
+    dest: BigQueryLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<BoxLocator>> {
+    let shared_args_v = shared_args.clone().verify(BigQueryLocator::features())?;
+    let gs_temp = find_gs_temp_dir(shared_args_v.temporary_storage())?;
+
+    // Extract from BigQuery to gs://.
+    let to_temp_ctx = ctx.child(o!("to_temp" => gs_temp.to_string()));
+    gs_temp
+        .write_remote_data(
+            to_temp_ctx,
+            Box::new(source),
+            shared_args.clone(),
+            source_args,
+            DestinationArguments::for_temporary(),
+        )
+        .await?;
+
+    // Load from gs:// into BigQuery.
+    let from_temp_ctx = ctx.child(o!("from_temp" => gs_temp.to_string()));
+    dest.write_remote_data(
+        from_temp_ctx,
+        Box::new(gs_temp),
+        shared_args,
+        SourceArguments::for_temporary(),
+        dest_args,
+    )
+    .await
+}
+
+async fn copy_from_bigquery(
+    ctx: Context,
+    source: BigQueryLocator,
+    dest: BigQueryLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<BoxLocator>> {
+    let shared_args = shared_args.verify(BigQueryLocator::features())?;
+    let source_args = source_args.verify(BigQueryLocator::features())?;
+    let dest_args = dest_args.verify(BigQueryLocator::features())?;
+
+    // Get our billing labels. The job runs in the destination project, so we
+    // use the destination's labels.
+    let source_gcloud_args = source_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let gcloud_args = dest_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --to-args")?;
+    let job_labels = gcloud_args.job_labels.to_owned();
+    let ctx = ctx.with_endpoints(&ApiEndpoints::for_copy(
+        &source_gcloud_args.endpoints(),
+        &gcloud_args.endpoints(),
+    )?);
+
+    let ctx = ctx.child(o!("source_table" => source.as_table_name().to_string()));
+    debug!(
+        ctx.log(),
+        "copying directly from {} to {}",
+        source.as_table_name(),
+        dest.as_table_name(),
+    );
+
+    // Rename our columns to match an existing destination table, if asked. We
+    // still read each column from the source using its original name.
+    let renames = match_table_columns(
+        &ctx,
+        &dest,
+        shared_args.schema(),
+        dest_args.match_columns(),
+    )
+    .await?;
+    let schema = renames.apply_to_schema(shared_args.schema());
+    let if_exists = renames.apply_to_if_exists(dest_args.if_exists());
+
+    // Update an existing destination table to match our schema, if asked.
+    let changes = evolve_table(
+        &ctx,
+        &dest,
+        &schema,
+        dest_args.schema_evolution(),
+        &job_labels,
+    )
+    .await?;
+
+    // Generate and run our copy SQL.
+    let dest_table = BqTable::for_table_name_and_columns(
+        dest.table_name.clone(),
+        &changes.apply_to_schema(&schema).columns,
+        Usage::FinalTable,
+    )?;
+    let mut query = Vec::new();
+    dest_table.write_copy_sql(
+        source.as_table_name(),
+        &source_args,
+        &renames,
+        &if_exists,
+        gcloud_args.delete_propagation()?.as_ref(),
+        &mut query,
+    )?;
+    let query =
+        String::from_utf8(query).expect("generated SQL should always be UTF-8");
+    debug!(ctx.log(), "copy sql: {}", query);
+    bigquery::execute_sql(&ctx, dest.project(), &query, &job_labels).await?;
 
     Ok(vec![dest.boxed()])
 }
@@ -3,7 +3,12 @@
 use std::{fmt, str::FromStr};
 
 use crate::common::*;
-use crate::drivers::bigquery_shared::{BqColumn, BqTable, TableName, Usage};
+use crate::drivers::bigquery_shared::{
+    BqColumn, BqTable, DataTypeBigQueryExt, TableName, Usage,
+};
+use crate::limits::{Limits, NameLength};
+use crate::reserved_words;
+use crate::schema::DataType;
 
 /// A JSON file containing BigQuery table schema.
 #[derive(Clone, Debug)]
@@ -60,6 +65,21 @@ impl LocatorStatic for BigQuerySchemaLocator {
             _placeholder: (),
         }
     }
+
+    fn lossy_data_type(data_type: &DataType) -> Option<String> {
+        data_type.bigquery_lossy_data_type()
+    }
+
+    fn is_reserved_word(name: &str) -> bool {
+        reserved_words::is_reserved(reserved_words::BIGQUERY, name)
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            max_columns: Some(10000),
+            max_name_length: Some(NameLength::Chars(300)),
+        }
+    }
 }
 
 /// Implementation of `schema`, but as a real `async` function.
@@ -73,13 +73,56 @@ impl BqColumn {
             }
             BqDataType::NonArray(ty) => (ty, Mode::Required),
         };
-        Ok(BqColumn {
-            name,
-            description: None,
-            ty: BqRecordOrNonArrayDataType::DataType(ty),
-            mode,
-            fields: vec![],
-        })
+        Self::for_name_type_and_mode(name, ty, mode)
+    }
+
+    /// Given a `BqStructField`, return a corresponding `BqColumn` which can be
+    /// used in the `fields` of a `RECORD` column.
+    fn for_struct_field(field: &BqStructField) -> Result<BqColumn> {
+        let name = field.name.clone().ok_or_else(|| {
+            format_err!("cannot represent anonymous STRUCT fields as RECORD fields")
+        })?;
+        let (ty, mode) = match &field.ty {
+            BqDataType::Array(ty) => (ty.to_owned(), Mode::Repeated),
+            BqDataType::NonArray(ty) => (ty.to_owned(), Mode::Nullable),
+        };
+        Self::for_name_type_and_mode(name, ty, mode)
+    }
+
+    /// Build a column with the specified `name`, `ty` and `mode`.
+    ///
+    /// BigQuery JSON schemas describe `STRUCT` types as `RECORD` columns with
+    /// nested `fields`, so we use that representation whenever we can. Structs
+    /// with anonymous fields, which we use to represent nested arrays, have no
+    /// `RECORD` equivalent, so we leave those as `STRUCT<...>`.
+    fn for_name_type_and_mode(
+        name: ColumnName,
+        ty: BqNonArrayDataType,
+        mode: Mode,
+    ) -> Result<BqColumn> {
+        match ty {
+            BqNonArrayDataType::Struct(fields)
+                if fields.iter().all(|f| f.name.is_some()) =>
+            {
+                Ok(BqColumn {
+                    name,
+                    description: None,
+                    ty: BqRecordOrNonArrayDataType::Record,
+                    mode,
+                    fields: fields
+                        .iter()
+                        .map(BqColumn::for_struct_field)
+                        .collect::<Result<Vec<_>>>()?,
+                })
+            }
+            ty => Ok(BqColumn {
+                name,
+                description: None,
+                ty: BqRecordOrNonArrayDataType::DataType(ty),
+                mode,
+                fields: vec![],
+            }),
+        }
     }
 
     /// Given a `BqColumn`, construct a portable `Column`.
@@ -329,6 +372,26 @@ AS ((
         }
     }
 
+    /// Does our export `SELECT` expression output this column unchanged? If
+    /// so, a BigQuery extract job will write the same CSV data that our export
+    /// SQL would.
+    pub(crate) fn exports_unchanged(&self) -> Result<bool> {
+        let ty = self.bq_data_type()?;
+        if needs_custom_json_export(&ty)?.in_sql_code() {
+            return Ok(false);
+        }
+        match ty {
+            // Keep this in sync with `write_export_select_expr_for_non_array`.
+            BqDataType::NonArray(BqNonArrayDataType::Date)
+            | BqDataType::NonArray(BqNonArrayDataType::Float64)
+            | BqDataType::NonArray(BqNonArrayDataType::Int64)
+            | BqDataType::NonArray(BqNonArrayDataType::Numeric)
+            | BqDataType::NonArray(BqNonArrayDataType::String)
+            | BqDataType::NonArray(BqNonArrayDataType::Stringified(_)) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
     /// Output a `SELECT`-clause expression for an `ARRAY<...>` column.
     fn write_export_select_expr_for_array(
         &self,
@@ -476,6 +539,54 @@ fn column_without_mode() {
     assert_eq!(col.mode, Mode::Nullable);
 }
 
+#[test]
+fn struct_columns_use_record_and_repeated() {
+    use crate::schema::{DataType, StructField};
+    use serde_json::json;
+
+    let point = DataType::Struct(vec![
+        StructField {
+            name: "x".to_owned(),
+            is_nullable: true,
+            data_type: DataType::Float64,
+        },
+        StructField {
+            name: "tags".to_owned(),
+            is_nullable: true,
+            data_type: DataType::Array(Box::new(DataType::Text)),
+        },
+    ]);
+    let col = Column {
+        name: "points".to_owned(),
+        is_nullable: true,
+        data_type: DataType::Array(Box::new(point)),
+        comment: None,
+    };
+    let name = ColumnName::try_from("points").unwrap();
+    let bq_col = BqColumn::for_column(name, &col, Usage::FinalTable).unwrap();
+    assert_eq!(
+        serde_json::to_value(&bq_col).unwrap(),
+        json!({
+            "name": "points",
+            "type": "RECORD",
+            "mode": "REPEATED",
+            "fields": [
+                { "name": "x", "type": "FLOAT64", "mode": "NULLABLE" },
+                { "name": "tags", "type": "STRING", "mode": "REPEATED" },
+            ],
+        }),
+    );
+    assert_eq!(bq_col.to_column().unwrap().data_type, col.data_type);
+
+    // We still need to load structs from CSV files as JSON strings.
+    let name = ColumnName::try_from("points").unwrap();
+    let bq_col = BqColumn::for_column(name, &col, Usage::CsvLoad).unwrap();
+    assert_eq!(
+        bq_col.bq_data_type().unwrap(),
+        BqDataType::NonArray(BqNonArrayDataType::String),
+    );
+}
+
 /// A column mode.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
@@ -15,6 +15,10 @@ mod grammar;
 pub(crate) trait DataTypeBigQueryExt {
     /// Can BigQuery import this type from a CSV file?
     fn bigquery_can_import_from_csv(&self) -> Result<bool>;
+
+    /// If BigQuery can't store this type without losing information, describe
+    /// what we'll store instead.
+    fn bigquery_lossy_data_type(&self) -> Option<String>;
 }
 
 impl DataTypeBigQueryExt for DataType {
@@ -23,6 +27,21 @@ impl DataTypeBigQueryExt for DataType {
         let bq_data_type = BqDataType::for_data_type(self, Usage::FinalTable)?;
         Ok(bq_data_type.bigquery_can_import_from_csv())
     }
+
+    fn bigquery_lossy_data_type(&self) -> Option<String> {
+        match self {
+            DataType::Decimal => Some(
+                "NUMERIC, which keeps at most 38 digits, 9 of them after the \
+                 decimal point"
+                    .to_owned(),
+            ),
+            DataType::GeoJson(srid) if *srid != Srid::wgs84() => Some(format!(
+                "STRING, because GEOGRAPHY only supports SRID {}",
+                Srid::wgs84(),
+            )),
+            _ => None,
+        }
+    }
 }
 
 /// How do we intend to use a BigQuery type?
@@ -536,6 +555,18 @@ impl fmt::Display for BqStructField {
     }
 }
 
+#[test]
+fn lossy_data_types() {
+    assert!(DataType::Decimal.bigquery_lossy_data_type().is_some());
+    assert!(DataType::GeoJson(Srid::new(3857))
+        .bigquery_lossy_data_type()
+        .is_some());
+    assert!(DataType::GeoJson(Srid::wgs84())
+        .bigquery_lossy_data_type()
+        .is_none());
+    assert!(DataType::Int16.bigquery_lossy_data_type().is_none());
+}
+
 #[test]
 fn nested_arrays() {
     let input = DataType::Array(Box::new(DataType::Array(Box::new(DataType::Array(
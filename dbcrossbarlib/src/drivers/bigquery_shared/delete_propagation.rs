@@ -0,0 +1,102 @@
+//! Propagating deletions from an incremental source to a BigQuery table.
+//!
+//! Change feeds typically report deleted rows by setting a boolean column
+//! like `is_deleted`. If we simply upsert these rows, the destination keeps
+//! them forever. Instead, we can either mark them as deleted using a
+//! `_deleted_at` column, or delete them by key.
+
+use std::str::FromStr;
+
+use super::{BqColumn, ColumnName};
+use crate::common::*;
+use crate::schema::DataType;
+
+/// The column we add to the destination table when soft-deleting rows.
+pub(crate) const DELETED_AT_COLUMN: &str = "_deleted_at";
+
+/// How should we handle source rows which are marked as deleted?
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum DeleteMode {
+    /// Set `_deleted_at` on the matching destination row.
+    #[default]
+    Soft,
+    /// Delete the matching destination row.
+    Hard,
+}
+
+impl FromStr for DeleteMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "soft" => Ok(DeleteMode::Soft),
+            "hard" => Ok(DeleteMode::Hard),
+            _ => Err(format_err!(
+                "unknown delete_mode {:?} (expected \"soft\" or \"hard\")",
+                s,
+            )),
+        }
+    }
+}
+
+/// How to propagate deleted rows during an upsert.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct DeletePropagation {
+    /// A `BOOL` column in the source which is true for deleted rows.
+    pub(crate) deleted_column: String,
+    /// What to do with deleted rows.
+    pub(crate) mode: DeleteMode,
+}
+
+impl DeletePropagation {
+    /// Find our `deleted_column` in `columns`, and make sure it's usable.
+    pub(crate) fn find_deleted_column<'a>(
+        &self,
+        columns: &'a [BqColumn],
+    ) -> Result<(usize, &'a BqColumn)> {
+        let name = ColumnName::try_from(&self.deleted_column)?;
+        let (idx, col) = columns
+            .iter()
+            .enumerate()
+            .find(|(_, c)| c.name == name)
+            .ok_or_else(|| {
+                format_err!("deleted_column {:?} is not in table", self.deleted_column)
+            })?;
+        if col.to_column()?.data_type != DataType::Bool {
+            return Err(format_err!(
+                "deleted_column {:?} must be a boolean column",
+                self.deleted_column,
+            ));
+        }
+        if self.mode == DeleteMode::Soft
+            && columns
+                .iter()
+                .any(|c| c.name == ColumnName::try_from(DELETED_AT_COLUMN).unwrap())
+        {
+            return Err(format_err!(
+                "cannot soft-delete rows because the source already has a {:?} column",
+                DELETED_AT_COLUMN,
+            ));
+        }
+        Ok((idx, col))
+    }
+
+    /// Check that we're being used with an upsert.
+    pub(crate) fn check_if_exists(&self, if_exists: &IfExists) -> Result<()> {
+        if if_exists.is_upsert() {
+            Ok(())
+        } else {
+            Err(format_err!(
+                "deleted_column requires --if-exists=upsert-on:KEY, not {}",
+                if_exists,
+            ))
+        }
+    }
+}
+
+#[test]
+fn parses_delete_mode() {
+    assert_eq!("soft".parse::<DeleteMode>().unwrap(), DeleteMode::Soft);
+    assert_eq!("hard".parse::<DeleteMode>().unwrap(), DeleteMode::Hard);
+    assert!("purge".parse::<DeleteMode>().is_err());
+}
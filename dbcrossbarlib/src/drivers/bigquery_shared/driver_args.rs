@@ -1,8 +1,16 @@
 //! Arguments which can be passed to various Google Cloud drivers.
 
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use serde_json::{Map, Value};
 
-use crate::clouds::gcloud::bigquery::Labels;
+use super::{DeleteMode, DeletePropagation, TableName};
+use crate::clouds::{
+    endpoints::{ApiEndpoint, ApiEndpoints},
+    gcloud::bigquery::{jobs::SchemaUpdateOption, Labels, LoadOptions},
+};
+use crate::common::*;
+use crate::driver_args::deserialize_opt_from_str;
 
 /// Parse version of `--to-arg` and `--from-arg` labels.
 #[derive(Clone, Debug, Deserialize)]
@@ -11,4 +19,249 @@ pub(crate) struct GCloudDriverArguments {
     /// Billing labels to apply to objects and jobs.
     #[serde(default)]
     pub(crate) job_labels: Labels,
+
+    /// How many files should each BigQuery load job read? By default, we load
+    /// all our files with a single job.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) files_per_load_job: Option<usize>,
+
+    /// How many load jobs may a single copy run against a BigQuery table?
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) max_load_jobs: Option<u32>,
+
+    /// If we're copying at most this many rows, use BigQuery's streaming
+    /// insert API instead of loading data from Google Cloud Storage.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) streaming_insert_max_rows: Option<usize>,
+
+    /// Fields to override in each BigQuery load job's `configuration.load`,
+    /// passed as `load_job.maxBadRecords=10`.
+    #[serde(default)]
+    load_job: Map<String, Value>,
+
+    /// Raw options for the underlying BigQuery load jobs, passed as
+    /// `raw.NAME=VALUE`. These are applied just like `load_job.*`.
+    #[serde(default)]
+    raw: Map<String, Value>,
+
+    /// Ignore extra values at the end of CSV rows when loading.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    ignore_unknown_values: Option<bool>,
+
+    /// Treat missing values at the end of CSV rows as `NULL` when loading.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    allow_jagged_rows: Option<bool>,
+
+    /// Allow load jobs to add new `NULLABLE` columns when appending.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    allow_field_addition: Option<bool>,
+
+    /// Allow load jobs to change `REQUIRED` columns to `NULLABLE` when
+    /// appending.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    allow_field_relaxation: Option<bool>,
+
+    /// Print each BigQuery load job's configuration instead of running it.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    load_job_audit: Option<bool>,
+
+    /// A `BOOL` column which is true for source rows that have been deleted.
+    /// Only used with `--if-exists=upsert-on:KEY`.
+    #[serde(default)]
+    deleted_column: Option<String>,
+
+    /// Should deleted rows be marked using `_deleted_at` (`soft`), or removed
+    /// (`hard`)?
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    delete_mode: Option<DeleteMode>,
+
+    /// When reading from a wildcard table like `dataset.events_*`, only read
+    /// tables whose `_TABLE_SUFFIX` is at least this value.
+    #[serde(default)]
+    table_suffix_min: Option<String>,
+
+    /// When reading from a wildcard table, only read tables whose
+    /// `_TABLE_SUFFIX` is at most this value.
+    #[serde(default)]
+    table_suffix_max: Option<String>,
+
+    /// Read source tables as they were at this time, using `FOR SYSTEM_TIME AS
+    /// OF`, so that several reads see the same data.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    as_of: Option<DateTime<Utc>>,
+
+    /// Send BigQuery API requests to this endpoint instead of the public one.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    bigquery_endpoint: Option<ApiEndpoint>,
+
+    /// Send Google Cloud Storage API requests to this endpoint instead of the
+    /// public one. This is used for our temporary files.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    storage_endpoint: Option<ApiEndpoint>,
+}
+
+impl GCloudDriverArguments {
+    /// Should we print our load jobs instead of running them?
+    pub(crate) fn load_job_audit(&self) -> bool {
+        self.load_job_audit.unwrap_or(false)
+    }
+
+    /// Extra options for our load jobs, including any `raw.*` options.
+    pub(crate) fn load_options(&self) -> Result<LoadOptions> {
+        let mut overrides = self.load_job.clone();
+        for (name, value) in &self.raw {
+            if overrides.contains_key(name) {
+                return Err(format_err!(
+                    "cannot pass both load_job.{} and raw.{}",
+                    name,
+                    name,
+                ));
+            }
+            overrides.insert(name.to_owned(), value.to_owned());
+        }
+        let mut schema_update_options = vec![];
+        if self.allow_field_addition.unwrap_or(false) {
+            schema_update_options.push(SchemaUpdateOption::AllowFieldAddition);
+        }
+        if self.allow_field_relaxation.unwrap_or(false) {
+            schema_update_options.push(SchemaUpdateOption::AllowFieldRelaxation);
+        }
+        Ok(LoadOptions {
+            ignore_unknown_values: self.ignore_unknown_values,
+            allow_jagged_rows: self.allow_jagged_rows,
+            schema_update_options,
+            ..LoadOptions::from_args(&overrides, self.load_job_audit())
+        })
+    }
+
+    /// How should we propagate deleted rows, if at all?
+    pub(crate) fn delete_propagation(&self) -> Result<Option<DeletePropagation>> {
+        match (&self.deleted_column, self.delete_mode) {
+            (Some(deleted_column), mode) => Ok(Some(DeletePropagation {
+                deleted_column: deleted_column.to_owned(),
+                mode: mode.unwrap_or_default(),
+            })),
+            (None, Some(_)) => Err(format_err!("delete_mode requires deleted_column")),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Build an SQL condition restricting `_TABLE_SUFFIX` to the bounds given
+    /// by `table_suffix_min` and `table_suffix_max`, if any.
+    pub(crate) fn table_suffix_filter(
+        &self,
+        table_name: &TableName,
+    ) -> Result<Option<String>> {
+        self.table_suffix_filter_on(table_name, "_TABLE_SUFFIX")
+    }
+
+    /// Like `table_suffix_filter`, but compare the bounds against
+    /// `suffix_expr` instead of `_TABLE_SUFFIX`.
+    pub(crate) fn table_suffix_filter_on(
+        &self,
+        table_name: &TableName,
+        suffix_expr: &str,
+    ) -> Result<Option<String>> {
+        let mut conditions = vec![];
+        let bounds = [
+            ("table_suffix_min", ">=", &self.table_suffix_min),
+            ("table_suffix_max", "<=", &self.table_suffix_max),
+        ];
+        for (arg_name, op, bound) in bounds.iter() {
+            if let Some(bound) = bound {
+                if !table_name.is_wildcard() {
+                    return Err(format_err!(
+                        "{} requires a wildcard table name like dataset.events_*, not {}",
+                        arg_name,
+                        table_name,
+                    ));
+                }
+                // Table suffixes can only contain characters which are valid
+                // in table names, so we don't need to escape anything.
+                if !bound.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(format_err!(
+                        "{} may only contain letters, digits and '_': {:?}",
+                        arg_name,
+                        bound,
+                    ));
+                }
+                conditions.push(format!("{} {} '{}'", suffix_expr, op, bound));
+            }
+        }
+        if conditions.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(conditions.join(" AND ")))
+        }
+    }
+
+    /// Build a `FOR SYSTEM_TIME AS OF` clause for reading `table_name` as it
+    /// was at `as_of`, if we have one.
+    pub(crate) fn system_time_clause(
+        &self,
+        table_name: &TableName,
+    ) -> Result<Option<String>> {
+        match self.as_of {
+            Some(_) if table_name.is_wildcard() => Err(format_err!(
+                "as_of cannot be used with a wildcard table name like {}",
+                table_name,
+            )),
+            Some(as_of) => Ok(Some(format!(
+                "FOR SYSTEM_TIME AS OF TIMESTAMP '{}'",
+                as_of.format("%Y-%m-%d %H:%M:%S%.6f UTC"),
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Any API endpoint overrides specified by these arguments.
+    pub(crate) fn endpoints(&self) -> ApiEndpoints {
+        ApiEndpoints {
+            bigquery_endpoint: self.bigquery_endpoint.clone(),
+            storage_endpoint: self.storage_endpoint.clone(),
+            ..ApiEndpoints::default()
+        }
+    }
+}
+
+#[test]
+fn raw_args_are_load_job_overrides() {
+    let args = DriverArguments::from_cli_args(&[
+        "raw.maxBadRecords=10".to_owned(),
+        "load_job.fieldDelimiter=|".to_owned(),
+    ])
+    .unwrap()
+    .deserialize::<GCloudDriverArguments>()
+    .unwrap();
+    let options = args.load_options().unwrap();
+    assert_eq!(options.overrides["maxBadRecords"], Value::from(10));
+    assert_eq!(options.overrides["fieldDelimiter"], Value::from("|"));
+
+    let args = DriverArguments::from_cli_args(&[
+        "raw.maxBadRecords=10".to_owned(),
+        "load_job.maxBadRecords=5".to_owned(),
+    ])
+    .unwrap()
+    .deserialize::<GCloudDriverArguments>()
+    .unwrap();
+    assert!(args.load_options().is_err());
+}
+
+#[test]
+fn load_options_include_load_job_flags() {
+    let args = DriverArguments::from_cli_args(&[
+        "ignore_unknown_values=true".to_owned(),
+        "allow_jagged_rows=true".to_owned(),
+        "allow_field_relaxation=true".to_owned(),
+    ])
+    .unwrap()
+    .deserialize::<GCloudDriverArguments>()
+    .unwrap();
+    let options = args.load_options().unwrap();
+    assert_eq!(options.ignore_unknown_values, Some(true));
+    assert_eq!(options.allow_jagged_rows, Some(true));
+    assert_eq!(
+        options.schema_update_options,
+        vec![SchemaUpdateOption::AllowFieldRelaxation],
+    );
 }
@@ -13,6 +13,7 @@
 mod column;
 mod column_name;
 mod data_type;
+mod delete_propagation;
 mod driver_args;
 mod export_udf;
 mod import_udf;
@@ -23,6 +24,7 @@ mod table_name;
 pub(crate) use self::column::*;
 pub(crate) use self::column_name::*;
 pub(crate) use self::data_type::*;
+pub(crate) use self::delete_propagation::*;
 pub(crate) use self::driver_args::*;
 pub(crate) use self::table::*;
 pub(crate) use self::table_name::*;
@@ -8,8 +8,12 @@ use std::{
     iter::FromIterator,
 };
 
-use super::{BqColumn, ColumnBigQueryExt, ColumnName, TableName, Usage};
+use super::{
+    BqColumn, ColumnBigQueryExt, ColumnName, DeleteMode, DeletePropagation,
+    GCloudDriverArguments, TableName, Usage, DELETED_AT_COLUMN,
+};
 use crate::clouds::gcloud::bigquery;
+use crate::column_matching::ColumnRenames;
 use crate::common::*;
 use crate::schema::{Column, Table};
 
@@ -24,6 +28,17 @@ enum CreateTableType {
     OrReplace,
 }
 
+impl CreateTableType {
+    /// Choose the `CREATE TABLE` variant to use for `if_exists`.
+    fn for_if_exists(if_exists: &IfExists) -> Self {
+        match if_exists {
+            IfExists::Append | IfExists::Upsert(_) => CreateTableType::IfNotExists,
+            IfExists::Error => CreateTableType::Plain,
+            IfExists::Overwrite => CreateTableType::OrReplace,
+        }
+    }
+}
+
 impl fmt::Display for CreateTableType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -166,8 +181,13 @@ impl BqTable {
         &self,
         source_table_name: &TableName,
         if_exists: &IfExists,
+        deletes: Option<&DeletePropagation>,
         f: &mut dyn Write,
     ) -> Result<()> {
+        if let Some(deletes) = deletes {
+            deletes.check_if_exists(if_exists)?;
+        }
+
         // Write out any helper functions we'll need to transform data.
         for (idx, col) in self.columns.iter().enumerate() {
             col.write_import_udf(f, idx)?;
@@ -175,12 +195,11 @@ impl BqTable {
 
         // Create the table with appropriate options. We do this explicitly so
         // that we preserve the NULLABLE property of each column.
-        let create_table_type = match if_exists {
-            IfExists::Append | IfExists::Upsert(_) => CreateTableType::IfNotExists,
-            IfExists::Error => CreateTableType::Plain,
-            IfExists::Overwrite => CreateTableType::OrReplace,
-        };
-        self.write_create_table_sql(create_table_type, f)?;
+        self.write_create_table_sql(
+            CreateTableType::for_if_exists(if_exists),
+            deletes,
+            f,
+        )?;
         writeln!(f)?;
 
         match if_exists {
@@ -188,17 +207,109 @@ impl BqTable {
                 self.write_insert_sql(source_table_name, f)?;
             }
             IfExists::Upsert(merge_keys) => {
-                self.write_merge_sql(source_table_name, merge_keys, f)?;
+                let source = source_table_name.dotted_and_quoted().to_string();
+                self.write_merge_sql(&source, merge_keys, true, deletes, f)?;
             }
         }
 
         Ok(())
     }
 
-    /// Write a CREATE TABLE statement for this table.
+    /// Generate SQL which copies rows directly from another BigQuery table,
+    /// without going through CSV files or temporary storage. The columns of
+    /// `source_table_name` must already have the correct BigQuery types.
+    ///
+    /// This `BqTable` should have been created with `Usage::FinalTable`.
+    pub(crate) fn write_copy_sql(
+        &self,
+        source_table_name: &TableName,
+        source_args: &SourceArguments<Verified>,
+        renames: &ColumnRenames,
+        if_exists: &IfExists,
+        deletes: Option<&DeletePropagation>,
+        f: &mut dyn Write,
+    ) -> Result<()> {
+        if let Some(deletes) = deletes {
+            deletes.check_if_exists(if_exists)?;
+        }
+        self.write_create_table_sql(
+            CreateTableType::for_if_exists(if_exists),
+            deletes,
+            f,
+        )?;
+        writeln!(f)?;
+
+        // Build a `SELECT` returning our source rows, reading any renamed
+        // columns using their source names.
+        let columns = self.columns.iter().map(|c| c.name.quoted()).join(",");
+        let select_columns = self
+            .columns
+            .iter()
+            .map(|c| {
+                let source = renames.source_name(c.name.as_str());
+                if source == c.name.as_str() {
+                    Ok(c.name.quoted().to_string())
+                } else {
+                    Ok(format!(
+                        "{} AS {}",
+                        ColumnName::try_from(source)?.quoted(),
+                        c.name.quoted(),
+                    ))
+                }
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join(",");
+        let mut select = format!(
+            "SELECT {} FROM {}",
+            select_columns,
+            source_table_name.dotted_and_quoted(),
+        );
+        if let Some(as_of) = source_system_time_clause(source_table_name, source_args)?
+        {
+            select.push_str(&format!(" {}", as_of));
+        }
+        if let Some(where_clause) =
+            source_where_clause(source_table_name, source_args)?
+        {
+            select.push_str(&format!(" WHERE {}", where_clause));
+        }
+
+        match if_exists {
+            IfExists::Append | IfExists::Error | IfExists::Overwrite => {
+                writeln!(
+                    f,
+                    "INSERT INTO {} ({})\n{};",
+                    self.name.dotted_and_quoted(),
+                    columns,
+                    select,
+                )?;
+            }
+            IfExists::Upsert(merge_keys) => {
+                let source = format!("({})", select);
+                self.write_merge_sql(&source, merge_keys, false, deletes, f)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Generate SQL which creates this table as appropriate for `if_exists`,
+    /// without inserting any data.
+    ///
+    /// This `BqTable` should have been created with `Usage::FinalTable`.
+    pub(crate) fn write_create_sql(
+        &self,
+        if_exists: &IfExists,
+        f: &mut dyn Write,
+    ) -> Result<()> {
+        self.write_create_table_sql(CreateTableType::for_if_exists(if_exists), None, f)
+    }
+
+    /// Write a CREATE TABLE statement for this table. If we're soft-deleting
+    /// rows, we also add a `_deleted_at` column.
     fn write_create_table_sql(
         &self,
         create_table_type: CreateTableType,
+        deletes: Option<&DeletePropagation>,
         f: &mut dyn Write,
     ) -> Result<()> {
         // Write the appropriate CREATE TABLE part.
@@ -219,12 +330,38 @@ impl BqTable {
                 write!(f, " NOT NULL")?;
             }
         }
+        if deletes.is_some_and(|d| d.mode == DeleteMode::Soft) {
+            write!(
+                f,
+                ",\n    {} TIMESTAMP",
+                ColumnName::try_from(DELETED_AT_COLUMN)?.quoted(),
+            )?;
+        }
 
         // Write the footer.
         writeln!(f, "\n);")?;
         Ok(())
     }
 
+    /// Generate `ALTER TABLE` SQL which adds our columns to an existing table
+    /// with our name.
+    pub(crate) fn write_add_columns_sql(&self, f: &mut dyn Write) -> Result<()> {
+        write!(f, "ALTER TABLE {}", self.name.dotted_and_quoted())?;
+        for (i, col) in self.columns.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(
+                f,
+                "\n    ADD COLUMN IF NOT EXISTS {} {}",
+                col.name.quoted(),
+                col.bq_data_type()?,
+            )?;
+        }
+        writeln!(f, ";")?;
+        Ok(())
+    }
+
     /// Generate SQL which `SELECT`s from a temp table, and fixes the types
     /// of columns that couldn't be imported from CSVs.
     ///
@@ -253,10 +390,18 @@ impl BqTable {
     }
 
     /// Generate a `MERGE INTO` statement using the specified columns.
+    ///
+    /// `source` may be either a quoted table name or a parenthesized subquery.
+    /// If `source_is_csv_load` is true, we assume that `source` was loaded from
+    /// CSV files, and we convert columns using our import UDFs. If `deletes`
+    /// is specified, source rows marked as deleted will be soft-deleted or
+    /// deleted in the destination, and will never be inserted.
     fn write_merge_sql(
         &self,
-        source_table_name: &TableName,
+        source: &str,
         merge_keys: &[String],
+        source_is_csv_load: bool,
+        deletes: Option<&DeletePropagation>,
         f: &mut dyn Write,
     ) -> Result<()> {
         // Convert `merge_keys` into actual column values for consistency.
@@ -292,12 +437,57 @@ impl BqTable {
 
         // A helper function to generate import SQL for a column.
         let col_import_expr = |c: &BqColumn, idx: usize| -> String {
+            if !source_is_csv_load {
+                return format!("temp.{}", c.name.quoted());
+            }
             let mut buf = vec![];
             c.write_import_expr(&mut buf, idx, Some("temp."))
                 .expect("should always be able to write col_import_expr");
             String::from_utf8(buf).expect("col_import_expr should be UTF-8")
         };
 
+        // Build the extra clauses we need to propagate deletions.
+        let mut deleted_clause = String::new();
+        let mut insert_condition = String::new();
+        let mut updates = self
+            .columns
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, c)| {
+                if merge_key_table.contains(&c.name) {
+                    None
+                } else {
+                    Some(format!(
+                        "{col} = {expr}",
+                        col = c.name.quoted(),
+                        expr = col_import_expr(c, idx),
+                    ))
+                }
+            })
+            .collect::<Vec<_>>();
+        if let Some(deletes) = deletes {
+            let (idx, deleted_col) = deletes.find_deleted_column(&self.columns)?;
+            let is_deleted =
+                format!("IFNULL({}, FALSE)", col_import_expr(deleted_col, idx));
+            let deleted_at = ColumnName::try_from(DELETED_AT_COLUMN)?;
+            deleted_clause = match deletes.mode {
+                DeleteMode::Soft => format!(
+                    "WHEN MATCHED AND {is_deleted} THEN UPDATE SET\n    \
+                     {col} = COALESCE(dest.{col}, CURRENT_TIMESTAMP())\n",
+                    is_deleted = is_deleted,
+                    col = deleted_at.quoted(),
+                ),
+                DeleteMode::Hard => {
+                    format!("WHEN MATCHED AND {} THEN DELETE\n", is_deleted)
+                }
+            };
+            if deletes.mode == DeleteMode::Soft {
+                // Rows which come back after being deleted are undeleted.
+                updates.push(format!("{} = NULL", deleted_at.quoted()));
+            }
+            insert_condition = format!(" AND NOT {}", is_deleted);
+        }
+
         // Generate our actual SQL.
         writeln!(
             f,
@@ -305,15 +495,15 @@ impl BqTable {
 USING {temp_table} AS temp
 ON
     {key_comparisons}
-WHEN MATCHED THEN UPDATE SET
+{deleted_clause}WHEN MATCHED THEN UPDATE SET
     {updates}
-WHEN NOT MATCHED THEN INSERT (
+WHEN NOT MATCHED{insert_condition} THEN INSERT (
     {columns}
 ) VALUES (
     {values}
 );"#,
             dest_table = self.name().dotted_and_quoted(),
-            temp_table = source_table_name.dotted_and_quoted(),
+            temp_table = source,
             key_comparisons = merge_keys
                 .iter()
                 .enumerate()
@@ -323,20 +513,9 @@ WHEN NOT MATCHED THEN INSERT (
                     expr = col_import_expr(c, idx),
                 ))
                 .join(" AND\n    "),
-            updates = self
-                .columns
-                .iter()
-                .enumerate()
-                .filter_map(|(idx, c)| if merge_key_table.contains(&c.name) {
-                    None
-                } else {
-                    Some(format!(
-                        "{col} = {expr}",
-                        col = c.name.quoted(),
-                        expr = col_import_expr(c, idx),
-                    ))
-                })
-                .join(",\n    "),
+            deleted_clause = deleted_clause,
+            updates = updates.join(",\n    "),
+            insert_condition = insert_condition,
             columns = self.columns.iter().map(|c| c.name.quoted()).join(",\n    "),
             values = self
                 .columns
@@ -367,12 +546,51 @@ WHEN NOT MATCHED THEN INSERT (
             col.write_export_select_expr(f, i)?;
         }
         write!(f, " FROM {}", self.name.dotted_and_quoted())?;
-        if let Some(where_clause) = source_args.where_clause() {
-            write!(f, " WHERE ({})", where_clause)?;
+        if let Some(as_of) = source_system_time_clause(&self.name, source_args)? {
+            write!(f, " {}", as_of)?;
+        }
+        if let Some(where_clause) = source_where_clause(&self.name, source_args)? {
+            write!(f, " WHERE {}", where_clause)?;
         }
         Ok(())
     }
 
+    /// Would a BigQuery extract job write the same data as our export SQL?
+    /// This is true if we have no `WHERE` clause, `_TABLE_SUFFIX` bounds or
+    /// `as_of` time, and every column exports unchanged.
+    pub(crate) fn exports_unchanged(
+        &self,
+        source_args: &SourceArguments<Verified>,
+    ) -> Result<bool> {
+        if source_where_clause(&self.name, source_args)?.is_some()
+            || source_system_time_clause(&self.name, source_args)?.is_some()
+        {
+            return Ok(false);
+        }
+        for col in &self.columns {
+            if !col.exports_unchanged()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Would extracting `self`, a full table as returned by `read_from_table`,
+    /// write the same data as exporting `real_table` (the columns we want)
+    /// using our export SQL?
+    pub(crate) fn extracts_same_as(
+        &self,
+        real_table: &BqTable,
+        source_args: &SourceArguments<Verified>,
+    ) -> Result<bool> {
+        let same_columns = self
+            .columns
+            .iter()
+            .map(|c| &c.name)
+            .eq(real_table.columns.iter().map(|c| &c.name));
+        Ok(same_columns && real_table.exports_unchanged(source_args)?)
+    }
+
     pub(crate) fn write_count_sql(
         &self,
         source_args: &SourceArguments<Verified>,
@@ -380,10 +598,409 @@ WHEN NOT MATCHED THEN INSERT (
     ) -> Result<()> {
         write!(f, "SELECT COUNT(*) AS `count`")?;
         write!(f, " FROM {}", self.name.dotted_and_quoted())?;
-        if let Some(where_clause) = source_args.where_clause() {
-            write!(f, " WHERE ({})", where_clause)?;
+        if let Some(as_of) = source_system_time_clause(&self.name, source_args)? {
+            write!(f, " {}", as_of)?;
+        }
+        if let Some(where_clause) = source_where_clause(&self.name, source_args)? {
+            write!(f, " WHERE {}", where_clause)?;
         }
 
         Ok(())
     }
 }
+
+/// Build a `FOR SYSTEM_TIME AS OF` clause for reading from `source_table_name`,
+/// if we were passed `--from-arg=as_of=TIME`.
+fn source_system_time_clause(
+    source_table_name: &TableName,
+    source_args: &SourceArguments<Verified>,
+) -> Result<Option<String>> {
+    let gcloud_args = source_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    gcloud_args.system_time_clause(source_table_name)
+}
+
+/// Build a `WHERE` clause for reading from `source_table_name`, combining any
+/// `--where` argument with the `_TABLE_SUFFIX` bounds for wildcard tables.
+fn source_where_clause(
+    source_table_name: &TableName,
+    source_args: &SourceArguments<Verified>,
+) -> Result<Option<String>> {
+    let gcloud_args = source_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let conditions = source_args
+        .where_clause()
+        .map(|where_clause| format!("({})", where_clause))
+        .into_iter()
+        .chain(gcloud_args.table_suffix_filter(source_table_name)?)
+        .collect::<Vec<_>>();
+    if conditions.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(conditions.join(" AND ")))
+    }
+}
+
+#[test]
+fn write_copy_sql_selects_directly_from_source() {
+    use crate::schema::DataType;
+
+    let columns = vec![
+        Column {
+            name: "id".to_owned(),
+            is_nullable: false,
+            data_type: DataType::Int64,
+            comment: None,
+        },
+        Column {
+            name: "tags".to_owned(),
+            is_nullable: true,
+            data_type: DataType::Array(Box::new(DataType::Text)),
+            comment: None,
+        },
+    ];
+    let dest_name = "project:dataset.dest".parse::<TableName>().unwrap();
+    let source_name = "project:dataset.source".parse::<TableName>().unwrap();
+    let dest_table =
+        BqTable::for_table_name_and_columns(dest_name, &columns, Usage::FinalTable)
+            .unwrap();
+    let source_args =
+        SourceArguments::new(DriverArguments::default(), Some("id > 10".to_owned()))
+            .verify(Features {
+                source_args: SourceArgumentsFeatures::WhereClause.into(),
+                ..Features::empty()
+            })
+            .unwrap();
+
+    let mut sql = vec![];
+    dest_table
+        .write_copy_sql(
+            &source_name,
+            &source_args,
+            &ColumnRenames::default(),
+            &IfExists::Append,
+            None,
+            &mut sql,
+        )
+        .unwrap();
+    let sql = String::from_utf8(sql).unwrap();
+    assert!(sql.contains("CREATE TABLE IF NOT EXISTS `project`.`dataset`.`dest`"));
+    assert!(sql.contains(
+        "SELECT `id`,`tags` FROM `project`.`dataset`.`source` WHERE (id > 10)"
+    ));
+    assert!(!sql.contains("ImportJson"));
+
+    let mut sql = vec![];
+    dest_table
+        .write_copy_sql(
+            &source_name,
+            &source_args,
+            &ColumnRenames::default(),
+            &IfExists::Upsert(vec!["id".to_owned()]),
+            None,
+            &mut sql,
+        )
+        .unwrap();
+    let sql = String::from_utf8(sql).unwrap();
+    assert!(sql.contains("USING (SELECT `id`,`tags` FROM"));
+    assert!(sql.contains("dest.`id` = temp.`id`"));
+
+    // Renamed columns are read using their source names.
+    let source_schema = Table {
+        name: "source".to_owned(),
+        columns: vec![
+            columns[0].clone(),
+            Column {
+                name: "TAGS".to_owned(),
+                ..columns[1].clone()
+            },
+        ],
+    };
+    let renames = ColumnMatching::CaseInsensitive
+        .plan(&source_schema, "dest", vec!["id", "tags"])
+        .unwrap();
+    let mut sql = vec![];
+    dest_table
+        .write_copy_sql(
+            &source_name,
+            &source_args,
+            &renames,
+            &IfExists::Append,
+            None,
+            &mut sql,
+        )
+        .unwrap();
+    let sql = String::from_utf8(sql).unwrap();
+    assert!(sql.contains("SELECT `id`,`TAGS` AS `tags` FROM"));
+}
+
+#[test]
+fn write_import_sql_propagates_deletes() {
+    use crate::schema::DataType;
+
+    let columns = vec![
+        Column {
+            name: "id".to_owned(),
+            is_nullable: false,
+            data_type: DataType::Int64,
+            comment: None,
+        },
+        Column {
+            name: "is_deleted".to_owned(),
+            is_nullable: true,
+            data_type: DataType::Bool,
+            comment: None,
+        },
+    ];
+    let dest_name = "project:dataset.dest".parse::<TableName>().unwrap();
+    let temp_name = "project:dataset.temp".parse::<TableName>().unwrap();
+    let dest_table =
+        BqTable::for_table_name_and_columns(dest_name, &columns, Usage::FinalTable)
+            .unwrap();
+    let upsert = IfExists::Upsert(vec!["id".to_owned()]);
+
+    let soft = DeletePropagation {
+        deleted_column: "is_deleted".to_owned(),
+        mode: DeleteMode::Soft,
+    };
+    let mut sql = vec![];
+    dest_table
+        .write_import_sql(&temp_name, &upsert, Some(&soft), &mut sql)
+        .unwrap();
+    let sql = String::from_utf8(sql).unwrap();
+    assert!(sql.contains("`_deleted_at` TIMESTAMP\n);"));
+    assert!(sql.contains(
+        "WHEN MATCHED AND IFNULL(temp.`is_deleted`, FALSE) THEN UPDATE SET\n    \
+         `_deleted_at` = COALESCE(dest.`_deleted_at`, CURRENT_TIMESTAMP())"
+    ));
+    assert!(sql.contains("`_deleted_at` = NULL"));
+    assert!(sql.contains("WHEN NOT MATCHED AND NOT IFNULL(temp.`is_deleted`, FALSE)"));
+
+    let hard = DeletePropagation {
+        deleted_column: "is_deleted".to_owned(),
+        mode: DeleteMode::Hard,
+    };
+    let mut sql = vec![];
+    dest_table
+        .write_import_sql(&temp_name, &upsert, Some(&hard), &mut sql)
+        .unwrap();
+    let sql = String::from_utf8(sql).unwrap();
+    assert!(!sql.contains("_deleted_at"));
+    assert!(
+        sql.contains("WHEN MATCHED AND IFNULL(temp.`is_deleted`, FALSE) THEN DELETE")
+    );
+
+    let mut sql = vec![];
+    assert!(dest_table
+        .write_import_sql(&temp_name, &IfExists::Append, Some(&hard), &mut sql)
+        .is_err());
+    let missing = DeletePropagation {
+        deleted_column: "gone".to_owned(),
+        mode: DeleteMode::Hard,
+    };
+    let mut sql = vec![];
+    assert!(dest_table
+        .write_import_sql(&temp_name, &upsert, Some(&missing), &mut sql)
+        .is_err());
+}
+
+#[test]
+fn write_add_columns_sql_adds_each_column() {
+    use crate::schema::DataType;
+
+    let column = |name: &str, data_type: DataType| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type,
+        comment: None,
+    };
+    let name = "project:dataset.dest".parse::<TableName>().unwrap();
+    let table = BqTable::for_table_name_and_columns(
+        name,
+        &[
+            column("note", DataType::Text),
+            column("day", DataType::Date),
+        ],
+        Usage::FinalTable,
+    )
+    .unwrap();
+    let mut sql = vec![];
+    table.write_add_columns_sql(&mut sql).unwrap();
+    assert_eq!(
+        String::from_utf8(sql).unwrap(),
+        "ALTER TABLE `project`.`dataset`.`dest`\n    \
+         ADD COLUMN IF NOT EXISTS `note` STRING,\n    \
+         ADD COLUMN IF NOT EXISTS `day` DATE;\n",
+    );
+}
+
+#[test]
+fn exports_unchanged_only_for_simple_columns() {
+    use crate::schema::DataType;
+
+    let column = |name: &str, data_type: DataType| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type,
+        comment: None,
+    };
+    let name = "project:dataset.source".parse::<TableName>().unwrap();
+    let features = Features {
+        source_args: SourceArgumentsFeatures::WhereClause.into(),
+        ..Features::empty()
+    };
+    let no_where = SourceArguments::new(DriverArguments::default(), None)
+        .verify(features)
+        .unwrap();
+    let with_where =
+        SourceArguments::new(DriverArguments::default(), Some("id > 10".to_owned()))
+            .verify(features)
+            .unwrap();
+
+    let simple = BqTable::for_table_name_and_columns(
+        name.clone(),
+        &[
+            column("id", DataType::Int64),
+            column("name", DataType::Text),
+            column("day", DataType::Date),
+        ],
+        Usage::FinalTable,
+    )
+    .unwrap();
+    assert!(simple.exports_unchanged(&no_where).unwrap());
+    assert!(!simple.exports_unchanged(&with_where).unwrap());
+
+    let complex = BqTable::for_table_name_and_columns(
+        name,
+        &[
+            column("id", DataType::Int64),
+            column("done", DataType::Bool),
+        ],
+        Usage::FinalTable,
+    )
+    .unwrap();
+    assert!(!complex.exports_unchanged(&no_where).unwrap());
+}
+
+#[test]
+fn write_export_sql_bounds_wildcard_table_suffix() {
+    use crate::schema::DataType;
+
+    let columns = vec![Column {
+        name: "id".to_owned(),
+        is_nullable: false,
+        data_type: DataType::Int64,
+        comment: None,
+    }];
+    let name = "project:dataset.events_*".parse::<TableName>().unwrap();
+    let table = BqTable::for_table_name_and_columns(name, &columns, Usage::FinalTable)
+        .unwrap();
+    let driver_args = DriverArguments::from_cli_args(&[
+        "table_suffix_min=20200101".to_owned(),
+        "table_suffix_max=20200131".to_owned(),
+    ])
+    .unwrap();
+    let source_args = SourceArguments::new(driver_args, Some("id > 10".to_owned()))
+        .verify(Features {
+            source_args: SourceArgumentsFeatures::DriverArgs
+                | SourceArgumentsFeatures::WhereClause,
+            ..Features::empty()
+        })
+        .unwrap();
+
+    let mut sql = vec![];
+    table.write_export_sql(&source_args, &mut sql).unwrap();
+    assert_eq!(
+        String::from_utf8(sql).unwrap(),
+        "SELECT `id` FROM `project`.`dataset`.`events_*` WHERE (id > 10) AND _TABLE_SUFFIX >= '20200101' AND _TABLE_SUFFIX <= '20200131'",
+    );
+    assert!(!table.exports_unchanged(&source_args).unwrap());
+}
+
+#[test]
+fn write_export_sql_reads_as_of_fixed_time() {
+    use crate::schema::DataType;
+
+    let columns = vec![Column {
+        name: "id".to_owned(),
+        is_nullable: false,
+        data_type: DataType::Int64,
+        comment: None,
+    }];
+    let table_and_args = |table: &str| {
+        let driver_args =
+            DriverArguments::from_cli_args(&["as_of=2020-06-01T12:00:00Z".to_owned()])
+                .unwrap();
+        let source_args = SourceArguments::new(driver_args, None)
+            .verify(Features {
+                source_args: SourceArgumentsFeatures::DriverArgs.into(),
+                ..Features::empty()
+            })
+            .unwrap();
+        let name = table.parse::<TableName>().unwrap();
+        let table =
+            BqTable::for_table_name_and_columns(name, &columns, Usage::FinalTable)
+                .unwrap();
+        (table, source_args)
+    };
+
+    let (table, args) = table_and_args("project:dataset.events");
+    let mut sql = vec![];
+    table.write_export_sql(&args, &mut sql).unwrap();
+    assert_eq!(
+        String::from_utf8(sql).unwrap(),
+        "SELECT `id` FROM `project`.`dataset`.`events` FOR SYSTEM_TIME AS OF TIMESTAMP '2020-06-01 12:00:00.000000 UTC'",
+    );
+    assert!(!table.exports_unchanged(&args).unwrap());
+
+    // BigQuery doesn't support time travel for wildcard tables.
+    let (table, args) = table_and_args("project:dataset.events_*");
+    assert!(table.write_export_sql(&args, &mut vec![]).is_err());
+}
+
+#[test]
+fn write_copy_sql_reads_as_of_fixed_time() {
+    use crate::schema::DataType;
+
+    let columns = vec![Column {
+        name: "id".to_owned(),
+        is_nullable: false,
+        data_type: DataType::Int64,
+        comment: None,
+    }];
+    let dest_name = "project:dataset.dest".parse::<TableName>().unwrap();
+    let source_name = "project:dataset.source".parse::<TableName>().unwrap();
+    let dest_table =
+        BqTable::for_table_name_and_columns(dest_name, &columns, Usage::FinalTable)
+            .unwrap();
+    let driver_args =
+        DriverArguments::from_cli_args(&["as_of=2020-06-01T12:00:00Z".to_owned()])
+            .unwrap();
+    let source_args = SourceArguments::new(driver_args, Some("id > 10".to_owned()))
+        .verify(Features {
+            source_args: SourceArgumentsFeatures::DriverArgs
+                | SourceArgumentsFeatures::WhereClause,
+            ..Features::empty()
+        })
+        .unwrap();
+
+    let mut sql = vec![];
+    dest_table
+        .write_copy_sql(
+            &source_name,
+            &source_args,
+            &ColumnRenames::default(),
+            &IfExists::Append,
+            None,
+            &mut sql,
+        )
+        .unwrap();
+    let sql = String::from_utf8(sql).unwrap();
+    assert!(sql.contains(
+        "SELECT `id` FROM `project`.`dataset`.`source` FOR SYSTEM_TIME AS OF TIMESTAMP '2020-06-01 12:00:00.000000 UTC' WHERE (id > 10)"
+    ));
+}
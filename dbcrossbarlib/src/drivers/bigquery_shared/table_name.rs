@@ -7,7 +7,9 @@ use std::{fmt, str::FromStr};
 use crate::common::*;
 use crate::drivers::bigquery::BigQueryLocator;
 
-/// A BigQuery table name of the form `"project:dataset.table"`.
+/// A BigQuery table name of the form `"project:dataset.table"`. When used as
+/// a source, the table may end in `*`, in which case it refers to all the
+/// tables with that prefix.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct TableName {
     /// The name of the Google Cloud project.
@@ -34,6 +36,26 @@ impl TableName {
         &self.table
     }
 
+    /// Is this a wildcard table name like `"project:dataset.events_*"`?
+    pub(crate) fn is_wildcard(&self) -> bool {
+        self.table.ends_with('*')
+    }
+
+    /// Return the table name without any trailing wildcard. For wildcard
+    /// names, this is the prefix shared by all the matching tables.
+    pub(crate) fn table_prefix(&self) -> &str {
+        self.table.trim_end_matches('*')
+    }
+
+    /// Return the name of another table in the same dataset.
+    pub(crate) fn sibling(&self, table: &str) -> TableName {
+        TableName {
+            project: self.project.clone(),
+            dataset: self.dataset.clone(),
+            table: table.to_owned(),
+        }
+    }
+
     /// Return a value which will be formatted as
     /// `"\`project\`.\`dataset\`.\`table\`"`, with "backtick" quoting.
     ///
@@ -52,6 +74,8 @@ impl TableName {
                 Regex::new("^([^:.]+):([^:.]+)$").expect("invalid regex in source");
         }
 
+        temporary_storage.check_allowed(BigQueryLocator::scheme())?;
+
         // Decide on what project and dataset to use.
         let temp = temporary_storage.find_scheme(BigQueryLocator::scheme());
         let (project, dataset) = if let Some(temp) = temp {
@@ -70,7 +94,7 @@ impl TableName {
         };
 
         let tag = TemporaryStorage::random_tag();
-        let table = format!("temp_{}_{}", self.table, tag);
+        let table = format!("temp_{}_{}", self.table_prefix(), tag);
         Ok(TableName {
             project,
             dataset,
@@ -98,6 +122,31 @@ fn temporary_table_name() {
         .unwrap()
         .to_string();
     assert!(temp_name.starts_with("project2:temp.temp_table_"));
+
+    // Wildcards are removed from temporary table names.
+    let wildcard_name = "project:dataset.events_*".parse::<TableName>().unwrap();
+    let temp_name = wildcard_name
+        .temporary_table_name(&TemporaryStorage::new(vec![]))
+        .unwrap()
+        .to_string();
+    assert!(temp_name.starts_with("project:dataset.temp_events__"));
+    assert!(!temp_name.contains('*'));
+}
+
+#[test]
+fn wildcard_table_names() {
+    let name = "project:dataset.events_*".parse::<TableName>().unwrap();
+    assert!(name.is_wildcard());
+    assert_eq!(name.table_prefix(), "events_");
+    assert_eq!(
+        name.dotted_and_quoted().to_string(),
+        "`project`.`dataset`.`events_*`",
+    );
+    assert!(!"project:dataset.events"
+        .parse::<TableName>()
+        .unwrap()
+        .is_wildcard());
+    assert!("project:dataset.ev*ents".parse::<TableName>().is_err());
 }
 
 impl fmt::Display for TableName {
@@ -118,6 +167,12 @@ impl FromStr for TableName {
             format_err!("could not parse BigQuery table name: {:?}", s)
         })?;
         let (project, dataset, table) = (&cap[1], &cap[2], &cap[3]);
+        if table.trim_end_matches('*').contains('*') {
+            return Err(format_err!(
+                "BigQuery table names may only contain `*` at the end: {:?}",
+                s,
+            ));
+        }
         Ok(TableName {
             project: project.to_string(),
             dataset: dataset.to_string(),
@@ -0,0 +1,474 @@
+//! Reading CSV files from inside `.zip` and `.tar` archives.
+//!
+//! We read `.tar` files ourselves, in a single pass, emitting each CSV file as
+//! a stream as we reach it. Compressed `.tar` files are decompressed by
+//! `decompress_stream`. For `.zip` files, we use the `unzip` command, which
+//! can jump straight to each member using the archive's central directory.
+
+use futures::executor::block_on;
+use std::{
+    io::{self, BufWriter},
+    path::Path,
+    process::Stdio,
+};
+use tokio::{io::BufReader, process::Command, sync::mpsc};
+
+use crate::common::*;
+use crate::csv_stream::csv_stream_name;
+use crate::decompress::{decompress_stream, missing_tool_error};
+use crate::tokio_glue::{copy_reader_to_stream, SyncStreamReader, SyncStreamWriter};
+
+/// The size of a block in a `.tar` file.
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// An archive format that we know how to read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum ArchiveFormat {
+    /// A `.zip` file.
+    Zip,
+    /// A `.tar` file, which may be compressed.
+    Tar,
+}
+
+impl ArchiveFormat {
+    /// Guess the archive format of `path` from its extension, or return `None`
+    /// if it isn't an archive.
+    pub(super) fn from_path(path: &Path) -> Option<ArchiveFormat> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if [".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tar.zst"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+        {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Read each CSV file in the archive at `path` as a separate stream.
+pub(super) async fn archive_streams(
+    ctx: Context,
+    format: ArchiveFormat,
+    path: &Path,
+) -> Result<BoxStream<CsvStream>> {
+    match format {
+        ArchiveFormat::Zip => {
+            let members = zip_members(&ctx, path).await?;
+            let path = path.to_owned();
+            let streams = stream::iter(members)
+                .map(move |member| zip_member_stream(&ctx, &path, &member));
+            Ok(streams.boxed())
+        }
+        ArchiveFormat::Tar => tar_streams(ctx, path).await,
+    }
+}
+
+/// The name of the stream we use for `member`.
+fn member_stream_name(member: &str) -> Result<String> {
+    let relative = member.trim_start_matches("./");
+    Ok(csv_stream_name("/", &format!("/{}", relative))?.to_owned())
+}
+
+/// List the CSV files in the `.zip` file at `path`.
+async fn zip_members(ctx: &Context, path: &Path) -> Result<Vec<String>> {
+    debug!(ctx.log(), "listing zip archive {}", path.display());
+    let output = Command::new("unzip")
+        .arg("-Z1")
+        .arg(path)
+        .stderr(Stdio::inherit())
+        .output()
+        .await
+        .map_err(|err| missing_tool_error(err, "unzip", "read .zip files"))
+        .with_context(|_| format!("error listing files in {}", path.display()))?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "error listing files in {}: {}",
+            path.display(),
+            output.status,
+        ));
+    }
+    let listing = String::from_utf8(output.stdout)
+        .with_context(|_| format!("non-UTF-8 file names in {}", path.display()))?;
+    Ok(csv_members(&listing))
+}
+
+/// Pick out the CSV files from a `.zip` listing.
+fn csv_members(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .filter(|member| is_csv_member(member))
+        .map(|member| member.to_owned())
+        .collect()
+}
+
+/// Is `member` a CSV file? Unlike when reading a directory, we ignore other
+/// files, because archives often contain things like `README` files.
+fn is_csv_member(member: &str) -> bool {
+    let lower = member.to_ascii_lowercase();
+    lower.ends_with(".csv") && !lower.starts_with("__macosx/")
+}
+
+/// Stream `member` from the `.zip` file at `path`.
+fn zip_member_stream(ctx: &Context, path: &Path, member: &str) -> Result<CsvStream> {
+    let name = member_stream_name(member)?;
+    let ctx = ctx.child(o!(
+        "stream" => name.clone(),
+        "archive" => format!("{}", path.display()),
+        "member" => member.to_owned(),
+    ));
+    debug!(ctx.log(), "extracting {} from {}", member, path.display());
+
+    // `unzip` treats member names as wildcard patterns, so escape any wildcard
+    // characters.
+    let mut escaped = String::with_capacity(member.len());
+    for c in member.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    let mut child = Command::new("unzip")
+        .arg("-p")
+        .arg(path)
+        .arg(escaped)
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| missing_tool_error(err, "unzip", "read .zip files"))
+        .with_context(|_| format!("error extracting files from {}", path.display()))?;
+    let child_stdout = child.stdout.take().expect("child should have stdout");
+    let child_stdout = BufReader::with_capacity(BUFFER_SIZE, child_stdout);
+    let data = copy_reader_to_stream(ctx.clone(), child_stdout)?;
+    ctx.spawn_process(
+        format!("extracting {} from {}", member, path.display()),
+        child,
+    );
+    Ok(CsvStream {
+        name,
+        data: data.boxed(),
+    })
+}
+
+/// Read the `.tar` file at `path` in a single pass, returning a stream which
+/// contains each CSV file as we reach it. Each stream must be read to the end
+/// before we can produce the next one.
+async fn tar_streams(ctx: Context, path: &Path) -> Result<BoxStream<CsvStream>> {
+    debug!(ctx.log(), "reading tar archive {}", path.display());
+    let ctx = ctx.child(o!("archive" => format!("{}", path.display())));
+    let data = ctx.file_io().read_file(&ctx, path).await?;
+    let data = decompress_stream(&ctx, data).await?;
+    let rdr = SyncStreamReader::new(ctx.clone(), data);
+
+    // Send each CSV file to `sender` as soon as we reach it.
+    let (mut sender, receiver) = mpsc::channel::<Result<CsvStream>>(1);
+    let worker_ctx = ctx.clone();
+    let path = path.to_owned();
+    let worker = spawn_blocking(move || -> Result<()> {
+        read_tar(rdr, |member, data| {
+            if !is_csv_member(member) {
+                return Ok(());
+            }
+            let name = member_stream_name(member)?;
+            let ctx = worker_ctx.child(o!(
+                "stream" => name.clone(),
+                "member" => member.to_owned(),
+            ));
+            debug!(ctx.log(), "extracting {} from {}", member, path.display());
+            let (mut wtr, stream_data) = SyncStreamWriter::pipe(ctx);
+            let csv_stream = CsvStream {
+                name,
+                data: stream_data.boxed(),
+            };
+            block_on(sender.send(Ok(csv_stream))).map_send_err()?;
+            let mut buffered = BufWriter::with_capacity(BUFFER_SIZE, &mut wtr);
+            let copied = io::copy(data, &mut buffered).and_then(|_| buffered.flush());
+            drop(buffered);
+            if let Err(err) = copied {
+                let err = format_err!(
+                    "error reading {} from {}: {}",
+                    member,
+                    path.display(),
+                    err,
+                );
+                // If our reader has gone away, it doesn't need the error.
+                let _ = wtr.send_error(format_err!("{}", err));
+                return Err(err);
+            }
+            Ok(())
+        })
+        .with_context(|_| format!("error reading {}", path.display()))?;
+        Ok(())
+    });
+    ctx.spawn_worker(worker.boxed());
+    Ok(receiver.boxed())
+}
+
+/// Read a `.tar` file from `rdr`, calling `f` with the name and contents of
+/// each regular file. We support the original `tar` format, POSIX `ustar`,
+/// GNU long names and `pax` paths, which covers the files written by GNU `tar`
+/// and `bsdtar`.
+fn read_tar<R, F>(mut rdr: R, mut f: F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(&str, &mut dyn Read) -> Result<()>,
+{
+    let mut header = [0u8; TAR_BLOCK_SIZE];
+    let mut long_name: Option<String> = None;
+    loop {
+        if !read_tar_block(&mut rdr, &mut header)? {
+            // Some tools omit the zero blocks at the end of the archive.
+            return Ok(());
+        }
+        if header.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+        check_tar_checksum(&header)?;
+        let size = tar_header_size(&header)?;
+        let padded_size = size
+            .checked_add(TAR_BLOCK_SIZE as u64 - 1)
+            .ok_or_else(|| format_err!("tar member is too large"))?
+            / TAR_BLOCK_SIZE as u64
+            * TAR_BLOCK_SIZE as u64;
+        let mut data = (&mut rdr).take(size);
+        match header[156] {
+            // A GNU long name for the next member.
+            b'L' => {
+                let mut name = vec![];
+                data.read_to_end(&mut name)?;
+                long_name = Some(tar_string(&name)?.to_owned());
+            }
+            // A `pax` header, which may contain a long name for the next
+            // member.
+            b'x' => {
+                let mut records = vec![];
+                data.read_to_end(&mut records)?;
+                if let Some(path) = pax_path(&records)? {
+                    long_name = Some(path);
+                }
+            }
+            // A regular file.
+            b'0' | 0 => {
+                let name = match long_name.take() {
+                    Some(name) => name,
+                    None => tar_header_name(&header)?,
+                };
+                f(&name, &mut data)?;
+            }
+            // Directories, links and anything else we don't need.
+            _ => long_name = None,
+        }
+
+        // Skip anything `f` didn't read, plus the padding after it.
+        let skip = padded_size - (size - data.limit());
+        io::copy(&mut (&mut rdr).take(skip), &mut io::sink())?;
+    }
+}
+
+/// Read exactly one block from `rdr` into `block`. Returns `false` if we're
+/// at the end of `rdr`.
+fn read_tar_block<R: Read>(rdr: &mut R, block: &mut [u8]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match rdr.read(&mut block[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => {
+                return Err(format_err!("tar file ended in the middle of a header"))
+            }
+            Ok(count) => filled += count,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Check the checksum of a `.tar` header, which also tells us whether we're
+/// actually reading a `.tar` file.
+fn check_tar_checksum(header: &[u8]) -> Result<()> {
+    let expected = parse_tar_octal(&header[148..156])?;
+    let actual = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                u64::from(b' ')
+            } else {
+                u64::from(b)
+            }
+        })
+        .sum::<u64>();
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(format_err!("invalid tar header (is this a tar file?)"))
+    }
+}
+
+/// Get the size of a `.tar` member from its header. Large sizes are stored in
+/// base-256, with the high bit of the first byte set.
+fn tar_header_size(header: &[u8]) -> Result<u64> {
+    let field = &header[124..136];
+    if field[0] & 0x80 != 0 {
+        let mut size = u64::from(field[0] & 0x7f);
+        for &b in &field[1..] {
+            size = size
+                .checked_mul(256)
+                .and_then(|size| size.checked_add(u64::from(b)))
+                .ok_or_else(|| format_err!("tar member is too large"))?;
+        }
+        Ok(size)
+    } else {
+        parse_tar_octal(field)
+    }
+}
+
+/// Parse an octal number from a `.tar` header, which may be padded with spaces
+/// or NUL bytes.
+fn parse_tar_octal(field: &[u8]) -> Result<u64> {
+    let s = tar_string(field)?.trim();
+    if s.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(s, 8)
+        .with_context(|_| format!("invalid number {:?} in tar header", s))
+        .map_err(Error::from)
+}
+
+/// Get the name of a `.tar` member from its header, including any `ustar`
+/// prefix.
+fn tar_header_name(header: &[u8]) -> Result<String> {
+    let name = tar_string(&header[0..100])?;
+    if &header[257..262] == b"ustar" {
+        let prefix = tar_string(&header[345..500])?;
+        if !prefix.is_empty() {
+            return Ok(format!("{}/{}", prefix, name));
+        }
+    }
+    Ok(name.to_owned())
+}
+
+/// Convert a NUL-terminated field in a `.tar` header to a string.
+fn tar_string(field: &[u8]) -> Result<&str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    str::from_utf8(&field[..len])
+        .context("non-UTF-8 file name in tar file")
+        .map_err(Error::from)
+}
+
+/// Find the `path` in a `pax` extended header, which contains records like
+/// `"20 path=dir/file.csv\n"`, where 20 is the length of the record.
+fn pax_path(mut records: &[u8]) -> Result<Option<String>> {
+    let err = || format_err!("invalid pax header in tar file");
+    let mut path = None;
+    while !records.is_empty() {
+        let space = records.iter().position(|&b| b == b' ').ok_or_else(err)?;
+        let len = str::from_utf8(&records[..space])
+            .ok()
+            .and_then(|len| len.parse::<usize>().ok())
+            .filter(|&len| len > space + 1 && len <= records.len())
+            .ok_or_else(err)?;
+        let record =
+            str::from_utf8(&records[space + 1..len - 1]).map_err(|_| err())?;
+        if let Some(value) = record.strip_prefix("path=") {
+            path = Some(value.to_owned());
+        }
+        records = &records[len..];
+    }
+    Ok(path)
+}
+
+#[test]
+fn recognizes_archive_extensions() {
+    assert_eq!(
+        ArchiveFormat::from_path(Path::new("data/Partner.ZIP")),
+        Some(ArchiveFormat::Zip),
+    );
+    assert_eq!(
+        ArchiveFormat::from_path(Path::new("data.tar.gz")),
+        Some(ArchiveFormat::Tar),
+    );
+    assert_eq!(
+        ArchiveFormat::from_path(Path::new("data.tgz")),
+        Some(ArchiveFormat::Tar),
+    );
+    assert_eq!(ArchiveFormat::from_path(Path::new("data.csv")), None);
+    assert_eq!(ArchiveFormat::from_path(Path::new("dir/")), None);
+}
+
+#[test]
+fn lists_csv_members() {
+    let listing = "README.txt\nexports/\nexports/a.csv\nexports/B.CSV\n__MACOSX/exports/._a.csv\n";
+    assert_eq!(
+        csv_members(listing),
+        vec!["exports/a.csv".to_owned(), "exports/B.CSV".to_owned()],
+    );
+}
+
+#[test]
+fn reads_tar_members_in_one_pass() {
+    /// Build a `.tar` header for `name`.
+    fn header(name: &str, size: usize, typeflag: u8) -> Vec<u8> {
+        let mut header = vec![0u8; TAR_BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].copy_from_slice(b"        ");
+        let checksum = header.iter().map(|&b| u64::from(b)).sum::<u64>();
+        header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+        header
+    }
+
+    /// Add a member to `tar`.
+    fn add(tar: &mut Vec<u8>, name: &str, typeflag: u8, data: &[u8]) {
+        tar.extend(header(name, data.len(), typeflag));
+        tar.extend(data);
+        let padding = (TAR_BLOCK_SIZE - data.len() % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+        tar.extend(vec![0u8; padding]);
+    }
+
+    let long_name = format!("{}/long.csv", "d".repeat(120));
+    let mut tar = vec![];
+    add(&mut tar, "dir/", b'5', b"");
+    add(&mut tar, "dir/a.csv", b'0', b"x\n1\n");
+    add(&mut tar, "README", b'0', &[b'r'; 600]);
+    add(
+        &mut tar,
+        "././@LongLink",
+        b'L',
+        format!("{}\0", long_name).as_bytes(),
+    );
+    add(&mut tar, "truncated", b'0', b"x\n2\n");
+    let pax = "25 path=dir/pax name.csv\n";
+    add(&mut tar, "PaxHeader", b'x', pax.as_bytes());
+    add(&mut tar, "short", 0, b"x\n3\n");
+    tar.extend(vec![0u8; 2 * TAR_BLOCK_SIZE]);
+
+    let mut members = vec![];
+    read_tar(&tar[..], |name, data| {
+        // Only read part of README, to make sure we skip the rest.
+        let mut contents = vec![0u8; 3];
+        let len = data.read(&mut contents)?;
+        contents.truncate(len);
+        members.push((name.to_owned(), String::from_utf8(contents).unwrap()));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(
+        members,
+        vec![
+            ("dir/a.csv".to_owned(), "x\n1".to_owned()),
+            ("README".to_owned(), "rrr".to_owned()),
+            (long_name, "x\n2".to_owned()),
+            ("dir/pax name.csv".to_owned(), "x\n3".to_owned()),
+        ],
+    );
+    assert!(is_csv_member("dir/a.csv"));
+    assert!(!is_csv_member("README"));
+
+    // Something which isn't a tar file.
+    assert!(read_tar(&[b'a'; TAR_BLOCK_SIZE][..], |_, _| Ok(())).is_err());
+}
@@ -0,0 +1,186 @@
+//! Checking that every CSV file in a directory has the columns we expect.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use super::source_args::CsvSourceArguments;
+use crate::common::*;
+use crate::decompress::open_decompressed;
+
+/// How to make the columns of a CSV file match our schema.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) enum ColumnOrder {
+    /// The file's columns already match our schema.
+    Matches,
+    /// The file has the same columns as our schema, but in a different order.
+    /// For each column in our schema, this is the index of the matching column
+    /// in the file.
+    Reorder(Vec<usize>),
+}
+
+/// Check the header of each file in `paths` against `schema`, and decide how
+/// to handle the columns in each file. If any files don't match, report all of
+/// them at once.
+pub(super) fn check_headers(
+    schema: &Table,
+    csv_source_args: &CsvSourceArguments,
+    base_path: &Path,
+    paths: &[PathBuf],
+) -> Result<Vec<ColumnOrder>> {
+    let mut orders = Vec::with_capacity(paths.len());
+    let mut problems = vec![];
+    for path in paths {
+        let mut rdr = csv_source_args.reader(open_decompressed(path)?)?;
+        let mut record = csv::ByteRecord::new();
+        csv_source_args
+            .read_record(&mut rdr, &mut record)
+            .with_context(|_| format!("error reading {}", path.display()))?;
+        let header = record
+            .iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect::<Vec<_>>();
+        match column_order(schema, &header) {
+            Ok(order) => orders.push(order),
+            Err(problem) => problems.push(format!("{}: {}", path.display(), problem)),
+        }
+    }
+    if problems.is_empty() {
+        Ok(orders)
+    } else {
+        Err(format_err!(
+            "{} of {} CSV files in {} do not match the schema:\n- {}",
+            problems.len(),
+            paths.len(),
+            base_path.display(),
+            problems.join("\n- "),
+        ))
+    }
+}
+
+/// Compare `header` to the columns in `schema`. If they can't be made to
+/// match, describe the problem.
+fn column_order(schema: &Table, header: &[String]) -> Result<ColumnOrder, String> {
+    let expected = schema
+        .columns
+        .iter()
+        .map(|c| &c.name[..])
+        .collect::<Vec<_>>();
+    if header.iter().map(|h| &h[..]).eq(expected.iter().cloned()) {
+        return Ok(ColumnOrder::Matches);
+    }
+
+    let mut seen = HashSet::new();
+    let duplicates = header
+        .iter()
+        .filter(|h| !seen.insert(&h[..]))
+        .map(|h| &h[..])
+        .collect::<Vec<_>>();
+    let missing = expected
+        .iter()
+        .filter(|name| !header.iter().any(|h| h == *name))
+        .cloned()
+        .collect::<Vec<_>>();
+    let unexpected = header
+        .iter()
+        .map(|h| &h[..])
+        .filter(|h| !expected.contains(h))
+        .collect::<Vec<_>>();
+
+    let mut problems = vec![];
+    if !duplicates.is_empty() {
+        problems.push(format!("duplicate columns {}", duplicates.join(", ")));
+    }
+    if !missing.is_empty() {
+        problems.push(format!("missing columns {}", missing.join(", ")));
+    }
+    if !unexpected.is_empty() {
+        problems.push(format!("unexpected columns {}", unexpected.join(", ")));
+    }
+    if !problems.is_empty() {
+        return Err(problems.join("; "));
+    }
+
+    // Same columns, different order.
+    let order = expected
+        .iter()
+        .map(|name| {
+            header
+                .iter()
+                .position(|h| h == name)
+                .expect("already checked for missing columns")
+        })
+        .collect();
+    Ok(ColumnOrder::Reorder(order))
+}
+
+/// Copy CSV data from `rdr` to `wtr`, writing column `order[i]` of each input
+/// row (including the header) as column `i` of the output.
+pub(super) fn reorder_columns<R: Read, W: Write>(
+    rdr: R,
+    wtr: W,
+    order: &[usize],
+) -> Result<()> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(rdr);
+    let mut wtr = csv::Writer::from_writer(wtr);
+    let mut record = csv::ByteRecord::new();
+    let mut reordered = csv::ByteRecord::new();
+    while rdr.read_byte_record(&mut record)? {
+        reordered.clear();
+        for &i in order {
+            let field = record
+                .get(i)
+                .ok_or_else(|| format_err!("CSV row has too few columns"))?;
+            reordered.push_field(field);
+        }
+        wtr.write_byte_record(&reordered)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[test]
+fn compares_headers_to_schema() {
+    use crate::schema::{Column, DataType};
+
+    let column = |name: &str| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type: DataType::Text,
+        comment: None,
+    };
+    let schema = Table {
+        name: "example".to_owned(),
+        columns: vec![column("a"), column("b"), column("c")],
+    };
+    let header =
+        |names: &[&str]| names.iter().map(|&n| n.to_owned()).collect::<Vec<String>>();
+
+    assert_eq!(
+        column_order(&schema, &header(&["a", "b", "c"])).unwrap(),
+        ColumnOrder::Matches,
+    );
+    assert_eq!(
+        column_order(&schema, &header(&["c", "a", "b"])).unwrap(),
+        ColumnOrder::Reorder(vec![1, 2, 0]),
+    );
+    assert_eq!(
+        column_order(&schema, &header(&["a", "b", "d"])).unwrap_err(),
+        "missing columns c; unexpected columns d",
+    );
+    assert_eq!(
+        column_order(&schema, &header(&["a", "b", "c", "a"])).unwrap_err(),
+        "duplicate columns a",
+    );
+}
+
+#[test]
+fn reorders_columns() {
+    let input = b"c,a,b\n3,1,\"x,y\"\n";
+    let mut output = vec![];
+    reorder_columns(&input[..], &mut output, &[1, 2, 0]).unwrap();
+    assert_eq!(output, b"a,b,c\n1,\"x,y\",3\n".to_vec());
+}
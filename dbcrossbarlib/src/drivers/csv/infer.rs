@@ -0,0 +1,154 @@
+//! Inferring column types from a sample of CSV data.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+use uuid::Uuid;
+
+use super::source_args::{CsvSourceArguments, RecordReader};
+use crate::common::*;
+use crate::from_csv_cell::FromCsvCell;
+use crate::schema::DataType;
+
+/// The types we can infer, from most to least specific. If a column could be
+/// more than one of these, we use the first.
+const CANDIDATES: &[DataType] = &[
+    DataType::Int64,
+    DataType::Float64,
+    DataType::Bool,
+    DataType::Date,
+    DataType::TimestampWithoutTimeZone,
+    DataType::TimestampWithTimeZone,
+    DataType::Uuid,
+];
+
+/// Can `cell` be parsed as a value of type `data_type`?
+fn cell_matches(data_type: &DataType, cell: &str) -> bool {
+    match data_type {
+        DataType::Int64 => i64::from_csv_cell(cell).is_ok(),
+        // Don't treat words like `inf` or `NaN` as numbers.
+        DataType::Float64 => {
+            cell.bytes().any(|b| b.is_ascii_digit())
+                && f64::from_csv_cell(cell).is_ok()
+        }
+        // Our CSV format accepts values like `y` or `0`, but those are more
+        // likely to be text or numbers, so only infer booleans from `true` and
+        // `false`.
+        DataType::Bool => {
+            cell.eq_ignore_ascii_case("true") || cell.eq_ignore_ascii_case("false")
+        }
+        DataType::Date => NaiveDate::from_csv_cell(cell).is_ok(),
+        DataType::TimestampWithoutTimeZone => {
+            NaiveDateTime::from_csv_cell(cell).is_ok()
+        }
+        DataType::TimestampWithTimeZone => {
+            DateTime::<FixedOffset>::from_csv_cell(cell).is_ok()
+        }
+        DataType::Uuid => Uuid::from_csv_cell(cell).is_ok(),
+        _ => false,
+    }
+}
+
+/// Infer the type of each of `column_count` columns by reading up to
+/// `max_rows` rows from `rdr`, which should already have read its headers.
+/// Columns with no non-empty values in the sample are treated as text.
+pub(super) fn infer_column_types<R: Read>(
+    csv_source_args: &CsvSourceArguments,
+    rdr: &mut RecordReader<R>,
+    column_count: usize,
+    max_rows: usize,
+) -> Result<Vec<DataType>> {
+    // For each column, the candidate types which matched every value so far,
+    // and whether we've seen any values at all.
+    let mut possible = vec![vec![true; CANDIDATES.len()]; column_count];
+    let mut seen_value = vec![false; column_count];
+    let mut record = csv::ByteRecord::new();
+    let mut rows = 0;
+    while rows < max_rows && csv_source_args.read_record(rdr, &mut record)? {
+        rows += 1;
+        for (idx, cell) in record.iter().enumerate().take(column_count) {
+            if cell.is_empty() {
+                continue;
+            }
+            // Cells which aren't valid UTF-8 can only be text.
+            let cell = String::from_utf8_lossy(cell);
+            seen_value[idx] = true;
+            for (candidate, is_possible) in
+                CANDIDATES.iter().zip(possible[idx].iter_mut())
+            {
+                if *is_possible && !cell_matches(candidate, &cell) {
+                    *is_possible = false;
+                }
+            }
+        }
+    }
+
+    Ok(possible
+        .iter()
+        .zip(seen_value)
+        .map(|(is_possible, seen_value)| {
+            CANDIDATES
+                .iter()
+                .zip(is_possible)
+                .find(|&(_, &is_possible)| seen_value && is_possible)
+                .map(|(candidate, _)| candidate.to_owned())
+                .unwrap_or(DataType::Text)
+        })
+        .collect())
+}
+
+#[test]
+fn infers_column_types() {
+    let data = "\
+i,f,b,d,ts,tstz,u,t,e,mixed
+1,1.5,true,2020-01-31,2020-01-31 10:00:00,2020-01-31T10:00:00+00,67e55044-10b1-426f-9247-bb680e5fe0c8,a,,1
+-2,2,FALSE,2020-02-01,2020-02-01T10:00:00.5,2020-02-01 10:00:00+01:00,67e55044-10b1-426f-9247-bb680e5fe0c9,inf,,x
+,NaN,,,,,,,,
+";
+    let args = CsvSourceArguments::default();
+    let mut header = csv::ByteRecord::new();
+    let mut rdr = args.reader(data.as_bytes()).unwrap();
+    args.read_record(&mut rdr, &mut header).unwrap();
+    let column_count = header.len();
+
+    // If we only look at 2 rows, we never see the `NaN`.
+    let types = infer_column_types(&args, &mut rdr, column_count, 2).unwrap();
+    assert_eq!(
+        types,
+        &[
+            DataType::Int64,
+            DataType::Float64,
+            DataType::Bool,
+            DataType::Date,
+            DataType::TimestampWithoutTimeZone,
+            DataType::TimestampWithTimeZone,
+            DataType::Uuid,
+            DataType::Text,
+            DataType::Text,
+            DataType::Text,
+        ],
+    );
+
+    // But if we look at more rows, `NaN` makes this a text column.
+    let mut rdr = args.reader(data.as_bytes()).unwrap();
+    args.read_record(&mut rdr, &mut header).unwrap();
+    let types = infer_column_types(&args, &mut rdr, column_count, 100).unwrap();
+    assert_eq!(types[1], DataType::Text);
+}
+
+#[test]
+fn infers_column_types_using_source_args() {
+    let data = "exported by tool\n# a comment\ni;d\n1;2020-01-31\n# 2;x\n";
+    let args = DriverArguments::from_cli_args(&[
+        "skip_lines=1".to_owned(),
+        "comment=#".to_owned(),
+        "delimiter=;".to_owned(),
+    ])
+    .unwrap()
+    .deserialize::<CsvSourceArguments>()
+    .unwrap();
+    let mut header = csv::ByteRecord::new();
+    let mut rdr = args.reader(data.as_bytes()).unwrap();
+    args.read_record(&mut rdr, &mut header).unwrap();
+    assert_eq!(header.len(), 2);
+    let types = infer_column_types(&args, &mut rdr, 2, 100).unwrap();
+    assert_eq!(types, &[DataType::Int64, DataType::Date]);
+}
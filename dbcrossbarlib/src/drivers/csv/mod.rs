@@ -1,6 +1,13 @@
 //! Driver for working with CSV files.
 
-use std::{ffi::OsStr, fmt, path::PathBuf, str::FromStr};
+use serde::Deserialize;
+use std::{
+    ffi::OsStr,
+    fmt,
+    path::{self, Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 use tokio::{
     fs,
     io::{self, BufReader},
@@ -8,10 +15,28 @@ use tokio::{
 use walkdir::WalkDir;
 
 use crate::common::*;
-use crate::concat::concatenate_csv_streams;
+use crate::concat::{concatenate_csv_streams, sort_csv_streams_by_name};
 use crate::csv_stream::csv_stream_name;
+use crate::decompress::{decompress_stream, open_decompressed};
+use crate::driver_args::deserialize_opt_from_str;
+use crate::row_filter::RowFilter;
 use crate::schema::{Column, DataType, Table};
 use crate::tokio_glue::{copy_reader_to_stream, copy_stream_to_writer};
+use crate::transform::spawn_sync_transform;
+
+mod archive;
+mod headers;
+mod infer;
+mod source_args;
+mod split;
+mod strict;
+
+use self::archive::{archive_streams, ArchiveFormat};
+use self::headers::{check_headers, reorder_columns, ColumnOrder};
+use self::infer::infer_column_types;
+use self::source_args::CsvSourceArguments;
+use self::split::split_csv_file;
+use self::strict::validate_csv;
 
 /// (Incomplete.) A CSV file containing data, or a directory containing CSV
 /// files.
@@ -52,10 +77,12 @@ impl Locator for CsvLocator {
         self
     }
 
-    fn schema(&self, _ctx: Context) -> BoxFuture<Option<Table>> {
+    fn schema(&self, ctx: Context) -> BoxFuture<Option<Table>> {
         // We're going to use a helper thread to do this, because `csv` is a
         // purely synchrnous library.
         let source = self.to_owned();
+        let infer_schema_rows = ctx.infer_schema_rows();
+        let schema_source_args = ctx.schema_source_args().to_owned();
         spawn_blocking(move || {
             match &source.path {
                 PathOrStdio::Stdio => {
@@ -65,29 +92,49 @@ impl Locator for CsvLocator {
                     Err(format_err!("cannot yet read CSV schema from stdin"))
                 }
                 PathOrStdio::Path(path) => {
-                    // Build our columns.
-                    let mut rdr = csv::Reader::from_path(path).with_context(|_| {
-                        format!("error opening {}", path.display())
-                    })?;
+                    // Build our columns, reading the file the same way that
+                    // `local_data` will.
+                    let csv_source_args = schema_source_args
+                        .deserialize::<CsvSourceArguments>()
+                        .context("could not parse --from-arg")?;
+                    let mut rdr = csv_source_args.reader(open_decompressed(path)?)?;
+                    let mut headers = csv::ByteRecord::new();
+                    csv_source_args
+                        .read_record(&mut rdr, &mut headers)
+                        .with_context(|_| {
+                            format!("error reading {}", path.display())
+                        })?;
                     let mut columns = vec![];
-                    let headers = rdr.headers().with_context(|_| {
-                        format!("error reading {}", path.display())
-                    })?;
-                    for col_name in headers {
+                    let data_types = match infer_schema_rows {
+                        Some(rows) => infer_column_types(
+                            &csv_source_args,
+                            &mut rdr,
+                            headers.len(),
+                            rows,
+                        )
+                        .with_context(|_| {
+                            format!("error reading {}", path.display())
+                        })?,
+                        None => vec![DataType::Text; headers.len()],
+                    };
+                    for (col_name, data_type) in headers.iter().zip(data_types) {
                         columns.push(Column {
-                            name: col_name.to_owned(),
+                            name: String::from_utf8_lossy(col_name).into_owned(),
                             is_nullable: true,
-                            data_type: DataType::Text,
+                            data_type,
                             comment: None,
                         })
                     }
 
-                    // Build our table.
-                    let name = path
-                        .file_stem()
-                        .unwrap_or_else(|| OsStr::new("data"))
-                        .to_string_lossy()
-                        .into_owned();
+                    // Build our table, removing both extensions from names like
+                    // `data.csv.gz`.
+                    let mut stem = Path::new(
+                        path.file_stem().unwrap_or_else(|| OsStr::new("data")),
+                    );
+                    if stem.extension() == Some(OsStr::new("csv")) {
+                        stem = Path::new(stem.file_stem().expect("stem has a name"));
+                    }
+                    let name = stem.as_os_str().to_string_lossy().into_owned();
                     Ok(Some(Table { name, columns }))
                 }
             }
@@ -95,6 +142,14 @@ impl Locator for CsvLocator {
         .boxed()
     }
 
+    fn estimate(
+        &self,
+        ctx: Context,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<Estimate>> {
+        estimate_helper(ctx, self.path.clone(), source_args).boxed()
+    }
+
     fn local_data(
         &self,
         ctx: Context,
@@ -131,89 +186,301 @@ async fn local_data_helper(
     shared_args: SharedArguments<Unverified>,
     source_args: SourceArguments<Unverified>,
 ) -> Result<Option<BoxStream<CsvStream>>> {
-    let _shared_args = shared_args.verify(CsvLocator::features())?;
-    let _source_args = source_args.verify(CsvLocator::features())?;
+    let shared_args = shared_args.verify(CsvLocator::features())?;
+    let source_args = source_args.verify(CsvLocator::features())?;
+
+    // Get our CSV-specific source arguments.
+    let csv_source_args = source_args
+        .driver_args()
+        .deserialize::<CsvSourceArguments>()
+        .context("could not parse --from-arg")?;
+
+    // Check our `--where` clause before we start reading anything.
+    let row_filter = source_args
+        .where_clause()
+        .map(|where_clause| RowFilter::new(where_clause, shared_args.schema()))
+        .transpose()?;
+
+    let streams =
+        read_csv_streams(ctx.clone(), path, shared_args.schema(), csv_source_args)
+            .await?;
+    Ok(Some(match row_filter {
+        Some(row_filter) => Arc::new(row_filter).filter_streams(ctx, streams),
+        None => streams,
+    }))
+}
+
+/// Read the CSV streams at `path`, which should match `schema`.
+async fn read_csv_streams(
+    ctx: Context,
+    path: PathOrStdio,
+    schema: &Table,
+    csv_source_args: CsvSourceArguments,
+) -> Result<BoxStream<CsvStream>> {
     match path {
         PathOrStdio::Stdio => {
+            if csv_source_args.split_size()?.is_some() {
+                return Err(format_err!(
+                    "split_mb can only be used with a single CSV file"
+                ));
+            }
             let data = BufReader::with_capacity(BUFFER_SIZE, io::stdin());
-            let stream = copy_reader_to_stream(ctx, data)?;
+            let stream = copy_reader_to_stream(ctx.clone(), data)?.boxed();
+            let stream = decompress_stream(&ctx, stream).await?;
             let csv_stream = CsvStream {
                 name: "data".to_owned(),
-                data: stream
+                data: clean_csv_stream(ctx, stream, &csv_source_args, "stdin")?
                     .map_err(move |e| format_err!("cannot read stdin: {}", e))
                     .boxed(),
             };
-            Ok(Some(box_stream_once(Ok(csv_stream))))
+            Ok(box_stream_once(Ok(csv_stream)))
         }
         PathOrStdio::Path(base_path) => {
-            // Recursively look at our paths, picking out the ones that look
-            // like CSVs. We do this synchronously because it's reasonably
-            // fast and we'd like to catch errors up front.
-            let mut paths = vec![];
-            debug!(ctx.log(), "walking {}", base_path.display());
-            let walker = WalkDir::new(&base_path).follow_links(true);
-            for dirent in walker.into_iter() {
-                let dirent = dirent.with_context(|_| {
-                    format!("error listing files in {}", base_path.display())
-                })?;
-                let p = dirent.path();
-                trace!(ctx.log(), "found dirent {}", p.display());
-                if dirent.file_type().is_dir() {
-                    continue;
-                } else if !dirent.file_type().is_file() {
-                    return Err(format_err!("not a file: {}", p.display()));
-                }
-
-                let ext = p.extension();
-                if ext == Some(OsStr::new("csv")) || ext == Some(OsStr::new("CSV")) {
-                    paths.push(p.to_owned());
-                } else {
+            // Split a single large file into several streams, so that we can
+            // parse it in parallel.
+            if let Some(split_size) = csv_source_args.split_size()? {
+                if is_named_pipe_path(&base_path)
+                    || base_path.is_dir()
+                    || ArchiveFormat::from_path(&base_path).is_some()
+                {
                     return Err(format_err!(
-                        "{} must end in *.csv or *.CSV",
-                        p.display()
+                        "split_mb can only be used with a single CSV file"
                     ));
                 }
+                let stream_path = path_to_stream_path(&base_path);
+                let name = csv_stream_name(&stream_path, &stream_path)?.to_owned();
+                return split_csv_file(ctx, name, base_path, split_size).await;
             }
 
-            let csv_streams = stream::iter(paths).map(Ok).and_then(move |file_path| {
-                let ctx = ctx.clone();
-                let base_path = base_path.clone();
-                async move {
-                    // Get the name of our stream.
-                    let name = csv_stream_name(
-                        &base_path.to_string_lossy(),
-                        &file_path.to_string_lossy(),
-                    )?
-                    .to_owned();
-                    let ctx = ctx.child(o!(
-                        "stream" => name.clone(),
-                        "path" => format!("{}", file_path.display())
-                    ));
+            // Read each CSV file inside an archive as a separate stream.
+            if let Some(format) = ArchiveFormat::from_path(&base_path) {
+                let streams = archive_streams(ctx.clone(), format, &base_path).await?;
+                let csv_streams = streams.and_then(move |stream| {
+                    let ctx = ctx.clone();
+                    let base_path = base_path.clone();
+                    let csv_source_args = csv_source_args.clone();
+                    async move {
+                        let source =
+                            format!("{} in {}", stream.name, base_path.display());
+                        let data = clean_csv_stream(
+                            ctx,
+                            stream.data,
+                            &csv_source_args,
+                            &source,
+                        )?
+                        .map_err(move |e| format_err!("cannot read {}: {}", source, e))
+                        .boxed();
+                        Ok(CsvStream {
+                            name: stream.name,
+                            data,
+                        })
+                    }
+                    .boxed()
+                });
+                return Ok(csv_streams.boxed());
+            }
+
+            // Named pipes can only be opened once, so we read them without
+            // checking their type first.
+            let is_pipe = is_named_pipe_path(&base_path);
+            let paths = if is_pipe {
+                vec![base_path.clone()]
+            } else {
+                csv_paths(&ctx, &base_path)?
+            };
+
+            // When reading a directory, make sure that every file has the
+            // columns in our schema, so that one odd file can't corrupt our
+            // output.
+            let column_orders = if !is_pipe && base_path.is_dir() {
+                check_headers(schema, &csv_source_args, &base_path, &paths)?
+            } else {
+                vec![ColumnOrder::Matches; paths.len()]
+            };
+
+            let files = paths.into_iter().zip(column_orders);
+            let csv_streams = stream::iter(files).map(Ok).and_then(
+                move |(file_path, column_order)| {
+                    let ctx = ctx.clone();
+                    let base_path = base_path.clone();
+                    let csv_source_args = csv_source_args.clone();
+                    async move {
+                        // Get the name of our stream.
+                        let name = csv_stream_name(
+                            &path_to_stream_path(&base_path),
+                            &path_to_stream_path(&file_path),
+                        )?
+                        .to_owned();
+                        let ctx = ctx.child(o!(
+                            "stream" => name.clone(),
+                            "path" => format!("{}", file_path.display())
+                        ));
+
+                        // Open our file.
+                        let stream = ctx.file_io().read_file(&ctx, &file_path).await?;
+                        let stream = decompress_stream(&ctx, stream).await?;
+                        let source = file_path.display().to_string();
+                        let mut data = clean_csv_stream(
+                            ctx.clone(),
+                            stream,
+                            &csv_source_args,
+                            &source,
+                        )?
+                        .map_err(move |e| {
+                            format_err!("cannot read {}: {}", file_path.display(), e)
+                        })
+                        .boxed();
+
+                        // Put our columns in the same order as our schema.
+                        if let ColumnOrder::Reorder(order) = column_order {
+                            data = spawn_sync_transform(
+                                ctx,
+                                "reorder_columns".to_owned(),
+                                data,
+                                move |_ctx, rdr, wtr| {
+                                    reorder_columns(rdr, wtr, &order)
+                                },
+                            )?;
+                        }
+
+                        Ok(CsvStream { name, data })
+                    }
+                    .boxed()
+                },
+            );
+
+            Ok(csv_streams.boxed())
+        }
+    }
+}
+
+/// Apply any validation and cleanups requested by `csv_source_args` to `data`,
+/// which was read from `source`.
+fn clean_csv_stream(
+    ctx: Context,
+    mut data: BoxStream<BytesMut>,
+    csv_source_args: &CsvSourceArguments,
+    source: &str,
+) -> Result<BoxStream<BytesMut>> {
+    if let Some(delimiter) = csv_source_args.strict_delimiter()? {
+        let source = source.to_owned();
+        data = spawn_sync_transform(
+            ctx.clone(),
+            "validate_csv".to_owned(),
+            data,
+            move |_ctx, rdr, wtr| validate_csv(rdr, wtr, delimiter, &source),
+        )?;
+    }
+    if csv_source_args.is_default() {
+        Ok(data)
+    } else {
+        let csv_source_args = csv_source_args.clone();
+        spawn_sync_transform(
+            ctx,
+            "clean_csv".to_owned(),
+            data,
+            move |_ctx, rdr, wtr| csv_source_args.clean_csv(rdr, wtr),
+        )
+    }
+}
+
+/// Recursively look at the paths in `base_path`, picking out the ones that look
+/// like CSVs. We do this synchronously because it's reasonably fast and we'd
+/// like to catch errors up front.
+fn csv_paths(ctx: &Context, base_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    debug!(ctx.log(), "walking {}", base_path.display());
+    let walker = WalkDir::new(base_path).follow_links(true);
+    for dirent in walker.into_iter() {
+        let dirent = dirent.with_context(|_| {
+            format!("error listing files in {}", base_path.display())
+        })?;
+        let p = dirent.path();
+        trace!(ctx.log(), "found dirent {}", p.display());
+        if dirent.file_type().is_dir() {
+            continue;
+        } else if !dirent.file_type().is_file() {
+            return Err(format_err!("not a file: {}", p.display()));
+        }
+
+        if is_csv_path(p) {
+            paths.push(p.to_owned());
+        } else {
+            return Err(format_err!(
+                "{} must end in *.csv or *.CSV, optionally followed by .gz, .zst or .bz2",
+                p.display()
+            ));
+        }
+    }
+    Ok(paths)
+}
+
+/// Does `path` look like a CSV file? We also accept compressed CSV files,
+/// which we decompress as we read them.
+fn is_csv_path(path: &Path) -> bool {
+    fn is_csv_ext(ext: Option<&OsStr>) -> bool {
+        ext == Some(OsStr::new("csv")) || ext == Some(OsStr::new("CSV"))
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") | Some("zst") | Some("bz2") => {
+            is_csv_ext(path.file_stem().map(Path::new).and_then(Path::extension))
+        }
+        _ => is_csv_ext(path.extension()),
+    }
+}
 
-                    // Open our file.
-                    let data = fs::File::open(file_path.clone()).await.with_context(
-                        |_| format!("cannot open {}", file_path.display()),
-                    )?;
+#[test]
+fn is_csv_path_accepts_compressed_csv_files() {
+    assert!(is_csv_path(Path::new("dir/file.csv")));
+    assert!(is_csv_path(Path::new("dir/FILE.CSV")));
+    assert!(is_csv_path(Path::new("dir/file.csv.gz")));
+    assert!(is_csv_path(Path::new("dir/file.csv.zst")));
+    assert!(!is_csv_path(Path::new("dir/file.gz")));
+    assert!(!is_csv_path(Path::new("dir/file.txt")));
+}
+
+/// Implementation of `estimate`, but as a real `async` function.
+async fn estimate_helper(
+    ctx: Context,
+    path: PathOrStdio,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<Estimate>> {
+    let _source_args = source_args.verify(CsvLocator::features())?;
+    match path {
+        // We can't look at standard input without consuming it.
+        PathOrStdio::Stdio => Ok(None),
+        PathOrStdio::Path(base_path) => {
+            let paths = csv_paths(&ctx, &base_path)?;
+            let mut total_bytes = 0;
+            for file_path in &paths {
+                let metadata = fs::metadata(file_path).await.with_context(|_| {
+                    format!("cannot stat {}", file_path.display())
+                })?;
+                total_bytes += metadata.len();
+            }
+            let files = u64::try_from(paths.len())?;
+            match paths.first() {
+                Some(first) => {
+                    let data =
+                        fs::File::open(first.clone()).await.with_context(|_| {
+                            format!("cannot open {}", first.display())
+                        })?;
                     let data = BufReader::with_capacity(BUFFER_SIZE, data);
-                    let stream = copy_reader_to_stream(ctx, data)?;
-
-                    Ok(CsvStream {
-                        name,
-                        data: stream
-                            .map_err(move |e| {
-                                format_err!(
-                                    "cannot read {}: {}",
-                                    file_path.display(),
-                                    e
-                                )
-                            })
-                            .boxed(),
-                    })
+                    let sample = copy_reader_to_stream(ctx.clone(), data)?
+                        .map_err(|e| format_err!("cannot read sample: {}", e))
+                        .boxed();
+                    Ok(Some(
+                        Estimate::from_csv_sample(&ctx, sample, total_bytes, files)
+                            .await?,
+                    ))
                 }
-                .boxed()
-            });
-
-            Ok(Some(csv_streams.boxed()))
+                None => Ok(Some(Estimate {
+                    rows: Some(0),
+                    bytes: Some(0),
+                    files: Some(0),
+                    method: "file sizes".to_owned(),
+                })),
+            }
         }
     }
 }
@@ -225,13 +492,43 @@ async fn write_local_data_helper(
     shared_args: SharedArguments<Unverified>,
     dest_args: DestinationArguments<Unverified>,
 ) -> Result<BoxStream<BoxFuture<BoxLocator>>> {
-    let _shared_args = shared_args.verify(CsvLocator::features())?;
+    let shared_args = shared_args.verify(CsvLocator::features())?;
     let dest_args = dest_args.verify(CsvLocator::features())?;
     let if_exists = dest_args.if_exists().to_owned();
+    let compression = dest_args.compression();
+
+    // Get our CSV-specific destination arguments.
+    let csv_dest_args = dest_args
+        .driver_args()
+        .deserialize::<CsvDestinationArguments>()
+        .context("could not parse --to-arg")?;
+    let line_ending = csv_dest_args.line_ending;
+    let convert_ctx = ctx.clone();
+    let mut data = data
+        .and_then(move |stream| {
+            let ctx = convert_ctx.clone();
+            async move { convert_line_endings(ctx, stream, line_ending) }
+        })
+        .boxed();
+
+    // If we're merging our streams, put them in a predictable order. We need
+    // to see every stream to do this, but `--stream-size` won't produce the
+    // next stream until we've written the previous one.
+    let merge = csv_dest_args.merge.unwrap_or(false);
+    if merge {
+        if shared_args.stream_size().is_some() {
+            return Err(format_err!(
+                "--to-arg=merge=true cannot be used with --stream-size"
+            ));
+        }
+        data = sort_csv_streams_by_name(&ctx, data).await?;
+    }
+
     match path {
         PathOrStdio::Stdio => {
             if_exists.warn_if_not_default_for_stdout(&ctx);
             let stream = concatenate_csv_streams(ctx.clone(), data)?;
+            let stream = compression.compress_csv_stream(&ctx, stream)?;
             let fut = async move {
                 copy_stream_to_writer(ctx.clone(), stream.data, io::stdout())
                     .await
@@ -244,7 +541,7 @@ async fn write_local_data_helper(
             Ok(box_stream_once(Ok(fut.boxed())))
         }
         PathOrStdio::Path(path) => {
-            if path.to_string_lossy().ends_with('/') {
+            if is_dir_path(&path) && !merge {
                 // Write streams to our directory as multiple files.
                 let result_stream = data.map_ok(move |stream| {
                     let path = path.clone();
@@ -254,11 +551,16 @@ async fn write_local_data_helper(
                     async move {
                         // TODO: This join does not handle `..` or nested `/` in
                         // a particularly safe fashion.
-                        let csv_path = path.join(&format!("{}.csv", stream.name));
+                        let csv_path = path.join(format!(
+                            "{}.csv{}",
+                            stream.name,
+                            compression.extension(),
+                        ));
                         let ctx = ctx.child(o!(
                             "stream" => stream.name.clone(),
                             "path" => format!("{}", csv_path.display()),
                         ));
+                        let stream = compression.compress_csv_stream(&ctx, stream)?;
                         write_stream_to_file(
                             ctx,
                             stream.data,
@@ -272,8 +574,19 @@ async fn write_local_data_helper(
                 });
                 Ok(result_stream.boxed())
             } else {
-                // Write all our streams as a single file.
+                // Write all our streams as a single file. If we were given a
+                // directory, name the file after our table.
+                let path = if is_dir_path(&path) {
+                    path.join(format!(
+                        "{}.csv{}",
+                        shared_args.schema().name,
+                        compression.extension(),
+                    ))
+                } else {
+                    path
+                };
                 let stream = concatenate_csv_streams(ctx.clone(), data)?;
+                let stream = compression.compress_csv_stream(&ctx, stream)?;
                 let fut = async move {
                     let ctx = ctx.child(o!(
                         "stream" => stream.name.clone(),
@@ -289,6 +602,107 @@ async fn write_local_data_helper(
     }
 }
 
+/// Parsed version of `--to-arg` values.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CsvDestinationArguments {
+    /// The line ending to use when writing CSV files.
+    #[serde(default)]
+    line_ending: LineEnding,
+
+    /// Combine all our streams into a single file, sorted by stream name.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    merge: Option<bool>,
+}
+
+/// Line endings for output CSV files.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum LineEnding {
+    /// Unix-style `\n` line endings. This is our standard format.
+    #[default]
+    Lf,
+    /// Windows-style `\r\n` line endings, which some Windows tools expect.
+    Crlf,
+}
+
+/// Rewrite `stream` to use `line_ending`. Our CSV interchange format always
+/// uses `\n`, so this is a no-op for `LineEnding::Lf`.
+fn convert_line_endings(
+    ctx: Context,
+    stream: CsvStream,
+    line_ending: LineEnding,
+) -> Result<CsvStream> {
+    match line_ending {
+        LineEnding::Lf => Ok(stream),
+        LineEnding::Crlf => {
+            let data = spawn_sync_transform(
+                ctx,
+                "convert_line_endings".to_owned(),
+                stream.data,
+                |_ctx, rdr, wtr| copy_csv_with_crlf(rdr, wtr),
+            )?;
+            Ok(CsvStream {
+                name: stream.name,
+                data,
+            })
+        }
+    }
+}
+
+/// Copy CSV data from `rdr` to `wtr`, terminating each record with `\r\n`.
+/// Line breaks inside quoted cells are left untouched.
+fn copy_csv_with_crlf<R: Read, W: Write>(rdr: R, wtr: W) -> Result<()> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(rdr);
+    let mut wtr = csv::WriterBuilder::new()
+        .flexible(true)
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(wtr);
+    let mut record = csv::ByteRecord::new();
+    while rdr.read_byte_record(&mut record)? {
+        wtr.write_byte_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Does `path` end with a path separator, indicating that it should be
+/// treated as a directory? On Windows, this accepts both `/` and `\\`.
+fn is_dir_path(path: &Path) -> bool {
+    path.to_string_lossy()
+        .chars()
+        .last()
+        .map(path::is_separator)
+        .unwrap_or(false)
+}
+
+/// Convert `path` to a string using `/` as a separator, so that
+/// `csv_stream_name` produces the same stream names on all platforms.
+fn path_to_stream_path(path: &Path) -> String {
+    let s = path.to_string_lossy();
+    if path::MAIN_SEPARATOR == '/' {
+        s.into_owned()
+    } else {
+        s.replace(path::MAIN_SEPARATOR, "/")
+    }
+}
+
+/// Is `path` a Windows named pipe, like `\\.\pipe\NAME`? We only check this
+/// on Windows, because elsewhere, this is an ordinary (if odd) relative path.
+fn is_named_pipe_path(path: &Path) -> bool {
+    cfg!(windows) && is_named_pipe_name(&path.to_string_lossy())
+}
+
+/// Does `name` look like a Windows named pipe? Windows ignores case here.
+fn is_named_pipe_name(name: &str) -> bool {
+    name.get(..9)
+        .map(|prefix| prefix.eq_ignore_ascii_case(r"\\.\pipe\"))
+        .unwrap_or(false)
+}
+
 /// Write `data` to `dest`, honoring `if_exists`.
 async fn write_stream_to_file(
     ctx: Context,
@@ -296,6 +710,21 @@ async fn write_stream_to_file(
     dest: PathBuf,
     if_exists: IfExists,
 ) -> Result<()> {
+    // Named pipes are created by the program reading from them, and they can't
+    // be truncated or replaced, so `if_exists` doesn't apply.
+    if is_named_pipe_path(&dest) {
+        debug!(ctx.log(), "writing stream to named pipe {}", dest.display());
+        let wtr = fs::OpenOptions::new()
+            .write(true)
+            .open(&dest)
+            .await
+            .with_context(|_| format!("cannot open {}", dest.display()))?;
+        copy_stream_to_writer(ctx.clone(), data, wtr)
+            .await
+            .with_context(|_| format!("error writing {}", dest.display()))?;
+        return Ok(());
+    }
+
     // Make sure our destination directory exists.
     let dir = dest
         .parent()
@@ -306,15 +735,9 @@ async fn write_stream_to_file(
 
     // Write our our CSV stream.
     debug!(ctx.log(), "writing stream to file {}", dest.display());
-    let wtr = if_exists
-        .to_async_open_options_no_append()?
-        .open(dest.clone())
+    ctx.file_io()
+        .write_file(&ctx, data, &dest, &if_exists)
         .await
-        .with_context(|_| format!("cannot open {}", dest.display()))?;
-    copy_stream_to_writer(ctx.clone(), data, wtr)
-        .await
-        .with_context(|_| format!("error writing {}", dest.display()))?;
-    Ok(())
 }
 
 impl LocatorStatic for CsvLocator {
@@ -326,12 +749,50 @@ impl LocatorStatic for CsvLocator {
         Features {
             locator: LocatorFeatures::Schema
                 | LocatorFeatures::LocalData
-                | LocatorFeatures::WriteLocalData,
+                | LocatorFeatures::WriteLocalData
+                | LocatorFeatures::Estimate,
             write_schema_if_exists: EnumSet::empty(),
-            source_args: EnumSet::empty(),
-            dest_args: EnumSet::empty(),
+            source_args: SourceArgumentsFeatures::DriverArgs
+                | SourceArgumentsFeatures::WhereClause,
+            dest_args: DestinationArgumentsFeatures::DriverArgs
+                | DestinationArgumentsFeatures::Compression,
             dest_if_exists: IfExistsFeatures::no_append(),
             _placeholder: (),
         }
     }
 }
+
+#[test]
+fn is_dir_path_detects_trailing_separators() {
+    assert!(is_dir_path(Path::new("dir/")));
+    assert!(is_dir_path(Path::new("/path/to/dir/")));
+    assert!(!is_dir_path(Path::new("file.csv")));
+    assert!(!is_dir_path(Path::new("dir/file.csv")));
+    assert!(!is_dir_path(Path::new("")));
+    if cfg!(windows) {
+        assert!(is_dir_path(Path::new(r"C:\dir\")));
+        assert!(is_dir_path(Path::new(r"\\server\share\dir\")));
+    }
+}
+
+#[test]
+fn is_named_pipe_name_detects_pipes() {
+    assert!(is_named_pipe_name(r"\\.\pipe\data"));
+    assert!(is_named_pipe_name(r"\\.\PIPE\data.csv"));
+    assert!(!is_named_pipe_name(r"\\server\pipe\data"));
+    assert!(!is_named_pipe_name(r"C:\pipe\data.csv"));
+    assert!(!is_named_pipe_name("pipe"));
+    assert!(!is_named_pipe_name("ü"));
+    assert_eq!(
+        is_named_pipe_path(Path::new(r"\\.\pipe\data")),
+        cfg!(windows),
+    );
+}
+
+#[test]
+fn copy_csv_with_crlf_rewrites_record_terminators() {
+    let input = b"a,b\n1,\"x\ny\"\n2,z\n".to_vec();
+    let mut output = vec![];
+    copy_csv_with_crlf(&input[..], &mut output).unwrap();
+    assert_eq!(output, b"a,b\r\n1,\"x\ny\"\r\n2,z\r\n".to_vec());
+}
@@ -0,0 +1,441 @@
+//! `--from-arg` values for CSV sources, which handle quirks of CSV files
+//! exported by other tools.
+
+use regex::bytes::Regex;
+use serde::Deserialize;
+use std::io::{self, BufRead, BufReader};
+
+use crate::common::*;
+use crate::driver_args::deserialize_opt_from_str;
+
+/// Parsed version of `--from-arg` values.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct CsvSourceArguments {
+    /// Skip this many lines at the start of each file, before the header.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    skip_lines: Option<usize>,
+
+    /// Ignore lines starting with this character.
+    #[serde(default)]
+    comment: Option<String>,
+
+    /// Does each line end with an extra delimiter, producing an empty last
+    /// column?
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    trailing_delimiter: Option<bool>,
+
+    /// The string separating fields. May contain escapes like `\t` or `\x1f`.
+    #[serde(default)]
+    delimiter: Option<String>,
+
+    /// A regular expression separating fields.
+    #[serde(default)]
+    delimiter_regex: Option<String>,
+
+    /// The string separating records. May contain escapes like `\x1e`.
+    #[serde(default)]
+    record_separator: Option<String>,
+
+    /// Check that our input follows RFC 4180 exactly.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    strict: Option<bool>,
+
+    /// Split a single large file into streams of about this many MiB, which
+    /// can be parsed in parallel.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    split_mb: Option<u64>,
+}
+
+impl CsvSourceArguments {
+    /// Do we need to clean up our input at all?
+    pub(super) fn is_default(&self) -> bool {
+        self.skip_lines.unwrap_or(0) == 0
+            && self.comment.is_none()
+            && !self.trailing_delimiter()
+            && self.delimiter.is_none()
+            && self.delimiter_regex.is_none()
+            && self.record_separator.is_none()
+    }
+
+    /// If we're in strict mode, return the delimiter to validate against.
+    pub(super) fn strict_delimiter(&self) -> Result<Option<u8>> {
+        if !self.strict.unwrap_or(false) {
+            return Ok(None);
+        }
+        if self.skip_lines.is_some()
+            || self.comment.is_some()
+            || self.delimiter_regex.is_some()
+            || self.record_separator.is_some()
+        {
+            return Err(format_err!(
+                "strict cannot be combined with skip_lines, comment, delimiter_regex or record_separator"
+            ));
+        }
+        match &self.delimiter {
+            None => Ok(Some(b',')),
+            Some(delimiter) => match &unescape(delimiter)?[..] {
+                &[delimiter] => Ok(Some(delimiter)),
+                _ => Err(format_err!(
+                    "strict can only be used with a one-character delimiter"
+                )),
+            },
+        }
+    }
+
+    /// If we should split a single large file into several streams, return
+    /// the approximate size of each stream in bytes.
+    pub(super) fn split_size(&self) -> Result<Option<u64>> {
+        match self.split_mb {
+            None => Ok(None),
+            Some(0) => Err(format_err!("split_mb must be greater than 0")),
+            Some(_) if !self.is_default() || self.strict.unwrap_or(false) => {
+                Err(format_err!(
+                    "split_mb cannot be combined with other --from-arg options"
+                ))
+            }
+            Some(mb) => Ok(Some(mb.saturating_mul(1024 * 1024))),
+        }
+    }
+
+    /// Should we remove an empty last column from each row?
+    fn trailing_delimiter(&self) -> bool {
+        self.trailing_delimiter.unwrap_or(false)
+    }
+
+    /// The comment character, as a byte.
+    fn comment_byte(&self) -> Result<Option<u8>> {
+        match &self.comment {
+            None => Ok(None),
+            Some(c) if c.len() == 1 => Ok(Some(c.as_bytes()[0])),
+            Some(c) => Err(format_err!(
+                "comment must be a single ASCII character, found {:?}",
+                c,
+            )),
+        }
+    }
+
+    /// The bytes separating each record.
+    fn record_separator(&self) -> Result<Vec<u8>> {
+        match &self.record_separator {
+            None => Ok(b"\n".to_vec()),
+            Some(sep) => {
+                let sep = unescape(sep)?;
+                if sep.is_empty() {
+                    Err(format_err!("record_separator cannot be empty"))
+                } else {
+                    Ok(sep)
+                }
+            }
+        }
+    }
+
+    /// How to split records into fields. Returns `None` if we can use an
+    /// ordinary CSV parser, possibly with a custom one-byte delimiter.
+    fn field_splitter(&self) -> Result<Option<FieldSplitter>> {
+        match (&self.delimiter, &self.delimiter_regex) {
+            (Some(_), Some(_)) => Err(format_err!(
+                "cannot specify both delimiter and delimiter_regex"
+            )),
+            (Some(delimiter), None) => {
+                let delimiter = unescape(delimiter)?;
+                if delimiter.is_empty() {
+                    Err(format_err!("delimiter cannot be empty"))
+                } else if delimiter.len() == 1 && self.record_separator.is_none() {
+                    Ok(None)
+                } else {
+                    Ok(Some(FieldSplitter::Literal(delimiter)))
+                }
+            }
+            (None, Some(re)) => {
+                let re = Regex::new(re)
+                    .with_context(|_| format!("invalid delimiter_regex {:?}", re))?;
+                Ok(Some(FieldSplitter::Regex(re)))
+            }
+            (None, None) if self.record_separator.is_some() => {
+                Ok(Some(FieldSplitter::Literal(b",".to_vec())))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Skip any leading lines in `rdr`, and return a reader which splits the
+    /// rest into records. The reader treats the header as an ordinary row.
+    pub(super) fn reader<R: Read>(&self, rdr: R) -> Result<RecordReader<R>> {
+        let mut rdr = BufReader::new(rdr);
+        let separator = self.record_separator()?;
+        let mut line = vec![];
+        for _ in 0..self.skip_lines.unwrap_or(0) {
+            if !read_until_separator(&mut rdr, &separator, &mut line)? {
+                break;
+            }
+        }
+        let comment = self.comment_byte()?;
+        match self.field_splitter()? {
+            None => {
+                let delimiter = match &self.delimiter {
+                    Some(delimiter) => unescape(delimiter)?[0],
+                    None => b',',
+                };
+                Ok(RecordReader::Csv(
+                    csv::ReaderBuilder::new()
+                        .has_headers(false)
+                        .flexible(self.trailing_delimiter())
+                        .delimiter(delimiter)
+                        .comment(comment)
+                        .from_reader(rdr),
+                ))
+            }
+            Some(splitter) => Ok(RecordReader::Split {
+                rdr,
+                separator,
+                splitter,
+                comment,
+                line,
+            }),
+        }
+    }
+
+    /// Read the next row from `rdr`, removing any trailing empty column.
+    pub(super) fn read_record<R: Read>(
+        &self,
+        rdr: &mut RecordReader<R>,
+        record: &mut csv::ByteRecord,
+    ) -> Result<bool> {
+        if !rdr.read_record(record)? {
+            return Ok(false);
+        }
+        if self.trailing_delimiter() && record.iter().next_back() == Some(&b""[..]) {
+            record.truncate(record.len() - 1);
+        }
+        Ok(true)
+    }
+
+    /// Copy CSV data from `rdr` to `wtr`, cleaning it up as we go.
+    pub(super) fn clean_csv<R: Read, W: Write>(&self, rdr: R, wtr: W) -> Result<()> {
+        let mut rdr = self.reader(rdr)?;
+        let mut wtr = csv::Writer::from_writer(wtr);
+        let mut record = csv::ByteRecord::new();
+        while self.read_record(&mut rdr, &mut record)? {
+            wtr.write_byte_record(&record)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// How to split a record into fields when we can't use a CSV parser. Fields
+/// split this way are never quoted.
+pub(super) enum FieldSplitter {
+    /// Split on a fixed string.
+    Literal(Vec<u8>),
+    /// Split on a regular expression.
+    Regex(Regex),
+}
+
+impl FieldSplitter {
+    /// Split `line` into fields, and append them to `record`.
+    fn split(&self, line: &[u8], record: &mut csv::ByteRecord) {
+        match self {
+            FieldSplitter::Literal(delimiter) => {
+                let mut start = 0;
+                let mut i = 0;
+                while i + delimiter.len() <= line.len() {
+                    if line[i..].starts_with(delimiter) {
+                        record.push_field(&line[start..i]);
+                        i += delimiter.len();
+                        start = i;
+                    } else {
+                        i += 1;
+                    }
+                }
+                record.push_field(&line[start..]);
+            }
+            FieldSplitter::Regex(re) => {
+                for field in re.split(line) {
+                    record.push_field(field);
+                }
+            }
+        }
+    }
+}
+
+/// Reads records from CSV-like input.
+pub(super) enum RecordReader<R: Read> {
+    /// An ordinary CSV parser.
+    Csv(csv::Reader<BufReader<R>>),
+    /// Our own parser for unusual delimiters and record separators.
+    Split {
+        rdr: BufReader<R>,
+        separator: Vec<u8>,
+        splitter: FieldSplitter,
+        comment: Option<u8>,
+        line: Vec<u8>,
+    },
+}
+
+impl<R: Read> RecordReader<R> {
+    /// Read the next record into `record`, returning false at the end of our
+    /// input.
+    fn read_record(&mut self, record: &mut csv::ByteRecord) -> Result<bool> {
+        match self {
+            RecordReader::Csv(rdr) => Ok(rdr.read_byte_record(record)?),
+            RecordReader::Split {
+                rdr,
+                separator,
+                splitter,
+                comment,
+                line,
+            } => loop {
+                if !read_until_separator(rdr, separator, line)? {
+                    return Ok(false);
+                }
+                if &separator[..] == b"\n" && line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                if line.is_empty() || comment.is_some_and(|c| line[0] == c) {
+                    continue;
+                }
+                record.clear();
+                splitter.split(line, record);
+                return Ok(true);
+            },
+        }
+    }
+}
+
+/// Read bytes from `rdr` into `buf` up to the next `separator`, which is not
+/// included. Returns false if we're at the end of our input.
+fn read_until_separator<R: BufRead>(
+    rdr: &mut R,
+    separator: &[u8],
+    buf: &mut Vec<u8>,
+) -> io::Result<bool> {
+    buf.clear();
+    let last = *separator.last().expect("separator should not be empty");
+    loop {
+        if rdr.read_until(last, buf)? == 0 {
+            return Ok(!buf.is_empty());
+        }
+        if buf.ends_with(separator) {
+            buf.truncate(buf.len() - separator.len());
+            return Ok(true);
+        }
+    }
+}
+
+/// Replace `\t`, `\n`, `\r`, `\\` and `\xNN` escapes in `s`, so that control
+/// characters can be passed on the command line.
+fn unescape(s: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b't') => out.push(b'\t'),
+            Some(b'n') => out.push(b'\n'),
+            Some(b'r') => out.push(b'\r'),
+            Some(b'\\') => out.push(b'\\'),
+            Some(b'x') => {
+                let hex = [bytes.next(), bytes.next()];
+                let hex = match hex {
+                    [Some(h), Some(l)] => [h, l],
+                    _ => return Err(format_err!("incomplete \\x escape in {:?}", s)),
+                };
+                let hex = std::str::from_utf8(&hex)?;
+                out.push(
+                    u8::from_str_radix(hex, 16)
+                        .with_context(|_| format!("invalid \\x escape in {:?}", s))?,
+                );
+            }
+            _ => return Err(format_err!("unknown escape in {:?}", s)),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+fn parse_args(args: &[&str]) -> CsvSourceArguments {
+    DriverArguments::from_cli_args(args)
+        .unwrap()
+        .deserialize::<CsvSourceArguments>()
+        .unwrap()
+}
+
+#[cfg(test)]
+fn clean(args: &[&str], input: &[u8]) -> Vec<u8> {
+    let mut output = vec![];
+    parse_args(args).clean_csv(input, &mut output).unwrap();
+    output
+}
+
+#[test]
+fn cleans_vendor_csv_quirks() {
+    let args = parse_args(&["skip_lines=2", "comment=#", "trailing_delimiter=true"]);
+    assert!(!args.is_default());
+    let input =
+        b"Exported by Vendor\nat 2020-01-01\na,b,\n# a comment\n1,\"x,\",\n2,,\n";
+    let mut output = vec![];
+    args.clean_csv(&input[..], &mut output).unwrap();
+    assert_eq!(output, b"a,b\n1,\"x,\"\n2,\n".to_vec());
+}
+
+#[test]
+fn rejects_long_comment_strings() {
+    let args = parse_args(&["comment=//"]);
+    assert!(args.reader(&b""[..]).is_err());
+}
+
+#[test]
+fn splits_on_unusual_delimiters() {
+    assert_eq!(
+        clean(&["delimiter=\\t"], b"a\tb\n\"x\ty\"\t2\n"),
+        b"a,b\nx\ty,2\n".to_vec(),
+    );
+    assert_eq!(
+        clean(&["delimiter=||"], b"a||b\r\n1,0||\"2\"\n\n"),
+        b"a,b\n\"1,0\",\"\"\"2\"\"\"\n".to_vec(),
+    );
+    assert_eq!(
+        clean(&["delimiter_regex= *; *"], b"a ; b\n1;2\n"),
+        b"a,b\n1,2\n".to_vec(),
+    );
+    assert_eq!(
+        clean(
+            &["delimiter=\\x1f", "record_separator=\\x1e"],
+            b"a\x1fb\x1e1\x1f2\x1e",
+        ),
+        b"a,b\n1,2\n".to_vec(),
+    );
+}
+
+#[test]
+fn strict_mode_requires_plain_csv() {
+    assert_eq!(parse_args(&[]).strict_delimiter().unwrap(), None);
+    assert_eq!(
+        parse_args(&["strict=true"]).strict_delimiter().unwrap(),
+        Some(b','),
+    );
+    assert_eq!(
+        parse_args(&["strict=true", "delimiter=\\t"])
+            .strict_delimiter()
+            .unwrap(),
+        Some(b'\t'),
+    );
+    assert!(parse_args(&["strict=true", "delimiter=||"])
+        .strict_delimiter()
+        .is_err());
+    assert!(parse_args(&["strict=true", "comment=#"])
+        .strict_delimiter()
+        .is_err());
+}
+
+#[test]
+fn unescapes_separators() {
+    assert_eq!(unescape("\\t|\\x1E\\\\").unwrap(), b"\t|\x1e\\".to_vec());
+    assert!(unescape("\\x1").is_err());
+    assert!(unescape("\\q").is_err());
+}
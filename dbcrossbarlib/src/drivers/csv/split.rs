@@ -0,0 +1,230 @@
+//! Splitting a single large CSV file into several streams, so that we can
+//! parse it in parallel.
+
+use std::{
+    cmp::max,
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+use tokio::{fs, io::AsyncReadExt};
+
+use crate::common::*;
+use crate::decompress::Compression;
+use crate::tokio_glue::copy_reader_to_stream;
+
+/// How many parts of a file should we scan at the same time?
+const PARALLEL_SCANS: usize = 8;
+
+/// Where to split a CSV file.
+#[derive(Debug, Eq, PartialEq)]
+struct SplitPoints {
+    /// The header row, including the line ending.
+    header: Vec<u8>,
+    /// The `(start, end)` byte ranges of each chunk of rows.
+    ranges: Vec<(u64, u64)>,
+}
+
+/// What we learned by scanning one segment of a file, without knowing whether
+/// the segment starts inside a quoted value.
+///
+/// We only look at quotes and newlines, which is much faster than parsing the
+/// CSV data. This is enough to avoid splitting a quoted value containing a
+/// newline, because an escaped quote (`""`) toggles our quote state twice.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct SegmentScan {
+    /// Does this segment contain an odd number of quotes?
+    odd_quotes: bool,
+    /// The offset just past the first newline outside of quotes, if the
+    /// segment starts outside of quotes.
+    first_row_end: Option<u64>,
+    /// The offset just past the first newline outside of quotes, if the
+    /// segment starts inside quotes.
+    first_row_end_if_quoted: Option<u64>,
+}
+
+impl SegmentScan {
+    /// Scan `rdr`, which contains the segment of a file starting at `start`.
+    fn new<R: Read>(rdr: R, start: u64) -> Result<SegmentScan> {
+        let mut rdr = BufReader::with_capacity(BUFFER_SIZE, rdr);
+        let mut scan = SegmentScan::default();
+        let mut offset = start;
+        loop {
+            let buf = rdr.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            if offset == 0 && Compression::detect(buf).is_some() {
+                return Err(format_err!("cannot use split_mb with compressed files"));
+            }
+            let len = buf.len();
+            for (i, &b) in buf.iter().enumerate() {
+                if b == b'"' {
+                    scan.odd_quotes = !scan.odd_quotes;
+                } else if b == b'\n' {
+                    let row_end = Some(offset + i as u64 + 1);
+                    if scan.odd_quotes {
+                        scan.first_row_end_if_quoted =
+                            scan.first_row_end_if_quoted.or(row_end);
+                    } else {
+                        scan.first_row_end = scan.first_row_end.or(row_end);
+                    }
+                }
+            }
+            offset += len as u64;
+            rdr.consume(len);
+        }
+        Ok(scan)
+    }
+}
+
+/// Combine the scans of each segment of a file of `len` bytes, and return the
+/// end of the header row and the `(start, end)` byte ranges of each chunk of
+/// rows. Each chunk ends at the first row boundary after the start of a
+/// segment.
+fn ranges_from_scans(scans: &[SegmentScan], len: u64) -> (u64, Vec<(u64, u64)>) {
+    // Find the first row boundary at or after the start of each segment, using
+    // the quote state at the start of each segment.
+    let mut in_quotes = false;
+    let mut row_ends = Vec::with_capacity(scans.len());
+    for scan in scans {
+        row_ends.push(if in_quotes {
+            scan.first_row_end_if_quoted
+        } else {
+            scan.first_row_end
+        });
+        in_quotes ^= scan.odd_quotes;
+    }
+    let mut next_row_end = None;
+    for row_end in row_ends.iter_mut().rev() {
+        next_row_end = row_end.or(next_row_end);
+        *row_end = next_row_end;
+    }
+
+    // If we never saw the end of our header, there are no rows.
+    let header_end = row_ends.first().cloned().flatten().unwrap_or(len);
+    let mut ranges = vec![];
+    let mut start = header_end;
+    for &row_end in row_ends.iter().skip(1).flatten() {
+        if row_end > start {
+            ranges.push((start, row_end));
+            start = row_end;
+        }
+    }
+    if start < len || ranges.is_empty() {
+        ranges.push((start, len));
+    }
+    (header_end, ranges)
+}
+
+/// Find record boundaries that we can use to split the file at `path` into
+/// chunks of roughly `split_size` bytes.
+///
+/// We scan segments of the file in parallel, each using its own file handle,
+/// and then combine the results. We use ordinary reads instead of `mmap`,
+/// because `dbcrossbar` forbids `unsafe` code, and mapping a file which might
+/// be truncated by another process is unsafe.
+async fn find_split_points(path: &Path, split_size: u64) -> Result<SplitPoints> {
+    let len = fs::metadata(path)
+        .await
+        .with_context(|_| format!("cannot open {}", path.display()))?
+        .len();
+    let segment_count = max(1, len / split_size + u64::from(len % split_size != 0));
+    let scans = stream::iter(0..segment_count)
+        .map(|idx| {
+            let path = path.to_owned();
+            let start = idx * split_size;
+            spawn_blocking(move || {
+                let mut file = File::open(&path)
+                    .with_context(|_| format!("cannot open {}", path.display()))?;
+                file.seek(SeekFrom::Start(start))?;
+                SegmentScan::new(file.take(split_size), start)
+            })
+        })
+        .buffered(PARALLEL_SCANS)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let (header_end, ranges) = ranges_from_scans(&scans, len);
+
+    let mut header = vec![];
+    fs::File::open(path)
+        .await
+        .with_context(|_| format!("cannot open {}", path.display()))?
+        .take(header_end)
+        .read_to_end(&mut header)
+        .await?;
+    Ok(SplitPoints { header, ranges })
+}
+
+/// Split the CSV file at `path` into streams of roughly `split_size` bytes,
+/// named `{name}_0001`, `{name}_0002`, etc. Each stream starts with a copy of
+/// the header row.
+pub(super) async fn split_csv_file(
+    ctx: Context,
+    name: String,
+    path: PathBuf,
+    split_size: u64,
+) -> Result<BoxStream<CsvStream>> {
+    let SplitPoints { header, ranges } = find_split_points(&path, split_size)
+        .await
+        .with_context(|_| format!("cannot split {}", path.display()))?;
+    debug!(
+        ctx.log(),
+        "splitting {} into {} streams",
+        path.display(),
+        ranges.len(),
+    );
+
+    let streams = stream::iter(ranges.into_iter().enumerate())
+        .map(Ok)
+        .and_then(move |(idx, (start, end))| {
+            let ctx = ctx.clone();
+            let name = format!("{}_{:04}", name, idx + 1);
+            let path = path.clone();
+            let header = BytesMut::from(&header[..]);
+            async move {
+                let ctx = ctx.child(o!("stream" => name.clone()));
+                let mut file = fs::File::open(&path)
+                    .await
+                    .with_context(|_| format!("cannot open {}", path.display()))?;
+                file.seek(SeekFrom::Start(start)).await?;
+                let rows = copy_reader_to_stream(ctx, file.take(end - start))?;
+                let data = stream::once(async { Ok(header) })
+                    .chain(rows)
+                    .map_err(move |e| {
+                        format_err!("cannot read {}: {}", path.display(), e)
+                    })
+                    .boxed();
+                Ok(CsvStream { name, data })
+            }
+            .boxed()
+        });
+    Ok(streams.boxed())
+}
+
+#[test]
+fn finds_split_points_outside_quotes() {
+    let data = b"a,b\n1,\"x\ny\"\n2,\"\"\"\"\n3,z\n";
+    let ranges = |split_size: usize| {
+        let scans = data
+            .chunks(split_size)
+            .enumerate()
+            .map(|(idx, chunk)| {
+                SegmentScan::new(chunk, (idx * split_size) as u64).unwrap()
+            })
+            .collect::<Vec<_>>();
+        ranges_from_scans(&scans, data.len() as u64)
+    };
+    assert_eq!(ranges(1), (4, vec![(4, 12), (12, 19), (19, 23)]));
+
+    // The segment starting at 10 is inside a quoted value.
+    assert_eq!(ranges(10), (4, vec![(4, 12), (12, 23)]));
+
+    // We always produce at least one stream, even without any rows.
+    let scans = vec![SegmentScan::new(&b"a,b\n"[..], 0).unwrap()];
+    assert_eq!(ranges_from_scans(&scans, 4), (4, vec![(4, 4)]));
+    let scans = vec![SegmentScan::new(&b"a,b"[..], 0).unwrap()];
+    assert_eq!(ranges_from_scans(&scans, 3), (3, vec![(3, 3)]));
+
+    assert!(SegmentScan::new(&[0x1f, 0x8b, 0, 0][..], 0).is_err());
+}
@@ -0,0 +1,242 @@
+//! Strict validation of CSV quoting, as described by [RFC 4180][rfc].
+//!
+//! The `csv` crate accepts many malformed files, such as files with stray
+//! quotes, and it guesses what they mean. This is normally what we want, but
+//! those guesses may produce data that a downstream loader rejects with an
+//! unhelpful error. In strict mode, we check each byte as it goes by, and
+//! report exactly where the input went wrong.
+//!
+//! [rfc]: https://tools.ietf.org/html/rfc4180
+
+use crate::common::*;
+
+/// Where we are in the CSV grammar.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    /// At the start of a field.
+    FieldStart,
+    /// Inside a field without quotes.
+    Unquoted,
+    /// Inside a quoted field.
+    Quoted,
+    /// Just after a `"` inside a quoted field, which either ends the field or
+    /// starts a `""` escape.
+    QuoteInQuoted,
+    /// Just after a `\r` outside of quotes, which must be followed by `\n`.
+    AfterCr,
+}
+
+/// Validates CSV data incrementally.
+struct Validator {
+    /// Our field delimiter.
+    delimiter: u8,
+    /// Where we are in the grammar.
+    state: State,
+    /// The 0-based offset of the next byte.
+    offset: u64,
+    /// The 1-based row we're reading, counting the header as row 1.
+    row: u64,
+    /// The 1-based line we're reading.
+    line: u64,
+    /// The offset of the last opening quote, for reporting unterminated
+    /// fields.
+    quote_offset: u64,
+    /// The number of fields we've seen in the current row.
+    fields: usize,
+    /// The number of fields in the first row.
+    expected_fields: Option<usize>,
+}
+
+impl Validator {
+    /// Create a new validator.
+    fn new(delimiter: u8) -> Self {
+        Validator {
+            delimiter,
+            state: State::FieldStart,
+            offset: 0,
+            row: 1,
+            line: 1,
+            quote_offset: 0,
+            fields: 0,
+            expected_fields: None,
+        }
+    }
+
+    /// Check the next chunk of our input.
+    fn feed(&mut self, buf: &[u8]) -> Result<()> {
+        for &b in buf {
+            self.byte(b)?;
+            self.offset += 1;
+        }
+        Ok(())
+    }
+
+    /// Check a single byte.
+    fn byte(&mut self, b: u8) -> Result<()> {
+        match (self.state, b) {
+            (State::AfterCr, b'\n') => self.end_record(),
+            (State::AfterCr, _) => {
+                Err(self.error("carriage return is not followed by a line feed"))
+            }
+            (State::Quoted, b'"') => {
+                self.state = State::QuoteInQuoted;
+                Ok(())
+            }
+            (State::Quoted, b'\n') => {
+                self.line += 1;
+                Ok(())
+            }
+            (State::Quoted, _) => Ok(()),
+            (State::QuoteInQuoted, b'"') => {
+                self.state = State::Quoted;
+                Ok(())
+            }
+            (State::FieldStart, b'"') => {
+                self.quote_offset = self.offset;
+                self.state = State::Quoted;
+                Ok(())
+            }
+            (State::Unquoted, b'"') => Err(self.error(
+                "unexpected quote in a field that does not start with a quote",
+            )),
+            (_, b) if b == self.delimiter => {
+                self.fields += 1;
+                self.state = State::FieldStart;
+                Ok(())
+            }
+            (State::FieldStart, b'\n') if self.fields == 0 => {
+                // Skip blank lines, like the `csv` crate does.
+                self.line += 1;
+                Ok(())
+            }
+            (_, b'\n') => self.end_record(),
+            (_, b'\r') => {
+                self.state = State::AfterCr;
+                Ok(())
+            }
+            (State::QuoteInQuoted, _) => {
+                Err(self.error("unexpected character after closing quote"))
+            }
+            (State::FieldStart, _) | (State::Unquoted, _) => {
+                self.state = State::Unquoted;
+                Ok(())
+            }
+        }
+    }
+
+    /// Finish a record, and make sure it has the right number of fields.
+    fn end_record(&mut self) -> Result<()> {
+        let fields = self.fields + 1;
+        match self.expected_fields {
+            None => self.expected_fields = Some(fields),
+            Some(expected) if expected != fields => {
+                return Err(self.error(&format!(
+                    "expected {} fields, found {}",
+                    expected, fields,
+                )));
+            }
+            Some(_) => {}
+        }
+        self.fields = 0;
+        self.row += 1;
+        self.line += 1;
+        self.state = State::FieldStart;
+        Ok(())
+    }
+
+    /// Check the end of our input.
+    fn finish(&mut self) -> Result<()> {
+        match self.state {
+            State::Quoted => Err(format_err!(
+                "malformed CSV at byte {} (row {}): quoted field is never closed",
+                self.quote_offset,
+                self.row,
+            )),
+            State::FieldStart if self.fields == 0 => Ok(()),
+            _ => self.end_record(),
+        }
+    }
+
+    /// Build an error at our current position.
+    fn error(&self, msg: &str) -> Error {
+        format_err!(
+            "malformed CSV at byte {} (row {}, line {}): {}",
+            self.offset,
+            self.row,
+            self.line,
+            msg,
+        )
+    }
+}
+
+/// Copy `rdr` to `wtr` unchanged, returning an error if it isn't valid CSV
+/// data using `delimiter`. `source` describes our input in error messages.
+pub(super) fn validate_csv<R: Read, W: Write>(
+    mut rdr: R,
+    mut wtr: W,
+    delimiter: u8,
+    source: &str,
+) -> Result<()> {
+    let mut validator = Validator::new(delimiter);
+    let mut buf = vec![0; BUFFER_SIZE];
+    loop {
+        let count = rdr.read(&mut buf)?;
+        if count == 0 {
+            break;
+        }
+        validator
+            .feed(&buf[..count])
+            .with_context(|_| format!("error reading {}", source))?;
+        wtr.write_all(&buf[..count])?;
+    }
+    validator
+        .finish()
+        .with_context(|_| format!("error reading {}", source))?;
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+fn validate(input: &[u8]) -> Result<()> {
+    let mut validator = Validator::new(b',');
+    validator.feed(input)?;
+    validator.finish()
+}
+
+#[test]
+fn accepts_valid_csv() {
+    validate(b"a,b\n1,2\n").unwrap();
+    validate(b"a,b\r\n\"x,\"\"y\"\"\nz\",2\r\n\n3,").unwrap();
+    validate(b"").unwrap();
+    let mut output = vec![];
+    validate_csv(&b"a;b\n\"1;2\";3\n"[..], &mut output, b';', "test").unwrap();
+    assert_eq!(output, b"a;b\n\"1;2\";3\n".to_vec());
+}
+
+#[test]
+fn reports_position_of_malformed_csv() {
+    let check = |input: &[u8], expected: &str| {
+        let err = validate(input).unwrap_err().to_string();
+        assert_eq!(err, expected);
+    };
+    check(
+        b"a,b\n1,x\"y\n",
+        "malformed CSV at byte 7 (row 2, line 2): unexpected quote in a field that does not start with a quote",
+    );
+    check(
+        b"a,b\n\"x\ny\"z,2\n",
+        "malformed CSV at byte 9 (row 2, line 3): unexpected character after closing quote",
+    );
+    check(
+        b"a,b\n1,2,3\n",
+        "malformed CSV at byte 9 (row 2, line 2): expected 2 fields, found 3",
+    );
+    check(
+        b"a,b\n1,2\r3,4\n",
+        "malformed CSV at byte 8 (row 2, line 2): carriage return is not followed by a line feed",
+    );
+    check(
+        b"a,b\n1,\"2\n",
+        "malformed CSV at byte 6 (row 2): quoted field is never closed",
+    );
+}
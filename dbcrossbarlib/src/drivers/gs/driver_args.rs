@@ -0,0 +1,134 @@
+//! Driver arguments for `gs://` sources and destinations.
+
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Duration};
+
+use crate::clouds::{
+    endpoints::{ApiEndpoint, ApiEndpoints},
+    object_metadata::ObjectMetadata,
+    signed_urls::SignedUrlWriter,
+};
+use crate::common::*;
+use crate::driver_args::{deserialize_opt_duration, deserialize_opt_from_str};
+
+/// How many files we download at the same time, unless told otherwise.
+const DEFAULT_PARALLEL_DOWNLOADS: usize = 4;
+
+/// Parsed version of `--from-arg` for `gs://` sources.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GsSourceArguments {
+    /// How many files should we download at the same time?
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    parallel_downloads: Option<usize>,
+
+    /// Send Google Cloud Storage API requests to this endpoint instead of the
+    /// public one.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    storage_endpoint: Option<ApiEndpoint>,
+}
+
+impl GsSourceArguments {
+    /// How many files should we download at the same time?
+    pub(crate) fn parallel_downloads(&self) -> Result<usize> {
+        match self.parallel_downloads {
+            Some(0) => Err(format_err!("parallel_downloads must be at least 1")),
+            Some(n) => Ok(n),
+            None => Ok(DEFAULT_PARALLEL_DOWNLOADS),
+        }
+    }
+
+    /// Any API endpoint overrides specified by these arguments.
+    pub(crate) fn endpoints(&self) -> ApiEndpoints {
+        ApiEndpoints {
+            storage_endpoint: self.storage_endpoint.clone(),
+            ..ApiEndpoints::default()
+        }
+    }
+}
+
+/// Parsed version of `--to-arg` for `gs://` destinations.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GsDestinationArguments {
+    /// Merge our output into at most this many files, using server-side
+    /// compose operations.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) max_files: Option<usize>,
+
+    /// Print a signed URL for each file we write, valid for this long.
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    signed_url_ttl: Option<Duration>,
+
+    /// Write our signed URLs to this local file instead of standard output.
+    #[serde(default)]
+    signed_url_manifest: Option<PathBuf>,
+
+    /// The `Content-Type` of each file we write.
+    #[serde(default)]
+    content_type: Option<String>,
+
+    /// The `Content-Encoding` of each file we write.
+    #[serde(default)]
+    content_encoding: Option<String>,
+
+    /// The `Cache-Control` header of each file we write.
+    #[serde(default)]
+    cache_control: Option<String>,
+
+    /// Custom metadata for each file we write.
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+
+    /// The storage class for each file we write.
+    #[serde(default)]
+    storage_class: Option<String>,
+
+    /// Send Google Cloud Storage API requests to this endpoint instead of the
+    /// public one.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    storage_endpoint: Option<ApiEndpoint>,
+}
+
+impl GsDestinationArguments {
+    /// Any API endpoint overrides specified by these arguments.
+    pub(crate) fn endpoints(&self) -> ApiEndpoints {
+        ApiEndpoints {
+            storage_endpoint: self.storage_endpoint.clone(),
+            ..ApiEndpoints::default()
+        }
+    }
+
+    /// Where should we send signed URLs, if anywhere?
+    pub(crate) fn signed_url_writer(&self) -> Result<Option<Arc<SignedUrlWriter>>> {
+        SignedUrlWriter::from_args(
+            self.signed_url_ttl,
+            self.signed_url_manifest.as_deref(),
+        )
+    }
+
+    /// The metadata to set on each file we write.
+    pub(crate) fn object_metadata(&self) -> ObjectMetadata {
+        ObjectMetadata {
+            content_type: self.content_type.clone(),
+            content_encoding: self.content_encoding.clone(),
+            cache_control: self.cache_control.clone(),
+            metadata: self.metadata.clone(),
+            storage_class: self.storage_class.clone(),
+        }
+    }
+}
+
+#[test]
+fn parses_parallel_downloads() {
+    let parse = |args: &[&str]| {
+        DriverArguments::from_cli_args(args)
+            .unwrap()
+            .deserialize::<GsSourceArguments>()
+            .unwrap()
+            .parallel_downloads()
+    };
+    assert_eq!(parse(&[]).unwrap(), DEFAULT_PARALLEL_DOWNLOADS);
+    assert_eq!(parse(&["parallel_downloads=16"]).unwrap(), 16);
+    assert!(parse(&["parallel_downloads=0"]).is_err());
+}
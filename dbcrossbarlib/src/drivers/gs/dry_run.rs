@@ -0,0 +1,86 @@
+//! Implementation of `GsLocator::dry_run_write`.
+
+use super::{
+    driver_args::GsDestinationArguments, write_remote_data::export_sql_if_needed,
+    GsLocator,
+};
+use crate::common::*;
+use crate::drivers::{
+    bigquery::BigQueryLocator, bigquery_shared::GCloudDriverArguments,
+};
+
+/// Describe how `write_remote_data` would copy `source` to `dest`. When we
+/// upload local data, there's nothing to describe.
+pub(crate) async fn dry_run_write_helper(
+    ctx: Context,
+    dest: GsLocator,
+    source: Option<BoxLocator>,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<String>> {
+    let source = match source {
+        Some(source) => source,
+        None => return Ok(vec![]),
+    };
+    if let Some(source) = source.as_any().downcast_ref::<GsLocator>() {
+        return Ok(vec![format!(
+            "-- copy files from {} to {} server-side",
+            source, dest,
+        )]);
+    }
+    let source = source
+        .as_any()
+        .downcast_ref::<BigQueryLocator>()
+        .ok_or_else(|| format_err!("not a bigquery locator: {}", source))?;
+    let source_table_name = source.as_table_name();
+
+    let shared_args = shared_args.verify(GsLocator::features())?;
+    let source_args = source_args.verify(BigQueryLocator::features())?;
+    let dest_args = dest_args.verify(GsLocator::features())?;
+    let gs_args = dest_args
+        .driver_args()
+        .deserialize::<GsDestinationArguments>()
+        .context("error parsing --to-args")?;
+    let gcloud_args = source_args
+        .driver_args()
+        .deserialize::<GCloudDriverArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx
+        .with_endpoints(&gcloud_args.endpoints())
+        .with_endpoints(&gs_args.endpoints());
+
+    // Decide whether we'd need to query into a temporary table first.
+    let mut sql = vec![];
+    let temp_table_name = match export_sql_if_needed(
+        &ctx,
+        source_table_name,
+        shared_args.schema(),
+        &source_args,
+    )
+    .await?
+    {
+        None => None,
+        Some(export_sql) => {
+            let temp_table_name = source_table_name
+                .temporary_table_name(shared_args.temporary_storage())?;
+            sql.push(format!(
+                "-- query into temporary table {}\n{}",
+                temp_table_name, export_sql,
+            ));
+            Some(temp_table_name)
+        }
+    };
+    let extract_table_name = temp_table_name.as_ref().unwrap_or(source_table_name);
+    sql.push(format!(
+        "-- extract job: {} to {}",
+        extract_table_name, dest
+    ));
+    if let Some(max_files) = gs_args.max_files {
+        sql.push(format!("-- compose into at most {} files", max_files));
+    }
+    if let Some(temp_table_name) = &temp_table_name {
+        sql.push(format!("-- drop temporary table {}", temp_table_name));
+    }
+    Ok(sql)
+}
@@ -0,0 +1,44 @@
+//! Estimating the size of data in Google Cloud Storage.
+
+use super::{driver_args::GsSourceArguments, GsLocator};
+use crate::clouds::gcloud::storage;
+use crate::common::*;
+
+/// Implementation of `estimate`, but as a real `async` function.
+///
+/// We add up the sizes of all the objects, and estimate the number of rows by
+/// sampling the beginning of the first object.
+pub(crate) async fn estimate_helper(
+    ctx: Context,
+    url: Url,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<Estimate>> {
+    let source_args = source_args.verify(GsLocator::features())?;
+    let gs_args = source_args
+        .driver_args()
+        .deserialize::<GsSourceArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&gs_args.endpoints());
+    debug!(ctx.log(), "estimating size of {}", url);
+
+    let items = storage::ls(&ctx, &url)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let total_bytes = items.iter().map(|item| item.size).sum::<u64>();
+    let files = u64::try_from(items.len())?;
+    match items.first() {
+        Some(first) => {
+            let sample = storage::download_file(&ctx, first).await?;
+            Ok(Some(
+                Estimate::from_csv_sample(&ctx, sample, total_bytes, files).await?,
+            ))
+        }
+        None => Ok(Some(Estimate {
+            rows: Some(0),
+            bytes: Some(0),
+            files: Some(0),
+            method: "file sizes".to_owned(),
+        })),
+    }
+}
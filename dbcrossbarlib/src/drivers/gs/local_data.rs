@@ -1,9 +1,14 @@
 //! Reading data from Google Cloud Storage.
 
-use super::GsLocator;
-use crate::clouds::gcloud::storage;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use super::{driver_args::GsSourceArguments, GsLocator};
+use crate::clouds::gcloud::storage::{self, StorageObject};
 use crate::common::*;
 use crate::csv_stream::csv_stream_name;
+use crate::decompress::decompress_stream;
+use crate::tokio_glue::bytes_channel;
 
 /// Implementation of `local_data`, but as a real `async` function.
 pub(crate) async fn local_data_helper(
@@ -13,30 +18,90 @@ pub(crate) async fn local_data_helper(
     source_args: SourceArguments<Unverified>,
 ) -> Result<Option<BoxStream<CsvStream>>> {
     let _shared_args = shared_args.verify(GsLocator::features())?;
-    let _source_args = source_args.verify(GsLocator::features())?;
-    debug!(ctx.log(), "getting CSV files from {}", url);
+    let source_args = source_args.verify(GsLocator::features())?;
+    let gs_args = source_args
+        .driver_args()
+        .deserialize::<GsSourceArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&gs_args.endpoints());
+    let parallel_downloads = gs_args.parallel_downloads()?;
+    debug!(
+        ctx.log(),
+        "getting CSV files from {} ({} at a time)", url, parallel_downloads,
+    );
 
-    let file_urls = storage::ls(&ctx, &url).await?;
+    // List our files and sort them by stream name, so that we start
+    // downloading them in the same order that `sort_csv_streams_by_name` would
+    // read them.
+    let mut items = storage::ls(&ctx, &url)
+        .await?
+        .map(|item| -> Result<(String, StorageObject)> {
+            let item = item?;
+            let name =
+                csv_stream_name(url.as_str(), &item.to_url_string())?.to_owned();
+            Ok((name, item))
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
+    items.sort_by(|a, b| a.0.cmp(&b.0));
 
-    let csv_streams = file_urls.and_then(move |item| {
+    // Each file is downloaded in the background once it gets a permit, so that
+    // we download several files at once even if our consumer reads them one at
+    // a time. Permits are granted in sorted order.
+    let downloads = Arc::new(Semaphore::new(parallel_downloads));
+    let csv_streams = stream::iter(items).map(Ok).and_then(move |(name, item)| {
         let ctx = ctx.clone();
-        let url = url.clone();
+        let downloads = downloads.clone();
         async move {
             // Stream the file from the cloud.
             let file_url = item.to_url_string();
-            let name = csv_stream_name(url.as_str(), &file_url)?;
-            let ctx =
-                ctx.child(o!("stream" => name.to_owned(), "url" => file_url.clone()));
-            let data = storage::download_file(&ctx, &item).await?;
+            let ctx = ctx.child(o!("stream" => name.clone(), "url" => file_url));
+            let data = prefetch_file(&ctx, item, downloads);
+            let data = decompress_stream(&ctx, data).await?;
 
             // Assemble everything into a CSV stream.
-            Ok(CsvStream {
-                name: name.to_owned(),
-                data,
-            })
+            Ok(CsvStream { name, data })
         }
         .boxed()
     });
 
     Ok(Some(csv_streams.boxed()))
 }
+
+/// Download `item` in a background worker once we get a permit from
+/// `downloads`, keeping a little ahead of whoever reads the returned stream.
+fn prefetch_file(
+    ctx: &Context,
+    item: StorageObject,
+    downloads: Arc<Semaphore>,
+) -> BoxStream<BytesMut> {
+    let (mut sender, receiver) = bytes_channel(1);
+    let worker_ctx = ctx.clone();
+    let worker: BoxFuture<()> = async move {
+        let _permit = downloads.acquire().await;
+        trace!(worker_ctx.log(), "starting download");
+        let mut data = match storage::download_file(&worker_ctx, &item).await {
+            Ok(data) => data,
+            Err(err) => {
+                sender.send(Err(err)).await.map_send_err()?;
+                return Ok(());
+            }
+        };
+        while let Some(chunk) = data.next().await {
+            let failed = chunk.is_err();
+            if sender.send(chunk).await.is_err() {
+                // Our reader has gone away, so stop downloading.
+                debug!(worker_ctx.log(), "download stream was closed");
+                return Ok(());
+            }
+            if failed {
+                return Ok(());
+            }
+        }
+        trace!(worker_ctx.log(), "finished download");
+        Ok(())
+    }
+    .boxed();
+    ctx.spawn_worker(worker);
+    receiver.boxed()
+}
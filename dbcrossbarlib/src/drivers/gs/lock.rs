@@ -0,0 +1,84 @@
+//! Holding `cp --lock` locks using lock objects in Google Cloud Storage.
+
+use chrono::Utc;
+use serde_json::json;
+use std::process;
+
+use super::GsLocator;
+use crate::clouds::gcloud::{gcloud_error_kind, storage, GCloudErrorKind};
+use crate::common::*;
+use crate::lock::{check_lock_name, lock_held_error};
+
+/// A lock object, which we created using `ifGenerationMatch=0` so that only one
+/// process can create it at a time.
+///
+/// Unlike PostgreSQL advisory locks, this will be left behind if `dbcrossbar`
+/// crashes, and it will need to be deleted by hand.
+#[derive(Debug)]
+struct GsLock {
+    /// The `gs://` URL of our lock object.
+    url: Url,
+}
+
+impl DestinationLock for GsLock {
+    fn release(self: Box<Self>, ctx: Context) -> BoxFuture<()> {
+        async move {
+            storage::delete_file(&ctx, &self.url)
+                .await
+                .with_context(|_| format!("could not release lock {}", self.url))?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// The URL of the lock object for `name`. We store this at the top of the
+/// bucket, so that `--if-exists=overwrite` doesn't delete it.
+fn lock_url(dest: &GsLocator, name: &str) -> Url {
+    let mut url = dest.as_url().to_owned();
+    url.set_path(&format!("/.dbcrossbar-locks/{}", name));
+    url
+}
+
+/// Acquire `name` by creating a lock object in the bucket of `dest`.
+pub(crate) async fn lock_helper(
+    ctx: Context,
+    dest: GsLocator,
+    name: String,
+) -> Result<Option<BoxDestinationLock>> {
+    check_lock_name(&name)?;
+    let url = lock_url(&dest, &name);
+    debug!(ctx.log(), "acquiring lock {}", url);
+    let contents = json!({
+        "lock": name,
+        "destination": dest.to_string(),
+        "pid": process::id(),
+        "locked_at": Utc::now().to_rfc3339(),
+    });
+    let contents = serde_json::to_vec(&contents)?;
+    let data = box_stream_once(Ok(BytesMut::from(&contents[..])));
+    match storage::upload_file(&ctx, data, &url).await {
+        Ok(_) => Ok(Some(Box::new(GsLock { url }))),
+        Err(err) if is_precondition_failed(&err) => Err(format_err!(
+            "{} (if no other process is running, delete {})",
+            lock_held_error(&name, &dest),
+            url,
+        )),
+        Err(err) => Err(err.context(format!("could not create lock {}", url)).into()),
+    }
+}
+
+/// Is `err` a Google Cloud "precondition failed" error, which means that our
+/// lock object already exists?
+fn is_precondition_failed(err: &Error) -> bool {
+    gcloud_error_kind(err) == Some(GCloudErrorKind::PreconditionFailed)
+}
+
+#[test]
+fn lock_url_is_at_top_of_bucket() {
+    let dest = "gs://example/exports/orders/".parse::<GsLocator>().unwrap();
+    assert_eq!(
+        lock_url(&dest, "nightly").as_str(),
+        "gs://example/.dbcrossbar-locks/nightly",
+    );
+}
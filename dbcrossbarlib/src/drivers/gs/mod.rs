@@ -3,14 +3,22 @@
 use std::{fmt, str::FromStr};
 
 use crate::common::*;
+use crate::copy_path::NoCopyPathError;
 use crate::drivers::bigquery::BigQueryLocator;
 
+mod driver_args;
+mod dry_run;
+mod estimate;
 mod local_data;
+mod lock;
 mod prepare_as_destination;
 mod write_local_data;
 mod write_remote_data;
 
+use dry_run::dry_run_write_helper;
+use estimate::estimate_helper;
 use local_data::local_data_helper;
+use lock::lock_helper;
 pub(crate) use prepare_as_destination::prepare_as_destination_helper;
 use write_local_data::write_local_data_helper;
 use write_remote_data::write_remote_data_helper;
@@ -25,6 +33,11 @@ impl GsLocator {
     pub(crate) fn as_url(&self) -> &Url {
         &self.url
     }
+
+    /// Does this locator point to a directory, ending in `/`?
+    pub(crate) fn is_directory(&self) -> bool {
+        self.url.path().ends_with('/')
+    }
 }
 
 impl fmt::Display for GsLocator {
@@ -59,6 +72,14 @@ impl Locator for GsLocator {
         self
     }
 
+    fn estimate(
+        &self,
+        ctx: Context,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<Estimate>> {
+        estimate_helper(ctx, self.url.clone(), source_args).boxed()
+    }
+
     fn local_data(
         &self,
         ctx: Context,
@@ -80,9 +101,14 @@ impl Locator for GsLocator {
     }
 
     fn supports_write_remote_data(&self, source: &dyn Locator) -> bool {
-        // We can only do `write_remote_data` if `source` is a `BigQueryLocator`.
-        // Otherwise, we need to do `write_local_data` like normal.
-        source.as_any().is::<BigQueryLocator>()
+        // We can only do `write_remote_data` if `source` is a `BigQueryLocator`,
+        // or if we're copying one `gs://` directory to another. Otherwise, we
+        // need to do `write_local_data` like normal.
+        if let Some(source) = source.as_any().downcast_ref::<GsLocator>() {
+            source.is_directory() && self.is_directory()
+        } else {
+            source.as_any().is::<BigQueryLocator>()
+        }
     }
 
     fn write_remote_data(
@@ -103,6 +129,33 @@ impl Locator for GsLocator {
         )
         .boxed()
     }
+
+    fn dry_run_write(
+        &self,
+        ctx: Context,
+        source: Option<BoxLocator>,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+        dest_args: DestinationArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        dry_run_write_helper(
+            ctx,
+            self.to_owned(),
+            source,
+            shared_args,
+            source_args,
+            dest_args,
+        )
+        .boxed()
+    }
+
+    fn lock(
+        &self,
+        ctx: Context,
+        name: String,
+    ) -> BoxFuture<Option<BoxDestinationLock>> {
+        lock_helper(ctx, self.to_owned(), name).boxed()
+    }
 }
 
 impl LocatorStatic for GsLocator {
@@ -112,14 +165,22 @@ impl LocatorStatic for GsLocator {
 
     fn features() -> Features {
         Features {
-            locator: LocatorFeatures::LocalData | LocatorFeatures::WriteLocalData,
+            locator: LocatorFeatures::LocalData
+                | LocatorFeatures::WriteLocalData
+                | LocatorFeatures::Estimate
+                | LocatorFeatures::Lock,
             write_schema_if_exists: EnumSet::empty(),
-            source_args: EnumSet::empty(),
-            dest_args: EnumSet::empty(),
-            dest_if_exists: IfExistsFeatures::Overwrite.into(),
+            source_args: SourceArgumentsFeatures::DriverArgs.into(),
+            dest_args: DestinationArgumentsFeatures::DriverArgs
+                | DestinationArgumentsFeatures::Compression,
+            dest_if_exists: IfExistsFeatures::Overwrite | IfExistsFeatures::Append,
             _placeholder: (),
         }
     }
+
+    fn remote_sources() -> &'static [&'static str] {
+        &["bigquery:", "gs:"]
+    }
 }
 
 /// Given a `TemporaryStorage`, extract a unique `gs://` temporary directory,
@@ -127,9 +188,10 @@ impl LocatorStatic for GsLocator {
 pub(crate) fn find_gs_temp_dir(
     temporary_storage: &TemporaryStorage,
 ) -> Result<GsLocator> {
+    temporary_storage.check_allowed(GsLocator::scheme())?;
     let mut temp = temporary_storage
         .find_scheme(GsLocator::scheme())
-        .ok_or_else(|| format_err!("need `--temporary=gs://...` argument"))?
+        .ok_or_else(|| NoCopyPathError::missing_temporary(GsLocator::scheme()))?
         .to_owned();
     if !temp.ends_with('/') {
         temp.push_str("/");
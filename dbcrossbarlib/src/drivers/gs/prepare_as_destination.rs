@@ -9,14 +9,15 @@ pub(crate) async fn prepare_as_destination_helper(
     gs_url: Url,
     if_exists: IfExists,
 ) -> Result<()> {
-    // Delete the existing output, if it exists.
-    if if_exists == IfExists::Overwrite {
-        storage::rmdir(&ctx, &gs_url).await?;
-        Ok(())
-    } else {
-        Err(format_err!(
-            "must specify `overwrite` for {} destination",
+    match if_exists {
+        // Delete the existing output, if it exists.
+        IfExists::Overwrite => storage::rmdir(&ctx, &gs_url).await,
+        // Leave any existing files alone. Files with the same names as our
+        // output will still be replaced.
+        IfExists::Append => Ok(()),
+        _ => Err(format_err!(
+            "must specify `overwrite` or `append` for {} destination",
             gs_url,
-        ))
+        )),
     }
 }
@@ -1,6 +1,8 @@
 //! Writing data to Google Cloud Storage.
 
-use super::{prepare_as_destination_helper, GsLocator};
+use super::{
+    driver_args::GsDestinationArguments, prepare_as_destination_helper, GsLocator,
+};
 use crate::clouds::gcloud::storage;
 use crate::common::*;
 
@@ -14,6 +16,20 @@ pub(crate) async fn write_local_data_helper(
 ) -> Result<BoxStream<BoxFuture<BoxLocator>>> {
     let _shared_args = shared_args.verify(GsLocator::features())?;
     let dest_args = dest_args.verify(GsLocator::features())?;
+    let gs_args = dest_args
+        .driver_args()
+        .deserialize::<GsDestinationArguments>()
+        .context("error parsing --to-args")?;
+    let ctx = ctx.with_endpoints(&gs_args.endpoints());
+    if gs_args.max_files.is_some() {
+        return Err(format_err!(
+            "--to-arg=max_files is only supported when exporting from BigQuery"
+        ));
+    }
+    let signed_url_writer = gs_args.signed_url_writer()?;
+    let metadata = gs_args.object_metadata();
+
+    let compression = dest_args.compression();
 
     // Delete the existing output, if it exists.
     let if_exists = dest_args.if_exists().to_owned();
@@ -23,12 +39,23 @@ pub(crate) async fn write_local_data_helper(
     let written = data.map_ok(move |stream| {
         let url = url.clone();
         let ctx = ctx.clone();
+        let signed_url_writer = signed_url_writer.clone();
+        let metadata = metadata.clone();
         async move {
-            let url = url.join(&format!("{}.csv", stream.name))?;
+            let url =
+                url.join(&format!("{}.csv{}", stream.name, compression.extension()))?;
             let ctx = ctx
                 .child(o!("stream" => stream.name.clone(), "url" => url.to_string()));
+            let stream = compression.compress_csv_stream(&ctx, stream)?;
 
             storage::upload_file(&ctx, stream.data, &url).await?;
+            if !metadata.is_empty() {
+                storage::update_metadata(&ctx, &url, &metadata).await?;
+            }
+            if let Some(writer) = signed_url_writer {
+                writer
+                    .write(&storage::sign_url("GET", writer.expires(), &url).await?)?;
+            }
             Ok(GsLocator { url }.boxed())
         }
         .boxed()
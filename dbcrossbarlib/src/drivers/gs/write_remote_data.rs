@@ -1,13 +1,25 @@
 //! Implementation of `GsLocator::write_remote_data`.
 
-use super::{prepare_as_destination_helper, GsLocator};
-use crate::clouds::gcloud::bigquery;
+use std::convert::TryFrom;
+
+use super::{
+    driver_args::{GsDestinationArguments, GsSourceArguments},
+    prepare_as_destination_helper, GsLocator,
+};
+use crate::clouds::gcloud::{bigquery, storage};
+use crate::clouds::{
+    endpoints::ApiEndpoints, object_metadata::ObjectMetadata,
+    signed_urls::SignedUrlWriter,
+};
 use crate::common::*;
 use crate::drivers::{
     bigquery::BigQueryLocator,
-    bigquery_shared::{BqTable, GCloudDriverArguments, Usage},
+    bigquery_shared::{BqTable, GCloudDriverArguments, TableName, Usage},
 };
 
+/// How many objects should we try to copy at a time?
+const PARALLEL_COPIES: usize = 10;
+
 /// Copy `source` to `dest` using `schema`.
 ///
 /// The function `BigQueryLocator::write_remote_data` isn't (yet) allowed to be
@@ -21,6 +33,19 @@ pub(crate) async fn write_remote_data_helper(
     source_args: SourceArguments<Unverified>,
     dest_args: DestinationArguments<Unverified>,
 ) -> Result<Vec<BoxLocator>> {
+    // If our source is another `gs://` directory, copy it server-side.
+    if let Some(source) = source.as_any().downcast_ref::<GsLocator>() {
+        return copy_from_gs(
+            ctx,
+            source.to_owned(),
+            dest,
+            shared_args,
+            source_args,
+            dest_args,
+        )
+        .await;
+    }
+
     // Convert the source locator into the underlying `TableName. This is a bit
     // fiddly because we're downcasting `source` and relying on knowledge about
     // the `GsLocator` type, and Rust doesn't make that especially easy.
@@ -39,15 +64,104 @@ pub(crate) async fn write_remote_data_helper(
     let schema = shared_args.schema();
     let temporary_storage = shared_args.temporary_storage();
     let if_exists = dest_args.if_exists().to_owned();
+    let gs_args = dest_args
+        .driver_args()
+        .deserialize::<GsDestinationArguments>()
+        .context("error parsing --to-args")?;
 
     // Get our billing labels.
-    let job_labels = source_args
+    let gcloud_args = source_args
         .driver_args()
         .deserialize::<GCloudDriverArguments>()
-        .context("error parsing --from-args")?
-        .job_labels
-        .to_owned();
+        .context("error parsing --from-args")?;
+    let job_labels = gcloud_args.job_labels.to_owned();
+    let ctx = ctx
+        .with_endpoints(&gcloud_args.endpoints())
+        .with_endpoints(&gs_args.endpoints());
+
+    // BigQuery can't extract some kinds of tables directly, so we may need to
+    // query our data into a temporary table first.
+    let temp_table_name =
+        match export_sql_if_needed(&ctx, &source_table_name, schema, &source_args)
+            .await?
+        {
+            None => None,
+            Some(export_sql) => {
+                let temp_table_name =
+                    source_table_name.temporary_table_name(temporary_storage)?;
 
+                // Run our query.
+                bigquery::query_to_table(
+                    &ctx,
+                    source.project(),
+                    &export_sql,
+                    &temp_table_name,
+                    &IfExists::Overwrite,
+                    &job_labels,
+                )
+                .await?;
+                Some(temp_table_name)
+            }
+        };
+    let extract_table_name = temp_table_name.as_ref().unwrap_or(&source_table_name);
+
+    // Delete the existing output, if it exists.
+    prepare_as_destination_helper(ctx.clone(), dest.as_url().to_owned(), if_exists)
+        .await?;
+
+    // Build and run a `bq extract` command.
+    let written = match gs_args.max_files {
+        None => {
+            bigquery::extract(
+                &ctx,
+                extract_table_name,
+                dest.as_url(),
+                true,
+                &job_labels,
+            )
+            .await?;
+            vec![dest.clone().boxed()]
+        }
+        Some(max_files) => {
+            extract_and_compose(
+                &ctx,
+                schema,
+                extract_table_name,
+                &dest,
+                max_files,
+                &job_labels,
+            )
+            .await?
+        }
+    };
+
+    // Delete temp table.
+    if let Some(temp_table_name) = &temp_table_name {
+        bigquery::drop_table(&ctx, temp_table_name, &job_labels).await?;
+    }
+
+    let metadata = gs_args.object_metadata();
+    let signed_url_writer = gs_args.signed_url_writer()?;
+    if !metadata.is_empty() || signed_url_writer.is_some() {
+        finish_written_objects(
+            &ctx,
+            dest.as_url(),
+            &metadata,
+            signed_url_writer.as_deref(),
+        )
+        .await?;
+    }
+    Ok(written)
+}
+
+/// Decide whether we can extract `source_table_name` directly. If not, return
+/// the SQL we need to run to query it into a temporary table.
+pub(crate) async fn export_sql_if_needed(
+    ctx: &Context,
+    source_table_name: &TableName,
+    schema: &Table,
+    source_args: &SourceArguments<Verified>,
+) -> Result<Option<String>> {
     // Construct a `BqTable` describing our source table.
     let source_table = BqTable::for_table_name_and_columns(
         source_table_name.clone(),
@@ -58,39 +172,270 @@ pub(crate) async fn write_remote_data_helper(
     // Look up our _actual_ table schema, which we'll need to handle the finer
     // details of exporting RECORDs and other things which aren't visible in the
     // portable schema. We do something similar in PostgreSQL imports.
-    let mut real_source_table =
-        BqTable::read_from_table(&ctx, &source_table_name).await?;
-    real_source_table = real_source_table.aligned_with(&source_table)?;
-
-    // We need to build a temporary export table.
-    let temp_table_name = source_table
-        .name()
-        .temporary_table_name(&temporary_storage)?;
-    let mut export_sql_data = vec![];
-    real_source_table.write_export_sql(&source_args, &mut export_sql_data)?;
-    let export_sql =
-        String::from_utf8(export_sql_data).expect("should always be UTF-8");
-    debug!(ctx.log(), "export SQL: {}", export_sql);
-
-    // Run our query.
-    bigquery::query_to_table(
+    let full_source_table = BqTable::read_from_table(ctx, source_table_name).await?;
+    let real_source_table = full_source_table.aligned_with(&source_table)?;
+
+    // BigQuery refuses to extract views, materialized views, external tables
+    // and wildcard tables, so we need to query those into a temporary table
+    // first. We also need to do this if our export SQL would change any data.
+    // But if we're exporting a regular table unchanged, we can extract it
+    // directly.
+    let table_type = if source_table_name.is_wildcard() {
+        None
+    } else {
+        Some(bigquery::table_type(ctx, source_table_name).await?)
+    };
+    let extract_directly = table_type.is_some_and(|t| t.can_extract())
+        && full_source_table.extracts_same_as(&real_source_table, source_args)?;
+    if extract_directly {
+        debug!(ctx.log(), "extracting {} directly", source_table_name);
+        Ok(None)
+    } else {
+        debug!(
+            ctx.log(),
+            "querying {} ({:?}) into a temporary table", source_table_name, table_type,
+        );
+        let mut export_sql_data = vec![];
+        real_source_table.write_export_sql(source_args, &mut export_sql_data)?;
+        let export_sql =
+            String::from_utf8(export_sql_data).expect("should always be UTF-8");
+        debug!(ctx.log(), "export SQL: {}", export_sql);
+        Ok(Some(export_sql))
+    }
+}
+
+/// Extract `table_name` into a temporary subdirectory of `dest`, and then
+/// compose the extracted chunks into at most `max_files` files.
+///
+/// BigQuery may write hundreds of small files, and some consumers would much
+/// prefer a few large ones. Since we can't strip header rows from files on the
+/// server, we extract the chunks without headers, and begin each output file
+/// with a separate header object.
+async fn extract_and_compose(
+    ctx: &Context,
+    schema: &Table,
+    table_name: &TableName,
+    dest: &GsLocator,
+    max_files: usize,
+    labels: &bigquery::Labels,
+) -> Result<Vec<BoxLocator>> {
+    if max_files == 0 {
+        return Err(format_err!("--to-arg=max_files must be at least 1"));
+    }
+    let dest_url = dest.as_url();
+    if !dest_url.path().ends_with('/') {
+        return Err(format_err!(
+            "--to-arg=max_files requires a gs:// directory ending in '/', not {}",
+            dest_url,
+        ));
+    }
+    let chunks_url =
+        dest_url.join(&format!("chunks-{}/", TemporaryStorage::random_tag()))?;
+    let ctx = ctx.child(o!("chunks_url" => chunks_url.to_string()));
+    bigquery::extract(&ctx, table_name, &chunks_url, false, labels).await?;
+
+    // Upload our header.
+    let mut header = vec![];
+    {
+        let mut wtr = csv::Writer::from_writer(&mut header);
+        wtr.write_record(schema.columns.iter().map(|c| &c.name))?;
+        wtr.flush()?;
+    }
+    let header_url = chunks_url.join("header.csv")?;
+    storage::upload_file(
         &ctx,
-        source.project(),
-        &export_sql,
-        &temp_table_name,
-        &IfExists::Overwrite,
-        &job_labels,
+        box_stream_once(Ok(BytesMut::from(&header[..]))),
+        &header_url,
     )
     .await?;
 
+    // List our chunks in order, and divide them into groups of roughly equal
+    // size.
+    let mut chunks = storage::ls(&ctx, &chunks_url)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    chunks.retain(|obj| {
+        obj.name.ends_with(".csv")
+            && obj.size > 0
+            && obj.to_url_string() != header_url.as_str()
+    });
+    chunks.sort_by(|a, b| a.name.cmp(&b.name));
+    let total_size = chunks.iter().map(|obj| obj.size).sum::<u64>();
+    let groups = group_by_size(
+        chunks.iter().map(|obj| obj.size).collect::<Vec<_>>(),
+        total_size,
+        max_files,
+    );
+
+    // Compose each group into an output file.
+    let mut written = vec![];
+    for (idx, group) in groups.iter().enumerate() {
+        let mut src_urls = vec![header_url.clone()];
+        for &chunk_idx in group {
+            src_urls.push(chunks[chunk_idx].to_url_string().parse::<Url>()?);
+        }
+        let url = dest_url.join(&format!("{:012}.csv", idx))?;
+        storage::compose(&ctx, &src_urls, &url).await?;
+        written.push(GsLocator { url }.boxed());
+    }
+
+    storage::rmdir(&ctx, &chunks_url).await?;
+    Ok(written)
+}
+
+/// Given the `sizes` of our chunks, split them into at most `max_groups`
+/// contiguous groups of roughly `total_size / max_groups` bytes. We always
+/// return at least one group, which may be empty.
+fn group_by_size(
+    sizes: Vec<u64>,
+    total_size: u64,
+    max_groups: usize,
+) -> Vec<Vec<usize>> {
+    let max_groups_u64 = u64::try_from(max_groups).unwrap_or(u64::MAX).max(1);
+    let target = total_size.div_ceil(max_groups_u64);
+    let mut groups = vec![vec![]];
+    let mut group_size = 0;
+    for (idx, size) in sizes.into_iter().enumerate() {
+        let current_is_full = group_size > 0 && group_size + size > target;
+        if current_is_full && groups.len() < max_groups {
+            groups.push(vec![]);
+            group_size = 0;
+        }
+        groups.last_mut().expect("always have a group").push(idx);
+        group_size += size;
+    }
+    groups
+}
+
+/// Copy all the CSV files in `source` to `dest`, using server-side copies
+/// instead of downloading and re-uploading the data.
+async fn copy_from_gs(
+    ctx: Context,
+    source: GsLocator,
+    dest: GsLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<BoxLocator>> {
+    let _shared_args = shared_args.verify(GsLocator::features())?;
+    let source_args = source_args.verify(GsLocator::features())?;
+    let dest_args = dest_args.verify(GsLocator::features())?;
+    let source_gs_args = source_args
+        .driver_args()
+        .deserialize::<GsSourceArguments>()
+        .context("error parsing --from-args")?;
+    let gs_args = dest_args
+        .driver_args()
+        .deserialize::<GsDestinationArguments>()
+        .context("error parsing --to-args")?;
+    let ctx = ctx.with_endpoints(&ApiEndpoints::for_copy(
+        &source_gs_args.endpoints(),
+        &gs_args.endpoints(),
+    )?);
+    if gs_args.max_files.is_some() {
+        return Err(format_err!(
+            "--to-arg=max_files is only supported when exporting from BigQuery"
+        ));
+    }
+    for locator in &[&source, &dest] {
+        if !locator.is_directory() {
+            return Err(format_err!(
+                "can only copy gs:// URLs ending in '/', got {}",
+                locator,
+            ));
+        }
+    }
+    let signed_url_writer = gs_args.signed_url_writer()?;
+    let metadata = gs_args.object_metadata();
+
     // Delete the existing output, if it exists.
+    let if_exists = dest_args.if_exists().to_owned();
     prepare_as_destination_helper(ctx.clone(), dest.as_url().to_owned(), if_exists)
         .await?;
 
-    // Build and run a `bq extract` command.
-    bigquery::extract(&ctx, &temp_table_name, dest.as_url(), &job_labels).await?;
+    // Copy each CSV file to the corresponding path under `dest`.
+    let (_, source_prefix) = storage::parse_gs_url(source.as_url())?;
+    let dest_url = dest.as_url().to_owned();
+    let copy_ctx = ctx.clone();
+    let copied = storage::ls(&ctx, source.as_url())
+        .await?
+        .try_filter(|item| futures::future::ready(item.name.ends_with(".csv")))
+        .map_ok(move |item| {
+            let ctx = copy_ctx.clone();
+            let source_prefix = source_prefix.clone();
+            let dest_url = dest_url.clone();
+            let signed_url_writer = signed_url_writer.clone();
+            let metadata = metadata.clone();
+            async move {
+                let src_url = item.to_url_string().parse::<Url>()?;
+                let rel_path =
+                    item.name.strip_prefix(&source_prefix).ok_or_else(|| {
+                        format_err!(
+                            "expected {} to start with {}",
+                            item.name,
+                            source_prefix
+                        )
+                    })?;
+                let url = dest_url.join(rel_path)?;
+                let storage_class = metadata.storage_class.as_deref();
+                storage::copy_file(&ctx, &src_url, &url, storage_class).await?;
+                if !metadata.is_empty_except_storage_class() {
+                    let metadata = ObjectMetadata {
+                        storage_class: None,
+                        ..metadata
+                    };
+                    storage::update_metadata(&ctx, &url, &metadata).await?;
+                }
+                if let Some(writer) = signed_url_writer {
+                    let signed_url =
+                        storage::sign_url("GET", writer.expires(), &url).await?;
+                    writer.write(&signed_url)?;
+                }
+                Ok(GsLocator { url }.boxed())
+            }
+        })
+        .try_buffer_unordered(PARALLEL_COPIES)
+        .try_collect::<Vec<BoxLocator>>()
+        .await?;
+    Ok(copied)
+}
 
-    // Delete temp table.
-    bigquery::drop_table(&ctx, &temp_table_name, &job_labels).await?;
-    Ok(vec![dest.boxed()])
+/// Set `metadata` on each object in `dest_url`, and write a signed URL for
+/// it if we have a `signed_url_writer`.
+async fn finish_written_objects(
+    ctx: &Context,
+    dest_url: &Url,
+    metadata: &ObjectMetadata,
+    signed_url_writer: Option<&SignedUrlWriter>,
+) -> Result<()> {
+    let objects = storage::ls(ctx, dest_url)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    for obj in objects {
+        let url = obj.to_url_string().parse::<Url>()?;
+        if !metadata.is_empty() {
+            storage::update_metadata(ctx, &url, metadata).await?;
+        }
+        if let Some(writer) = signed_url_writer {
+            writer.write(&storage::sign_url("GET", writer.expires(), &url).await?)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn group_by_size_balances_groups() {
+    assert_eq!(group_by_size(vec![], 0, 3), vec![Vec::<usize>::new()]);
+    assert_eq!(
+        group_by_size(vec![10, 10, 10, 10], 40, 2),
+        vec![vec![0, 1], vec![2, 3]],
+    );
+    assert_eq!(
+        group_by_size(vec![30, 5, 5, 5, 5], 50, 3),
+        vec![vec![0], vec![1, 2, 3], vec![4]],
+    );
+    assert_eq!(group_by_size(vec![1, 1, 1], 3, 10).len(), 3);
+    assert_eq!(group_by_size(vec![1, 1, 1], 3, 1), vec![vec![0, 1, 2]]);
 }
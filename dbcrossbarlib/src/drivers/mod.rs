@@ -8,6 +8,7 @@ use std::collections::HashMap;
 use crate::common::*;
 use crate::locator::{LocatorDriver, LocatorDriverWrapper};
 
+pub mod azblob;
 pub mod bigml;
 pub mod bigquery;
 pub mod bigquery_schema;
@@ -16,12 +17,16 @@ pub mod csv;
 pub mod dbcrossbar_schema;
 pub mod dbcrossbar_ts;
 pub mod gs;
+pub mod mysql;
 pub mod postgres;
 pub mod postgres_shared;
 pub mod postgres_sql;
 pub mod redshift;
 pub mod s3;
 pub mod shopify;
+pub mod snowflake;
+pub mod sqlite;
+pub mod synthetic;
 
 /// A helper which builds a `Box<dyn LocatorDriver>` for a type implementating
 /// `LocatorStatic`.
@@ -32,6 +37,7 @@ fn driver<L: LocatorStatic>() -> Box<dyn LocatorDriver> {
 lazy_static! {
     /// A list of known drivers, computed the first time we use it and cached.
     static ref KNOWN_DRIVERS: Vec<Box<dyn LocatorDriver>> = vec![
+        driver::<azblob::AzblobLocator>(),
         driver::<bigml::BigMlLocator>(),
         driver::<bigquery::BigQueryLocator>(),
         driver::<bigquery_schema::BigQuerySchemaLocator>(),
@@ -39,11 +45,15 @@ lazy_static! {
         driver::<dbcrossbar_schema::DbcrossbarSchemaLocator>(),
         driver::<dbcrossbar_ts::DbcrossbarTsLocator>(),
         driver::<gs::GsLocator>(),
+        driver::<mysql::MysqlLocator>(),
         driver::<postgres::PostgresLocator>(),
         driver::<postgres_sql::PostgresSqlLocator>(),
         driver::<redshift::RedshiftLocator>(),
         driver::<s3::S3Locator>(),
         driver::<shopify::ShopifyLocator>(),
+        driver::<snowflake::SnowflakeLocator>(),
+        driver::<sqlite::SqliteLocator>(),
+        driver::<synthetic::SyntheticLocator>(),
     ];
 
     /// A hash table of all known drivers, indexed by scheme and computed the
@@ -0,0 +1,84 @@
+//! Running the `mysql` command-line client.
+//!
+//! We talk to MySQL using `mysql` instead of a native client library, in the
+//! same way that we use `aws s3` for S3. This gives us `LOAD DATA LOCAL INFILE`
+//! and all of MySQL's authentication options for free.
+
+use percent_encoding::percent_decode_str;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::common::*;
+
+/// Build a `mysql` command which connects to the database in `url`, and which
+/// prints query results as raw, tab-separated lines without headers.
+pub(crate) fn mysql_command(url: &UrlWithHiddenPassword) -> Result<Command> {
+    let url = url.with_password();
+    let host = url
+        .host_str()
+        .ok_or_else(|| format_err!("no host in MySQL locator"))?;
+    let database = url.path().trim_start_matches('/');
+    let mut command = Command::new("mysql");
+    command
+        .args(["--batch", "--raw", "--skip-column-names"])
+        .arg("--default-character-set=utf8mb4")
+        .arg(format!("--host={}", host))
+        .arg(format!("--port={}", url.port().unwrap_or(3306)))
+        .arg(format!(
+            "--database={}",
+            percent_decode_str(database).decode_utf8()?
+        ));
+    if !url.username().is_empty() {
+        let user = percent_decode_str(url.username()).decode_utf8()?;
+        command.arg(format!("--user={}", user));
+    }
+    // Pass the password using the environment, so that it doesn't show up in
+    // `ps` output.
+    if let Some(password) = url.password() {
+        let password = percent_decode_str(password).decode_utf8()?;
+        command.env("MYSQL_PWD", password.as_ref());
+    }
+    Ok(command)
+}
+
+/// Run `sql` against the database in `url`, and return each line of output.
+pub(crate) async fn query_lines(
+    ctx: &Context,
+    url: &UrlWithHiddenPassword,
+    sql: &str,
+) -> Result<Vec<String>> {
+    trace!(ctx.log(), "running MySQL query: {}", sql);
+    let output = mysql_command(url)?
+        .arg("--execute")
+        .arg(sql)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("error running `mysql`")?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "`mysql` failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    let stdout =
+        String::from_utf8(output.stdout).context("`mysql` printed invalid UTF-8")?;
+    Ok(stdout.lines().map(|line| line.to_owned()).collect())
+}
+
+/// Quote `ident` as a MySQL identifier.
+pub(crate) fn mysql_quote_ident(ident: &str) -> String {
+    format!("`{}`", ident.replace('`', "``"))
+}
+
+/// Quote `s` as a MySQL string literal.
+pub(crate) fn mysql_quote_string(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+#[test]
+fn quotes_identifiers_and_strings() {
+    assert_eq!(mysql_quote_ident("order`s"), "`order``s`");
+    assert_eq!(mysql_quote_string(r"it's a \ test"), r"'it''s a \\ test'");
+}
@@ -0,0 +1,200 @@
+//! MySQL column types.
+
+use std::{fmt, str::FromStr};
+
+use crate::common::*;
+use crate::schema::DataType;
+
+/// A MySQL column type, as found in `information_schema.columns.column_type`
+/// or in a `CREATE TABLE` statement.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct MysqlDataType {
+    /// The lowercase name of the type, such as `"varchar"`.
+    name: String,
+    /// Any arguments, such as `"255"` for `varchar(255)`.
+    args: Option<String>,
+    /// Is this an unsigned numeric type?
+    unsigned: bool,
+}
+
+impl MysqlDataType {
+    /// Create a new type with no arguments.
+    fn new(name: &str, args: Option<&str>) -> Self {
+        MysqlDataType {
+            name: name.to_owned(),
+            args: args.map(|args| args.to_owned()),
+            unsigned: false,
+        }
+    }
+
+    /// The MySQL type we use to store `ty`.
+    pub(crate) fn for_data_type(ty: &DataType) -> Self {
+        match ty {
+            DataType::Array(_)
+            | DataType::GeoJson(_)
+            | DataType::Json
+            | DataType::Struct(_) => Self::new("json", None),
+            DataType::Bool => Self::new("tinyint", Some("1")),
+            DataType::Date => Self::new("date", None),
+            DataType::Decimal => Self::new("decimal", Some("65,30")),
+            DataType::Float32 => Self::new("float", None),
+            DataType::Float64 => Self::new("double", None),
+            DataType::Int16 => Self::new("smallint", None),
+            DataType::Int32 => Self::new("int", None),
+            DataType::Int64 => Self::new("bigint", None),
+            DataType::Text => Self::new("longtext", None),
+            DataType::TimestampWithoutTimeZone | DataType::TimestampWithTimeZone => {
+                Self::new("datetime", Some("6"))
+            }
+            DataType::Uuid => Self::new("char", Some("36")),
+        }
+    }
+
+    /// If we can't store `ty` without losing information, describe what we'll
+    /// do instead.
+    pub(crate) fn lossy_for_data_type(ty: &DataType) -> Option<String> {
+        match ty {
+            DataType::Array(_) => Some(
+                "JSON, which doesn't keep the type of the array's elements".to_owned(),
+            ),
+            DataType::Decimal => Some(
+                "DECIMAL(65,30), which holds at most 35 digits before the decimal point"
+                    .to_owned(),
+            ),
+            DataType::GeoJson(_) => Some("JSON, without a spatial index".to_owned()),
+            DataType::Struct(_) => Some(
+                "JSON, which doesn't keep the types of the struct's fields".to_owned(),
+            ),
+            DataType::TimestampWithTimeZone => Some(
+                "DATETIME(6) in UTC, which will be read back without a time zone"
+                    .to_owned(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Convert this type to a portable `DataType`.
+    pub(crate) fn to_data_type(&self) -> Result<DataType> {
+        let args = self.args.as_ref().map(|args| &args[..]);
+        match (&self.name[..], args, self.unsigned) {
+            ("tinyint", Some("1"), false) | ("bool", _, _) | ("boolean", _, _) => {
+                Ok(DataType::Bool)
+            }
+            ("tinyint", _, _) => Ok(DataType::Int16),
+            ("smallint", _, false) => Ok(DataType::Int16),
+            ("smallint", _, true) | ("mediumint", _, _) => Ok(DataType::Int32),
+            ("int", _, false) | ("integer", _, false) => Ok(DataType::Int32),
+            ("int", _, true) | ("integer", _, true) => Ok(DataType::Int64),
+            ("bigint", _, false) => Ok(DataType::Int64),
+            ("bigint", _, true) | ("decimal", _, _) | ("numeric", _, _) => {
+                Ok(DataType::Decimal)
+            }
+            ("float", _, _) => Ok(DataType::Float32),
+            ("double", _, _) | ("real", _, _) => Ok(DataType::Float64),
+            ("date", _, _) => Ok(DataType::Date),
+            ("datetime", _, _) => Ok(DataType::TimestampWithoutTimeZone),
+            ("timestamp", _, _) => Ok(DataType::TimestampWithTimeZone),
+            ("year", _, _) => Ok(DataType::Int16),
+            ("json", _, _) => Ok(DataType::Json),
+            ("char", _, _)
+            | ("varchar", _, _)
+            | ("tinytext", _, _)
+            | ("text", _, _)
+            | ("mediumtext", _, _)
+            | ("longtext", _, _)
+            | ("enum", _, _)
+            | ("set", _, _)
+            | ("time", _, _) => Ok(DataType::Text),
+            _ => Err(format_err!(
+                "cannot convert MySQL type {} to a portable type",
+                self
+            )),
+        }
+    }
+}
+
+impl FromStr for MysqlDataType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_ascii_lowercase();
+        let (base, unsigned) = match s.find(" unsigned") {
+            Some(idx) => (&s[..idx], true),
+            None => (&s[..], false),
+        };
+        let (name, args) = match base.find('(') {
+            Some(idx) if base.ends_with(')') => {
+                (&base[..idx], Some(&base[idx + 1..base.len() - 1]))
+            }
+            Some(_) => return Err(format_err!("cannot parse MySQL type {:?}", s)),
+            None => (base, None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(format_err!("cannot parse MySQL type {:?}", s));
+        }
+        Ok(MysqlDataType {
+            name: name.to_owned(),
+            args: args.map(|args| args.to_owned()),
+            unsigned,
+        })
+    }
+}
+
+impl fmt::Display for MysqlDataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name.to_ascii_uppercase())?;
+        if let Some(args) = &self.args {
+            write!(f, "({})", args)?;
+        }
+        if self.unsigned {
+            write!(f, " UNSIGNED")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn parses_column_types() {
+    let examples = &[
+        ("tinyint(1)", DataType::Bool),
+        ("tinyint(4)", DataType::Int16),
+        ("int(11)", DataType::Int32),
+        ("int unsigned", DataType::Int64),
+        ("bigint(20) unsigned", DataType::Decimal),
+        ("decimal(10,2)", DataType::Decimal),
+        ("double", DataType::Float64),
+        ("datetime(6)", DataType::TimestampWithoutTimeZone),
+        ("timestamp", DataType::TimestampWithTimeZone),
+        ("varchar(255)", DataType::Text),
+        ("enum('a','b')", DataType::Text),
+        ("json", DataType::Json),
+    ];
+    for (column_type, expected) in examples {
+        let ty = column_type.parse::<MysqlDataType>().unwrap();
+        assert_eq!(&ty.to_data_type().unwrap(), expected, "{}", column_type);
+    }
+    assert!("blob"
+        .parse::<MysqlDataType>()
+        .unwrap()
+        .to_data_type()
+        .is_err());
+}
+
+#[test]
+fn formats_types_for_data_types() {
+    assert_eq!(
+        MysqlDataType::for_data_type(&DataType::Decimal).to_string(),
+        "DECIMAL(65,30)",
+    );
+    assert_eq!(
+        MysqlDataType::for_data_type(&DataType::Bool).to_string(),
+        "TINYINT(1)",
+    );
+    assert_eq!(
+        "int(10) unsigned"
+            .parse::<MysqlDataType>()
+            .unwrap()
+            .to_string(),
+        "INT(10) UNSIGNED",
+    );
+}
@@ -0,0 +1,90 @@
+//! Driver arguments for `mysql:` destinations.
+
+use serde::Deserialize;
+
+use crate::common::*;
+use crate::driver_args::deserialize_opt_from_str;
+
+/// How many rows we put in each `INSERT` or `REPLACE` statement, unless told
+/// otherwise.
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// How should we load data into MySQL? Managed MySQL services differ in which
+/// of these they allow.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LoadStrategy {
+    /// Pipe CSV data to `LOAD DATA LOCAL INFILE`. This is the fastest, but it
+    /// requires the server setting `local_infile`.
+    #[default]
+    LocalInfile,
+    /// Send multi-row `INSERT` statements.
+    Insert,
+    /// Send multi-row `REPLACE` statements, which replace any existing rows
+    /// with the same primary or unique key.
+    Replace,
+}
+
+impl LoadStrategy {
+    /// The SQL verb we use for statements which load batches of rows.
+    pub(crate) fn verb(self) -> Option<&'static str> {
+        match self {
+            LoadStrategy::LocalInfile => None,
+            LoadStrategy::Insert => Some("INSERT"),
+            LoadStrategy::Replace => Some("REPLACE"),
+        }
+    }
+}
+
+/// Parsed version of `--to-arg` for `mysql:` destinations.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MysqlDestinationArguments {
+    /// How should we load our data?
+    #[serde(default)]
+    load_strategy: LoadStrategy,
+
+    /// How many rows should we put in each `INSERT` or `REPLACE` statement?
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    batch_size: Option<usize>,
+}
+
+impl MysqlDestinationArguments {
+    /// How should we load our data?
+    pub(crate) fn load_strategy(&self) -> LoadStrategy {
+        self.load_strategy
+    }
+
+    /// How many rows should we put in each `INSERT` or `REPLACE` statement?
+    pub(crate) fn batch_size(&self) -> Result<usize> {
+        match (self.load_strategy, self.batch_size) {
+            (LoadStrategy::LocalInfile, Some(_)) => Err(format_err!(
+                "batch_size requires load_strategy=insert or load_strategy=replace"
+            )),
+            (_, Some(0)) => Err(format_err!("batch_size must be at least 1")),
+            (_, Some(n)) => Ok(n),
+            (_, None) => Ok(DEFAULT_BATCH_SIZE),
+        }
+    }
+}
+
+#[test]
+fn parses_destination_arguments() {
+    let parse = |args: &[&str]| {
+        DriverArguments::from_cli_args(args)
+            .unwrap()
+            .deserialize::<MysqlDestinationArguments>()
+    };
+    let args = parse(&[]).unwrap();
+    assert_eq!(args.load_strategy(), LoadStrategy::LocalInfile);
+    assert_eq!(args.batch_size().unwrap(), DEFAULT_BATCH_SIZE);
+    let args = parse(&["load_strategy=replace", "batch_size=50"]).unwrap();
+    assert_eq!(args.load_strategy(), LoadStrategy::Replace);
+    assert_eq!(args.batch_size().unwrap(), 50);
+    assert!(parse(&["load_strategy=insert", "batch_size=0"])
+        .unwrap()
+        .batch_size()
+        .is_err());
+    assert!(parse(&["batch_size=50"]).unwrap().batch_size().is_err());
+    assert!(parse(&["load_strategy=copy"]).is_err());
+}
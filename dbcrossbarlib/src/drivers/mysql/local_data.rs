@@ -0,0 +1,132 @@
+//! Reading data from a MySQL table.
+
+use std::process::Stdio;
+use tokio::io::BufReader;
+
+use super::{
+    client::{mysql_command, mysql_quote_ident},
+    MysqlLocator,
+};
+use crate::common::*;
+use crate::schema::{Column, DataType};
+use crate::tokio_glue::copy_reader_to_stream;
+
+/// Copy the specified table from the database, returning a `CsvStream`.
+pub(crate) async fn local_data_helper(
+    ctx: Context,
+    source: MysqlLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<BoxStream<CsvStream>>> {
+    let shared_args = shared_args.verify(MysqlLocator::features())?;
+    let source_args = source_args.verify(MysqlLocator::features())?;
+    let schema = shared_args.schema();
+
+    let table_name = source.table_name().to_owned();
+    let ctx =
+        ctx.child(o!("stream" => table_name.clone(), "table" => table_name.clone()));
+    debug!(ctx.log(), "reading data from {}", source);
+
+    // Ask MySQL to format each row as a line of CSV, and stream it back to us.
+    let sql = export_sql(schema, &table_name, source_args.where_clause());
+    debug!(ctx.log(), "export SQL: {}", sql);
+    let mut child = mysql_command(source.url())?
+        .arg("--execute")
+        .arg(&sql)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("error running `mysql`")?;
+    let child_stdout = child.stdout.take().expect("child should have stdout");
+    let child_stdout = BufReader::with_capacity(BUFFER_SIZE, child_stdout);
+    let rows = copy_reader_to_stream(ctx.clone(), child_stdout)?;
+    ctx.spawn_process(format!("mysql export from {}", source), child);
+
+    // MySQL doesn't print a header, so add our own.
+    let data = box_stream_once(Ok(csv_header(schema)?)).chain(rows).boxed();
+    let csv_stream = CsvStream {
+        name: table_name,
+        data,
+    };
+    Ok(Some(box_stream_once(Ok(csv_stream))))
+}
+
+/// A CSV header line for `schema`.
+fn csv_header(schema: &Table) -> Result<BytesMut> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    wtr.write_record(schema.columns.iter().map(|c| &c.name))?;
+    let header = wtr
+        .into_inner()
+        .map_err(|_| format_err!("could not write CSV header"))?;
+    Ok(BytesMut::from(&header[..]))
+}
+
+/// Generate SQL which prints each row of `table_name` as a line of CSV.
+fn export_sql(schema: &Table, table_name: &str, where_clause: Option<&str>) -> String {
+    let cells = schema
+        .columns
+        .iter()
+        .map(export_cell_expr)
+        .collect::<Vec<_>>();
+    let mut sql = format!(
+        "SET time_zone = '+00:00'; SELECT CONCAT_WS(',', {}) FROM {}",
+        cells.join(", "),
+        mysql_quote_ident(table_name),
+    );
+    if let Some(where_clause) = where_clause {
+        sql.push_str(&format!(" WHERE ({})", where_clause));
+    }
+    sql
+}
+
+/// An SQL expression which formats `column` as a quoted CSV cell, or as an
+/// empty cell if it's `NULL`.
+fn export_cell_expr(column: &Column) -> String {
+    let name = mysql_quote_ident(&column.name);
+    let value = match &column.data_type {
+        DataType::Bool => format!(
+            "CASE WHEN {0} IS NULL THEN NULL WHEN {0} THEN 'true' ELSE 'false' END",
+            name,
+        ),
+        DataType::TimestampWithoutTimeZone => {
+            format!("DATE_FORMAT({}, '%Y-%m-%dT%H:%i:%s.%f')", name)
+        }
+        DataType::TimestampWithTimeZone => {
+            format!("DATE_FORMAT({}, '%Y-%m-%dT%H:%i:%s.%fZ')", name)
+        }
+        _ => name,
+    };
+    format!(
+        "IFNULL(CONCAT('\"', REPLACE({}, '\"', '\"\"'), '\"'), '')",
+        value,
+    )
+}
+
+#[test]
+fn generates_export_sql() {
+    let schema = Table {
+        name: "orders".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "paid".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Bool,
+                comment: None,
+            },
+        ],
+    };
+    assert_eq!(
+        export_sql(&schema, "orders", Some("id > 10")),
+        "SET time_zone = '+00:00'; SELECT CONCAT_WS(',', \
+         IFNULL(CONCAT('\"', REPLACE(`id`, '\"', '\"\"'), '\"'), ''), \
+         IFNULL(CONCAT('\"', REPLACE(CASE WHEN `paid` IS NULL THEN NULL WHEN `paid` THEN 'true' ELSE 'false' END, '\"', '\"\"'), '\"'), '')) \
+         FROM `orders` WHERE (id > 10)",
+    );
+    assert_eq!(&csv_header(&schema).unwrap()[..], &b"id,paid\n"[..]);
+}
@@ -0,0 +1,87 @@
+//! Reading table schemas from MySQL's `information_schema`.
+
+use super::{
+    client::{mysql_quote_string, query_lines},
+    MysqlDataType, MysqlLocator,
+};
+use crate::common::*;
+use crate::schema::Column;
+
+/// Implementation of `schema`, but as a real `async` function.
+pub(crate) async fn schema_helper(
+    ctx: Context,
+    source: MysqlLocator,
+) -> Result<Option<Table>> {
+    let table = fetch_table(&ctx, source.url(), source.table_name())
+        .await?
+        .ok_or_else(|| format_err!("no such table {}", source))?;
+    Ok(Some(table))
+}
+
+/// Look up `table_name` in the database at `url`, returning `None` if it
+/// doesn't exist.
+pub(crate) async fn fetch_table(
+    ctx: &Context,
+    url: &UrlWithHiddenPassword,
+    table_name: &str,
+) -> Result<Option<Table>> {
+    debug!(ctx.log(), "fetching MySQL schema for {}", table_name);
+    let sql = format!(
+        "SELECT column_name, column_type, is_nullable \
+         FROM information_schema.columns \
+         WHERE table_schema = DATABASE() AND table_name = {} \
+         ORDER BY ordinal_position",
+        mysql_quote_string(table_name),
+    );
+    let lines = query_lines(ctx, url, &sql).await?;
+    if lines.is_empty() {
+        return Ok(None);
+    }
+    let columns = lines
+        .iter()
+        .map(|line| parse_column(line))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|_| format!("could not read schema for {}", table_name))?;
+    Ok(Some(Table {
+        name: table_name.to_owned(),
+        columns,
+    }))
+}
+
+/// Parse a tab-separated line from our `information_schema` query.
+fn parse_column(line: &str) -> Result<Column> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    if fields.len() != 3 {
+        return Err(format_err!(
+            "unexpected MySQL column information {:?}",
+            line
+        ));
+    }
+    let data_type = fields[1]
+        .parse::<MysqlDataType>()?
+        .to_data_type()
+        .with_context(|_| format!("cannot read column {}", fields[0]))?;
+    Ok(Column {
+        name: fields[0].to_owned(),
+        is_nullable: fields[2] == "YES",
+        data_type,
+        comment: None,
+    })
+}
+
+#[test]
+fn parses_information_schema_columns() {
+    use crate::schema::DataType;
+
+    let column = parse_column("id\tbigint(20)\tNO").unwrap();
+    assert_eq!(column.name, "id");
+    assert!(!column.is_nullable);
+    assert_eq!(column.data_type, DataType::Int64);
+
+    let column = parse_column("active\ttinyint(1)\tYES").unwrap();
+    assert!(column.is_nullable);
+    assert_eq!(column.data_type, DataType::Bool);
+
+    assert!(parse_column("picture\tblob\tYES").is_err());
+    assert!(parse_column("garbage").is_err());
+}
@@ -0,0 +1,576 @@
+//! Writing data to a MySQL table using `LOAD DATA LOCAL INFILE`, or using
+//! batches of `INSERT` or `REPLACE` statements.
+
+use std::{io::BufWriter, process::Stdio, sync::Arc};
+
+use super::{
+    client::{mysql_command, mysql_quote_ident, mysql_quote_string, query_lines},
+    driver_args::{LoadStrategy, MysqlDestinationArguments},
+    MysqlDataType, MysqlLocator,
+};
+use crate::common::*;
+use crate::schema::{Column, DataType};
+use crate::tokio_glue::copy_stream_to_writer;
+use crate::transform::spawn_sync_transform;
+
+/// Implementation of `write_local_data`, but as a real `async` function.
+pub(crate) async fn write_local_data_helper(
+    ctx: Context,
+    dest: MysqlLocator,
+    data: BoxStream<CsvStream>,
+    shared_args: SharedArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<BoxStream<BoxFuture<BoxLocator>>> {
+    let shared_args = shared_args.verify(MysqlLocator::features())?;
+    let dest_args = dest_args.verify(MysqlLocator::features())?;
+
+    // Look up our arguments.
+    let schema = shared_args.schema();
+    let if_exists = dest_args.if_exists().to_owned();
+    let mysql_args = dest_args
+        .driver_args()
+        .deserialize::<MysqlDestinationArguments>()
+        .context("could not parse --to-arg")?;
+    let load_strategy = mysql_args.load_strategy();
+    let batch_size = mysql_args.batch_size()?;
+    if let (LoadStrategy::Replace, IfExists::Upsert(_)) = (load_strategy, &if_exists) {
+        return Err(format_err!(
+            "load_strategy=replace cannot be used with --if-exists=upsert-on"
+        ));
+    }
+    let ctx = ctx.child(o!("table" => dest.table_name().to_owned()));
+    debug!(ctx.log(), "writing data streams to {}", dest);
+
+    // Create our destination table before loading anything.
+    let prepare_sql = prepare_table_sql(schema, dest.table_name(), &if_exists)?;
+    debug!(ctx.log(), "prepare SQL: {}", prepare_sql);
+    let prepare = query_lines(&ctx, dest.url(), &prepare_sql);
+    ctx.audit_sql(dest.table_name(), &prepare_sql, prepare)
+        .await?;
+
+    // Load each stream using a separate `mysql` process.
+    let load_script = match load_strategy.verb() {
+        None => {
+            let load_sql = match &if_exists {
+                IfExists::Upsert(keys) => upsert_sql(schema, dest.table_name(), keys),
+                _ => load_sql(schema, dest.table_name()),
+            };
+            LoadScript::LocalInfile(load_sql)
+        }
+        Some(verb) => LoadScript::Batches(Arc::new(BatchScript::new(
+            schema,
+            dest.table_name(),
+            &if_exists,
+            verb,
+            batch_size,
+        ))),
+    };
+    let audit_sql = load_script.audit_sql();
+    debug!(ctx.log(), "load SQL: {}", audit_sql);
+    let written = data.map_ok(move |stream| {
+        let ctx = ctx.child(o!("stream" => stream.name.clone()));
+        let dest = dest.clone();
+        let load_script = load_script.clone();
+        let audit_sql = audit_sql.clone();
+        async move {
+            let mut command = mysql_command(dest.url())?;
+            let data = match load_script {
+                LoadScript::LocalInfile(load_sql) => {
+                    // `mysql` reads the file named in `LOAD DATA LOCAL INFILE`
+                    // itself, so we point it at its own standard input.
+                    command
+                        .arg("--local-infile=1")
+                        .arg("--execute")
+                        .arg(&load_sql);
+
+                    // MySQL needs consistent line endings, so rewrite our CSV
+                    // data before loading it.
+                    spawn_sync_transform(
+                        ctx.clone(),
+                        "normalize_csv_for_mysql".to_owned(),
+                        stream.data,
+                        |_ctx, rdr, wtr| normalize_csv(rdr, wtr),
+                    )?
+                }
+                LoadScript::Batches(batch_script) => {
+                    // Convert our CSV data into SQL, which `mysql` will read
+                    // from standard input.
+                    spawn_sync_transform(
+                        ctx.clone(),
+                        "csv_to_mysql_batches".to_owned(),
+                        stream.data,
+                        move |_ctx, rdr, wtr| batch_script.write_sql(rdr, wtr),
+                    )?
+                }
+            };
+            let mut child = command
+                .stdin(Stdio::piped())
+                // Throw away stdout so it doesn't corrupt our output.
+                .stdout(Stdio::null())
+                .spawn()
+                .context("error running `mysql`")?;
+            let child_stdin = child.stdin.take().expect("child should have stdin");
+            let load = async {
+                copy_stream_to_writer(ctx.clone(), data, child_stdin)
+                    .await
+                    .context("error copying data to `mysql`")?;
+                let status = child
+                    .await
+                    .with_context(|_| format!("error finishing load into {}", dest))?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(format_err!("`mysql` returned error: {}", status))
+                }
+            };
+            ctx.audit_sql(dest.table_name(), &audit_sql, load).await?;
+            Ok(dest.boxed())
+        }
+        .boxed()
+    });
+    Ok(written.boxed())
+}
+
+/// The SQL we send to `mysql` to load each stream.
+#[derive(Clone)]
+enum LoadScript {
+    /// Run this SQL, which reads CSV data from standard input using `LOAD DATA
+    /// LOCAL INFILE`.
+    LocalInfile(String),
+    /// Convert our CSV data to SQL statements, and send them to standard
+    /// input.
+    Batches(Arc<BatchScript>),
+}
+
+impl LoadScript {
+    /// A summary of the SQL we'll run for each stream, for logs and
+    /// `--audit-log`.
+    fn audit_sql(&self) -> String {
+        match self {
+            LoadScript::LocalInfile(load_sql) => load_sql.to_owned(),
+            LoadScript::Batches(batch_script) => batch_script.audit_sql(),
+        }
+    }
+}
+
+/// Converts CSV data into batches of `INSERT` or `REPLACE` statements, for
+/// servers which don't allow `LOAD DATA LOCAL INFILE`.
+struct BatchScript {
+    /// SQL to run before loading any rows, ending in `;`.
+    before: String,
+    /// The start of each statement, up to and including `VALUES`.
+    statement: String,
+    /// The columns we expect to find in our CSV data, in order.
+    columns: Vec<Column>,
+    /// The maximum number of rows in each statement.
+    batch_size: usize,
+    /// SQL to run after loading all our rows, ending in `;` unless empty.
+    after: String,
+}
+
+impl BatchScript {
+    /// Build a script which uses `verb` to load data matching `schema` into
+    /// `table_name`, according to `if_exists`.
+    fn new(
+        schema: &Table,
+        table_name: &str,
+        if_exists: &IfExists,
+        verb: &str,
+        batch_size: usize,
+    ) -> BatchScript {
+        let mut before = "SET time_zone = '+00:00';".to_owned();
+        let (target, after) = match if_exists {
+            IfExists::Upsert(keys) => {
+                before.push_str(&format!(" {};", upsert_prepare_sql(table_name)));
+                let after =
+                    format!("{};", upsert_finish_sql(schema, table_name, keys));
+                (UPSERT_TEMP_TABLE, after)
+            }
+            _ => (table_name, String::new()),
+        };
+        let columns = schema
+            .columns
+            .iter()
+            .map(|column| mysql_quote_ident(&column.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        BatchScript {
+            before,
+            statement: format!(
+                "{} INTO {} ({}) VALUES",
+                verb,
+                mysql_quote_ident(target),
+                columns,
+            ),
+            columns: schema.columns.clone(),
+            batch_size,
+            after,
+        }
+    }
+
+    /// A summary of the SQL in this script.
+    fn audit_sql(&self) -> String {
+        let mut sql = format!("{} {} (...);", self.before, self.statement);
+        if !self.after.is_empty() {
+            sql.push(' ');
+            sql.push_str(&self.after);
+        }
+        sql
+    }
+
+    /// Read CSV data from `rdr`, and write SQL statements to `wtr`.
+    fn write_sql(&self, rdr: impl Read, wtr: impl Write) -> Result<()> {
+        let mut rdr = csv::Reader::from_reader(rdr);
+        let mut wtr = BufWriter::new(wtr);
+        writeln!(wtr, "{}", self.before)?;
+        let mut rows_in_statement = 0;
+        let mut record = csv::StringRecord::new();
+        while rdr.read_record(&mut record)? {
+            if rows_in_statement == 0 {
+                write!(wtr, "{}\n(", self.statement)?;
+            } else {
+                write!(wtr, ",\n(")?;
+            }
+            for (idx, column) in self.columns.iter().enumerate() {
+                if idx > 0 {
+                    write!(wtr, ", ")?;
+                }
+                let cell = mysql_quote_string(record.get(idx).unwrap_or_default());
+                write!(wtr, "{}", import_expr(column, &cell))?;
+            }
+            write!(wtr, ")")?;
+            rows_in_statement += 1;
+            if rows_in_statement == self.batch_size {
+                writeln!(wtr, ";")?;
+                rows_in_statement = 0;
+            }
+        }
+        if rows_in_statement > 0 {
+            writeln!(wtr, ";")?;
+        }
+        if !self.after.is_empty() {
+            writeln!(wtr, "{}", self.after)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// Rewrite CSV data using `\n` line endings and minimal quoting.
+fn normalize_csv(rdr: impl Read, wtr: impl Write) -> Result<()> {
+    let mut rdr = csv::Reader::from_reader(rdr);
+    let mut wtr = csv::Writer::from_writer(wtr);
+    wtr.write_byte_record(rdr.byte_headers()?)?;
+    let mut record = csv::ByteRecord::new();
+    while rdr.read_byte_record(&mut record)? {
+        wtr.write_byte_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Generate SQL to prepare `table_name` according to `if_exists`.
+fn prepare_table_sql(
+    schema: &Table,
+    table_name: &str,
+    if_exists: &IfExists,
+) -> Result<String> {
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| {
+            let mut def = format!(
+                "{} {}",
+                mysql_quote_ident(&column.name),
+                MysqlDataType::for_data_type(&column.data_type),
+            );
+            if !column.is_nullable {
+                def.push_str(" NOT NULL");
+            }
+            def
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let table_name = mysql_quote_ident(table_name);
+    match if_exists {
+        IfExists::Error => Ok(format!("CREATE TABLE {} ({})", table_name, columns)),
+        IfExists::Append => Ok(format!(
+            "CREATE TABLE IF NOT EXISTS {} ({})",
+            table_name, columns,
+        )),
+        IfExists::Overwrite => Ok(format!(
+            "DROP TABLE IF EXISTS {0}; CREATE TABLE {0} ({1})",
+            table_name, columns,
+        )),
+        IfExists::Upsert(keys) => {
+            for key in keys {
+                if !schema.columns.iter().any(|column| &column.name == key) {
+                    return Err(format_err!(
+                        "upsert key {:?} is not a column in {}",
+                        key,
+                        table_name,
+                    ));
+                }
+            }
+            Ok(format!(
+                "CREATE TABLE IF NOT EXISTS {} ({})",
+                table_name, columns,
+            ))
+        }
+    }
+}
+
+/// The name of the temporary table we use for each upserted stream. MySQL
+/// temporary tables belong to a single connection, and each stream uses its
+/// own `mysql` process, so they never conflict.
+const UPSERT_TEMP_TABLE: &str = "dbcrossbar_upsert";
+
+/// Generate SQL which loads CSV data from standard input into a temporary
+/// table, and then replaces any rows in `table_name` with the same `keys`.
+///
+/// We delete and insert, instead of using `ON DUPLICATE KEY UPDATE`, so that
+/// `keys` don't need a unique index.
+fn upsert_sql(schema: &Table, table_name: &str, keys: &[String]) -> String {
+    format!(
+        "{}; {}; {}",
+        upsert_prepare_sql(table_name),
+        load_sql(schema, UPSERT_TEMP_TABLE),
+        upsert_finish_sql(schema, table_name, keys),
+    )
+}
+
+/// Generate SQL which creates the temporary table we upsert from.
+fn upsert_prepare_sql(table_name: &str) -> String {
+    format!(
+        "CREATE TEMPORARY TABLE {} LIKE {}",
+        mysql_quote_ident(UPSERT_TEMP_TABLE),
+        mysql_quote_ident(table_name),
+    )
+}
+
+/// Generate SQL which replaces any rows in `table_name` with the same `keys`
+/// as the rows in our temporary table.
+fn upsert_finish_sql(schema: &Table, table_name: &str, keys: &[String]) -> String {
+    let table = mysql_quote_ident(table_name);
+    let temp = mysql_quote_ident(UPSERT_TEMP_TABLE);
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| mysql_quote_ident(&column.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let matches = keys
+        .iter()
+        .map(|key| {
+            let key = mysql_quote_ident(key);
+            format!("d.{0} = s.{0}", key)
+        })
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    format!(
+        "START TRANSACTION; \
+         DELETE d FROM {table} AS d INNER JOIN {temp} AS s ON {matches}; \
+         INSERT INTO {table} ({columns}) SELECT {columns} FROM {temp}; \
+         COMMIT",
+        temp = temp,
+        table = table,
+        matches = matches,
+        columns = columns,
+    )
+}
+
+/// Generate SQL which loads CSV data from standard input into `table_name`.
+fn load_sql(schema: &Table, table_name: &str) -> String {
+    let vars = (0..schema.columns.len())
+        .map(|idx| format!("@c{}", idx))
+        .collect::<Vec<_>>();
+    let assignments = schema
+        .columns
+        .iter()
+        .zip(&vars)
+        .map(|(column, var)| {
+            format!(
+                "{} = {}",
+                mysql_quote_ident(&column.name),
+                import_expr(column, var),
+            )
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "SET time_zone = '+00:00'; \
+         LOAD DATA LOCAL INFILE '/dev/stdin' INTO TABLE {} CHARACTER SET utf8mb4 \
+         FIELDS TERMINATED BY ',' OPTIONALLY ENCLOSED BY '\"' ESCAPED BY '' \
+         LINES TERMINATED BY '\\n' IGNORE 1 LINES ({}) SET {}",
+        mysql_quote_ident(table_name),
+        vars.join(", "),
+        assignments.join(", "),
+    )
+}
+
+/// An SQL expression which converts the CSV cell in `var` to a value for
+/// `column`. Empty cells are `NULL`.
+fn import_expr(column: &Column, var: &str) -> String {
+    match &column.data_type {
+        DataType::Bool => format!(
+            "CASE WHEN {0} = '' THEN NULL \
+             WHEN LOWER({0}) IN ('1', 'y', 'yes', 'on', 't', 'true') THEN 1 \
+             ELSE 0 END",
+            var,
+        ),
+        // MySQL accepts `T` separators and numeric offsets, but not `Z`.
+        DataType::TimestampWithoutTimeZone => {
+            format!("REPLACE(NULLIF({}, ''), 'T', ' ')", var)
+        }
+        DataType::TimestampWithTimeZone => format!(
+            "REPLACE(REPLACE(NULLIF({}, ''), 'T', ' '), 'Z', '+00:00')",
+            var,
+        ),
+        _ => format!("NULLIF({}, '')", var),
+    }
+}
+
+#[test]
+fn generates_prepare_and_load_sql() {
+    let schema = Table {
+        name: "orders".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "paid".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Bool,
+                comment: None,
+            },
+        ],
+    };
+    assert_eq!(
+        prepare_table_sql(&schema, "orders", &IfExists::Overwrite).unwrap(),
+        "DROP TABLE IF EXISTS `orders`; CREATE TABLE `orders` (`id` BIGINT NOT NULL, `paid` TINYINT(1))",
+    );
+    assert_eq!(
+        prepare_table_sql(&schema, "orders", &IfExists::Append).unwrap(),
+        "CREATE TABLE IF NOT EXISTS `orders` (`id` BIGINT NOT NULL, `paid` TINYINT(1))",
+    );
+    assert_eq!(
+        prepare_table_sql(&schema, "orders", &IfExists::Upsert(vec!["id".to_owned()]))
+            .unwrap(),
+        "CREATE TABLE IF NOT EXISTS `orders` (`id` BIGINT NOT NULL, `paid` TINYINT(1))",
+    );
+    assert!(prepare_table_sql(
+        &schema,
+        "orders",
+        &IfExists::Upsert(vec!["missing".to_owned()])
+    )
+    .is_err());
+    assert_eq!(
+        load_sql(&schema, "orders"),
+        "SET time_zone = '+00:00'; \
+         LOAD DATA LOCAL INFILE '/dev/stdin' INTO TABLE `orders` CHARACTER SET utf8mb4 \
+         FIELDS TERMINATED BY ',' OPTIONALLY ENCLOSED BY '\"' ESCAPED BY '' \
+         LINES TERMINATED BY '\\n' IGNORE 1 LINES (@c0, @c1) \
+         SET `id` = NULLIF(@c0, ''), \
+         `paid` = CASE WHEN @c1 = '' THEN NULL \
+         WHEN LOWER(@c1) IN ('1', 'y', 'yes', 'on', 't', 'true') THEN 1 \
+         ELSE 0 END",
+    );
+}
+
+#[test]
+fn normalizes_line_endings() {
+    let mut output: Vec<u8> = vec![];
+    normalize_csv(&b"id,note\r\n1,\"a\r\nb\"\r\n"[..], &mut output).unwrap();
+    assert_eq!(output, b"id,note\n1,\"a\r\nb\"\n".to_vec());
+}
+
+#[test]
+fn generates_upsert_sql() {
+    let schema = Table {
+        name: "orders".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "note".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Text,
+                comment: None,
+            },
+        ],
+    };
+    assert_eq!(
+        upsert_sql(&schema, "orders", &["id".to_owned()]),
+        "CREATE TEMPORARY TABLE `dbcrossbar_upsert` LIKE `orders`; \
+         SET time_zone = '+00:00'; \
+         LOAD DATA LOCAL INFILE '/dev/stdin' INTO TABLE `dbcrossbar_upsert` CHARACTER SET utf8mb4 \
+         FIELDS TERMINATED BY ',' OPTIONALLY ENCLOSED BY '\"' ESCAPED BY '' \
+         LINES TERMINATED BY '\\n' IGNORE 1 LINES (@c0, @c1) \
+         SET `id` = NULLIF(@c0, ''), `note` = NULLIF(@c1, ''); \
+         START TRANSACTION; \
+         DELETE d FROM `orders` AS d INNER JOIN `dbcrossbar_upsert` AS s ON d.`id` = s.`id`; \
+         INSERT INTO `orders` (`id`, `note`) SELECT `id`, `note` FROM `dbcrossbar_upsert`; \
+         COMMIT",
+    );
+}
+
+#[test]
+fn generates_batches_of_inserts() {
+    let schema = Table {
+        name: "orders".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "note".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Text,
+                comment: None,
+            },
+        ],
+    };
+    let script = BatchScript::new(&schema, "orders", &IfExists::Append, "INSERT", 2);
+    let mut output = vec![];
+    script
+        .write_sql(&b"id,note\n1,it's\n2,\n3,c\n"[..], &mut output)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "SET time_zone = '+00:00';\n\
+         INSERT INTO `orders` (`id`, `note`) VALUES\n\
+         (NULLIF('1', ''), NULLIF('it''s', '')),\n\
+         (NULLIF('2', ''), NULLIF('', ''));\n\
+         INSERT INTO `orders` (`id`, `note`) VALUES\n\
+         (NULLIF('3', ''), NULLIF('c', ''));\n",
+    );
+
+    let script = BatchScript::new(
+        &schema,
+        "orders",
+        &IfExists::Upsert(vec!["id".to_owned()]),
+        "INSERT",
+        100,
+    );
+    let mut output = vec![];
+    script.write_sql(&b"id,note\n"[..], &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "SET time_zone = '+00:00'; \
+         CREATE TEMPORARY TABLE `dbcrossbar_upsert` LIKE `orders`;\n\
+         START TRANSACTION; \
+         DELETE d FROM `orders` AS d INNER JOIN `dbcrossbar_upsert` AS s ON d.`id` = s.`id`; \
+         INSERT INTO `orders` (`id`, `note`) SELECT `id`, `note` FROM `dbcrossbar_upsert`; \
+         COMMIT;\n",
+    );
+}
@@ -1,8 +1,8 @@
 //! Implementation of `count`, but as a real `async` function.
 
-use super::PostgresLocator;
+use super::{driver_args::PostgresSourceArguments, PostgresLocator};
 use crate::common::*;
-use crate::drivers::postgres_shared::{connect, CheckCatalog, PgCreateTable};
+use crate::drivers::postgres_shared::{CheckCatalog, PgCreateTable};
 
 /// Implementation of `count`, but as a real `async` function.
 pub(crate) async fn count_helper(
@@ -15,11 +15,17 @@ pub(crate) async fn count_helper(
     let source_args = source_args.verify(PostgresLocator::features())?;
 
     // Get the parts of our locator.
-    let url = locator.url.clone();
     let table_name = locator.table_name.clone();
 
     // Look up the arguments we'll need.
     let schema = shared_args.schema();
+    let pg_args = source_args
+        .driver_args()
+        .deserialize::<PostgresSourceArguments>()
+        .context("error parsing --from-args")?;
+
+    // Connect to the database, which may be a replica.
+    let (url, conn) = pg_args.connect(&ctx, &locator.url).await?;
 
     // Convert our schema to a native PostgreSQL schema.
     let pg_create_table = PgCreateTable::from_pg_catalog_or_default(
@@ -39,7 +45,6 @@ pub(crate) async fn count_helper(
     debug!(ctx.log(), "count SQL: {}", sql);
 
     // Run our query.
-    let conn = connect(&ctx, &url).await?;
     let stmt = conn.prepare(&sql).await?;
     let rows = conn
         .query(&stmt, &[])
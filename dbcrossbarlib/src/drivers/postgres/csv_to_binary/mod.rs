@@ -79,31 +79,50 @@ pub(crate) fn copy_csv_to_pg_binary(
     wtr.write_u32::<NE>(0)?; // Flags.
     wtr.write_u32::<NE>(0)?; // Extension area length.
 
-    // Iterate over our CSV rows.
-    for (row_idx, row) in rdr.records().enumerate() {
-        // Check for read errors.
-        let row = row?;
+    // Every row has the same number of fields, because `csv::Reader` rejects
+    // rows of the wrong length.
+    let field_count = i16::try_from(table.columns.len())?;
+
+    // Iterate over our CSV rows. We reuse a single `ByteRecord`, which avoids
+    // allocating memory for each row, and which allows us to skip UTF-8
+    // validation for empty cells. This matters for very wide tables where
+    // most cells are `NULL`.
+    let mut row = csv::ByteRecord::new();
+    let mut row_idx = 0;
+    while rdr.read_byte_record(&mut row)? {
+        row_idx += 1;
 
         // Write our tuple field count.
-        wtr.write_i16::<NE>(i16::try_from(row.len())?)?;
+        wtr.write_i16::<NE>(field_count)?;
 
         // Write each of our rows. Using `zip` allows Rust to omit bounds
         // checks on the `row` and `columns` arrays.
         for (cell, col) in row.iter().zip(table.columns.iter()) {
-            cell_to_binary(&mut wtr, col, cell).with_context(|_| {
-                format!(
-                    "could not convert row {}, column {} ({:?})",
-                    row_idx + 1, // Add 1 for header row.
-                    col.name,
-                    cell,
-                )
-            })?;
+            // Handle `NULL` values without any further work.
+            if cell.is_empty() && col.is_nullable {
+                wtr.write_all(&NULL_LEN)?;
+                continue;
+            }
+            str::from_utf8(cell)
+                .map_err(Error::from)
+                .and_then(|cell| cell_to_binary(&mut wtr, col, cell))
+                .with_context(|_| {
+                    format!(
+                        "could not convert row {}, column {} ({:?})",
+                        row_idx, // Header row is 0.
+                        col.name,
+                        String::from_utf8_lossy(cell),
+                    )
+                })?;
         }
     }
 
     Ok(())
 }
 
+/// The length we write for a `NULL` value, in network byte order.
+const NULL_LEN: [u8; 4] = (-1i32).to_be_bytes();
+
 /// Convert a cell to PostgreSQL `BINARY` format.
 fn cell_to_binary(wtr: &mut BufferedWriter, col: &PgColumn, cell: &str) -> Result<()> {
     if cell.is_empty() && col.is_nullable {
@@ -135,45 +154,42 @@ fn array_to_binary(
     data_type: &PgScalarDataType,
     cell: &str,
 ) -> Result<()> {
-    // TODO: For now, we can only handle single-dimensional arrays like
-    // `[1,2,3]`. Multidimensional arrays would require us to figure out things
-    // like `[[1,2], [3]]` and what order to serialize nested elements in.
-    if dimension_count != 1 {
-        return Err(format_err!(
-            "arrays with {} dimensions cannot yet be written to PostgreSQL",
-            dimension_count,
-        ));
+    // Parse our cell into a JSON value.
+    let json: Value = serde_json::from_str(cell).context("cannot parse JSON")?;
+    if !json.is_array() {
+        return Err(format_err!("expected JSON array, found {}", json));
     }
 
-    // Parse our cell into a JSON value.
-    let json = serde_json::from_str(cell).context("cannot parse JSON")?;
-    let json_array = match json {
-        Value::Array(json_array) => json_array,
-        other => return Err(format_err!("expected JSON array, found {}", other)),
-    };
+    // PostgreSQL arrays are always "rectangular", so figure out the size of
+    // each dimension, and collect our elements in the order PostgreSQL expects
+    // them (with the last index varying fastest).
+    let dimension_count = usize::try_from(dimension_count)?;
+    let sizes = array_sizes(&json, dimension_count)?;
+    let mut elems = vec![];
+    flatten_array(&json, &sizes, &mut elems)?;
 
     // Write our array, using `write_value` to calculate the total length.
     let mut buffer = vec![];
     wtr.write_value(&mut buffer, |wtr| {
         // The number of dimensions in our array.
-        WriteBytesExt::write_i32::<NE>(wtr, dimension_count)?;
+        WriteBytesExt::write_i32::<NE>(wtr, i32::try_from(sizes.len())?)?;
 
-        // Has NULL? (I'm not sure what this does, but I figure it's safer
-        // to assume we might have NULLs than otherwise.)
-        WriteBytesExt::write_i32::<NE>(wtr, 1)?;
+        // Does our array contain any NULL elements?
+        let has_null = elems.iter().any(|elem| elem.is_null());
+        WriteBytesExt::write_i32::<NE>(wtr, if has_null { 1 } else { 0 })?;
 
         // The OID for our `data_type`, so PostgreSQL knows how to parse this.
         WriteBytesExt::write_i32::<NE>(wtr, data_type.oid()?)?;
 
-        // Array dimension 1 of 1: Size.
-        WriteBytesExt::write_i32::<NE>(wtr, i32::try_from(json_array.len())?)?;
-
-        // Array dimension 1 of 1: Lower bound. We want 1-based, because that's the default
-        // in PostgreSQL.
-        WriteBytesExt::write_i32::<NE>(wtr, 1)?;
+        // For each dimension, write the size and the lower bound. We want
+        // 1-based lower bounds, because that's the default in PostgreSQL.
+        for &size in &sizes {
+            WriteBytesExt::write_i32::<NE>(wtr, i32::try_from(size)?)?;
+            WriteBytesExt::write_i32::<NE>(wtr, 1)?;
+        }
 
         // Elements.
-        for elem in &json_array {
+        for elem in elems {
             match elem {
                 Value::Null => {
                     WriteBytesExt::write_i32::<NE>(wtr, -1)?;
@@ -189,6 +205,57 @@ fn array_to_binary(
     Ok(())
 }
 
+/// Figure out the size of each of the `dimension_count` dimensions of `json`,
+/// by looking at the first element at each level of nesting. If we find an
+/// empty array, the remaining dimensions have size 0.
+fn array_sizes(json: &Value, dimension_count: usize) -> Result<Vec<usize>> {
+    let mut sizes = Vec::with_capacity(dimension_count);
+    let mut current = Some(json);
+    for _ in 0..dimension_count {
+        match current {
+            Some(Value::Array(elems)) => {
+                sizes.push(elems.len());
+                current = elems.first();
+            }
+            Some(other) => {
+                return Err(format_err!(
+                    "expected {}-dimensional JSON array, found {}",
+                    dimension_count,
+                    other,
+                ))
+            }
+            None => sizes.push(0),
+        }
+    }
+    Ok(sizes)
+}
+
+/// Append the elements of the nested array `json` to `elems`, checking that
+/// each level of nesting matches `sizes`.
+fn flatten_array<'a>(
+    json: &'a Value,
+    sizes: &[usize],
+    elems: &mut Vec<&'a Value>,
+) -> Result<()> {
+    match (sizes.split_first(), json) {
+        (None, elem) => {
+            elems.push(elem);
+            Ok(())
+        }
+        (Some((&size, rest)), Value::Array(nested)) if nested.len() == size => {
+            for elem in nested {
+                flatten_array(elem, rest, elems)?;
+            }
+            Ok(())
+        }
+        (Some((&size, _)), other) => Err(format_err!(
+            "PostgreSQL arrays must be rectangular, expected array of length {}, found {}",
+            size,
+            other,
+        )),
+    }
+}
+
 /// Interpret a JSON value as `data_type` and write it out as a `BINARY` value.
 fn json_to_binary<W: Write>(
     wtr: &mut W,
@@ -318,6 +385,125 @@ fn parse_ewkb_fallback() {
         .unwrap();
 }
 
+/// A buffer that we can write to as a `Box<dyn Write>`, and then read back.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Write::write(&mut *self.0.lock().unwrap(), buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn writes_nulls_for_empty_cells() {
+    let table = PgCreateTable::parse(
+        "test.sql".to_owned(),
+        "CREATE TABLE t (a int, b text, c text NOT NULL);".to_owned(),
+    )
+    .unwrap();
+    let buffer = SharedBuffer::default();
+    let csv = "a,b,c\n,,\n1,,x\n";
+    copy_csv_to_pg_binary(&table, Box::new(csv.as_bytes()), Box::new(buffer.clone()))
+        .unwrap();
+    let bytes = buffer.0.lock().unwrap().clone();
+
+    let mut expected: Vec<u8> = vec![];
+    expected.extend_from_slice(b"PGCOPY\n\xff\r\n\0");
+    expected.extend_from_slice(&[0; 8]);
+    // Row 1: two `NULL` values, and an empty string for our `NOT NULL` column.
+    expected.extend_from_slice(&[0, 3]);
+    expected.extend_from_slice(&NULL_LEN);
+    expected.extend_from_slice(&NULL_LEN);
+    expected.extend_from_slice(&[0, 0, 0, 0]);
+    // Row 2.
+    expected.extend_from_slice(&[0, 3]);
+    expected.extend_from_slice(&[0, 0, 0, 4, 0, 0, 0, 1]);
+    expected.extend_from_slice(&NULL_LEN);
+    expected.extend_from_slice(&[0, 0, 0, 1, b'x']);
+    assert_eq!(bytes, expected);
+
+    // Errors still report the row and column.
+    let err = copy_csv_to_pg_binary(
+        &table,
+        Box::new("a,b,c\nnope,,x\n".as_bytes()),
+        Box::new(SharedBuffer::default()),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("row 1, column a"));
+}
+
+#[test]
+fn writes_array_elements_in_order() {
+    let to_binary =
+        |dimension_count, ty: &PgScalarDataType, cell| -> Result<Vec<u8>> {
+            let buffer = SharedBuffer::default();
+            let mut wtr = BufferedWriter::new(Box::new(buffer.clone()));
+            array_to_binary(&mut wtr, dimension_count, ty, cell)?;
+            wtr.flush()?;
+            let bytes = buffer.0.lock().unwrap().clone();
+            Ok(bytes)
+        };
+
+    // A two-dimensional `int[][]` array with a `NULL`.
+    let bytes = to_binary(2, &PgScalarDataType::Int, "[[1,2],[null,4]]").unwrap();
+    let mut expected: Vec<u8> = vec![];
+    for word in &[
+        56, // Length of the array value (14 * 4 bytes).
+        2,  // Dimensions.
+        1,  // Has NULL.
+        PgScalarDataType::Int.oid().unwrap(),
+        2, // Size of dimension 1.
+        1, // Lower bound of dimension 1.
+        2, // Size of dimension 2.
+        1, // Lower bound of dimension 2.
+        4,
+        1,
+        4,
+        2,
+        -1, // NULL element.
+        4,
+        4,
+    ] {
+        WriteBytesExt::write_i32::<NE>(&mut expected, *word).unwrap();
+    }
+    assert_eq!(bytes, expected);
+
+    // Other element types and dimensions.
+    let examples = &[
+        (1, PgScalarDataType::Text, r#"["a",null,"c"]"#),
+        (1, PgScalarDataType::Bigint, "[]"),
+        (2, PgScalarDataType::DoublePrecision, "[[1.5],[-2]]"),
+        (2, PgScalarDataType::Int, "[]"),
+        (
+            1,
+            PgScalarDataType::TimestampWithTimeZone,
+            r#"["2020-01-01T00:00:00Z"]"#,
+        ),
+        (
+            3,
+            PgScalarDataType::TimestampWithoutTimeZone,
+            r#"[[["2020-01-01T00:00:00"]]]"#,
+        ),
+    ];
+    for (dimension_count, ty, cell) in examples {
+        to_binary(*dimension_count, ty, cell).unwrap();
+    }
+
+    // Ragged and mis-nested arrays.
+    assert!(to_binary(2, &PgScalarDataType::Int, "[[1,2],[3]]").is_err());
+    assert!(to_binary(2, &PgScalarDataType::Int, "[[1],2]").is_err());
+    assert!(to_binary(2, &PgScalarDataType::Int, "[1,2]").is_err());
+    assert!(to_binary(1, &PgScalarDataType::Int, "[[1]]").is_err());
+    assert!(to_binary(1, &PgScalarDataType::Int, "1").is_err());
+}
+
 /// Parse a CSV cell and write it out as a PostgreSQL binary value. This works
 /// for any type implementing `FromCsvCell` and `WriteBinary`. More complicated
 /// cases will need to do this manually.
@@ -0,0 +1,144 @@
+//! Implementation of `PostgresLocator::dry_run_read` and `dry_run_write`.
+
+use super::{
+    driver_args::PostgresDestinationArguments,
+    load_tracking::LoadTracker,
+    local_data::export_sql,
+    write_local_data::{
+        add_columns_sql, copy_stream_sql, match_table_columns, plan_table_evolution,
+        prepare_table_sql,
+    },
+    PostgresLocator,
+};
+use crate::common::*;
+use crate::drivers::postgres_shared::{CheckCatalog, PgColumn, PgCreateTable};
+
+/// Describe the SQL that `local_data` would run.
+pub(crate) async fn dry_run_read_helper(
+    ctx: Context,
+    source: PostgresLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Vec<String>> {
+    let shared_args = shared_args.verify(PostgresLocator::features())?;
+    let source_args = source_args.verify(PostgresLocator::features())?;
+    let sql = export_sql(
+        &ctx,
+        source.url(),
+        source.table_name(),
+        shared_args.schema(),
+        &source_args,
+    )
+    .await?;
+    Ok(vec![sql])
+}
+
+/// Describe the SQL that `write_local_data` would run, or that
+/// `write_remote_data` would run if we have a `source`.
+pub(crate) async fn dry_run_write_helper(
+    ctx: Context,
+    dest: PostgresLocator,
+    source: Option<BoxLocator>,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<String>> {
+    let source = source
+        .map(|source| -> Result<PostgresLocator> {
+            Ok(source
+                .as_any()
+                .downcast_ref::<PostgresLocator>()
+                .ok_or_else(|| format_err!("not a postgres:// locator: {}", source))?
+                .to_owned())
+        })
+        .transpose()?;
+
+    let shared_args = shared_args.verify(PostgresLocator::features())?;
+    let dest_args = dest_args.verify(PostgresLocator::features())?;
+
+    // Look up our arguments.
+    let schema = shared_args.schema();
+    let if_exists = dest_args.if_exists().to_owned();
+    let pg_args = dest_args
+        .driver_args()
+        .deserialize::<PostgresDestinationArguments>()
+        .context("error parsing --to-args")?;
+    let url = pg_args.url(dest.url());
+
+    // Rename our columns to match an existing destination table, if asked.
+    // This only reads the catalog.
+    let renames = match_table_columns(
+        &ctx,
+        &url,
+        dest.table_name(),
+        schema,
+        dest_args.match_columns(),
+    )
+    .await?;
+    let schema = renames.apply_to_schema(schema);
+    let if_exists = renames.apply_to_if_exists(&if_exists);
+
+    // Decide how we'd evolve the destination table, without changing it.
+    let mut sql = vec![];
+    let changes = plan_table_evolution(
+        &ctx,
+        &url,
+        dest.table_name(),
+        &schema,
+        dest_args.schema_evolution(),
+    )
+    .await?;
+    if !changes.add_columns.is_empty() {
+        sql.push(add_columns_sql(dest.table_name(), &changes.add_columns)?);
+    }
+    let schema = changes.apply_to_schema(&schema);
+
+    // Look up our destination table. Any columns we would have added aren't in
+    // the catalog yet, so we add them ourselves.
+    let mut existing_schema = schema.clone();
+    existing_schema
+        .columns
+        .retain(|c| !changes.add_columns.iter().any(|added| added.name == c.name));
+    let mut dest_table = PgCreateTable::from_pg_catalog_or_default(
+        &ctx,
+        CheckCatalog::from(&if_exists),
+        &url,
+        dest.table_name(),
+        &existing_schema,
+    )
+    .await?;
+    for column in &changes.add_columns {
+        dest_table.columns.push(PgColumn::from_column(column)?);
+    }
+    let dest_table =
+        dest_table.aligned_with(&PgCreateTable::from_name_and_columns(
+            dest.table_name().to_owned(),
+            &schema.columns,
+        )?)?;
+
+    // If we're copying directly from another PostgreSQL table, describe our
+    // export.
+    if let Some(source) = source {
+        let source_args = source_args.verify(PostgresLocator::features())?;
+        let mut sql_bytes: Vec<u8> = vec![];
+        dest_table.write_binary_export_sql(
+            &mut sql_bytes,
+            source.table_name(),
+            &renames,
+            &source_args,
+        )?;
+        sql.push(format!(
+            "-- on {}\n{}",
+            source,
+            String::from_utf8(sql_bytes).expect("should always be UTF-8"),
+        ));
+    }
+
+    sql.extend(prepare_table_sql(dest_table.clone(), &if_exists));
+    if let Some(load_id) = pg_args.load_id() {
+        let tracker = LoadTracker::new(dest.table_name(), load_id);
+        sql.extend(tracker.prepare_loads_table_sql(&if_exists));
+    }
+    sql.extend(copy_stream_sql(&dest_table, &if_exists)?);
+    Ok(sql)
+}
@@ -0,0 +1,54 @@
+//! Implementation of `estimate`.
+
+use super::{driver_args::PostgresSourceArguments, PostgresLocator};
+use crate::common::*;
+
+/// Implementation of `estimate`, but as a real `async` function.
+///
+/// We use the planner statistics in `pg_class`, which are updated by `VACUUM`
+/// and `ANALYZE`, so they may be out of date.
+pub(crate) async fn estimate_helper(
+    ctx: Context,
+    locator: PostgresLocator,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<Estimate>> {
+    let source_args = source_args.verify(PostgresLocator::features())?;
+    let pg_args = source_args
+        .driver_args()
+        .deserialize::<PostgresSourceArguments>()
+        .context("error parsing --from-args")?;
+
+    let table_name = locator.table_name();
+    let schema = table_name.schema().unwrap_or("public");
+    let table = table_name.table();
+
+    let sql = r#"
+SELECT
+    c.reltuples::bigint AS row_count,
+    pg_total_relation_size(c.oid) AS size_bytes
+FROM pg_class c
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    n.nspname = $1 AND
+    c.relname = $2
+"#;
+    let (_url, client) = pg_args.connect(&ctx, locator.url()).await?;
+    let rows = client
+        .query(sql, &[&schema, &table])
+        .await
+        .context("error looking up table statistics")?;
+    if rows.len() != 1 {
+        return Err(format_err!("could not find table {}", locator));
+    }
+    // `reltuples` is negative (on newer versions of PostgreSQL) or zero if
+    // the table has never been analyzed, so we treat negative values as
+    // unknown.
+    let row_count: i64 = rows[0].get("row_count");
+    let size_bytes: i64 = rows[0].get("size_bytes");
+    Ok(Some(Estimate {
+        rows: u64::try_from(row_count).ok(),
+        bytes: u64::try_from(size_bytes).ok(),
+        files: None,
+        method: "table statistics".to_owned(),
+    }))
+}
@@ -0,0 +1,178 @@
+//! Recording which chunks have been loaded into a PostgreSQL table, so that
+//! re-running a partially failed append never loads the same chunk twice.
+
+use super::Client;
+use crate::common::*;
+use crate::drivers::postgres_shared::{pg_quote, TableName};
+
+/// The table where we record loaded chunks. We create this in the same schema
+/// as the destination table.
+const LOADS_TABLE: &str = "_dbcrossbar_loads";
+
+/// Records the chunks loaded into a destination table as part of a named load.
+///
+/// Each chunk is copied in a transaction which also inserts a row into
+/// `_dbcrossbar_loads`, so either both happen or neither does.
+#[derive(Clone, Debug)]
+pub(crate) struct LoadTracker {
+    /// Where we record our loads.
+    loads_table: TableName,
+    /// The table we're loading.
+    dest: TableName,
+    /// The user's name for this load.
+    load_id: String,
+}
+
+impl LoadTracker {
+    /// Track chunks loaded into `dest` as part of `load_id`.
+    pub(crate) fn new(dest: &TableName, load_id: &str) -> LoadTracker {
+        LoadTracker {
+            loads_table: TableName::new(
+                dest.schema().map(|s| s.to_owned()),
+                LOADS_TABLE,
+            ),
+            dest: dest.to_owned(),
+            load_id: load_id.to_owned(),
+        }
+    }
+
+    /// Generate SQL to create our loads table if it doesn't exist. When we
+    /// overwrite our destination table, we also forget any chunks loaded into
+    /// the old one.
+    pub(crate) fn prepare_loads_table_sql(&self, if_exists: &IfExists) -> Vec<String> {
+        let mut sql = vec![format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+    table_name text NOT NULL,
+    load_id text NOT NULL,
+    chunk_id text NOT NULL,
+    loaded_at timestamp with time zone NOT NULL DEFAULT now(),
+    PRIMARY KEY (table_name, load_id, chunk_id)
+)",
+            self.loads_table.quoted(),
+        )];
+        if let IfExists::Overwrite = if_exists {
+            sql.push(format!(
+                "DELETE FROM {} WHERE table_name = {}",
+                self.loads_table.quoted(),
+                pg_quote(&self.dest.unquoted()),
+            ));
+        }
+        sql
+    }
+
+    /// Run the SQL generated by `prepare_loads_table_sql`.
+    pub(crate) async fn prepare_loads_table(
+        &self,
+        ctx: &Context,
+        client: &Client,
+        if_exists: &IfExists,
+    ) -> Result<()> {
+        debug!(ctx.log(), "preparing {}", self.loads_table.quoted());
+        for sql in self.prepare_loads_table_sql(if_exists) {
+            let stmt = client.prepare(&sql).await?;
+            ctx.audit_sql(
+                &self.loads_table.quoted().to_string(),
+                &sql,
+                client.execute(&stmt, &[]),
+            )
+            .await
+            .with_context(|_| {
+                format!("error preparing {}", self.loads_table.quoted())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Start a transaction on `client` for loading `chunk_id`. Returns `false`
+    /// (and rolls back) if `chunk_id` has already been loaded.
+    pub(crate) async fn begin_chunk(
+        &self,
+        ctx: &Context,
+        client: &Client,
+        chunk_id: &str,
+    ) -> Result<bool> {
+        client
+            .batch_execute("BEGIN")
+            .await
+            .context("could not start PostgreSQL transaction")?;
+        let sql = format!(
+            "SELECT EXISTS (
+    SELECT 1 FROM {}
+    WHERE table_name = $1 AND load_id = $2 AND chunk_id = $3
+)",
+            self.loads_table.quoted(),
+        );
+        let row = client
+            .query_one(&sql[..], &[&self.dest.unquoted(), &self.load_id, &chunk_id])
+            .await
+            .with_context(|_| {
+                format!("error reading {}", self.loads_table.quoted())
+            })?;
+        let loaded: bool = row.get(0);
+        if loaded {
+            debug!(
+                ctx.log(),
+                "skipping chunk {} (already loaded by {})", chunk_id, self.load_id,
+            );
+            client
+                .batch_execute("ROLLBACK")
+                .await
+                .context("could not roll back PostgreSQL transaction")?;
+        }
+        Ok(!loaded)
+    }
+
+    /// Record that `chunk_id` has been loaded, and commit the transaction
+    /// started by `begin_chunk`. If another copy has loaded `chunk_id` in the
+    /// meantime, our primary key makes this fail, and nothing is committed.
+    pub(crate) async fn commit_chunk(
+        &self,
+        ctx: &Context,
+        client: &Client,
+        chunk_id: &str,
+    ) -> Result<()> {
+        let sql = format!(
+            "INSERT INTO {} (table_name, load_id, chunk_id) VALUES ($1, $2, $3)",
+            self.loads_table.quoted(),
+        );
+        let dest = self.dest.unquoted();
+        ctx.audit_sql(
+            &self.loads_table.quoted().to_string(),
+            &sql,
+            client.execute(&sql[..], &[&dest, &self.load_id, &chunk_id]),
+        )
+        .await
+        .with_context(|_| {
+            format!(
+                "error recording chunk {:?} in {}",
+                chunk_id,
+                self.loads_table.quoted(),
+            )
+        })?;
+        client
+            .batch_execute("COMMIT")
+            .await
+            .context("could not commit PostgreSQL transaction")?;
+        Ok(())
+    }
+}
+
+#[test]
+fn loads_table_is_created_next_to_destination() {
+    let dest = "sales.orders".parse::<TableName>().unwrap();
+    let tracker = LoadTracker::new(&dest, "nightly-2020-06-01");
+    let sql = tracker.prepare_loads_table_sql(&IfExists::Append);
+    assert_eq!(sql.len(), 1);
+    assert!(sql[0]
+        .starts_with("CREATE TABLE IF NOT EXISTS \"sales\".\"_dbcrossbar_loads\" ("));
+    assert!(sql[0].contains("PRIMARY KEY (table_name, load_id, chunk_id)"));
+
+    let dest = "orders".parse::<TableName>().unwrap();
+    let tracker = LoadTracker::new(&dest, "nightly-2020-06-01");
+    let sql = tracker.prepare_loads_table_sql(&IfExists::Overwrite);
+    assert!(sql[0].starts_with("CREATE TABLE IF NOT EXISTS \"_dbcrossbar_loads\" ("));
+    assert_eq!(
+        sql[1],
+        "DELETE FROM \"_dbcrossbar_loads\" WHERE table_name = 'orders'",
+    );
+}
@@ -3,11 +3,9 @@
 use bytes::Bytes;
 use failure::Fail;
 
-use super::PostgresLocator;
+use super::{driver_args::PostgresSourceArguments, PostgresLocator};
 use crate::common::*;
-use crate::drivers::postgres_shared::{
-    connect, CheckCatalog, PgCreateTable, TableName,
-};
+use crate::drivers::postgres_shared::{CheckCatalog, PgCreateTable, TableName};
 
 /// Copy the specified table from the database, returning a `CsvStream`.
 pub(crate) async fn local_data_helper(
@@ -22,6 +20,10 @@ pub(crate) async fn local_data_helper(
 
     // Look up the arguments we'll need.
     let schema = shared_args.schema();
+    let pg_args = source_args
+        .driver_args()
+        .deserialize::<PostgresSourceArguments>()
+        .context("error parsing --from-args")?;
 
     // Set up our logger.
     let ctx = ctx.child(
@@ -34,24 +36,14 @@ pub(crate) async fn local_data_helper(
         table_name.quoted()
     );
 
-    // Try to look up our table schema in the database.
-    let pg_create_table = PgCreateTable::from_pg_catalog_or_default(
-        &ctx,
-        CheckCatalog::Yes,
-        &url,
-        &table_name,
-        schema,
-    )
-    .await?;
+    // Connect to the database, which may be a replica.
+    let (url, conn) = pg_args.connect(&ctx, &url).await?;
 
     // Generate SQL for query.
-    let mut sql_bytes: Vec<u8> = vec![];
-    pg_create_table.write_export_sql(&mut sql_bytes, &source_args)?;
-    let sql = String::from_utf8(sql_bytes).expect("should always be UTF-8");
+    let sql = export_sql(&ctx, &url, &table_name, schema, &source_args).await?;
     debug!(ctx.log(), "export SQL: {}", sql);
 
     // Copy the data out of PostgreSQL as a CSV stream.
-    let conn = connect(&ctx, &url).await?;
     let stmt = conn.prepare(&sql).await?;
     let rdr = conn
         .copy_out(&stmt)
@@ -75,3 +67,27 @@ pub(crate) async fn local_data_helper(
     let box_stream = stream::once(async { Ok(csv_stream) }).boxed();
     Ok(Some(box_stream))
 }
+
+/// Generate the `COPY (SELECT ...) TO STDOUT` SQL we use to export
+/// `table_name`, looking up its actual column types in the database.
+pub(crate) async fn export_sql(
+    ctx: &Context,
+    url: &UrlWithHiddenPassword,
+    table_name: &TableName,
+    schema: &Table,
+    source_args: &SourceArguments<Verified>,
+) -> Result<String> {
+    // Try to look up our table schema in the database.
+    let pg_create_table = PgCreateTable::from_pg_catalog_or_default(
+        ctx,
+        CheckCatalog::Yes,
+        url,
+        table_name,
+        schema,
+    )
+    .await?;
+
+    let mut sql_bytes: Vec<u8> = vec![];
+    pg_create_table.write_export_sql(&mut sql_bytes, source_args)?;
+    Ok(String::from_utf8(sql_bytes).expect("should always be UTF-8"))
+}
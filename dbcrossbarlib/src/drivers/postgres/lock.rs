@@ -0,0 +1,71 @@
+//! Holding `cp --lock` locks using PostgreSQL advisory locks.
+
+use std::fmt;
+
+use super::PostgresLocator;
+use crate::common::*;
+use crate::drivers::postgres_shared::{connect, Client};
+use crate::lock::{check_lock_name, lock_held_error};
+
+/// A session-level advisory lock, which PostgreSQL will release automatically
+/// if our connection dies.
+struct PostgresLock {
+    /// The key we passed to `hashtext` to get our lock ID.
+    key: String,
+    /// The connection holding our lock.
+    client: Client,
+}
+
+impl fmt::Debug for PostgresLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostgresLock")
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+impl DestinationLock for PostgresLock {
+    fn release(self: Box<Self>, ctx: Context) -> BoxFuture<()> {
+        async move {
+            debug!(
+                ctx.log(),
+                "releasing PostgreSQL advisory lock {:?}", self.key
+            );
+            let row = self
+                .client
+                .query_one("SELECT pg_advisory_unlock(hashtext($1))", &[&self.key])
+                .await
+                .context("could not release PostgreSQL advisory lock")?;
+            if row.get::<_, bool>(0) {
+                Ok(())
+            } else {
+                Err(format_err!(
+                    "PostgreSQL advisory lock {:?} was not held",
+                    self.key,
+                ))
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Acquire `name` as an advisory lock on the database in `dest`.
+pub(crate) async fn lock_helper(
+    ctx: Context,
+    dest: PostgresLocator,
+    name: String,
+) -> Result<Option<BoxDestinationLock>> {
+    check_lock_name(&name)?;
+    let key = format!("dbcrossbar:{}", name);
+    debug!(ctx.log(), "acquiring PostgreSQL advisory lock {:?}", key);
+    let client = connect(&ctx, dest.url()).await?;
+    let row = client
+        .query_one("SELECT pg_try_advisory_lock(hashtext($1))", &[&key])
+        .await
+        .context("could not acquire PostgreSQL advisory lock")?;
+    if row.get::<_, bool>(0) {
+        Ok(Some(Box::new(PostgresLock { key, client })))
+    } else {
+        Err(lock_held_error(&name, &dest))
+    }
+}
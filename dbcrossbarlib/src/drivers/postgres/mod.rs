@@ -9,19 +9,35 @@ use std::{
 };
 
 use crate::common::*;
-use crate::drivers::postgres_shared::{Client, PgCreateTable, TableName};
+use crate::drivers::postgres_shared::{Client, PgCreateTable, PgDataType, TableName};
+use crate::limits::{Limits, NameLength};
+use crate::reserved_words;
+use crate::schema::DataType;
 
 mod count;
 mod csv_to_binary;
+mod driver_args;
+mod dry_run;
+mod estimate;
+mod load_tracking;
 mod local_data;
+mod lock;
+mod query_schema;
 mod write_local_data;
+mod write_remote_data;
 
 use self::count::count_helper;
+use self::dry_run::{dry_run_read_helper, dry_run_write_helper};
+use self::estimate::estimate_helper;
 use self::local_data::local_data_helper;
+use self::lock::lock_helper;
+use self::query_schema::query_schema_helper;
 use self::write_local_data::write_local_data_helper;
+use self::write_remote_data::write_remote_data_helper;
 
 pub(crate) use write_local_data::{
     columns_to_update_for_upsert, create_temp_table_for, prepare_table,
+    prepare_table_sql, temp_table_for,
 };
 
 /// A Postgres database URL and a table name.
@@ -121,6 +137,15 @@ impl Locator for PostgresLocator {
         .boxed()
     }
 
+    fn query_schema(
+        &self,
+        ctx: Context,
+        sql: String,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<Table>> {
+        query_schema_helper(ctx, self.to_owned(), sql, source_args).boxed()
+    }
+
     fn count(
         &self,
         ctx: Context,
@@ -130,6 +155,14 @@ impl Locator for PostgresLocator {
         count_helper(ctx, self.to_owned(), shared_args, source_args).boxed()
     }
 
+    fn estimate(
+        &self,
+        ctx: Context,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<Estimate>> {
+        estimate_helper(ctx, self.to_owned(), source_args).boxed()
+    }
+
     fn local_data(
         &self,
         ctx: Context,
@@ -156,6 +189,67 @@ impl Locator for PostgresLocator {
         write_local_data_helper(ctx, self.clone(), data, shared_args, dest_args)
             .boxed()
     }
+
+    fn supports_write_remote_data(&self, source: &dyn Locator) -> bool {
+        // If we're copying from another PostgreSQL table, we can pipe the data
+        // across in `BINARY` format without converting it to CSV.
+        source.as_any().is::<PostgresLocator>()
+    }
+
+    fn write_remote_data(
+        &self,
+        ctx: Context,
+        source: BoxLocator,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+        dest_args: DestinationArguments<Unverified>,
+    ) -> BoxFuture<Vec<BoxLocator>> {
+        write_remote_data_helper(
+            ctx,
+            source,
+            self.to_owned(),
+            shared_args,
+            source_args,
+            dest_args,
+        )
+        .boxed()
+    }
+
+    fn dry_run_read(
+        &self,
+        ctx: Context,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        dry_run_read_helper(ctx, self.to_owned(), shared_args, source_args).boxed()
+    }
+
+    fn dry_run_write(
+        &self,
+        ctx: Context,
+        source: Option<BoxLocator>,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+        dest_args: DestinationArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        dry_run_write_helper(
+            ctx,
+            self.to_owned(),
+            source,
+            shared_args,
+            source_args,
+            dest_args,
+        )
+        .boxed()
+    }
+
+    fn lock(
+        &self,
+        ctx: Context,
+        name: String,
+    ) -> BoxFuture<Option<BoxDestinationLock>> {
+        lock_helper(ctx, self.to_owned(), name).boxed()
+    }
 }
 
 impl LocatorStatic for PostgresLocator {
@@ -166,12 +260,18 @@ impl LocatorStatic for PostgresLocator {
     fn features() -> Features {
         Features {
             locator: LocatorFeatures::Schema
+                | LocatorFeatures::QuerySchema
                 | LocatorFeatures::LocalData
                 | LocatorFeatures::WriteLocalData
-                | LocatorFeatures::Count,
+                | LocatorFeatures::Count
+                | LocatorFeatures::Estimate
+                | LocatorFeatures::Lock,
             write_schema_if_exists: EnumSet::empty(),
-            source_args: SourceArgumentsFeatures::WhereClause.into(),
-            dest_args: EnumSet::empty(),
+            source_args: SourceArgumentsFeatures::DriverArgs
+                | SourceArgumentsFeatures::WhereClause,
+            dest_args: DestinationArgumentsFeatures::DriverArgs
+                | DestinationArgumentsFeatures::SchemaEvolution
+                | DestinationArgumentsFeatures::MatchColumns,
             dest_if_exists: IfExistsFeatures::Overwrite
                 | IfExistsFeatures::Append
                 | IfExistsFeatures::Error
@@ -179,4 +279,23 @@ impl LocatorStatic for PostgresLocator {
             _placeholder: (),
         }
     }
+
+    fn remote_sources() -> &'static [&'static str] {
+        &["postgres:"]
+    }
+
+    fn lossy_data_type(data_type: &DataType) -> Option<String> {
+        PgDataType::lossy_for_data_type(data_type)
+    }
+
+    fn is_reserved_word(name: &str) -> bool {
+        reserved_words::is_reserved(reserved_words::POSTGRES, name)
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            max_columns: Some(1600),
+            max_name_length: Some(NameLength::Bytes(63)),
+        }
+    }
 }
@@ -0,0 +1,48 @@
+//! Implementation of `query_schema`.
+
+use super::PostgresLocator;
+use crate::common::*;
+use crate::drivers::postgres_shared::{
+    connect, pg_data_type_for_type_name, PgColumn, PgCreateTable,
+};
+use crate::schema::Table;
+
+/// Implementation of `query_schema`, but as a real `async` function.
+///
+/// We prepare `sql` without executing it, and ask PostgreSQL to describe the
+/// resulting columns. PostgreSQL can't tell us whether query columns may be
+/// `NULL`, so we assume they all can.
+pub(crate) async fn query_schema_helper(
+    ctx: Context,
+    locator: PostgresLocator,
+    sql: String,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<Table>> {
+    let _source_args = source_args.verify(PostgresLocator::features())?;
+
+    let client = connect(&ctx, locator.url()).await?;
+    let statement = client
+        .prepare(&sql)
+        .await
+        .with_context(|_| format!("could not describe query using {}", locator))?;
+    let columns = statement
+        .columns()
+        .iter()
+        .map(|col| {
+            let data_type = pg_data_type_for_type_name(col.type_().name())
+                .with_context(|_| format!("error in query column {:?}", col.name()))?;
+            Ok(PgColumn {
+                name: col.name().to_owned(),
+                data_type,
+                is_nullable: true,
+            })
+        })
+        .collect::<Result<Vec<PgColumn>>>()?;
+    let pg_table = PgCreateTable {
+        name: locator.table_name().to_owned(),
+        columns,
+        if_not_exists: false,
+        temporary: false,
+    };
+    Ok(Some(pg_table.to_table()?))
+}
@@ -4,14 +4,22 @@ use futures::pin_mut;
 use itertools::Itertools;
 use std::{collections::HashSet, io::prelude::*, iter::FromIterator, str};
 
-use super::{csv_to_binary::copy_csv_to_pg_binary, Client, PostgresLocator};
+use super::{
+    csv_to_binary::copy_csv_to_pg_binary, driver_args::PostgresDestinationArguments,
+    load_tracking::LoadTracker, Client, PostgresLocator,
+};
+use crate::column_matching::ColumnRenames;
 use crate::common::*;
-use crate::drivers::postgres_shared::{connect, CheckCatalog, Ident, PgCreateTable};
-use crate::tokio_glue::try_forward;
+use crate::drivers::postgres_shared::{
+    connect, CheckCatalog, Ident, PgColumn, PgCreateTable, TableName,
+};
+use crate::schema::Column;
+use crate::schema_evolution::SchemaChanges;
+use crate::tokio_glue::{try_forward, ConsumeWithParallelism};
 use crate::transform::spawn_sync_transform;
 
 /// If `table_name` exists, `DROP` it.
-async fn drop_table_if_exists(
+pub(crate) async fn drop_table_if_exists(
     ctx: &Context,
     client: &mut Client,
     table: &PgCreateTable,
@@ -21,14 +29,23 @@ async fn drop_table_if_exists(
         "deleting table {} if exists",
         table.name.quoted(),
     );
-    let drop_sql = format!("DROP TABLE IF EXISTS {}", &table.name.quoted());
+    let drop_sql = drop_table_sql(table);
     let drop_stmt = client.prepare(&drop_sql).await?;
-    client.execute(&drop_stmt, &[]).await.with_context(|_| {
-        format!("error deleting existing {}", table.name.quoted())
-    })?;
+    ctx.audit_sql(
+        &table.name.quoted().to_string(),
+        &drop_sql,
+        client.execute(&drop_stmt, &[]),
+    )
+    .await
+    .with_context(|_| format!("error deleting existing {}", table.name.quoted()))?;
     Ok(())
 }
 
+/// Generate SQL to `DROP` `table` if it exists.
+fn drop_table_sql(table: &PgCreateTable) -> String {
+    format!("DROP TABLE IF EXISTS {}", &table.name.quoted())
+}
+
 /// Run the specified `CREATE TABLE` SQL.
 async fn create_table(
     ctx: &Context,
@@ -39,13 +56,26 @@ async fn create_table(
     let create_sql = format!("{}", table);
     debug!(ctx.log(), "CREATE TABLE SQL: {}", create_sql);
     let create_stmt = client.prepare(&create_sql).await?;
-    client
-        .execute(&create_stmt, &[])
-        .await
-        .with_context(|_| format!("error creating {}", &table.name.quoted()))?;
+    ctx.audit_sql(
+        &table.name.quoted().to_string(),
+        &create_sql,
+        client.execute(&create_stmt, &[]),
+    )
+    .await
+    .with_context(|_| format!("error creating {}", &table.name.quoted()))?;
     Ok(())
 }
 
+/// Build a temporary table based on `table`, but using a different name.
+pub(crate) fn temp_table_for(table: &PgCreateTable) -> Result<PgCreateTable> {
+    let mut temp_table = table.to_owned();
+    let temp_name = table.name.temporary_table_name()?;
+    temp_table.name = temp_name;
+    temp_table.if_not_exists = false;
+    temp_table.temporary = true;
+    Ok(temp_table)
+}
+
 /// Create a temporary table based on `table`, but using a different name. This
 /// table will only live as long as the `client`.
 pub(crate) async fn create_temp_table_for(
@@ -53,11 +83,7 @@ pub(crate) async fn create_temp_table_for(
     client: &mut Client,
     table: &PgCreateTable,
 ) -> Result<PgCreateTable> {
-    let mut temp_table = table.to_owned();
-    let temp_name = table.name.temporary_table_name()?;
-    temp_table.name = temp_name;
-    temp_table.if_not_exists = false;
-    temp_table.temporary = true;
+    let temp_table = temp_table_for(table)?;
     create_table(ctx, client, &temp_table).await?;
     Ok(temp_table)
 }
@@ -70,12 +96,40 @@ pub(crate) async fn create_temp_table_for(
 pub(crate) async fn prepare_table(
     ctx: &Context,
     client: &mut Client,
-    mut table: PgCreateTable,
+    table: PgCreateTable,
     if_exists: &IfExists,
 ) -> Result<()> {
+    let (drop_first, table) = table_for_if_exists(table, if_exists);
+    if drop_first {
+        drop_table_if_exists(ctx, client, &table).await?;
+    }
+    create_table(ctx, client, &table).await
+}
+
+/// Generate the SQL that `prepare_table` would run.
+pub(crate) fn prepare_table_sql(
+    table: PgCreateTable,
+    if_exists: &IfExists,
+) -> Vec<String> {
+    let (drop_first, table) = table_for_if_exists(table, if_exists);
+    let mut sql = vec![];
+    if drop_first {
+        sql.push(drop_table_sql(&table));
+    }
+    sql.push(format!("{}", table));
+    sql
+}
+
+/// Adjust `table` to match `if_exists`, and decide whether we need to `DROP`
+/// it before we create it.
+fn table_for_if_exists(
+    mut table: PgCreateTable,
+    if_exists: &IfExists,
+) -> (bool, PgCreateTable) {
+    let mut drop_first = false;
     match if_exists {
         IfExists::Overwrite => {
-            drop_table_if_exists(ctx, client, &table).await?;
+            drop_first = true;
             table.if_not_exists = false;
         }
         IfExists::Append => {
@@ -97,7 +151,105 @@ pub(crate) async fn prepare_table(
             table.if_not_exists = true;
         }
     }
-    create_table(ctx, client, &table).await
+    (drop_first, table)
+}
+
+/// Generate `ALTER TABLE` SQL adding `columns` to `table_name`.
+pub(crate) fn add_columns_sql(
+    table_name: &TableName,
+    columns: &[Column],
+) -> Result<String> {
+    let add_columns = columns
+        .iter()
+        .map(|c| {
+            Ok(format!(
+                "ADD COLUMN IF NOT EXISTS {}",
+                PgColumn::from_column(c)?
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(format!(
+        "ALTER TABLE {}
+    {}",
+        table_name.quoted(),
+        add_columns.join(",\n    "),
+    ))
+}
+
+/// If `table_name` already exists, compare it to `schema` and decide what
+/// `schema_evolution` would change, without changing anything.
+pub(crate) async fn plan_table_evolution(
+    ctx: &Context,
+    url: &UrlWithHiddenPassword,
+    table_name: &TableName,
+    schema: &Table,
+    schema_evolution: SchemaEvolution,
+) -> Result<SchemaChanges> {
+    // With `fail`, we just report any missing columns when we look up the
+    // destination table.
+    if schema_evolution == SchemaEvolution::Fail {
+        return Ok(SchemaChanges::default());
+    }
+    let existing = match PgCreateTable::from_pg_catalog(ctx, url, table_name).await? {
+        Some(existing) => existing,
+        None => return Ok(SchemaChanges::default()),
+    };
+    schema_evolution.plan(schema, &table_name.quoted().to_string(), |name| {
+        existing.columns.iter().any(|c| c.name == name)
+    })
+}
+
+/// If `table_name` already exists, compare it to `schema` and apply
+/// `schema_evolution`, adding any missing columns. The caller is responsible
+/// for leaving out any ignored columns.
+pub(crate) async fn evolve_table(
+    ctx: &Context,
+    url: &UrlWithHiddenPassword,
+    table_name: &TableName,
+    schema: &Table,
+    schema_evolution: SchemaEvolution,
+) -> Result<SchemaChanges> {
+    let changes =
+        plan_table_evolution(ctx, url, table_name, schema, schema_evolution).await?;
+    if !changes.add_columns.is_empty() {
+        let sql = add_columns_sql(table_name, &changes.add_columns)?;
+        debug!(ctx.log(), "adding columns: {}", sql);
+        let client = connect(ctx, url).await?;
+        let stmt = client.prepare(&sql).await?;
+        ctx.audit_sql(
+            &table_name.quoted().to_string(),
+            &sql,
+            client.execute(&stmt, &[]),
+        )
+        .await
+        .with_context(|_| {
+            format!("error adding columns to {}", table_name.quoted())
+        })?;
+    }
+    Ok(changes)
+}
+
+/// If `table_name` already exists, decide which columns in `schema` need to
+/// be renamed to match its columns using `column_matching`.
+pub(crate) async fn match_table_columns(
+    ctx: &Context,
+    url: &UrlWithHiddenPassword,
+    table_name: &TableName,
+    schema: &Table,
+    column_matching: ColumnMatching,
+) -> Result<ColumnRenames> {
+    if column_matching == ColumnMatching::Exact {
+        return Ok(ColumnRenames::default());
+    }
+    let existing = match PgCreateTable::from_pg_catalog(ctx, url, table_name).await? {
+        Some(existing) => existing,
+        None => return Ok(ColumnRenames::default()),
+    };
+    column_matching.plan(
+        schema,
+        &table_name.quoted().to_string(),
+        existing.columns.iter().map(|c| &c.name[..]),
+    )
 }
 
 /// Generate the `COPY ... FROM ...` SQL we'll pass to `copy_in`. `data_format`
@@ -122,10 +274,28 @@ fn copy_from_sql(table: &PgCreateTable, data_format: &str) -> Result<String> {
     Ok(copy_sql)
 }
 
-/// Given `stream` containing CSV data, plus a the URL and table_name for a
-/// destination, as well as `"COPY FROM"` SQL, copy the data into the specified
-/// destination.
-async fn copy_from_stream<'a>(
+/// Generate the SQL that we run to copy each `BINARY` data stream into
+/// `dest_table`.
+pub(crate) fn copy_stream_sql(
+    dest_table: &PgCreateTable,
+    if_exists: &IfExists,
+) -> Result<Vec<String>> {
+    if let IfExists::Upsert(cols) = if_exists {
+        let temp_table = temp_table_for(dest_table)?;
+        Ok(vec![
+            format!("{}", temp_table),
+            copy_from_sql(&temp_table, "BINARY")?,
+            upsert_sql(&temp_table, dest_table, cols)?,
+            drop_table_sql(&temp_table),
+        ])
+    } else {
+        Ok(vec![copy_from_sql(dest_table, "BINARY")?])
+    }
+}
+
+/// Given `stream` containing PostgreSQL `BINARY` data, plus a the URL and
+/// table_name for a destination, copy the data into the specified destination.
+pub(crate) async fn copy_from_stream<'a>(
     ctx: &'a Context,
     client: &'a mut Client,
     dest: &'a PgCreateTable,
@@ -134,15 +304,21 @@ async fn copy_from_stream<'a>(
     debug!(ctx.log(), "copying data into {:?}", dest.name);
     let copy_from_sql = copy_from_sql(&dest, "BINARY")?;
     let stmt = client.prepare(&copy_from_sql).await?;
-    let sink = client
-        .copy_in::<_, BytesMut>(&stmt)
-        .await
-        .with_context(|_| format!("error copying data into {}", dest.name.quoted()))?;
+    let copy = async {
+        let sink = client
+            .copy_in::<_, BytesMut>(&stmt)
+            .await
+            .with_context(|_| {
+                format!("error copying data into {}", dest.name.quoted())
+            })?;
 
-    // `CopyInSink` is a weird sink, and we have to "pin" it directly into our
-    // stack in order to forward data to it.
-    pin_mut!(sink);
-    try_forward(ctx, stream, sink).await?;
+        // `CopyInSink` is a weird sink, and we have to "pin" it directly into
+        // our stack in order to forward data to it.
+        pin_mut!(sink);
+        try_forward(ctx, stream, sink).await
+    };
+    ctx.audit_sql(&dest.name.quoted().to_string(), &copy_from_sql, copy)
+        .await?;
     Ok(())
 }
 
@@ -224,7 +400,13 @@ pub(crate) async fn upsert_from(
         sql,
     );
     let stmt = client.prepare(&sql).await?;
-    client.execute(&stmt, &[]).await.with_context(|_| {
+    ctx.audit_sql(
+        &dest_table.name.quoted().to_string(),
+        &sql,
+        client.execute(&stmt, &[]),
+    )
+    .await
+    .with_context(|_| {
         format!(
             "error upserting from {} to {}",
             src_table.name.quoted(),
@@ -239,7 +421,7 @@ pub(crate) async fn upsert_from(
 pub(crate) async fn write_local_data_helper(
     ctx: Context,
     dest: PostgresLocator,
-    mut data: BoxStream<CsvStream>,
+    data: BoxStream<CsvStream>,
     shared_args: SharedArguments<Unverified>,
     dest_args: DestinationArguments<Unverified>,
 ) -> Result<BoxStream<BoxFuture<BoxLocator>>> {
@@ -249,8 +431,12 @@ pub(crate) async fn write_local_data_helper(
     // Look up our arguments.
     let schema = shared_args.schema();
     let if_exists = dest_args.if_exists().to_owned();
+    let pg_args = dest_args
+        .driver_args()
+        .deserialize::<PostgresDestinationArguments>()
+        .context("error parsing --to-args")?;
 
-    let url = dest.url.clone();
+    let url = pg_args.url(dest.url());
     let table_name = dest.table_name.clone();
     let ctx = ctx.child(o!("table" => table_name.unquoted()));
     debug!(
@@ -260,86 +446,158 @@ pub(crate) async fn write_local_data_helper(
         table_name.quoted(),
     );
 
+    // Rename our columns to match an existing destination table, if asked.
+    let renames = match_table_columns(
+        &ctx,
+        &url,
+        dest.table_name(),
+        schema,
+        dest_args.match_columns(),
+    )
+    .await?;
+    let data = renames.apply_to_data(&ctx, schema, data);
+    let schema = renames.apply_to_schema(schema);
+    let if_exists = renames.apply_to_if_exists(&if_exists);
+
+    // Update an existing destination table to match our schema, if asked.
+    let changes = evolve_table(
+        &ctx,
+        &url,
+        dest.table_name(),
+        &schema,
+        dest_args.schema_evolution(),
+    )
+    .await?;
+    let data = changes.apply_to_data(&ctx, &schema, data);
+    let schema = changes.apply_to_schema(&schema);
+
     // Try to look up our destination table schema in the database.
     let dest_table = PgCreateTable::from_pg_catalog_or_default(
         &ctx,
         CheckCatalog::from(&if_exists),
-        dest.url(),
+        &url,
         dest.table_name(),
-        schema,
+        &schema,
     )
     .await?;
 
-    // Connect to PostgreSQL and prepare our destination table.
+    // Connect to PostgreSQL and prepare our destination table, and our loads
+    // table if we're tracking which chunks we've loaded.
     let mut client = connect(&ctx, &url).await?;
     prepare_table(&ctx, &mut client, dest_table.clone(), &if_exists).await?;
+    let tracker = pg_args
+        .load_id()
+        .map(|load_id| LoadTracker::new(dest.table_name(), load_id));
+    if let Some(tracker) = &tracker {
+        tracker
+            .prepare_loads_table(&ctx, &client, &if_exists)
+            .await?;
+    }
+    drop(client);
 
-    // Insert data streams one at a time, because parallel insertion _probably_
-    // won't gain much with Postgres (but we haven't measured).
-    let fut = async move {
-        while let Some(result) = data.next().await {
-            match result {
-                Err(err) => {
-                    debug!(ctx.log(), "error reading stream of streams: {}", err);
-                    return Err(err);
-                }
-                Ok(csv_stream) => {
-                    let ctx = ctx.child(o!("stream" => csv_stream.name.clone()));
-
-                    // Convert our CSV stream into a PostgreSQL `BINARY` stream.
-                    let transform_table = dest_table.clone();
-                    let binary_stream = spawn_sync_transform(
-                        ctx.clone(),
-                        "copy_csv_to_pg_binary".to_owned(),
-                        csv_stream.data,
-                        move |_ctx, rdr, wtr| {
-                            copy_csv_to_pg_binary(&transform_table, rdr, wtr)
-                        },
-                    )?;
-
-                    // Decide whether to do an upsert or regular insert.
-                    if let IfExists::Upsert(cols) = &if_exists {
-                        // Create temp table.
-                        let temp_table =
-                            create_temp_table_for(&ctx, &mut client, &dest_table)
-                                .await?;
-
-                        // Copy into temp table.
-                        copy_from_stream(
-                            &ctx,
-                            &mut client,
-                            &temp_table,
-                            binary_stream,
-                        )
-                        .await?;
-
-                        // Upsert from temp table into dest.
-                        upsert_from(
-                            &ctx,
-                            &mut client,
-                            &temp_table,
-                            &dest_table,
-                            &cols,
-                        )
-                        .await?;
-
-                        // Delete temp table (which always exists, but we can
-                        // re-use this function).
-                        drop_table_if_exists(&ctx, &mut client, &temp_table).await?;
-                    } else {
-                        // Copy directly into dest.
-                        copy_from_stream(
-                            &ctx,
-                            &mut client,
-                            &dest_table,
-                            binary_stream,
-                        )
-                        .await?;
-                    }
-                }
-            }
+    // Copy each data stream using its own connection, so that we can run up
+    // to `--max-streams` `COPY` statements at once.
+    let parallelism = copy_parallelism(&if_exists, shared_args.max_streams());
+    let copies = data.map_ok(move |csv_stream| {
+        let ctx = ctx.child(o!("stream" => csv_stream.name.clone()));
+        let url = url.clone();
+        let dest_table = dest_table.clone();
+        let if_exists = if_exists.clone();
+        let tracker = tracker.clone();
+        async move {
+            ctx.check_cancelled()?;
+            copy_csv_stream(
+                &ctx,
+                &url,
+                &dest_table,
+                &if_exists,
+                tracker.as_ref(),
+                csv_stream,
+            )
+            .await
         }
+        .boxed()
+    });
+    let fut = async move {
+        copies.boxed().consume_with_parallelism(parallelism).await?;
         Ok(dest.boxed())
     };
     Ok(box_stream_once(Ok(fut.boxed())))
 }
+
+/// How many streams should we copy into PostgreSQL at once?
+///
+/// We can safely run several `COPY` statements in parallel. But concurrent
+/// upserts into the same table may deadlock on its unique index, and if two
+/// streams contain the same key, there would be no way to know which one wins.
+/// So we upsert one stream at a time, in the order we receive them.
+fn copy_parallelism(if_exists: &IfExists, max_streams: usize) -> usize {
+    match if_exists {
+        IfExists::Upsert(_) => 1,
+        _ => max_streams,
+    }
+}
+
+#[test]
+fn upserts_one_stream_at_a_time() {
+    assert_eq!(copy_parallelism(&IfExists::Append, 4), 4);
+    assert_eq!(copy_parallelism(&IfExists::Overwrite, 4), 4);
+    assert_eq!(
+        copy_parallelism(&IfExists::Upsert(vec!["id".to_owned()]), 4),
+        1,
+    );
+}
+
+/// Copy `csv_stream` into `dest_table` using a new connection to `url`. If we
+/// have a `tracker`, we skip streams which have already been loaded, and record
+/// this stream as loaded in the same transaction as our copy.
+async fn copy_csv_stream(
+    ctx: &Context,
+    url: &UrlWithHiddenPassword,
+    dest_table: &PgCreateTable,
+    if_exists: &IfExists,
+    tracker: Option<&LoadTracker>,
+    csv_stream: CsvStream,
+) -> Result<()> {
+    let mut client = connect(ctx, url).await?;
+    let chunk_id = csv_stream.name.clone();
+    if let Some(tracker) = tracker {
+        if !tracker.begin_chunk(ctx, &client, &chunk_id).await? {
+            // Read the stream to the end, because some sources produce their
+            // streams one after another.
+            return csv_stream.data.try_for_each(|_| async { Ok(()) }).await;
+        }
+    }
+
+    // Convert our CSV stream into a PostgreSQL `BINARY` stream.
+    let transform_table = dest_table.clone();
+    let binary_stream = spawn_sync_transform(
+        ctx.clone(),
+        "copy_csv_to_pg_binary".to_owned(),
+        csv_stream.data,
+        move |_ctx, rdr, wtr| copy_csv_to_pg_binary(&transform_table, rdr, wtr),
+    )?;
+
+    // Decide whether to do an upsert or regular insert.
+    if let IfExists::Upsert(cols) = if_exists {
+        // Create temp table. This is only visible to our own connection.
+        let temp_table = create_temp_table_for(ctx, &mut client, dest_table).await?;
+
+        // Copy into temp table.
+        copy_from_stream(ctx, &mut client, &temp_table, binary_stream).await?;
+
+        // Upsert from temp table into dest.
+        upsert_from(ctx, &mut client, &temp_table, dest_table, cols).await?;
+
+        // Delete temp table (which always exists, but we can re-use this
+        // function).
+        drop_table_if_exists(ctx, &mut client, &temp_table).await?;
+    } else {
+        // Copy directly into dest.
+        copy_from_stream(ctx, &mut client, dest_table, binary_stream).await?;
+    }
+    if let Some(tracker) = tracker {
+        tracker.commit_chunk(ctx, &client, &chunk_id).await?;
+    }
+    Ok(())
+}
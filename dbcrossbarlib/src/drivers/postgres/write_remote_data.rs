@@ -0,0 +1,171 @@
+//! Implementation of `PostgresLocator::write_remote_data`.
+
+use bytes::Bytes;
+use failure::Fail;
+
+use super::{
+    create_temp_table_for,
+    driver_args::{PostgresDestinationArguments, PostgresSourceArguments},
+    load_tracking::LoadTracker,
+    prepare_table,
+    write_local_data::{
+        copy_from_stream, drop_table_if_exists, evolve_table, match_table_columns,
+        upsert_from,
+    },
+    PostgresLocator,
+};
+use crate::common::*;
+use crate::drivers::postgres_shared::{connect, CheckCatalog, PgCreateTable};
+
+/// Copy `source` to `dest`, streaming PostgreSQL's `BINARY` format straight from
+/// `COPY TO` on the source into `COPY FROM` on the destination. This avoids
+/// converting the data to CSV and back.
+pub(crate) async fn write_remote_data_helper(
+    ctx: Context,
+    source: BoxLocator,
+    dest: PostgresLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<BoxLocator>> {
+    let source = source
+        .as_any()
+        .downcast_ref::<PostgresLocator>()
+        .ok_or_else(|| format_err!("not a postgres:// locator: {}", source))?
+        .to_owned();
+
+    // Verify our arguments.
+    let shared_args = shared_args.verify(PostgresLocator::features())?;
+    let source_args = source_args.verify(PostgresLocator::features())?;
+    let dest_args = dest_args.verify(PostgresLocator::features())?;
+
+    // Look up the arguments we'll need.
+    let schema = shared_args.schema();
+    let pg_args = source_args
+        .driver_args()
+        .deserialize::<PostgresSourceArguments>()
+        .context("error parsing --from-args")?;
+    let dest_pg_args = dest_args
+        .driver_args()
+        .deserialize::<PostgresDestinationArguments>()
+        .context("error parsing --to-args")?;
+    let dest_url = dest_pg_args.url(dest.url());
+    let if_exists = dest_args.if_exists().to_owned();
+
+    let ctx = ctx.child(o!(
+        "source_table" => source.table_name().unquoted(),
+        "table" => dest.table_name().unquoted(),
+    ));
+    debug!(
+        ctx.log(),
+        "copying {} table {} to {} table {} using BINARY format",
+        source.url(),
+        source.table_name().quoted(),
+        dest.url(),
+        dest.table_name().quoted(),
+    );
+
+    // Rename our columns to match an existing destination table, if asked. We
+    // still read each column from the source using its original name.
+    let renames = match_table_columns(
+        &ctx,
+        &dest_url,
+        dest.table_name(),
+        schema,
+        dest_args.match_columns(),
+    )
+    .await?;
+    let schema = renames.apply_to_schema(schema);
+    let if_exists = renames.apply_to_if_exists(&if_exists);
+
+    // Update an existing destination table to match our schema, if asked. We
+    // only export the columns we keep, so there's no data to filter.
+    let changes = evolve_table(
+        &ctx,
+        &dest_url,
+        dest.table_name(),
+        &schema,
+        dest_args.schema_evolution(),
+    )
+    .await?;
+    let schema = changes.apply_to_schema(&schema);
+
+    // Look up our destination table schema. We cast all our source columns to
+    // these types, because `BINARY` data can only be loaded into columns of
+    // exactly the same type.
+    let dest_table = PgCreateTable::from_pg_catalog_or_default(
+        &ctx,
+        CheckCatalog::from(&if_exists),
+        &dest_url,
+        dest.table_name(),
+        &schema,
+    )
+    .await?;
+
+    // Generate SQL for our export.
+    let mut sql_bytes: Vec<u8> = vec![];
+    dest_table.write_binary_export_sql(
+        &mut sql_bytes,
+        source.table_name(),
+        &renames,
+        &source_args,
+    )?;
+    let sql = String::from_utf8(sql_bytes).expect("should always be UTF-8");
+    debug!(ctx.log(), "export SQL: {}", sql);
+
+    // Prepare our destination table.
+    let mut dest_client = connect(&ctx, &dest_url).await?;
+    prepare_table(&ctx, &mut dest_client, dest_table.clone(), &if_exists).await?;
+
+    // If we're tracking loads, we treat the whole source table as one chunk.
+    let tracker = dest_pg_args
+        .load_id()
+        .map(|load_id| LoadTracker::new(dest.table_name(), load_id));
+    let chunk_id = source.table_name().unquoted();
+    if let Some(tracker) = &tracker {
+        tracker
+            .prepare_loads_table(&ctx, &dest_client, &if_exists)
+            .await?;
+        if !tracker.begin_chunk(&ctx, &dest_client, &chunk_id).await? {
+            return Ok(vec![dest.boxed()]);
+        }
+    }
+
+    // Start copying data out of our source. This data never passes through a
+    // `CsvStream`, so we report our own progress.
+    let (_source_url, source_client) = pg_args.connect(&ctx, source.url()).await?;
+    let stmt = source_client.prepare(&sql).await?;
+    let read_ctx = ctx.clone();
+    let progress = ctx.progress_counter(&source.table_name().unquoted());
+    let read_progress = progress.clone();
+    let binary_stream = source_client
+        .copy_out(&stmt)
+        .await
+        .map_err(|err| -> Error {
+            err.context("error querying PostgreSQL for data").into()
+        })?
+        .map_ok(move |bytes: Bytes| -> BytesMut {
+            trace!(read_ctx.log(), "read {} bytes", bytes.len());
+            read_progress.add_bytes(bytes.len() as u64);
+            bytes.as_ref().into()
+        })
+        .map_err(|err| err.context("error reading data from PostgreSQL").into())
+        .boxed();
+
+    // Write it into our destination table.
+    if let IfExists::Upsert(cols) = &if_exists {
+        let temp_table =
+            create_temp_table_for(&ctx, &mut dest_client, &dest_table).await?;
+        copy_from_stream(&ctx, &mut dest_client, &temp_table, binary_stream).await?;
+        upsert_from(&ctx, &mut dest_client, &temp_table, &dest_table, cols).await?;
+        drop_table_if_exists(&ctx, &mut dest_client, &temp_table).await?;
+    } else {
+        copy_from_stream(&ctx, &mut dest_client, &dest_table, binary_stream).await?;
+    }
+    progress.finish();
+    if let Some(tracker) = &tracker {
+        tracker.commit_chunk(&ctx, &dest_client, &chunk_id).await?;
+    }
+
+    Ok(vec![dest.boxed()])
+}
@@ -248,6 +248,33 @@ fn pg_data_type(
     }
 }
 
+/// Choose an appropriate `DataType` using PostgreSQL's internal type name, such
+/// as `int8` or `_text`. This is what we see when describing a query instead of
+/// looking up a table in `information_schema`.
+pub(crate) fn pg_data_type_for_type_name(type_name: &str) -> Result<PgDataType> {
+    let data_type = match type_name {
+        _ if type_name.starts_with('_') => "ARRAY",
+        "bool" => "boolean",
+        "bpchar" => "character",
+        "citext" => "USER-DEFINED",
+        "float4" => "real",
+        "float8" => "double precision",
+        "geometry" => {
+            return Err(format_err!(
+                "cannot determine SRID of geometry columns in a query"
+            ))
+        }
+        "int2" => "smallint",
+        "int4" => "integer",
+        "int8" => "bigint",
+        "timestamp" => "timestamp without time zone",
+        "timestamptz" => "timestamp with time zone",
+        "varchar" => "character varying",
+        other => other,
+    };
+    pg_data_type(data_type, "pg_catalog", type_name)
+}
+
 #[test]
 fn parsing_pg_data_type() {
     let array = |ty| PgDataType::Array {
@@ -359,5 +386,7 @@ fn parsing_pg_data_type() {
             &pg_data_type(data_type, udt_schema, udt_name).unwrap(),
             expected,
         );
+        assert_eq!(&pg_data_type_for_type_name(udt_name).unwrap(), expected);
     }
+    assert!(pg_data_type_for_type_name("geometry").is_err());
 }
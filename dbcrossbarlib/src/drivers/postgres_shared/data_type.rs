@@ -46,6 +46,18 @@ impl PgDataType {
         }
     }
 
+    /// If PostgreSQL can't store `ty` without losing information, describe
+    /// what we'll store instead.
+    pub(crate) fn lossy_for_data_type(ty: &DataType) -> Option<String> {
+        match ty {
+            DataType::Struct(_) => Some(
+                "jsonb, which doesn't keep the types of the struct's fields"
+                    .to_owned(),
+            ),
+            _ => None,
+        }
+    }
+
     /// Convert this `PgDataType` to a portable `DataType`.
     pub(crate) fn to_data_type(&self) -> Result<DataType> {
         match self {
@@ -1,8 +1,6 @@
 //! Code shared between various PostgreSQL-related drivers.
 
 use failure::Fail;
-use native_tls::TlsConnector;
-use postgres_native_tls::MakeTlsConnector;
 use std::{fmt, str::FromStr};
 pub use tokio_postgres::Client;
 use tokio_postgres::Config;
@@ -14,6 +12,7 @@ mod column;
 mod data_type;
 mod table;
 
+pub(crate) use self::catalog::pg_data_type_for_type_name;
 pub(crate) use self::column::PgColumn;
 pub(crate) use self::data_type::{PgDataType, PgScalarDataType};
 pub(crate) use self::table::{CheckCatalog, PgCreateTable};
@@ -29,11 +28,8 @@ pub(crate) async fn connect(
     // Build a basic config from our URL args.
     let config = Config::from_str(base_url.with_password().as_str())
         .context("could not configure PostgreSQL connection")?;
-    let tls_connector = TlsConnector::builder()
-        .build()
-        .context("could not build PostgreSQL TLS connector")?;
     let (client, connection) = config
-        .connect(MakeTlsConnector::new(tls_connector))
+        .connect(make_tls_connector()?)
         .await
         .context("could not connect to PostgreSQL")?;
 
@@ -47,6 +43,26 @@ pub(crate) async fn connect(
     Ok(client)
 }
 
+/// Build a TLS connector using the operating system's TLS library.
+#[cfg(feature = "tls-native")]
+fn make_tls_connector() -> Result<postgres_native_tls::MakeTlsConnector> {
+    let tls_connector = native_tls::TlsConnector::builder()
+        .build()
+        .context("could not build PostgreSQL TLS connector")?;
+    Ok(postgres_native_tls::MakeTlsConnector::new(tls_connector))
+}
+
+/// Build a TLS connector using `rustls`, with the same certificates as our
+/// HTTPS clients.
+#[cfg(all(feature = "tls-rustls", not(feature = "tls-native")))]
+fn make_tls_connector() -> Result<tokio_postgres_rustls::MakeRustlsConnect> {
+    use crate::clouds::http_client::rustls_client_config;
+
+    let config =
+        rustls_client_config().context("could not build PostgreSQL TLS connector")?;
+    Ok(tokio_postgres_rustls::MakeRustlsConnect::new(config))
+}
+
 /// Escape and quote a PostgreSQL string literal. See the [docs][]. We need this
 /// because PostgreSQL doesn't accept `$1`-style escapes in certain places in
 /// its SQL grammar.
@@ -3,7 +3,8 @@
 use itertools::Itertools;
 use std::{collections::HashMap, fmt, iter::FromIterator, sync::Arc};
 
-use super::{catalog, PgColumn, TableName};
+use super::{catalog, Ident, PgColumn, TableName};
+use crate::column_matching::ColumnRenames;
 use crate::common::*;
 use crate::parse_error::{Annotation, FileInfo, ParseError};
 use crate::schema::Column;
@@ -207,6 +208,41 @@ impl PgCreateTable {
         Ok(())
     }
 
+    /// Write a `COPY (SELECT ...) TO STDOUT WITH BINARY` statement which reads
+    /// rows from `source_table_name`, casting each column to the type used by
+    /// this table. The output can be passed directly to `COPY ... FROM STDIN
+    /// WITH BINARY` for this table, without any further conversion. Columns
+    /// in `renames` are read using their original source names.
+    pub(crate) fn write_binary_export_sql(
+        &self,
+        f: &mut dyn Write,
+        source_table_name: &TableName,
+        renames: &ColumnRenames,
+        source_args: &SourceArguments<Verified>,
+    ) -> Result<()> {
+        if self.columns.is_empty() {
+            return Err(format_err!("cannot export 0 columns"));
+        }
+        write!(f, "COPY (SELECT ")?;
+        let mut sep = Separator::new(",");
+        for col in &self.columns {
+            write!(
+                f,
+                "{sep}{source}::{ty} AS {name}",
+                sep = sep.display(),
+                source = Ident(renames.source_name(&col.name)),
+                name = Ident(&col.name),
+                ty = col.data_type,
+            )?;
+        }
+        write!(f, " FROM {}", source_table_name.quoted())?;
+        if let Some(where_clause) = source_args.where_clause() {
+            write!(f, " WHERE ({})", where_clause)?;
+        }
+        write!(f, ") TO STDOUT WITH BINARY")?;
+        Ok(())
+    }
+
     /// Write a `SELECT ...` statement for this table.
     pub(crate) fn write_export_select_sql(
         &self,
@@ -363,4 +399,48 @@ mod test {
         let parsed_again = pg_parsed_again.to_table().unwrap();
         assert_eq!(parsed_again, expected);
     }
+
+    #[test]
+    fn binary_export_sql_casts_to_dest_types() {
+        let dest_table = PgCreateTable::from_name_and_columns(
+            "dest".parse::<TableName>().unwrap(),
+            &[
+                Column {
+                    name: "id".to_string(),
+                    is_nullable: false,
+                    data_type: DataType::Int64,
+                    comment: None,
+                },
+                Column {
+                    name: "tags".to_string(),
+                    is_nullable: true,
+                    data_type: DataType::Array(Box::new(DataType::Text)),
+                    comment: None,
+                },
+            ],
+        )
+        .unwrap();
+        let source_args = SourceArguments::new(
+            DriverArguments::default(),
+            Some("id > 1".to_owned()),
+        )
+        .verify(Features {
+            source_args: SourceArgumentsFeatures::WhereClause.into(),
+            ..Features::empty()
+        })
+        .unwrap();
+        let mut out = vec![];
+        dest_table
+            .write_binary_export_sql(
+                &mut out,
+                &"public.src".parse::<TableName>().unwrap(),
+                &ColumnRenames::default(),
+                &source_args,
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"COPY (SELECT "id"::bigint AS "id","tags"::text[] AS "tags" FROM "public"."src" WHERE (id > 1)) TO STDOUT WITH BINARY"#,
+        );
+    }
 }
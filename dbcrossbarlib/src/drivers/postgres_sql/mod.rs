@@ -8,7 +8,10 @@ use std::{
 };
 
 use crate::common::*;
-use crate::drivers::postgres_shared::{PgCreateTable, TableName};
+use crate::drivers::postgres_shared::{PgCreateTable, PgDataType, TableName};
+use crate::limits::{Limits, NameLength};
+use crate::reserved_words;
+use crate::schema::DataType;
 
 /// An SQL file containing a `CREATE TABLE` statement using Postgres syntax.
 #[derive(Clone, Debug)]
@@ -65,6 +68,21 @@ impl LocatorStatic for PostgresSqlLocator {
             _placeholder: (),
         }
     }
+
+    fn lossy_data_type(data_type: &DataType) -> Option<String> {
+        PgDataType::lossy_for_data_type(data_type)
+    }
+
+    fn is_reserved_word(name: &str) -> bool {
+        reserved_words::is_reserved(reserved_words::POSTGRES, name)
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            max_columns: Some(1600),
+            max_name_length: Some(NameLength::Bytes(63)),
+        }
+    }
 }
 
 /// Implementation of `schema`, but as a real `async` function.
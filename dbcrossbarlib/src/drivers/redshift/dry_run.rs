@@ -0,0 +1,89 @@
+//! Implementation of `RedshiftLocator::dry_run_read` and `dry_run_write`.
+
+use super::{
+    write_remote_data::{copy_sql, upsert_sql, VerifyRedshiftCanImportFromCsv},
+    RedshiftLocator,
+};
+use crate::common::*;
+use crate::drivers::{
+    postgres::{prepare_table_sql, temp_table_for},
+    postgres_shared::{CheckCatalog, PgCreateTable},
+    s3::{find_s3_temp_dir, S3Locator},
+};
+
+/// Describe how `local_data` would unload our table to S3 and download it.
+pub(crate) async fn dry_run_read_helper(
+    ctx: Context,
+    source: RedshiftLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Vec<String>> {
+    let shared_args_v = shared_args.clone().verify(RedshiftLocator::features())?;
+    let s3_temp = find_s3_temp_dir(shared_args_v.temporary_storage())?;
+    let mut sql = s3_temp
+        .dry_run_write(
+            ctx,
+            Some(Box::new(source)),
+            shared_args,
+            source_args,
+            DestinationArguments::for_temporary(),
+        )
+        .await?;
+    sql.push(format!("-- download CSV files from {}", s3_temp));
+    Ok(sql)
+}
+
+/// Describe the SQL that `write_local_data` would run, or that
+/// `write_remote_data` would run if we have a `source`.
+pub(crate) async fn dry_run_write_helper(
+    ctx: Context,
+    dest: RedshiftLocator,
+    source: Option<BoxLocator>,
+    shared_args: SharedArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<String>> {
+    let shared_args = shared_args.verify(RedshiftLocator::features())?;
+    let dest_args = dest_args.verify(RedshiftLocator::features())?;
+
+    // Figure out where we'd load our CSV files from.
+    let mut sql = vec![];
+    let source_url = match source {
+        Some(source) => source
+            .as_any()
+            .downcast_ref::<S3Locator>()
+            .ok_or_else(|| format_err!("not a s3:// locator: {}", source))?
+            .as_url()
+            .to_owned(),
+        None => {
+            let s3_temp = find_s3_temp_dir(shared_args.temporary_storage())?;
+            sql.push(format!("-- upload CSV files to {}", s3_temp));
+            s3_temp.as_url().to_owned()
+        }
+    };
+
+    // Look up our destination table, exactly as `write_remote_data` would.
+    let schema = shared_args.schema();
+    let if_exists = dest_args.if_exists().to_owned();
+    schema.verify_redshift_can_import_from_csv()?;
+    let pg_create_table = PgCreateTable::from_pg_catalog_or_default(
+        &ctx,
+        CheckCatalog::from(&if_exists),
+        dest.url(),
+        dest.table_name(),
+        schema,
+    )
+    .await?;
+
+    // Never include our credentials.
+    let credentials = "-- credentials omitted\n";
+    sql.extend(prepare_table_sql(pg_create_table.clone(), &if_exists));
+    if let IfExists::Upsert(upsert_keys) = &if_exists {
+        let temp_table = temp_table_for(&pg_create_table)?;
+        sql.push(format!("{}", temp_table));
+        sql.push(copy_sql(&temp_table, &source_url, credentials));
+        sql.extend(upsert_sql(&temp_table, &pg_create_table, upsert_keys)?);
+    } else {
+        sql.push(copy_sql(&pg_create_table, &source_url, credentials));
+    }
+    Ok(sql)
+}
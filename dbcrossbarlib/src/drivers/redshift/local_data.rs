@@ -1,4 +1,4 @@
-//! Helper for reading data from BigQuery.
+//! Helper for reading data from Redshift.
 
 use super::RedshiftLocator;
 use crate::common::*;
@@ -29,7 +29,7 @@ pub(crate) async fn local_data_helper(
         )
         .await?;
 
-    // Copy from a temporary gs:// location.
+    // Copy from a temporary s3:// location.
     let from_temp_ctx = ctx.child(o!("from_temp" => s3_temp.to_string()));
     s3_temp
         .local_data(from_temp_ctx, shared_args, s3_source_args)
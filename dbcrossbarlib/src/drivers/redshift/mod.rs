@@ -8,20 +8,26 @@ use std::{
     str::{self, FromStr},
 };
 
+use crate::clouds::aws::{AwsCredentials, AwsRole};
 use crate::common::*;
 use crate::drivers::postgres::PostgresLocator;
 use crate::drivers::{
     postgres_shared::{pg_quote, TableName},
     s3::S3Locator,
 };
+use crate::limits::{Limits, NameLength};
+use crate::reserved_words;
+use crate::schema::DataType;
 
+mod dry_run;
 mod local_data;
 mod write_local_data;
 mod write_remote_data;
 
+use dry_run::{dry_run_read_helper, dry_run_write_helper};
 use local_data::local_data_helper;
 use write_local_data::write_local_data_helper;
-use write_remote_data::write_remote_data_helper;
+use write_remote_data::{write_remote_data_helper, VerifyRedshiftCanImportFromCsv};
 
 /// A locator for a Redshift table.
 #[derive(Debug, Clone)]
@@ -125,6 +131,27 @@ impl Locator for RedshiftLocator {
         )
         .boxed()
     }
+
+    fn dry_run_read(
+        &self,
+        ctx: Context,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        dry_run_read_helper(ctx, self.to_owned(), shared_args, source_args).boxed()
+    }
+
+    fn dry_run_write(
+        &self,
+        ctx: Context,
+        source: Option<BoxLocator>,
+        shared_args: SharedArguments<Unverified>,
+        _source_args: SourceArguments<Unverified>,
+        dest_args: DestinationArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        dry_run_write_helper(ctx, self.to_owned(), source, shared_args, dest_args)
+            .boxed()
+    }
 }
 
 impl LocatorStatic for RedshiftLocator {
@@ -148,12 +175,46 @@ impl LocatorStatic for RedshiftLocator {
             _placeholder: (),
         }
     }
+
+    fn remote_sources() -> &'static [&'static str] {
+        &["s3:"]
+    }
+
+    fn can_write_data_type(data_type: &DataType) -> bool {
+        data_type.verify_redshift_can_import_from_csv().is_ok()
+    }
+
+    fn is_reserved_word(name: &str) -> bool {
+        reserved_words::is_reserved(reserved_words::REDSHIFT, name)
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            max_columns: Some(1600),
+            max_name_length: Some(NameLength::Bytes(127)),
+        }
+    }
 }
 
 /// Given a `DriverArgs` structure, convert it into Redshift credentials SQL.
-pub(crate) fn credentials_sql(args: &DriverArguments) -> Result<String> {
+///
+/// If `assume_role_arn` (and optionally `external_id`) are present, we assume
+/// that role ourselves and pass the resulting temporary credentials to
+/// Redshift. Any other arguments are passed through as-is.
+pub(crate) async fn credentials_sql(
+    ctx: &Context,
+    args: &DriverArguments,
+) -> Result<String> {
+    let mut map = args.deserialize::<HashMap<String, String>>()?;
+    let assume_role_arn = map.remove("assume_role_arn");
+    let external_id = map.remove("external_id");
+    let role = AwsRole::from_args(assume_role_arn.as_deref(), external_id.as_deref())?;
+    if let Some(role) = role {
+        let creds = AwsCredentials::for_role(ctx, Some(&role)).await?;
+        add_role_credentials(&mut map, &creds)?;
+    }
+
     let mut out = vec![];
-    let map = args.deserialize::<HashMap<String, String>>()?;
     for (k, v) in &map {
         lazy_static! {
             static ref KEY_RE: Regex =
@@ -166,3 +227,56 @@ pub(crate) fn credentials_sql(args: &DriverArguments) -> Result<String> {
     }
     Ok(String::from_utf8(out).expect("found non-UTF-8 SQL"))
 }
+
+/// Add the credentials for an assumed role to the Redshift credentials in
+/// `map`, making sure they don't conflict with any other credentials.
+fn add_role_credentials(
+    map: &mut HashMap<String, String>,
+    creds: &AwsCredentials,
+) -> Result<()> {
+    for key in map.keys() {
+        let lower = key.to_ascii_lowercase();
+        if matches!(
+            lower.as_str(),
+            "iam_role"
+                | "credentials"
+                | "access_key_id"
+                | "secret_access_key"
+                | "session_token"
+        ) {
+            return Err(format_err!(
+                "cannot pass both assume_role_arn and {} as Redshift credentials",
+                key,
+            ));
+        }
+    }
+    map.insert("access_key_id".to_owned(), creds.access_key_id.clone());
+    map.insert(
+        "secret_access_key".to_owned(),
+        creds.secret_access_key.clone(),
+    );
+    if let Some(session_token) = &creds.session_token {
+        map.insert("session_token".to_owned(), session_token.clone());
+    }
+    Ok(())
+}
+
+#[test]
+fn add_role_credentials_rejects_conflicts() {
+    let creds = AwsCredentials {
+        access_key_id: "ASIAEXAMPLE".to_owned(),
+        secret_access_key: "secret".to_owned(),
+        session_token: Some("token".to_owned()),
+        default_region: None,
+    };
+
+    let mut map = HashMap::new();
+    map.insert("region".to_owned(), "us-east-1".to_owned());
+    add_role_credentials(&mut map, &creds).unwrap();
+    assert_eq!(map["access_key_id"], "ASIAEXAMPLE");
+    assert_eq!(map["session_token"], "token");
+
+    let mut map = HashMap::new();
+    map.insert("IAM_ROLE".to_owned(), "arn:aws:iam::1:role/r".to_owned());
+    assert!(add_role_credentials(&mut map, &creds).is_err());
+}
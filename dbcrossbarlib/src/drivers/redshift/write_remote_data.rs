@@ -6,9 +6,7 @@ use super::{credentials_sql, RedshiftLocator};
 use crate::common::*;
 use crate::drivers::{
     postgres::{columns_to_update_for_upsert, create_temp_table_for, prepare_table},
-    postgres_shared::{
-        connect, pg_quote, CheckCatalog, Client, Ident, PgCreateTable, TableName,
-    },
+    postgres_shared::{connect, pg_quote, CheckCatalog, Client, Ident, PgCreateTable},
     s3::S3Locator,
 };
 use crate::schema::{Column, DataType};
@@ -67,7 +65,7 @@ pub(crate) async fn write_remote_data_helper(
             create_temp_table_for(&ctx, &mut client, &pg_create_table).await?;
 
         // Copy data into our temporary table.
-        copy_in(&ctx, &client, &source_url, &temp_table.name, to_args).await?;
+        copy_in(&ctx, &client, &source_url, &temp_table, to_args).await?;
 
         // Build our upsert SQL.
         upsert_from_temp_table(
@@ -79,37 +77,64 @@ pub(crate) async fn write_remote_data_helper(
         )
         .await?;
     } else {
-        copy_in(&ctx, &client, &source_url, &table_name, to_args).await?;
+        copy_in(&ctx, &client, &source_url, &pg_create_table, to_args).await?;
     }
 
     Ok(vec![dest.boxed()])
 }
 
+/// Generate `COPY` SQL which loads CSV files from `source_s3_url` into
+/// `dest_table`.
+///
+/// We always list our columns, in the same order as our CSV files, so that we
+/// can load into an existing table whose columns are in a different order.
+pub(crate) fn copy_sql(
+    dest_table: &PgCreateTable,
+    source_s3_url: &Url,
+    credentials: &str,
+) -> String {
+    format!(
+        "COPY {dest} ({columns}) FROM {source}\n{credentials}FORMAT CSV\nIGNOREHEADER 1\nDATEFORMAT 'auto'\nTIMEFORMAT 'auto'",
+        dest = dest_table.name.quoted(),
+        columns = dest_table.columns.iter().map(|c| Ident(&c.name)).join(", "),
+        source = pg_quote(source_s3_url.as_str()), // `$1` doesn't work here.
+        credentials = credentials,
+    )
+}
+
 /// Copy data from S3 into a RedShift table.
 async fn copy_in(
     ctx: &Context,
     client: &Client,
     source_s3_url: &Url,
-    dest_table: &TableName,
+    dest_table: &PgCreateTable,
     to_args: &DriverArguments,
 ) -> Result<()> {
+    let dest_table_name = &dest_table.name;
     debug!(
         ctx.log(),
         "Copying into {} from {}",
-        dest_table.unquoted(),
+        dest_table_name.unquoted(),
         source_s3_url.as_str(),
     );
-    let copy_sql = format!(
-        "COPY {dest} FROM {source}\n{credentials}FORMAT CSV\nIGNOREHEADER 1\nDATEFORMAT 'auto'\nTIMEFORMAT 'auto'",
-        dest = dest_table.quoted(),
-        source = pg_quote(source_s3_url.as_str()), // `$1` doesn't work here.
-        credentials = credentials_sql(to_args)?,
+    let sql = copy_sql(
+        dest_table,
+        source_s3_url,
+        &credentials_sql(ctx, to_args).await?,
     );
-    let copy_stmt = client.prepare(&copy_sql).await?;
-    client.execute(&copy_stmt, &[]).await.with_context(|_| {
+    // Never write our credentials to the audit log.
+    let audited_sql = copy_sql(dest_table, source_s3_url, "-- credentials omitted\n");
+    let copy_stmt = client.prepare(&sql).await?;
+    ctx.audit_sql(
+        &dest_table_name.quoted().to_string(),
+        &audited_sql,
+        client.execute(&copy_stmt, &[]),
+    )
+    .await
+    .with_context(|_| {
         format!(
             "error copying to {} from {}",
-            dest_table.quoted(),
+            dest_table_name.quoted(),
             source_s3_url
         )
     })?;
@@ -135,7 +160,13 @@ async fn upsert_from_temp_table(
             upsert_sql.len(),
             sql,
         );
-        transaction.execute(&sql[..], &[]).await.with_context(|_| {
+        ctx.audit_sql(
+            &dest_table.name.quoted().to_string(),
+            sql,
+            transaction.execute(&sql[..], &[]),
+        )
+        .await
+        .with_context(|_| {
             format!(
                 "error upserting into {} from {}",
                 dest_table.name.quoted(),
@@ -152,7 +183,7 @@ async fn upsert_from_temp_table(
 /// Generate the SQL needed to perform an upsert.
 ///
 /// This will destructively modify and then delete `temp_table`.
-fn upsert_sql(
+pub(crate) fn upsert_sql(
     temp_table: &PgCreateTable,
     dest_table: &PgCreateTable,
     upsert_keys: &[String],
@@ -214,7 +245,7 @@ INSERT INTO {dest_table} ({all_columns}) (
 }
 
 /// Extension trait for verifying Redshift compatibility.
-trait VerifyRedshiftCanImportFromCsv {
+pub(crate) trait VerifyRedshiftCanImportFromCsv {
     /// Can Redshift import the data described by this type from a CSV file?
     fn verify_redshift_can_import_from_csv(&self) -> Result<()>;
 }
@@ -262,3 +293,17 @@ impl VerifyRedshiftCanImportFromCsv for DataType {
         }
     }
 }
+
+#[test]
+fn copy_sql_lists_columns_in_csv_order() {
+    let dest_table = PgCreateTable::parse(
+        "test.sql".to_owned(),
+        "CREATE TABLE t (b text, a int)".to_owned(),
+    )
+    .unwrap();
+    let source_s3_url = "s3://bucket/dir/".parse::<Url>().unwrap();
+    assert_eq!(
+        copy_sql(&dest_table, &source_s3_url, ""),
+        "COPY \"t\" (\"b\", \"a\") FROM 's3://bucket/dir/'\nFORMAT CSV\nIGNOREHEADER 1\nDATEFORMAT 'auto'\nTIMEFORMAT 'auto'",
+    );
+}
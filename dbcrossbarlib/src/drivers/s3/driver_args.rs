@@ -0,0 +1,132 @@
+//! Driver arguments for `s3://` sources and destinations.
+
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Duration};
+
+use crate::clouds::{
+    aws::AwsRole,
+    endpoints::{ApiEndpoint, ApiEndpoints},
+    object_metadata::ObjectMetadata,
+    signed_urls::SignedUrlWriter,
+};
+use crate::common::*;
+use crate::driver_args::{deserialize_opt_duration, deserialize_opt_from_str};
+
+/// Parsed version of `--from-arg` for `s3://` sources.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct S3SourceArguments {
+    /// The ARN of an IAM role to assume before reading.
+    #[serde(default)]
+    assume_role_arn: Option<String>,
+
+    /// The external ID required to assume `assume_role_arn`.
+    #[serde(default)]
+    external_id: Option<String>,
+
+    /// Pass this to `aws s3 --endpoint-url`, for VPC endpoints and
+    /// S3-compatible services.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    s3_endpoint: Option<ApiEndpoint>,
+}
+
+impl S3SourceArguments {
+    /// The role we should assume, if any.
+    pub(crate) fn role(&self) -> Result<Option<AwsRole>> {
+        AwsRole::from_args(
+            self.assume_role_arn.as_deref(),
+            self.external_id.as_deref(),
+        )
+    }
+
+    /// Any API endpoint overrides specified by these arguments.
+    pub(crate) fn endpoints(&self) -> ApiEndpoints {
+        ApiEndpoints {
+            s3_endpoint: self.s3_endpoint.clone(),
+            ..ApiEndpoints::default()
+        }
+    }
+}
+
+/// Parsed version of `--to-arg` for `s3://` destinations.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct S3DestinationArguments {
+    /// The ARN of an IAM role to assume before writing.
+    #[serde(default)]
+    assume_role_arn: Option<String>,
+
+    /// The external ID required to assume `assume_role_arn`.
+    #[serde(default)]
+    external_id: Option<String>,
+
+    /// Pass this to `aws s3 --endpoint-url`, for VPC endpoints and
+    /// S3-compatible services.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    s3_endpoint: Option<ApiEndpoint>,
+
+    /// Print a signed URL for each file we write, valid for this long.
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    signed_url_ttl: Option<Duration>,
+
+    /// Write our signed URLs to this local file instead of standard output.
+    #[serde(default)]
+    signed_url_manifest: Option<PathBuf>,
+
+    /// The `Content-Type` of each file we write.
+    #[serde(default)]
+    content_type: Option<String>,
+
+    /// The `Content-Encoding` of each file we write.
+    #[serde(default)]
+    content_encoding: Option<String>,
+
+    /// The `Cache-Control` header of each file we write.
+    #[serde(default)]
+    cache_control: Option<String>,
+
+    /// Custom metadata for each file we write.
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+
+    /// The storage class for each file we write.
+    #[serde(default)]
+    storage_class: Option<String>,
+}
+
+impl S3DestinationArguments {
+    /// The role we should assume, if any.
+    pub(crate) fn role(&self) -> Result<Option<AwsRole>> {
+        AwsRole::from_args(
+            self.assume_role_arn.as_deref(),
+            self.external_id.as_deref(),
+        )
+    }
+
+    /// Any API endpoint overrides specified by these arguments.
+    pub(crate) fn endpoints(&self) -> ApiEndpoints {
+        ApiEndpoints {
+            s3_endpoint: self.s3_endpoint.clone(),
+            ..ApiEndpoints::default()
+        }
+    }
+
+    /// Where should we send signed URLs, if anywhere?
+    pub(crate) fn signed_url_writer(&self) -> Result<Option<Arc<SignedUrlWriter>>> {
+        SignedUrlWriter::from_args(
+            self.signed_url_ttl,
+            self.signed_url_manifest.as_deref(),
+        )
+    }
+
+    /// The metadata to set on each file we write.
+    pub(crate) fn object_metadata(&self) -> ObjectMetadata {
+        ObjectMetadata {
+            content_type: self.content_type.clone(),
+            content_encoding: self.content_encoding.clone(),
+            cache_control: self.cache_control.clone(),
+            metadata: self.metadata.clone(),
+            storage_class: self.storage_class.clone(),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Implementation of `S3Locator::dry_run_write`.
+
+use super::{write_remote_data::unload_sql, S3Locator};
+use crate::common::*;
+use crate::drivers::{
+    postgres_shared::{CheckCatalog, PgCreateTable},
+    redshift::RedshiftLocator,
+};
+
+/// Describe how `write_remote_data` would copy `source` to `dest`. When we
+/// upload local data, there's nothing to describe.
+pub(crate) async fn dry_run_write_helper(
+    ctx: Context,
+    dest: S3Locator,
+    source: Option<BoxLocator>,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Vec<String>> {
+    let source = match source {
+        Some(source) => source,
+        None => return Ok(vec![]),
+    };
+    if let Some(source) = source.as_any().downcast_ref::<S3Locator>() {
+        return Ok(vec![format!(
+            "-- copy files from {} to {} server-side",
+            source, dest,
+        )]);
+    }
+    let source = source
+        .as_any()
+        .downcast_ref::<RedshiftLocator>()
+        .ok_or_else(|| format_err!("not a redshift:// locator: {}", source))?;
+
+    let shared_args = shared_args.verify(S3Locator::features())?;
+    let source_args = source_args.verify(RedshiftLocator::features())?;
+
+    // Build the same `UNLOAD` as `write_remote_data`, but without credentials.
+    let pg_create_table = PgCreateTable::from_pg_catalog_or_default(
+        &ctx,
+        CheckCatalog::Yes,
+        source.url(),
+        source.table_name(),
+        shared_args.schema(),
+    )
+    .await?;
+    let mut sql_bytes: Vec<u8> = vec![];
+    pg_create_table.write_export_select_sql(&mut sql_bytes, &source_args)?;
+    let select_sql = String::from_utf8(sql_bytes).expect("should always be UTF-8");
+    Ok(vec![unload_sql(
+        &select_sql,
+        dest.as_url(),
+        "-- credentials omitted\n",
+    )])
+}
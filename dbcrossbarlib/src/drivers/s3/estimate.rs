@@ -0,0 +1,45 @@
+//! Estimating the size of data in S3.
+
+use super::{driver_args::S3SourceArguments, S3Locator};
+use crate::clouds::aws::{s3, AwsCredentials};
+use crate::common::*;
+
+/// Implementation of `estimate`, but as a real `async` function.
+///
+/// We add up the sizes of all the objects, and estimate the number of rows by
+/// sampling the beginning of the first object.
+pub(crate) async fn estimate_helper(
+    ctx: Context,
+    url: Url,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<Estimate>> {
+    let source_args = source_args.verify(S3Locator::features())?;
+    let s3_args = source_args
+        .driver_args()
+        .deserialize::<S3SourceArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&s3_args.endpoints());
+    let creds = AwsCredentials::for_role(&ctx, s3_args.role()?.as_ref()).await?;
+    debug!(ctx.log(), "estimating size of {}", url);
+
+    let objects = s3::ls_objects(&ctx, &creds, &url)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let total_bytes = objects.iter().map(|object| object.size).sum::<u64>();
+    let files = u64::try_from(objects.len())?;
+    match objects.first() {
+        Some(first) => {
+            let sample = s3::download_file(&ctx, &creds, &first.url).await?;
+            Ok(Some(
+                Estimate::from_csv_sample(&ctx, sample, total_bytes, files).await?,
+            ))
+        }
+        None => Ok(Some(Estimate {
+            rows: Some(0),
+            bytes: Some(0),
+            files: Some(0),
+            method: "file sizes".to_owned(),
+        })),
+    }
+}
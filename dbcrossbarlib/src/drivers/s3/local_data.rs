@@ -1,9 +1,10 @@
 //! Reading data from AWS S3.
 
-use super::S3Locator;
-use crate::clouds::aws::s3;
+use super::{driver_args::S3SourceArguments, S3Locator};
+use crate::clouds::aws::{s3, AwsCredentials};
 use crate::common::*;
 use crate::csv_stream::csv_stream_name;
+use crate::decompress::decompress_stream;
 
 /// Implementation of `local_data`, but as a real `async` function.
 pub(crate) async fn local_data_helper(
@@ -13,12 +14,18 @@ pub(crate) async fn local_data_helper(
     source_args: SourceArguments<Unverified>,
 ) -> Result<Option<BoxStream<CsvStream>>> {
     let _shared_args = shared_args.verify(S3Locator::features())?;
-    let _source_args = source_args.verify(S3Locator::features())?;
+    let source_args = source_args.verify(S3Locator::features())?;
+    let s3_args = source_args
+        .driver_args()
+        .deserialize::<S3SourceArguments>()
+        .context("error parsing --from-args")?;
+    let ctx = ctx.with_endpoints(&s3_args.endpoints());
+    let creds = AwsCredentials::for_role(&ctx, s3_args.role()?.as_ref()).await?;
 
     debug!(ctx.log(), "getting CSV files from {}", url);
 
     // List the files at our URL.
-    let file_urls = s3::ls(&ctx, &url).await?;
+    let file_urls = s3::ls(&ctx, &creds, &url).await?;
 
     // Convert into `CsvStream` values lazily in case there are a lot of CSV
     // files we need to read.
@@ -29,13 +36,15 @@ pub(crate) async fn local_data_helper(
     let csv_streams = file_urls.and_then(move |file_url| {
         let ctx = ctx.clone();
         let url = url.clone();
+        let creds = creds.clone();
         async move {
             // Stream the file from the cloud.
             let name = csv_stream_name(url.as_str(), file_url.as_str())?.to_owned();
             let ctx = ctx.child(
                 o!("stream" => name.clone(), "url" => file_url.as_str().to_owned()),
             );
-            let data = s3::download_file(&ctx, &file_url).await?;
+            let data = s3::download_file(&ctx, &creds, &file_url).await?;
+            let data = decompress_stream(&ctx, data).await?;
 
             // Assemble everything into a CSV stream.
             Ok(CsvStream { name, data })
@@ -2,14 +2,24 @@
 
 use std::{fmt, str::FromStr};
 
+use crate::clouds::{
+    aws::{sign_s3_url, AwsCredentials},
+    signed_urls::SignedUrlWriter,
+};
 use crate::common::*;
+use crate::copy_path::NoCopyPathError;
 use crate::drivers::redshift::RedshiftLocator;
 
+mod driver_args;
+mod dry_run;
+mod estimate;
 mod local_data;
 mod prepare_as_destination;
 mod write_local_data;
 mod write_remote_data;
 
+use dry_run::dry_run_write_helper;
+use estimate::estimate_helper;
 use local_data::local_data_helper;
 pub(crate) use prepare_as_destination::prepare_as_destination_helper;
 use write_local_data::write_local_data_helper;
@@ -25,6 +35,11 @@ impl S3Locator {
     pub(crate) fn as_url(&self) -> &Url {
         &self.url
     }
+
+    /// Does this locator point to a directory, ending in `/`?
+    pub(crate) fn is_directory(&self) -> bool {
+        self.url.path().ends_with('/')
+    }
 }
 
 impl fmt::Display for S3Locator {
@@ -59,6 +74,14 @@ impl Locator for S3Locator {
         self
     }
 
+    fn estimate(
+        &self,
+        ctx: Context,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<Estimate>> {
+        estimate_helper(ctx, self.url.clone(), source_args).boxed()
+    }
+
     fn local_data(
         &self,
         ctx: Context,
@@ -80,10 +103,14 @@ impl Locator for S3Locator {
     }
 
     fn supports_write_remote_data(&self, source: &dyn Locator) -> bool {
-        // We can only do `write_remote_data` if `source` is a
-        // `RedshiftLocator`. Otherwise, we need to do `write_local_data` like
-        // normal.
-        source.as_any().is::<RedshiftLocator>()
+        // We can only do `write_remote_data` if `source` is a `RedshiftLocator`,
+        // or if we're copying one `s3://` directory to another. Otherwise, we
+        // need to do `write_local_data` like normal.
+        if let Some(source) = source.as_any().downcast_ref::<S3Locator>() {
+            source.is_directory() && self.is_directory()
+        } else {
+            source.as_any().is::<RedshiftLocator>()
+        }
     }
 
     fn write_remote_data(
@@ -104,6 +131,18 @@ impl Locator for S3Locator {
         )
         .boxed()
     }
+
+    fn dry_run_write(
+        &self,
+        ctx: Context,
+        source: Option<BoxLocator>,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+        _dest_args: DestinationArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        dry_run_write_helper(ctx, self.to_owned(), source, shared_args, source_args)
+            .boxed()
+    }
 }
 
 impl LocatorStatic for S3Locator {
@@ -113,14 +152,37 @@ impl LocatorStatic for S3Locator {
 
     fn features() -> Features {
         Features {
-            locator: LocatorFeatures::LocalData | LocatorFeatures::WriteLocalData,
+            locator: LocatorFeatures::LocalData
+                | LocatorFeatures::WriteLocalData
+                | LocatorFeatures::Estimate,
             write_schema_if_exists: EnumSet::empty(),
-            source_args: EnumSet::empty(),
-            dest_args: EnumSet::empty(),
-            dest_if_exists: IfExistsFeatures::Overwrite.into(),
+            source_args: SourceArgumentsFeatures::DriverArgs.into(),
+            dest_args: DestinationArgumentsFeatures::DriverArgs
+                | DestinationArgumentsFeatures::Compression,
+            dest_if_exists: IfExistsFeatures::Overwrite | IfExistsFeatures::Append,
             _placeholder: (),
         }
     }
+
+    fn remote_sources() -> &'static [&'static str] {
+        &["redshift:", "s3:"]
+    }
+}
+
+/// Sign `url` and pass it to `writer`.
+fn write_signed_url(
+    creds: &AwsCredentials,
+    writer: &SignedUrlWriter,
+    url: &Url,
+) -> Result<()> {
+    let (signed_url, session_token) =
+        sign_s3_url(creds, "GET", writer.expires(), url)?;
+    if session_token.is_some() {
+        return Err(format_err!(
+            "signed s3:// URLs do not support AWS_SESSION_TOKEN"
+        ));
+    }
+    writer.write(&signed_url)
 }
 
 /// Given a `TemporaryStorage`, extract a unique `s3://` temporary directory,
@@ -128,9 +190,10 @@ impl LocatorStatic for S3Locator {
 pub(crate) fn find_s3_temp_dir(
     temporary_storage: &TemporaryStorage,
 ) -> Result<S3Locator> {
+    temporary_storage.check_allowed(S3Locator::scheme())?;
     let mut temp = temporary_storage
         .find_scheme(S3Locator::scheme())
-        .ok_or_else(|| format_err!("need `--temporary=s3://...` argument"))?
+        .ok_or_else(|| NoCopyPathError::missing_temporary(S3Locator::scheme()))?
         .to_owned();
     if !temp.ends_with('/') {
         temp.push_str("/");
@@ -1,7 +1,10 @@
 //! Writing data to AWS S3.
 
-use super::{prepare_as_destination_helper, S3Locator};
-use crate::clouds::aws::s3;
+use super::{
+    driver_args::S3DestinationArguments, prepare_as_destination_helper,
+    write_signed_url, S3Locator,
+};
+use crate::clouds::aws::{s3, AwsCredentials};
 use crate::common::*;
 
 /// Implementation of `write_local_data`, but as a real `async` function.
@@ -17,19 +20,36 @@ pub(crate) async fn write_local_data_helper(
 
     // Look up our arguments.
     let if_exists = dest_args.if_exists().to_owned();
+    let compression = dest_args.compression();
+    let s3_args = dest_args
+        .driver_args()
+        .deserialize::<S3DestinationArguments>()
+        .context("error parsing --to-args")?;
+    let signed_url_writer = s3_args.signed_url_writer()?;
+    let metadata = s3_args.object_metadata();
+    let ctx = ctx.with_endpoints(&s3_args.endpoints());
+    let creds = AwsCredentials::for_role(&ctx, s3_args.role()?.as_ref()).await?;
 
     // Delete the existing output, if it exists.
-    prepare_as_destination_helper(ctx.clone(), url.clone(), if_exists).await?;
+    prepare_as_destination_helper(ctx.clone(), &creds, url.clone(), if_exists).await?;
 
     // Spawn our uploader threads.
     let written = data.map_ok(move |stream| {
         let url = url.clone();
         let ctx = ctx.clone();
+        let signed_url_writer = signed_url_writer.clone();
+        let metadata = metadata.clone();
+        let creds = creds.clone();
         async move {
-            let url = url.join(&format!("{}.csv", stream.name))?;
+            let url =
+                url.join(&format!("{}.csv{}", stream.name, compression.extension()))?;
             let ctx = ctx
                 .child(o!("stream" => stream.name.clone(), "url" => url.to_string()));
-            s3::upload_file(&ctx, stream.data, &url).await?;
+            let stream = compression.compress_csv_stream(&ctx, stream)?;
+            s3::upload_file(&ctx, &creds, stream.data, &url, &metadata).await?;
+            if let Some(writer) = signed_url_writer {
+                write_signed_url(&creds, &writer, &url)?;
+            }
             Ok(S3Locator { url }.boxed())
         }
         .boxed()
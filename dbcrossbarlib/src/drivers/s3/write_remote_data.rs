@@ -1,6 +1,14 @@
-//! Implementation of `GsLocator::write_remote_data`.
+//! Implementation of `S3Locator::write_remote_data`.
 
-use super::{prepare_as_destination_helper, S3Locator};
+use super::{
+    driver_args::{S3DestinationArguments, S3SourceArguments},
+    prepare_as_destination_helper, write_signed_url, S3Locator,
+};
+use crate::clouds::{
+    aws::{s3, AwsCredentials},
+    endpoints::ApiEndpoints,
+    signed_urls::SignedUrlWriter,
+};
 use crate::common::*;
 use crate::drivers::{
     postgres_shared::{connect, pg_quote, CheckCatalog, PgCreateTable},
@@ -20,6 +28,19 @@ pub(crate) async fn write_remote_data_helper(
     source_args: SourceArguments<Unverified>,
     dest_args: DestinationArguments<Unverified>,
 ) -> Result<Vec<BoxLocator>> {
+    // If our source is another `s3://` directory, copy it server-side.
+    if let Some(source) = source.as_any().downcast_ref::<S3Locator>() {
+        return copy_from_s3(
+            ctx,
+            source.to_owned(),
+            dest,
+            shared_args,
+            source_args,
+            dest_args,
+        )
+        .await;
+    }
+
     // Convert the source locator into `RedshiftLocator`.
     let source = source
         .as_any()
@@ -34,10 +55,27 @@ pub(crate) async fn write_remote_data_helper(
     let schema = shared_args.schema();
     let from_args = source_args.driver_args();
     let if_exists = dest_args.if_exists().to_owned();
+    let s3_args = dest_args
+        .driver_args()
+        .deserialize::<S3DestinationArguments>()
+        .context("error parsing --to-args")?;
+    if !s3_args.object_metadata().is_empty() {
+        return Err(format_err!(
+            "cannot set object metadata when unloading from Redshift"
+        ));
+    }
+    let signed_url_writer = s3_args.signed_url_writer()?;
+    let ctx = ctx.with_endpoints(&s3_args.endpoints());
+    let creds = AwsCredentials::for_role(&ctx, s3_args.role()?.as_ref()).await?;
 
     // Delete the existing output, if it exists.
-    prepare_as_destination_helper(ctx.clone(), dest.as_url().to_owned(), if_exists)
-        .await?;
+    prepare_as_destination_helper(
+        ctx.clone(),
+        &creds,
+        dest.as_url().to_owned(),
+        if_exists,
+    )
+    .await?;
 
     // Convert our schema to a native PostgreSQL schema.
     let table_name = source.table_name();
@@ -60,15 +98,116 @@ pub(crate) async fn write_remote_data_helper(
 
     // Export as CSV.
     let client = connect(&ctx, source.url()).await?;
-    let unload_sql = format!(
-        "UNLOAD ({source}) TO {dest}\n{credentials}HEADER FORMAT CSV",
-        source = pg_quote(&select_sql),
-        dest = pg_quote(dest.as_url().as_str()),
-        credentials = credentials_sql(from_args)?,
+    let unload_sql = unload_sql(
+        &select_sql,
+        dest.as_url(),
+        &credentials_sql(&ctx, from_args).await?,
     );
     let unload_stmt = client.prepare(&unload_sql).await?;
     client.execute(&unload_stmt, &[]).await.with_context(|_| {
         format!("error copying {} to {}", table_name.quoted(), dest)
     })?;
+
+    if let Some(writer) = signed_url_writer {
+        sign_written_objects(&ctx, &creds, dest.as_url(), &writer).await?;
+    }
+    Ok(vec![dest.boxed()])
+}
+
+/// Generate `UNLOAD` SQL which exports the results of `select_sql` to
+/// `dest_url` as CSV files.
+pub(crate) fn unload_sql(
+    select_sql: &str,
+    dest_url: &Url,
+    credentials: &str,
+) -> String {
+    format!(
+        "UNLOAD ({source}) TO {dest}\n{credentials}HEADER FORMAT CSV",
+        source = pg_quote(select_sql),
+        dest = pg_quote(dest_url.as_str()),
+        credentials = credentials,
+    )
+}
+
+/// Copy all the CSV files in `source` to `dest`, using server-side copies
+/// instead of downloading and re-uploading the data.
+async fn copy_from_s3(
+    ctx: Context,
+    source: S3Locator,
+    dest: S3Locator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<BoxLocator>> {
+    let _shared_args = shared_args.verify(S3Locator::features())?;
+    let source_args = source_args.verify(S3Locator::features())?;
+    let dest_args = dest_args.verify(S3Locator::features())?;
+
+    let source_s3_args = source_args
+        .driver_args()
+        .deserialize::<S3SourceArguments>()
+        .context("error parsing --from-args")?;
+    let s3_args = dest_args
+        .driver_args()
+        .deserialize::<S3DestinationArguments>()
+        .context("error parsing --to-args")?;
+    let signed_url_writer = s3_args.signed_url_writer()?;
+
+    // `aws s3 cp` uses a single set of credentials for both buckets, so we can
+    // only assume one role.
+    let role = match (source_s3_args.role()?, s3_args.role()?) {
+        (Some(source_role), Some(dest_role)) if source_role != dest_role => {
+            return Err(format_err!(
+                "cannot assume different roles for {} and {}",
+                source,
+                dest,
+            ));
+        }
+        (source_role, dest_role) => dest_role.or(source_role),
+    };
+    let ctx = ctx.with_endpoints(&ApiEndpoints::for_copy(
+        &source_s3_args.endpoints(),
+        &s3_args.endpoints(),
+    )?);
+    let creds = AwsCredentials::for_role(&ctx, role.as_ref()).await?;
+
+    // Delete the existing output, if it exists.
+    let if_exists = dest_args.if_exists().to_owned();
+    prepare_as_destination_helper(
+        ctx.clone(),
+        &creds,
+        dest.as_url().to_owned(),
+        if_exists,
+    )
+    .await?;
+
+    s3::copy_dir(
+        &ctx,
+        &creds,
+        source.as_url(),
+        dest.as_url(),
+        &s3_args.object_metadata(),
+    )
+    .await?;
+    if let Some(writer) = signed_url_writer {
+        sign_written_objects(&ctx, &creds, dest.as_url(), &writer).await?;
+    }
     Ok(vec![dest.boxed()])
 }
+
+/// Write a signed URL for each object in `dest_url`.
+async fn sign_written_objects(
+    ctx: &Context,
+    creds: &AwsCredentials,
+    dest_url: &Url,
+    writer: &SignedUrlWriter,
+) -> Result<()> {
+    let urls = s3::ls(ctx, creds, dest_url)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    for url in urls {
+        write_signed_url(creds, writer, &url)?;
+    }
+    Ok(())
+}
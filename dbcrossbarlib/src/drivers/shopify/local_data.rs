@@ -9,10 +9,11 @@ use serde_json::Value;
 use std::{collections::HashMap, str::FromStr};
 use tokio::{
     sync::mpsc::Sender,
-    time::{delay_for, Duration},
+    time::Duration,
 };
 
 use super::{json_to_csv::write_rows, ShopifyLocator};
+use crate::clouds::http_client::reqwest_client;
 use crate::common::*;
 use crate::credentials::CredentialsManager;
 use crate::tokio_glue::{box_stream_once, bytes_channel, SendResultExt};
@@ -37,13 +38,15 @@ pub(crate) async fn local_data_helper(
     let auth_token = creds.get_required("auth_token")?.to_owned();
 
     // Loop over pages until we run out.
+    let client = reqwest_client()?;
     let mut include_headers = true;
     let worker_ctx = ctx.clone();
     let (mut sender, receiver) = bytes_channel(1);
     let worker: BoxFuture<()> = async move {
-        let client = Client::new();
         let mut next_url = url.clone();
         loop {
+            worker_ctx.check_cancelled()?;
+
             // Query Shopify and forward any errors to our consumer. We allow a
             // few retries to deal with transient errors, but not too many,
             // because we're not distinguishing between permanent and temporary
@@ -64,7 +67,10 @@ pub(crate) async fn local_data_helper(
                     .await;
                     match result {
                         Ok(resp) => WaitStatus::Finished(resp),
-                        Err(err) => WaitStatus::FailedTemporarily(err),
+                        Err(err) => {
+                            worker_ctx.retrying(&err);
+                            WaitStatus::FailedTemporarily(err)
+                        }
                     }
                 }
             })
@@ -103,7 +109,10 @@ pub(crate) async fn local_data_helper(
                 // If we're starting to overheat, wait a full second, giving enough
                 // time regenerate at least 2 API calls worth of credit.
                 if resp.call_limit.should_wait() {
-                    delay_for(Duration::from_millis(1000)).await;
+                    worker_ctx
+                        .cancellation()
+                        .delay_for(Duration::from_millis(1000))
+                        .await?;
                 }
             } else {
                 // No more pages of data to fetch!
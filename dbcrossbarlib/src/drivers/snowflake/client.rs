@@ -0,0 +1,129 @@
+//! Running the `snowsql` command-line client.
+//!
+//! Like our MySQL driver, we talk to Snowflake using its command-line client
+//! instead of a native client library. `snowsql` handles Snowflake's many
+//! authentication options, and it can `GET` files from internal stages.
+
+use percent_encoding::percent_decode_str;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::common::*;
+
+/// Build a `snowsql` command which connects to the database and schema in
+/// `url`, and which prints query results as tab-separated lines without
+/// headers.
+pub(crate) fn snowsql_command(url: &UrlWithHiddenPassword) -> Result<Command> {
+    let url = url.with_password();
+    let account = url
+        .host_str()
+        .ok_or_else(|| format_err!("no account in Snowflake locator"))?;
+    let mut path = url.path().trim_start_matches('/').splitn(2, '/');
+    let database = path.next().unwrap_or("");
+    let schema = path.next().unwrap_or("");
+    let mut command = Command::new("snowsql");
+    command
+        .arg(format!("--accountname={}", account))
+        .arg(format!(
+            "--dbname={}",
+            percent_decode_str(database).decode_utf8()?
+        ))
+        .arg(format!(
+            "--schemaname={}",
+            percent_decode_str(schema).decode_utf8()?
+        ))
+        .args(["-o", "friendly=false", "-o", "header=false"])
+        .args(["-o", "timing=false", "-o", "output_format=tsv"])
+        .args(["-o", "exit_on_error=true"]);
+    if !url.username().is_empty() {
+        let user = percent_decode_str(url.username()).decode_utf8()?;
+        command.arg(format!("--username={}", user));
+    }
+    for (key, value) in url.query_pairs() {
+        match &key[..] {
+            "warehouse" => command.arg(format!("--warehouse={}", value)),
+            "role" => command.arg(format!("--rolename={}", value)),
+            _ => {
+                return Err(format_err!(
+                    "unknown Snowflake locator parameter {:?}",
+                    key
+                ))
+            }
+        };
+    }
+    // Pass the password using the environment, so that it doesn't show up in
+    // `ps` output.
+    if let Some(password) = url.password() {
+        let password = percent_decode_str(password).decode_utf8()?;
+        command.env("SNOWSQL_PWD", password.as_ref());
+    }
+    Ok(command)
+}
+
+/// Run `sql` against the database in `url`, and return each line of output.
+/// `sql` may contain several statements, which will be run in the same
+/// session.
+///
+/// We pass `sql` to `snowsql` on standard input, because it may contain
+/// credentials which shouldn't show up in `ps` output.
+pub(crate) async fn query_lines(
+    ctx: &Context,
+    url: &UrlWithHiddenPassword,
+    sql: &str,
+) -> Result<Vec<String>> {
+    // Don't log `sql`, because it may contain credentials. Our callers log a
+    // redacted version.
+    trace!(ctx.log(), "running `snowsql`");
+    let mut child = snowsql_command(url)?
+        .arg("--filename=/dev/stdin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("error running `snowsql`")?;
+    let mut child_stdin = child.stdin.take().expect("child should have stdin");
+    child_stdin
+        .write_all(sql.as_bytes())
+        .await
+        .context("error sending SQL to `snowsql`")?;
+    drop(child_stdin);
+    let output = child
+        .wait_with_output()
+        .await
+        .context("error running `snowsql`")?;
+    if !output.status.success() {
+        // `snowsql` prints most errors on standard output.
+        return Err(format_err!(
+            "`snowsql` failed with {}: {} {}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout).trim(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    let stdout =
+        String::from_utf8(output.stdout).context("`snowsql` printed invalid UTF-8")?;
+    Ok(stdout.lines().map(|line| line.to_owned()).collect())
+}
+
+/// Quote `ident` as a Snowflake identifier. Quoted identifiers are
+/// case-sensitive, so `orders` and `ORDERS` are different tables.
+pub(crate) fn snowflake_quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Quote `s` as a Snowflake string literal.
+pub(crate) fn snowflake_quote_string(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[test]
+fn quotes_identifiers_and_strings() {
+    assert_eq!(
+        snowflake_quote_ident("my \"table\""),
+        "\"my \"\"table\"\"\""
+    );
+    assert_eq!(
+        snowflake_quote_string(r"it's a \ test"),
+        r"'it\'s a \\ test'"
+    );
+}
@@ -0,0 +1,160 @@
+//! Snowflake column types.
+
+use std::fmt;
+
+use crate::common::*;
+use crate::schema::{DataType, Srid};
+
+/// A Snowflake column type, as found in `information_schema.columns.data_type`
+/// or in a `CREATE TABLE` statement.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct SnowflakeDataType {
+    /// The uppercase name of the type, such as `"NUMBER"`.
+    name: String,
+    /// Any arguments, such as `"38,9"` for `NUMBER(38,9)`.
+    args: Option<String>,
+}
+
+impl SnowflakeDataType {
+    /// Create a new type.
+    fn new(name: &str, args: Option<&str>) -> Self {
+        SnowflakeDataType {
+            name: name.to_owned(),
+            args: args.map(|args| args.to_owned()),
+        }
+    }
+
+    /// Create a type from the `data_type` and `numeric_scale` columns of
+    /// `information_schema.columns`.
+    pub(crate) fn from_information_schema(
+        data_type: &str,
+        numeric_scale: Option<u32>,
+    ) -> Self {
+        let name = data_type.trim().to_ascii_uppercase();
+        match (&name[..], numeric_scale) {
+            ("NUMBER", Some(scale)) => {
+                Self::new(&name, Some(&format!("38,{}", scale)))
+            }
+            _ => Self::new(&name, None),
+        }
+    }
+
+    /// The Snowflake type we use to store `ty`.
+    pub(crate) fn for_data_type(ty: &DataType) -> Result<Self> {
+        match ty {
+            DataType::Array(_) => Ok(Self::new("ARRAY", None)),
+            DataType::Bool => Ok(Self::new("BOOLEAN", None)),
+            DataType::Date => Ok(Self::new("DATE", None)),
+            DataType::Decimal => Ok(Self::new("NUMBER", Some("38,9"))),
+            DataType::Float32 | DataType::Float64 => Ok(Self::new("FLOAT", None)),
+            DataType::GeoJson(srid) if *srid == Srid::wgs84() => {
+                Ok(Self::new("GEOGRAPHY", None))
+            }
+            DataType::GeoJson(srid) => Err(format_err!(
+                "Snowflake GEOGRAPHY columns only support SRID {}, not {}",
+                Srid::wgs84(),
+                srid,
+            )),
+            DataType::Int16 => Ok(Self::new("SMALLINT", None)),
+            DataType::Int32 => Ok(Self::new("INTEGER", None)),
+            DataType::Int64 => Ok(Self::new("BIGINT", None)),
+            DataType::Json => Ok(Self::new("VARIANT", None)),
+            DataType::Struct(_) => Ok(Self::new("OBJECT", None)),
+            DataType::Text | DataType::Uuid => Ok(Self::new("TEXT", None)),
+            DataType::TimestampWithoutTimeZone => Ok(Self::new("TIMESTAMP_NTZ", None)),
+            DataType::TimestampWithTimeZone => Ok(Self::new("TIMESTAMP_TZ", None)),
+        }
+    }
+
+    /// If we can't store `ty` without losing information, describe what we'll
+    /// do instead.
+    pub(crate) fn lossy_for_data_type(ty: &DataType) -> Option<String> {
+        match ty {
+            DataType::Array(_) => Some(
+                "ARRAY, which doesn't keep the type of the array's elements"
+                    .to_owned(),
+            ),
+            DataType::Decimal => Some(
+                "NUMBER(38,9), which holds at most 29 digits before the decimal point and 9 after"
+                    .to_owned(),
+            ),
+            DataType::Struct(_) => Some(
+                "OBJECT, which doesn't keep the types of the struct's fields"
+                    .to_owned(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Convert this type to a portable `DataType`.
+    pub(crate) fn to_data_type(&self) -> Result<DataType> {
+        match (&self.name[..], self.args.as_ref().map(|args| &args[..])) {
+            ("NUMBER", Some(args)) if args.ends_with(",0") => Ok(DataType::Int64),
+            ("NUMBER", _) => Ok(DataType::Decimal),
+            ("FLOAT", _) => Ok(DataType::Float64),
+            ("TEXT", _) => Ok(DataType::Text),
+            ("BOOLEAN", _) => Ok(DataType::Bool),
+            ("DATE", _) => Ok(DataType::Date),
+            ("TIMESTAMP_NTZ", _) => Ok(DataType::TimestampWithoutTimeZone),
+            ("TIMESTAMP_LTZ", _) | ("TIMESTAMP_TZ", _) => {
+                Ok(DataType::TimestampWithTimeZone)
+            }
+            ("ARRAY", _) | ("OBJECT", _) | ("VARIANT", _) => Ok(DataType::Json),
+            ("GEOGRAPHY", _) => Ok(DataType::GeoJson(Srid::wgs84())),
+            _ => Err(format_err!(
+                "cannot convert Snowflake type {} to a portable type",
+                self
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SnowflakeDataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(args) = &self.args {
+            write!(f, "({})", args)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn converts_information_schema_types() {
+    let examples = &[
+        ("NUMBER", Some(0), DataType::Int64),
+        ("NUMBER", Some(2), DataType::Decimal),
+        ("FLOAT", None, DataType::Float64),
+        ("TEXT", None, DataType::Text),
+        ("TIMESTAMP_NTZ", None, DataType::TimestampWithoutTimeZone),
+        ("TIMESTAMP_LTZ", None, DataType::TimestampWithTimeZone),
+        ("VARIANT", None, DataType::Json),
+        ("GEOGRAPHY", None, DataType::GeoJson(Srid::wgs84())),
+    ];
+    for (data_type, scale, expected) in examples {
+        let ty = SnowflakeDataType::from_information_schema(data_type, *scale);
+        assert_eq!(&ty.to_data_type().unwrap(), expected, "{}", ty);
+    }
+    assert!(SnowflakeDataType::from_information_schema("BINARY", None)
+        .to_data_type()
+        .is_err());
+}
+
+#[test]
+fn formats_types_for_data_types() {
+    assert_eq!(
+        SnowflakeDataType::for_data_type(&DataType::Decimal)
+            .unwrap()
+            .to_string(),
+        "NUMBER(38,9)",
+    );
+    assert_eq!(
+        SnowflakeDataType::for_data_type(&DataType::TimestampWithTimeZone)
+            .unwrap()
+            .to_string(),
+        "TIMESTAMP_TZ",
+    );
+    assert!(
+        SnowflakeDataType::for_data_type(&DataType::GeoJson(Srid::new(3857))).is_err()
+    );
+}
@@ -0,0 +1,89 @@
+//! Driver arguments for `snowflake:` destinations.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::client::snowflake_quote_string;
+use crate::clouds::aws::{AwsCredentials, AwsRole};
+use crate::common::*;
+
+/// Parsed version of `--to-arg` for `snowflake:` destinations.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SnowflakeDestinationArguments {
+    /// Let Snowflake read from S3 using this storage integration, instead of
+    /// passing it our AWS credentials.
+    #[serde(default)]
+    storage_integration: Option<String>,
+
+    /// Assume this AWS role, and pass the temporary credentials to Snowflake.
+    #[serde(default)]
+    assume_role_arn: Option<String>,
+
+    /// The external ID to use when assuming `assume_role_arn`.
+    #[serde(default)]
+    external_id: Option<String>,
+}
+
+impl SnowflakeDestinationArguments {
+    /// Build the SQL which tells a Snowflake stage how to read from S3.
+    pub(crate) async fn stage_credentials_sql(&self, ctx: &Context) -> Result<String> {
+        let role = AwsRole::from_args(
+            self.assume_role_arn.as_deref(),
+            self.external_id.as_deref(),
+        )?;
+        if let Some(integration) = &self.storage_integration {
+            lazy_static! {
+                static ref INTEGRATION_RE: Regex =
+                    Regex::new("^[_A-Za-z][_A-Za-z0-9$]*$")
+                        .expect("invalid regex in source code");
+            }
+            if role.is_some() {
+                return Err(format_err!(
+                    "cannot pass both storage_integration and assume_role_arn"
+                ));
+            }
+            if !INTEGRATION_RE.is_match(integration) {
+                return Err(format_err!(
+                    "invalid Snowflake storage integration {:?}",
+                    integration
+                ));
+            }
+            return Ok(format!("STORAGE_INTEGRATION = {}", integration));
+        }
+        let creds = AwsCredentials::for_role(ctx, role.as_ref()).await?;
+        Ok(credentials_sql(&creds))
+    }
+}
+
+/// Build a `CREDENTIALS` clause for `creds`.
+fn credentials_sql(creds: &AwsCredentials) -> String {
+    let mut sql = format!(
+        "CREDENTIALS = (AWS_KEY_ID = {} AWS_SECRET_KEY = {}",
+        snowflake_quote_string(&creds.access_key_id),
+        snowflake_quote_string(&creds.secret_access_key),
+    );
+    if let Some(session_token) = &creds.session_token {
+        sql.push_str(&format!(
+            " AWS_TOKEN = {}",
+            snowflake_quote_string(session_token)
+        ));
+    }
+    sql.push(')');
+    sql
+}
+
+#[test]
+fn builds_credentials_sql() {
+    let creds = AwsCredentials {
+        access_key_id: "ASIAEXAMPLE".to_owned(),
+        secret_access_key: "secret".to_owned(),
+        session_token: Some("token".to_owned()),
+        default_region: None,
+    };
+    assert_eq!(
+        credentials_sql(&creds),
+        "CREDENTIALS = (AWS_KEY_ID = 'ASIAEXAMPLE' AWS_SECRET_KEY = 'secret' AWS_TOKEN = 'token')",
+    );
+}
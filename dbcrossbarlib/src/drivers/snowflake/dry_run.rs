@@ -0,0 +1,72 @@
+//! Implementation of `SnowflakeLocator::dry_run_read` and `dry_run_write`.
+
+use std::env;
+
+use super::{
+    driver_args::SnowflakeDestinationArguments, local_data::export_sql,
+    write_remote_data::LoadSql, SnowflakeLocator,
+};
+use crate::common::*;
+use crate::drivers::s3::{find_s3_temp_dir, S3Locator};
+
+/// Describe the SQL that `local_data` would run.
+pub(crate) async fn dry_run_read_helper(
+    source: SnowflakeLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Vec<String>> {
+    let shared_args = shared_args.verify(SnowflakeLocator::features())?;
+    let source_args = source_args.verify(SnowflakeLocator::features())?;
+    let tag = TemporaryStorage::random_tag();
+    let local_dir = env::temp_dir().join(format!("dbcrossbar-snowflake-{}", tag));
+    Ok(vec![export_sql(
+        shared_args.schema(),
+        source.table_name(),
+        source_args.where_clause(),
+        &format!("@~/dbcrossbar/{}/", tag),
+        &local_dir,
+    )?])
+}
+
+/// Describe the SQL that `write_local_data` would run, or that
+/// `write_remote_data` would run if we have a `source`.
+pub(crate) async fn dry_run_write_helper(
+    dest: SnowflakeLocator,
+    source: Option<BoxLocator>,
+    shared_args: SharedArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<String>> {
+    let shared_args = shared_args.verify(SnowflakeLocator::features())?;
+    let dest_args = dest_args.verify(SnowflakeLocator::features())?;
+    dest_args
+        .driver_args()
+        .deserialize::<SnowflakeDestinationArguments>()
+        .context("error parsing --to-args")?;
+
+    // Figure out where we'd load our CSV files from.
+    let mut sql = vec![];
+    let source_url = match source {
+        Some(source) => source
+            .as_any()
+            .downcast_ref::<S3Locator>()
+            .ok_or_else(|| format_err!("not a s3:// locator: {}", source))?
+            .as_url()
+            .to_owned(),
+        None => {
+            let s3_temp = find_s3_temp_dir(shared_args.temporary_storage())?;
+            sql.push(format!("-- upload CSV files to {}", s3_temp));
+            s3_temp.as_url().to_owned()
+        }
+    };
+
+    let tag = TemporaryStorage::random_tag();
+    let load = LoadSql {
+        schema: shared_args.schema(),
+        table_name: dest.table_name(),
+        if_exists: dest_args.if_exists(),
+        source_url: &source_url,
+        tag: &tag,
+    };
+    sql.push(load.to_sql("CREDENTIALS = (-- omitted\n)")?);
+    Ok(sql)
+}
@@ -0,0 +1,205 @@
+//! Reading data from a Snowflake table, by unloading it to an internal stage
+//! and downloading the files.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+use super::{
+    client::{query_lines, snowflake_quote_ident, snowflake_quote_string},
+    SnowflakeLocator,
+};
+use crate::common::*;
+use crate::schema::{Column, DataType};
+use crate::tokio_glue::copy_reader_to_stream;
+
+/// Copy the specified table from the database, returning a `CsvStream` for
+/// each file that Snowflake unloads.
+pub(crate) async fn local_data_helper(
+    ctx: Context,
+    source: SnowflakeLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<BoxStream<CsvStream>>> {
+    let shared_args = shared_args.verify(SnowflakeLocator::features())?;
+    let source_args = source_args.verify(SnowflakeLocator::features())?;
+    let schema = shared_args.schema();
+
+    let table_name = source.table_name().to_owned();
+    let ctx = ctx.child(o!("table" => table_name.clone()));
+    debug!(ctx.log(), "reading data from {}", source);
+
+    // Unload our table to our user stage, download it, and clean up the stage.
+    let tag = TemporaryStorage::random_tag();
+    let local_dir = env::temp_dir().join(format!("dbcrossbar-snowflake-{}", tag));
+    fs::create_dir_all(&local_dir)
+        .await
+        .with_context(|_| format!("cannot create {}", local_dir.display()))?;
+    let sql = export_sql(
+        schema,
+        &table_name,
+        source_args.where_clause(),
+        &format!("@~/dbcrossbar/{}/", tag),
+        &local_dir,
+    )?;
+    debug!(ctx.log(), "export SQL: {}", sql);
+    let export = query_lines(&ctx, source.url(), &sql);
+    ctx.audit_sql(&table_name, &sql, export).await?;
+
+    // Return each downloaded file as a stream.
+    let mut paths = vec![];
+    let mut entries = fs::read_dir(&local_dir)
+        .await
+        .with_context(|_| format!("cannot list {}", local_dir.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        paths.push(entry.path());
+    }
+    paths.sort();
+    debug!(ctx.log(), "downloaded {} files from Snowflake", paths.len());
+    let csv_streams = stream::iter(paths).map(Ok).and_then(move |path| {
+        let ctx = ctx.clone();
+        let local_dir = local_dir.clone();
+        async move { file_stream(ctx, local_dir, path).await }.boxed()
+    });
+    Ok(Some(csv_streams.boxed()))
+}
+
+/// Read the downloaded file at `path`, and delete it once we're done.
+async fn file_stream(
+    ctx: Context,
+    local_dir: PathBuf,
+    path: PathBuf,
+) -> Result<CsvStream> {
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| format_err!("cannot get stream name for {}", path.display()))?
+        .to_owned();
+    let ctx = ctx.child(o!("stream" => name.clone()));
+    let file = fs::File::open(&path)
+        .await
+        .with_context(|_| format!("cannot open {}", path.display()))?;
+    let rows = copy_reader_to_stream(ctx, file)?;
+    let cleanup = stream::once(async move {
+        fs::remove_file(&path)
+            .await
+            .with_context(|_| format!("cannot delete {}", path.display()))?;
+        // This only succeeds once all our files are gone.
+        let _ = fs::remove_dir(&local_dir).await;
+        Ok::<Option<BytesMut>, Error>(None)
+    })
+    .try_filter_map(|chunk| async move { Ok(chunk) });
+    Ok(CsvStream {
+        name,
+        data: rows.chain(cleanup).boxed(),
+    })
+}
+
+/// Generate SQL which unloads `table_name` to `stage_path` as CSV files, and
+/// downloads them to `local_dir`.
+pub(crate) fn export_sql(
+    schema: &Table,
+    table_name: &str,
+    where_clause: Option<&str>,
+    stage_path: &str,
+    local_dir: &Path,
+) -> Result<String> {
+    let local_dir = local_dir
+        .to_str()
+        .ok_or_else(|| format_err!("invalid path {}", local_dir.display()))?;
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| {
+            format!(
+                "{} AS {}",
+                export_expr(column),
+                snowflake_quote_ident(&column.name)
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut select = format!(
+        "SELECT {} FROM {}",
+        columns.join(", "),
+        snowflake_quote_ident(table_name),
+    );
+    if let Some(where_clause) = where_clause {
+        select.push_str(&format!(" WHERE ({})", where_clause));
+    }
+    Ok(format!(
+        "COPY INTO {stage} FROM ({select}) \
+         FILE_FORMAT = (TYPE = CSV COMPRESSION = NONE FIELD_OPTIONALLY_ENCLOSED_BY = '\"' \
+         NULL_IF = ('') EMPTY_FIELD_AS_NULL = FALSE) HEADER = TRUE; \
+         GET {stage} {local_url}; \
+         REMOVE {stage};",
+        stage = stage_path,
+        select = select,
+        local_url = snowflake_quote_string(&format!("file://{}/", local_dir)),
+    ))
+}
+
+/// An SQL expression which formats `column` the way `dbcrossbar` expects.
+fn export_expr(column: &Column) -> String {
+    let name = snowflake_quote_ident(&column.name);
+    match &column.data_type {
+        // `IFF` would turn `NULL` into `'false'`.
+        DataType::Bool => format!(
+            "CASE WHEN {0} THEN 'true' WHEN NOT {0} THEN 'false' END",
+            name,
+        ),
+        DataType::Date => format!("TO_VARCHAR({}, 'YYYY-MM-DD')", name),
+        DataType::TimestampWithoutTimeZone => format!(
+            "TO_VARCHAR({}, 'YYYY-MM-DD\"T\"HH24:MI:SS.FF6')",
+            name,
+        ),
+        DataType::TimestampWithTimeZone => format!(
+            "TO_VARCHAR(CONVERT_TIMEZONE('UTC', {}), 'YYYY-MM-DD\"T\"HH24:MI:SS.FF6\"Z\"')",
+            name,
+        ),
+        DataType::Array(_) | DataType::Json | DataType::Struct(_) => {
+            format!("TO_JSON({})", name)
+        }
+        DataType::GeoJson(_) => format!("TO_JSON(ST_ASGEOJSON({}))", name),
+        _ => name,
+    }
+}
+
+#[test]
+fn generates_export_sql() {
+    let schema = Table {
+        name: "orders".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "paid".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Bool,
+                comment: None,
+            },
+        ],
+    };
+    assert_eq!(
+        export_sql(
+            &schema,
+            "orders",
+            Some("id > 10"),
+            "@~/dbcrossbar/x/",
+            Path::new("/tmp/x"),
+        )
+        .unwrap(),
+        "COPY INTO @~/dbcrossbar/x/ FROM (SELECT \"id\" AS \"id\", \
+         CASE WHEN \"paid\" THEN 'true' WHEN NOT \"paid\" THEN 'false' END AS \"paid\" \
+         FROM \"orders\" WHERE (id > 10)) \
+         FILE_FORMAT = (TYPE = CSV COMPRESSION = NONE FIELD_OPTIONALLY_ENCLOSED_BY = '\"' \
+         NULL_IF = ('') EMPTY_FIELD_AS_NULL = FALSE) HEADER = TRUE; \
+         GET @~/dbcrossbar/x/ 'file:///tmp/x/'; \
+         REMOVE @~/dbcrossbar/x/;",
+    );
+}
@@ -0,0 +1,89 @@
+//! Reading table schemas from Snowflake's `information_schema`.
+
+use super::{
+    client::{query_lines, snowflake_quote_string},
+    SnowflakeDataType, SnowflakeLocator,
+};
+use crate::common::*;
+use crate::schema::Column;
+
+/// Implementation of `schema`, but as a real `async` function.
+pub(crate) async fn schema_helper(
+    ctx: Context,
+    source: SnowflakeLocator,
+) -> Result<Option<Table>> {
+    let table = fetch_table(&ctx, source.url(), source.table_name())
+        .await?
+        .ok_or_else(|| format_err!("no such table {}", source))?;
+    Ok(Some(table))
+}
+
+/// Look up `table_name` in the schema at `url`, returning `None` if it
+/// doesn't exist.
+pub(crate) async fn fetch_table(
+    ctx: &Context,
+    url: &UrlWithHiddenPassword,
+    table_name: &str,
+) -> Result<Option<Table>> {
+    debug!(ctx.log(), "fetching Snowflake schema for {}", table_name);
+    let sql = format!(
+        "SELECT column_name, data_type, COALESCE(numeric_scale, -1), is_nullable \
+         FROM information_schema.columns \
+         WHERE table_schema = CURRENT_SCHEMA() AND table_name = {} \
+         ORDER BY ordinal_position",
+        snowflake_quote_string(table_name),
+    );
+    let lines = query_lines(ctx, url, &sql).await?;
+    if lines.is_empty() {
+        return Ok(None);
+    }
+    let columns = lines
+        .iter()
+        .map(|line| parse_column(line))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|_| format!("could not read schema for {}", table_name))?;
+    Ok(Some(Table {
+        name: table_name.to_owned(),
+        columns,
+    }))
+}
+
+/// Parse a tab-separated line from our `information_schema` query.
+fn parse_column(line: &str) -> Result<Column> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    if fields.len() != 4 {
+        return Err(format_err!(
+            "unexpected Snowflake column information {:?}",
+            line
+        ));
+    }
+    // We use -1 to represent a `NULL` scale.
+    let numeric_scale = fields[2].parse::<u32>().ok();
+    let data_type =
+        SnowflakeDataType::from_information_schema(fields[1], numeric_scale)
+            .to_data_type()
+            .with_context(|_| format!("cannot read column {}", fields[0]))?;
+    Ok(Column {
+        name: fields[0].to_owned(),
+        is_nullable: fields[3] == "YES",
+        data_type,
+        comment: None,
+    })
+}
+
+#[test]
+fn parses_information_schema_columns() {
+    use crate::schema::DataType;
+
+    let column = parse_column("ID\tNUMBER\t0\tNO").unwrap();
+    assert_eq!(column.name, "ID");
+    assert!(!column.is_nullable);
+    assert_eq!(column.data_type, DataType::Int64);
+
+    let column = parse_column("NOTE\tTEXT\t-1\tYES").unwrap();
+    assert!(column.is_nullable);
+    assert_eq!(column.data_type, DataType::Text);
+
+    assert!(parse_column("DATA\tBINARY\t-1\tYES").is_err());
+    assert!(parse_column("ID\tNUMBER").is_err());
+}
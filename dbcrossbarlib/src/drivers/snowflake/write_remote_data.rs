@@ -0,0 +1,307 @@
+//! Implementation of `SnowflakeLocator::write_remote_data`, which loads CSV
+//! files from S3 using a temporary external stage.
+
+use super::{
+    client::{query_lines, snowflake_quote_ident, snowflake_quote_string},
+    driver_args::SnowflakeDestinationArguments,
+    SnowflakeDataType, SnowflakeLocator,
+};
+use crate::common::*;
+use crate::drivers::s3::S3Locator;
+use crate::schema::{Column, DataType};
+
+/// Copy `source` to `dest` using `schema`.
+pub(crate) async fn write_remote_data_helper(
+    ctx: Context,
+    source: BoxLocator,
+    dest: SnowflakeLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<Vec<BoxLocator>> {
+    let source_url = source
+        .as_any()
+        .downcast_ref::<S3Locator>()
+        .ok_or_else(|| format_err!("not a s3:// locator: {}", source))?
+        .as_url()
+        .to_owned();
+    let ctx = ctx.child(o!("source_url" => source_url.as_str().to_owned()));
+
+    let shared_args = shared_args.verify(SnowflakeLocator::features())?;
+    let _source_args = source_args.verify(Features::empty())?;
+    let dest_args = dest_args.verify(SnowflakeLocator::features())?;
+
+    // Look up our arguments.
+    let schema = shared_args.schema();
+    let if_exists = dest_args.if_exists().to_owned();
+    let to_args = dest_args
+        .driver_args()
+        .deserialize::<SnowflakeDestinationArguments>()
+        .context("error parsing --to-args")?;
+    let credentials = to_args.stage_credentials_sql(&ctx).await?;
+
+    // Build and run our SQL. We run everything in one `snowsql` session,
+    // because our stage and our upsert table are both temporary.
+    let tag = TemporaryStorage::random_tag();
+    let load = LoadSql {
+        schema,
+        table_name: dest.table_name(),
+        if_exists: &if_exists,
+        source_url: &source_url,
+        tag: &tag,
+    };
+    let sql = load.to_sql(&credentials)?;
+    // Never write our credentials to the audit log.
+    let audited_sql = load.to_sql("CREDENTIALS = (-- omitted\n)")?;
+    debug!(ctx.log(), "load SQL: {}", audited_sql);
+    let load = query_lines(&ctx, dest.url(), &sql);
+    ctx.audit_sql(dest.table_name(), &audited_sql, load)
+        .await
+        .with_context(|_| format!("error copying to {} from {}", dest, source_url))?;
+
+    Ok(vec![dest.boxed()])
+}
+
+/// The information we need to generate SQL for loading data from S3.
+pub(crate) struct LoadSql<'a> {
+    pub(crate) schema: &'a Table,
+    pub(crate) table_name: &'a str,
+    pub(crate) if_exists: &'a IfExists,
+    pub(crate) source_url: &'a Url,
+    pub(crate) tag: &'a str,
+}
+
+impl<'a> LoadSql<'a> {
+    /// Generate SQL which loads our CSV files from S3, using `credentials` to
+    /// access them.
+    pub(crate) fn to_sql(&self, credentials: &str) -> Result<String> {
+        let stage = snowflake_quote_ident(&format!("dbcrossbar_stage_{}", self.tag));
+        let table = snowflake_quote_ident(self.table_name);
+        let mut sql = vec![
+            format!(
+                "CREATE TEMPORARY STAGE {} URL = {} {}",
+                stage,
+                snowflake_quote_string(self.source_url.as_str()),
+                credentials,
+            ),
+            prepare_table_sql(self.schema, self.table_name, self.if_exists)?,
+        ];
+        if let IfExists::Upsert(keys) = self.if_exists {
+            let temp =
+                snowflake_quote_ident(&format!("dbcrossbar_upsert_{}", self.tag));
+            sql.push(format!("CREATE TEMPORARY TABLE {} LIKE {}", temp, table));
+            sql.push(copy_sql(self.schema, &temp, &stage));
+            sql.push(merge_sql(self.schema, &table, &temp, keys));
+        } else {
+            sql.push(copy_sql(self.schema, &table, &stage));
+        }
+        Ok(sql.join(";\n") + ";")
+    }
+}
+
+/// Generate SQL to prepare `table_name` according to `if_exists`.
+fn prepare_table_sql(
+    schema: &Table,
+    table_name: &str,
+    if_exists: &IfExists,
+) -> Result<String> {
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| {
+            let mut def = format!(
+                "{} {}",
+                snowflake_quote_ident(&column.name),
+                SnowflakeDataType::for_data_type(&column.data_type).with_context(
+                    |_| format!("cannot write column {}", column.name)
+                )?,
+            );
+            if !column.is_nullable {
+                def.push_str(" NOT NULL");
+            }
+            Ok(def)
+        })
+        .collect::<Result<Vec<_>>>()?
+        .join(", ");
+    let table_name = snowflake_quote_ident(table_name);
+    match if_exists {
+        IfExists::Error => Ok(format!("CREATE TABLE {} ({})", table_name, columns)),
+        IfExists::Append => Ok(format!(
+            "CREATE TABLE IF NOT EXISTS {} ({})",
+            table_name, columns,
+        )),
+        IfExists::Overwrite => Ok(format!(
+            "CREATE OR REPLACE TABLE {} ({})",
+            table_name, columns,
+        )),
+        IfExists::Upsert(keys) => {
+            for key in keys {
+                if !schema.columns.iter().any(|column| &column.name == key) {
+                    return Err(format_err!(
+                        "upsert key {:?} is not a column in {}",
+                        key,
+                        table_name,
+                    ));
+                }
+            }
+            Ok(format!(
+                "CREATE TABLE IF NOT EXISTS {} ({})",
+                table_name, columns,
+            ))
+        }
+    }
+}
+
+/// Generate `COPY INTO` SQL which loads every CSV file in `stage` into `table`.
+///
+/// We always list our columns, in the same order as our CSV files, so that we
+/// can load into an existing table whose columns are in a different order.
+fn copy_sql(schema: &Table, table: &str, stage: &str) -> String {
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| snowflake_quote_ident(&column.name))
+        .collect::<Vec<_>>();
+    let values = schema
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(idx, column)| import_expr(column, &format!("${}", idx + 1)))
+        .collect::<Vec<_>>();
+    format!(
+        "COPY INTO {} ({}) FROM (SELECT {} FROM @{}) \
+         FILE_FORMAT = (TYPE = CSV SKIP_HEADER = 1 FIELD_OPTIONALLY_ENCLOSED_BY = '\"' \
+         NULL_IF = (''))",
+        table,
+        columns.join(", "),
+        values.join(", "),
+        stage,
+    )
+}
+
+/// An SQL expression which converts the CSV cell `cell` to a value for
+/// `column`.
+fn import_expr(column: &Column, cell: &str) -> String {
+    match &column.data_type {
+        DataType::Array(_) | DataType::Json | DataType::Struct(_) => {
+            format!("PARSE_JSON({})", cell)
+        }
+        DataType::GeoJson(_) => format!("TO_GEOGRAPHY({})", cell),
+        _ => cell.to_owned(),
+    }
+}
+
+/// Generate SQL which merges `temp` into `table`, matching rows on `keys`.
+fn merge_sql(schema: &Table, table: &str, temp: &str, keys: &[String]) -> String {
+    let matches = keys
+        .iter()
+        .map(|key| {
+            let key = snowflake_quote_ident(key);
+            format!("d.{0} = s.{0}", key)
+        })
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    let updates = schema
+        .columns
+        .iter()
+        .filter(|column| !keys.contains(&column.name))
+        .map(|column| {
+            let name = snowflake_quote_ident(&column.name);
+            format!("d.{0} = s.{0}", name)
+        })
+        .collect::<Vec<_>>();
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| snowflake_quote_ident(&column.name))
+        .collect::<Vec<_>>();
+    let values = columns
+        .iter()
+        .map(|column| format!("s.{}", column))
+        .collect::<Vec<_>>();
+    let mut sql = format!(
+        "MERGE INTO {} AS d USING {} AS s ON {}",
+        table, temp, matches,
+    );
+    // `UPDATE SET` needs at least one column.
+    if !updates.is_empty() {
+        sql.push_str(&format!(
+            " WHEN MATCHED THEN UPDATE SET {}",
+            updates.join(", ")
+        ));
+    }
+    sql.push_str(&format!(
+        " WHEN NOT MATCHED THEN INSERT ({}) VALUES ({})",
+        columns.join(", "),
+        values.join(", "),
+    ));
+    sql
+}
+
+#[cfg(test)]
+fn example_schema() -> Table {
+    Table {
+        name: "orders".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "extra".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Json,
+                comment: None,
+            },
+        ],
+    }
+}
+
+#[test]
+fn generates_load_sql() {
+    let schema = example_schema();
+    let source_url = "s3://bucket/dir/".parse::<Url>().unwrap();
+    let load = LoadSql {
+        schema: &schema,
+        table_name: "orders",
+        if_exists: &IfExists::Overwrite,
+        source_url: &source_url,
+        tag: "x",
+    };
+    assert_eq!(
+        load.to_sql("STORAGE_INTEGRATION = s3_int").unwrap(),
+        "CREATE TEMPORARY STAGE \"dbcrossbar_stage_x\" URL = 's3://bucket/dir/' STORAGE_INTEGRATION = s3_int;\n\
+         CREATE OR REPLACE TABLE \"orders\" (\"id\" BIGINT NOT NULL, \"extra\" VARIANT);\n\
+         COPY INTO \"orders\" (\"id\", \"extra\") FROM (SELECT $1, PARSE_JSON($2) FROM @\"dbcrossbar_stage_x\") \
+         FILE_FORMAT = (TYPE = CSV SKIP_HEADER = 1 FIELD_OPTIONALLY_ENCLOSED_BY = '\"' NULL_IF = (''));",
+    );
+    assert!(prepare_table_sql(
+        &schema,
+        "orders",
+        &IfExists::Upsert(vec!["missing".to_owned()])
+    )
+    .is_err());
+}
+
+#[test]
+fn generates_merge_sql() {
+    let schema = example_schema();
+    assert_eq!(
+        merge_sql(&schema, "\"orders\"", "\"temp\"", &["id".to_owned()]),
+        "MERGE INTO \"orders\" AS d USING \"temp\" AS s ON d.\"id\" = s.\"id\" \
+         WHEN MATCHED THEN UPDATE SET d.\"extra\" = s.\"extra\" \
+         WHEN NOT MATCHED THEN INSERT (\"id\", \"extra\") VALUES (s.\"id\", s.\"extra\")",
+    );
+    assert_eq!(
+        merge_sql(
+            &schema,
+            "\"orders\"",
+            "\"temp\"",
+            &["id".to_owned(), "extra".to_owned()]
+        ),
+        "MERGE INTO \"orders\" AS d USING \"temp\" AS s ON d.\"id\" = s.\"id\" AND d.\"extra\" = s.\"extra\" \
+         WHEN NOT MATCHED THEN INSERT (\"id\", \"extra\") VALUES (s.\"id\", s.\"extra\")",
+    );
+}
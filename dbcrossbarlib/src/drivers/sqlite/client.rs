@@ -0,0 +1,75 @@
+//! Running the `sqlite3` command-line client.
+//!
+//! We talk to SQLite using `sqlite3` instead of linking against the SQLite
+//! library, in the same way that we use `mysql` for MySQL.
+
+use std::{path::Path, process::Stdio};
+use tokio::process::Command;
+
+use crate::common::*;
+
+/// How long should `sqlite3` wait for another process to release its lock
+/// on the database, in milliseconds?
+const BUSY_TIMEOUT_MS: u32 = 30_000;
+
+/// Build an `sqlite3` command which opens the database at `path`, stops at
+/// the first error, and prints query results as CSV without headers.
+pub(crate) fn sqlite_command(path: &Path) -> Command {
+    let mut command = Command::new("sqlite3");
+    command
+        .args(["-batch", "-bail", "-csv", "-noheader"])
+        // `.mode csv` uses `\r\n` line endings, so ask for `\n` explicitly.
+        .args(["-newline", "\n"])
+        .arg("-cmd")
+        .arg(format!(".timeout {}", BUSY_TIMEOUT_MS))
+        .arg(path);
+    command
+}
+
+/// Run `sql` against the database at `path`, and return each row of output.
+pub(crate) async fn query_rows(
+    ctx: &Context,
+    path: &Path,
+    sql: &str,
+) -> Result<Vec<csv::StringRecord>> {
+    trace!(ctx.log(), "running SQLite query: {}", sql);
+    let output = sqlite_command(path)
+        .arg(sql)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("error running `sqlite3`")?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "`sqlite3` failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(&output.stdout[..]);
+    let rows = rdr
+        .records()
+        .collect::<Result<Vec<_>, _>>()
+        .context("`sqlite3` printed invalid CSV")?;
+    Ok(rows)
+}
+
+/// Quote `ident` as an SQLite identifier.
+pub(crate) fn sqlite_quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Quote `s` as an SQLite string literal. Backslashes have no special meaning
+/// in SQLite strings.
+pub(crate) fn sqlite_quote_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+#[test]
+fn quotes_identifiers_and_strings() {
+    assert_eq!(sqlite_quote_ident(r#"my "table""#), r#""my ""table""""#);
+    assert_eq!(sqlite_quote_string(r"it's a \ test"), r"'it''s a \ test'");
+}
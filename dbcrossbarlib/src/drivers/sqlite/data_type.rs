@@ -0,0 +1,149 @@
+//! SQLite column types.
+//!
+//! SQLite stores values using a handful of storage classes, and only uses a
+//! column's declared type to choose a "type affinity". So when we create
+//! tables, we declare types which describe the portable type we want to read
+//! back, and which also give SQLite the right affinity.
+
+use crate::common::*;
+use crate::schema::DataType;
+
+/// The type we declare for columns of type `ty`.
+pub(crate) fn declared_type_for(ty: &DataType) -> &'static str {
+    match ty {
+        DataType::Array(_)
+        | DataType::GeoJson(_)
+        | DataType::Json
+        | DataType::Struct(_) => "JSON",
+        DataType::Bool => "BOOLEAN",
+        DataType::Date => "DATE",
+        DataType::Decimal => "NUMERIC",
+        DataType::Float32 | DataType::Float64 => "REAL",
+        DataType::Int16 | DataType::Int32 | DataType::Int64 => "INTEGER",
+        DataType::Text => "TEXT",
+        DataType::TimestampWithoutTimeZone => "DATETIME",
+        DataType::TimestampWithTimeZone => "TIMESTAMPTZ",
+        DataType::Uuid => "UUID",
+    }
+}
+
+/// If we can't store `ty` without losing information, describe what we'll do
+/// instead.
+pub(crate) fn lossy_for_data_type(ty: &DataType) -> Option<String> {
+    match ty {
+        DataType::Array(_) => Some(
+            "JSON text, which doesn't keep the type of the array's elements"
+                .to_owned(),
+        ),
+        DataType::Decimal => Some(
+            "NUMERIC, which SQLite may store as a 64-bit floating point number"
+                .to_owned(),
+        ),
+        DataType::GeoJson(_) => Some("JSON text, without a spatial index".to_owned()),
+        DataType::Struct(_) => Some(
+            "JSON text, which doesn't keep the types of the struct's fields"
+                .to_owned(),
+        ),
+        _ => None,
+    }
+}
+
+/// Convert a declared SQLite column type to a portable `DataType`.
+///
+/// We recognize the types we declare ourselves, plus common names from other
+/// databases. Anything else is handled using SQLite's own affinity rules.
+pub(crate) fn data_type_for_declared(declared: &str) -> Result<DataType> {
+    let declared = declared.trim().to_ascii_uppercase();
+    // Ignore arguments like the `255` in `VARCHAR(255)`.
+    let name = declared
+        .split('(')
+        .next()
+        .expect("split always returns at least one value")
+        .trim();
+    match name {
+        "BOOL" | "BOOLEAN" => return Ok(DataType::Bool),
+        "DATE" => return Ok(DataType::Date),
+        "DATETIME" | "TIMESTAMP" | "TIMESTAMP WITHOUT TIME ZONE" => {
+            return Ok(DataType::TimestampWithoutTimeZone)
+        }
+        "TIMESTAMPTZ" | "TIMESTAMP WITH TIME ZONE" => {
+            return Ok(DataType::TimestampWithTimeZone)
+        }
+        "JSON" | "JSONB" => return Ok(DataType::Json),
+        "UUID" => return Ok(DataType::Uuid),
+        "DECIMAL" | "NUMERIC" => return Ok(DataType::Decimal),
+        _ => {}
+    }
+
+    // These are the affinity rules from "Datatypes In SQLite", in order.
+    if name.contains("INT") {
+        Ok(DataType::Int64)
+    } else if name.contains("CHAR") || name.contains("CLOB") || name.contains("TEXT") {
+        Ok(DataType::Text)
+    } else if name.contains("BLOB") {
+        Err(format_err!(
+            "cannot convert SQLite type {} to a portable type",
+            declared
+        ))
+    } else if name.contains("REAL") || name.contains("FLOA") || name.contains("DOUB") {
+        Ok(DataType::Float64)
+    } else if name.is_empty() {
+        // Columns without a declared type can hold anything, so treat them
+        // as text.
+        Ok(DataType::Text)
+    } else {
+        Err(format_err!(
+            "cannot convert SQLite type {} to a portable type",
+            declared
+        ))
+    }
+}
+
+#[test]
+fn parses_declared_types() {
+    let examples = &[
+        ("INTEGER", DataType::Int64),
+        ("bigint", DataType::Int64),
+        ("BOOLEAN", DataType::Bool),
+        ("varchar(255)", DataType::Text),
+        ("TEXT", DataType::Text),
+        ("", DataType::Text),
+        ("double precision", DataType::Float64),
+        ("REAL", DataType::Float64),
+        ("NUMERIC(10,2)", DataType::Decimal),
+        ("DATE", DataType::Date),
+        ("DATETIME", DataType::TimestampWithoutTimeZone),
+        ("TIMESTAMPTZ", DataType::TimestampWithTimeZone),
+        ("JSON", DataType::Json),
+        ("UUID", DataType::Uuid),
+    ];
+    for (declared, expected) in examples {
+        assert_eq!(
+            &data_type_for_declared(declared).unwrap(),
+            expected,
+            "{}",
+            declared
+        );
+    }
+    assert!(data_type_for_declared("BLOB").is_err());
+    assert!(data_type_for_declared("GEOMETRY").is_err());
+}
+
+#[test]
+fn declared_types_round_trip() {
+    let examples = &[
+        DataType::Bool,
+        DataType::Date,
+        DataType::Decimal,
+        DataType::Float64,
+        DataType::Int64,
+        DataType::Json,
+        DataType::Text,
+        DataType::TimestampWithoutTimeZone,
+        DataType::TimestampWithTimeZone,
+        DataType::Uuid,
+    ];
+    for ty in examples {
+        assert_eq!(&data_type_for_declared(declared_type_for(ty)).unwrap(), ty);
+    }
+}
@@ -0,0 +1,131 @@
+//! Reading data from an SQLite table.
+
+use std::process::Stdio;
+use tokio::io::BufReader;
+
+use super::{
+    client::{sqlite_command, sqlite_quote_ident},
+    SqliteLocator,
+};
+use crate::common::*;
+use crate::schema::{Column, DataType};
+use crate::tokio_glue::copy_reader_to_stream;
+
+/// Copy the specified table from the database, returning a `CsvStream`.
+pub(crate) async fn local_data_helper(
+    ctx: Context,
+    source: SqliteLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<BoxStream<CsvStream>>> {
+    let shared_args = shared_args.verify(SqliteLocator::features())?;
+    let source_args = source_args.verify(SqliteLocator::features())?;
+    let schema = shared_args.schema();
+
+    let table_name = source.table_name().to_owned();
+    let ctx =
+        ctx.child(o!("stream" => table_name.clone(), "table" => table_name.clone()));
+    debug!(ctx.log(), "reading data from {}", source);
+    if !source.path().exists() {
+        return Err(format_err!("no such database {}", source.path().display()));
+    }
+
+    // `sqlite3` can print query results as CSV, so stream them back to us.
+    let sql = export_sql(schema, &table_name, source_args.where_clause());
+    debug!(ctx.log(), "export SQL: {}", sql);
+    let mut child = sqlite_command(source.path())
+        .arg(&sql)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("error running `sqlite3`")?;
+    let child_stdout = child.stdout.take().expect("child should have stdout");
+    let child_stdout = BufReader::with_capacity(BUFFER_SIZE, child_stdout);
+    let rows = copy_reader_to_stream(ctx.clone(), child_stdout)?;
+    ctx.spawn_process(format!("sqlite3 export from {}", source), child);
+
+    // `sqlite3` doesn't print a header for empty results, so add our own.
+    let data = box_stream_once(Ok(csv_header(schema)?)).chain(rows).boxed();
+    let csv_stream = CsvStream {
+        name: table_name,
+        data,
+    };
+    Ok(Some(box_stream_once(Ok(csv_stream))))
+}
+
+/// A CSV header line for `schema`.
+fn csv_header(schema: &Table) -> Result<BytesMut> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    wtr.write_record(schema.columns.iter().map(|c| &c.name))?;
+    let header = wtr
+        .into_inner()
+        .map_err(|_| format_err!("could not write CSV header"))?;
+    Ok(BytesMut::from(&header[..]))
+}
+
+/// Generate SQL which selects each row of `table_name`, formatted for CSV.
+fn export_sql(schema: &Table, table_name: &str, where_clause: Option<&str>) -> String {
+    let cells = schema
+        .columns
+        .iter()
+        .map(export_cell_expr)
+        .collect::<Vec<_>>();
+    let mut sql = format!(
+        "SELECT {} FROM {}",
+        cells.join(", "),
+        sqlite_quote_ident(table_name),
+    );
+    if let Some(where_clause) = where_clause {
+        sql.push_str(&format!(" WHERE ({})", where_clause));
+    }
+    sql
+}
+
+/// An SQL expression which formats `column` for our CSV output. `sqlite3`
+/// prints `NULL` as an empty cell.
+fn export_cell_expr(column: &Column) -> String {
+    let name = sqlite_quote_ident(&column.name);
+    match &column.data_type {
+        // SQLite stores booleans as integers, but accept text values, too.
+        DataType::Bool => format!(
+            "CASE WHEN {0} IS NULL THEN NULL \
+             WHEN LOWER({0}) IN ('1', 't', 'true', 'y', 'yes', 'on') THEN 'true' \
+             ELSE 'false' END",
+            name,
+        ),
+        // By default, `sqlite3` only prints 15 significant digits.
+        DataType::Float32 | DataType::Float64 => format!(
+            "CASE WHEN {0} IS NULL THEN NULL ELSE printf('%!.17g', {0}) END",
+            name,
+        ),
+        _ => name,
+    }
+}
+
+#[test]
+fn generates_export_sql() {
+    let schema = Table {
+        name: "orders".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "paid".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Bool,
+                comment: None,
+            },
+        ],
+    };
+    assert_eq!(
+        export_sql(&schema, "orders", Some("id > 10")),
+        "SELECT \"id\", CASE WHEN \"paid\" IS NULL THEN NULL \
+         WHEN LOWER(\"paid\") IN ('1', 't', 'true', 'y', 'yes', 'on') THEN 'true' \
+         ELSE 'false' END FROM \"orders\" WHERE (id > 10)",
+    );
+    assert_eq!(&csv_header(&schema).unwrap()[..], &b"id,paid\n"[..]);
+}
@@ -0,0 +1,171 @@
+//! A driver for working with SQLite, using the `sqlite3` command-line client.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::common::*;
+use crate::limits::Limits;
+use crate::reserved_words;
+use crate::schema::DataType;
+
+mod client;
+mod data_type;
+mod local_data;
+mod schema;
+mod write_local_data;
+
+use self::local_data::local_data_helper;
+use self::schema::schema_helper;
+use self::write_local_data::write_local_data_helper;
+
+/// An SQLite database file and a table name, such as
+/// `sqlite:path/to/db.sqlite3#table`.
+#[derive(Clone, Debug)]
+pub struct SqliteLocator {
+    path: PathBuf,
+    table_name: String,
+}
+
+impl SqliteLocator {
+    /// The path to our database file.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The table name associated with this locator.
+    pub(crate) fn table_name(&self) -> &str {
+        &self.table_name
+    }
+}
+
+impl fmt::Display for SqliteLocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}#{}",
+            Self::scheme(),
+            self.path.display(),
+            self.table_name
+        )
+    }
+}
+
+impl FromStr for SqliteLocator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if !s.starts_with(Self::scheme()) {
+            return Err(format_err!("expected {} to begin with sqlite:", s));
+        }
+        let rest = &s[Self::scheme().len()..];
+        let (path, table_name) = match rest.rfind('#') {
+            Some(idx) => (&rest[..idx], &rest[idx + 1..]),
+            None => {
+                return Err(format_err!(
+                    "SQLite locator needs to end with #table_name"
+                ))
+            }
+        };
+        if path.is_empty() {
+            return Err(format_err!(
+                "SQLite locator must include a path, as in sqlite:db.sqlite3#table"
+            ));
+        }
+        if table_name.is_empty() {
+            return Err(format_err!("SQLite locator needs to end with #table_name"));
+        }
+        Ok(SqliteLocator {
+            path: PathBuf::from(path),
+            table_name: table_name.to_owned(),
+        })
+    }
+}
+
+#[test]
+fn from_str_parses_paths_and_table_names() {
+    let l = "sqlite:data/dev.sqlite3#orders"
+        .parse::<SqliteLocator>()
+        .unwrap();
+    assert_eq!(l.path(), Path::new("data/dev.sqlite3"));
+    assert_eq!(l.table_name(), "orders");
+    assert_eq!(l.to_string(), "sqlite:data/dev.sqlite3#orders");
+    assert!("sqlite:data/dev.sqlite3".parse::<SqliteLocator>().is_err());
+    assert!("sqlite:#orders".parse::<SqliteLocator>().is_err());
+    assert!("sqlite:dev.sqlite3#".parse::<SqliteLocator>().is_err());
+    assert!("csv:dev.sqlite3#orders".parse::<SqliteLocator>().is_err());
+}
+
+impl Locator for SqliteLocator {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self, ctx: Context) -> BoxFuture<Option<Table>> {
+        schema_helper(ctx, self.to_owned()).boxed()
+    }
+
+    fn local_data(
+        &self,
+        ctx: Context,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<BoxStream<CsvStream>>> {
+        local_data_helper(ctx, self.to_owned(), shared_args, source_args).boxed()
+    }
+
+    fn write_local_data(
+        &self,
+        ctx: Context,
+        data: BoxStream<CsvStream>,
+        shared_args: SharedArguments<Unverified>,
+        dest_args: DestinationArguments<Unverified>,
+    ) -> BoxFuture<BoxStream<BoxFuture<BoxLocator>>> {
+        write_local_data_helper(ctx, self.to_owned(), data, shared_args, dest_args)
+            .boxed()
+    }
+}
+
+impl LocatorStatic for SqliteLocator {
+    fn scheme() -> &'static str {
+        "sqlite:"
+    }
+
+    fn features() -> Features {
+        Features {
+            locator: LocatorFeatures::Schema
+                | LocatorFeatures::LocalData
+                | LocatorFeatures::WriteLocalData,
+            write_schema_if_exists: EnumSet::empty(),
+            source_args: SourceArgumentsFeatures::WhereClause.into(),
+            dest_args: EnumSet::empty(),
+            dest_if_exists: IfExistsFeatures::Overwrite
+                | IfExistsFeatures::Append
+                | IfExistsFeatures::Error,
+            _placeholder: (),
+        }
+    }
+
+    /// This locator type is currently unstable.
+    fn is_unstable() -> bool {
+        true
+    }
+
+    fn lossy_data_type(data_type: &DataType) -> Option<String> {
+        data_type::lossy_for_data_type(data_type)
+    }
+
+    fn is_reserved_word(name: &str) -> bool {
+        reserved_words::is_reserved(reserved_words::SQLITE, name)
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            // This is the default `SQLITE_MAX_COLUMN`.
+            max_columns: Some(2000),
+            max_name_length: None,
+        }
+    }
+}
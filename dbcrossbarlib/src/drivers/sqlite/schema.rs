@@ -0,0 +1,104 @@
+//! Reading table schemas from SQLite using `PRAGMA table_info`.
+
+use std::path::Path;
+
+use super::{
+    client::{query_rows, sqlite_quote_string},
+    data_type::data_type_for_declared,
+    SqliteLocator,
+};
+use crate::common::*;
+use crate::schema::Column;
+
+/// Implementation of `schema`, but as a real `async` function.
+pub(crate) async fn schema_helper(
+    ctx: Context,
+    source: SqliteLocator,
+) -> Result<Option<Table>> {
+    let table = fetch_table(&ctx, source.path(), source.table_name())
+        .await?
+        .ok_or_else(|| format_err!("no such table {}", source))?;
+    Ok(Some(table))
+}
+
+/// Look up `table_name` in the database at `path`, returning `None` if it
+/// doesn't exist.
+pub(crate) async fn fetch_table(
+    ctx: &Context,
+    path: &Path,
+    table_name: &str,
+) -> Result<Option<Table>> {
+    debug!(ctx.log(), "fetching SQLite schema for {}", table_name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let sql = format!(
+        "SELECT name, type, \"notnull\", pk FROM pragma_table_info({}) ORDER BY cid",
+        sqlite_quote_string(table_name),
+    );
+    let rows = query_rows(ctx, path, &sql).await?;
+    if rows.is_empty() {
+        return Ok(None);
+    }
+    let columns = rows
+        .iter()
+        .map(parse_column)
+        .collect::<Result<Vec<_>>>()
+        .with_context(|_| format!("could not read schema for {}", table_name))?;
+    Ok(Some(Table {
+        name: table_name.to_owned(),
+        columns,
+    }))
+}
+
+/// Parse a row from `PRAGMA table_info`.
+fn parse_column(row: &csv::StringRecord) -> Result<Column> {
+    if row.len() != 4 {
+        return Err(format_err!(
+            "unexpected SQLite column information {:?}",
+            row
+        ));
+    }
+    let data_type = data_type_for_declared(&row[1])
+        .with_context(|_| format!("cannot read column {}", &row[0]))?;
+    // `INTEGER PRIMARY KEY` columns are aliases for the `rowid`, so they can
+    // never be `NULL`, even if they aren't declared `NOT NULL`.
+    let is_integer_primary_key =
+        &row[3] != "0" && row[1].trim().eq_ignore_ascii_case("INTEGER");
+    Ok(Column {
+        name: row[0].to_owned(),
+        is_nullable: &row[2] == "0" && !is_integer_primary_key,
+        data_type,
+        comment: None,
+    })
+}
+
+#[test]
+fn parses_table_info_rows() {
+    use crate::schema::DataType;
+
+    let column =
+        parse_column(&csv::StringRecord::from(vec!["id", "INTEGER", "0", "1"]))
+            .unwrap();
+    assert_eq!(column.name, "id");
+    assert!(!column.is_nullable);
+    assert_eq!(column.data_type, DataType::Int64);
+
+    let column = parse_column(&csv::StringRecord::from(vec![
+        "active", "BOOLEAN", "0", "0",
+    ]))
+    .unwrap();
+    assert!(column.is_nullable);
+    assert_eq!(column.data_type, DataType::Bool);
+
+    let column =
+        parse_column(&csv::StringRecord::from(vec!["name", "TEXT", "1", "0"]))
+            .unwrap();
+    assert!(!column.is_nullable);
+
+    assert!(
+        parse_column(&csv::StringRecord::from(vec!["picture", "BLOB", "0", "0"]))
+            .is_err()
+    );
+    assert!(parse_column(&csv::StringRecord::from(vec!["garbage"])).is_err());
+}
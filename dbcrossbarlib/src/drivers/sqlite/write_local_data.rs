@@ -0,0 +1,333 @@
+//! Writing data to an SQLite table using batched `INSERT` statements.
+
+use std::{process::Stdio, sync::Arc};
+use tokio::sync::Mutex;
+
+use super::{
+    client::{query_rows, sqlite_command, sqlite_quote_ident, sqlite_quote_string},
+    data_type::declared_type_for,
+    SqliteLocator,
+};
+use crate::common::*;
+use crate::from_csv_cell::FromCsvCell;
+use crate::schema::{Column, DataType};
+use crate::tokio_glue::copy_stream_to_writer;
+use crate::transform::spawn_sync_transform;
+
+/// How many rows should we include in each `INSERT` statement?
+const ROWS_PER_INSERT: usize = 500;
+
+/// How many rows should we insert in each transaction? This must be a
+/// multiple of `ROWS_PER_INSERT`.
+const ROWS_PER_TRANSACTION: usize = 10_000;
+
+/// Implementation of `write_local_data`, but as a real `async` function.
+pub(crate) async fn write_local_data_helper(
+    ctx: Context,
+    dest: SqliteLocator,
+    data: BoxStream<CsvStream>,
+    shared_args: SharedArguments<Unverified>,
+    dest_args: DestinationArguments<Unverified>,
+) -> Result<BoxStream<BoxFuture<BoxLocator>>> {
+    let shared_args = shared_args.verify(SqliteLocator::features())?;
+    let dest_args = dest_args.verify(SqliteLocator::features())?;
+
+    // Look up our arguments.
+    let schema = shared_args.schema().to_owned();
+    let if_exists = dest_args.if_exists().to_owned();
+    let ctx = ctx.child(o!("table" => dest.table_name().to_owned()));
+    debug!(ctx.log(), "writing data streams to {}", dest);
+
+    // Create our destination table before loading anything.
+    let prepare_sql = prepare_table_sql(&schema, dest.table_name(), &if_exists);
+    debug!(ctx.log(), "prepare SQL: {}", prepare_sql);
+    let prepare = query_rows(&ctx, dest.path(), &prepare_sql);
+    ctx.audit_sql(dest.table_name(), &prepare_sql, prepare)
+        .await?;
+
+    // SQLite only allows one writer at a time, so load one stream at a time
+    // instead of waiting for each other's locks.
+    let write_lock = Arc::new(Mutex::new(()));
+    let written = data.map_ok(move |stream| {
+        let ctx = ctx.child(o!("stream" => stream.name.clone()));
+        let dest = dest.clone();
+        let schema = schema.clone();
+        let write_lock = write_lock.clone();
+        async move {
+            let _guard = write_lock.lock().await;
+
+            // Convert our CSV data to SQL, and pipe it into `sqlite3`.
+            let table_name = dest.table_name().to_owned();
+            let sql = spawn_sync_transform(
+                ctx.clone(),
+                "csv_to_sqlite_inserts".to_owned(),
+                stream.data,
+                move |_ctx, rdr, wtr| {
+                    csv_to_insert_sql(&schema, &table_name, rdr, wtr)
+                },
+            )?;
+            let mut child = sqlite_command(dest.path())
+                .stdin(Stdio::piped())
+                // Throw away stdout so it doesn't corrupt our output.
+                .stdout(Stdio::null())
+                .spawn()
+                .context("error running `sqlite3`")?;
+            let child_stdin = child.stdin.take().expect("child should have stdin");
+            let load = async {
+                copy_stream_to_writer(ctx.clone(), sql, child_stdin)
+                    .await
+                    .context("error copying data to `sqlite3`")?;
+                let status = child
+                    .await
+                    .with_context(|_| format!("error finishing load into {}", dest))?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(format_err!("`sqlite3` returned error: {}", status))
+                }
+            };
+            let load_description = format!(
+                "INSERT INTO {} (batched, {} rows per transaction)",
+                sqlite_quote_ident(dest.table_name()),
+                ROWS_PER_TRANSACTION,
+            );
+            ctx.audit_sql(dest.table_name(), &load_description, load)
+                .await?;
+            Ok(dest.boxed())
+        }
+        .boxed()
+    });
+    Ok(written.boxed())
+}
+
+/// Generate SQL to prepare `table_name` according to `if_exists`.
+fn prepare_table_sql(
+    schema: &Table,
+    table_name: &str,
+    if_exists: &IfExists,
+) -> String {
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| {
+            let mut def = format!(
+                "{} {}",
+                sqlite_quote_ident(&column.name),
+                declared_type_for(&column.data_type),
+            );
+            if !column.is_nullable {
+                def.push_str(" NOT NULL");
+            }
+            def
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let table_name = sqlite_quote_ident(table_name);
+    match if_exists {
+        IfExists::Error => format!("CREATE TABLE {} ({})", table_name, columns),
+        IfExists::Append => {
+            format!("CREATE TABLE IF NOT EXISTS {} ({})", table_name, columns)
+        }
+        IfExists::Overwrite => format!(
+            "DROP TABLE IF EXISTS {0}; CREATE TABLE {0} ({1})",
+            table_name, columns,
+        ),
+        IfExists::Upsert(_) => {
+            unreachable!("upsert should have been rejected by verify")
+        }
+    }
+}
+
+/// Convert CSV data into batches of `INSERT` statements for `table_name`,
+/// wrapped in transactions.
+fn csv_to_insert_sql(
+    schema: &Table,
+    table_name: &str,
+    rdr: impl Read,
+    mut wtr: impl Write,
+) -> Result<()> {
+    let mut rdr = csv::Reader::from_reader(rdr);
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| sqlite_quote_ident(&column.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let insert = format!(
+        "INSERT INTO {} ({}) VALUES\n",
+        sqlite_quote_ident(table_name),
+        columns,
+    );
+
+    let mut record = csv::StringRecord::new();
+    let mut rows: usize = 0;
+    while rdr.read_record(&mut record)? {
+        if record.len() != schema.columns.len() {
+            return Err(format_err!(
+                "expected {} columns, found {} in row {}",
+                schema.columns.len(),
+                record.len(),
+                rows + 1,
+            ));
+        }
+        if rows.is_multiple_of(ROWS_PER_INSERT) {
+            if rows > 0 {
+                wtr.write_all(b";\n")?;
+            }
+            if rows.is_multiple_of(ROWS_PER_TRANSACTION) {
+                if rows > 0 {
+                    wtr.write_all(b"COMMIT;\n")?;
+                }
+                wtr.write_all(b"BEGIN;\n")?;
+            }
+            wtr.write_all(insert.as_bytes())?;
+        } else {
+            wtr.write_all(b",\n")?;
+        }
+        let values = schema
+            .columns
+            .iter()
+            .zip(record.iter())
+            .map(|(column, cell)| sql_literal(column, cell))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|_| format!("error in row {}", rows + 1))?;
+        write!(wtr, "({})", values.join(", "))?;
+        rows += 1;
+    }
+    if rows > 0 {
+        wtr.write_all(b";\nCOMMIT;\n")?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Convert a CSV `cell` to an SQL literal for `column`. Empty cells are
+/// `NULL`. We parse numbers and booleans, so the only values we include
+/// as-is are ones we've formatted ourselves.
+fn sql_literal(column: &Column, cell: &str) -> Result<String> {
+    if cell.is_empty() {
+        return Ok("NULL".to_owned());
+    }
+    let context = || format!("cannot parse {:?} for column {}", cell, column.name);
+    match &column.data_type {
+        DataType::Bool => {
+            let value = bool::from_csv_cell(cell).with_context(|_| context())?;
+            Ok(if value { "1" } else { "0" }.to_owned())
+        }
+        DataType::Int16 | DataType::Int32 | DataType::Int64 => {
+            Ok(i64::from_csv_cell(cell)
+                .with_context(|_| context())?
+                .to_string())
+        }
+        DataType::Float32 | DataType::Float64 => {
+            let value = f64::from_csv_cell(cell).with_context(|_| context())?;
+            if value.is_nan() {
+                // SQLite turns NaN into `NULL`, so make that explicit.
+                Ok("NULL".to_owned())
+            } else if value.is_infinite() {
+                // SQLite has no literal for infinity, but this overflows to it.
+                Ok(if value > 0.0 { "9e999" } else { "-9e999" }.to_owned())
+            } else {
+                Ok(format!("{:?}", value))
+            }
+        }
+        _ => Ok(sqlite_quote_string(cell)),
+    }
+}
+
+#[test]
+fn generates_prepare_sql() {
+    let schema = Table {
+        name: "orders".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "paid".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Bool,
+                comment: None,
+            },
+        ],
+    };
+    assert_eq!(
+        prepare_table_sql(&schema, "orders", &IfExists::Overwrite),
+        "DROP TABLE IF EXISTS \"orders\"; CREATE TABLE \"orders\" (\"id\" INTEGER NOT NULL, \"paid\" BOOLEAN)",
+    );
+    assert_eq!(
+        prepare_table_sql(&schema, "orders", &IfExists::Append),
+        "CREATE TABLE IF NOT EXISTS \"orders\" (\"id\" INTEGER NOT NULL, \"paid\" BOOLEAN)",
+    );
+}
+
+#[test]
+fn converts_csv_to_batched_inserts() {
+    let schema = Table {
+        name: "t".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "score".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Float64,
+                comment: None,
+            },
+            Column {
+                name: "note".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Text,
+                comment: None,
+            },
+        ],
+    };
+    let mut output: Vec<u8> = vec![];
+    csv_to_insert_sql(
+        &schema,
+        "t",
+        &b"id,score,note\n1,1.5,it's\n2,,\n"[..],
+        &mut output,
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "BEGIN;\n\
+         INSERT INTO \"t\" (\"id\", \"score\", \"note\") VALUES\n\
+         (1, 1.5, 'it''s'),\n\
+         (2, NULL, NULL);\n\
+         COMMIT;\n",
+    );
+
+    // Values which aren't what they claim to be are rejected.
+    let mut output: Vec<u8> = vec![];
+    assert!(csv_to_insert_sql(
+        &schema,
+        "t",
+        &b"id,score,note\n1; DROP TABLE t,1,x\n"[..],
+        &mut output,
+    )
+    .is_err());
+
+    // Large inputs are split into several statements and transactions.
+    let mut input = "id,score,note\n".to_owned();
+    for i in 0..(ROWS_PER_TRANSACTION + 1) {
+        input.push_str(&format!("{},,\n", i));
+    }
+    let mut output: Vec<u8> = vec![];
+    csv_to_insert_sql(&schema, "t", input.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.matches("BEGIN;").count(), 2);
+    assert_eq!(output.matches("COMMIT;").count(), 2);
+    assert_eq!(
+        output.matches("INSERT INTO").count(),
+        ROWS_PER_TRANSACTION / ROWS_PER_INSERT + 1,
+    );
+}
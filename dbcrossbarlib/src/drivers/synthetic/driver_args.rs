@@ -0,0 +1,123 @@
+//! Driver arguments controlling how we generate synthetic data.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::common::*;
+use crate::driver_args::deserialize_opt_from_str;
+
+/// How should we choose values for a column?
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Distribution {
+    /// Pick every possible value with equal probability.
+    #[default]
+    Uniform,
+    /// Generate values in order, wrapping around when we run out.
+    Sequential,
+    /// Make small values much more common than large ones.
+    Skewed,
+}
+
+/// Options which can be set for all columns, or for a single column.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ColumnOptions {
+    /// The fraction of values in a nullable column which should be `NULL`.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) null_rate: Option<f64>,
+
+    /// The number of distinct non-`NULL` values to generate.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) cardinality: Option<u64>,
+
+    /// How to choose values.
+    #[serde(default)]
+    pub(crate) distribution: Option<Distribution>,
+}
+
+impl ColumnOptions {
+    /// Fill in any missing options in `self` using the values in `defaults`.
+    pub(crate) fn or(&self, defaults: &ColumnOptions) -> ColumnOptions {
+        ColumnOptions {
+            null_rate: self.null_rate.or(defaults.null_rate),
+            cardinality: self.cardinality.or(defaults.cardinality),
+            distribution: self.distribution.or(defaults.distribution),
+        }
+    }
+
+    /// Make sure these options make sense.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(null_rate) = self.null_rate {
+            if !(0.0..=1.0).contains(&null_rate) {
+                return Err(format_err!(
+                    "null_rate must be between 0 and 1, found {}",
+                    null_rate,
+                ));
+            }
+        }
+        if self.cardinality == Some(0) {
+            return Err(format_err!("cardinality must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+/// Source arguments for `synthetic:`, specified using `--from-arg`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SyntheticSourceArguments {
+    /// A random seed. The same seed and schema will always produce the same
+    /// data.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) seed: Option<u64>,
+
+    /// The default fraction of values in nullable columns which should be
+    /// `NULL`.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) null_rate: Option<f64>,
+
+    /// The default number of distinct values to generate for each column.
+    #[serde(default, deserialize_with = "deserialize_opt_from_str")]
+    pub(crate) cardinality: Option<u64>,
+
+    /// The default distribution of values for each column.
+    #[serde(default)]
+    pub(crate) distribution: Option<Distribution>,
+
+    /// Options for specific columns, which override the defaults above.
+    #[serde(default)]
+    pub(crate) columns: HashMap<String, ColumnOptions>,
+}
+
+impl SyntheticSourceArguments {
+    /// Get the options for the column named `name`.
+    pub(crate) fn column_options(&self, name: &str) -> ColumnOptions {
+        let defaults = ColumnOptions {
+            null_rate: self.null_rate,
+            cardinality: self.cardinality,
+            distribution: self.distribution,
+        };
+        match self.columns.get(name) {
+            Some(options) => options.or(&defaults),
+            None => defaults,
+        }
+    }
+}
+
+#[test]
+fn parse_source_arguments() {
+    let args = DriverArguments::from_cli_args(&[
+        "seed=42".to_owned(),
+        "null_rate=0.5".to_owned(),
+        "columns.id.distribution=sequential".to_owned(),
+        "columns.id.null_rate=0".to_owned(),
+    ])
+    .unwrap();
+    let args = args.deserialize::<SyntheticSourceArguments>().unwrap();
+    assert_eq!(args.seed, Some(42));
+    assert_eq!(args.column_options("name").null_rate, Some(0.5));
+    let id = args.column_options("id");
+    assert_eq!(id.null_rate, Some(0.0));
+    assert_eq!(id.distribution, Some(Distribution::Sequential));
+}
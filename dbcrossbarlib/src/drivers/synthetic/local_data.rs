@@ -0,0 +1,310 @@
+//! Generating synthetic CSV data.
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::{
+    driver_args::{ColumnOptions, Distribution, SyntheticSourceArguments},
+    SyntheticLocator,
+};
+use crate::common::*;
+use crate::schema::{Column, DataType};
+use crate::tokio_glue::SyncStreamWriter;
+
+/// The default fraction of `NULL` values in nullable columns.
+const DEFAULT_NULL_RATE: f64 = 0.1;
+
+/// The number of distinct values we generate when no cardinality is
+/// specified, and we're not generating sequential values.
+const DEFAULT_CARDINALITY: u64 = 1 << 31;
+
+pub(crate) async fn local_data_helper(
+    ctx: Context,
+    source: SyntheticLocator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Option<BoxStream<CsvStream>>> {
+    let shared_args = shared_args.verify(SyntheticLocator::features())?;
+    let source_args = source_args.verify(SyntheticLocator::features())?;
+    let schema = shared_args.schema().to_owned();
+    let args = source_args
+        .driver_args()
+        .deserialize::<SyntheticSourceArguments>()
+        .context("could not parse --from-arg")?;
+    for name in args.columns.keys() {
+        if !schema.columns.iter().any(|c| &c.name == name) {
+            return Err(format_err!(
+                "--from-arg refers to unknown column {:?}",
+                name
+            ));
+        }
+    }
+    let generators = schema
+        .columns
+        .iter()
+        .map(|col| ColumnGenerator::new(col, args.column_options(&col.name)))
+        .collect::<Result<Vec<_>>>()?;
+    let seed = args.seed.unwrap_or_else(rand::random);
+    debug!(
+        ctx.log(),
+        "generating {} rows for {} with seed {}", source.rows, schema.name, seed,
+    );
+
+    // Generate our data in a background thread.
+    let rows = source.rows;
+    let name = schema.name.clone();
+    let wtr_ctx = ctx.child(o!("mode" => "output"));
+    let (wtr, data) = SyncStreamWriter::pipe(wtr_ctx);
+    let worker_fut = spawn_blocking(move || -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        write_rows(&mut rng, &schema.columns, &generators, rows, wtr)
+    });
+    ctx.spawn_worker(worker_fut.boxed());
+
+    let csv_stream = CsvStream {
+        name,
+        data: data.boxed(),
+    };
+    Ok(Some(box_stream_once(Ok(csv_stream))))
+}
+
+/// Write `rows` rows of CSV data to `wtr`.
+fn write_rows<R: Rng>(
+    rng: &mut R,
+    columns: &[Column],
+    generators: &[ColumnGenerator],
+    rows: u64,
+    wtr: impl Write,
+) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(wtr);
+    wtr.write_record(columns.iter().map(|c| &c.name))?;
+    for row_idx in 0..rows {
+        for generator in generators {
+            wtr.write_field(&generator.generate(rng, row_idx)?)?;
+        }
+        // Finish our record. To do this, we need to write an empty iterator.
+        let empty: &[&str] = &[];
+        wtr.write_record(empty)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Generates values for a single column.
+#[derive(Debug)]
+struct ColumnGenerator {
+    /// The type of data to generate.
+    data_type: DataType,
+    /// The name of our column, which we use to make text values look nicer.
+    name: String,
+    /// The fraction of values which should be `NULL`.
+    null_rate: f64,
+    /// The number of distinct values to generate, if specified.
+    cardinality: Option<u64>,
+    /// How to choose values.
+    distribution: Distribution,
+}
+
+impl ColumnGenerator {
+    /// Create a generator for `column` using `options`.
+    fn new(column: &Column, options: ColumnOptions) -> Result<Self> {
+        options
+            .validate()
+            .with_context(|_| format!("bad options for column {:?}", column.name))?;
+        let null_rate = if column.is_nullable {
+            options.null_rate.unwrap_or(DEFAULT_NULL_RATE)
+        } else {
+            0.0
+        };
+        Ok(ColumnGenerator {
+            data_type: column.data_type.clone(),
+            name: column.name.clone(),
+            null_rate,
+            cardinality: options.cardinality,
+            distribution: options.distribution.unwrap_or_default(),
+        })
+    }
+
+    /// Generate a CSV cell for the row with index `row_idx`.
+    fn generate<R: Rng>(&self, rng: &mut R, row_idx: u64) -> Result<String> {
+        // Always consume the same number of random values per cell, so that
+        // changing the options for one column doesn't change the others.
+        let is_null = rng.gen::<f64>() < self.null_rate;
+        let r = rng.gen::<u64>();
+        let s = rng.gen::<u64>();
+        if is_null {
+            return Ok(String::new());
+        }
+        let k = match self.distribution {
+            Distribution::Sequential => match self.cardinality {
+                Some(cardinality) => row_idx % cardinality,
+                None => row_idx,
+            },
+            Distribution::Uniform => {
+                r % self.cardinality.unwrap_or(DEFAULT_CARDINALITY)
+            }
+            Distribution::Skewed => {
+                // Pick a random number of bits, and then a random value with
+                // that many bits, which makes small values much more common.
+                let cardinality = self.cardinality.unwrap_or(DEFAULT_CARDINALITY);
+                let max_bits = 64 - (cardinality - 1).leading_zeros();
+                let bits = u32::try_from(s % u64::from(max_bits + 1))
+                    .expect("bit count should always fit in u32");
+                let range = 1u64.checked_shl(bits).unwrap_or(u64::MAX);
+                r % range.min(cardinality)
+            }
+        };
+        let value = value_for_index(&self.data_type, &self.name, k)?;
+        if self.data_type.serializes_as_json_for_csv() {
+            Ok(serde_json::to_string(&value)?)
+        } else {
+            match value {
+                Value::Bool(true) => Ok("t".to_owned()),
+                Value::Bool(false) => Ok("f".to_owned()),
+                Value::Number(n) => Ok(n.to_string()),
+                Value::String(s) => Ok(s),
+                _ => Err(format_err!(
+                    "cannot serialize {} as {:?}",
+                    value,
+                    self.data_type,
+                )),
+            }
+        }
+    }
+}
+
+/// Build the `k`th possible value of `data_type`. Distinct values of `k` will
+/// normally produce distinct values, except for types like `Bool` which have
+/// only a few possible values.
+fn value_for_index(data_type: &DataType, name: &str, k: u64) -> Result<Value> {
+    let epoch = NaiveDate::from_ymd(2000, 1, 1);
+    // Stay within about 100 years of `epoch`.
+    let days = i64::try_from(k % 36_525).expect("days should fit in i64");
+    let seconds =
+        i64::try_from(k % (36_525 * 24 * 60 * 60)).expect("seconds should fit in i64");
+    let small = u32::try_from(k % (1 << 31)).expect("value should fit in u32");
+    Ok(match data_type {
+        DataType::Array(elem_type) => {
+            let len = k % 4;
+            let elems = (0..len)
+                .map(|i| value_for_index(elem_type, name, k.wrapping_add(i)))
+                .collect::<Result<Vec<_>>>()?;
+            Value::Array(elems)
+        }
+        DataType::Bool => Value::Bool(k % 2 == 1),
+        DataType::Date => {
+            let date = epoch + Duration::days(days);
+            Value::String(date.format("%Y-%m-%d").to_string())
+        }
+        DataType::Decimal => Value::String(format!("{}.{:02}", k / 100, k % 100)),
+        DataType::Float32 | DataType::Float64 => json!(f64::from(small) / 4.0),
+        DataType::GeoJson(_) => {
+            let lon = f64::from(small % 360) - 180.0;
+            let lat = f64::from((small / 360) % 180) - 90.0;
+            json!({ "type": "Point", "coordinates": [lon, lat] })
+        }
+        DataType::Int16 => json!(k % (1 << 15)),
+        DataType::Int32 => json!(small),
+        DataType::Int64 => json!(k % (1 << 63)),
+        DataType::Json => json!({ "value": k }),
+        DataType::Struct(fields) => {
+            let mut obj = serde_json::Map::new();
+            for field in fields {
+                obj.insert(
+                    field.name.clone(),
+                    value_for_index(&field.data_type, &field.name, k)?,
+                );
+            }
+            Value::Object(obj)
+        }
+        DataType::Text => Value::String(format!("{} {}", name, k)),
+        DataType::TimestampWithoutTimeZone => {
+            let timestamp: NaiveDateTime =
+                epoch.and_hms(0, 0, 0) + Duration::seconds(seconds);
+            Value::String(timestamp.format("%Y-%m-%d %H:%M:%S").to_string())
+        }
+        DataType::TimestampWithTimeZone => {
+            let timestamp: NaiveDateTime =
+                epoch.and_hms(0, 0, 0) + Duration::seconds(seconds);
+            Value::String(timestamp.format("%Y-%m-%d %H:%M:%S+00").to_string())
+        }
+        DataType::Uuid => {
+            // Multiplying by an odd constant scrambles our bits while keeping
+            // distinct values distinct.
+            let bits =
+                u128::from(k).wrapping_mul(0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835);
+            Value::String(Uuid::from_u128(bits).to_string())
+        }
+    })
+}
+
+#[test]
+fn generated_values_are_valid() {
+    use crate::validate::check_value;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let types = &[
+        DataType::Array(Box::new(DataType::Int32)),
+        DataType::Bool,
+        DataType::Date,
+        DataType::Decimal,
+        DataType::Float64,
+        DataType::Int16,
+        DataType::Int64,
+        DataType::Json,
+        DataType::Text,
+        DataType::TimestampWithoutTimeZone,
+        DataType::TimestampWithTimeZone,
+        DataType::Uuid,
+    ];
+    for data_type in types {
+        let column = Column {
+            name: "c".to_owned(),
+            is_nullable: false,
+            data_type: data_type.clone(),
+            comment: None,
+        };
+        for &distribution in &[
+            Distribution::Uniform,
+            Distribution::Sequential,
+            Distribution::Skewed,
+        ] {
+            let options = ColumnOptions {
+                distribution: Some(distribution),
+                ..ColumnOptions::default()
+            };
+            let generator = ColumnGenerator::new(&column, options).unwrap();
+            for row_idx in 0..100 {
+                let cell = generator.generate(&mut rng, row_idx).unwrap();
+                check_value(data_type, &cell).unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn cardinality_and_null_rate_are_respected() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let column = Column {
+        name: "c".to_owned(),
+        is_nullable: true,
+        data_type: DataType::Int32,
+        comment: None,
+    };
+    let options = ColumnOptions {
+        null_rate: Some(0.5),
+        cardinality: Some(3),
+        distribution: None,
+    };
+    let generator = ColumnGenerator::new(&column, options).unwrap();
+    let cells = (0..1000)
+        .map(|i| generator.generate(&mut rng, i).unwrap())
+        .collect::<Vec<_>>();
+    let nulls = cells.iter().filter(|c| c.is_empty()).count();
+    assert!(nulls > 400 && nulls < 600, "{} nulls", nulls);
+    for cell in &cells {
+        assert!(["", "0", "1", "2"].contains(&cell.as_str()), "{:?}", cell);
+    }
+}
@@ -0,0 +1,94 @@
+//! Driver for generating synthetic test data.
+
+use std::{fmt, str::FromStr};
+
+use crate::common::*;
+
+mod driver_args;
+mod local_data;
+
+use local_data::local_data_helper;
+
+/// Generate a specified number of rows of fake data matching `--schema`.
+///
+/// This is mostly useful for benchmarking, and for filling test databases.
+#[derive(Clone, Debug)]
+pub struct SyntheticLocator {
+    /// The number of rows to generate.
+    rows: u64,
+}
+
+impl fmt::Display for SyntheticLocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", Self::scheme(), self.rows)
+    }
+}
+
+impl FromStr for SyntheticLocator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if !s.starts_with(Self::scheme()) {
+            return Err(format_err!("expected {} to begin with synthetic:", s));
+        }
+        let rows = s[Self::scheme().len()..].parse::<u64>().with_context(|_| {
+            format!("expected a row count like synthetic:1000, found {:?}", s)
+        })?;
+        Ok(SyntheticLocator { rows })
+    }
+}
+
+#[test]
+fn parse_synthetic_locator() {
+    let loc = SyntheticLocator::from_str("synthetic:1000").unwrap();
+    assert_eq!(loc.rows, 1000);
+    assert_eq!(loc.to_string(), "synthetic:1000");
+    assert!(SyntheticLocator::from_str("synthetic:").is_err());
+    assert!(SyntheticLocator::from_str("synthetic:-1").is_err());
+}
+
+impl Locator for SyntheticLocator {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn count(
+        &self,
+        _ctx: Context,
+        _shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<usize> {
+        let rows = self.rows;
+        async move {
+            let _source_args = source_args.verify(Self::features())?;
+            Ok(usize::try_from(rows).context("too many rows to count")?)
+        }
+        .boxed()
+    }
+
+    fn local_data(
+        &self,
+        ctx: Context,
+        shared_args: SharedArguments<Unverified>,
+        source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<BoxStream<CsvStream>>> {
+        local_data_helper(ctx, self.clone(), shared_args, source_args).boxed()
+    }
+}
+
+impl LocatorStatic for SyntheticLocator {
+    fn scheme() -> &'static str {
+        "synthetic:"
+    }
+
+    fn features() -> Features {
+        Features {
+            locator: LocatorFeatures::Count | LocatorFeatures::LocalData,
+            write_schema_if_exists: EnumSet::empty(),
+            source_args: SourceArgumentsFeatures::DriverArgs.into(),
+            dest_args: EnumSet::empty(),
+            dest_if_exists: EnumSet::empty(),
+            _placeholder: (),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Estimating how much data a locator contains, before we try to copy it.
+
+use serde::Serialize;
+
+use crate::common::*;
+
+/// How many bytes of CSV data should we read when estimating the number of
+/// rows in a file?
+const SAMPLE_BYTES: usize = 1024 * 1024;
+
+/// An approximate description of how much data a locator contains.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Estimate {
+    /// The approximate number of rows, if we know it.
+    pub rows: Option<u64>,
+    /// The approximate size of the data in bytes, if we know it. For databases,
+    /// this is the storage used by the table, which may be quite different
+    /// from the size of the same data as CSV.
+    pub bytes: Option<u64>,
+    /// The number of files or objects, if the data is stored that way.
+    pub files: Option<u64>,
+    /// How we computed this estimate, such as `"table statistics"`.
+    pub method: String,
+}
+
+impl Estimate {
+    /// Estimate the size of `total_bytes` of CSV data, stored in `files` files,
+    /// by reading the first few rows of `sample` (one of those files).
+    pub(crate) async fn from_csv_sample(
+        ctx: &Context,
+        mut sample: BoxStream<BytesMut>,
+        total_bytes: u64,
+        files: u64,
+    ) -> Result<Estimate> {
+        let mut buffer = Vec::with_capacity(SAMPLE_BYTES);
+        let mut complete = true;
+        while let Some(chunk) = sample.next().await {
+            buffer.extend_from_slice(&chunk?);
+            if buffer.len() >= SAMPLE_BYTES {
+                complete = false;
+                break;
+            }
+        }
+        // Dropping `sample` here will stop the rest of the download.
+        drop(sample);
+        let rows = rows_from_sample(&buffer, complete, total_bytes, files)?;
+        debug!(
+            ctx.log(),
+            "sampled {} bytes, estimating {:?} rows in {} bytes",
+            buffer.len(),
+            rows,
+            total_bytes,
+        );
+        Ok(Estimate {
+            rows,
+            bytes: Some(total_bytes),
+            files: Some(files),
+            method: if complete && files == 1 {
+                "file size".to_owned()
+            } else {
+                "file sizes and sampling".to_owned()
+            },
+        })
+    }
+}
+
+/// Estimate how many rows are in `total_bytes` of CSV data spread over `files`
+/// files, each with a header, based on the rows in `sample`. If `complete` is
+/// true, `sample` is an entire file. Otherwise, the last row in `sample` may be
+/// truncated.
+fn rows_from_sample(
+    sample: &[u8],
+    complete: bool,
+    total_bytes: u64,
+    files: u64,
+) -> Result<Option<u64>> {
+    let mut rdr = csv::Reader::from_reader(sample);
+    rdr.byte_headers().context("could not parse CSV headers")?;
+    let header_bytes = rdr.position().byte();
+
+    // Find the end of each complete row.
+    let mut row_ends = vec![];
+    let mut record = csv::ByteRecord::new();
+    while rdr
+        .read_byte_record(&mut record)
+        .context("could not parse CSV sample")?
+    {
+        row_ends.push(rdr.position().byte());
+    }
+    if !complete {
+        row_ends.pop();
+    }
+
+    // If we have the whole file, we don't need to estimate.
+    if complete && files == 1 {
+        return Ok(Some(u64::try_from(row_ends.len())?));
+    }
+    let (rows, row_bytes) = match row_ends.last() {
+        Some(&end) => (u64::try_from(row_ends.len())?, end - header_bytes),
+        None => return Ok(None),
+    };
+    if row_bytes == 0 {
+        return Ok(None);
+    }
+    let data_bytes = total_bytes.saturating_sub(header_bytes * files);
+    Ok(Some(data_bytes * rows / row_bytes))
+}
+
+#[test]
+fn estimates_rows_from_sample() {
+    let sample = b"a,b\n1,2\n3,4\n5,6\n7,";
+
+    // A complete, single file.
+    assert_eq!(
+        rows_from_sample(&sample[..16], true, 16, 1).unwrap(),
+        Some(3),
+    );
+
+    // A truncated sample of a bigger file. The rows are 4 bytes each, and we
+    // ignore the partial row at the end.
+    assert_eq!(
+        rows_from_sample(sample, false, 4 + 4 * 1000, 1).unwrap(),
+        Some(1000),
+    );
+
+    // Several files, each with a header.
+    assert_eq!(
+        rows_from_sample(sample, false, 2 * (4 + 4 * 1000), 2).unwrap(),
+        Some(2000),
+    );
+
+    // Only headers.
+    assert_eq!(rows_from_sample(b"a,b\n", false, 1000, 1).unwrap(), None);
+}
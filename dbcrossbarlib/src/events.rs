@@ -0,0 +1,389 @@
+//! Structured events describing what we're doing.
+//!
+//! Every `Context` carries an `EventBus`, which is shared by all its children.
+//! Drivers and pipeline stages emit events to the bus, and any number of
+//! subscribers can observe them: the CLI, a metrics exporter, or a library
+//! caller's own code.
+
+use serde::Serialize;
+use slog::{Drain, Never, OwnedKVList, Record};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{LineWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use crate::common::*;
+use crate::schema::DataType;
+
+/// Something which happened while running a command.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A log record, forwarded from our logger by `EventBus::log_drain`.
+    Log {
+        /// The log level, such as `"WARN"`.
+        level: String,
+        /// The log message.
+        message: String,
+    },
+    /// Something which the user may want to know about, like bad data.
+    Warning {
+        /// A description of the problem.
+        message: String,
+    },
+    /// A driver will write a column using a type which loses information.
+    LossyType {
+        /// The locator we're writing to.
+        locator: String,
+        /// The column, such as `"price"` or `"address.zip"`.
+        column: String,
+        /// The portable type of the column.
+        data_type: DataType,
+        /// What the driver will write instead.
+        description: String,
+    },
+    /// We've finished part of our work.
+    Progress {
+        /// What we've just done.
+        message: String,
+        /// How many units of work we've completed so far.
+        completed: u64,
+        /// How many units of work there are in total, if we know.
+        total: Option<u64>,
+    },
+    /// We've sent more data from a stream to its destination.
+    StreamProgress {
+        /// The name of the stream.
+        stream: String,
+        /// How many bytes we've sent so far.
+        bytes: u64,
+        /// How many rows we've sent so far, if we know.
+        rows: Option<u64>,
+        /// Have we sent the whole stream?
+        finished: bool,
+    },
+    /// Something failed with what may be a temporary error, so we may try it
+    /// again.
+    Retry {
+        /// A description of the error.
+        message: String,
+    },
+    /// A named count, such as the number of streams written.
+    Metric {
+        /// The name of this metric, such as `"streams_written"`.
+        name: String,
+        /// The value of this metric.
+        value: u64,
+    },
+    /// A remote job, such as a BigQuery load or extract job, has finished.
+    JobFinished {
+        /// The ID of this job.
+        job_id: String,
+        /// Statistics reported for this job, such as `"bytes_processed"` or
+        /// `"slot_ms"`.
+        statistics: BTreeMap<String, u64>,
+    },
+    /// We ran an SQL statement which may change a destination.
+    SqlExecuted {
+        /// Where we ran the statement, such as a table or project name. This
+        /// never includes passwords.
+        target: String,
+        /// The SQL we ran, with any credentials removed.
+        sql: String,
+        /// When we started running the statement, in RFC 3339 format.
+        started_at: String,
+        /// How long the statement took, in milliseconds.
+        duration_ms: u64,
+        /// The error, if the statement failed.
+        error: Option<String>,
+    },
+}
+
+/// Receives events from an `EventBus`.
+///
+/// Subscribers may be called from many threads at once, and they should return
+/// quickly, because they're called synchronously by the code that emits the
+/// event.
+pub trait EventSubscriber: Send + Sync + 'static {
+    /// Handle a single event.
+    fn handle_event(&self, event: &Event);
+}
+
+impl<F> EventSubscriber for F
+where
+    F: Fn(&Event) + Send + Sync + 'static,
+{
+    fn handle_event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Delivers events to all our subscribers. Cloning an `EventBus` returns
+/// another handle to the same bus.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    /// Everybody who wants to hear about our events.
+    subscribers: Arc<RwLock<Vec<Arc<dyn EventSubscriber>>>>,
+}
+
+impl EventBus {
+    /// Create a new event bus with no subscribers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send all future events to `subscriber`.
+    pub fn subscribe(&self, subscriber: Arc<dyn EventSubscriber>) {
+        self.subscribers
+            .write()
+            .expect("lock poisoned, giving up")
+            .push(subscriber);
+    }
+
+    /// Send `event` to all our subscribers.
+    pub fn emit(&self, event: Event) {
+        // Copy our subscribers so that we don't hold the lock while calling
+        // them, in case one of them emits or subscribes.
+        let subscribers = self
+            .subscribers
+            .read()
+            .expect("lock poisoned, giving up")
+            .clone();
+        for subscriber in subscribers {
+            subscriber.handle_event(&event);
+        }
+    }
+
+    /// Create an `slog` drain which emits each log record as an `Event::Log`.
+    /// This can be combined with other drains using `slog::Duplicate`.
+    pub fn log_drain(&self) -> EventDrain {
+        EventDrain { bus: self.clone() }
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self
+            .subscribers
+            .read()
+            .expect("lock poisoned, giving up")
+            .len();
+        write!(f, "EventBus {{ subscribers: {} }}", count)
+    }
+}
+
+/// An `slog` drain which forwards log records to an `EventBus`.
+pub struct EventDrain {
+    /// The bus to send records to.
+    bus: EventBus,
+}
+
+impl Drain for EventDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record<'_>, _values: &OwnedKVList) -> Result<(), Never> {
+        self.bus.emit(Event::Log {
+            level: record.level().as_short_str().to_owned(),
+            message: record.msg().to_string(),
+        });
+        Ok(())
+    }
+}
+
+/// A subscriber which writes each event to a file as a line of JSON.
+pub struct JsonLinesSubscriber {
+    /// Where to write our events.
+    wtr: Mutex<LineWriter<File>>,
+}
+
+impl JsonLinesSubscriber {
+    /// Create a subscriber which writes to `path`, replacing any existing file.
+    pub fn create(path: &Path) -> Result<Self> {
+        let f = File::create(path)
+            .with_context(|_| format!("could not create {}", path.display()))?;
+        Ok(JsonLinesSubscriber {
+            wtr: Mutex::new(LineWriter::new(f)),
+        })
+    }
+}
+
+impl EventSubscriber for JsonLinesSubscriber {
+    fn handle_event(&self, event: &Event) {
+        let mut wtr = self.wtr.lock().expect("lock poisoned, giving up");
+        // There's nobody to report errors to, so ignore them.
+        if serde_json::to_writer(&mut *wtr, event).is_ok() {
+            let _ = writeln!(wtr);
+        }
+    }
+}
+
+/// A subscriber which appends every `Event::SqlExecuted` to a file as a line of
+/// JSON, so that there's a permanent record of what we did to our
+/// destinations.
+pub struct SqlAuditLog {
+    /// Where to write our events.
+    wtr: Mutex<LineWriter<File>>,
+}
+
+impl SqlAuditLog {
+    /// Create a subscriber which appends to `path`, creating it if necessary.
+    pub fn open(path: &Path) -> Result<Self> {
+        let f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|_| format!("could not open {}", path.display()))?;
+        Ok(SqlAuditLog {
+            wtr: Mutex::new(LineWriter::new(f)),
+        })
+    }
+}
+
+impl EventSubscriber for SqlAuditLog {
+    fn handle_event(&self, event: &Event) {
+        if let Event::SqlExecuted { .. } = event {
+            let mut wtr = self.wtr.lock().expect("lock poisoned, giving up");
+            // There's nobody to report errors to, so ignore them.
+            if serde_json::to_writer(&mut *wtr, event).is_ok() {
+                let _ = writeln!(wtr);
+            }
+        }
+    }
+}
+
+/// A subscriber which remembers every warning, so that we can print a summary
+/// once we're done.
+#[derive(Default)]
+pub struct WarningSummary {
+    /// The warnings we've seen so far.
+    warnings: Mutex<Vec<String>>,
+}
+
+impl WarningSummary {
+    /// Create a new, empty summary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All the warnings we've seen, in order.
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings
+            .lock()
+            .expect("lock poisoned, giving up")
+            .clone()
+    }
+}
+
+impl EventSubscriber for WarningSummary {
+    fn handle_event(&self, event: &Event) {
+        let warning = match event {
+            Event::Warning { message } => message.to_owned(),
+            Event::LossyType {
+                locator,
+                column,
+                description,
+                ..
+            } => format!(
+                "column {:?} will be written to {} as {}",
+                column, locator, description,
+            ),
+            _ => return,
+        };
+        self.warnings
+            .lock()
+            .expect("lock poisoned, giving up")
+            .push(warning);
+    }
+}
+
+#[test]
+fn delivers_events_to_all_subscribers() {
+    let bus = EventBus::new();
+    let seen = Arc::new(Mutex::new(vec![]));
+    for _ in 0..2 {
+        let seen = seen.clone();
+        bus.subscribe(Arc::new(move |event: &Event| {
+            seen.lock().unwrap().push(event.to_owned());
+        }));
+    }
+
+    let log = Logger::root(bus.log_drain().fuse(), o!());
+    warn!(log, "hello {}", "world");
+    bus.clone().emit(Event::Metric {
+        name: "streams_written".to_owned(),
+        value: 2,
+    });
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 4);
+    assert_eq!(
+        seen[0],
+        Event::Log {
+            level: "WARN".to_owned(),
+            message: "hello world".to_owned(),
+        },
+    );
+    assert_eq!(
+        serde_json::to_string(&seen[3]).unwrap(),
+        r#"{"type":"metric","name":"streams_written","value":2}"#,
+    );
+}
+
+#[test]
+fn summarizes_warnings() {
+    let bus = EventBus::new();
+    let summary = Arc::new(WarningSummary::new());
+    bus.subscribe(summary.clone());
+    bus.emit(Event::Warning {
+        message: "bad data".to_owned(),
+    });
+    bus.emit(Event::Metric {
+        name: "streams_written".to_owned(),
+        value: 2,
+    });
+    bus.emit(Event::LossyType {
+        locator: "bigquery:p:d.t".to_owned(),
+        column: "price".to_owned(),
+        data_type: DataType::Decimal,
+        description: "NUMERIC".to_owned(),
+    });
+    assert_eq!(
+        summary.warnings(),
+        &[
+            "bad data",
+            "column \"price\" will be written to bigquery:p:d.t as NUMERIC",
+        ],
+    );
+}
+
+#[test]
+fn appends_sql_to_audit_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+    for sql in &["CREATE TABLE t (id INT)", "DROP TABLE t"] {
+        let log = SqlAuditLog::open(&path).unwrap();
+        log.handle_event(&Event::Metric {
+            name: "streams_written".to_owned(),
+            value: 1,
+        });
+        log.handle_event(&Event::SqlExecuted {
+            target: "t".to_owned(),
+            sql: sql.to_string(),
+            started_at: "2020-01-01T00:00:00+00:00".to_owned(),
+            duration_ms: 5,
+            error: None,
+        });
+    }
+    let lines = std::fs::read_to_string(&path).unwrap();
+    let lines = lines.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[1],
+        r#"{"type":"sql_executed","target":"t","sql":"DROP TABLE t","started_at":"2020-01-01T00:00:00+00:00","duration_ms":5,"error":null}"#,
+    );
+}
@@ -0,0 +1,436 @@
+//! Check a stream of CSV streams against simple data contracts.
+//!
+//! Expectations are loaded from a JSON file that looks like:
+//!
+//! ```json
+//! [
+//!   { "column": "id", "rule": "not_null" },
+//!   { "column": "status", "rule": "in_set", "values": ["active", "closed"] },
+//!   { "column": "email", "rule": "matches", "regex": "^[^@]+@[^@]+$", "on_failure": "warn" },
+//!   { "column": "age", "rule": "between", "min": 0, "max": 150 }
+//! ]
+//! ```
+
+use regex::Regex;
+use serde::Deserialize;
+use std::{fmt, fs, path::Path, sync::Arc};
+
+use crate::common::*;
+use crate::schema::Column;
+use crate::transform::spawn_sync_transform;
+
+/// A single expectation, as it appears in an expectations file.
+#[derive(Clone, Debug, Deserialize)]
+struct ExpectationSpec {
+    /// The column to check.
+    column: String,
+    /// The check to perform.
+    #[serde(flatten)]
+    rule: RuleSpec,
+    /// What to do when a value fails this check.
+    #[serde(default)]
+    on_failure: OnFailure,
+}
+
+/// The checks we support, as they appear in an expectations file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+enum RuleSpec {
+    /// The column must never be `NULL`.
+    NotNull,
+    /// Non-`NULL` values must be one of `values`.
+    InSet { values: Vec<String> },
+    /// Non-`NULL` values must match `regex`.
+    Matches { regex: String },
+    /// Non-`NULL` values must be between `min` and `max`, inclusive.
+    Between {
+        #[serde(default)]
+        min: Option<Bound>,
+        #[serde(default)]
+        max: Option<Bound>,
+    },
+}
+
+/// A bound for a `between` expectation.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum Bound {
+    /// Compare values as numbers.
+    Number(f64),
+    /// Compare values as strings. This works for ISO 8601 dates and
+    /// timestamps.
+    Text(String),
+}
+
+impl Bound {
+    /// Compare `value` to this bound, returning `None` if `value` can't be
+    /// compared.
+    fn compare(&self, value: &str) -> Option<std::cmp::Ordering> {
+        match self {
+            Bound::Number(bound) => value.parse::<f64>().ok()?.partial_cmp(bound),
+            Bound::Text(bound) => Some(value.cmp(bound.as_str())),
+        }
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::Number(n) => write!(f, "{}", n),
+            Bound::Text(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// What should we do when a value fails an expectation?
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OnFailure {
+    /// Fail as soon as we see the first bad value.
+    #[default]
+    Error,
+    /// Log a warning summarizing the bad values, and keep going.
+    Warn,
+}
+
+/// A compiled check.
+#[derive(Clone, Debug)]
+enum Rule {
+    NotNull,
+    InSet(Vec<String>),
+    Matches(Regex),
+    Between(Option<Bound>, Option<Bound>),
+}
+
+impl Rule {
+    /// Compile `spec`.
+    fn compile(spec: &RuleSpec) -> Result<Rule> {
+        match spec {
+            RuleSpec::NotNull => Ok(Rule::NotNull),
+            RuleSpec::InSet { values } => Ok(Rule::InSet(values.to_owned())),
+            RuleSpec::Matches { regex } => Ok(Rule::Matches(
+                Regex::new(regex)
+                    .with_context(|_| format!("invalid regex {:?}", regex))?,
+            )),
+            RuleSpec::Between {
+                min: None,
+                max: None,
+            } => Err(format_err!("`between` needs a `min`, a `max` or both")),
+            RuleSpec::Between { min, max } => {
+                Ok(Rule::Between(min.to_owned(), max.to_owned()))
+            }
+        }
+    }
+
+    /// Does `cell` satisfy this rule?
+    fn check(&self, cell: &str) -> bool {
+        use std::cmp::Ordering;
+        if cell.is_empty() {
+            // Only `not_null` cares about `NULL` values.
+            return !matches!(self, Rule::NotNull);
+        }
+        match self {
+            Rule::NotNull => true,
+            Rule::InSet(values) => values.iter().any(|v| v == cell),
+            Rule::Matches(re) => re.is_match(cell),
+            Rule::Between(min, max) => {
+                let min_ok = min.as_ref().is_none_or(|min| {
+                    matches!(
+                        min.compare(cell),
+                        Some(Ordering::Greater) | Some(Ordering::Equal)
+                    )
+                });
+                let max_ok = max.as_ref().is_none_or(|max| {
+                    matches!(
+                        max.compare(cell),
+                        Some(Ordering::Less) | Some(Ordering::Equal)
+                    )
+                });
+                min_ok && max_ok
+            }
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::NotNull => write!(f, "not_null"),
+            Rule::InSet(values) => write!(f, "in_set {:?}", values),
+            Rule::Matches(re) => write!(f, "matches {:?}", re.as_str()),
+            Rule::Between(min, max) => {
+                write!(f, "between ")?;
+                match min {
+                    Some(min) => write!(f, "{}", min)?,
+                    None => write!(f, "-")?,
+                }
+                write!(f, " and ")?;
+                match max {
+                    Some(max) => write!(f, "{}", max),
+                    None => write!(f, "-"),
+                }
+            }
+        }
+    }
+}
+
+/// A compiled expectation.
+#[derive(Clone, Debug)]
+struct Expectation {
+    /// The name of the column to check.
+    column: String,
+    /// The check to perform.
+    rule: Rule,
+    /// What to do when a value fails this check.
+    on_failure: OnFailure,
+}
+
+/// A list of expectations to check while copying data.
+#[derive(Clone, Debug)]
+pub struct Expectations {
+    expectations: Arc<Vec<Expectation>>,
+}
+
+impl Expectations {
+    /// Load expectations from a JSON file.
+    pub fn from_json_file(path: &Path) -> Result<Expectations> {
+        let json = fs::read_to_string(path)
+            .with_context(|_| format!("could not read {}", path.display()))?;
+        Ok(Self::from_json(&json)
+            .with_context(|_| format!("error parsing {}", path.display()))?)
+    }
+
+    /// Parse expectations from a JSON string.
+    fn from_json(json: &str) -> Result<Expectations> {
+        let specs = serde_json::from_str::<Vec<ExpectationSpec>>(json)?;
+        let expectations = specs
+            .iter()
+            .map(|spec| {
+                Ok(Expectation {
+                    column: spec.column.clone(),
+                    rule: Rule::compile(&spec.rule).with_context(|_| {
+                        format!("bad expectation for {:?}", spec.column)
+                    })?,
+                    on_failure: spec.on_failure,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Expectations {
+            expectations: Arc::new(expectations),
+        })
+    }
+
+    /// Find the index of each expectation's column in `columns`.
+    fn column_indices(&self, columns: &[Column]) -> Result<Vec<usize>> {
+        self.expectations
+            .iter()
+            .map(|e| {
+                columns
+                    .iter()
+                    .position(|c| c.name == e.column)
+                    .ok_or_else(|| {
+                        format_err!("expectation for unknown column {:?}", e.column)
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Given a stream of CSV streams, check each value against `expectations`.
+/// The data itself is passed through unchanged.
+pub fn check_expectations(
+    ctx: Context,
+    schema: Table,
+    expectations: Expectations,
+    streams: BoxStream<CsvStream>,
+) -> Result<BoxStream<CsvStream>> {
+    // Make sure all our columns exist before we start.
+    let indices = expectations.column_indices(&schema.columns)?;
+    let ctx = ctx.child(o!("streams_transform" => "check_expectations"));
+    let checked = streams.and_then(move |stream| {
+        let ctx = ctx.clone();
+        let expectations = expectations.clone();
+        let indices = indices.clone();
+        async move {
+            let name = stream.name.clone();
+            let data = spawn_sync_transform(
+                ctx,
+                format!("check expectations {}", name),
+                stream.data,
+                move |ctx, rdr, wtr| {
+                    check_csv(&ctx, &name, &expectations, &indices, rdr, wtr)
+                },
+            )?;
+            Ok(CsvStream {
+                name: stream.name,
+                data,
+            })
+        }
+    });
+    Ok(checked.boxed())
+}
+
+/// Failures of a single `warn` expectation.
+#[derive(Debug, Default)]
+struct Failures {
+    /// How many bad values did we find?
+    count: usize,
+    /// A description of the first bad value we found.
+    first_failure: Option<String>,
+}
+
+/// Copy CSV data from `rdr` to `wtr`, checking each row against
+/// `expectations`. `indices` contains the column index for each expectation.
+fn check_csv(
+    ctx: &Context,
+    stream_name: &str,
+    expectations: &Expectations,
+    indices: &[usize],
+    rdr: impl Read,
+    wtr: impl Write,
+) -> Result<()> {
+    let mut rdr = csv::Reader::from_reader(rdr);
+    let mut wtr = csv::Writer::from_writer(wtr);
+    let hdr = rdr
+        .byte_headers()
+        .with_context(|_| format!("cannot read headers of {}", stream_name))?
+        .to_owned();
+    wtr.write_byte_record(&hdr)?;
+
+    let mut failures = expectations
+        .expectations
+        .iter()
+        .map(|_| Failures::default())
+        .collect::<Vec<_>>();
+    let mut record = csv::StringRecord::new();
+    let mut row_idx: usize = 0;
+    while rdr
+        .read_record(&mut record)
+        .with_context(|_| format!("cannot read row from {}", stream_name))?
+    {
+        for ((expectation, &idx), failures) in expectations
+            .expectations
+            .iter()
+            .zip(indices)
+            .zip(failures.iter_mut())
+        {
+            let cell = record.get(idx).unwrap_or_default();
+            if !expectation.rule.check(cell) {
+                // Data rows start on line 2, after the header.
+                let line = row_idx + 2;
+                if expectation.on_failure == OnFailure::Error {
+                    return Err(format_err!(
+                        "{} line {}: column {:?}: expected {}, found {:?}",
+                        stream_name,
+                        line,
+                        expectation.column,
+                        expectation.rule,
+                        cell,
+                    ));
+                }
+                failures.count += 1;
+                if failures.first_failure.is_none() {
+                    failures.first_failure =
+                        Some(format!("line {}: {:?}", line, cell));
+                }
+            }
+        }
+        wtr.write_record(&record)?;
+        row_idx += 1;
+    }
+    wtr.flush()?;
+
+    for (expectation, failures) in expectations.expectations.iter().zip(&failures) {
+        if let Some(first_failure) = &failures.first_failure {
+            ctx.warning(format!(
+                "{}: column {:?} failed {} in {} of {} rows (first at {})",
+                stream_name,
+                expectation.column,
+                expectation.rule,
+                failures.count,
+                row_idx,
+                first_failure,
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn parses_and_checks_expectations() {
+    let expectations = Expectations::from_json(
+        r#"[
+            { "column": "id", "rule": "not_null" },
+            { "column": "status", "rule": "in_set", "values": ["a", "b"] },
+            { "column": "email", "rule": "matches", "regex": "^[^@]+@[^@]+$", "on_failure": "warn" },
+            { "column": "age", "rule": "between", "min": 0, "max": 150 },
+            { "column": "born", "rule": "between", "min": "1900-01-01" }
+        ]"#,
+    )
+    .unwrap();
+    let rules = expectations
+        .expectations
+        .iter()
+        .map(|e| &e.rule)
+        .collect::<Vec<_>>();
+    assert!(rules[0].check("1"));
+    assert!(!rules[0].check(""));
+    assert!(rules[1].check("a"));
+    assert!(rules[1].check(""));
+    assert!(!rules[1].check("c"));
+    assert!(rules[2].check("x@example.com"));
+    assert!(!rules[2].check("example.com"));
+    assert!(rules[3].check("150"));
+    assert!(!rules[3].check("-1"));
+    assert!(!rules[3].check("old"));
+    assert!(rules[4].check("1969-07-20"));
+    assert!(!rules[4].check("1899-12-31"));
+    assert_eq!(expectations.expectations[2].on_failure, OnFailure::Warn);
+
+    assert!(
+        Expectations::from_json(r#"[{ "column": "x", "rule": "between" }]"#).is_err()
+    );
+    assert!(Expectations::from_json(
+        r#"[{ "column": "x", "rule": "matches", "regex": "(" }]"#
+    )
+    .is_err());
+}
+
+#[test]
+fn check_csv_passes_data_through() {
+    use crate::schema::DataType;
+
+    let (ctx, _worker_fut) = Context::create_for_test("check_csv_passes_data_through");
+    let columns = ["id", "status"]
+        .iter()
+        .map(|&name| Column {
+            name: name.to_owned(),
+            is_nullable: true,
+            data_type: DataType::Text,
+            comment: None,
+        })
+        .collect::<Vec<_>>();
+    let input = "id,status\n1,a\n2,c\n";
+
+    let warn = Expectations::from_json(
+        r#"[{ "column": "status", "rule": "in_set", "values": ["a"], "on_failure": "warn" }]"#,
+    )
+    .unwrap();
+    let indices = warn.column_indices(&columns).unwrap();
+    let mut output = vec![];
+    check_csv(&ctx, "test", &warn, &indices, input.as_bytes(), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), input);
+
+    let error = Expectations::from_json(
+        r#"[{ "column": "status", "rule": "in_set", "values": ["a"] }]"#,
+    )
+    .unwrap();
+    let err = check_csv(&ctx, "test", &error, &indices, input.as_bytes(), vec![])
+        .unwrap_err();
+    assert!(err.to_string().contains("line 3"), "{}", err);
+
+    let unknown =
+        Expectations::from_json(r#"[{ "column": "nope", "rule": "not_null" }]"#)
+            .unwrap();
+    assert!(unknown.column_indices(&columns).is_err());
+}
@@ -0,0 +1,209 @@
+//! Reading and writing local files.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio::{fs, io::BufReader as AsyncBufReader};
+
+use crate::common::*;
+use crate::tokio_glue::{
+    copy_reader_to_stream, copy_stream_to_writer, SyncStreamReader, SyncStreamWriter,
+};
+
+/// The buffer size to use for `FileIo::Threads`. This is larger than
+/// `BUFFER_SIZE`, because each read or write is a blocking system call on a
+/// thread which has nothing else to do.
+const THREADS_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// How should we read and write local files?
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum FileIo {
+    /// Use `tokio`'s async file API. This runs each read or write as a
+    /// separate job on `tokio`'s blocking thread pool, which adds overhead to
+    /// every 64 KiB chunk.
+    #[default]
+    Tokio,
+    /// Give each file its own long-running background thread, which uses
+    /// ordinary blocking I/O with 1 MiB buffers. This is usually faster for
+    /// large local copies, at the cost of one thread per open file.
+    Threads,
+}
+
+impl FileIo {
+    /// Open `path` and return its contents as a stream.
+    pub(crate) async fn read_file(
+        self,
+        ctx: &Context,
+        path: &Path,
+    ) -> Result<BoxStream<BytesMut>> {
+        match self {
+            FileIo::Tokio => {
+                let file = fs::File::open(path)
+                    .await
+                    .with_context(|_| format!("cannot open {}", path.display()))?;
+                let file = AsyncBufReader::with_capacity(BUFFER_SIZE, file);
+                Ok(copy_reader_to_stream(ctx.clone(), file)?.boxed())
+            }
+            FileIo::Threads => {
+                let open_path = path.to_owned();
+                let file = spawn_blocking(move || {
+                    Ok(File::open(&open_path).with_context(|_| {
+                        format!("cannot open {}", open_path.display())
+                    })?)
+                })
+                .await?;
+                let (wtr, stream) = SyncStreamWriter::pipe(ctx.clone());
+                let path = path.to_owned();
+                let worker_fut =
+                    spawn_blocking(move || read_file_sync(file, wtr, &path));
+                ctx.spawn_worker(worker_fut.boxed());
+                Ok(stream.boxed())
+            }
+        }
+    }
+
+    /// Write `data` to `path`, which must not already exist unless `if_exists`
+    /// is `IfExists::Overwrite`.
+    pub(crate) async fn write_file(
+        self,
+        ctx: &Context,
+        data: BoxStream<BytesMut>,
+        path: &Path,
+        if_exists: &IfExists,
+    ) -> Result<()> {
+        match self {
+            FileIo::Tokio => {
+                let wtr = if_exists
+                    .to_async_open_options_no_append()?
+                    .open(path)
+                    .await
+                    .with_context(|_| format!("cannot open {}", path.display()))?;
+                copy_stream_to_writer(ctx.clone(), data, wtr)
+                    .await
+                    .with_context(|_| format!("error writing {}", path.display()))?;
+            }
+            FileIo::Threads => {
+                let open_options = if_exists.to_sync_open_options_no_append()?;
+                let rdr = SyncStreamReader::new(ctx.clone(), data);
+                let path = path.to_owned();
+                spawn_blocking(move || write_file_sync(rdr, &open_options, path))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copy `file` to `wtr` using large reads, reporting any read errors to both
+/// `wtr` and our caller.
+fn read_file_sync(file: File, mut wtr: SyncStreamWriter, path: &Path) -> Result<()> {
+    let mut rdr = BufReader::with_capacity(THREADS_BUFFER_SIZE, file);
+    let mut buffer = vec![0u8; THREADS_BUFFER_SIZE];
+    loop {
+        match rdr.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(count) => {
+                if wtr.write_all(&buffer[..count]).is_err() {
+                    // Our reader has gone away, so there's no point in
+                    // reading any more.
+                    return Ok(());
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => {
+                let err = format_err!("error reading {}: {}", path.display(), err);
+                // If our reader has gone away, it doesn't need the error.
+                let _ = wtr.send_error(format_err!("{}", err));
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Copy `rdr` to a new file at `path`, using large writes.
+fn write_file_sync(
+    mut rdr: SyncStreamReader,
+    open_options: &OpenOptions,
+    path: PathBuf,
+) -> Result<()> {
+    let file = open_options
+        .open(&path)
+        .with_context(|_| format!("cannot open {}", path.display()))?;
+    let mut wtr = BufWriter::with_capacity(THREADS_BUFFER_SIZE, file);
+    io::copy(&mut rdr, &mut wtr)
+        .and_then(|_| wtr.flush())
+        .with_context(|_| format!("error writing {}", path.display()))?;
+    Ok(())
+}
+
+impl fmt::Display for FileIo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileIo::Tokio => "tokio".fmt(f),
+            FileIo::Threads => "threads".fmt(f),
+        }
+    }
+}
+
+impl FromStr for FileIo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<FileIo> {
+        match s {
+            "tokio" => Ok(FileIo::Tokio),
+            "threads" => Ok(FileIo::Threads),
+            // `io_uring` would need either an `io_uring` crate or our own
+            // `unsafe` system calls, and we have neither, so explain what to
+            // use instead.
+            "io_uring" | "io-uring" => Err(format_err!(
+                "--file-io={} is not supported, try --file-io=threads",
+                s,
+            )),
+            _ => Err(format_err!("unknown file I/O backend: {}", s)),
+        }
+    }
+}
+
+#[test]
+fn parses_and_displays_file_io() {
+    for file_io in &[FileIo::Tokio, FileIo::Threads] {
+        assert_eq!(&file_io.to_string().parse::<FileIo>().unwrap(), file_io);
+    }
+    let err = "io_uring".parse::<FileIo>().unwrap_err();
+    assert!(err.to_string().contains("--file-io=threads"));
+    assert!("aio".parse::<FileIo>().is_err());
+}
+
+#[test]
+fn threads_round_trip_files() {
+    let (ctx, worker_fut) = Context::create_for_test("threads_round_trip_files");
+    let dir = std::env::temp_dir().join(format!(
+        "dbcrossbar-file-io-{}",
+        TemporaryStorage::random_tag()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.csv");
+    let data = "a,b\n1,2\n".repeat(1000);
+
+    let cmd_fut = async move {
+        let input = box_stream_once(Ok(BytesMut::from(data.as_bytes())));
+        FileIo::Threads
+            .write_file(&ctx, input, &path, &IfExists::Error)
+            .await?;
+        let output = FileIo::Threads.read_file(&ctx, &path).await?;
+        let output = output
+            .try_fold(BytesMut::new(), |mut acc, bytes| async move {
+                acc.extend_from_slice(&bytes);
+                Ok(acc)
+            })
+            .await?;
+        assert_eq!(&output[..], data.as_bytes());
+        Ok(())
+    };
+    run_futures_with_runtime(cmd_fut.boxed(), worker_fut).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,7 +1,7 @@
 //! What to do if the destination already exists.
 
 use itertools::Itertools;
-use std::{fmt, str::FromStr};
+use std::{fmt, fs, str::FromStr};
 use tokio::fs as tokio_fs;
 
 use crate::args::DisplayEnumSet;
@@ -93,9 +93,31 @@ impl IfExists {
         Ok(open_options)
     }
 
+    /// Convert to a `std::fs::OpenOptions` value, returning an error for
+    /// `IfExists::Append`.
+    pub(crate) fn to_sync_open_options_no_append(&self) -> Result<fs::OpenOptions> {
+        let mut open_options = fs::OpenOptions::new();
+        open_options.write(true);
+        match self {
+            IfExists::Error => {
+                open_options.create_new(true);
+            }
+            IfExists::Overwrite => {
+                open_options.create(true).truncate(true);
+            }
+            IfExists::Append => {
+                return Err(format_err!("appending not supported"));
+            }
+            IfExists::Upsert(_) => {
+                return Err(format_err!("upsert not supported"));
+            }
+        }
+        Ok(open_options)
+    }
+
     pub(crate) fn warn_if_not_default_for_stdout(&self, ctx: &Context) {
         if self != &IfExists::default() {
-            warn!(ctx.log(), "{} ignored for stdout", self)
+            ctx.warning(format!("{} ignored for stdout", self))
         }
     }
 
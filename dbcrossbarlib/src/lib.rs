@@ -19,30 +19,67 @@
 // We handle this using `cargo deny` instead.
 #![allow(clippy::multiple_crate_versions)]
 
+#[cfg(not(any(feature = "tls-native", feature = "tls-rustls")))]
+compile_error!("enable either the `tls-native` or the `tls-rustls` feature");
+
 use std::result;
 
 pub(crate) mod args;
+pub mod auth;
+pub mod cancellation;
+pub mod capabilities;
+pub mod checkpoint;
 pub(crate) mod clouds;
+pub(crate) mod column_matching;
+pub mod column_stats;
+pub(crate) mod compress;
 pub(crate) mod concat;
 pub mod config;
 pub(crate) mod context;
+pub mod copy_path;
+pub mod cost;
 pub(crate) mod credentials;
 pub(crate) mod csv_stream;
+pub(crate) mod decompress;
+pub mod doctor;
 mod driver_args;
 pub mod drivers;
+pub mod estimate;
+pub mod events;
+pub mod expectations;
+pub(crate) mod file_io;
 pub(crate) mod from_csv_cell;
 pub(crate) mod from_json_value;
 pub(crate) mod if_exists;
+pub mod limits;
 pub(crate) mod locator;
+pub mod lock;
+pub mod lossy_types;
+pub mod monitor;
+pub mod names;
+pub mod normalize;
+pub mod notify;
+pub mod pipeline;
 pub(crate) mod parse_error;
 pub(crate) mod path_or_stdio;
+pub mod progress;
+pub mod provenance;
 pub mod rechunk;
+mod reserved_words;
+pub mod round_trip;
+pub(crate) mod row_filter;
 pub mod schema;
+pub(crate) mod schema_evolution;
+pub mod select;
 pub(crate) mod separator;
+pub mod serve;
+pub mod shard;
 mod temporary_storage;
 pub mod tokio_glue;
 pub(crate) mod transform;
 mod url_with_hidden_password;
+pub mod validate;
+pub mod verify;
 
 /// Standard error type for this library.
 pub use failure::Error;
@@ -57,12 +94,19 @@ pub use args::{
     ArgumentState, DestinationArguments, SharedArguments, SourceArguments, Unverified,
     Verified,
 };
+pub use cancellation::{CancellationToken, Cancelled};
+pub use column_matching::ColumnMatching;
+pub use compress::OutputCompression;
 pub use context::Context;
 pub use csv_stream::CsvStream;
 pub use driver_args::DriverArguments;
+pub use file_io::FileIo;
 pub use if_exists::IfExists;
 pub use locator::{BoxLocator, DisplayOutputLocators, Locator, UnparsedLocator};
-pub use temporary_storage::TemporaryStorage;
+pub use schema_evolution::SchemaEvolution;
+pub use temporary_storage::{
+    TemporarySelection, TemporaryStorage, TemporaryStoragePolicy,
+};
 pub use tokio_glue::{run_futures_with_runtime, ConsumeWithParallelism};
 
 /// Definitions included by all the files in this crate.
@@ -93,16 +137,21 @@ pub(crate) mod common {
             SharedArguments, SourceArguments, SourceArgumentsFeatures, Unverified,
             Verified,
         },
+        column_matching::ColumnMatching,
+        compress::OutputCompression,
         context::Context,
         csv_stream::CsvStream,
         driver_args::DriverArguments,
+        estimate::Estimate,
         if_exists::{IfExists, IfExistsFeatures},
         locator::{
             BoxLocator, DisplayOutputLocators, Features, Locator, LocatorFeatures,
             LocatorStatic,
         },
+        lock::{BoxDestinationLock, DestinationLock},
         path_or_stdio::PathOrStdio,
         schema::Table,
+        schema_evolution::SchemaEvolution,
         temporary_storage::TemporaryStorage,
         tokio_glue::{
             async_read_to_end, async_read_to_string, box_stream_once,
@@ -0,0 +1,140 @@
+//! Checking tables against the limits of a destination before we copy any
+//! data.
+
+use std::fmt;
+
+use crate::common::*;
+
+/// The maximum length of a name, which some databases count in bytes and
+/// others in characters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NameLength {
+    /// At most this many bytes of UTF-8.
+    Bytes(usize),
+    /// At most this many Unicode characters.
+    Chars(usize),
+}
+
+impl NameLength {
+    /// Does `name` fit within this limit?
+    fn allows(self, name: &str) -> bool {
+        match self {
+            NameLength::Bytes(max) => name.len() <= max,
+            NameLength::Chars(max) => name.chars().count() <= max,
+        }
+    }
+
+    /// The maximum length, in whatever units we use.
+    fn max(self) -> usize {
+        match self {
+            NameLength::Bytes(max) | NameLength::Chars(max) => max,
+        }
+    }
+}
+
+impl fmt::Display for NameLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameLength::Bytes(max) => write!(f, "{} bytes", max),
+            NameLength::Chars(max) => write!(f, "{} characters", max),
+        }
+    }
+}
+
+/// Limits on the tables which a driver can write.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Limits {
+    /// The maximum number of columns in a table.
+    pub max_columns: Option<usize>,
+    /// The maximum length of a column or struct field name.
+    pub max_name_length: Option<NameLength>,
+}
+
+impl Limits {
+    /// Describe each way in which `table` exceeds these limits.
+    pub fn find_violations(&self, table: &Table) -> Vec<String> {
+        let mut violations = vec![];
+        if let Some(max_columns) = self.max_columns {
+            if table.columns.len() > max_columns {
+                violations.push(format!(
+                    "table has {} columns, but at most {} are allowed",
+                    table.columns.len(),
+                    max_columns,
+                ));
+            }
+        }
+        if let Some(max_name_length) = self.max_name_length {
+            for column in &table.columns {
+                if !max_name_length.allows(&column.name) {
+                    violations.push(format!(
+                        "column {:?} has a name longer than {} (try \
+                         --normalize-names=max-length={})",
+                        column.name,
+                        max_name_length,
+                        max_name_length.max(),
+                    ));
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Make sure that `table` fits within the limits of `dest`, returning an error
+/// which lists every problem if it doesn't.
+pub fn check_limits(dest: &dyn Locator, table: &Table) -> Result<()> {
+    let violations = dest.driver()?.limits().find_violations(table);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        let mut msg = format!("cannot write this table to {}:", dest);
+        for violation in &violations {
+            msg.push_str("\n- ");
+            msg.push_str(violation);
+        }
+        Err(format_err!("{}", msg))
+    }
+}
+
+#[test]
+fn finds_limit_violations() {
+    use crate::schema::{Column, DataType};
+
+    let column = |name: &str, data_type| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type,
+        comment: None,
+    };
+    let table = Table {
+        name: "example".to_owned(),
+        columns: vec![
+            column("id", DataType::Int64),
+            column("größe", DataType::Text),
+            column("postcode", DataType::Text),
+        ],
+    };
+
+    let limits = Limits {
+        max_columns: Some(2),
+        max_name_length: Some(NameLength::Bytes(6)),
+    };
+    assert_eq!(
+        limits.find_violations(&table),
+        &[
+            "table has 3 columns, but at most 2 are allowed",
+            "column \"größe\" has a name longer than 6 bytes (try --normalize-names=max-length=6)",
+            "column \"postcode\" has a name longer than 6 bytes (try --normalize-names=max-length=6)",
+        ],
+    );
+
+    let limits = Limits {
+        max_columns: None,
+        max_name_length: Some(NameLength::Chars(7)),
+    };
+    assert_eq!(
+        limits.find_violations(&table),
+        &["column \"postcode\" has a name longer than 7 characters (try --normalize-names=max-length=7)"],
+    );
+    assert!(Limits::default().find_violations(&table).is_empty());
+}
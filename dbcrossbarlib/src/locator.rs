@@ -5,8 +5,11 @@ use regex::Regex;
 use std::{fmt, marker::PhantomData, str::FromStr};
 
 use crate::args::EnumSetExt;
+use crate::capabilities::Capabilities;
 use crate::common::*;
 use crate::drivers::find_driver;
+use crate::limits::Limits;
+use crate::schema::DataType;
 
 /// When called from the CLI, should we display a list of individual locators
 /// for each data stream?
@@ -48,11 +51,34 @@ pub trait Locator: fmt::Debug + fmt::Display + Send + Sync + 'static {
     /// [so]: https://stackoverflow.com/a/33687996
     fn as_any(&self) -> &dyn Any;
 
+    /// Look up the driver for this locator.
+    fn driver(&self) -> Result<&'static dyn LocatorDriver> {
+        let s = self.to_string();
+        find_driver(locator_scheme(&s)?, true)
+    }
+
+    /// Describe what the driver for this locator can do.
+    fn capabilities(&self) -> Result<Capabilities> {
+        Ok(self.driver()?.capabilities())
+    }
+
     /// Return a table schema, if available.
     fn schema(&self, _ctx: Context) -> BoxFuture<Option<Table>> {
         async { Ok(None) }.boxed()
     }
 
+    /// Return the schema of the rows that `sql` would produce if it were run
+    /// against the database at this locator, without fully running the query.
+    /// Returns `None` if this locator can't describe queries.
+    fn query_schema(
+        &self,
+        _ctx: Context,
+        _sql: String,
+        _source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<Table>> {
+        async { Ok(None) }.boxed()
+    }
+
     /// Write a table schema to this locator, if that's the sort of thing that
     /// we can do.
     fn write_schema(
@@ -76,6 +102,16 @@ pub trait Locator: fmt::Debug + fmt::Display + Send + Sync + 'static {
         async move { Err(err) }.boxed()
     }
 
+    /// Estimate how much data is stored at this locator, without reading all
+    /// of it. Returns `None` if this locator can't estimate its size.
+    fn estimate(
+        &self,
+        _ctx: Context,
+        _source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Option<Estimate>> {
+        async { Ok(None) }.boxed()
+    }
+
     /// If this locator can be used as a local data source, return a stream of
     /// CSV streams. This function type is bit hairy:
     ///
@@ -159,24 +195,66 @@ pub trait Locator: fmt::Debug + fmt::Display + Send + Sync + 'static {
         let err = format_err!("cannot write_remote_data from source {}", source);
         async move { Err(err) }.boxed()
     }
+
+    /// Describe the SQL statements and staging steps that `local_data` would
+    /// run, for `cp --dry-run`. Staging steps are returned as SQL comments.
+    ///
+    /// This may look at the source's metadata, but it must never change
+    /// anything, and it must never include credentials. Returns an empty list
+    /// if we have nothing to describe.
+    fn dry_run_read(
+        &self,
+        _ctx: Context,
+        _shared_args: SharedArguments<Unverified>,
+        _source_args: SourceArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        async { Ok(vec![]) }.boxed()
+    }
+
+    /// Describe the SQL statements and staging steps that `write_local_data`
+    /// would run, or that `write_remote_data` would run if `source` is
+    /// present. The same rules apply as for `dry_run_read`.
+    fn dry_run_write(
+        &self,
+        _ctx: Context,
+        _source: Option<BoxLocator>,
+        _shared_args: SharedArguments<Unverified>,
+        _source_args: SourceArguments<Unverified>,
+        _dest_args: DestinationArguments<Unverified>,
+    ) -> BoxFuture<Vec<String>> {
+        async { Ok(vec![]) }.boxed()
+    }
+
+    /// Acquire the lock `name` using this destination, or fail if another
+    /// process already holds it. Returns `None` if this locator doesn't support
+    /// locks.
+    fn lock(
+        &self,
+        _ctx: Context,
+        _name: String,
+    ) -> BoxFuture<Option<BoxDestinationLock>> {
+        async { Ok(None) }.boxed()
+    }
 }
 
 /// A value of an unknown type implementing `Locator`.
 pub type BoxLocator = Box<dyn Locator>;
 
-fn parse_locator(s: &str, enable_unstable: bool) -> Result<BoxLocator> {
-    // Parse our locator into a URL-style scheme and the rest.
+/// Extract the URL-style scheme from a locator, including the trailing `:`.
+fn locator_scheme(s: &str) -> Result<&str> {
     lazy_static! {
         static ref SCHEME_RE: Regex =
             Regex::new("^[A-Za-z][-A-Za-z0-9+.]*:").expect("invalid regex in source");
     }
-    let cap = SCHEME_RE
-        .captures(s)
+    let mat = SCHEME_RE
+        .find(s)
         .ok_or_else(|| format_err!("cannot parse locator: {:?}", s))?;
-    let scheme = &cap[0];
+    Ok(mat.as_str())
+}
 
+fn parse_locator(s: &str, enable_unstable: bool) -> Result<BoxLocator> {
     // Select an appropriate locator type.
-    let driver = find_driver(scheme, enable_unstable)?;
+    let driver = find_driver(locator_scheme(s)?, enable_unstable)?;
     driver.parse(s)
 }
 
@@ -199,6 +277,7 @@ fn locator_from_str_to_string_roundtrip() {
         "postgres-sql:dir/my_table.sql",
         "s3://example/my-dir/",
         "shopify://example.myshopify.com/admin/api/2020-04/orders.json",
+        "synthetic:1000",
     ];
     for locator in locators.into_iter() {
         let parsed: BoxLocator = parse_locator(locator, true).unwrap();
@@ -218,6 +297,11 @@ impl UnparsedLocator {
     pub fn parse(&self, enable_unstable: bool) -> Result<BoxLocator> {
         parse_locator(&self.0, enable_unstable)
     }
+
+    /// The original text of this locator.
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 impl FromStr for UnparsedLocator {
@@ -232,10 +316,13 @@ impl FromStr for UnparsedLocator {
 /// What `Locator` features are supported by a given driver?
 pub enum LocatorFeatures {
     Schema,
+    QuerySchema,
     WriteSchema,
     LocalData,
     WriteLocalData,
     Count,
+    Estimate,
+    Lock,
 }
 
 /// A collection of all the features supported by a given driver. This is
@@ -270,6 +357,9 @@ impl fmt::Display for Features {
         if self.locator.contains(LocatorFeatures::Schema) {
             writeln!(f, "- conv FROM")?;
         }
+        if self.locator.contains(LocatorFeatures::QuerySchema) {
+            writeln!(f, "- cp --schema-query")?;
+        }
         if self.locator.contains(LocatorFeatures::WriteSchema) {
             writeln!(f, "- conv TO:")?;
             writeln!(f, "  {}", self.write_schema_if_exists.display())?;
@@ -280,6 +370,9 @@ impl fmt::Display for Features {
                 writeln!(f, "  {}", self.source_args.display())?;
             }
         }
+        if self.locator.contains(LocatorFeatures::Estimate) {
+            writeln!(f, "- estimate")?;
+        }
         if self.locator.contains(LocatorFeatures::LocalData) {
             writeln!(f, "- cp FROM:")?;
             if !self.source_args.is_empty() {
@@ -293,6 +386,9 @@ impl fmt::Display for Features {
             }
             writeln!(f, "  {}", self.dest_if_exists.display())?;
         }
+        if self.locator.contains(LocatorFeatures::Lock) {
+            writeln!(f, "- cp --lock")?;
+        }
         Ok(())
     }
 }
@@ -315,6 +411,37 @@ pub trait LocatorStatic: Locator + Clone + FromStr<Err = Error> + Sized {
     fn is_unstable() -> bool {
         false
     }
+
+    /// Schemes of locators which this driver may accept as sources for
+    /// `write_remote_data`. This should match `supports_write_remote_data`,
+    /// ignoring any checks which require an actual locator.
+    fn remote_sources() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Can this driver write columns of type `data_type`?
+    fn can_write_data_type(_data_type: &DataType) -> bool {
+        true
+    }
+
+    /// If this driver writes columns of type `data_type` in a way that loses
+    /// information, describe what we'll write instead. The elements of arrays
+    /// and the fields of structs will be checked separately, unless this
+    /// returns a description for the array or struct itself.
+    fn lossy_data_type(_data_type: &DataType) -> Option<String> {
+        None
+    }
+
+    /// Is `name` a reserved word which this driver will need to quote when it
+    /// uses it as a column name? This ignores case.
+    fn is_reserved_word(_name: &str) -> bool {
+        false
+    }
+
+    /// Limits on the tables which this driver can write.
+    fn limits() -> Limits {
+        Limits::default()
+    }
 }
 
 /// Interface to a locator driver. This exists because we Rust can't treat
@@ -339,6 +466,26 @@ pub trait LocatorDriver: Send + Sync + 'static {
     /// Is this driver unstable?
     fn is_unstable(&self) -> bool;
 
+    /// Schemes of locators which this driver may accept as sources for
+    /// `write_remote_data`.
+    fn remote_sources(&self) -> &'static [&'static str];
+
+    /// Can this driver write columns of type `data_type`?
+    fn can_write_data_type(&self, data_type: &DataType) -> bool;
+
+    /// If this driver writes `data_type` in a way that loses information,
+    /// describe what we'll write instead.
+    fn lossy_data_type(&self, data_type: &DataType) -> Option<String>;
+
+    /// Is `name` a reserved word for this driver?
+    fn is_reserved_word(&self, name: &str) -> bool;
+
+    /// Limits on the tables which this driver can write.
+    fn limits(&self) -> Limits;
+
+    /// Describe what this driver can do.
+    fn capabilities(&self) -> Capabilities;
+
     /// Parse a locator string and return a [`BoxLocator`].
     fn parse(&self, s: &str) -> Result<BoxLocator>;
 }
@@ -372,6 +519,30 @@ impl<L: LocatorStatic> LocatorDriver for LocatorDriverWrapper<L> {
         L::is_unstable()
     }
 
+    fn remote_sources(&self) -> &'static [&'static str] {
+        L::remote_sources()
+    }
+
+    fn can_write_data_type(&self, data_type: &DataType) -> bool {
+        L::can_write_data_type(data_type)
+    }
+
+    fn lossy_data_type(&self, data_type: &DataType) -> Option<String> {
+        L::lossy_data_type(data_type)
+    }
+
+    fn is_reserved_word(&self, name: &str) -> bool {
+        L::is_reserved_word(name)
+    }
+
+    fn limits(&self) -> Limits {
+        L::limits()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::for_driver(self)
+    }
+
     fn parse(&self, s: &str) -> Result<BoxLocator> {
         Ok(Box::new(s.parse::<L>()?))
     }
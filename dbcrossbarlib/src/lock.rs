@@ -0,0 +1,60 @@
+//! Named locks, held using a destination, which prevent two `dbcrossbar`
+//! processes from loading the same data at the same time.
+
+use std::fmt;
+
+use crate::common::*;
+
+/// A lock held using a destination.
+///
+/// Locks must be released explicitly using `release`, because releasing a lock
+/// may require talking to a remote server.
+pub trait DestinationLock: fmt::Debug + Send + Sync + 'static {
+    /// Release this lock.
+    fn release(self: Box<Self>, ctx: Context) -> BoxFuture<()>;
+}
+
+/// A value of an unknown type implementing `DestinationLock`.
+pub type BoxDestinationLock = Box<dyn DestinationLock>;
+
+/// Make sure that `name` is a valid lock name. We're strict about this,
+/// because some drivers use lock names as parts of object names.
+pub(crate) fn check_lock_name(name: &str) -> Result<()> {
+    let is_valid = !name.is_empty()
+        && name.len() <= 128
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+        && !name.starts_with('.');
+    if is_valid {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "lock name {:?} must contain only ASCII letters, digits, `_`, `-` and `.`",
+            name,
+        ))
+    }
+}
+
+/// The error we return when somebody else holds a lock.
+pub(crate) fn lock_held_error(name: &str, locator: &dyn Locator) -> Error {
+    format_err!(
+        "lock {:?} on {} is held by another process, so refusing to copy",
+        name,
+        locator,
+    )
+}
+
+#[test]
+fn checks_lock_names() {
+    for &name in &["nightly", "orders_2020-01.load", "A1"] {
+        assert!(check_lock_name(name).is_ok(), "{:?} should be valid", name);
+    }
+    for &name in &["", ".hidden", "a/b", "two words", "ü"] {
+        assert!(
+            check_lock_name(name).is_err(),
+            "{:?} should be invalid",
+            name
+        );
+    }
+}
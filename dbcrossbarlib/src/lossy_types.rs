@@ -0,0 +1,148 @@
+//! Warning about columns which a driver can't write without losing
+//! information.
+//!
+//! Most portable types map exactly onto a native type in each driver. But a
+//! few don't, such as `decimal` values in BigQuery, which can only keep 9
+//! digits after the decimal point. We warn about these before copying, so
+//! that nobody is surprised by the results.
+
+use crate::common::*;
+use crate::events::Event;
+use crate::locator::LocatorDriver;
+use crate::schema::DataType;
+
+/// A column which a driver can't write without losing information.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LossyColumn {
+    /// The path to this column, such as `"price"`, `"prices[]"` or
+    /// `"address.zip"`.
+    pub column: String,
+    /// The portable type of the column.
+    pub data_type: DataType,
+    /// What the driver will write instead.
+    pub description: String,
+}
+
+/// Find all the columns in `table` which `driver` can't write exactly.
+pub fn find_lossy_columns(
+    driver: &dyn LocatorDriver,
+    table: &Table,
+) -> Vec<LossyColumn> {
+    let mut lossy = vec![];
+    for col in &table.columns {
+        find_lossy_columns_in(driver, &col.name, &col.data_type, &mut lossy);
+    }
+    lossy
+}
+
+/// Add any lossy columns in `data_type` to `lossy`, looking inside arrays and
+/// structs.
+fn find_lossy_columns_in(
+    driver: &dyn LocatorDriver,
+    path: &str,
+    data_type: &DataType,
+    lossy: &mut Vec<LossyColumn>,
+) {
+    if let Some(description) = driver.lossy_data_type(data_type) {
+        lossy.push(LossyColumn {
+            column: path.to_owned(),
+            data_type: data_type.to_owned(),
+            description,
+        });
+        return;
+    }
+    match data_type {
+        DataType::Array(elem) => {
+            find_lossy_columns_in(driver, &format!("{}[]", path), elem, lossy)
+        }
+        DataType::Struct(fields) => {
+            for field in fields {
+                let field_path = format!("{}.{}", path, field.name);
+                find_lossy_columns_in(driver, &field_path, &field.data_type, lossy);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Warn about any columns in `table` which can't be written to `dest` without
+/// losing information, emitting an `Event::LossyType` for each. If `ctx` was
+/// created with `--deny-warnings`, return an error instead of continuing.
+pub fn check_lossy_columns(
+    ctx: &Context,
+    dest: &dyn Locator,
+    table: &Table,
+) -> Result<()> {
+    let lossy = find_lossy_columns(dest.driver()?, table);
+    for col in &lossy {
+        warn!(
+            ctx.log(),
+            "column {:?} will be written to {} as {}",
+            col.column,
+            dest,
+            col.description,
+        );
+        ctx.emit(Event::LossyType {
+            locator: dest.to_string(),
+            column: col.column.clone(),
+            data_type: col.data_type.clone(),
+            description: col.description.clone(),
+        });
+    }
+    if ctx.deny_warnings() && !lossy.is_empty() {
+        Err(format_err!(
+            "{} columns would lose information when written to {}, and \
+             --deny-warnings was specified",
+            lossy.len(),
+            dest,
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[test]
+fn finds_nested_lossy_columns() {
+    use crate::drivers::find_driver;
+    use crate::schema::{Column, StructField};
+
+    let column = |name: &str, data_type| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type,
+        comment: None,
+    };
+    let table = Table {
+        name: "example".to_owned(),
+        columns: vec![
+            column("id", DataType::Int64),
+            column("price", DataType::Decimal),
+            column("prices", DataType::Array(Box::new(DataType::Decimal))),
+            column(
+                "address",
+                DataType::Struct(vec![StructField {
+                    name: "lat".to_owned(),
+                    is_nullable: true,
+                    data_type: DataType::Decimal,
+                }]),
+            ),
+        ],
+    };
+
+    let bigquery = find_driver("bigquery:", false).unwrap();
+    let lossy = find_lossy_columns(bigquery, &table)
+        .into_iter()
+        .map(|col| col.column)
+        .collect::<Vec<_>>();
+    assert_eq!(lossy, &["price", "prices[]", "address.lat"]);
+
+    let postgres = find_driver("postgres:", false).unwrap();
+    let lossy = find_lossy_columns(postgres, &table)
+        .into_iter()
+        .map(|col| col.column)
+        .collect::<Vec<_>>();
+    assert_eq!(lossy, &["address"]);
+
+    let csv = find_driver("csv:", false).unwrap();
+    assert!(find_lossy_columns(csv, &table).is_empty());
+}
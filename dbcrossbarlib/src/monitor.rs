@@ -0,0 +1,300 @@
+//! A live summary of a running command, built from our structured events.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::events::{Event, EventSubscriber};
+
+/// How many recent errors should we show?
+const MAX_RECENT_ERRORS: usize = 5;
+
+/// What we know about a single stream.
+#[derive(Debug)]
+struct StreamState {
+    /// How many bytes we've sent.
+    bytes: u64,
+    /// How many rows we've sent, if we know.
+    rows: Option<u64>,
+    /// Have we finished?
+    finished: bool,
+    /// When did we first hear about this stream?
+    started: Instant,
+    /// When did we last hear about this stream?
+    updated: Instant,
+}
+
+impl StreamState {
+    /// Our average throughput, in bytes per second.
+    fn bytes_per_second(&self) -> f64 {
+        per_second(self.bytes, self.updated.duration_since(self.started))
+    }
+}
+
+/// Everything we display.
+#[derive(Debug)]
+struct MonitorState {
+    /// When did we start?
+    started: Instant,
+    /// What are we doing now?
+    stage: String,
+    /// Our streams, by name.
+    streams: BTreeMap<String, StreamState>,
+    /// How many times have we retried something?
+    retries: u64,
+    /// Our most recent errors and warnings, oldest first.
+    recent_errors: VecDeque<String>,
+}
+
+impl MonitorState {
+    /// Summarize our totals and throughput as a single line of text.
+    fn summary(&self) -> String {
+        let elapsed = self.started.elapsed();
+        let total_bytes = self.streams.values().map(|s| s.bytes).sum::<u64>();
+        let finished = self.streams.values().filter(|s| s.finished).count();
+        let mut summary = format!(
+            "{} elapsed, {}/{} streams done, {} ({}/s)",
+            format_duration(elapsed),
+            finished,
+            self.streams.len(),
+            format_bytes(total_bytes),
+            format_byte_rate(per_second(total_bytes, elapsed)),
+        );
+        // Only report rows if we know how many every stream has sent.
+        let rows = self.streams.values().map(|s| s.rows).sum::<Option<u64>>();
+        if let Some(rows) = rows.filter(|_| !self.streams.is_empty()) {
+            summary.push_str(&format!(
+                ", {} rows ({:.0}/s)",
+                rows,
+                per_second(rows, elapsed),
+            ));
+        }
+        summary.push_str(&format!(", {} retries", self.retries));
+        summary
+    }
+
+    /// Remember an error or warning, forgetting the oldest if we have too many.
+    fn push_error(&mut self, message: String) {
+        if self.recent_errors.len() == MAX_RECENT_ERRORS {
+            self.recent_errors.pop_front();
+        }
+        self.recent_errors.push_back(message);
+    }
+}
+
+/// A subscriber which keeps track of per-stream progress, the current stage,
+/// throughput, retries and recent errors, so that we can display them.
+#[derive(Debug)]
+pub struct Monitor {
+    /// Our state, updated by each event.
+    state: Mutex<MonitorState>,
+}
+
+impl Monitor {
+    /// Create a new monitor. You'll need to subscribe it to an `EventBus`.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Monitor {
+            state: Mutex::new(MonitorState {
+                started: Instant::now(),
+                stage: "starting".to_owned(),
+                streams: BTreeMap::new(),
+                retries: 0,
+                recent_errors: VecDeque::new(),
+            }),
+        })
+    }
+
+    /// Summarize our totals and throughput as a single line of text.
+    pub fn summary(&self) -> String {
+        let state = self.state.lock().expect("lock poisoned, giving up");
+        state.summary()
+    }
+
+    /// Render our current state as lines of text, each at most `width`
+    /// characters long.
+    pub fn render(&self, width: usize) -> Vec<String> {
+        let state = self.state.lock().expect("lock poisoned, giving up");
+        let mut lines = vec![
+            state.summary(),
+            format!("stage: {}", state.stage),
+            String::new(),
+        ];
+        for (name, stream) in &state.streams {
+            let mut line = format!(
+                "  {} {}: {} ({}/s)",
+                if stream.finished { "done" } else { "...." },
+                name,
+                format_bytes(stream.bytes),
+                format_byte_rate(stream.bytes_per_second()),
+            );
+            if let Some(rows) = stream.rows {
+                line.push_str(&format!(", {} rows", rows));
+            }
+            lines.push(line);
+        }
+        if !state.recent_errors.is_empty() {
+            lines.push(String::new());
+            lines.push("recent errors:".to_owned());
+            for error in &state.recent_errors {
+                lines.push(format!("  {}", error.replace('\n', " ")));
+            }
+        }
+        lines
+            .into_iter()
+            .map(|line| line.chars().take(width).collect())
+            .collect()
+    }
+}
+
+impl EventSubscriber for Monitor {
+    fn handle_event(&self, event: &Event) {
+        let mut guard = self.state.lock().expect("lock poisoned, giving up");
+        let state = &mut *guard;
+        match event {
+            Event::Log { level, message } if level == "INFO" => {
+                state.stage = message.to_owned();
+            }
+            Event::Log { level, message } if level == "ERROR" || level == "WARN" => {
+                state.push_error(message.to_owned());
+            }
+            Event::Warning { message } => state.push_error(message.to_owned()),
+            Event::Progress { message, .. } => state.stage = message.to_owned(),
+            Event::StreamProgress {
+                stream,
+                bytes,
+                rows,
+                finished,
+            } => {
+                let now = Instant::now();
+                let stream_state =
+                    state
+                        .streams
+                        .entry(stream.to_owned())
+                        .or_insert(StreamState {
+                            bytes: 0,
+                            rows: None,
+                            finished: false,
+                            started: now,
+                            updated: now,
+                        });
+                stream_state.bytes = *bytes;
+                stream_state.rows = *rows;
+                stream_state.finished = *finished;
+                stream_state.updated = now;
+                if !*finished {
+                    state.stage = "copying data".to_owned();
+                }
+            }
+            Event::Retry { message } => {
+                state.retries += 1;
+                state.push_error(format!("retrying: {}", message));
+            }
+            Event::JobFinished { job_id, .. } => {
+                state.stage = format!("finished job {}", job_id);
+            }
+            Event::SqlExecuted { target, error, .. } => {
+                state.stage = format!("ran SQL on {}", target);
+                if let Some(error) = error {
+                    state.push_error(error.to_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Divide `count` by the number of seconds in `elapsed`.
+fn per_second(count: u64, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds > 0.0 {
+        as_f64(count) / seconds
+    } else {
+        0.0
+    }
+}
+
+/// Convert `count` to `f64` for display.
+fn as_f64(count: u64) -> f64 {
+    // Losing precision above 2^53 doesn't matter for progress reports.
+    #[allow(clippy::cast_precision_loss)]
+    let count = count as f64;
+    count
+}
+
+/// Format `bytes` using binary units, like `1.5 MiB`.
+fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    format_byte_rate(as_f64(bytes))
+}
+
+/// Format a possibly fractional number of bytes, such as a rate, using binary
+/// units.
+fn format_byte_rate(bytes: f64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024.0 {
+        return format!("{:.0} B", bytes);
+    }
+    let mut value = bytes;
+    let mut unit = "B";
+    for &next_unit in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+/// Format `duration` like `1:02:03`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[test]
+fn formats_bytes_and_durations() {
+    assert_eq!(format_bytes(10), "10 B");
+    assert_eq!(format_bytes(1536), "1.5 KiB");
+    assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    assert_eq!(format_duration(Duration::from_secs(3723)), "1:02:03");
+}
+
+#[test]
+fn monitor_tracks_events() {
+    let monitor = Monitor::new();
+    monitor.handle_event(&Event::StreamProgress {
+        stream: "part1".to_owned(),
+        bytes: 2048,
+        rows: Some(10),
+        finished: true,
+    });
+    monitor.handle_event(&Event::Retry {
+        message: "server error".to_owned(),
+    });
+    monitor.handle_event(&Event::Progress {
+        message: "wrote csv:out/part1.csv".to_owned(),
+        completed: 1,
+        total: None,
+    });
+    let lines = monitor.render(200);
+    assert!(lines[0].contains("1/1 streams done"), "{:?}", lines);
+    assert!(lines[0].contains("10 rows"), "{:?}", lines);
+    assert!(lines[0].contains("1 retries"), "{:?}", lines);
+    assert!(monitor.summary().contains("1/1 streams done"));
+    assert_eq!(lines[1], "stage: wrote csv:out/part1.csv");
+    assert!(
+        lines.contains(&"  done part1: 2.0 KiB (0 B/s), 10 rows".to_owned()),
+        "{:?}",
+        lines
+    );
+    assert_eq!(lines.last().unwrap(), "  retrying: server error");
+    assert!(monitor
+        .render(10)
+        .iter()
+        .all(|line| line.chars().count() <= 10));
+}
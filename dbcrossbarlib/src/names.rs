@@ -0,0 +1,377 @@
+//! Normalizing table and column names for destinations with stricter
+//! identifier rules than our source.
+
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use crate::common::*;
+use crate::locator::LocatorDriver;
+use crate::transform::spawn_sync_transform;
+
+/// Rules for normalizing names. Parsed from a comma-separated list like
+/// `lowercase,replace-illegal,max-length=63`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct NameRules {
+    /// Convert names to lowercase.
+    lowercase: bool,
+    /// Replace anything other than ASCII letters, digits and `_` with `_`.
+    replace_illegal: bool,
+    /// Truncate names to at most this many bytes.
+    max_length: Option<usize>,
+    /// Add this suffix to names which are reserved words in the destination.
+    reserved_suffix: Option<String>,
+}
+
+impl NameRules {
+    /// Normalize a single name, without worrying about duplicates.
+    fn normalize_name(&self, name: &str) -> String {
+        // `to_lowercase` uses Unicode's default case mapping, which never
+        // depends on the current locale.
+        let mut name = if self.lowercase {
+            name.to_lowercase()
+        } else {
+            name.to_owned()
+        };
+        if self.replace_illegal {
+            let mut replaced = String::with_capacity(name.len());
+            for c in name.chars() {
+                if c.is_ascii_alphanumeric() {
+                    replaced.push(c);
+                } else if !replaced.ends_with('_') {
+                    replaced.push('_');
+                }
+            }
+            let trimmed = replaced.trim_matches('_');
+            name = if trimmed.is_empty() {
+                "_".to_owned()
+            } else if trimmed.starts_with(|c: char| c.is_ascii_digit()) {
+                format!("_{}", trimmed)
+            } else {
+                trimmed.to_owned()
+            };
+        }
+        self.truncate(&name, 0).to_owned()
+    }
+
+    /// Truncate `name` so that it fits in `max_length` with `reserved` bytes
+    /// to spare, without splitting a character.
+    fn truncate<'a>(&self, name: &'a str, reserved: usize) -> &'a str {
+        let max_length = match self.max_length {
+            Some(max_length) => max_length.saturating_sub(reserved),
+            None => return name,
+        };
+        let mut end = name.len().min(max_length);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        &name[..end]
+    }
+
+    /// Normalize the table and column names in `schema` for the destination
+    /// `dest`. If two columns end up with the same name (ignoring case), we add
+    /// `_2`, `_3`, etc., to the later ones.
+    pub fn normalize_schema(
+        &self,
+        dest: &dyn LocatorDriver,
+        schema: &Table,
+    ) -> Result<Table> {
+        let mut normalized = schema.to_owned();
+        normalized.name = self.normalize_name(&schema.name);
+        let mut seen = HashSet::new();
+        for column in &mut normalized.columns {
+            let mut base = self.normalize_name(&column.name);
+            if let Some(suffix) = &self.reserved_suffix {
+                if dest.is_reserved_word(&base) {
+                    base = format!("{}{}", self.truncate(&base, suffix.len()), suffix);
+                }
+            }
+            let mut name = base.clone();
+            let mut n = 2;
+            while !seen.insert(name.to_lowercase()) {
+                let suffix = format!("_{}", n);
+                let prefix = self.truncate(&base, suffix.len());
+                if prefix.is_empty() {
+                    return Err(format_err!(
+                        "cannot make a unique name for column {:?}",
+                        column.name,
+                    ));
+                }
+                name = format!("{}{}", prefix, suffix);
+                n += 1;
+            }
+            column.name = name;
+        }
+        Ok(normalized)
+    }
+}
+
+impl FromStr for NameRules {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut rules = NameRules::default();
+        for rule in s.split(',') {
+            match rule {
+                "lowercase" => rules.lowercase = true,
+                "replace-illegal" => rules.replace_illegal = true,
+                _ if rule.starts_with("reserved-suffix=") => {
+                    let suffix = &rule["reserved-suffix=".len()..];
+                    if suffix.is_empty() {
+                        return Err(format_err!("invalid name rule {:?}", rule));
+                    }
+                    rules.reserved_suffix = Some(suffix.to_owned());
+                }
+                _ if rule.starts_with("max-length=") => {
+                    let len = rule["max-length=".len()..]
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&len| len > 0)
+                        .ok_or_else(|| format_err!("invalid name rule {:?}", rule))?;
+                    rules.max_length = Some(len);
+                }
+                _ => return Err(format_err!("unknown name rule {:?}", rule)),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+impl fmt::Display for NameRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rules = vec![];
+        if self.lowercase {
+            rules.push("lowercase".to_owned());
+        }
+        if self.replace_illegal {
+            rules.push("replace-illegal".to_owned());
+        }
+        if let Some(max_length) = self.max_length {
+            rules.push(format!("max-length={}", max_length));
+        }
+        if let Some(reserved_suffix) = &self.reserved_suffix {
+            rules.push(format!("reserved-suffix={}", reserved_suffix));
+        }
+        write!(f, "{}", rules.join(","))
+    }
+}
+
+/// Log each column in `original` which was renamed in `renamed` for `dest`,
+/// and each column name which `dest` will need to quote because it's a
+/// reserved word.
+pub fn report_names(
+    ctx: &Context,
+    dest: &dyn Locator,
+    original: &Table,
+    renamed: &Table,
+) -> Result<()> {
+    let driver = dest.driver()?;
+    for (old, new) in original.columns.iter().zip(&renamed.columns) {
+        if old.name != new.name {
+            info!(
+                ctx.log(),
+                "renaming column {:?} to {:?} for {}", old.name, new.name, dest,
+            );
+        }
+        if driver.is_reserved_word(&new.name) {
+            info!(
+                ctx.log(),
+                "column {:?} is a reserved word in {}, so it will be quoted",
+                new.name,
+                dest,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Given a stream of CSV streams, replace each header with the column names
+/// from `schema`, which should be the output of `NameRules::normalize_schema`.
+pub fn rename_csv_headers(
+    ctx: Context,
+    schema: &Table,
+    streams: BoxStream<CsvStream>,
+) -> BoxStream<CsvStream> {
+    let ctx = ctx.child(o!("streams_transform" => "rename_csv_headers"));
+    let names = schema
+        .columns
+        .iter()
+        .map(|c| c.name.clone())
+        .collect::<Vec<_>>();
+    streams
+        .and_then(move |stream| {
+            let ctx = ctx.clone();
+            let names = names.clone();
+            async move {
+                let name = stream.name.clone();
+                let data = spawn_sync_transform(
+                    ctx,
+                    format!("rename headers {}", name),
+                    stream.data,
+                    move |_ctx, rdr, wtr| rename_csv_header(&name, &names, rdr, wtr),
+                )?;
+                Ok(CsvStream {
+                    name: stream.name,
+                    data,
+                })
+            }
+        })
+        .boxed()
+}
+
+/// Copy CSV data from `rdr` to `wtr`, replacing the header with `names`.
+fn rename_csv_header(
+    stream_name: &str,
+    names: &[String],
+    rdr: impl Read,
+    wtr: impl Write,
+) -> Result<()> {
+    let mut rdr = csv::Reader::from_reader(rdr);
+    let mut wtr = csv::Writer::from_writer(wtr);
+    let hdr = rdr
+        .byte_headers()
+        .with_context(|_| format!("cannot read headers of {}", stream_name))?;
+    if hdr.len() != names.len() {
+        return Err(format_err!(
+            "expected {} columns in {}, found {}",
+            names.len(),
+            stream_name,
+            hdr.len(),
+        ));
+    }
+    wtr.write_record(names)?;
+
+    let mut record = csv::ByteRecord::new();
+    while rdr
+        .read_byte_record(&mut record)
+        .with_context(|_| format!("cannot read row from {}", stream_name))?
+    {
+        wtr.write_byte_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[test]
+fn parses_name_rules() {
+    let rules = "lowercase,replace-illegal,max-length=63"
+        .parse::<NameRules>()
+        .unwrap();
+    assert_eq!(
+        rules,
+        NameRules {
+            lowercase: true,
+            replace_illegal: true,
+            max_length: Some(63),
+            reserved_suffix: None,
+        },
+    );
+    assert_eq!(rules.to_string(), "lowercase,replace-illegal,max-length=63");
+    let rules = "reserved-suffix=_col".parse::<NameRules>().unwrap();
+    assert_eq!(rules.reserved_suffix, Some("_col".to_owned()));
+    assert_eq!(rules.to_string(), "reserved-suffix=_col");
+    assert!("reserved-suffix=".parse::<NameRules>().is_err());
+    assert!("uppercase".parse::<NameRules>().is_err());
+    assert!("max-length=0".parse::<NameRules>().is_err());
+}
+
+#[test]
+fn normalizes_names() {
+    let rules = "lowercase,replace-illegal".parse::<NameRules>().unwrap();
+    let examples = &[
+        ("My Column (%)", "my_column"),
+        ("id", "id"),
+        ("2019 Sales", "_2019_sales"),
+        ("Größe", "gr_e"),
+        ("%", "_"),
+        ("İstanbul", "i_stanbul"),
+    ];
+    for &(input, expected) in examples {
+        assert_eq!(rules.normalize_name(input), expected, "{:?}", input);
+    }
+
+    // We never split a multi-byte character.
+    let rules = "max-length=3".parse::<NameRules>().unwrap();
+    assert_eq!(rules.normalize_name("Größe"), "Gr");
+}
+
+#[test]
+fn deduplicates_columns_after_truncation() {
+    use crate::drivers::find_driver;
+    use crate::schema::{Column, DataType};
+
+    let column = |name: &str| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type: DataType::Text,
+        comment: None,
+    };
+    let schema = Table {
+        name: "My Table".to_owned(),
+        columns: vec![
+            column("customer name"),
+            column("Customer Name!"),
+            column("customer_names"),
+        ],
+    };
+    let rules = "lowercase,replace-illegal,max-length=13"
+        .parse::<NameRules>()
+        .unwrap();
+    let postgres = find_driver("postgres:", false).unwrap();
+    let normalized = rules.normalize_schema(postgres, &schema).unwrap();
+    assert_eq!(normalized.name, "my_table");
+    let names = normalized
+        .columns
+        .iter()
+        .map(|c| &c.name[..])
+        .collect::<Vec<_>>();
+    assert_eq!(names, &["customer_name", "customer_na_2", "customer_na_3"]);
+}
+
+#[test]
+fn renames_reserved_words() {
+    use crate::drivers::find_driver;
+    use crate::schema::{Column, DataType};
+
+    let column = |name: &str| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type: DataType::Text,
+        comment: None,
+    };
+    let schema = Table {
+        name: "orders".to_owned(),
+        columns: vec![column("Order"), column("group"), column("key")],
+    };
+    let rules = "reserved-suffix=_col,max-length=7"
+        .parse::<NameRules>()
+        .unwrap();
+
+    let postgres = find_driver("postgres:", false).unwrap();
+    let names = rules
+        .normalize_schema(postgres, &schema)
+        .unwrap()
+        .columns
+        .into_iter()
+        .map(|c| c.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, &["Ord_col", "gro_col", "key"]);
+
+    let mysql = find_driver("mysql:", true).unwrap();
+    let names = rules
+        .normalize_schema(mysql, &schema)
+        .unwrap()
+        .columns
+        .into_iter()
+        .map(|c| c.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, &["Ord_col", "gro_col", "key_col"]);
+}
+
+#[test]
+fn renames_csv_headers() {
+    let names = vec!["my_column".to_owned(), "id".to_owned()];
+    let mut output = vec![];
+    rename_csv_header("test", &names, &b"My Column (%),ID\na,1\n"[..], &mut output)
+        .unwrap();
+    assert_eq!(output, b"my_column,id\na,1\n".to_vec());
+    assert!(rename_csv_header("test", &names, &b"a\n1\n"[..], &mut vec![]).is_err());
+}
@@ -0,0 +1,686 @@
+//! Normalize messy CSV values into our CSV interchange format.
+
+use chrono::NaiveDate;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use crate::common::*;
+use crate::schema::{Column, DataType};
+use crate::transform::spawn_sync_transform;
+
+/// A rule which applies either to every column of a suitable type, or to a
+/// single named column. Parsed from strings of the form `[COL=]RULE`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnRule<T> {
+    /// The column this rule applies to, or `None` for all suitable columns.
+    column: Option<String>,
+    /// The rule itself.
+    rule: T,
+}
+
+impl<T> ColumnRule<T> {
+    /// Does this rule apply to `column`?
+    fn applies_to(&self, column: &Column) -> bool {
+        match &self.column {
+            Some(name) => name == &column.name,
+            None => true,
+        }
+    }
+
+    /// Make sure that if we name a column, it exists and has a suitable type.
+    fn check_column(
+        &self,
+        columns: &[Column],
+        flag: &str,
+        is_suitable: impl Fn(&DataType) -> bool,
+    ) -> Result<()> {
+        if let Some(name) = &self.column {
+            let column = columns
+                .iter()
+                .find(|c| &c.name == name)
+                .ok_or_else(|| format_err!("{}: no column named {:?}", flag, name))?;
+            if !is_suitable(&column.data_type) {
+                return Err(format_err!(
+                    "{}: column {:?} has type {:?}",
+                    flag,
+                    name,
+                    column.data_type,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> FromStr for ColumnRule<T>
+where
+    T: FromStr<Err = Error>,
+{
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.find('=') {
+            Some(0) => Err(format_err!("missing column name in {:?}", s)),
+            Some(pos) => Ok(ColumnRule {
+                column: Some(s[..pos].to_owned()),
+                rule: s[pos + 1..].parse()?,
+            }),
+            None => Ok(ColumnRule {
+                column: None,
+                rule: s.parse()?,
+            }),
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for ColumnRule<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(column) = &self.column {
+            write!(f, "{}=", column)?;
+        }
+        write!(f, "{}", self.rule)
+    }
+}
+
+/// A pair of strings to treat as `true` and `false`, written `TRUE/FALSE`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BoolRule {
+    /// The string we treat as `true`.
+    true_value: String,
+    /// The string we treat as `false`.
+    false_value: String,
+}
+
+impl BoolRule {
+    /// Normalize `cell` to `t` or `f`, if it matches this rule.
+    fn normalize(&self, cell: &str) -> Option<&'static str> {
+        if cell.eq_ignore_ascii_case(&self.true_value) {
+            Some("t")
+        } else if cell.eq_ignore_ascii_case(&self.false_value) {
+            Some("f")
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for BoolRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(2, '/');
+        match (parts.next(), parts.next()) {
+            (Some(t), Some(f)) if !t.is_empty() && !f.is_empty() && t != f => {
+                Ok(BoolRule {
+                    true_value: t.to_owned(),
+                    false_value: f.to_owned(),
+                })
+            }
+            _ => Err(format_err!("expected TRUE/FALSE, found {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for BoolRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.true_value, self.false_value)
+    }
+}
+
+/// A `strftime`-style date format, such as `%d/%m/%Y`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DateFormat(String);
+
+impl DateFormat {
+    /// Normalize `cell` to `%Y-%m-%d`, if it matches this format.
+    fn normalize(&self, cell: &str) -> Option<String> {
+        NaiveDate::parse_from_str(cell, &self.0)
+            .ok()
+            .map(|d| d.format("%Y-%m-%d").to_string())
+    }
+}
+
+impl FromStr for DateFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.contains('%') {
+            Ok(DateFormat(s.to_owned()))
+        } else {
+            Err(format_err!(
+                "expected a date format like %d/%m/%Y, found {:?}",
+                s
+            ))
+        }
+    }
+}
+
+impl fmt::Display for DateFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The separators used by a locale- or currency-specific number format,
+/// written as an example like `1.234,56` or `$1,234.56`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NumberFormat {
+    /// The character used to separate groups of thousands.
+    thousands: char,
+    /// The character used to separate the integer and fractional parts.
+    decimal: char,
+    /// The format as originally written.
+    example: String,
+}
+
+impl NumberFormat {
+    /// Normalize `cell` to a plain number like `-1234.56`, if it matches this
+    /// format. We ignore currency symbols, and treat `(1,234.56)` as negative,
+    /// the way many accounting exports do.
+    fn normalize(&self, cell: &str) -> Option<String> {
+        let is_padding = |c: char| is_currency_symbol(c) || c.is_whitespace();
+        let mut negative = false;
+        let mut s = cell.trim();
+        if s.len() >= 2 && s.starts_with('(') && s.ends_with(')') {
+            negative = true;
+            s = &s[1..s.len() - 1];
+        }
+        s = s.trim_matches(is_padding);
+        if let Some(rest) = s.strip_prefix('-') {
+            if negative {
+                return None;
+            }
+            negative = true;
+            s = rest.trim_start_matches(is_padding);
+        }
+
+        // Split into integer and fractional parts, and check that any
+        // thousands separators are in the right places.
+        let mut parts = s.splitn(2, self.decimal);
+        let int_part = parts.next()?;
+        let frac_part = parts.next();
+        let all_digits =
+            |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        let mut groups = int_part.split(self.thousands);
+        let first_group = groups.next()?;
+        let mut digits = String::with_capacity(s.len() + 1);
+        if negative {
+            digits.push('-');
+        }
+        if !all_digits(first_group) {
+            return None;
+        }
+        digits.push_str(first_group);
+        let mut has_groups = false;
+        for group in groups {
+            if group.len() != 3 || !all_digits(group) {
+                return None;
+            }
+            has_groups = true;
+            digits.push_str(group);
+        }
+        if has_groups && first_group.len() > 3 {
+            return None;
+        }
+        if let Some(frac_part) = frac_part {
+            if !all_digits(frac_part) {
+                return None;
+            }
+            digits.push('.');
+            digits.push_str(frac_part);
+        }
+        Some(digits)
+    }
+}
+
+/// Common currency symbols which may appear in numbers.
+const CURRENCY_SYMBOLS: &str = "$¢£¤¥֏؋৲৳৻૱௹฿៛₠₡₢₣₤₥₦₧₨₩₪₫€₭₮₯₰₱₲₳₴₵₶₷₸₹₺₻₼₽₾₿";
+
+/// Is `c` a currency symbol (such as `$`, `€` or `£`)?
+fn is_currency_symbol(c: char) -> bool {
+    CURRENCY_SYMBOLS.contains(c)
+}
+
+impl FromStr for NumberFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // Look for something shaped like `1,234.56`, ignoring any currency
+        // symbols, and extract the separators.
+        lazy_static! {
+            static ref EXAMPLE_RE: Regex =
+                Regex::new(r"^[^0-9]*?1([^0-9])234([^0-9])56[^0-9]*$")
+                    .expect("invalid `EXAMPLE_RE` in source");
+        }
+        let err = || {
+            format_err!(
+                "expected a number format like 1,234.56 or 1.234,56, found {:?}",
+                s
+            )
+        };
+        let cap = EXAMPLE_RE.captures(s).ok_or_else(err)?;
+        let thousands = cap[1].chars().next().ok_or_else(err)?;
+        let decimal = cap[2].chars().next().ok_or_else(err)?;
+        if thousands == decimal {
+            return Err(err());
+        }
+        Ok(NumberFormat {
+            thousands,
+            decimal,
+            example: s.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for NumberFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.example)
+    }
+}
+
+/// A cleanup step for text values.
+#[derive(Debug, EnumSetType)]
+pub enum Cleanup {
+    /// Remove leading and trailing whitespace.
+    Trim,
+    /// Treat values containing only whitespace as `NULL`.
+    EmptyAsNull,
+    /// Replace line breaks and the whitespace around them with a single space.
+    CollapseNewlines,
+}
+
+impl Cleanup {
+    /// The name of this cleanup step.
+    fn name(self) -> &'static str {
+        match self {
+            Cleanup::Trim => "trim",
+            Cleanup::EmptyAsNull => "empty-as-null",
+            Cleanup::CollapseNewlines => "collapse-newlines",
+        }
+    }
+}
+
+/// A set of cleanup steps, written as a comma-separated list like
+/// `trim,empty-as-null`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cleanups(EnumSet<Cleanup>);
+
+impl Cleanups {
+    /// Apply these cleanups to `cell`.
+    fn apply(self, cell: &str) -> Cow<'_, str> {
+        lazy_static! {
+            static ref NEWLINES_RE: Regex =
+                Regex::new(r"\s*[\r\n]+\s*").expect("invalid `NEWLINES_RE` in source");
+        }
+
+        let mut value = Cow::Borrowed(cell);
+        if self.0.contains(Cleanup::CollapseNewlines) {
+            if let Cow::Owned(collapsed) = NEWLINES_RE.replace_all(&value, " ") {
+                value = Cow::Owned(collapsed);
+            }
+        }
+        if self.0.contains(Cleanup::Trim) {
+            value = match value {
+                Cow::Borrowed(s) => Cow::Borrowed(s.trim()),
+                Cow::Owned(s) => Cow::Owned(s.trim().to_owned()),
+            };
+        }
+        if self.0.contains(Cleanup::EmptyAsNull) && value.trim().is_empty() {
+            value = Cow::Borrowed("");
+        }
+        value
+    }
+}
+
+impl FromStr for Cleanups {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut cleanups = EnumSet::empty();
+        for name in s.split(',') {
+            let cleanup = EnumSet::<Cleanup>::all()
+                .iter()
+                .find(|c| c.name() == name)
+                .ok_or_else(|| format_err!("unknown cleanup {:?}", name))?;
+            cleanups |= cleanup;
+        }
+        Ok(Cleanups(cleanups))
+    }
+}
+
+impl fmt::Display for Cleanups {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, cleanup) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", cleanup.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// Options controlling how we normalize CSV values.
+#[derive(Clone, Debug, Default)]
+pub struct NormalizeOptions {
+    /// Cleanup steps to apply before parsing values.
+    pub cleanups: Vec<ColumnRule<Cleanups>>,
+    /// Extra strings to accept as booleans.
+    pub bools: Vec<ColumnRule<BoolRule>>,
+    /// Extra formats to accept for dates.
+    pub dates: Vec<ColumnRule<DateFormat>>,
+    /// Extra formats to accept for numbers.
+    pub numbers: Vec<ColumnRule<NumberFormat>>,
+}
+
+impl NormalizeOptions {
+    /// Do these options leave the data unchanged?
+    pub fn is_empty(&self) -> bool {
+        self.cleanups.is_empty()
+            && self.bools.is_empty()
+            && self.dates.is_empty()
+            && self.numbers.is_empty()
+    }
+
+    /// Make sure every rule naming a column refers to a suitable column.
+    fn check_columns(&self, columns: &[Column]) -> Result<()> {
+        for rule in &self.cleanups {
+            rule.check_column(columns, "--cleanup", |_| true)?;
+        }
+        for rule in &self.bools {
+            rule.check_column(columns, "--parse-bool", |ty| ty == &DataType::Bool)?;
+        }
+        for rule in &self.dates {
+            rule.check_column(columns, "--parse-date", |ty| ty == &DataType::Date)?;
+        }
+        for rule in &self.numbers {
+            rule.check_column(columns, "--parse-number", is_numeric)?;
+        }
+        Ok(())
+    }
+
+    /// Build a normalizer for a single column.
+    fn for_column(&self, column: &Column) -> ColumnNormalizer {
+        let mut normalizer = ColumnNormalizer::default();
+        for rule in self.cleanups.iter().filter(|r| r.applies_to(column)) {
+            normalizer.cleanups |= rule.rule.0;
+        }
+        match column.data_type {
+            DataType::Bool => {
+                normalizer.bools = self
+                    .bools
+                    .iter()
+                    .filter(|r| r.applies_to(column))
+                    .map(|r| r.rule.clone())
+                    .collect();
+            }
+            DataType::Date => {
+                normalizer.dates = self
+                    .dates
+                    .iter()
+                    .filter(|r| r.applies_to(column))
+                    .map(|r| r.rule.clone())
+                    .collect();
+            }
+            ref ty if is_numeric(ty) => {
+                normalizer.numbers = self
+                    .numbers
+                    .iter()
+                    .filter(|r| r.applies_to(column))
+                    .map(|r| r.rule.clone())
+                    .collect();
+            }
+            _ => {}
+        }
+        normalizer
+    }
+}
+
+/// Is `data_type` a type which `--parse-number` applies to?
+fn is_numeric(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Decimal
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+    )
+}
+
+/// The rules which apply to a single column.
+#[derive(Debug, Default)]
+struct ColumnNormalizer {
+    /// Cleanup steps to apply first.
+    cleanups: EnumSet<Cleanup>,
+    /// Boolean rules to try, in order.
+    bools: Vec<BoolRule>,
+    /// Date formats to try, in order.
+    dates: Vec<DateFormat>,
+    /// Number formats to try, in order.
+    numbers: Vec<NumberFormat>,
+}
+
+impl ColumnNormalizer {
+    /// Does this normalizer leave values unchanged?
+    fn is_empty(&self) -> bool {
+        self.cleanups.is_empty()
+            && self.bools.is_empty()
+            && self.dates.is_empty()
+            && self.numbers.is_empty()
+    }
+
+    /// Normalize a single cell, writing the result to `out`. Values which
+    /// don't match any rule are passed through unchanged (apart from
+    /// cleanups).
+    fn normalize(&self, cell: &str, out: &mut String) {
+        out.clear();
+        let cell = Cleanups(self.cleanups).apply(cell);
+        let cell = cell.as_ref();
+        if let Some(b) = self.bools.iter().find_map(|r| r.normalize(cell)) {
+            out.push_str(b);
+        } else if let Some(d) = self.dates.iter().find_map(|r| r.normalize(cell)) {
+            out.push_str(&d);
+        } else if let Some(n) = self.numbers.iter().find_map(|r| r.normalize(cell)) {
+            out.push_str(&n);
+        } else {
+            out.push_str(cell);
+        }
+    }
+}
+
+/// Given a stream of CSV streams, normalize the values in each stream using
+/// `options` and the column types in `schema`.
+pub fn normalize_csvs(
+    ctx: Context,
+    schema: Table,
+    options: NormalizeOptions,
+    streams: BoxStream<CsvStream>,
+) -> Result<BoxStream<CsvStream>> {
+    options.check_columns(&schema.columns)?;
+    let ctx = ctx.child(o!("streams_transform" => "normalize_csvs"));
+    let normalized = streams.and_then(move |stream| {
+        let ctx = ctx.clone();
+        let columns = schema.columns.clone();
+        let options = options.clone();
+        async move {
+            let name = stream.name.clone();
+            let data = spawn_sync_transform(
+                ctx,
+                format!("normalize {}", name),
+                stream.data,
+                move |_ctx, rdr, wtr| {
+                    normalize_csv(&name, &columns, &options, rdr, wtr)
+                },
+            )?;
+            Ok(CsvStream {
+                name: stream.name,
+                data,
+            })
+        }
+    });
+    Ok(normalized.boxed())
+}
+
+/// Copy CSV data from `rdr` to `wtr`, normalizing each value.
+fn normalize_csv(
+    stream_name: &str,
+    columns: &[Column],
+    options: &NormalizeOptions,
+    rdr: impl Read,
+    wtr: impl Write,
+) -> Result<()> {
+    let mut rdr = csv::Reader::from_reader(rdr);
+    let mut wtr = csv::Writer::from_writer(wtr);
+    let hdr = rdr
+        .byte_headers()
+        .with_context(|_| format!("cannot read headers of {}", stream_name))?
+        .to_owned();
+    if hdr.len() != columns.len() {
+        return Err(format_err!(
+            "{} has {} columns, but the schema has {}",
+            stream_name,
+            hdr.len(),
+            columns.len(),
+        ));
+    }
+    wtr.write_byte_record(&hdr)?;
+
+    let normalizers = columns
+        .iter()
+        .map(|c| options.for_column(c))
+        .collect::<Vec<_>>();
+    let mut record = csv::StringRecord::new();
+    let mut out_record = csv::StringRecord::new();
+    let mut buf = String::new();
+    while rdr
+        .read_record(&mut record)
+        .with_context(|_| format!("cannot read row from {}", stream_name))?
+    {
+        out_record.clear();
+        for (cell, normalizer) in record.iter().zip(&normalizers) {
+            if normalizer.is_empty() {
+                out_record.push_field(cell);
+            } else {
+                normalizer.normalize(cell, &mut buf);
+                out_record.push_field(&buf);
+            }
+        }
+        wtr.write_record(&out_record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[test]
+fn parse_column_rules() {
+    let rule = "yes/no".parse::<ColumnRule<BoolRule>>().unwrap();
+    assert_eq!(rule.column, None);
+    assert_eq!(rule.to_string(), "yes/no");
+
+    let rule = "born=%d/%m/%Y".parse::<ColumnRule<DateFormat>>().unwrap();
+    assert_eq!(rule.column.as_deref(), Some("born"));
+    assert_eq!(rule.to_string(), "born=%d/%m/%Y");
+
+    assert!("=yes/no".parse::<ColumnRule<BoolRule>>().is_err());
+    assert!("yes".parse::<ColumnRule<BoolRule>>().is_err());
+    assert!("x/x".parse::<ColumnRule<BoolRule>>().is_err());
+    assert!("d/m/y".parse::<ColumnRule<DateFormat>>().is_err());
+}
+
+#[test]
+fn normalize_csv_values() {
+    let columns = vec![
+        Column {
+            name: "active".to_owned(),
+            is_nullable: true,
+            data_type: DataType::Bool,
+            comment: None,
+        },
+        Column {
+            name: "born".to_owned(),
+            is_nullable: true,
+            data_type: DataType::Date,
+            comment: None,
+        },
+        Column {
+            name: "died".to_owned(),
+            is_nullable: true,
+            data_type: DataType::Date,
+            comment: None,
+        },
+    ];
+    let options = NormalizeOptions {
+        cleanups: vec![],
+        bools: vec!["oui/non".parse().unwrap()],
+        dates: vec![
+            "born=%d/%m/%Y".parse().unwrap(),
+            "%m/%d/%Y".parse().unwrap(),
+        ],
+        numbers: vec![],
+    };
+    options.check_columns(&columns).unwrap();
+
+    let input = "active,born,died\nOui,20/07/1969,07/20/2019\nnon,,\nt,2001-01-01,x\n";
+    let expected = "active,born,died\nt,1969-07-20,2019-07-20\nf,,\nt,2001-01-01,x\n";
+    let mut output = vec![];
+    normalize_csv("test", &columns, &options, input.as_bytes(), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+    let bad_options = NormalizeOptions {
+        bools: vec!["born=yes/no".parse().unwrap()],
+        ..NormalizeOptions::default()
+    };
+    assert!(bad_options.check_columns(&columns).is_err());
+}
+
+#[test]
+fn cleanup_values() {
+    let cleanups = "trim,empty-as-null,collapse-newlines"
+        .parse::<Cleanups>()
+        .unwrap();
+    assert_eq!(cleanups.to_string(), "trim,empty-as-null,collapse-newlines");
+    assert!("trim,squish".parse::<Cleanups>().is_err());
+
+    let examples = &[
+        ("trim", "  a b  ", "a b"),
+        ("trim", "a", "a"),
+        ("empty-as-null", "   ", ""),
+        ("empty-as-null", " a ", " a "),
+        ("collapse-newlines", "a \r\n\n b", "a b"),
+        ("trim,collapse-newlines", " a\nb\n", "a b"),
+    ];
+    for &(cleanups, input, expected) in examples {
+        let cleanups = cleanups.parse::<Cleanups>().unwrap();
+        assert_eq!(cleanups.apply(input), expected);
+    }
+}
+
+#[test]
+fn normalize_numbers() {
+    let eu = "1.234,56".parse::<NumberFormat>().unwrap();
+    let us = "$1,234.56".parse::<NumberFormat>().unwrap();
+    let ch = "1'234.56".parse::<NumberFormat>().unwrap();
+    assert_eq!(us.to_string(), "$1,234.56");
+    let examples = &[
+        (&eu, "1.234,56", Some("1234.56")),
+        (&eu, "-1.234.567,8 €", Some("-1234567.8")),
+        (&eu, "12", Some("12")),
+        (&us, "$1,234.56", Some("1234.56")),
+        (&us, "-$1,234", Some("-1234")),
+        (&us, "($1,234.56)", Some("-1234.56")),
+        (&us, "1.234,56", None),
+        (&us, "n/a", None),
+        (&us, "", None),
+        (&ch, "1'234.5", Some("1234.5")),
+    ];
+    for &(format, input, expected) in examples {
+        assert_eq!(format.normalize(input).as_deref(), expected, "{:?}", input);
+    }
+
+    assert!("1,234,56".parse::<NumberFormat>().is_err());
+    assert!("1234.56".parse::<NumberFormat>().is_err());
+    assert!("price".parse::<NumberFormat>().is_err());
+}
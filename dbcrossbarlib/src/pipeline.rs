@@ -0,0 +1,252 @@
+//! Multi-step pipelines, with dependencies between steps.
+//!
+//! Pipelines are loaded from a JSON file that looks like:
+//!
+//! ```json
+//! {
+//!   "steps": [
+//!     { "name": "extract", "args": ["cp", "postgres://localhost:5432/db#t", "csv:/tmp/t/"] },
+//!     { "name": "load", "depends_on": ["extract"], "args": ["cp", "csv:/tmp/t/", "bigquery:p:d.t"] },
+//!     { "name": "analyze", "depends_on": ["load"], "exec": ["bq", "query", "SELECT 1"] }
+//!   ]
+//! }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crate::common::*;
+
+/// A single step in a pipeline.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// The name of this step.
+    pub name: String,
+    /// Steps which must finish before this one starts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Arguments to pass to `dbcrossbar`, like `["cp", FROM, TO]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// An external program and its arguments, like `["psql", "-c", SQL]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exec: Vec<String>,
+}
+
+/// A pipeline, as it appears in a pipeline file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineSpec {
+    /// The steps in this pipeline.
+    steps: Vec<Step>,
+}
+
+/// A validated pipeline.
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    /// Our steps, in the order we should run them if we run them one at a
+    /// time.
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    /// Load a pipeline from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Pipeline> {
+        let json = fs::read_to_string(path)
+            .with_context(|_| format!("could not read {}", path.display()))?;
+        Ok(Self::from_json(&json)
+            .with_context(|_| format!("error parsing {}", path.display()))?)
+    }
+
+    /// Parse a pipeline from a JSON string.
+    fn from_json(json: &str) -> Result<Pipeline> {
+        let spec = serde_json::from_str::<PipelineSpec>(json)?;
+        Self::from_steps(spec.steps)
+    }
+
+    /// Check `steps` and sort them so that each step comes after its
+    /// dependencies. Otherwise, steps keep the order they were declared in.
+    fn from_steps(steps: Vec<Step>) -> Result<Pipeline> {
+        if steps.is_empty() {
+            return Err(format_err!("pipeline has no steps"));
+        }
+        let mut indices = HashMap::new();
+        for (idx, step) in steps.iter().enumerate() {
+            if indices.insert(&step.name[..], idx).is_some() {
+                return Err(format_err!("duplicate step {:?}", step.name));
+            }
+            if step.args.is_empty() == step.exec.is_empty() {
+                return Err(format_err!(
+                    "step {:?} must have exactly one of \"args\" or \"exec\"",
+                    step.name,
+                ));
+            }
+        }
+        for step in &steps {
+            for dep in &step.depends_on {
+                if !indices.contains_key(&dep[..]) {
+                    return Err(format_err!(
+                        "step {:?} depends on unknown step {:?}",
+                        step.name,
+                        dep,
+                    ));
+                }
+            }
+        }
+
+        // Repeatedly pick the first step whose dependencies have all been
+        // picked. If we can't find one, we have a cycle.
+        let mut ordered = Vec::with_capacity(steps.len());
+        let mut picked = vec![false; steps.len()];
+        let mut done = HashSet::new();
+        while ordered.len() < steps.len() {
+            let next = (0..steps.len()).find(|&idx| {
+                !picked[idx]
+                    && steps[idx]
+                        .depends_on
+                        .iter()
+                        .all(|dep| done.contains(&dep[..]))
+            });
+            match next {
+                Some(idx) => {
+                    picked[idx] = true;
+                    done.insert(&steps[idx].name[..]);
+                    ordered.push(steps[idx].clone());
+                }
+                None => {
+                    let stuck = steps
+                        .iter()
+                        .enumerate()
+                        .filter(|&(idx, _)| !picked[idx])
+                        .map(|(_, step)| &step.name[..])
+                        .collect::<Vec<_>>();
+                    return Err(format_err!(
+                        "pipeline steps have circular dependencies: {}",
+                        stuck.join(", "),
+                    ));
+                }
+            }
+        }
+        Ok(Pipeline { steps: ordered })
+    }
+
+    /// Our steps, each after all of its dependencies.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+}
+
+/// The steps of a pipeline which have finished, so that we can resume it
+/// after a failure.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PipelineState {
+    /// Each finished step, as it was defined when it ran.
+    completed: BTreeMap<String, Step>,
+}
+
+impl PipelineState {
+    /// Load our state from `path`, or start from scratch if it doesn't
+    /// exist.
+    pub fn load(path: &Path) -> Result<PipelineState> {
+        if !path.exists() {
+            return Ok(PipelineState::default());
+        }
+        let json = fs::read_to_string(path)
+            .with_context(|_| format!("could not read {}", path.display()))?;
+        Ok(serde_json::from_str(&json)
+            .with_context(|_| format!("error parsing {}", path.display()))?)
+    }
+
+    /// Save our state to `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+            .with_context(|_| format!("could not write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Record that `step` has finished.
+    pub fn mark_completed(&mut self, step: &Step) {
+        self.completed.insert(step.name.clone(), step.to_owned());
+    }
+
+    /// Which steps of `pipeline` can we skip? A step can only be skipped if
+    /// it finished without being changed since, and if all of its
+    /// dependencies can be skipped, too.
+    pub fn finished_steps(&self, pipeline: &Pipeline) -> HashSet<String> {
+        let mut finished = HashSet::new();
+        for step in pipeline.steps() {
+            if self.completed.get(&step.name) == Some(step)
+                && step.depends_on.iter().all(|dep| finished.contains(dep))
+            {
+                finished.insert(step.name.clone());
+            }
+        }
+        finished
+    }
+}
+
+#[test]
+fn pipeline_steps_follow_dependencies() {
+    let pipeline = Pipeline::from_json(
+        r#"{
+  "steps": [
+    { "name": "load", "depends_on": ["extract"], "args": ["cp", "csv:a/", "csv:b/"] },
+    { "name": "extract", "args": ["cp", "csv:in/", "csv:a/"] },
+    { "name": "report", "exec": ["true"] }
+  ]
+}"#,
+    )
+    .unwrap();
+    let names = pipeline
+        .steps()
+        .iter()
+        .map(|s| &s.name[..])
+        .collect::<Vec<_>>();
+    assert_eq!(names, &["extract", "load", "report"]);
+}
+
+#[test]
+fn invalid_pipelines_are_rejected() {
+    let invalid = &[
+        r#"{ "steps": [] }"#,
+        r#"{ "steps": [{ "name": "a" }] }"#,
+        r#"{ "steps": [{ "name": "a", "args": ["cp"], "exec": ["true"] }] }"#,
+        r#"{ "steps": [{ "name": "a", "args": ["cp"] }, { "name": "a", "args": ["cp"] }] }"#,
+        r#"{ "steps": [{ "name": "a", "depends_on": ["b"], "args": ["cp"] }] }"#,
+        r#"{ "steps": [
+            { "name": "a", "depends_on": ["b"], "args": ["cp"] },
+            { "name": "b", "depends_on": ["a"], "args": ["cp"] }
+        ] }"#,
+    ];
+    for &json in invalid {
+        assert!(Pipeline::from_json(json).is_err(), "{}", json);
+    }
+}
+
+#[test]
+fn changed_steps_and_their_dependents_are_rerun() {
+    let json = r#"{
+  "steps": [
+    { "name": "a", "exec": ["true"] },
+    { "name": "b", "depends_on": ["a"], "exec": ["true"] },
+    { "name": "c", "exec": ["true"] }
+  ]
+}"#;
+    let pipeline = Pipeline::from_json(json).unwrap();
+    let mut state = PipelineState::default();
+    for step in pipeline.steps() {
+        state.mark_completed(step);
+    }
+    assert_eq!(state.finished_steps(&pipeline).len(), 3);
+
+    let changed = Pipeline::from_json(&json.replacen("true", "false", 1)).unwrap();
+    let finished = state.finished_steps(&changed);
+    assert_eq!(finished.len(), 1);
+    assert!(finished.contains("c"));
+}
@@ -0,0 +1,193 @@
+//! Counting the bytes and rows which flow through each stream, and reporting
+//! them as `Event::StreamProgress`.
+//!
+//! `cp` counts every stream which passes through the local machine. Drivers
+//! which move data some other way can report their own progress using
+//! `Context::progress_counter`.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::common::*;
+use crate::events::Event;
+
+/// How often should we emit `Event::StreamProgress` for a single stream?
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Counts the bytes and rows sent by a single stream, and emits an
+/// `Event::StreamProgress` at most once a second, and again when the stream
+/// finishes.
+#[derive(Debug)]
+pub struct ProgressCounter {
+    /// Where to emit our events.
+    ctx: Context,
+    /// The name of our stream.
+    stream: String,
+    /// How many bytes we've sent.
+    bytes: AtomicU64,
+    /// How many rows we've sent.
+    rows: AtomicU64,
+    /// Has anybody told us about rows? If not, we don't know how many there
+    /// are.
+    counts_rows: AtomicBool,
+    /// When did we last emit an event?
+    last_report: Mutex<Option<Instant>>,
+}
+
+impl ProgressCounter {
+    /// Create a counter for `stream`, which emits events using `ctx`.
+    pub fn new(ctx: Context, stream: &str) -> Arc<Self> {
+        Arc::new(ProgressCounter {
+            ctx,
+            stream: stream.to_owned(),
+            bytes: AtomicU64::new(0),
+            rows: AtomicU64::new(0),
+            counts_rows: AtomicBool::new(false),
+            last_report: Mutex::new(None),
+        })
+    }
+
+    /// Record that we've sent `bytes` more bytes.
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::SeqCst);
+        self.report_if_due();
+    }
+
+    /// Record that we've sent `rows` more rows.
+    pub fn add_rows(&self, rows: u64) {
+        self.counts_rows.store(true, Ordering::SeqCst);
+        self.rows.fetch_add(rows, Ordering::SeqCst);
+        self.report_if_due();
+    }
+
+    /// Record that we've sent the whole stream.
+    pub fn finish(&self) {
+        self.report(true);
+    }
+
+    /// Emit an event if we haven't done so recently.
+    fn report_if_due(&self) {
+        let mut last_report = self.last_report.lock().expect("lock poisoned");
+        if last_report.is_none_or(|t| t.elapsed() >= PROGRESS_INTERVAL) {
+            *last_report = Some(Instant::now());
+            drop(last_report);
+            self.report(false);
+        }
+    }
+
+    /// Emit an event describing our current progress.
+    fn report(&self, finished: bool) {
+        let rows = if self.counts_rows.load(Ordering::SeqCst) {
+            Some(self.rows.load(Ordering::SeqCst))
+        } else {
+            None
+        };
+        self.ctx.emit(Event::StreamProgress {
+            stream: self.stream.clone(),
+            bytes: self.bytes.load(Ordering::SeqCst),
+            rows,
+            finished,
+        });
+    }
+}
+
+/// Counts the rows in CSV data with a header, one chunk at a time, without
+/// parsing it. We only need to track whether we're inside a quoted value,
+/// because a `""` escape inside quotes leaves and re-enters the quotes.
+#[derive(Debug, Default)]
+struct CsvRowCounter {
+    /// Have we seen the end of our header row?
+    seen_header: bool,
+    /// Are we inside a quoted value?
+    in_quotes: bool,
+    /// Have we seen any data since the last line ending?
+    in_row: bool,
+}
+
+impl CsvRowCounter {
+    /// Count the rows which end in `chunk`.
+    fn rows_in(&mut self, chunk: &[u8]) -> u64 {
+        let mut rows = 0;
+        for &b in chunk {
+            match b {
+                b'"' => {
+                    self.in_quotes = !self.in_quotes;
+                    self.in_row = true;
+                }
+                b'\n' if !self.in_quotes => {
+                    if self.seen_header {
+                        rows += 1;
+                    }
+                    self.seen_header = true;
+                    self.in_row = false;
+                }
+                _ => self.in_row = true,
+            }
+        }
+        rows
+    }
+
+    /// Count a final row without a line ending, if there is one.
+    fn finish(&self) -> u64 {
+        if self.seen_header && self.in_row {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// Count the bytes and rows in each stream in `streams`, and report them as
+/// `Event::StreamProgress`.
+pub fn report_stream_progress(
+    ctx: Context,
+    streams: BoxStream<CsvStream>,
+) -> BoxStream<CsvStream> {
+    streams
+        .map_ok(move |stream| {
+            let counter = ctx.progress_counter(&stream.name);
+            let row_counter = Arc::new(Mutex::new(CsvRowCounter::default()));
+            let counted = {
+                let counter = counter.clone();
+                let row_counter = row_counter.clone();
+                stream.data.inspect_ok(move |chunk| {
+                    let rows =
+                        row_counter.lock().expect("lock poisoned").rows_in(chunk);
+                    counter.add_bytes(chunk.len() as u64);
+                    counter.add_rows(rows);
+                })
+            };
+
+            // Once all our data has been read, count any final row without a
+            // line ending, and report that we've finished.
+            let finished = stream::once(async move {
+                counter.add_rows(row_counter.lock().expect("lock poisoned").finish());
+                counter.finish();
+                None::<Result<BytesMut>>
+            })
+            .filter_map(futures::future::ready);
+
+            CsvStream {
+                name: stream.name,
+                data: counted.chain(finished).boxed(),
+            }
+        })
+        .boxed()
+}
+
+#[test]
+fn counts_csv_rows_across_chunks() {
+    let mut counter = CsvRowCounter::default();
+    assert_eq!(counter.rows_in(b"id,note\n1,\"a"), 0);
+    assert_eq!(counter.rows_in(b"\nb\"\n2,\"\"\"quoted\"\"\"\n3,"), 2);
+    assert_eq!(counter.finish(), 1);
+
+    let mut counter = CsvRowCounter::default();
+    assert_eq!(counter.rows_in(b"id\r\n1\r\n2\r\n"), 2);
+    assert_eq!(counter.finish(), 0);
+}
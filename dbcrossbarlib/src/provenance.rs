@@ -0,0 +1,169 @@
+//! Adding row-level provenance columns to a stream of CSV streams.
+
+use chrono::{SecondsFormat, Utc};
+use uuid::{Builder, Uuid, Variant, Version};
+
+use crate::common::*;
+use crate::schema::{Column, DataType};
+use crate::transform::spawn_sync_transform;
+
+/// The column containing the time this copy started.
+const LOADED_AT_COLUMN: &str = "_dbcrossbar_loaded_at";
+
+/// The column containing our job ID.
+const JOB_ID_COLUMN: &str = "_dbcrossbar_job_id";
+
+/// The column containing the name of the stream each row came from.
+const SOURCE_FILE_COLUMN: &str = "_source_file";
+
+/// Provenance information for a single copy.
+#[derive(Clone, Debug)]
+pub struct Provenance {
+    /// When this copy started, in our CSV interchange format.
+    loaded_at: String,
+    /// A unique ID for this copy.
+    job_id: String,
+}
+
+impl Provenance {
+    /// Create provenance information for a new copy. If `job_id` is `None`, a
+    /// random UUID will be used.
+    pub fn new(job_id: Option<String>) -> Provenance {
+        let job_id = job_id.unwrap_or_else(random_job_id);
+        Provenance {
+            loaded_at: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            job_id,
+        }
+    }
+
+    /// The ID of this job.
+    pub fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    /// Add our provenance columns to the end of `schema`.
+    pub fn add_columns_to_schema(&self, schema: &Table) -> Result<Table> {
+        let mut schema = schema.to_owned();
+        for (name, data_type) in &[
+            (LOADED_AT_COLUMN, DataType::TimestampWithTimeZone),
+            (JOB_ID_COLUMN, DataType::Text),
+            (SOURCE_FILE_COLUMN, DataType::Text),
+        ] {
+            if schema.columns.iter().any(|c| c.name == *name) {
+                return Err(format_err!(
+                    "cannot add provenance column {:?}, because it already exists",
+                    name,
+                ));
+            }
+            schema.columns.push(Column {
+                name: (*name).to_owned(),
+                is_nullable: false,
+                data_type: data_type.to_owned(),
+                comment: Some("Added by dbcrossbar".to_owned()),
+            });
+        }
+        Ok(schema)
+    }
+
+    /// Given a stream of CSV streams, add our provenance columns to each row.
+    pub fn add_columns(
+        &self,
+        ctx: Context,
+        streams: BoxStream<CsvStream>,
+    ) -> BoxStream<CsvStream> {
+        let ctx = ctx.child(o!("streams_transform" => "add_provenance"));
+        let provenance = self.clone();
+        streams
+            .and_then(move |stream| {
+                let ctx = ctx.clone();
+                let provenance = provenance.clone();
+                async move {
+                    let name = stream.name.clone();
+                    let data = spawn_sync_transform(
+                        ctx,
+                        format!("add provenance {}", name),
+                        stream.data,
+                        move |_ctx, rdr, wtr| provenance.add_to_csv(&name, rdr, wtr),
+                    )?;
+                    Ok(CsvStream {
+                        name: stream.name,
+                        data,
+                    })
+                }
+            })
+            .boxed()
+    }
+
+    /// Copy CSV data from `rdr` to `wtr`, adding our columns.
+    fn add_to_csv(
+        &self,
+        stream_name: &str,
+        rdr: impl Read,
+        wtr: impl Write,
+    ) -> Result<()> {
+        let mut rdr = csv::Reader::from_reader(rdr);
+        let mut wtr = csv::Writer::from_writer(wtr);
+        let mut hdr = rdr
+            .byte_headers()
+            .with_context(|_| format!("cannot read headers of {}", stream_name))?
+            .to_owned();
+        hdr.push_field(LOADED_AT_COLUMN.as_bytes());
+        hdr.push_field(JOB_ID_COLUMN.as_bytes());
+        hdr.push_field(SOURCE_FILE_COLUMN.as_bytes());
+        wtr.write_byte_record(&hdr)?;
+
+        let mut record = csv::ByteRecord::new();
+        while rdr
+            .read_byte_record(&mut record)
+            .with_context(|_| format!("cannot read row from {}", stream_name))?
+        {
+            record.push_field(self.loaded_at.as_bytes());
+            record.push_field(self.job_id.as_bytes());
+            record.push_field(stream_name.as_bytes());
+            wtr.write_byte_record(&record)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// Generate a random version 4 UUID.
+fn random_job_id() -> String {
+    let uuid: Uuid = Builder::from_bytes(rand::random())
+        .set_variant(Variant::RFC4122)
+        .set_version(Version::Random)
+        .build();
+    uuid.to_string()
+}
+
+#[test]
+fn adds_provenance_columns() {
+    let provenance = Provenance::new(Some("job-1".to_owned()));
+    let schema = Table {
+        name: "test".to_owned(),
+        columns: vec![Column {
+            name: "id".to_owned(),
+            is_nullable: false,
+            data_type: DataType::Int32,
+            comment: None,
+        }],
+    };
+    let extended = provenance.add_columns_to_schema(&schema).unwrap();
+    assert_eq!(extended.columns.len(), 4);
+    assert_eq!(extended.columns[3].name, SOURCE_FILE_COLUMN);
+    assert!(provenance.add_columns_to_schema(&extended).is_err());
+
+    let mut output = vec![];
+    provenance
+        .add_to_csv("people", "id\n1\n".as_bytes(), &mut output)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!(
+            "id,_dbcrossbar_loaded_at,_dbcrossbar_job_id,_source_file\n1,{},job-1,people\n",
+            provenance.loaded_at,
+        ),
+    );
+
+    assert_eq!(Provenance::new(None).job_id().len(), 36);
+}
@@ -0,0 +1,407 @@
+//! A test harness for checking that a driver preserves our data.
+//!
+//! Driver authors can use [`RoundTrip`] to certify a driver or a new data
+//! type: we read test data from a source (typically `synthetic:N`), write it
+//! to the locator being tested, read it back, and check that every value
+//! survived. Values are compared after parsing them according to the schema,
+//! so `t` and `true`, or `1.50` and `1.5`, are considered equal. Rows may be
+//! returned in any order.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::common::*;
+use crate::from_csv_cell::FromCsvCell;
+use crate::schema::{Column, DataType};
+use crate::tokio_glue::ConsumeWithParallelism;
+use crate::validate::check_value;
+
+/// A round-trip test for a single schema.
+pub struct RoundTrip {
+    /// The schema of our test data.
+    pub schema: Table,
+    /// Where to read our test data, such as `synthetic:1000`.
+    pub source: BoxLocator,
+    /// Driver arguments for `source`, such as `seed=42`.
+    pub source_args: DriverArguments,
+    /// Driver arguments to use when writing to the locator being tested.
+    pub to_args: DriverArguments,
+    /// Driver arguments to use when reading from the locator being tested.
+    pub from_args: DriverArguments,
+    /// Temporary storage, for drivers which need it.
+    pub temporary_storage: TemporaryStorage,
+}
+
+impl RoundTrip {
+    /// Create a round-trip test which reads data matching `schema` from
+    /// `source`, using default arguments everywhere.
+    pub fn new(schema: Table, source: BoxLocator) -> RoundTrip {
+        RoundTrip {
+            schema,
+            source,
+            source_args: DriverArguments::default(),
+            to_args: DriverArguments::default(),
+            from_args: DriverArguments::default(),
+            temporary_storage: TemporaryStorage::new(vec![]),
+        }
+    }
+
+    /// Copy our test data to `locator`, overwriting anything there, and copy
+    /// it back. Return an error describing the first difference we find.
+    pub async fn run(&self, ctx: &Context, locator: &BoxLocator) -> Result<()> {
+        let ctx = ctx.child(o!("round_trip" => locator.to_string()));
+        let shared_args = SharedArguments::new(
+            self.schema.clone(),
+            self.temporary_storage.clone(),
+            1,
+        );
+
+        // Read our test data into memory, so that we can compare against it.
+        let source_args = SourceArguments::new(self.source_args.clone(), None);
+        let expected = read_all(&ctx, &self.source, &shared_args, source_args)
+            .await
+            .with_context(|_| format!("cannot read test data from {}", self.source))?;
+
+        // Write our test data to `locator`.
+        debug!(ctx.log(), "writing {} bytes of test data", expected.len());
+        let data = box_stream_once(Ok(CsvStream {
+            name: self.schema.name.clone(),
+            data: box_stream_once(Ok(BytesMut::from(&expected[..]))),
+        }));
+        let dest_args = DestinationArguments::new(
+            self.to_args.clone(),
+            IfExists::Overwrite,
+            SchemaEvolution::default(),
+            ColumnMatching::default(),
+            OutputCompression::default(),
+        );
+        locator
+            .write_local_data(ctx.clone(), data, shared_args.clone(), dest_args)
+            .await?
+            .consume_with_parallelism(1)
+            .await?;
+
+        // Read it back and compare.
+        let source_args = SourceArguments::new(self.from_args.clone(), None);
+        let actual = read_all(&ctx, locator, &shared_args, source_args)
+            .await
+            .with_context(|_| {
+                format!("cannot read test data back from {}", locator)
+            })?;
+        compare_csv_data(&self.schema, &expected, &actual)
+    }
+}
+
+/// Read all the CSV data in `locator`, and concatenate it into a single CSV
+/// file with one header.
+async fn read_all(
+    ctx: &Context,
+    locator: &BoxLocator,
+    shared_args: &SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<Vec<u8>> {
+    let mut streams = locator
+        .local_data(ctx.clone(), shared_args.clone(), source_args)
+        .await?
+        .ok_or_else(|| format_err!("cannot read data from {}", locator))?;
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    let mut wrote_headers = false;
+    while let Some(stream) = streams.try_next().await? {
+        let name = stream.name.clone();
+        let data = stream.data.try_concat().await?;
+        let mut rdr = csv::Reader::from_reader(&data[..]);
+        let headers = rdr
+            .byte_headers()
+            .with_context(|_| format!("cannot read headers of {}", name))?
+            .to_owned();
+        if !wrote_headers {
+            wtr.write_byte_record(&headers)?;
+            wrote_headers = true;
+        }
+        for record in rdr.byte_records() {
+            let record =
+                record.with_context(|_| format!("cannot read row from {}", name))?;
+            wtr.write_byte_record(&record)?;
+        }
+    }
+    wtr.into_inner().map_err(|err| format_err!("{}", err))
+}
+
+/// Check that `expected` and `actual` contain the same values, ignoring row
+/// order, column order and formatting differences.
+fn compare_csv_data(schema: &Table, expected: &[u8], actual: &[u8]) -> Result<()> {
+    let mut expected = parse_rows(schema, expected).context("error in test data")?;
+    let mut actual = parse_rows(schema, actual).context("error in output data")?;
+    if expected.len() != actual.len() {
+        return Err(format_err!(
+            "expected {} rows, found {}",
+            expected.len(),
+            actual.len(),
+        ));
+    }
+    expected.sort();
+    actual.sort();
+    for (expected, actual) in expected.iter().zip(&actual) {
+        if expected != actual {
+            return Err(format_err!(
+                "round trip changed row:\n  expected: {}\n  found:    {}",
+                expected,
+                actual,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parse CSV data into one canonical JSON string per row, with columns in
+/// schema order. We use strings so that rows are easy to sort and print.
+fn parse_rows(schema: &Table, data: &[u8]) -> Result<Vec<String>> {
+    let mut rdr = csv::Reader::from_reader(data);
+    let headers = rdr.headers()?.to_owned();
+    let positions = schema
+        .columns
+        .iter()
+        .map(|col| {
+            headers
+                .iter()
+                .position(|h| h == col.name)
+                .ok_or_else(|| format_err!("missing column {:?}", col.name))
+        })
+        .collect::<Result<Vec<usize>>>()?;
+    let mut rows = vec![];
+    for record in rdr.records() {
+        let record = record?;
+        let mut row = Map::new();
+        for (col, &pos) in schema.columns.iter().zip(&positions) {
+            let value = canonical_cell(col, record.get(pos).unwrap_or_default())
+                .with_context(|_| format!("error in column {:?}", col.name))?;
+            row.insert(col.name.clone(), value);
+        }
+        rows.push(serde_json::to_string(&row)?);
+    }
+    Ok(rows)
+}
+
+/// Convert a CSV cell into a canonical JSON value for comparison.
+pub(crate) fn canonical_cell(col: &Column, cell: &str) -> Result<Value> {
+    if cell.is_empty() {
+        Ok(Value::Null)
+    } else {
+        canonical_value(&col.data_type, cell)
+    }
+}
+
+/// Convert a non-empty CSV cell into a canonical JSON value for comparison.
+fn canonical_value(data_type: &DataType, cell: &str) -> Result<Value> {
+    match data_type {
+        DataType::Array(_) | DataType::Struct(_) => {
+            canonical_json(data_type, &Value::from_csv_cell(cell)?)
+        }
+        DataType::Json | DataType::GeoJson(_) => Value::from_csv_cell(cell),
+        DataType::Bool => Ok(Value::Bool(bool::from_csv_cell(cell)?)),
+        DataType::Date => Ok(Value::String(
+            NaiveDate::from_csv_cell(cell)?
+                .format("%Y-%m-%d")
+                .to_string(),
+        )),
+        DataType::Decimal => {
+            check_value(data_type, cell)?;
+            Ok(Value::String(canonical_decimal(cell)))
+        }
+        // Use `Debug` formatting, which round-trips and handles `NaN`.
+        DataType::Float32 => {
+            Ok(Value::String(format!("{:?}", f32::from_csv_cell(cell)?)))
+        }
+        DataType::Float64 => {
+            Ok(Value::String(format!("{:?}", f64::from_csv_cell(cell)?)))
+        }
+        DataType::Int16 => Ok(Value::from(i16::from_csv_cell(cell)?)),
+        DataType::Int32 => Ok(Value::from(i32::from_csv_cell(cell)?)),
+        DataType::Int64 => Ok(Value::from(i64::from_csv_cell(cell)?)),
+        DataType::Text => Ok(Value::String(cell.to_owned())),
+        DataType::TimestampWithoutTimeZone => Ok(Value::String(
+            NaiveDateTime::from_csv_cell(cell)?
+                .format("%Y-%m-%dT%H:%M:%S%.f")
+                .to_string(),
+        )),
+        DataType::TimestampWithTimeZone => Ok(Value::String(
+            DateTime::<Utc>::from_csv_cell(cell)?
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        )),
+        DataType::Uuid => Ok(Value::String(Uuid::from_csv_cell(cell)?.to_string())),
+    }
+}
+
+/// Convert a JSON value found inside an array or struct into a canonical
+/// value for comparison.
+fn canonical_json(data_type: &DataType, value: &Value) -> Result<Value> {
+    match (data_type, value) {
+        (_, Value::Null) => Ok(Value::Null),
+        (DataType::Array(elem_type), Value::Array(elems)) => Ok(Value::Array(
+            elems
+                .iter()
+                .map(|elem| canonical_json(elem_type, elem))
+                .collect::<Result<Vec<_>>>()?,
+        )),
+        (DataType::Struct(fields), Value::Object(obj)) => {
+            let mut canonical = Map::new();
+            for field in fields {
+                let field_value = obj.get(&field.name).unwrap_or(&Value::Null);
+                canonical.insert(
+                    field.name.clone(),
+                    canonical_json(&field.data_type, field_value)?,
+                );
+            }
+            Ok(Value::Object(canonical))
+        }
+        (DataType::Json, _) | (DataType::GeoJson(_), Value::Object(_)) => {
+            Ok(value.to_owned())
+        }
+        (DataType::Array(_), _) | (DataType::Struct(_), _) => {
+            Err(format_err!("cannot convert {} to {:?}", value, data_type))
+        }
+        (_, Value::String(s)) if !s.is_empty() => canonical_value(data_type, s),
+        (_, Value::Bool(_)) | (_, Value::Number(_)) => {
+            canonical_value(data_type, &value.to_string())
+        }
+        _ => Err(format_err!("cannot convert {} to {:?}", value, data_type)),
+    }
+}
+
+/// Remove redundant signs and zeros from a decimal number, so that `+01.50`
+/// and `1.5` compare equal.
+fn canonical_decimal(cell: &str) -> String {
+    if cell.contains(['e', 'E']) {
+        return cell.to_ascii_lowercase();
+    }
+    let (negative, digits) = match cell.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, cell.trim_start_matches('+')),
+    };
+    let (int_part, frac_part) = match digits.find('.') {
+        Some(idx) => (&digits[..idx], digits[idx + 1..].trim_end_matches('0')),
+        None => (digits, ""),
+    };
+    let int_part = match int_part.trim_start_matches('0') {
+        "" => "0",
+        int_part => int_part,
+    };
+    let is_zero = int_part == "0" && frac_part.is_empty();
+    let mut result = String::new();
+    if negative && !is_zero {
+        result.push('-');
+    }
+    result.push_str(int_part);
+    if !frac_part.is_empty() {
+        result.push('.');
+        result.push_str(frac_part);
+    }
+    result
+}
+
+#[test]
+fn compares_csv_data_by_value() {
+    use crate::schema::StructField;
+
+    let schema = Table {
+        name: "example".to_owned(),
+        columns: vec![
+            Column {
+                name: "b".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Bool,
+                comment: None,
+            },
+            Column {
+                name: "d".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Decimal,
+                comment: None,
+            },
+            Column {
+                name: "ts".to_owned(),
+                is_nullable: true,
+                data_type: DataType::TimestampWithTimeZone,
+                comment: None,
+            },
+            Column {
+                name: "s".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Struct(vec![StructField {
+                    name: "x".to_owned(),
+                    is_nullable: true,
+                    data_type: DataType::Float64,
+                }]),
+                comment: None,
+            },
+        ],
+    };
+    let expected = b"b,d,ts,s\n\
+                     t,1.50,2000-01-01T00:00:00Z,\"{\"\"x\"\":1}\"\n\
+                     f,,,\n";
+    let reordered = b"s,ts,d,b\n\
+                      ,,,false\n\
+                      \"{\"\"x\"\":1.0}\",2000-01-01 00:00:00+00,+1.5,true\n";
+    compare_csv_data(&schema, expected, reordered).unwrap();
+
+    let changed = b"b,d,ts,s\nt,1.51,2000-01-01T00:00:00Z,\nf,,,\n";
+    assert!(compare_csv_data(&schema, expected, changed).is_err());
+    let missing = b"b,d,ts,s\nt,1.50,2000-01-01T00:00:00Z,\n";
+    assert!(compare_csv_data(&schema, expected, missing).is_err());
+
+    assert_eq!(canonical_decimal("-00.100"), "-0.1");
+    assert_eq!(canonical_decimal("-0.00"), "0");
+    assert_eq!(canonical_decimal("+12"), "12");
+}
+
+#[test]
+fn round_trips_synthetic_data_through_csv() {
+    use crate::schema::StructField;
+    use crate::UnparsedLocator;
+
+    let (ctx, worker_fut) = Context::create_for_test("round_trips_through_csv");
+    let cmd_fut = async move {
+        let mut columns = vec![];
+        for (name, data_type) in [
+            ("b", DataType::Bool),
+            ("d", DataType::Date),
+            ("dec", DataType::Decimal),
+            ("f32", DataType::Float32),
+            ("f64", DataType::Float64),
+            ("i64", DataType::Int64),
+            ("j", DataType::Json),
+            ("t", DataType::Text),
+            ("ts", DataType::TimestampWithTimeZone),
+            ("u", DataType::Uuid),
+            ("a", DataType::Array(Box::new(DataType::Int32))),
+            (
+                "s",
+                DataType::Struct(vec![StructField {
+                    name: "x".to_owned(),
+                    is_nullable: true,
+                    data_type: DataType::Text,
+                }]),
+            ),
+        ] {
+            columns.push(Column {
+                name: name.to_owned(),
+                is_nullable: true,
+                data_type,
+                comment: None,
+            });
+        }
+        let schema = Table {
+            name: "example".to_owned(),
+            columns,
+        };
+
+        let dir = tempfile::tempdir()?;
+        let parse = |s: &str| s.parse::<UnparsedLocator>()?.parse(false);
+        let mut round_trip = RoundTrip::new(schema, parse("synthetic:50")?);
+        round_trip.source_args = DriverArguments::from_cli_args(&["seed=1"])?;
+        let dest = parse(&format!("csv:{}/out.csv", dir.path().display()))?;
+        round_trip.run(&ctx, &dest).await
+    };
+    run_futures_with_runtime(cmd_fut.boxed(), worker_fut).unwrap();
+}
@@ -0,0 +1,1058 @@
+//! Applying `--where` clauses to CSV data, for drivers which can't push them
+//! down into a database query.
+//!
+//! We support a small subset of SQL: comparisons, `IS [NOT] NULL`, `[NOT] IN`,
+//! `[NOT] LIKE`, `[NOT] BETWEEN`, `AND`, `OR`, `NOT` and parentheses. Columns
+//! are compared according to their types in the schema, and string literals
+//! are converted to the type of the column they're compared against, so
+//! `created_at >= '2020-01-01'` works as expected. Integer and decimal columns
+//! are compared exactly, without converting them to floating point.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use regex::Regex;
+use std::{cmp::Ordering, fmt, sync::Arc};
+
+use crate::common::*;
+use crate::from_csv_cell::FromCsvCell;
+use crate::parse_error::{Annotation, FileInfo, ParseError};
+use crate::schema::DataType;
+use crate::transform::spawn_sync_transform;
+
+/// A parsed `--where` expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CompareOp, Operand),
+    IsNull(Operand, bool),
+    In(Operand, Vec<Operand>, bool),
+    Like(Operand, String, bool),
+    Between(Operand, Operand, Operand, bool),
+    Operand(Operand),
+}
+
+/// A column or a literal value.
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Column(String),
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+}
+
+/// A comparison operator.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// Does `ordering` satisfy this operator?
+    fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            CompareOp::Eq => ordering == Ordering::Equal,
+            CompareOp::Ne => ordering != Ordering::Equal,
+            CompareOp::Lt => ordering == Ordering::Less,
+            CompareOp::Le => ordering != Ordering::Greater,
+            CompareOp::Gt => ordering == Ordering::Greater,
+            CompareOp::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+peg::parser! {
+    grammar where_grammar() for str {
+        /// A complete `WHERE` expression.
+        pub rule where_clause() -> Expr
+            = ws()? expr:or_expr() ws()? { expr }
+
+        rule or_expr() -> Expr
+            = first:and_expr() rest:(ws() kw("OR") ws()? expr:and_expr() { expr })* {
+                rest.into_iter().fold(first, |l, r| Expr::Or(Box::new(l), Box::new(r)))
+            }
+
+        rule and_expr() -> Expr
+            = first:not_expr() rest:(ws() kw("AND") ws()? expr:not_expr() { expr })* {
+                rest.into_iter().fold(first, |l, r| Expr::And(Box::new(l), Box::new(r)))
+            }
+
+        rule not_expr() -> Expr
+            = kw("NOT") ws()? expr:not_expr() { Expr::Not(Box::new(expr)) }
+            / predicate()
+
+        rule predicate() -> Expr
+            = "(" ws()? expr:or_expr() ws()? ")" { expr }
+            / l:operand() ws()? op:compare_op() ws()? r:operand() {
+                Expr::Compare(l, op, r)
+            }
+            / o:operand() ws() kw("IS") ws() not:(kw("NOT") ws())? kw("NULL") {
+                Expr::IsNull(o, not.is_some())
+            }
+            / o:operand() not:(ws() kw("NOT"))? ws() kw("IN") ws()? "(" ws()?
+                items:(operand() ** (ws()? "," ws()?)) ws()? ")"
+            {
+                Expr::In(o, items, not.is_some())
+            }
+            / o:operand() not:(ws() kw("NOT"))? ws() kw("LIKE") ws()? pattern:string() {
+                Expr::Like(o, pattern, not.is_some())
+            }
+            / o:operand() not:(ws() kw("NOT"))? ws() kw("BETWEEN") ws()
+                low:operand() ws() kw("AND") ws() high:operand()
+            {
+                Expr::Between(o, low, high, not.is_some())
+            }
+            / o:operand() { Expr::Operand(o) }
+
+        rule compare_op() -> CompareOp
+            = "<=" { CompareOp::Le }
+            / ">=" { CompareOp::Ge }
+            / "<>" { CompareOp::Ne }
+            / "!=" { CompareOp::Ne }
+            / "=" { CompareOp::Eq }
+            / "<" { CompareOp::Lt }
+            / ">" { CompareOp::Gt }
+
+        rule operand() -> Operand
+            = quiet! {
+                kw("NULL") { Operand::Null }
+                / kw("TRUE") { Operand::Bool(true) }
+                / kw("FALSE") { Operand::Bool(false) }
+                / (kw("DATE") / kw("TIMESTAMP")) ws()? s:string() { Operand::String(s) }
+                / n:number() { Operand::Number(n) }
+                / s:string() { Operand::String(s) }
+                / id:identifier() { Operand::Column(id) }
+            }
+            / expected!("column or value")
+
+        /// A number, such as `-1` or `2.5e10`.
+        rule number() -> String
+            = n:$(
+                "-"? ['0'..='9']+ ("." ['0'..='9']*)?
+                (['e' | 'E'] ['+' | '-']? ['0'..='9']+)?
+            ) { n.to_owned() }
+
+        /// A single-quoted string, with `''` for a single quote.
+        rule string() -> String
+            = "'" s:$((!['\''] [_] / "''")*) "'" { s.replace("''", "'") }
+
+        /// A column name, either bare or double-quoted.
+        rule identifier() -> String
+            = id:$(
+                ['A'..='Z' | 'a'..='z' | '_'] ['A'..='Z' | 'a'..='z' | '_' | '0'..='9']*
+            ) {?
+                if is_keyword(id) {
+                    Err("column name")
+                } else {
+                    Ok(id.to_owned())
+                }
+            }
+            / "\"" quoted:$((!['"'] [_] / "\"\"")*) "\"" {
+                quoted.replace("\"\"", "\"")
+            }
+
+        /// One or more characters of whitespace.
+        rule ws() = quiet! { [' ' | '\t' | '\r' | '\n']+ }
+
+        /// A keyword, ignoring case, which isn't part of a longer name.
+        rule kw(literal: &'static str)
+            = i(literal) !['A'..='Z' | 'a'..='z' | '_' | '0'..='9']
+
+        /// Match a string literal, ignoring case.
+        rule i(literal: &'static str)
+            // From https://github.com/kevinmehall/rust-peg/issues/216.
+            = input:$([_]*<{literal.len()}>) {?
+                if input.eq_ignore_ascii_case(literal) {
+                    Ok(())
+                } else {
+                    Err(literal)
+                }
+            }
+    }
+}
+
+/// Is `id` a keyword which can't be used as a bare column name? `DATE` and
+/// `TIMESTAMP` are allowed, because they're common column names.
+fn is_keyword(id: &str) -> bool {
+    [
+        "AND", "BETWEEN", "FALSE", "IN", "IS", "LIKE", "NOT", "NULL", "OR", "TRUE",
+    ]
+    .iter()
+    .any(|kw| kw.eq_ignore_ascii_case(id))
+}
+
+/// How we compare values of a column.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    Bool,
+    Integer,
+    Decimal,
+    Float,
+    Date,
+    Timestamp,
+    TimestampTz,
+    Text,
+}
+
+impl Kind {
+    /// How should we compare values of `data_type`?
+    fn for_data_type(data_type: &DataType) -> Kind {
+        match data_type {
+            DataType::Bool => Kind::Bool,
+            DataType::Date => Kind::Date,
+            DataType::Decimal => Kind::Decimal,
+            DataType::Float32 | DataType::Float64 => Kind::Float,
+            DataType::Int16 | DataType::Int32 | DataType::Int64 => Kind::Integer,
+            DataType::TimestampWithoutTimeZone => Kind::Timestamp,
+            DataType::TimestampWithTimeZone => Kind::TimestampTz,
+            DataType::Array(_)
+            | DataType::GeoJson(_)
+            | DataType::Json
+            | DataType::Struct(_)
+            | DataType::Text
+            | DataType::Uuid => Kind::Text,
+        }
+    }
+
+    /// A name for this kind, for error messages.
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Bool => "boolean",
+            Kind::Integer => "integer",
+            Kind::Decimal => "decimal",
+            Kind::Float => "floating-point number",
+            Kind::Date => "date",
+            Kind::Timestamp => "timestamp",
+            Kind::TimestampTz => "timestamp with time zone",
+            Kind::Text => "text",
+        }
+    }
+
+    /// Is this a numeric kind? All numeric kinds can be compared with each
+    /// other.
+    fn is_number(self) -> bool {
+        match self {
+            Kind::Integer | Kind::Decimal | Kind::Float => true,
+            Kind::Bool
+            | Kind::Date
+            | Kind::Timestamp
+            | Kind::TimestampTz
+            | Kind::Text => false,
+        }
+    }
+
+    /// Can values of this kind be compared with values of `other`?
+    fn is_comparable_with(self, other: Kind) -> bool {
+        self == other || (self.is_number() && other.is_number())
+    }
+}
+
+/// An exact decimal number, so that we can compare `numeric` values with
+/// arbitrary precision.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Decimal {
+    /// Is this number less than zero? Always false for zero.
+    negative: bool,
+    /// The digits before the decimal point, without leading zeros.
+    int_digits: String,
+    /// The digits after the decimal point, without trailing zeros.
+    frac_digits: String,
+}
+
+impl Decimal {
+    /// The largest exponent we accept, so that a value like `1e999999999`
+    /// can't make us allocate huge numbers of digits.
+    const MAX_EXPONENT: i64 = 100_000;
+
+    /// Parse a number like `-12.50` or `1.25e3`.
+    fn parse(s: &str) -> Result<Decimal> {
+        let err = || format_err!("expected a decimal number, found {:?}", s);
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+            Some(idx) => {
+                let exponent =
+                    unsigned[idx + 1..].parse::<i64>().map_err(|_| err())?;
+                if exponent.abs() > Decimal::MAX_EXPONENT {
+                    return Err(err());
+                }
+                (&unsigned[..idx], exponent)
+            }
+            None => (unsigned, 0),
+        };
+        let (int_part, frac_part) = match mantissa.find('.') {
+            Some(idx) => (&mantissa[..idx], &mantissa[idx + 1..]),
+            None => (mantissa, ""),
+        };
+        if int_part.is_empty() && frac_part.is_empty()
+            || !int_part.bytes().all(|b| b.is_ascii_digit())
+            || !frac_part.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(err());
+        }
+
+        // Move the decimal point to account for our exponent.
+        let digits = format!("{}{}", int_part, frac_part);
+        let point = i64::try_from(int_part.len()).map_err(|_| err())? + exponent;
+        let (int_digits, frac_digits) = if point <= 0 {
+            let zeros = usize::try_from(-point).map_err(|_| err())?;
+            (String::new(), format!("{}{}", "0".repeat(zeros), digits))
+        } else {
+            let point = usize::try_from(point).map_err(|_| err())?;
+            if point >= digits.len() {
+                let zeros = point - digits.len();
+                (format!("{}{}", digits, "0".repeat(zeros)), String::new())
+            } else {
+                (digits[..point].to_owned(), digits[point..].to_owned())
+            }
+        };
+        let int_digits = int_digits.trim_start_matches('0').to_owned();
+        let frac_digits = frac_digits.trim_end_matches('0').to_owned();
+        let is_zero = int_digits.is_empty() && frac_digits.is_empty();
+        Ok(Decimal {
+            negative: negative && !is_zero,
+            int_digits,
+            frac_digits,
+        })
+    }
+
+    /// Compare the absolute values of two decimals.
+    fn cmp_magnitude(&self, other: &Decimal) -> Ordering {
+        self.int_digits
+            .len()
+            .cmp(&other.int_digits.len())
+            .then_with(|| self.int_digits.cmp(&other.int_digits))
+            .then_with(|| self.frac_digits.cmp(&other.frac_digits))
+    }
+
+    /// Convert to the nearest `f64`, for comparing with floating point values.
+    fn to_f64(&self) -> f64 {
+        self.to_string()
+            .parse::<f64>()
+            .expect("a decimal should always parse as f64")
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Decimal {
+        Decimal::parse(&value.to_string()).expect("an i64 is always a decimal")
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negative {
+            write!(f, "-")?;
+        }
+        if self.int_digits.is_empty() {
+            write!(f, "0")?;
+        } else {
+            write!(f, "{}", self.int_digits)?;
+        }
+        if !self.frac_digits.is_empty() {
+            write!(f, ".{}", self.frac_digits)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => self.cmp_magnitude(other),
+            (true, true) => other.cmp_magnitude(self),
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A value we can compare.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Decimal(Decimal),
+    Float(f64),
+    Date(NaiveDate),
+    Timestamp(NaiveDateTime),
+    TimestampTz(DateTime<Utc>),
+    Text(String),
+}
+
+impl Value {
+    /// Parse `s` as a value of type `kind`.
+    fn parse(kind: Kind, s: &str) -> Result<Value> {
+        Ok(match kind {
+            Kind::Bool => Value::Bool(bool::from_csv_cell(s)?),
+            Kind::Integer => Value::Integer(i64::from_csv_cell(s)?),
+            Kind::Decimal => Value::Decimal(Decimal::parse(s)?),
+            Kind::Float => Value::Float(f64::from_csv_cell(s)?),
+            Kind::Date => Value::Date(NaiveDate::from_csv_cell(s)?),
+            // Dates are treated as midnight, as in SQL.
+            Kind::Timestamp => {
+                Value::Timestamp(NaiveDateTime::from_csv_cell(s).or_else(|err| {
+                    s.parse::<NaiveDate>()
+                        .map(|d| d.and_hms(0, 0, 0))
+                        .map_err(|_| err)
+                })?)
+            }
+            Kind::TimestampTz => Value::TimestampTz(
+                DateTime::<Utc>::from_csv_cell(s).or_else(|err| {
+                    DateTime::parse_from_rfc3339(s)
+                        .map(|t| t.with_timezone(&Utc))
+                        .or_else(|_| {
+                            s.parse::<NaiveDate>()
+                                .map(|d| DateTime::from_utc(d.and_hms(0, 0, 0), Utc))
+                        })
+                        .map_err(|_| err)
+                })?,
+            ),
+            Kind::Text => Value::Text(s.to_owned()),
+        })
+    }
+
+    /// Parse the numeric literal `s`, which will be compared as `kind`. We
+    /// compare integer columns with non-integer literals exactly, as decimals.
+    fn parse_number_literal(kind: Kind, s: &str) -> Result<Value> {
+        Ok(match kind {
+            Kind::Integer => match s.parse::<i64>() {
+                Ok(value) => Value::Integer(value),
+                Err(_) => Value::Decimal(Decimal::parse(s)?),
+            },
+            Kind::Float => Value::Float(f64::from_csv_cell(s)?),
+            _ => Value::Decimal(Decimal::parse(s)?),
+        })
+    }
+
+    /// Convert a numeric value to the nearest `f64`.
+    fn to_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(Decimal::from(*i).to_f64()),
+            Value::Decimal(d) => Some(d.to_f64()),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// Compare two values, returning `None` if either is `NULL` or they can't
+    /// be ordered.
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+            (Value::Decimal(a), Value::Decimal(b)) => Some(a.cmp(b)),
+            (Value::Integer(a), Value::Decimal(b)) => Some(Decimal::from(*a).cmp(b)),
+            (Value::Decimal(a), Value::Integer(b)) => Some(a.cmp(&Decimal::from(*b))),
+            // Floating point values are inexact anyway, so we compare them
+            // with other numbers as floating point.
+            (Value::Float(a), b) => b.to_f64().and_then(|b| a.partial_cmp(&b)),
+            (a, Value::Float(b)) => a.to_f64().and_then(|a| a.partial_cmp(b)),
+            (Value::Date(a), Value::Date(b)) => Some(a.cmp(b)),
+            (Value::Timestamp(a), Value::Timestamp(b)) => Some(a.cmp(b)),
+            (Value::TimestampTz(a), Value::TimestampTz(b)) => Some(a.cmp(b)),
+            (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+/// An operand which has been checked against our schema.
+#[derive(Clone, Debug)]
+enum Bound {
+    /// An index into `RowFilter::columns`.
+    Column(usize),
+    /// A literal value.
+    Value(Value),
+}
+
+/// A condition which has been checked against our schema.
+#[derive(Clone, Debug)]
+enum Cond {
+    Or(Box<Cond>, Box<Cond>),
+    And(Box<Cond>, Box<Cond>),
+    Not(Box<Cond>),
+    Compare(Bound, CompareOp, Bound),
+    IsNull(Bound, bool),
+    In(Bound, Vec<Bound>, bool),
+    Like(Bound, Regex, bool),
+    Between(Bound, Bound, Bound, bool),
+    Value(Bound),
+}
+
+/// A `--where` clause which we can apply to CSV data.
+#[derive(Debug)]
+pub(crate) struct RowFilter {
+    /// The columns used by `cond`, and how to compare them.
+    columns: Vec<(String, Kind)>,
+    /// The condition each row must meet.
+    cond: Cond,
+}
+
+impl RowFilter {
+    /// Parse `where_clause`, and check it against `schema`.
+    pub(crate) fn new(where_clause: &str, schema: &Table) -> Result<RowFilter> {
+        let file_info =
+            Arc::new(FileInfo::new("--where".to_owned(), where_clause.to_owned()));
+        let expr = where_grammar::where_clause(where_clause).map_err(|err| {
+            ParseError::new(
+                file_info.clone(),
+                vec![Annotation::primary(
+                    err.location.offset,
+                    format!("expected {}", err.expected),
+                )],
+                format!("error parsing {}", file_info.name),
+            )
+        })?;
+        let mut binder = Binder {
+            schema,
+            columns: vec![],
+        };
+        let cond = binder
+            .bind(&expr)
+            .with_context(|_| format!("cannot apply --where {:?}", where_clause))?;
+        Ok(RowFilter {
+            columns: binder.columns,
+            cond,
+        })
+    }
+
+    /// Given a stream of CSV streams, keep only the rows which match our
+    /// condition.
+    pub(crate) fn filter_streams(
+        self: Arc<Self>,
+        ctx: Context,
+        streams: BoxStream<CsvStream>,
+    ) -> BoxStream<CsvStream> {
+        let ctx = ctx.child(o!("streams_transform" => "filter_rows"));
+        streams
+            .and_then(move |stream| {
+                let ctx = ctx.clone();
+                let filter = self.clone();
+                async move {
+                    let name = stream.name.clone();
+                    let data = spawn_sync_transform(
+                        ctx,
+                        format!("filter rows {}", name),
+                        stream.data,
+                        move |_ctx, rdr, wtr| filter.filter_csv(&name, rdr, wtr),
+                    )?;
+                    Ok(CsvStream {
+                        name: stream.name,
+                        data,
+                    })
+                }
+            })
+            .boxed()
+    }
+
+    /// Copy the rows of `rdr` which match our condition to `wtr`.
+    fn filter_csv(
+        &self,
+        stream_name: &str,
+        rdr: impl Read,
+        wtr: impl Write,
+    ) -> Result<()> {
+        let mut rdr = csv::Reader::from_reader(rdr);
+        let mut wtr = csv::Writer::from_writer(wtr);
+        let hdr = rdr
+            .headers()
+            .with_context(|_| format!("cannot read headers of {}", stream_name))?
+            .to_owned();
+        let indices = self
+            .columns
+            .iter()
+            .map(|(name, _)| {
+                hdr.iter().position(|h| h == name.as_str()).ok_or_else(|| {
+                    format_err!("cannot find column {:?} in {}", name, stream_name)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        wtr.write_record(&hdr)?;
+
+        let mut record = csv::StringRecord::new();
+        let mut values = Vec::with_capacity(self.columns.len());
+        let mut row = 0;
+        while rdr
+            .read_record(&mut record)
+            .with_context(|_| format!("cannot read row from {}", stream_name))?
+        {
+            row += 1;
+            values.clear();
+            for (&idx, (name, kind)) in indices.iter().zip(&self.columns) {
+                let cell = record.get(idx).unwrap_or("");
+                values.push(if cell.is_empty() {
+                    Value::Null
+                } else {
+                    Value::parse(*kind, cell).with_context(|_| {
+                        format!(
+                            "cannot filter row {} of {}: bad value in column {:?}",
+                            row, stream_name, name,
+                        )
+                    })?
+                });
+            }
+            if eval(&self.cond, &values) == Some(true) {
+                wtr.write_record(&record)?;
+            }
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// Checks an `Expr` against a schema, building a `Cond`.
+struct Binder<'a> {
+    /// The schema of our data.
+    schema: &'a Table,
+    /// The columns we've used so far.
+    columns: Vec<(String, Kind)>,
+}
+
+impl Binder<'_> {
+    /// Bind `expr`.
+    fn bind(&mut self, expr: &Expr) -> Result<Cond> {
+        Ok(match expr {
+            Expr::Or(l, r) => {
+                Cond::Or(Box::new(self.bind(l)?), Box::new(self.bind(r)?))
+            }
+            Expr::And(l, r) => {
+                Cond::And(Box::new(self.bind(l)?), Box::new(self.bind(r)?))
+            }
+            Expr::Not(e) => Cond::Not(Box::new(self.bind(e)?)),
+            Expr::Compare(l, op, r) => {
+                let kind = self.kind_of(&[l, r])?;
+                Cond::Compare(self.operand(l, kind)?, *op, self.operand(r, kind)?)
+            }
+            Expr::IsNull(o, negated) => {
+                let kind = self.kind_of(&[o])?;
+                Cond::IsNull(self.operand(o, kind)?, *negated)
+            }
+            Expr::In(o, items, negated) => {
+                let mut all = vec![o];
+                all.extend(items);
+                let kind = self.kind_of(&all)?;
+                let items = items
+                    .iter()
+                    .map(|item| self.operand(item, kind))
+                    .collect::<Result<Vec<_>>>()?;
+                Cond::In(self.operand(o, kind)?, items, *negated)
+            }
+            Expr::Like(o, pattern, negated) => {
+                let kind = self.kind_of(&[o])?;
+                if kind != Kind::Text {
+                    return Err(format_err!("LIKE can only be used with text"));
+                }
+                Cond::Like(self.operand(o, kind)?, like_regex(pattern)?, *negated)
+            }
+            Expr::Between(o, low, high, negated) => {
+                let kind = self.kind_of(&[o, low, high])?;
+                Cond::Between(
+                    self.operand(o, kind)?,
+                    self.operand(low, kind)?,
+                    self.operand(high, kind)?,
+                    *negated,
+                )
+            }
+            Expr::Operand(o) => {
+                let kind = self.kind_of(&[o])?;
+                if kind != Kind::Bool && *o != Operand::Null {
+                    return Err(format_err!(
+                        "expected a condition, found {} value",
+                        kind.name()
+                    ));
+                }
+                Cond::Value(self.operand(o, Kind::Bool)?)
+            }
+        })
+    }
+
+    /// Decide how to compare `operands`. We use the type of the first column,
+    /// or failing that, the first literal with a type. All columns must have
+    /// the same type.
+    fn kind_of(&self, operands: &[&Operand]) -> Result<Kind> {
+        let mut kind = None;
+        for operand in operands {
+            if let Operand::Column(name) = operand {
+                let column_kind =
+                    Kind::for_data_type(&self.find_column(name)?.data_type);
+                match kind {
+                    None => kind = Some(column_kind),
+                    Some(kind) if !kind.is_comparable_with(column_kind) => {
+                        return Err(format_err!(
+                            "cannot compare {} with {} column {:?}",
+                            kind.name(),
+                            column_kind.name(),
+                            name,
+                        ));
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        Ok(kind.unwrap_or_else(|| {
+            operands
+                .iter()
+                .find_map(|operand| match operand {
+                    Operand::Bool(_) => Some(Kind::Bool),
+                    Operand::Number(_) => Some(Kind::Decimal),
+                    _ => None,
+                })
+                .unwrap_or(Kind::Text)
+        }))
+    }
+
+    /// Bind `operand`, which will be compared as `kind`.
+    fn operand(&mut self, operand: &Operand, kind: Kind) -> Result<Bound> {
+        Ok(match operand {
+            Operand::Column(name) => {
+                // Parse each column using its own type, because we may compare
+                // different kinds of numbers.
+                let column_kind =
+                    Kind::for_data_type(&self.find_column(name)?.data_type);
+                let idx = match self.columns.iter().position(|(n, _)| n == name) {
+                    Some(idx) => idx,
+                    None => {
+                        self.columns.push((name.to_owned(), column_kind));
+                        self.columns.len() - 1
+                    }
+                };
+                Bound::Column(idx)
+            }
+            Operand::Null => Bound::Value(Value::Null),
+            Operand::Bool(b) if kind == Kind::Bool => Bound::Value(Value::Bool(*b)),
+            Operand::Number(n) if kind.is_number() => {
+                Bound::Value(Value::parse_number_literal(kind, n)?)
+            }
+            Operand::String(s) if kind.is_number() => {
+                Bound::Value(Value::parse_number_literal(kind, s).with_context(
+                    |_| format!("cannot compare {:?} with {}", s, kind.name()),
+                )?)
+            }
+            Operand::String(s) => {
+                Bound::Value(Value::parse(kind, s).with_context(|_| {
+                    format!("cannot compare {:?} with {}", s, kind.name())
+                })?)
+            }
+            Operand::Bool(_) | Operand::Number(_) => {
+                let hint = if kind == Kind::Text {
+                    " (to compare CSV columns as numbers or booleans, pass --schema or --infer-schema-rows)"
+                } else {
+                    ""
+                };
+                return Err(format_err!(
+                    "cannot compare {:?} with {}{}",
+                    operand,
+                    kind.name(),
+                    hint,
+                ));
+            }
+        })
+    }
+
+    /// Look up a column in our schema.
+    fn find_column(&self, name: &str) -> Result<&crate::schema::Column> {
+        self.schema
+            .columns
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| format_err!("no column {:?} in {}", name, self.schema.name))
+    }
+}
+
+/// Convert an SQL `LIKE` pattern into a regular expression.
+fn like_regex(pattern: &str) -> Result<Regex> {
+    let mut re = "(?s)^".to_owned();
+    let mut literal = String::new();
+    for c in pattern.chars() {
+        if c == '%' || c == '_' {
+            re.push_str(&regex::escape(&literal));
+            literal.clear();
+            re.push_str(if c == '%' { ".*" } else { "." });
+        } else {
+            literal.push(c);
+        }
+    }
+    re.push_str(&regex::escape(&literal));
+    re.push('$');
+    Ok(Regex::new(&re)?)
+}
+
+/// Look up the value of `bound` in `values`.
+fn value<'a>(bound: &'a Bound, values: &'a [Value]) -> &'a Value {
+    match bound {
+        Bound::Column(idx) => &values[*idx],
+        Bound::Value(value) => value,
+    }
+}
+
+/// Evaluate `cond` using SQL's three-valued logic, where `None` means
+/// "unknown".
+fn eval(cond: &Cond, values: &[Value]) -> Option<bool> {
+    match cond {
+        Cond::Or(l, r) => match (eval(l, values), eval(r, values)) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+        Cond::And(l, r) => match (eval(l, values), eval(r, values)) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        Cond::Not(e) => eval(e, values).map(|b| !b),
+        Cond::Compare(l, op, r) => compare(value(l, values), *op, value(r, values)),
+        Cond::IsNull(o, negated) => {
+            Some((*value(o, values) == Value::Null) != *negated)
+        }
+        Cond::In(o, items, negated) => {
+            let o = value(o, values);
+            let mut result = Some(false);
+            for item in items {
+                match compare(o, CompareOp::Eq, value(item, values)) {
+                    Some(true) => {
+                        result = Some(true);
+                        break;
+                    }
+                    Some(false) => {}
+                    None => result = None,
+                }
+            }
+            result.map(|b| b != *negated)
+        }
+        Cond::Like(o, re, negated) => match value(o, values) {
+            Value::Text(s) => Some(re.is_match(s) != *negated),
+            _ => None,
+        },
+        Cond::Between(o, low, high, negated) => {
+            let o = value(o, values);
+            let above = compare(o, CompareOp::Ge, value(low, values));
+            let below = compare(o, CompareOp::Le, value(high, values));
+            let between = match (above, below) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+            between.map(|b| b != *negated)
+        }
+        Cond::Value(o) => match value(o, values) {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        },
+    }
+}
+
+/// Compare two values, returning `None` if either is `NULL`.
+fn compare(l: &Value, op: CompareOp, r: &Value) -> Option<bool> {
+    if *l == Value::Null || *r == Value::Null {
+        return None;
+    }
+    // Values which can't be ordered, like NaN, are only unequal.
+    Some(l.compare(r).map_or(op == CompareOp::Ne, |o| op.matches(o)))
+}
+
+#[cfg(test)]
+fn test_schema() -> Table {
+    use crate::schema::Column;
+
+    let column = |name: &str, data_type: DataType| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type,
+        comment: None,
+    };
+    Table {
+        name: "t".to_owned(),
+        columns: vec![
+            column("id", DataType::Int64),
+            column("name", DataType::Text),
+            column("active", DataType::Bool),
+            column("created_at", DataType::TimestampWithoutTimeZone),
+            column("Odd Name", DataType::Text),
+        ],
+    }
+}
+
+#[test]
+fn parses_where_clauses() {
+    let parsed = where_grammar::where_clause(
+        "id >= 10 AND NOT (name LIKE 'a%' OR name IS NULL) or \"Odd Name\" in ('x', 'y''s')",
+    )
+    .unwrap();
+    let col = |n: &str| Operand::Column(n.to_owned());
+    assert_eq!(
+        parsed,
+        Expr::Or(
+            Box::new(Expr::And(
+                Box::new(Expr::Compare(
+                    col("id"),
+                    CompareOp::Ge,
+                    Operand::Number("10".to_owned()),
+                )),
+                Box::new(Expr::Not(Box::new(Expr::Or(
+                    Box::new(Expr::Like(col("name"), "a%".to_owned(), false)),
+                    Box::new(Expr::IsNull(col("name"), false)),
+                )))),
+            )),
+            Box::new(Expr::In(
+                col("Odd Name"),
+                vec![
+                    Operand::String("x".to_owned()),
+                    Operand::String("y's".to_owned()),
+                ],
+                false,
+            )),
+        ),
+    );
+    for bad in &["", "id >", "id = 1 AND", "(id = 1", "and = 1", "id = 'x"] {
+        assert!(where_grammar::where_clause(bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn filters_csv_rows() {
+    let schema = test_schema();
+    let input = "id,name,active,created_at,Odd Name\n\
+                 1,alice,t,2020-01-01 00:00:00,x\n\
+                 2,bob,f,2020-02-01 12:00:00,\n\
+                 3,,,2019-12-31 23:59:59,y\n\
+                 10,carol,t,,z\n";
+    let examples = &[
+        ("id > 2", vec![3, 10]),
+        ("id BETWEEN 2 AND 3", vec![2, 3]),
+        ("id NOT IN (1, 2)", vec![3, 10]),
+        ("active", vec![1, 10]),
+        ("NOT active", vec![2]),
+        ("active IS NULL", vec![3]),
+        ("name LIKE '%o%'", vec![2, 10]),
+        ("name NOT LIKE '%o%'", vec![1]),
+        ("created_at >= '2020-01-01'", vec![1, 2]),
+        (
+            "created_at < DATE '2020-01-01' OR created_at IS NULL",
+            vec![3, 10],
+        ),
+        ("\"Odd Name\" <> 'x'", vec![3, 10]),
+        ("id = 1 OR name = 'bob' AND active = FALSE", vec![1, 2]),
+    ];
+    for (where_clause, expected) in examples {
+        let filter = RowFilter::new(where_clause, &schema).unwrap();
+        let mut output = vec![];
+        filter
+            .filter_csv("test", input.as_bytes(), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("id,name,active,created_at,Odd Name"));
+        let ids = lines
+            .map(|l| l.split(',').next().unwrap().parse::<i64>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(&ids, expected, "{}", where_clause);
+    }
+}
+
+#[test]
+fn rejects_invalid_conditions() {
+    let schema = test_schema();
+    for bad in &[
+        "missing = 1",
+        "name = 1",
+        "id = 'abc'",
+        "id LIKE '1%'",
+        "name",
+        "id = name",
+        "created_at > 'yesterday'",
+    ] {
+        assert!(RowFilter::new(bad, &schema).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn parses_and_compares_decimals() {
+    let d = |s: &str| Decimal::parse(s).unwrap();
+    assert_eq!(d("0012.3400").to_string(), "12.34");
+    assert_eq!(d("-0.0").to_string(), "0");
+    assert_eq!(d("-.5").to_string(), "-0.5");
+    assert_eq!(d("1.25e3").to_string(), "1250");
+    assert_eq!(d("125E-4").to_string(), "0.0125");
+    assert_eq!(d("+7.").to_string(), "7");
+    assert_eq!(d("0.1"), d("0.100"));
+    assert!(d("0.1") < d("0.10000000000000000001"));
+    assert!(d("9007199254740993") > d("9007199254740992"));
+    assert!(d("-2") < d("-1.5"));
+    assert!(d("-0.5") < d("0"));
+    assert!(d("10") > d("9.99"));
+    for bad in &["", "-", ".", "1..2", "1e", "abc", "1e1000000000", "1.2.3"] {
+        assert!(Decimal::parse(bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn compares_numbers_exactly() {
+    use crate::schema::Column;
+
+    let column = |name: &str, data_type: DataType| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type,
+        comment: None,
+    };
+    let schema = Table {
+        name: "t".to_owned(),
+        columns: vec![
+            column("id", DataType::Int64),
+            column("amount", DataType::Decimal),
+            column("score", DataType::Float64),
+        ],
+    };
+    let input = "id,amount,score\n\
+                 9007199254740992,0.1,0.5\n\
+                 9007199254740993,0.10000000000000000001,1.5\n\
+                 9007199254740994,12345678901234567890.5,2\n";
+    let examples = &[
+        ("id = 9007199254740993", vec![9007199254740993]),
+        (
+            "id > 9007199254740992.5",
+            vec![9007199254740993, 9007199254740994],
+        ),
+        (
+            "id IN (9007199254740992, 9007199254740994)",
+            vec![9007199254740992, 9007199254740994],
+        ),
+        ("amount = 0.1", vec![9007199254740992]),
+        ("amount > 0.1", vec![9007199254740993, 9007199254740994]),
+        ("amount = '12345678901234567890.5'", vec![9007199254740994]),
+        ("amount < 1E1", vec![9007199254740992, 9007199254740993]),
+        ("score > amount", vec![9007199254740992, 9007199254740993]),
+        ("score >= 2", vec![9007199254740994]),
+    ];
+    for (where_clause, expected) in examples {
+        let filter = RowFilter::new(where_clause, &schema).unwrap();
+        let mut output = vec![];
+        filter
+            .filter_csv("test", input.as_bytes(), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let ids = output
+            .lines()
+            .skip(1)
+            .map(|l| l.split(',').next().unwrap().parse::<i64>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(&ids, expected, "{}", where_clause);
+    }
+}
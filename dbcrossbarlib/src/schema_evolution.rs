@@ -0,0 +1,205 @@
+//! What to do when appending to a destination whose columns differ from our
+//! schema.
+
+use itertools::Itertools;
+use std::{fmt, str::FromStr};
+
+use crate::common::*;
+use crate::schema::Column;
+use crate::select::ColumnSelection;
+
+/// How to handle source columns which are missing from an existing
+/// destination table when appending or upserting.
+///
+/// Columns which exist only in the destination are always left out of the
+/// copy, so they'll be filled in with `NULL` or their default values.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum SchemaEvolution {
+    /// Refuse to copy any columns which the destination doesn't have.
+    #[default]
+    Fail,
+    /// Add any missing columns to the destination, as nullable columns.
+    AddMissingColumns,
+    /// Leave out any columns which the destination doesn't have.
+    IgnoreExtra,
+}
+
+impl SchemaEvolution {
+    /// Compare `schema` against the columns of the existing table
+    /// `dest_name`, using `dest_has_column` to look up column names, and
+    /// decide what we need to change.
+    pub(crate) fn plan(
+        self,
+        schema: &Table,
+        dest_name: &str,
+        dest_has_column: impl Fn(&str) -> bool,
+    ) -> Result<SchemaChanges> {
+        let missing = schema
+            .columns
+            .iter()
+            .filter(|c| !dest_has_column(&c.name))
+            .collect::<Vec<_>>();
+        let mut changes = SchemaChanges::default();
+        if missing.is_empty() {
+            return Ok(changes);
+        }
+        match self {
+            SchemaEvolution::Fail => {
+                return Err(format_err!(
+                    "{} has no columns named {} (try --schema-evolution=add-missing-columns or --schema-evolution=ignore-extra)",
+                    dest_name,
+                    missing.iter().map(|c| &c.name).join(", "),
+                ));
+            }
+            SchemaEvolution::AddMissingColumns => {
+                // Existing rows won't have values for these columns, so they
+                // must be nullable.
+                changes.add_columns = missing
+                    .into_iter()
+                    .map(|c| Column {
+                        is_nullable: true,
+                        ..c.to_owned()
+                    })
+                    .collect();
+            }
+            SchemaEvolution::IgnoreExtra => {
+                changes.ignore_columns =
+                    missing.into_iter().map(|c| c.name.clone()).collect();
+            }
+        }
+        Ok(changes)
+    }
+}
+
+impl fmt::Display for SchemaEvolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaEvolution::Fail => "fail".fmt(f),
+            SchemaEvolution::AddMissingColumns => "add-missing-columns".fmt(f),
+            SchemaEvolution::IgnoreExtra => "ignore-extra".fmt(f),
+        }
+    }
+}
+
+impl FromStr for SchemaEvolution {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SchemaEvolution> {
+        match s {
+            "fail" => Ok(SchemaEvolution::Fail),
+            "add-missing-columns" => Ok(SchemaEvolution::AddMissingColumns),
+            "ignore-extra" => Ok(SchemaEvolution::IgnoreExtra),
+            _ => Err(format_err!("unknown schema evolution policy: {}", s)),
+        }
+    }
+}
+
+/// Changes needed before we can append to an existing table.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct SchemaChanges {
+    /// Columns to add to the destination before copying.
+    pub(crate) add_columns: Vec<Column>,
+    /// Source columns to leave out of the copy.
+    pub(crate) ignore_columns: Vec<String>,
+}
+
+impl SchemaChanges {
+    /// Return `schema` without any ignored columns.
+    pub(crate) fn apply_to_schema(&self, schema: &Table) -> Table {
+        Table {
+            name: schema.name.clone(),
+            columns: schema
+                .columns
+                .iter()
+                .filter(|c| !self.ignore_columns.contains(&c.name))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Remove any ignored columns from `data`, which should match `schema`.
+    pub(crate) fn apply_to_data(
+        &self,
+        ctx: &Context,
+        schema: &Table,
+        data: BoxStream<CsvStream>,
+    ) -> BoxStream<CsvStream> {
+        if self.ignore_columns.is_empty() {
+            data
+        } else {
+            let kept = self.apply_to_schema(schema);
+            ColumnSelection::from_names(kept.columns.into_iter().map(|c| c.name))
+                .select_csv_columns(ctx.to_owned(), data)
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_schema() -> Table {
+    use crate::schema::DataType;
+
+    let column = |name: &str, is_nullable: bool| Column {
+        name: name.to_owned(),
+        is_nullable,
+        data_type: DataType::Text,
+        comment: None,
+    };
+    Table {
+        name: "t".to_owned(),
+        columns: vec![column("id", false), column("a", true), column("b", false)],
+    }
+}
+
+#[test]
+fn parse_and_display() {
+    for &policy in &[
+        SchemaEvolution::Fail,
+        SchemaEvolution::AddMissingColumns,
+        SchemaEvolution::IgnoreExtra,
+    ] {
+        assert_eq!(
+            policy.to_string().parse::<SchemaEvolution>().unwrap(),
+            policy
+        );
+    }
+    assert!("add".parse::<SchemaEvolution>().is_err());
+}
+
+#[test]
+fn plans_schema_changes() {
+    let schema = test_schema();
+    let dest_has_column = |name: &str| name == "id" || name == "extra";
+
+    assert!(SchemaEvolution::Fail
+        .plan(&schema, "dest", dest_has_column)
+        .is_err());
+    assert_eq!(
+        SchemaEvolution::Fail
+            .plan(&schema, "dest", |_| true)
+            .unwrap(),
+        SchemaChanges::default(),
+    );
+
+    let changes = SchemaEvolution::AddMissingColumns
+        .plan(&schema, "dest", dest_has_column)
+        .unwrap();
+    assert!(changes.ignore_columns.is_empty());
+    assert_eq!(
+        changes
+            .add_columns
+            .iter()
+            .map(|c| (&c.name[..], c.is_nullable))
+            .collect::<Vec<_>>(),
+        vec![("a", true), ("b", true)],
+    );
+
+    let changes = SchemaEvolution::IgnoreExtra
+        .plan(&schema, "dest", dest_has_column)
+        .unwrap();
+    assert!(changes.add_columns.is_empty());
+    assert_eq!(changes.ignore_columns, vec!["a", "b"]);
+    assert_eq!(
+        changes.apply_to_schema(&schema).columns,
+        vec![schema.columns[0].clone()],
+    );
+}
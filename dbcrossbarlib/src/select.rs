@@ -0,0 +1,421 @@
+//! Selecting and renaming columns during a copy, using `--select`.
+
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use crate::common::*;
+use crate::schema::Column;
+use crate::transform::spawn_sync_transform;
+
+/// A single column in a `--select` list.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct SelectedColumn {
+    /// The name of the column in the source.
+    source: String,
+    /// The name of the column in the destination.
+    name: String,
+}
+
+/// A list of columns to copy, parsed from something like `"a, b AS c"`.
+/// Column names containing spaces, commas or other special characters can be
+/// written in double quotes, as in `"first name" AS first_name`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnSelection {
+    /// The columns to copy, in order.
+    columns: Vec<SelectedColumn>,
+}
+
+impl ColumnSelection {
+    /// Select the columns named `names`, without renaming them.
+    pub(crate) fn from_names(names: impl IntoIterator<Item = String>) -> Self {
+        ColumnSelection {
+            columns: names
+                .into_iter()
+                .map(|name| SelectedColumn {
+                    source: name.clone(),
+                    name,
+                })
+                .collect(),
+        }
+    }
+
+    /// Select the columns named by the first value in each pair, renaming them
+    /// to the second.
+    pub(crate) fn from_renames(
+        renames: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        ColumnSelection {
+            columns: renames
+                .into_iter()
+                .map(|(source, name)| SelectedColumn { source, name })
+                .collect(),
+        }
+    }
+
+    /// Return a copy of `schema` containing only the source columns we need to
+    /// read, in the order they were selected, using their original names.
+    pub fn source_schema(&self, schema: &Table) -> Result<Table> {
+        let mut seen = HashSet::new();
+        let mut columns = vec![];
+        for selected in &self.columns {
+            let column = find_column(schema, &selected.source)?;
+            if seen.insert(&column.name) {
+                columns.push(column.to_owned());
+            }
+        }
+        Ok(Table {
+            name: schema.name.clone(),
+            columns,
+        })
+    }
+
+    /// Return the schema of our output, with the selected columns from
+    /// `schema` in order, renamed as requested.
+    pub fn select_schema(&self, schema: &Table) -> Result<Table> {
+        let mut seen = HashSet::new();
+        let mut columns = vec![];
+        for selected in &self.columns {
+            if !seen.insert(selected.name.to_lowercase()) {
+                return Err(format_err!(
+                    "--select includes more than one column named {:?}",
+                    selected.name,
+                ));
+            }
+            let mut column = find_column(schema, &selected.source)?.to_owned();
+            column.name = selected.name.clone();
+            columns.push(column);
+        }
+        Ok(Table {
+            name: schema.name.clone(),
+            columns,
+        })
+    }
+
+    /// Given a stream of CSV streams, keep only the selected columns, in
+    /// order, and rename them. Columns are found using the CSV headers, so
+    /// this works whether or not the source has already dropped the
+    /// unselected columns.
+    pub fn select_csv_columns(
+        &self,
+        ctx: Context,
+        streams: BoxStream<CsvStream>,
+    ) -> BoxStream<CsvStream> {
+        let ctx = ctx.child(o!("streams_transform" => "select_csv_columns"));
+        let selection = self.to_owned();
+        streams
+            .and_then(move |stream| {
+                let ctx = ctx.clone();
+                let selection = selection.clone();
+                async move {
+                    let name = stream.name.clone();
+                    let data = spawn_sync_transform(
+                        ctx,
+                        format!("select columns {}", name),
+                        stream.data,
+                        move |_ctx, rdr, wtr| selection.select_csv(&name, rdr, wtr),
+                    )?;
+                    Ok(CsvStream {
+                        name: stream.name,
+                        data,
+                    })
+                }
+            })
+            .boxed()
+    }
+
+    /// Copy CSV data from `rdr` to `wtr`, keeping only the selected columns.
+    fn select_csv(
+        &self,
+        stream_name: &str,
+        rdr: impl Read,
+        wtr: impl Write,
+    ) -> Result<()> {
+        let mut rdr = csv::Reader::from_reader(rdr);
+        let mut wtr = csv::Writer::from_writer(wtr);
+        let hdr = rdr
+            .headers()
+            .with_context(|_| format!("cannot read headers of {}", stream_name))?;
+        let indices = self
+            .columns
+            .iter()
+            .map(|selected| {
+                hdr.iter()
+                    .position(|name| name == selected.source)
+                    .ok_or_else(|| {
+                        format_err!(
+                            "cannot find column {:?} in {}",
+                            selected.source,
+                            stream_name,
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        wtr.write_record(self.columns.iter().map(|selected| &selected.name))?;
+
+        let mut record = csv::ByteRecord::new();
+        let mut selected = csv::ByteRecord::new();
+        while rdr
+            .read_byte_record(&mut record)
+            .with_context(|_| format!("cannot read row from {}", stream_name))?
+        {
+            selected.clear();
+            for &idx in &indices {
+                selected.push_field(record.get(idx).ok_or_else(|| {
+                    format_err!("missing column in row of {}", stream_name)
+                })?);
+            }
+            wtr.write_byte_record(&selected)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// Find the column named `name` in `schema`.
+fn find_column<'a>(schema: &'a Table, name: &str) -> Result<&'a Column> {
+    schema
+        .columns
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| {
+            format_err!(
+                "--select: no column {:?} in {} (available columns: {})",
+                name,
+                schema.name,
+                schema
+                    .columns
+                    .iter()
+                    .map(|c| &c.name[..])
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+        })
+}
+
+impl FromStr for ColumnSelection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            chars: s.chars().peekable(),
+        };
+        let mut columns = vec![];
+        loop {
+            let source = parser
+                .identifier()?
+                .ok_or_else(|| format_err!("expected a column name in {:?}", s))?;
+            let name = if parser.keyword_as() {
+                parser.identifier()?.ok_or_else(|| {
+                    format_err!("expected a column name after AS in {:?}", s)
+                })?
+            } else {
+                source.clone()
+            };
+            columns.push(SelectedColumn { source, name });
+            parser.skip_whitespace();
+            match parser.chars.next() {
+                None => break,
+                Some(',') => {}
+                Some(c) => {
+                    return Err(format_err!("unexpected {:?} in {:?}", c, s));
+                }
+            }
+        }
+        Ok(ColumnSelection { columns })
+    }
+}
+
+impl fmt::Display for ColumnSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", DisplayIdentifier(&column.source))?;
+            if column.name != column.source {
+                write!(f, " AS {}", DisplayIdentifier(&column.name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Display a column name, quoting it if necessary.
+struct DisplayIdentifier<'a>(&'a str);
+
+impl fmt::Display for DisplayIdentifier<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.0.is_empty()
+            && !self.0.eq_ignore_ascii_case("as")
+            && self.0.chars().all(is_bare_identifier_char)
+        {
+            write!(f, "{}", self.0)
+        } else {
+            write!(f, "\"{}\"", self.0.replace('"', "\"\""))
+        }
+    }
+}
+
+/// Can `c` appear in a column name without quotes?
+fn is_bare_identifier_char(c: char) -> bool {
+    !c.is_whitespace() && c != ',' && c != '"'
+}
+
+/// A tiny parser for `--select` lists.
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    /// Skip over any whitespace.
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    /// Parse a bare or double-quoted column name, if there is one.
+    fn identifier(&mut self) -> Result<Option<String>> {
+        self.skip_whitespace();
+        let mut name = String::new();
+        if self.chars.peek() == Some(&'"') {
+            self.chars.next();
+            loop {
+                match self.chars.next() {
+                    Some('"') if self.chars.peek() == Some(&'"') => {
+                        self.chars.next();
+                        name.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => name.push(c),
+                    None => {
+                        return Err(format_err!("unterminated quoted column name"))
+                    }
+                }
+            }
+            if name.is_empty() {
+                return Err(format_err!("column names cannot be empty"));
+            }
+        } else {
+            while let Some(&c) = self.chars.peek() {
+                if !is_bare_identifier_char(c) {
+                    break;
+                }
+                name.push(c);
+                self.chars.next();
+            }
+            if name.is_empty() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(name))
+    }
+
+    /// Parse an optional `AS` keyword (in any case), which must be followed by
+    /// whitespace or a quoted name.
+    fn keyword_as(&mut self) -> bool {
+        self.skip_whitespace();
+        let mut lookahead = self.chars.clone();
+        let matched = matches!(lookahead.next(), Some('a') | Some('A'))
+            && matches!(lookahead.next(), Some('s') | Some('S'))
+            && lookahead
+                .peek()
+                .is_some_and(|&c| c.is_whitespace() || c == '"');
+        if matched {
+            self.chars = lookahead;
+        }
+        matched
+    }
+}
+
+#[test]
+fn parses_column_selections() {
+    let selection = "col_a, col_b AS b,\"first name\" as first_name, \"x\"\"y\""
+        .parse::<ColumnSelection>()
+        .unwrap();
+    let pairs = selection
+        .columns
+        .iter()
+        .map(|c| (&c.source[..], &c.name[..]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        pairs,
+        &[
+            ("col_a", "col_a"),
+            ("col_b", "b"),
+            ("first name", "first_name"),
+            ("x\"y", "x\"y"),
+        ],
+    );
+    assert_eq!(
+        selection.to_string(),
+        "col_a, col_b AS b, \"first name\" AS first_name, \"x\"\"y\"",
+    );
+    assert_eq!(
+        selection.to_string().parse::<ColumnSelection>().unwrap(),
+        selection,
+    );
+
+    // A column may be named `as`, if it's quoted.
+    let selection = "\"as\" AS a".parse::<ColumnSelection>().unwrap();
+    assert_eq!(selection.columns[0].source, "as");
+
+    for bad in &["", "a,", ",a", "a AS", "a b", "\"a", "a AS \"\""] {
+        assert!(bad.parse::<ColumnSelection>().is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn selects_and_renames_schema_columns() {
+    use crate::schema::DataType;
+
+    let column = |name: &str, data_type: DataType| Column {
+        name: name.to_owned(),
+        is_nullable: true,
+        data_type,
+        comment: None,
+    };
+    let schema = Table {
+        name: "orders".to_owned(),
+        columns: vec![
+            column("id", DataType::Int64),
+            column("total", DataType::Decimal),
+            column("note", DataType::Text),
+        ],
+    };
+    let selection = "note, id AS order_id, id"
+        .parse::<ColumnSelection>()
+        .unwrap();
+    let source = selection.source_schema(&schema).unwrap();
+    assert_eq!(
+        source.columns,
+        vec![schema.columns[2].clone(), schema.columns[0].clone()]
+    );
+    let dest = selection.select_schema(&schema).unwrap();
+    assert_eq!(
+        dest.columns,
+        vec![
+            column("note", DataType::Text),
+            column("order_id", DataType::Int64),
+            column("id", DataType::Int64),
+        ],
+    );
+
+    let missing = "id, price".parse::<ColumnSelection>().unwrap();
+    assert!(missing.source_schema(&schema).is_err());
+    assert!(missing.select_schema(&schema).is_err());
+    let duplicate = "id, total AS ID".parse::<ColumnSelection>().unwrap();
+    assert!(duplicate.select_schema(&schema).is_err());
+}
+
+#[test]
+fn selects_csv_columns() {
+    let selection = "c, a AS x".parse::<ColumnSelection>().unwrap();
+    let mut output = vec![];
+    selection
+        .select_csv("test", &b"a,b,c\n1,2,3\n4,5,\"6,7\"\n"[..], &mut output)
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "c,x\n3,1\n\"6,7\",4\n",);
+    assert!(selection
+        .select_csv("test", &b"a,b\n1,2\n"[..], &mut vec![])
+        .is_err());
+}
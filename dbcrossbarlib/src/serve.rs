@@ -0,0 +1,610 @@
+//! An HTTP API for running copy jobs, so that `dbcrossbar` can be run as a
+//! shared service with a single set of credentials.
+//!
+//! Each job runs `dbcrossbar cp` in a separate process, with an event log that
+//! we read to report progress. The API looks like:
+//!
+//! - `GET /health`: Check that the server is running.
+//! - `POST /jobs`: Submit a job, with a body like `{"args": ["csv:in/", "csv:out/"]}`.
+//! - `GET /jobs`: List all jobs.
+//! - `GET /jobs/$ID`: Get the status and progress of a job.
+//! - `GET /jobs/$ID/log`: Get the log output of a job.
+//! - `DELETE /jobs/$ID`: Cancel a job.
+
+use chrono::Utc;
+use futures::future::{self, Either};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::{
+    process::Command,
+    sync::{oneshot, Semaphore},
+};
+
+use crate::common::*;
+use crate::notify::hide_password;
+
+/// The largest request body we'll accept.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// How many finished jobs should we remember? When more jobs than this have
+/// finished, we forget the oldest ones and delete their logs, so that a
+/// long-running server doesn't grow without bound.
+const MAX_FINISHED_JOBS: usize = 1000;
+
+/// The state of a job.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for another job to finish.
+    Queued,
+    /// Currently running.
+    Running,
+    /// Finished successfully.
+    Succeeded,
+    /// Finished with an error.
+    Failed,
+    /// Cancelled using `DELETE /jobs/$ID`.
+    Cancelled,
+}
+
+impl JobState {
+    /// Has this job stopped running?
+    fn is_finished(self) -> bool {
+        match self {
+            JobState::Queued | JobState::Running => false,
+            JobState::Succeeded | JobState::Failed | JobState::Cancelled => true,
+        }
+    }
+}
+
+/// The progress of a job, summarized from its event log.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct JobProgress {
+    /// The most recent progress message, if any.
+    pub message: Option<String>,
+    /// How many streams have we started sending?
+    pub streams: u64,
+    /// How many streams have we finished sending?
+    pub streams_finished: u64,
+    /// How many bytes have we sent, in total?
+    pub bytes: u64,
+    /// How many rows have we sent, in total, in streams which count rows?
+    pub rows: u64,
+    /// How many warnings have we seen?
+    pub warnings: u64,
+}
+
+impl JobProgress {
+    /// Summarize an event log containing one JSON event per line. We ignore
+    /// lines we can't parse, because the log may still be being written.
+    pub fn from_event_log(events: &str) -> JobProgress {
+        let mut progress = JobProgress::default();
+        let mut stream_bytes = BTreeMap::<String, (u64, u64, bool)>::new();
+        for line in events.lines() {
+            let event = match serde_json::from_str::<Value>(line) {
+                Ok(event) => event,
+                Err(_) => continue,
+            };
+            match event["type"].as_str() {
+                Some("progress") => {
+                    progress.message = event["message"].as_str().map(str::to_owned);
+                }
+                Some("stream_progress") => {
+                    if let Some(stream) = event["stream"].as_str() {
+                        stream_bytes.insert(
+                            stream.to_owned(),
+                            (
+                                event["bytes"].as_u64().unwrap_or(0),
+                                event["rows"].as_u64().unwrap_or(0),
+                                event["finished"].as_bool().unwrap_or(false),
+                            ),
+                        );
+                    }
+                }
+                Some("warning") | Some("lossy_type") => progress.warnings += 1,
+                _ => {}
+            }
+        }
+        progress.streams = stream_bytes.len() as u64;
+        progress.streams_finished = stream_bytes
+            .values()
+            .filter(|(_, _, finished)| *finished)
+            .count() as u64;
+        progress.bytes = stream_bytes.values().map(|(bytes, _, _)| bytes).sum();
+        progress.rows = stream_bytes.values().map(|(_, rows, _)| rows).sum();
+        progress
+    }
+}
+
+/// What we report about a job.
+#[derive(Clone, Debug, Serialize)]
+pub struct JobStatus {
+    /// The ID of this job.
+    pub id: u64,
+    /// The arguments passed to `dbcrossbar cp`, with any passwords hidden.
+    pub args: Vec<String>,
+    /// The current state of this job.
+    pub state: JobState,
+    /// Why this job failed, if it did.
+    pub error: Option<String>,
+    /// When this job was submitted.
+    pub submitted_at: String,
+    /// When this job finished, if it has.
+    pub finished_at: Option<String>,
+    /// The progress of this job.
+    pub progress: JobProgress,
+}
+
+/// A job we're keeping track of.
+struct Job {
+    /// The status of this job. `progress` is only updated when we're asked.
+    status: JobStatus,
+    /// Send a message here to cancel this job.
+    cancel: Option<oneshot::Sender<()>>,
+}
+
+/// The body of `POST /jobs`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubmitJob {
+    /// Arguments to pass to `dbcrossbar cp`.
+    args: Vec<String>,
+}
+
+/// Runs and keeps track of copy jobs.
+pub struct JobManager {
+    /// Used to log what we're doing.
+    ctx: Context,
+    /// The `dbcrossbar` executable to run.
+    exe: PathBuf,
+    /// Global arguments to pass before `cp`, such as `--enable-unstable`.
+    global_args: Vec<String>,
+    /// A directory in which to keep each job's event log and log output.
+    work_dir: PathBuf,
+    /// If set, clients must send `Authorization: Bearer $TOKEN`.
+    token: Option<String>,
+    /// Limits how many jobs can run at once.
+    slots: Semaphore,
+    /// The ID of the next job.
+    next_id: AtomicU64,
+    /// All our jobs, by ID.
+    jobs: Mutex<BTreeMap<u64, Job>>,
+}
+
+impl JobManager {
+    /// Create a new job manager, which runs up to `max_jobs` jobs at once
+    /// using `exe`, and which keeps its files in `work_dir`.
+    pub fn new(
+        ctx: Context,
+        exe: PathBuf,
+        global_args: Vec<String>,
+        work_dir: PathBuf,
+        max_jobs: usize,
+        token: Option<String>,
+    ) -> Arc<JobManager> {
+        Arc::new(JobManager {
+            ctx,
+            exe,
+            global_args,
+            work_dir,
+            token,
+            slots: Semaphore::new(max_jobs),
+            next_id: AtomicU64::new(1),
+            jobs: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// The path to the event log for job `id`.
+    fn event_log_path(&self, id: u64) -> PathBuf {
+        self.work_dir.join(format!("job-{}.events.jsonl", id))
+    }
+
+    /// The path to the log output for job `id`.
+    fn log_path(&self, id: u64) -> PathBuf {
+        self.work_dir.join(format!("job-{}.log", id))
+    }
+
+    /// Submit a new job which runs `dbcrossbar cp` with `args`.
+    fn submit(self: &Arc<Self>, args: Vec<String>) -> JobStatus {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (cancel, cancel_rx) = oneshot::channel();
+        let status = JobStatus {
+            id,
+            args: args.iter().map(|a| hide_password(a)).collect(),
+            state: JobState::Queued,
+            error: None,
+            submitted_at: Utc::now().to_rfc3339(),
+            finished_at: None,
+            progress: JobProgress::default(),
+        };
+        info!(
+            self.ctx.log(),
+            "submitted job {}: cp {}",
+            id,
+            status.args.join(" ")
+        );
+        self.lock_jobs().insert(
+            id,
+            Job {
+                status: status.clone(),
+                cancel: Some(cancel),
+            },
+        );
+        tokio::spawn(self.clone().run_job(id, args, cancel_rx));
+        status
+    }
+
+    /// Run job `id` when there's room, until it finishes or is cancelled.
+    async fn run_job(
+        self: Arc<Self>,
+        id: u64,
+        args: Vec<String>,
+        mut cancel_rx: oneshot::Receiver<()>,
+    ) {
+        // Wait for our turn.
+        let permit =
+            match future::select(self.slots.acquire().boxed(), &mut cancel_rx).await {
+                Either::Left((permit, _)) => permit,
+                Either::Right(_) => {
+                    self.finish(id, JobState::Cancelled, None);
+                    return;
+                }
+            };
+        self.set_state(id, JobState::Running);
+        let result = self.run_process(id, &args, cancel_rx).await;
+        drop(permit);
+        match result {
+            Ok(true) => self.finish(id, JobState::Succeeded, None),
+            Ok(false) => self.finish(id, JobState::Cancelled, None),
+            Err(err) => self.finish(id, JobState::Failed, Some(err.to_string())),
+        }
+    }
+
+    /// Run `dbcrossbar cp` for job `id`. Returns `false` if the job was
+    /// cancelled.
+    async fn run_process(
+        &self,
+        id: u64,
+        args: &[String],
+        cancel_rx: oneshot::Receiver<()>,
+    ) -> Result<bool> {
+        let log =
+            File::create(self.log_path(id)).context("could not create job log")?;
+        let child = Command::new(&self.exe)
+            .args(&self.global_args)
+            .arg("--event-log")
+            .arg(self.event_log_path(id))
+            .arg("cp")
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::from(log.try_clone()?))
+            .stderr(Stdio::from(log))
+            // If we're cancelled, we'll drop `child`, which kills it.
+            .kill_on_drop(true)
+            .spawn()
+            .context("could not start `dbcrossbar cp`")?;
+        match future::select(child.boxed(), cancel_rx).await {
+            Either::Left((status, _)) => {
+                let status = status.context("error waiting for `dbcrossbar cp`")?;
+                if status.success() {
+                    Ok(true)
+                } else {
+                    Err(format_err!("`dbcrossbar cp` failed with {}", status))
+                }
+            }
+            Either::Right(_) => Ok(false),
+        }
+    }
+
+    /// Lock our job table.
+    fn lock_jobs(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, Job>> {
+        self.jobs.lock().expect("lock poisoned, giving up")
+    }
+
+    /// Update the state of job `id`.
+    fn set_state(&self, id: u64, state: JobState) {
+        if let Some(job) = self.lock_jobs().get_mut(&id) {
+            job.status.state = state;
+        }
+    }
+
+    /// Record that job `id` has finished.
+    fn finish(&self, id: u64, state: JobState, error: Option<String>) {
+        match &error {
+            Some(error) => error!(self.ctx.log(), "job {} failed: {}", id, error),
+            None => info!(self.ctx.log(), "job {} finished: {:?}", id, state),
+        }
+        let mut jobs = self.lock_jobs();
+        if let Some(job) = jobs.get_mut(&id) {
+            job.status.state = state;
+            job.status.error = error;
+            job.status.finished_at = Some(Utc::now().to_rfc3339());
+            job.cancel = None;
+        }
+
+        // Forget our oldest finished jobs, if we have too many.
+        for old_id in finished_jobs_to_forget(&jobs, MAX_FINISHED_JOBS) {
+            debug!(self.ctx.log(), "forgetting job {}", old_id);
+            jobs.remove(&old_id);
+            for path in &[self.event_log_path(old_id), self.log_path(old_id)] {
+                if let Err(err) = fs::remove_file(path) {
+                    debug!(
+                        self.ctx.log(),
+                        "could not remove {}: {}",
+                        path.display(),
+                        err,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Get the status of job `id`, including its latest progress.
+    fn status(&self, id: u64) -> Option<JobStatus> {
+        let mut status = self.lock_jobs().get(&id)?.status.clone();
+        status.progress = read_progress(&self.event_log_path(id));
+        Some(status)
+    }
+
+    /// Get the status of every job.
+    fn statuses(&self) -> Vec<JobStatus> {
+        let ids = self.lock_jobs().keys().copied().collect::<Vec<_>>();
+        ids.into_iter().filter_map(|id| self.status(id)).collect()
+    }
+
+    /// Cancel job `id`. Returns `None` if there's no such job, and `false` if
+    /// it has already finished.
+    fn cancel(&self, id: u64) -> Option<bool> {
+        let mut jobs = self.lock_jobs();
+        let job = jobs.get_mut(&id)?;
+        if job.status.state.is_finished() {
+            return Some(false);
+        }
+        info!(self.ctx.log(), "cancelling job {}", id);
+        if let Some(cancel) = job.cancel.take() {
+            // If the job just finished, there's nothing left to cancel.
+            let _ = cancel.send(());
+        }
+        Some(true)
+    }
+
+    /// Does `req` have the right credentials?
+    fn is_authorized(&self, req: &Request<Body>) -> bool {
+        match &self.token {
+            None => true,
+            Some(token) => req
+                .headers()
+                .get(header::AUTHORIZATION)
+                .is_some_and(|value| is_bearer_token(value.as_bytes(), token)),
+        }
+    }
+}
+
+/// Is `authorization` equal to `Bearer $TOKEN`? We compare in constant time,
+/// so that clients can't guess the token one byte at a time by timing our
+/// responses.
+fn is_bearer_token(authorization: &[u8], token: &str) -> bool {
+    let expected = format!("Bearer {}", token);
+    ring::constant_time::verify_slices_are_equal(authorization, expected.as_bytes())
+        .is_ok()
+}
+
+/// Return the IDs of the oldest finished jobs in `jobs`, if more than
+/// `max_finished` jobs have finished. Job IDs increase over time, so we keep
+/// the finished jobs with the highest IDs.
+fn finished_jobs_to_forget(
+    jobs: &BTreeMap<u64, Job>,
+    max_finished: usize,
+) -> Vec<u64> {
+    let finished = jobs
+        .iter()
+        .filter(|(_, job)| job.status.state.is_finished())
+        .map(|(&id, _)| id)
+        .collect::<Vec<_>>();
+    let excess = finished.len().saturating_sub(max_finished);
+    finished[..excess].to_vec()
+}
+
+/// Read the progress of a job from its event log. Jobs which haven't started
+/// have no log, and no progress.
+fn read_progress(path: &Path) -> JobProgress {
+    fs::read_to_string(path)
+        .map(|events| JobProgress::from_event_log(&events))
+        .unwrap_or_default()
+}
+
+/// Start serving our API on `addr`. Returns the address we're listening on
+/// (which is useful if `addr` has port 0), and a future which runs the
+/// server.
+pub fn serve(
+    addr: SocketAddr,
+    jobs: Arc<JobManager>,
+) -> Result<(SocketAddr, BoxFuture<()>)> {
+    let make_service = make_service_fn(move |_conn| {
+        let jobs = jobs.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req| {
+                let jobs = jobs.clone();
+                async move { Ok::<_, hyper::Error>(handle_request(&jobs, req).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)
+        .with_context(|_| format!("could not listen on {}", addr))?
+        .serve(make_service);
+    let local_addr = server.local_addr();
+    Ok((local_addr, server.map_err(Error::from).boxed()))
+}
+
+/// Handle a single HTTP request.
+async fn handle_request(jobs: &Arc<JobManager>, req: Request<Body>) -> Response<Body> {
+    debug!(jobs.ctx.log(), "{} {}", req.method(), req.uri().path());
+    let path = req
+        .uri()
+        .path()
+        .trim_matches('/')
+        .split('/')
+        .map(|s| s.to_owned())
+        .collect::<Vec<_>>();
+    let path = path.iter().map(|s| &s[..]).collect::<Vec<_>>();
+    if path != ["health"] && !jobs.is_authorized(&req) {
+        return error_response(StatusCode::UNAUTHORIZED, "missing or invalid token");
+    }
+    let parse_id = |id: &str| id.parse::<u64>().ok();
+    let method = req.method().clone();
+    match (&method, &path[..]) {
+        (&Method::GET, ["health"]) => {
+            json_response(StatusCode::OK, json!({ "status": "ok" }))
+        }
+        (&Method::GET, ["jobs"]) => json_response(StatusCode::OK, jobs.statuses()),
+        (&Method::POST, ["jobs"]) => match read_submit_job(req).await {
+            Ok(submit) => json_response(StatusCode::CREATED, jobs.submit(submit.args)),
+            Err(err) => error_response(StatusCode::BAD_REQUEST, &err.to_string()),
+        },
+        (&Method::GET, ["jobs", id]) => {
+            match parse_id(id).and_then(|id| jobs.status(id)) {
+                Some(status) => json_response(StatusCode::OK, &status),
+                None => error_response(StatusCode::NOT_FOUND, "no such job"),
+            }
+        }
+        (&Method::GET, ["jobs", id, "log"]) => {
+            match parse_id(id).filter(|&id| jobs.lock_jobs().contains_key(&id)) {
+                Some(id) => {
+                    let log =
+                        fs::read_to_string(jobs.log_path(id)).unwrap_or_default();
+                    Response::builder()
+                        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                        .body(Body::from(log))
+                        .expect("could not build response")
+                }
+                None => error_response(StatusCode::NOT_FOUND, "no such job"),
+            }
+        }
+        (&Method::DELETE, ["jobs", id]) => match parse_id(id)
+            .and_then(|id| jobs.cancel(id))
+        {
+            Some(true) => {
+                json_response(StatusCode::ACCEPTED, json!({ "status": "cancelling" }))
+            }
+            Some(false) => {
+                error_response(StatusCode::CONFLICT, "job has already finished")
+            }
+            None => error_response(StatusCode::NOT_FOUND, "no such job"),
+        },
+        _ => error_response(StatusCode::NOT_FOUND, "no such endpoint"),
+    }
+}
+
+/// Parse the body of `POST /jobs`.
+async fn read_submit_job(req: Request<Body>) -> Result<SubmitJob> {
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .context("could not read request body")?;
+    if body.len() > MAX_BODY_SIZE {
+        return Err(format_err!("request body is too large"));
+    }
+    let submit = serde_json::from_slice::<SubmitJob>(&body)
+        .context("expected a body like {\"args\": [\"FROM\", \"TO\"]}")?;
+    if submit.args.is_empty() {
+        return Err(format_err!(
+            "\"args\" must include a source and destination"
+        ));
+    }
+    Ok(submit)
+}
+
+/// Build a JSON response.
+fn json_response<T: Serialize>(status: StatusCode, value: T) -> Response<Body> {
+    let body = serde_json::to_vec(&value).expect("could not serialize response");
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .expect("could not build response")
+}
+
+/// Build a JSON error response.
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, json!({ "error": message }))
+}
+
+#[test]
+fn summarizes_event_logs() {
+    let events = r#"{"type":"log","level":"INFO","message":"hello"}
+{"type":"stream_progress","stream":"a","bytes":10,"rows":1,"finished":false}
+{"type":"stream_progress","stream":"b","bytes":5,"rows":null,"finished":true}
+{"type":"stream_progress","stream":"a","bytes":20,"rows":2,"finished":true}
+{"type":"warning","message":"bad data"}
+{"type":"progress","message":"wrote a","completed":1,"total":2}
+{"type":"stream_progr"#;
+    assert_eq!(
+        JobProgress::from_event_log(events),
+        JobProgress {
+            message: Some("wrote a".to_owned()),
+            streams: 2,
+            streams_finished: 2,
+            bytes: 25,
+            rows: 2,
+            warnings: 1,
+        },
+    );
+}
+
+#[test]
+fn compares_bearer_tokens() {
+    assert!(is_bearer_token(b"Bearer secret", "secret"));
+    assert!(!is_bearer_token(b"Bearer secreT", "secret"));
+    assert!(!is_bearer_token(b"Bearer secret2", "secret"));
+    assert!(!is_bearer_token(b"secret", "secret"));
+    assert!(!is_bearer_token(b"", "secret"));
+}
+
+#[test]
+fn forgets_oldest_finished_jobs() {
+    let job = |id, state| {
+        let status = JobStatus {
+            id,
+            args: vec![],
+            state,
+            error: None,
+            submitted_at: Utc::now().to_rfc3339(),
+            finished_at: None,
+            progress: JobProgress::default(),
+        };
+        (
+            id,
+            Job {
+                status,
+                cancel: None,
+            },
+        )
+    };
+    let jobs = vec![
+        job(1, JobState::Succeeded),
+        job(2, JobState::Running),
+        job(3, JobState::Failed),
+        job(4, JobState::Cancelled),
+        job(5, JobState::Queued),
+        job(6, JobState::Succeeded),
+    ]
+    .into_iter()
+    .collect::<BTreeMap<_, _>>();
+    assert_eq!(finished_jobs_to_forget(&jobs, 2), vec![1, 3]);
+    assert_eq!(finished_jobs_to_forget(&jobs, 4), Vec::<u64>::new());
+}
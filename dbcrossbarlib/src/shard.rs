@@ -0,0 +1,182 @@
+//! Splitting a large copy between several processes using `--shard`.
+
+use crc32c::crc32c;
+use std::{fmt, str::FromStr};
+
+use crate::common::*;
+use crate::locator::UnparsedLocator;
+
+/// The placeholder in a destination which is replaced by the shard index.
+const SHARD_PLACEHOLDER: &str = "{shard}";
+
+/// One of several processes sharing a copy, parsed from `INDEX/COUNT`, where
+/// `INDEX` starts at 0. Each process handles the streams or tables whose
+/// names hash to its index, so the processes don't need to talk to each
+/// other.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Shard {
+    /// Which shard this is, from `0` to `count - 1`.
+    index: u32,
+    /// How many shards there are in total.
+    count: u32,
+}
+
+impl Shard {
+    /// Which shard this is, starting at 0.
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    /// How many shards there are in total.
+    pub fn count(self) -> u32 {
+        self.count
+    }
+
+    /// Should this shard handle the stream or table named `name`? Every name
+    /// belongs to exactly one shard, and it's always the same one, whichever
+    /// machine we run on.
+    pub fn includes(self, name: &str) -> bool {
+        crc32c(name.as_bytes()) % self.count == self.index
+    }
+
+    /// Replace any `{shard}` in `dest` with our index. Unless `dest` contains
+    /// `{shard}`, every shard writes to the same place, so we refuse to
+    /// overwrite it.
+    pub fn expand_destination(
+        self,
+        dest: &UnparsedLocator,
+        if_exists: &IfExists,
+    ) -> Result<UnparsedLocator> {
+        let dest = dest.as_str();
+        if !dest.contains(SHARD_PLACEHOLDER)
+            && self.count > 1
+            && *if_exists == IfExists::Overwrite
+        {
+            return Err(format_err!(
+                "cannot use --if-exists=overwrite with --shard, because each shard would overwrite the others (use --if-exists=append, or include {} in {})",
+                SHARD_PLACEHOLDER,
+                dest,
+            ));
+        }
+        dest.replace(SHARD_PLACEHOLDER, &self.index.to_string())
+            .parse()
+    }
+
+    /// Given a stream of CSV streams, keep only the ones which belong to this
+    /// shard.
+    pub fn filter_streams(
+        self,
+        ctx: Context,
+        streams: BoxStream<CsvStream>,
+    ) -> BoxStream<CsvStream> {
+        streams
+            .try_filter(move |stream| {
+                let included = self.includes(&stream.name);
+                if !included {
+                    debug!(
+                        ctx.log(),
+                        "skipping stream {} (belongs to another shard)", stream.name,
+                    );
+                }
+                futures::future::ready(included)
+            })
+            .boxed()
+    }
+}
+
+impl FromStr for Shard {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = || -> Option<Shard> {
+            let mut parts = s.splitn(2, '/');
+            let index = parts.next()?.trim().parse::<u32>().ok()?;
+            let count = parts.next()?.trim().parse::<u32>().ok()?;
+            Some(Shard { index, count })
+        };
+        let shard = parse().ok_or_else(|| {
+            format_err!("expected a shard like \"3/8\", found {:?}", s)
+        })?;
+        if shard.count == 0 || shard.index >= shard.count {
+            return Err(format_err!(
+                "shard index must be from 0 to {}, found {:?}",
+                shard.count.saturating_sub(1),
+                s,
+            ));
+        }
+        Ok(shard)
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[test]
+fn parses_shards() {
+    let shard = "3/8".parse::<Shard>().unwrap();
+    assert_eq!((shard.index(), shard.count()), (3, 8));
+    assert_eq!(shard.to_string(), "3/8");
+    assert_eq!("0/1".parse::<Shard>().unwrap().count(), 1);
+    for bad in &["", "3", "8/8", "0/0", "-1/8", "a/8", "1/2/3"] {
+        assert!(bad.parse::<Shard>().is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn expands_destinations() {
+    let shard = "3/8".parse::<Shard>().unwrap();
+    let dest = "gs://bucket/out/{shard}/"
+        .parse::<UnparsedLocator>()
+        .unwrap();
+    assert_eq!(
+        shard
+            .expand_destination(&dest, &IfExists::Overwrite)
+            .unwrap()
+            .as_str(),
+        "gs://bucket/out/3/",
+    );
+    let dest = "postgres://localhost:5432/db#t"
+        .parse::<UnparsedLocator>()
+        .unwrap();
+    assert!(shard
+        .expand_destination(&dest, &IfExists::Overwrite)
+        .is_err());
+    assert_eq!(
+        shard
+            .expand_destination(&dest, &IfExists::Append)
+            .unwrap()
+            .as_str(),
+        dest.as_str(),
+    );
+
+    // A single shard can overwrite as usual.
+    let shard = "0/1".parse::<Shard>().unwrap();
+    assert!(shard
+        .expand_destination(&dest, &IfExists::Overwrite)
+        .is_ok());
+}
+
+#[test]
+fn each_name_belongs_to_one_shard() {
+    let shards = (0..4)
+        .map(|i| format!("{}/4", i).parse::<Shard>().unwrap())
+        .collect::<Vec<_>>();
+    let mut counts = vec![0; shards.len()];
+    for n in 0..1000 {
+        let name = format!("part-{:05}.csv", n);
+        let owners = shards
+            .iter()
+            .filter(|shard| shard.includes(&name))
+            .map(|shard| shard.index() as usize)
+            .collect::<Vec<_>>();
+        assert_eq!(owners.len(), 1, "{}", name);
+        counts[owners[0]] += 1;
+    }
+    // Names should be spread fairly evenly.
+    for count in counts {
+        assert!(count > 200, "{}", count);
+    }
+}
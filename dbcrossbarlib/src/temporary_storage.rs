@@ -2,16 +2,90 @@
 
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use std::iter;
+use std::{fmt, iter, path::PathBuf, str::FromStr, sync::Arc};
 
+use crate::clouds::gcloud::recording::Recording;
 use crate::common::*;
 use crate::config::Configuration;
+use crate::copy_path::NoCopyPathError;
+
+/// Chooses between several temporary locations with the same scheme.
+///
+/// Library users can implement this to pick locations using their own rules.
+pub trait TemporaryStoragePolicy: fmt::Debug + Send + Sync + 'static {
+    /// Choose one of `candidates`, which all have the scheme `scheme`, and
+    /// which are never empty. Locations passed on the command line come before
+    /// those from our configuration file.
+    fn choose<'a>(&self, scheme: &str, candidates: &[&'a str]) -> Option<&'a str>;
+}
+
+/// Our built-in temporary storage policies, as specified by
+/// `--temporary-policy`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum TemporarySelection {
+    /// Use the first matching location.
+    #[default]
+    First,
+    /// Prefer locations whose names contain this region, like
+    /// `gs://example-temp-us-east1/`, and fall back to the first location.
+    Region(String),
+}
+
+impl TemporaryStoragePolicy for TemporarySelection {
+    fn choose<'a>(&self, _scheme: &str, candidates: &[&'a str]) -> Option<&'a str> {
+        match self {
+            TemporarySelection::First => candidates.first().copied(),
+            TemporarySelection::Region(region) => candidates
+                .iter()
+                .find(|c| contains_region(c, region))
+                .or_else(|| candidates.first())
+                .copied(),
+        }
+    }
+}
+
+impl FromStr for TemporarySelection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "first" {
+            Ok(TemporarySelection::First)
+        } else if let Some(region) = s.strip_prefix("region:") {
+            if region.is_empty() {
+                return Err(format_err!("missing region in {:?}", s));
+            }
+            Ok(TemporarySelection::Region(region.to_owned()))
+        } else {
+            Err(format_err!(
+                "unknown temporary policy {:?} (expected \"first\" or \"region:$REGION\")",
+                s,
+            ))
+        }
+    }
+}
+
+/// Does `location` contain `region`, surrounded by non-alphanumeric characters
+/// or the ends of the string?
+fn contains_region(location: &str, region: &str) -> bool {
+    let location = location.to_ascii_lowercase();
+    let region = region.to_ascii_lowercase();
+    location.match_indices(&region).any(|(idx, _)| {
+        let before = location[..idx].chars().next_back();
+        let after = location[idx + region.len()..].chars().next();
+        !before.is_some_and(|c| c.is_ascii_alphanumeric())
+            && !after.is_some_and(|c| c.is_ascii_alphanumeric())
+    })
+}
 
 /// Provides different types of temporary storage.
 #[derive(Clone, Debug)]
 pub struct TemporaryStorage {
     /// Various places we can store things temporarily.
     locations: Vec<String>,
+    /// How to choose between locations with the same scheme.
+    policy: Arc<dyn TemporaryStoragePolicy>,
+    /// Should we refuse to stage data anywhere, as requested by `--no-temp`?
+    forbidden: bool,
 }
 
 impl TemporaryStorage {
@@ -19,7 +93,39 @@ impl TemporaryStorage {
     /// of locator-like strings, such as `gs://bucket/tempdir` or
     /// `bigquery:project:dataset`.
     pub fn new(locations: Vec<String>) -> Self {
-        TemporaryStorage { locations }
+        TemporaryStorage {
+            locations,
+            policy: Arc::new(TemporarySelection::First),
+            forbidden: false,
+        }
+    }
+
+    /// Create a `TemporaryStorage` object which provides no temporary storage,
+    /// and which makes drivers fail instead of staging data elsewhere.
+    pub fn forbidden() -> Self {
+        TemporaryStorage {
+            locations: vec![],
+            policy: Arc::new(TemporarySelection::First),
+            forbidden: true,
+        }
+    }
+
+    /// Have we been told not to stage data anywhere?
+    pub fn is_forbidden(&self) -> bool {
+        self.forbidden
+    }
+
+    /// Fail if we've been told not to stage data, because we would need to
+    /// stage it in a location with `scheme`.
+    pub(crate) fn check_allowed(&self, scheme: &str) -> Result<()> {
+        if self.forbidden {
+            Err(NoCopyPathError::TemporaryForbidden {
+                scheme: scheme.to_owned(),
+            }
+            .into())
+        } else {
+            Ok(())
+        }
     }
 
     /// Like `new`, but also use temporaries from `config`.
@@ -29,20 +135,51 @@ impl TemporaryStorage {
     ) -> Result<Self> {
         // These go _after_, so that they can be overridden by values in `locations`.
         locations.extend(config.temporaries()?);
-        Ok(TemporaryStorage { locations })
+        Ok(TemporaryStorage::new(locations))
+    }
+
+    /// Use `policy` to choose between locations with the same scheme.
+    pub fn with_policy(mut self, policy: Arc<dyn TemporaryStoragePolicy>) -> Self {
+        self.policy = policy;
+        self
     }
 
     /// Find a location with the specified scheme.
-    pub fn find_scheme<'a, 'b>(&'a self, scheme: &'b str) -> Option<&'a str> {
+    pub fn find_scheme<'a>(&'a self, scheme: &str) -> Option<&'a str> {
         assert!(scheme.ends_with(':'));
-        self.locations
+        let candidates = self
+            .locations
             .iter()
-            .find(|l| l.starts_with(scheme))
+            .filter(|l| l.starts_with(scheme))
             .map(|l| l.as_str())
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            None
+        } else {
+            self.policy.choose(scheme, &candidates)
+        }
+    }
+
+    /// Find a unique local temporary directory, including a random component,
+    /// if we were given `--temporary=file:$DIR`. Drivers which normally stage
+    /// data in the cloud may use this to spool small copies through local disk
+    /// instead.
+    pub(crate) fn find_file_temp_dir(&self) -> Option<PathBuf> {
+        let temp = self.find_scheme("file:")?;
+        // Accept both `file:/tmp/dir` and `file:///tmp/dir`.
+        let path = &temp["file:".len()..];
+        let path = path.strip_prefix("//").unwrap_or(path);
+        Some(PathBuf::from(path).join(Self::random_tag()))
     }
 
     /// Generate a random alphanumeric tag for use in temporary directory names.
+    ///
+    /// When recording or replaying Google Cloud requests, this returns a
+    /// predictable tag instead, so that recorded URLs match.
     pub fn random_tag() -> String {
+        if let Some(tag) = Recording::deterministic_tag() {
+            return tag;
+        }
         let mut rng = thread_rng();
         iter::repeat(())
             .map(|()| rng.sample(Alphanumeric))
@@ -62,6 +199,60 @@ fn find_schema() {
     assert_eq!(storage.find_scheme("gs:"), Some("gs://example/1/"));
 }
 
+#[test]
+fn find_scheme_with_region_policy() {
+    let storage = TemporaryStorage::new(vec![
+        "gs://example-temp-us/".to_string(),
+        "gs://example-temp-us-east1/".to_string(),
+        "bigquery:example:temp_us".to_string(),
+    ]);
+    let policy = "region:US-EAST1".parse::<TemporarySelection>().unwrap();
+    let storage = storage.with_policy(Arc::new(policy));
+    assert_eq!(
+        storage.find_scheme("gs:"),
+        Some("gs://example-temp-us-east1/")
+    );
+    assert_eq!(
+        storage.find_scheme("bigquery:"),
+        Some("bigquery:example:temp_us")
+    );
+    assert_eq!(storage.find_scheme("s3:"), None);
+
+    let policy = "region:us".parse::<TemporarySelection>().unwrap();
+    assert_eq!(
+        policy.choose("gs:", &["gs://a-usa/", "gs://b-us/"]),
+        Some("gs://b-us/"),
+    );
+    assert!("region:".parse::<TemporarySelection>().is_err());
+    assert!("nearest".parse::<TemporarySelection>().is_err());
+}
+
+#[test]
+fn find_file_temp_dir() {
+    for temp in &["file:/tmp/dbcrossbar", "file:///tmp/dbcrossbar/"] {
+        let storage = TemporaryStorage::new(vec![temp.to_string()]);
+        let dir = storage.find_file_temp_dir().unwrap();
+        assert!(dir.starts_with("/tmp/dbcrossbar"));
+        assert_eq!(dir.components().count(), 4);
+    }
+    assert!(TemporaryStorage::new(vec![]).find_file_temp_dir().is_none());
+}
+
+#[test]
+fn forbidden_storage_refuses_to_stage_data() {
+    let storage = TemporaryStorage::forbidden();
+    assert!(storage.is_forbidden());
+    assert_eq!(storage.find_scheme("gs:"), None);
+    let err = storage.check_allowed("gs:").unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NoCopyPathError>(),
+        Some(&NoCopyPathError::TemporaryForbidden {
+            scheme: "gs:".to_owned()
+        }),
+    );
+    TemporaryStorage::new(vec![]).check_allowed("gs:").unwrap();
+}
+
 #[test]
 fn random_tag() {
     assert_eq!(TemporaryStorage::random_tag().len(), 10);
@@ -108,6 +108,7 @@ where
 {
     trace!(ctx.log(), "forwarding stream to sender");
     while let Some(result) = stream.next().await {
+        ctx.check_cancelled()?;
         match result {
             Ok(bytes) => sender.send(Ok(bytes)).await.map_send_err()?,
             Err(err) => {
@@ -136,6 +137,7 @@ where
 {
     trace!(ctx.log(), "begin copy_stream_to_writer");
     while let Some(result) = stream.next().await {
+        ctx.check_cancelled()?;
         match result {
             Err(err) => {
                 error!(ctx.log(), "error reading stream: {}", err);
@@ -169,6 +171,8 @@ where
     let worker: BoxFuture<()> = async move {
         let mut buffer = vec![0u8; 64 * 1024];
         loop {
+            ctx.check_cancelled()?;
+
             // Read the data. This consumes `rdr`, so we'll have to put it back
             // below.
             trace!(ctx.log(), "reading bytes from reader");
@@ -1,6 +1,6 @@
 //! Support for URLs with passwords that can be printed and debugged safely.
 
-use std::{borrow::Cow, fmt};
+use std::{borrow::Cow, fmt, str::FromStr};
 
 use crate::common::*;
 
@@ -50,3 +50,11 @@ impl fmt::Display for UrlWithHiddenPassword {
         fmt::Display::fmt(&self.without_password(), f)
     }
 }
+
+impl FromStr for UrlWithHiddenPassword {
+    type Err = url::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(UrlWithHiddenPassword(s.parse::<Url>()?))
+    }
+}
@@ -0,0 +1,321 @@
+//! Check that the data in a stream of CSV streams matches our schema.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+use geo_types::Geometry;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{fmt, str::FromStr};
+use uuid::Uuid;
+
+use crate::common::*;
+use crate::from_csv_cell::FromCsvCell;
+use crate::schema::{Column, DataType};
+use crate::transform::spawn_sync_transform;
+
+/// What should we do when we find a value that doesn't match the declared
+/// type of its column?
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ValidationMode {
+    /// Log a warning summarizing each column with bad data, and keep going.
+    Warn,
+    /// Fail as soon as we see the first bad value.
+    Error,
+}
+
+impl FromStr for ValidationMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "warn" => Ok(ValidationMode::Warn),
+            "error" => Ok(ValidationMode::Error),
+            _ => Err(format_err!("unknown validation mode: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ValidationMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationMode::Warn => write!(f, "warn"),
+            ValidationMode::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Given a stream of CSV streams, check the values in each stream against the
+/// column types in `schema`. The data itself is passed through unchanged.
+///
+/// Only one out of every `sample_every` rows will be checked, which allows
+/// trading thoroughness for speed on very large inputs.
+pub fn validate_csvs(
+    ctx: Context,
+    schema: Table,
+    mode: ValidationMode,
+    sample_every: usize,
+    streams: BoxStream<CsvStream>,
+) -> Result<BoxStream<CsvStream>> {
+    if sample_every == 0 {
+        return Err(format_err!("cannot validate every 0th row"));
+    }
+    let ctx = ctx.child(o!("streams_transform" => "validate_csvs"));
+    let validated = streams.and_then(move |stream| {
+        let ctx = ctx.clone();
+        let columns = schema.columns.clone();
+        async move {
+            let name = stream.name.clone();
+            let data = spawn_sync_transform(
+                ctx,
+                format!("validate {}", name),
+                stream.data,
+                move |ctx, rdr, wtr| {
+                    validate_csv(&ctx, &name, &columns, mode, sample_every, rdr, wtr)
+                },
+            )?;
+            Ok(CsvStream {
+                name: stream.name,
+                data,
+            })
+        }
+    });
+    Ok(validated.boxed())
+}
+
+/// Problems we found in a single column.
+#[derive(Debug, Default)]
+struct ColumnProblems {
+    /// How many bad values did we find?
+    count: usize,
+    /// A description of the first bad value we found.
+    first_error: Option<String>,
+}
+
+/// Copy CSV data from `rdr` to `wtr`, checking every `sample_every`th row
+/// against `columns`.
+fn validate_csv(
+    ctx: &Context,
+    stream_name: &str,
+    columns: &[Column],
+    mode: ValidationMode,
+    sample_every: usize,
+    rdr: impl Read,
+    wtr: impl Write,
+) -> Result<()> {
+    let mut rdr = csv::Reader::from_reader(rdr);
+    let mut wtr = csv::Writer::from_writer(wtr);
+    let hdr = rdr
+        .byte_headers()
+        .with_context(|_| format!("cannot read headers of {}", stream_name))?
+        .to_owned();
+    if hdr.len() != columns.len() {
+        return Err(format_err!(
+            "{} has {} columns, but the schema has {}",
+            stream_name,
+            hdr.len(),
+            columns.len(),
+        ));
+    }
+    wtr.write_byte_record(&hdr)?;
+
+    let mut problems = columns
+        .iter()
+        .map(|_| ColumnProblems::default())
+        .collect::<Vec<_>>();
+    let mut checked_rows: usize = 0;
+    let mut record = csv::StringRecord::new();
+    let mut row_idx: usize = 0;
+    let mut rows_until_check: usize = 0;
+    while rdr
+        .read_record(&mut record)
+        .with_context(|_| format!("cannot read row from {}", stream_name))?
+    {
+        if rows_until_check == 0 {
+            rows_until_check = sample_every;
+            checked_rows += 1;
+            for ((col, cell), col_problems) in
+                columns.iter().zip(record.iter()).zip(problems.iter_mut())
+            {
+                if let Err(err) = check_cell(col, cell) {
+                    // Data rows start on line 2, after the header.
+                    let line = row_idx + 2;
+                    if mode == ValidationMode::Error {
+                        return Err(format_err!(
+                            "{} line {}: column {:?}: {}",
+                            stream_name,
+                            line,
+                            col.name,
+                            err,
+                        ));
+                    }
+                    col_problems.count += 1;
+                    if col_problems.first_error.is_none() {
+                        col_problems.first_error =
+                            Some(format!("line {}: {}", line, err));
+                    }
+                }
+            }
+        }
+        wtr.write_record(&record)?;
+        rows_until_check -= 1;
+        row_idx += 1;
+    }
+    wtr.flush()?;
+
+    debug!(
+        ctx.log(),
+        "validated {} of {} rows in {}", checked_rows, row_idx, stream_name,
+    );
+    for (col, col_problems) in columns.iter().zip(problems.iter()) {
+        if let Some(first_error) = &col_problems.first_error {
+            ctx.warning(format!(
+                "{}: column {:?} ({:?}) has {} bad values of {} checked (first at {})",
+                stream_name,
+                col.name,
+                col.data_type,
+                col_problems.count,
+                checked_rows,
+                first_error,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check whether `cell` is a valid value for `column`.
+fn check_cell(column: &Column, cell: &str) -> Result<()> {
+    if cell.is_empty() {
+        // An empty cell is `NULL`, except in text columns, where it might
+        // also be an empty string.
+        if column.is_nullable || column.data_type == DataType::Text {
+            Ok(())
+        } else {
+            Err(format_err!("unexpected NULL in non-nullable column"))
+        }
+    } else {
+        check_value(&column.data_type, cell)
+    }
+}
+
+/// Check whether `cell` can be parsed as a value of type `data_type`.
+pub(crate) fn check_value(data_type: &DataType, cell: &str) -> Result<()> {
+    lazy_static! {
+        static ref DECIMAL_RE: Regex =
+            Regex::new(r"^[-+]?(?:[0-9]+(?:\.[0-9]*)?|\.[0-9]+)(?:[eE][-+]?[0-9]+)?$")
+                .expect("invalid `DECIMAL_RE` in source");
+    }
+
+    match data_type {
+        // These are serialized as JSON, so just make sure we can parse them.
+        DataType::Array(_) | DataType::Json | DataType::Struct(_) => {
+            serde_json::Value::from_csv_cell(cell).map(|_| ())
+        }
+        DataType::Bool => bool::from_csv_cell(cell).map(|_| ()),
+        DataType::Date => NaiveDate::from_csv_cell(cell).map(|_| ()),
+        DataType::Decimal if DECIMAL_RE.is_match(cell) => Ok(()),
+        DataType::Decimal => Err(format_err!("cannot parse {:?} as decimal", cell)),
+        DataType::Float32 => f32::from_csv_cell(cell).map(|_| ()),
+        DataType::Float64 => f64::from_csv_cell(cell).map(|_| ()),
+        DataType::GeoJson(_) => Geometry::<f64>::from_csv_cell(cell).map(|_| ()),
+        DataType::Int16 => i16::from_csv_cell(cell).map(|_| ()),
+        DataType::Int32 => i32::from_csv_cell(cell).map(|_| ()),
+        DataType::Int64 => i64::from_csv_cell(cell).map(|_| ()),
+        DataType::Text => Ok(()),
+        DataType::TimestampWithoutTimeZone => {
+            NaiveDateTime::from_csv_cell(cell).map(|_| ())
+        }
+        DataType::TimestampWithTimeZone => {
+            DateTime::<FixedOffset>::from_csv_cell(cell).map(|_| ())
+        }
+        DataType::Uuid => Uuid::from_csv_cell(cell).map(|_| ()),
+    }
+}
+
+#[test]
+fn check_values() {
+    let valid = &[
+        (DataType::Bool, "t"),
+        (DataType::Date, "1969-07-20"),
+        (DataType::Decimal, "-1.5e10"),
+        (DataType::Decimal, ".5"),
+        (DataType::Int16, "32767"),
+        (DataType::Json, r#"{"a": 1}"#),
+        (DataType::TimestampWithoutTimeZone, "1969-07-20 20:17:39"),
+        (DataType::TimestampWithTimeZone, "1969-07-20 20:17:39+00"),
+        (DataType::Uuid, "084ec3bb-3193-4ffb-8b74-99a288e8432c"),
+    ];
+    for (data_type, cell) in valid {
+        assert!(check_value(data_type, cell).is_ok(), "{:?}", cell);
+    }
+
+    let invalid = &[
+        (DataType::Bool, "maybe"),
+        (DataType::Date, "07/20/1969"),
+        (DataType::Decimal, "1.2.3"),
+        (DataType::Int16, "32768"),
+        (DataType::Int64, "1.5"),
+        (DataType::Json, "{"),
+        (DataType::TimestampWithTimeZone, "1969-07-20 20:17:39"),
+    ];
+    for (data_type, cell) in invalid {
+        assert!(check_value(data_type, cell).is_err(), "{:?}", cell);
+    }
+}
+
+#[test]
+fn validate_csv_passes_data_through() {
+    let (ctx, _worker_fut) =
+        Context::create_for_test("validate_csv_passes_data_through");
+    let columns = vec![
+        Column {
+            name: "id".to_owned(),
+            is_nullable: false,
+            data_type: DataType::Int32,
+            comment: None,
+        },
+        Column {
+            name: "when".to_owned(),
+            is_nullable: true,
+            data_type: DataType::Date,
+            comment: None,
+        },
+    ];
+    let input = "id,when\n1,1969-07-20\nx,\n3,bad\n";
+
+    let mut output = vec![];
+    validate_csv(
+        &ctx,
+        "test",
+        &columns,
+        ValidationMode::Warn,
+        1,
+        input.as_bytes(),
+        &mut output,
+    )
+    .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), input);
+
+    let err = validate_csv(
+        &ctx,
+        "test",
+        &columns,
+        ValidationMode::Error,
+        1,
+        input.as_bytes(),
+        vec![],
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("line 3"), "{}", err);
+
+    // Only check rows 1 and 3, which skips the bad `id`.
+    let err = validate_csv(
+        &ctx,
+        "test",
+        &columns,
+        ValidationMode::Error,
+        2,
+        input.as_bytes(),
+        vec![],
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("line 4"), "{}", err);
+}
@@ -0,0 +1,328 @@
+//! Checking that a copy wrote the same rows that it read.
+//!
+//! We "tally" the rows on each side of a copy. A tally always includes the
+//! number of rows, and may also include a checksum of their values. Values are
+//! compared after parsing them according to the schema (just like
+//! [`crate::round_trip`]), so `t` and `true` are considered equal, and rows may
+//! be stored in any order.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use crate::common::*;
+use crate::round_trip::canonical_cell;
+use crate::schema::Column;
+use crate::tokio_glue::ConsumeWithParallelism;
+use crate::transform::spawn_sync_transform;
+
+/// How should we check the destination after a copy?
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VerifyMode {
+    /// Compare the number of rows in the source and destination.
+    Count,
+    /// Compare the number of rows and a checksum of their values.
+    Checksum,
+}
+
+impl FromStr for VerifyMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "count" => Ok(VerifyMode::Count),
+            "checksum" => Ok(VerifyMode::Checksum),
+            _ => Err(format_err!("unknown verification mode: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for VerifyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyMode::Count => write!(f, "count"),
+            VerifyMode::Checksum => write!(f, "checksum"),
+        }
+    }
+}
+
+/// A summary of the rows in a table or a stream of CSV streams.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RowTally {
+    /// The number of rows.
+    pub rows: u64,
+    /// The wrapping sum of the hashes of each row. This doesn't depend on row
+    /// order, and it allows us to subtract rows that were already present
+    /// before an append.
+    pub checksum: Option<u64>,
+}
+
+impl RowTally {
+    /// The tally of an empty table, for `mode`.
+    pub fn empty(mode: VerifyMode) -> RowTally {
+        let checksum = match mode {
+            VerifyMode::Count => None,
+            VerifyMode::Checksum => Some(0),
+        };
+        RowTally { rows: 0, checksum }
+    }
+
+    /// The rows in `self` but not in `before`, assuming that `self` was
+    /// created by appending rows to `before`.
+    pub fn appended_since(self, before: RowTally) -> RowTally {
+        RowTally {
+            rows: self.rows.wrapping_sub(before.rows),
+            checksum: match (self.checksum, before.checksum) {
+                (Some(after), Some(before)) => Some(after.wrapping_sub(before)),
+                _ => None,
+            },
+        }
+    }
+
+    /// Return an error if `actual` (tallied at `dest`) doesn't match the rows
+    /// we expected to copy from `source`.
+    pub fn check(
+        self,
+        source: &dyn fmt::Display,
+        actual: RowTally,
+        dest: &dyn fmt::Display,
+    ) -> Result<()> {
+        if self.rows != actual.rows {
+            return Err(format_err!(
+                "verification failed: copied {} rows from {}, but found {} in {}",
+                self.rows,
+                source,
+                actual.rows,
+                dest,
+            ));
+        }
+        if let (Some(expected), Some(found)) = (self.checksum, actual.checksum) {
+            if expected != found {
+                return Err(format_err!(
+                    "verification failed: rows in {} have a different checksum in {}",
+                    source,
+                    dest,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tallies rows as they pass through a stream of CSV streams.
+#[derive(Clone, Debug)]
+pub struct RowCounter {
+    /// Should we compute checksums?
+    mode: VerifyMode,
+    /// The columns we expect to see, used to parse values for checksums.
+    columns: Arc<Vec<Column>>,
+    /// The rows tallied so far.
+    tally: Arc<Mutex<RowTally>>,
+}
+
+impl RowCounter {
+    /// Create a counter for rows matching `schema`.
+    pub fn new(mode: VerifyMode, schema: &Table) -> Self {
+        RowCounter {
+            mode,
+            columns: Arc::new(schema.columns.clone()),
+            tally: Arc::new(Mutex::new(RowTally::empty(mode))),
+        }
+    }
+
+    /// The rows tallied so far.
+    pub fn tally(&self) -> RowTally {
+        *self.tally.lock().expect("lock poisoned, giving up")
+    }
+
+    /// Given a stream of CSV streams, tally the rows in each stream while
+    /// passing the data through unchanged.
+    pub fn count_rows(
+        &self,
+        ctx: Context,
+        streams: BoxStream<CsvStream>,
+    ) -> BoxStream<CsvStream> {
+        let ctx = ctx.child(o!("streams_transform" => "count_rows"));
+        let counter = self.clone();
+        streams
+            .and_then(move |stream| {
+                let ctx = ctx.clone();
+                let counter = counter.clone();
+                async move {
+                    let name = stream.name.clone();
+                    let data = spawn_sync_transform(
+                        ctx,
+                        format!("count rows {}", name),
+                        stream.data,
+                        move |_ctx, rdr, wtr| counter.count_csv(&name, rdr, wtr),
+                    )?;
+                    Ok(CsvStream {
+                        name: stream.name,
+                        data,
+                    })
+                }
+            })
+            .boxed()
+    }
+
+    /// Copy CSV data from `rdr` to `wtr`, and add its rows to our tally.
+    fn count_csv(
+        &self,
+        stream_name: &str,
+        rdr: impl Read,
+        wtr: impl Write,
+    ) -> Result<()> {
+        let mut rdr = csv::Reader::from_reader(rdr);
+        let mut wtr = csv::Writer::from_writer(wtr);
+        let hdr = rdr
+            .headers()
+            .with_context(|_| format!("cannot read headers of {}", stream_name))?
+            .to_owned();
+        wtr.write_record(&hdr)?;
+
+        // Drivers may return columns in a different order, so look them up
+        // by name.
+        let positions = match self.mode {
+            VerifyMode::Count => vec![],
+            VerifyMode::Checksum => self
+                .columns
+                .iter()
+                .map(|col| {
+                    hdr.iter().position(|h| h == col.name).ok_or_else(|| {
+                        format_err!("{} has no column {:?}", stream_name, col.name)
+                    })
+                })
+                .collect::<Result<Vec<usize>>>()?,
+        };
+
+        let mut rows: u64 = 0;
+        let mut checksum: u64 = 0;
+        let mut record = csv::StringRecord::new();
+        while rdr
+            .read_record(&mut record)
+            .with_context(|_| format!("cannot read row from {}", stream_name))?
+        {
+            rows += 1;
+            if self.mode == VerifyMode::Checksum {
+                let hash = self.row_hash(&positions, &record).with_context(|_| {
+                    format!("cannot checksum row from {}", stream_name)
+                })?;
+                checksum = checksum.wrapping_add(hash);
+            }
+            wtr.write_record(&record)?;
+        }
+        wtr.flush()?;
+
+        let mut tally = self.tally.lock().expect("lock poisoned, giving up");
+        tally.rows += rows;
+        tally.checksum = tally.checksum.map(|c| c.wrapping_add(checksum));
+        Ok(())
+    }
+
+    /// Hash the canonical values in `record`.
+    fn row_hash(
+        &self,
+        positions: &[usize],
+        record: &csv::StringRecord,
+    ) -> Result<u64> {
+        let mut hasher = DefaultHasher::new();
+        for (col, &pos) in self.columns.iter().zip(positions) {
+            let value = canonical_cell(col, record.get(pos).unwrap_or_default())
+                .with_context(|_| format!("error in column {:?}", col.name))?;
+            serde_json::to_string(&value)?.hash(&mut hasher);
+        }
+        Ok(hasher.finish())
+    }
+}
+
+/// Tally the rows at `locator`. When we only need a count, we ask the driver
+/// to count them if it can. Otherwise, we read all the data.
+pub async fn tally_rows(
+    ctx: &Context,
+    mode: VerifyMode,
+    locator: &dyn Locator,
+    shared_args: SharedArguments<Unverified>,
+    source_args: SourceArguments<Unverified>,
+) -> Result<RowTally> {
+    if mode == VerifyMode::Count && locator.capabilities()?.count {
+        let rows = locator
+            .count(ctx.clone(), shared_args, source_args)
+            .await
+            .with_context(|_| format!("error counting rows in {}", locator))?;
+        return Ok(RowTally {
+            rows: rows as u64,
+            checksum: None,
+        });
+    }
+
+    let shared_args_v = shared_args.clone().verify(Features::empty())?;
+    let counter = RowCounter::new(mode, shared_args_v.schema());
+    let max_streams = shared_args.max_streams();
+    let data = locator
+        .local_data(ctx.clone(), shared_args, source_args)
+        .await?
+        .ok_or_else(|| format_err!("don't know how to read data from {}", locator))?;
+    counter
+        .count_rows(ctx.clone(), data)
+        .map_ok(|stream| -> BoxFuture<()> {
+            stream
+                .data
+                .try_for_each(|_| futures::future::ok(()))
+                .boxed()
+        })
+        .boxed()
+        .consume_with_parallelism(max_streams)
+        .await
+        .with_context(|_| format!("error reading rows from {}", locator))?;
+    Ok(counter.tally())
+}
+
+#[test]
+fn parses_and_displays_verify_mode() {
+    for mode in &[VerifyMode::Count, VerifyMode::Checksum] {
+        let parsed = mode.to_string().parse::<VerifyMode>().unwrap();
+        assert_eq!(&parsed, mode);
+    }
+    assert!("rows".parse::<VerifyMode>().is_err());
+}
+
+#[test]
+fn checksums_ignore_row_order_and_formatting() {
+    use crate::schema::DataType;
+
+    let schema = Table {
+        name: "t".to_owned(),
+        columns: vec![
+            Column {
+                name: "id".to_owned(),
+                is_nullable: false,
+                data_type: DataType::Int64,
+                comment: None,
+            },
+            Column {
+                name: "ok".to_owned(),
+                is_nullable: true,
+                data_type: DataType::Bool,
+                comment: None,
+            },
+        ],
+    };
+    let tally = |data: &str| {
+        let counter = RowCounter::new(VerifyMode::Checksum, &schema);
+        counter.count_csv("t", data.as_bytes(), vec![]).unwrap();
+        counter.tally()
+    };
+    let expected = tally("id,ok\n1,t\n2,\n");
+    assert_eq!(expected.rows, 2);
+    assert_eq!(tally("ok,id\n,2\ntrue,1\n"), expected);
+    assert_ne!(tally("id,ok\n1,f\n2,\n"), expected);
+
+    let appended = tally("id,ok\n1,t\n2,\n3,f\n").appended_since(expected);
+    assert_eq!(appended, tally("id,ok\n3,f\n"));
+    assert!(appended.check(&"a", tally("id,ok\n3,f\n"), &"b").is_ok());
+    assert!(expected.check(&"a", appended, &"b").is_err());
+}
@@ -10,12 +10,12 @@ function dbxb {
     ../../../target/debug/dbcrossbar --enable-unstable "$@" 2>&1
 }
 
-for c in cp count "schema conv"; do
+for c in "auth login" bench cp count doctor estimate export pipeline "schema conv" serve; do
     dbxb $c --help | tail -n +2 > "$(echo "$c" | sed 's/ /_/g')"_help.txt
 done
 
 dbxb features > features.txt
 
-for d in bigml bigquery csv gs postgres redshift s3 shopify; do
+for d in azblob bigml bigquery csv gs mysql postgres redshift s3 shopify sqlite synthetic; do
     dbxb features $d > features_$d.txt
 done
\ No newline at end of file